@@ -0,0 +1,19 @@
+-- Vanity codes that resolve to a guild, like an invite
+CREATE TABLE guild_vanity_urls (
+    guild_id BIGINT PRIMARY KEY REFERENCES guilds (id) ON DELETE CASCADE,
+    code VARCHAR(32) NOT NULL UNIQUE,
+    CONSTRAINT code_length CHECK (
+        LENGTH(code) >= 3
+        AND LENGTH(code) <= 32
+    )
+);
+-- Audit log of administrative changes made to a guild
+CREATE TABLE audit_log_entries (
+    id BIGINT PRIMARY KEY,
+    guild_id BIGINT NOT NULL REFERENCES guilds (id) ON DELETE CASCADE,
+    user_id BIGINT REFERENCES users (id) ON DELETE SET NULL,
+    action TEXT NOT NULL,
+    old_value TEXT,
+    new_value TEXT
+);
+CREATE INDEX idx_audit_log_entries_guild_id ON audit_log_entries USING HASH (guild_id);
@@ -0,0 +1,24 @@
+-- Resumable attachment uploads, backed by S3 multipart uploads
+CREATE TABLE upload_sessions (
+    id BIGINT PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    channel_id BIGINT NOT NULL REFERENCES channels (id) ON DELETE CASCADE,
+    -- The ID the message will be created with once the upload completes
+    message_id BIGINT NOT NULL UNIQUE,
+    filename TEXT NOT NULL,
+    content_type VARCHAR(255) NOT NULL,
+    size BIGINT NOT NULL,
+    uploaded BIGINT NOT NULL DEFAULT 0,
+    s3_upload_id TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT uploaded_within_size CHECK (uploaded <= size)
+);
+CREATE INDEX idx_upload_sessions_user_id ON upload_sessions USING HASH (user_id);
+-- Parts of an upload that have already landed in S3
+CREATE TABLE upload_session_parts (
+    session_id BIGINT NOT NULL REFERENCES upload_sessions (id) ON DELETE CASCADE,
+    part_number INTEGER NOT NULL,
+    size BIGINT NOT NULL,
+    etag TEXT,
+    PRIMARY KEY (session_id, part_number)
+);
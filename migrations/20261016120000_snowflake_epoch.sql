@@ -0,0 +1,8 @@
+-- The epoch all stored snowflakes were generated with, it must not change once data exists
+CREATE TABLE snowflake_epoch (
+    -- Ensures there is only ever a single row
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    epoch BIGINT NOT NULL
+);
+-- Existing data was generated with the default epoch of 2023-01-01T00:00:00Z
+INSERT INTO snowflake_epoch (epoch) SELECT 1672531200000 WHERE EXISTS (SELECT 1 FROM users);
@@ -0,0 +1,12 @@
+-- Tracks push notifications a user has not responded to, per channel
+CREATE TABLE notification_states (
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    channel_id BIGINT NOT NULL REFERENCES channels (id) ON DELETE CASCADE,
+    -- The number of pushes sent since the user last read the channel
+    unanswered INTEGER NOT NULL DEFAULT 0,
+    -- Whether pushes were suppressed and the channel should be included in the next digest
+    pending_digest BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (user_id, channel_id)
+);
+-- The digest job only ever looks at pending rows
+CREATE INDEX idx_notification_states_pending ON notification_states (user_id) WHERE pending_digest;
@@ -0,0 +1,4 @@
+-- Denormalized channel activity, maintained when messages are created or deleted
+ALTER TABLE channels
+ADD COLUMN last_message_id BIGINT,
+ADD COLUMN message_count BIGINT NOT NULL DEFAULT 0;
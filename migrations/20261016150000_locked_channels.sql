@@ -0,0 +1,2 @@
+-- Locked channels can be read by all members, but only posted in by privileged ones
+ALTER TABLE channels ADD COLUMN locked BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,15 @@
+-- Attachments waiting to be checked by the configured attachment scanner
+CREATE TABLE attachment_scans (
+    attachment_id INTEGER NOT NULL,
+    message_id BIGINT NOT NULL,
+    -- The number of times scanning the attachment failed
+    attempts INTEGER NOT NULL DEFAULT 0,
+    enqueued_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (attachment_id, message_id),
+    FOREIGN KEY (attachment_id, message_id) REFERENCES attachments (id, message_id) ON DELETE CASCADE
+);
+CREATE INDEX idx_attachment_scans_enqueued_at ON attachment_scans (enqueued_at);
+-- Quarantined attachments were moved out of the public bucket after being flagged by the scanner
+ALTER TABLE attachments ADD COLUMN quarantined BOOLEAN NOT NULL DEFAULT FALSE;
+-- Flagged messages contain at least one quarantined attachment
+ALTER TABLE messages ADD COLUMN flagged BOOLEAN NOT NULL DEFAULT FALSE;
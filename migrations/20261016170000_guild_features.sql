@@ -0,0 +1,9 @@
+-- Features granted to a guild by an administrator, gating functionality that is rolled out per guild
+ALTER TABLE guilds ADD COLUMN features TEXT[] NOT NULL DEFAULT '{}';
+
+-- Guilds already using gated functionality keep access to it
+UPDATE guilds SET features = array_append(features, 'VANITY_URL')
+WHERE id IN (SELECT guild_id FROM guild_vanity_urls);
+
+UPDATE guilds SET features = array_append(features, 'ANNOUNCEMENT_CHANNELS')
+WHERE id IN (SELECT guild_id FROM channels WHERE locked);
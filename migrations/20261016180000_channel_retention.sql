@@ -0,0 +1,5 @@
+-- Messages older than this many days are removed from the channel, NULL keeps them forever
+ALTER TABLE channels ADD COLUMN retention_days INT CHECK (retention_days > 0);
+
+-- The object an audit log entry refers to, such as the channel whose messages expired
+ALTER TABLE audit_log_entries ADD COLUMN target_id BIGINT;
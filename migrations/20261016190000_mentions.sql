@@ -0,0 +1,20 @@
+-- Index of the users mentioned in each message, powering the recent mentions inbox
+CREATE TABLE IF NOT EXISTS mentions (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    message_id BIGINT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    channel_id BIGINT NOT NULL REFERENCES channels(id) ON DELETE CASCADE,
+    guild_id BIGINT NOT NULL REFERENCES guilds(id) ON DELETE CASCADE,
+    PRIMARY KEY (user_id, message_id)
+);
+
+CREATE INDEX IF NOT EXISTS mentions_user_guild_idx ON mentions (user_id, guild_id, message_id DESC);
+
+-- Backfill from existing messages, only members of the guild can be mentioned
+INSERT INTO mentions (user_id, message_id, channel_id, guild_id)
+SELECT DISTINCT mem.user_id, m.id, m.channel_id, c.guild_id
+FROM messages m
+JOIN channels c ON c.id = m.channel_id
+CROSS JOIN LATERAL regexp_matches(m.content, '<@(\d{1,20})>', 'g') AS r(match)
+JOIN members mem ON mem.guild_id = c.guild_id AND mem.user_id = r.match[1]::NUMERIC
+WHERE mem.user_id IS DISTINCT FROM m.user_id
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,36 @@
+-- Configures what happens when a member joins a guild
+CREATE TABLE guild_onboarding (
+    guild_id BIGINT PRIMARY KEY REFERENCES guilds (id) ON DELETE CASCADE,
+    -- Channels new members follow, starting out with a read state at the channel's latest message
+    default_channel_ids BIGINT[] NOT NULL DEFAULT '{}',
+    -- The channel welcome messages are posted in
+    system_channel_id BIGINT REFERENCES channels (id) ON DELETE SET NULL,
+    welcome_message TEXT
+);
+
+CREATE TABLE onboarding_questions (
+    id BIGINT PRIMARY KEY,
+    guild_id BIGINT NOT NULL REFERENCES guilds (id) ON DELETE CASCADE,
+    prompt TEXT NOT NULL,
+    position INTEGER NOT NULL
+);
+CREATE INDEX idx_onboarding_questions_guild_id ON onboarding_questions (guild_id);
+
+CREATE TABLE onboarding_options (
+    id BIGINT PRIMARY KEY,
+    question_id BIGINT NOT NULL REFERENCES onboarding_questions (id) ON DELETE CASCADE,
+    label TEXT NOT NULL,
+    -- The roles members choosing this option are assigned
+    role_ids BIGINT[] NOT NULL DEFAULT '{}',
+    position INTEGER NOT NULL
+);
+CREATE INDEX idx_onboarding_options_question_id ON onboarding_options (question_id);
+
+-- The options members picked while onboarding
+CREATE TABLE onboarding_answers (
+    user_id BIGINT NOT NULL,
+    guild_id BIGINT NOT NULL,
+    option_id BIGINT NOT NULL REFERENCES onboarding_options (id) ON DELETE CASCADE,
+    PRIMARY KEY (user_id, guild_id, option_id),
+    FOREIGN KEY (user_id, guild_id) REFERENCES members (user_id, guild_id) ON DELETE CASCADE
+);
@@ -0,0 +1,19 @@
+-- Links that let users join a guild as a guest, with access to a single channel only
+CREATE TABLE guest_links (
+    code VARCHAR(16) PRIMARY KEY,
+    guild_id BIGINT NOT NULL REFERENCES guilds (id) ON DELETE CASCADE,
+    channel_id BIGINT NOT NULL REFERENCES channels (id) ON DELETE CASCADE,
+    creator_id BIGINT REFERENCES users (id) ON DELETE SET NULL,
+    can_post BOOLEAN NOT NULL DEFAULT FALSE,
+    -- How long guests joining through the link keep their access, in seconds
+    access_duration INTEGER NOT NULL CHECK (access_duration > 0),
+    -- UNIX timestamp after which the link can no longer be used, if any
+    expires_at BIGINT
+);
+CREATE INDEX idx_guest_links_channel_id ON guest_links USING HASH (channel_id);
+-- Guests are members restricted to a single channel until their access expires
+ALTER TABLE members ADD COLUMN guest_channel_id BIGINT REFERENCES channels (id) ON DELETE CASCADE;
+ALTER TABLE members ADD COLUMN guest_can_post BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE members ADD COLUMN guest_expires_at BIGINT;
+ALTER TABLE members ADD CONSTRAINT guest_expiry CHECK ((guest_channel_id IS NULL) = (guest_expires_at IS NULL));
+CREATE INDEX idx_members_guest_expires_at ON members (guest_expires_at) WHERE guest_expires_at IS NOT NULL;
@@ -0,0 +1,16 @@
+-- Usernames are unique regardless of case, so rename accounts that only differ
+-- in case from an older account, keeping the oldest one's name intact
+UPDATE users
+SET username = left(lower(username), 12) || '_' || id::TEXT
+WHERE EXISTS (
+        SELECT 1
+        FROM users other
+        WHERE lower(other.username) = lower(users.username)
+            AND other.id < users.id
+    );
+-- Usernames are stored in their normalized (lowercase) form
+UPDATE users
+SET username = lower(username)
+WHERE username <> lower(username);
+ALTER TABLE users DROP CONSTRAINT users_username_key;
+CREATE UNIQUE INDEX users_username_lower_key ON users (lower(username));
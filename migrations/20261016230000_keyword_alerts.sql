@@ -0,0 +1,13 @@
+-- Keywords that alert subscribed moderators when they appear in a new message
+CREATE TABLE moderation_keywords (
+    guild_id BIGINT NOT NULL REFERENCES guilds (id) ON DELETE CASCADE,
+    keyword TEXT NOT NULL,
+    PRIMARY KEY (guild_id, keyword)
+);
+-- Moderators who receive keyword alerts of a guild
+CREATE TABLE keyword_alert_subscribers (
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    PRIMARY KEY (guild_id, user_id),
+    FOREIGN KEY (user_id, guild_id) REFERENCES members (user_id, guild_id) ON DELETE CASCADE
+);
@@ -0,0 +1,8 @@
+-- Identities of users at external authentication providers, such as OIDC
+CREATE TABLE external_identities (
+    provider TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    PRIMARY KEY (provider, subject)
+);
+CREATE INDEX external_identities_user_id_idx ON external_identities (user_id);
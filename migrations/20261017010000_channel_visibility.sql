@@ -0,0 +1,9 @@
+-- The channels each member of a guild can view, resolved in one place.
+-- Queries exposing per-channel state must filter through this view,
+-- so that future visibility rules automatically apply to all of them.
+CREATE VIEW channel_visibility AS
+SELECT m.user_id, c.guild_id, c.id AS channel_id
+FROM channels c
+JOIN members m ON m.guild_id = c.guild_id
+-- Guests can only view the channel they were invited to
+WHERE m.guest_channel_id IS NULL OR m.guest_channel_id = c.id;
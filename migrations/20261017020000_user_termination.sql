@@ -0,0 +1,2 @@
+-- Track users whose accounts were terminated instance-wide
+ALTER TABLE users ADD COLUMN terminated_at BIGINT;
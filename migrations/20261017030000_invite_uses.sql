@@ -0,0 +1,9 @@
+-- Record which invite each member joined through
+CREATE TABLE invite_uses (
+    id BIGSERIAL PRIMARY KEY,
+    guild_id BIGINT NOT NULL REFERENCES guilds (id) ON DELETE CASCADE,
+    code TEXT NOT NULL,
+    user_id BIGINT REFERENCES users (id) ON DELETE SET NULL,
+    used_at BIGINT NOT NULL
+);
+CREATE INDEX invite_uses_guild_id_code_idx ON invite_uses (guild_id, code, used_at DESC);
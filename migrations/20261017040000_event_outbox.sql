@@ -0,0 +1,10 @@
+-- Gateway events and push notifications written in the same transaction as the changes they announce,
+-- emitted by the outbox relay once that transaction committed
+CREATE TABLE event_outbox (
+    id BIGSERIAL PRIMARY KEY,
+    -- The serialized entry, see `OutboxEntry`
+    entry TEXT NOT NULL,
+    -- The number of times emitting the entry failed
+    attempts INTEGER NOT NULL DEFAULT 0,
+    enqueued_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,4 @@
+-- The version of the S3 key layout objects are stored under, see `KEYSPACE_VERSION`
+-- Existing objects use the original, unprefixed layout and are moved once they are accessed
+ALTER TABLE attachments ADD COLUMN key_version SMALLINT NOT NULL DEFAULT 1;
+ALTER TABLE upload_sessions ADD COLUMN key_version SMALLINT NOT NULL DEFAULT 1;
@@ -0,0 +1,10 @@
+-- Codes handed out by administrators, required to register an account if REGISTRATION_CODE_REQUIRED is set
+CREATE TABLE registration_codes (
+    code VARCHAR(16) PRIMARY KEY,
+    creator_id BIGINT REFERENCES users (id) ON DELETE SET NULL,
+    -- The number of accounts that may be registered with the code, unlimited if NULL
+    max_uses INTEGER CHECK (max_uses > 0),
+    uses INTEGER NOT NULL DEFAULT 0,
+    -- UNIX timestamp the code was created at
+    created_at BIGINT NOT NULL
+);
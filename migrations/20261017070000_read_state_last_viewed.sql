@@ -0,0 +1,2 @@
+-- Track when a read state was last advanced by the user viewing the channel
+ALTER TABLE read_states ADD COLUMN last_viewed_at BIGINT;
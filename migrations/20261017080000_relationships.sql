@@ -0,0 +1,11 @@
+-- Friendships and pending friend requests between users
+-- Every relationship is stored once from the perspective of each user involved
+CREATE TABLE relationships (
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    other_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- 1: friends, 2: incoming request, 3: outgoing request
+    relationship_type SMALLINT NOT NULL CHECK (relationship_type BETWEEN 1 AND 3),
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (user_id, other_id),
+    CHECK (user_id <> other_id)
+);
@@ -0,0 +1,4 @@
+-- The number of days after which the attachments of a guild are moved to archive storage, NULL to never archive them
+ALTER TABLE guilds ADD COLUMN attachment_archive_days INTEGER CHECK (attachment_archive_days > 0);
+-- The S3 storage class the attachment is currently stored under
+ALTER TABLE attachments ADD COLUMN storage_class TEXT NOT NULL DEFAULT 'STANDARD';
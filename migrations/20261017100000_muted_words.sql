@@ -0,0 +1,2 @@
+-- Words and phrases the user does not want to be notified about, lowercased
+ALTER TABLE prefs ADD COLUMN muted_words TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,2 @@
+-- The hash of the user's profile banner, stored like avatars but under a separate key prefix
+ALTER TABLE users ADD COLUMN banner_hash TEXT;
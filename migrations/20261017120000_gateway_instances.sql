@@ -0,0 +1,12 @@
+-- Running instances of the application, and when they were last known to be alive
+CREATE TABLE gateway_instances (
+    id TEXT PRIMARY KEY,
+    heartbeat_at BIGINT NOT NULL
+);
+-- Users connected to the gateway of an instance, so that they can be shown as offline if the instance dies
+CREATE TABLE gateway_connections (
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    instance_id TEXT NOT NULL,
+    PRIMARY KEY (user_id, instance_id)
+);
+CREATE INDEX idx_gateway_connections_instance_id ON gateway_connections (instance_id);
@@ -0,0 +1,24 @@
+-- Reports of messages, users or guilds breaking the rules, triaged by the instance's administrators
+CREATE TABLE reports (
+    id BIGINT PRIMARY KEY,
+    reporter_id BIGINT REFERENCES users (id) ON DELETE SET NULL,
+    -- 1: message, 2: user, 3: guild
+    target_type SMALLINT NOT NULL CHECK (target_type BETWEEN 1 AND 3),
+    target_id BIGINT NOT NULL,
+    -- The guild the reported object belongs to, if any
+    guild_id BIGINT REFERENCES guilds (id) ON DELETE SET NULL,
+    category SMALLINT NOT NULL,
+    details TEXT,
+    -- Whether the report was forwarded to the moderators of its guild
+    forwarded BOOLEAN NOT NULL DEFAULT FALSE,
+    -- 1: open, 2: claimed, 3: resolved
+    status SMALLINT NOT NULL DEFAULT 1 CHECK (status BETWEEN 1 AND 3),
+    claimed_by BIGINT REFERENCES users (id) ON DELETE SET NULL,
+    action_taken SMALLINT,
+    resolved_at BIGINT,
+    CHECK ((status = 3) = (action_taken IS NOT NULL))
+);
+CREATE INDEX idx_reports_status ON reports (status, id);
+CREATE INDEX idx_reports_guild_id ON reports (guild_id, id) WHERE forwarded;
+-- A user may only have one unresolved report about the same object
+CREATE UNIQUE INDEX idx_reports_pending ON reports (reporter_id, target_type, target_id) WHERE status <> 3;
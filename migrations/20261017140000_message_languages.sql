@@ -0,0 +1,2 @@
+-- The ISO 639-3 code of the language a message is written in, if it was detected
+ALTER TABLE messages ADD COLUMN lang TEXT;
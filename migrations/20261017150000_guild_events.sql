@@ -0,0 +1,29 @@
+-- Events guilds schedule for their members, such as game nights or meetings
+CREATE TABLE guild_events (
+    id BIGINT PRIMARY KEY,
+    guild_id BIGINT NOT NULL REFERENCES guilds (id) ON DELETE CASCADE,
+    creator_id BIGINT REFERENCES users (id) ON DELETE SET NULL,
+    -- The channel the event takes place in, if any
+    channel_id BIGINT REFERENCES channels (id) ON DELETE SET NULL,
+    title TEXT NOT NULL,
+    description TEXT,
+    -- UNIX timestamps
+    starts_at BIGINT NOT NULL,
+    ends_at BIGINT,
+    -- Whether attendees were reminded of the event shortly before it starts
+    reminded BOOLEAN NOT NULL DEFAULT FALSE,
+    CHECK (ends_at IS NULL OR ends_at > starts_at)
+);
+CREATE INDEX idx_guild_events_guild_id ON guild_events (guild_id, starts_at);
+CREATE INDEX idx_guild_events_pending_reminders ON guild_events (starts_at) WHERE NOT reminded;
+-- Answers of members to the events of their guild, removed when they leave it
+CREATE TABLE guild_event_rsvps (
+    event_id BIGINT NOT NULL REFERENCES guild_events (id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL,
+    guild_id BIGINT NOT NULL,
+    -- 1: going, 2: interested, 3: declined
+    status SMALLINT NOT NULL CHECK (status BETWEEN 1 AND 3),
+    PRIMARY KEY (event_id, user_id),
+    FOREIGN KEY (user_id, guild_id) REFERENCES members (user_id, guild_id) ON DELETE CASCADE
+);
+CREATE INDEX idx_guild_event_rsvps_member ON guild_event_rsvps (user_id, guild_id);
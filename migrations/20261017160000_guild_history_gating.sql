@@ -0,0 +1,2 @@
+-- Whether members can only read messages sent after they joined the guild
+ALTER TABLE guilds ADD COLUMN hide_history_before_join BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+-- Who users share their presence with. 0: everyone, 1: friends, 2: nobody
+ALTER TABLE prefs ADD COLUMN presence_sharing SMALLINT NOT NULL DEFAULT 0;
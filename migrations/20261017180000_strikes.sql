@@ -0,0 +1,17 @@
+-- Strikes against users breaking the rules, which escalate into timeouts and suspensions
+CREATE TABLE strikes (
+    id BIGINT PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- The administrator who issued the strike, if they still exist
+    issuer_id BIGINT REFERENCES users (id) ON DELETE SET NULL,
+    -- Whether the strike was issued by automated moderation instead of an administrator
+    automated BOOLEAN NOT NULL,
+    reason TEXT NOT NULL,
+    -- 1: warning, 2: timeout, 3: suspension
+    penalty SMALLINT NOT NULL CHECK (penalty BETWEEN 1 AND 3),
+    penalty_ends_at BIGINT,
+    expires_at BIGINT NOT NULL,
+    revoked_at BIGINT,
+    CHECK ((penalty = 1) = (penalty_ends_at IS NULL))
+);
+CREATE INDEX idx_strikes_user_id ON strikes (user_id, expires_at) WHERE revoked_at IS NULL;
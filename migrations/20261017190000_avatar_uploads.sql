@@ -0,0 +1,18 @@
+-- Avatars accepted by the REST API, waiting to be uploaded to S3 in the background
+CREATE TABLE avatar_uploads (
+    -- The user or guild the avatar is set on
+    holder_id BIGINT NOT NULL,
+    -- 1: user avatar, 2: user banner, 3: guild avatar
+    slot SMALLINT NOT NULL CHECK (slot BETWEEN 1 AND 3),
+    -- The hash of the pending avatar, already set on the holder
+    avatar_hash TEXT NOT NULL,
+    -- The hash of the last avatar that finished uploading, restored if the upload fails
+    previous_hash TEXT,
+    content BYTEA NOT NULL,
+    -- The number of times uploading the avatar was attempted
+    attempts INTEGER NOT NULL DEFAULT 0,
+    -- When the upload may be attempted next, pushed back while an instance is uploading it
+    available_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (holder_id, slot)
+);
+CREATE INDEX idx_avatar_uploads_available_at ON avatar_uploads (available_at);
@@ -0,0 +1,28 @@
+-- Rules guild moderators set up to check every new message against
+CREATE TABLE automod_rules (
+    id BIGINT PRIMARY KEY,
+    guild_id BIGINT NOT NULL REFERENCES guilds (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    -- 1: keyword, 2: mention count, 3: link spam, 4: attachment type
+    trigger_type SMALLINT NOT NULL CHECK (trigger_type BETWEEN 1 AND 4),
+    -- The keywords or content types matched by keyword and attachment type triggers
+    trigger_values TEXT[] NOT NULL DEFAULT '{}',
+    -- The number of mentions or links allowed by mention count and link spam triggers
+    trigger_limit INT,
+    -- Actions, at most one of each
+    block BOOLEAN NOT NULL DEFAULT FALSE,
+    alert_channel_id BIGINT REFERENCES channels (id) ON DELETE SET NULL,
+    -- In seconds
+    timeout_duration INT
+);
+CREATE INDEX idx_automod_rules_guild_id ON automod_rules (guild_id);
+-- Members timed out by auto-moderation rules, removed when they leave the guild
+CREATE TABLE automod_timeouts (
+    user_id BIGINT NOT NULL,
+    guild_id BIGINT NOT NULL,
+    -- UNIX timestamp
+    until BIGINT NOT NULL,
+    PRIMARY KEY (user_id, guild_id),
+    FOREIGN KEY (user_id, guild_id) REFERENCES members (user_id, guild_id) ON DELETE CASCADE
+);
@@ -0,0 +1,5 @@
+-- Trigram indexes let member searches match names case-insensitively without scanning every member
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX idx_users_username_trgm ON users USING GIN (username gin_trgm_ops);
+CREATE INDEX idx_users_display_name_trgm ON users USING GIN (display_name gin_trgm_ops);
+CREATE INDEX idx_members_nickname_trgm ON members USING GIN (nickname gin_trgm_ops);
@@ -0,0 +1,15 @@
+-- Direct sessions are uploaded to S3 through a presigned URL instead of in parts
+ALTER TABLE upload_sessions ADD COLUMN direct BOOLEAN NOT NULL DEFAULT FALSE;
+-- Avatars and banners clients upload to S3 through a presigned URL, until they are finalized
+CREATE TABLE direct_uploads (
+    id BIGINT PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- The user or guild the file is set on once finalized
+    holder_id BIGINT NOT NULL,
+    -- 1: user avatar, 2: user banner, 3: guild avatar
+    slot SMALLINT NOT NULL CHECK (slot BETWEEN 1 AND 3),
+    content_type VARCHAR(255) NOT NULL,
+    size BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX idx_direct_uploads_created_at ON direct_uploads (created_at);
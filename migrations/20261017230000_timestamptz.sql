@@ -0,0 +1,25 @@
+-- Store all timestamps exposed through the API as TIMESTAMPTZ instead of UNIX timestamps in seconds
+ALTER TABLE members
+    ALTER COLUMN joined_at TYPE TIMESTAMPTZ USING to_timestamp(joined_at),
+    ALTER COLUMN guest_expires_at TYPE TIMESTAMPTZ USING to_timestamp(guest_expires_at);
+ALTER TABLE guest_links ALTER COLUMN expires_at TYPE TIMESTAMPTZ USING to_timestamp(expires_at);
+ALTER TABLE invite_uses ALTER COLUMN used_at TYPE TIMESTAMPTZ USING to_timestamp(used_at);
+ALTER TABLE registration_codes ALTER COLUMN created_at TYPE TIMESTAMPTZ USING to_timestamp(created_at);
+ALTER TABLE read_states ALTER COLUMN last_viewed_at TYPE TIMESTAMPTZ USING to_timestamp(last_viewed_at);
+ALTER TABLE relationships ALTER COLUMN created_at TYPE TIMESTAMPTZ USING to_timestamp(created_at);
+ALTER TABLE reports ALTER COLUMN resolved_at TYPE TIMESTAMPTZ USING to_timestamp(resolved_at);
+ALTER TABLE guild_events
+    ALTER COLUMN starts_at TYPE TIMESTAMPTZ USING to_timestamp(starts_at),
+    ALTER COLUMN ends_at TYPE TIMESTAMPTZ USING to_timestamp(ends_at);
+ALTER TABLE strikes
+    ALTER COLUMN penalty_ends_at TYPE TIMESTAMPTZ USING to_timestamp(penalty_ends_at),
+    ALTER COLUMN expires_at TYPE TIMESTAMPTZ USING to_timestamp(expires_at),
+    ALTER COLUMN revoked_at TYPE TIMESTAMPTZ USING to_timestamp(revoked_at);
+ALTER TABLE users ALTER COLUMN terminated_at TYPE TIMESTAMPTZ USING to_timestamp(terminated_at);
+ALTER TABLE automod_timeouts ALTER COLUMN until TYPE TIMESTAMPTZ USING to_timestamp(until);
+-- Messages record when they were sent and last edited, derived from their ID for existing messages
+ALTER TABLE messages ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE messages ADD COLUMN edited_at TIMESTAMPTZ;
+UPDATE messages SET created_at = to_timestamp(
+    ((id >> 22) + COALESCE((SELECT epoch FROM snowflake_epoch), 1672531200000)) / 1000.0
+);
@@ -0,0 +1,9 @@
+-- Which messages of a guild's channels wake the devices of its members. 0: all messages, 1: only mentions, 2: nothing
+ALTER TABLE guilds ADD COLUMN default_notification_level SMALLINT NOT NULL DEFAULT 1;
+-- The levels users set for individual channels, taking precedence over their guild's default
+CREATE TABLE notification_overrides (
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    channel_id BIGINT NOT NULL REFERENCES channels (id) ON DELETE CASCADE,
+    level SMALLINT NOT NULL,
+    PRIMARY KEY (user_id, channel_id)
+);
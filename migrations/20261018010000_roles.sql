@@ -0,0 +1,18 @@
+-- Roles grant permissions to the members of a guild they are assigned to
+CREATE TABLE roles (
+    id BIGINT PRIMARY KEY,
+    guild_id BIGINT NOT NULL REFERENCES guilds (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    -- A bitfield of the permissions the role grants
+    permissions BIGINT NOT NULL DEFAULT 0
+);
+CREATE INDEX idx_roles_guild_id ON roles (guild_id);
+
+CREATE TABLE member_roles (
+    user_id BIGINT NOT NULL,
+    guild_id BIGINT NOT NULL,
+    role_id BIGINT NOT NULL REFERENCES roles (id) ON DELETE CASCADE,
+    PRIMARY KEY (user_id, guild_id, role_id),
+    FOREIGN KEY (user_id, guild_id) REFERENCES members (user_id, guild_id) ON DELETE CASCADE
+);
+CREATE INDEX idx_member_roles_role_id ON member_roles (role_id);
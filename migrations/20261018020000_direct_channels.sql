@@ -0,0 +1,29 @@
+-- Direct message channels belong to no guild, they can only be viewed by their recipients
+ALTER TABLE channels ALTER COLUMN guild_id DROP NOT NULL;
+ALTER TABLE mentions ALTER COLUMN guild_id DROP NOT NULL;
+-- Direct message channels have no name, only the names of guild channels are bounded
+ALTER TABLE channels DROP CONSTRAINT channel_name_in_bounds;
+ALTER TABLE channels ADD CONSTRAINT channel_name_in_bounds CHECK (
+    guild_id IS NULL OR char_length(name) BETWEEN 3 AND 32
+);
+
+-- Every recipient of a direct message channel is stored along with the other recipient,
+-- so that each pair of users shares at most one channel
+CREATE TABLE channel_recipients (
+    channel_id BIGINT NOT NULL REFERENCES channels (id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    other_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    PRIMARY KEY (channel_id, user_id),
+    UNIQUE (user_id, other_id),
+    CHECK (user_id <> other_id)
+);
+
+CREATE OR REPLACE VIEW channel_visibility AS
+SELECT m.user_id, c.guild_id, c.id AS channel_id
+FROM channels c
+JOIN members m ON m.guild_id = c.guild_id
+-- Guests can only view the channel they were invited to
+WHERE m.guest_channel_id IS NULL OR m.guest_channel_id = c.id
+UNION ALL
+SELECT r.user_id, NULL::BIGINT AS guild_id, r.channel_id
+FROM channel_recipients r;
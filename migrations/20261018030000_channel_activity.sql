@@ -0,0 +1,31 @@
+-- The most recent activity involving a user in each channel, maintained as messages are committed
+CREATE TABLE channel_activity (
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    channel_id BIGINT NOT NULL REFERENCES channels (id) ON DELETE CASCADE,
+    -- The most recent message the user sent, was mentioned in, or received in a direct message channel
+    last_activity_id BIGINT NOT NULL,
+    -- The most recent message mentioning the user
+    last_mention_id BIGINT,
+    PRIMARY KEY (user_id, channel_id)
+);
+
+CREATE INDEX channel_activity_user_idx ON channel_activity (user_id, last_activity_id DESC);
+
+INSERT INTO channel_activity (user_id, channel_id, last_activity_id, last_mention_id)
+SELECT user_id, channel_id, MAX(last_activity_id), MAX(last_mention_id)
+FROM (
+    SELECT user_id, channel_id, MAX(id) AS last_activity_id, NULL::BIGINT AS last_mention_id
+    FROM messages
+    WHERE user_id IS NOT NULL
+    GROUP BY user_id, channel_id
+    UNION ALL
+    SELECT user_id, channel_id, MAX(message_id), MAX(message_id)
+    FROM mentions
+    GROUP BY user_id, channel_id
+    UNION ALL
+    SELECT r.user_id, r.channel_id, c.last_message_id, NULL
+    FROM channel_recipients r
+    JOIN channels c ON c.id = r.channel_id
+    WHERE c.last_message_id IS NOT NULL
+) activity
+GROUP BY user_id, channel_id;
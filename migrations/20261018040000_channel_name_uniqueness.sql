@@ -0,0 +1,35 @@
+-- Guild channel names are lowercased, trimmed and have their words joined with dashes
+UPDATE channels
+SET name = lower(regexp_replace(regexp_replace(name, '^\s+|\s+$', '', 'g'), '\s+', '-', 'g'))
+WHERE guild_id IS NOT NULL;
+
+-- Channels sharing a name with an older channel of their guild get the lowest free numeric suffix,
+-- in the order they were created, while staying within the 32 character limit
+DO $$
+DECLARE
+    duplicate RECORD;
+    suffix INT;
+    candidate TEXT;
+BEGIN
+    FOR duplicate IN
+        SELECT c.id, c.guild_id, c.name
+        FROM channels c
+        WHERE EXISTS (
+            SELECT 1 FROM channels o WHERE o.guild_id = c.guild_id AND o.name = c.name AND o.id < c.id
+        )
+        ORDER BY c.id
+    LOOP
+        suffix := 2;
+        LOOP
+            candidate := left(duplicate.name, 31 - length(suffix::TEXT)) || '-' || suffix;
+            EXIT WHEN NOT EXISTS (
+                SELECT 1 FROM channels WHERE guild_id = duplicate.guild_id AND name = candidate
+            );
+            suffix := suffix + 1;
+        END LOOP;
+        UPDATE channels SET name = candidate WHERE id = duplicate.id;
+    END LOOP;
+END $$;
+
+-- Direct message channels have no guild, so they never conflict
+ALTER TABLE channels ADD CONSTRAINT channels_guild_id_name_key UNIQUE (guild_id, name);
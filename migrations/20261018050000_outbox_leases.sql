@@ -0,0 +1,2 @@
+-- Entries are leased by a relay while they are emitted, and can be leased again once the lease expired
+ALTER TABLE event_outbox ADD COLUMN leased_until TIMESTAMPTZ;
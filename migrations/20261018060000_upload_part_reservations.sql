@@ -0,0 +1,2 @@
+-- Parts are reserved while they are uploading, and can be reserved again once the reservation expired
+ALTER TABLE upload_session_parts ADD COLUMN reserved_until TIMESTAMPTZ;
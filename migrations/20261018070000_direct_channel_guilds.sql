@@ -0,0 +1,4 @@
+-- Direct message channels, and only those, belong to no guild
+ALTER TABLE channels ADD CONSTRAINT direct_channels_without_guild CHECK (
+    (channel_type = 'DIRECT_MESSAGE') = (guild_id IS NULL)
+);
@@ -1,20 +1,62 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
 use aws_config::BehaviorVersion;
 use aws_sdk_s3::{
     Client, Config as S3Config,
     config::{Credentials as S3Creds, Region},
+    types::StorageClass,
 };
 
+use chrono::Utc;
 use derive_builder::Builder;
 use dotenvy::dotenv;
 use secrecy::{ExposeSecret, Secret};
+use uuid::Uuid;
 
-use super::ops::Ops;
-use crate::{external::FirebaseMessaging, models::errors::BuildError};
+use super::{
+    avatar_uploads::{self, AvatarUploader},
+    ops::{INSTANCE_HEARTBEAT_INTERVAL, Ops},
+    outbox::{self, OutboxRelay},
+    query_limiter::QueryLimiter,
+    read_only::ReadOnlyMode,
+    scheduler,
+    startup::StartupReport,
+};
+use crate::{
+    external::{AttachmentScanner, AuthProvider, FirebaseMessaging, HttpScanner, OidcProvider},
+    models::{
+        errors::{BuildError, ConfigError},
+        keyword_alert::KeywordMatcherCache,
+        relationship::DmPolicy,
+        snowflake::{EPOCH, Snowflake},
+        standing::StandingPolicy,
+        user::User,
+    },
+    rest::rate_limit::{RateLimitRegistry, RateLimiter, RateQuota},
+};
+
+/// The default rate at which bots may send messages: 5 per second, in bursts of up to 10,
+/// queuing messages for up to 10 seconds before rejecting them.
+const DEFAULT_BOT_MESSAGE_QUOTA: RateQuota = RateQuota::new(5, 10, Duration::from_secs(10));
+/// The default rate at which users may send messages in a single channel: 1 per second, in bursts of up to 5.
+const DEFAULT_CHANNEL_MESSAGE_QUOTA: RateQuota = RateQuota::new(1, 5, Duration::ZERO);
+/// The default rate at which users may send typing indicators in a single channel: 1 per second, in bursts of up to 3.
+const DEFAULT_TYPING_QUOTA: RateQuota = RateQuota::new(1, 3, Duration::ZERO);
+/// The default rate at which accounts may be signed up from a single IP address: 1 every 10 minutes, in bursts of up to 3.
+const DEFAULT_SIGNUP_QUOTA: RateQuota = RateQuota::per_period(1, Duration::from_secs(600), 3, Duration::ZERO);
+/// The default time browsers only connect over HTTPS for once they saw `Strict-Transport-Security`: one year.
+const DEFAULT_HSTS_MAX_AGE: Duration = Duration::from_secs(365 * 24 * 3600);
 use crate::{
     external::{Database, S3Service},
-    gateway::Gateway,
+    gateway::{EventSink, Gateway},
     models::errors::AppError,
 };
 
@@ -25,9 +67,23 @@ pub type S3Client = Client;
 pub struct ApplicationState {
     db: Database,
     gateway: Gateway,
+    /// Receives emitted gateway events instead of the gateway, if set
+    events: Option<Arc<dyn EventSink>>,
     pub config: Config,
     s3: Option<S3Service>,
     fcm: Option<FirebaseMessaging>,
+    scanner: Option<Arc<dyn AttachmentScanner>>,
+    auth_providers: Vec<Arc<dyn AuthProvider>>,
+    keyword_matchers: KeywordMatcherCache,
+    bot_message_limiter: RateLimiter<Snowflake<User>>,
+    signup_limiter: RateLimiter<IpAddr>,
+    rate_limits: RateLimitRegistry,
+    outbox_relay: OutboxRelay,
+    avatar_uploader: AvatarUploader,
+    query_limiter: QueryLimiter,
+    read_only: ReadOnlyMode,
+    /// Identifies this instance among all instances sharing the database, changes on every start.
+    instance_id: Uuid,
 }
 
 impl ApplicationState {
@@ -35,14 +91,26 @@ impl ApplicationState {
     ///
     /// ## Errors
     ///
-    /// * [`AppError`] - If the application fails to initialize.
+    /// * [`AppError::Config`] - If the configuration is invalid.
+    /// * [`AppError::Startup`] - If the startup self-check fails.
     ///
     /// ## Returns
     ///
     /// A new application state wrapped in an `Arc`.
     pub async fn from_env() -> Result<Arc<Self>, AppError> {
-        let config = Config::from_env();
+        Self::from_config(Config::from_env()?).await
+    }
 
+    /// Create a new application state from the given configuration.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Startup`] - If the startup self-check fails.
+    ///
+    /// ## Returns
+    ///
+    /// A new application state wrapped in an `Arc`.
+    pub async fn from_config(config: Config) -> Result<Arc<Self>, AppError> {
         let s3 = {
             config.s3_config().map_or_else(
                 || {
@@ -66,28 +134,68 @@ impl ApplicationState {
                         .behavior_version(BehaviorVersion::v2025_08_07())
                         .build();
 
-                    Some(S3Service::new(Client::from_conf(s3conf)))
+                    // Presigned requests are signed for the host they are sent to, so they need their own client
+                    let presigner = s3_config
+                        .public_url()
+                        .map(|url| Client::from_conf(s3conf.to_builder().endpoint_url(url).build()));
+
+                    Some(S3Service::new(Client::from_conf(s3conf), presigner))
                 },
             )
         };
 
+        let mut report = StartupReport::new();
+
         let fcm = match FirebaseMessaging::new() {
             Ok(fcm) => Some(fcm),
+            // Credentials that are present but unusable are a misconfiguration, rather than an opt-out
+            Err(e) if std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS").is_some() => {
+                report.fail("push notifications", e);
+                None
+            }
             Err(e) => {
                 tracing::warn!("Failed to initialize Firebase Messaging - Push Notifications will be unavailable: {e}");
                 None
             }
         };
 
+        let scanner = config.scanner_url().map_or_else(
+            || {
+                tracing::warn!("Attachment scanner not configured - Attachments will not be scanned.");
+                None
+            },
+            |url| Some(Arc::new(HttpScanner::new(url)) as Arc<dyn AttachmentScanner>),
+        );
+
+        let auth_providers = config
+            .oidc()
+            .map(|oidc| Arc::new(OidcProvider::new(oidc.clone())) as Arc<dyn AuthProvider>)
+            .into_iter()
+            .collect();
+
+        let read_only = config.read_only();
+        let query_limiter = QueryLimiter::new(config.query_limits());
         let mut state = Self {
             db: Database::new(),
             gateway: Gateway::new(),
+            events: None,
             fcm,
+            bot_message_limiter: RateLimiter::new(*config.bot_message_quota()),
+            signup_limiter: RateLimiter::new(*config.signup_quota()),
+            rate_limits: RateLimitRegistry::new(*config.channel_message_quota(), *config.typing_quota()),
             config,
             s3,
+            scanner,
+            auth_providers,
+            keyword_matchers: KeywordMatcherCache::new(),
+            outbox_relay: OutboxRelay::new(),
+            avatar_uploader: AvatarUploader::new(),
+            query_limiter,
+            read_only: ReadOnlyMode::new(read_only),
+            instance_id: Uuid::new_v4(),
         };
 
-        state.init().await?;
+        state.init(report).await?;
 
         Ok(Arc::new_cyclic(|w| {
             state.db.bind_to(w.clone());
@@ -102,29 +210,50 @@ impl ApplicationState {
 
     /// Create a new application state from the individual components.
     ///
+    /// If an event sink is given, emitted gateway events are sent to it instead of the gateway,
+    /// and the gateway actor is not started.
+    ///
     /// ## Errors
     ///
-    /// * [`AppError`] - If the application fails to initialize.
+    /// * [`AppError::Startup`] - If the startup self-check fails.
     ///
     /// ## Returns
     ///
     /// A new application state wrapped in an `Arc`.
+    #[allow(clippy::too_many_arguments)] // One argument per component
     pub async fn from_components(
         db: Database,
         gateway: Gateway,
         config: Config,
         s3: Option<S3Service>,
         fcm: Option<FirebaseMessaging>,
+        scanner: Option<Arc<dyn AttachmentScanner>>,
+        auth_providers: Vec<Arc<dyn AuthProvider>>,
+        events: Option<Arc<dyn EventSink>>,
     ) -> Result<Arc<Self>, AppError> {
+        let read_only = config.read_only();
+        let query_limiter = QueryLimiter::new(config.query_limits());
         let mut state = Self {
             db,
             gateway,
+            events,
+            bot_message_limiter: RateLimiter::new(*config.bot_message_quota()),
+            signup_limiter: RateLimiter::new(*config.signup_quota()),
+            rate_limits: RateLimitRegistry::new(*config.channel_message_quota(), *config.typing_quota()),
             config,
             s3,
             fcm,
+            scanner,
+            auth_providers,
+            keyword_matchers: KeywordMatcherCache::new(),
+            outbox_relay: OutboxRelay::new(),
+            avatar_uploader: AvatarUploader::new(),
+            query_limiter,
+            read_only: ReadOnlyMode::new(read_only),
+            instance_id: Uuid::new_v4(),
         };
 
-        state.init().await?;
+        state.init(StartupReport::new()).await?;
 
         let shared_state = Arc::new_cyclic(|w| {
             state.db.bind_to(w.clone());
@@ -132,46 +261,148 @@ impl ApplicationState {
                 s3.bind_to(w.clone());
             }
             state.gateway.bind_to(w.clone());
-            state.gateway.start();
+            if state.events.is_none() {
+                state.gateway.start();
+            }
             state
         });
 
         Ok(shared_state)
     }
 
-    /// Initializes the application
+    /// Initializes the application, verifying that all configured services are usable.
+    ///
+    /// Every check is performed even if an earlier one failed, unless it depends on it,
+    /// and the outcome is logged as a single summary.
+    ///
+    /// ## Arguments
+    ///
+    /// * `report` - The report to add the checks to, may already contain checks performed during setup.
     ///
     /// ## Errors
     ///
-    /// * [`sqlx::Error`] - If the database connection fails.
-    async fn init(&mut self) -> Result<(), AppError> {
-        self.db.connect(self.config.database_url().expose_secret()).await?;
-        tracing::info!("Database is ready.");
-        if let Some(s3) = self.s3.as_mut() {
-            s3.create_buckets().await?;
-            tracing::info!("S3 is ready.");
+    /// * [`AppError::Startup`] - If any of the checks failed, containing the full report.
+    async fn init(&mut self, mut report: StartupReport) -> Result<(), AppError> {
+        // Connecting applies all pending migrations, and fails if the database was migrated by a newer version
+        match self.db.connect(self.config.database_url().expose_secret()).await {
+            Ok(()) => report.pass("database", "connected, migrations up to date"),
+            Err(e) => report.fail("database", e),
+        }
+
+        if report.has_failed("database") {
+            report.skip("snowflake epoch", "database unavailable");
+        } else {
+            match self.ops().verify_snowflake_epoch().await {
+                Ok(()) => report.pass("snowflake epoch", self.config.snowflake_epoch().to_string()),
+                Err(e) => report.fail("snowflake epoch", e),
+            }
+        }
+
+        match &self.s3 {
+            Some(s3) => {
+                let ready = match s3.create_buckets().await {
+                    Ok(()) => s3.probe_buckets().await,
+                    Err(e) => Err(e),
+                };
+                match ready {
+                    Ok(()) => report.pass("s3", "all buckets are writable"),
+                    Err(e) => report.fail("s3", e),
+                }
+            }
+            None => report.skip("s3", "not configured, file uploads will be unavailable"),
+        }
+
+        match &self.fcm {
+            Some(fcm) => match fcm.verify_credentials().await {
+                Ok(()) => report.pass("push notifications", "credentials are valid"),
+                Err(e) => report.fail("push notifications", e),
+            },
+            None if !report.has_failed("push notifications") => {
+                report.skip("push notifications", "not configured");
+            }
+            None => {}
+        }
+
+        for provider in &self.auth_providers {
+            match provider.check().await {
+                Ok(()) => report.pass("identity provider", format!("{} is reachable", provider.name())),
+                Err(e) => report.fail("identity provider", format!("{}: {e}", provider.name())),
+            }
+        }
+
+        report.log();
+
+        if !report.is_ok() {
+            return Err(AppError::Startup(report));
         }
         Ok(())
     }
 
     /// Spawn maintenance tasks to run in the background.
     pub fn spawn_background_tasks(self: &Arc<Self>) {
-        let app = self.clone();
-
-        tokio::spawn(async move {
-            loop {
-                tracing::info!("Clearing stale FCM tokens...");
-                match app.ops().clear_stale_fcm_tokens().await {
-                    Ok(count) => {
-                        tracing::info!("Cleared {} stale FCM tokens.", count);
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to clear stale FCM tokens: {}", e);
-                    }
-                }
-                tokio::time::sleep(tokio::time::Duration::from_secs(3600 * 24 /* 1 day */)).await;
-            }
+        outbox::spawn_relay(self);
+        // The first run on startup resets users left online by instances that crashed or were redeployed
+        // Every instance heartbeats, all other jobs work on shared data and run on a single instance
+        scheduler::spawn_periodic(self, "gateway_heartbeat", INSTANCE_HEARTBEAT_INTERVAL, async |app| {
+            app.ops().instances().heartbeat(app.instance_id()).await?;
+            app.ops().instances().reset_stale_presences().await
         });
+        scheduler::spawn_exclusive(
+            self,
+            "clear_stale_fcm_tokens",
+            Duration::from_secs(3600 * 24 /* 1 day */),
+            async |app| app.ops().notifications().clear_stale_fcm_tokens().await,
+        );
+        scheduler::spawn_exclusive(
+            self,
+            "send_notification_digests",
+            self.config.digest_interval(),
+            async |app| app.ops().notifications().send_notification_digests().await,
+        );
+        scheduler::spawn_exclusive(
+            self,
+            "send_guild_event_reminders",
+            Duration::from_secs(60 /* 1 minute */),
+            async |app| app.ops().guild_events().send_event_reminders().await,
+        );
+        scheduler::spawn_exclusive(
+            self,
+            "sweep_expired_messages",
+            Duration::from_secs(3600 /* 1 hour */),
+            async |app| app.ops().messages().sweep_expired_messages().await,
+        );
+        scheduler::spawn_exclusive(
+            self,
+            "remove_expired_guests",
+            Duration::from_secs(60 * 5 /* 5 minutes */),
+            async |app| app.ops().guilds().remove_expired_guests().await,
+        );
+        if self.s3.is_some() {
+            avatar_uploads::spawn_uploader(self);
+            scheduler::spawn_exclusive(
+                self,
+                "archive_attachments",
+                Duration::from_secs(3600 /* 1 hour */),
+                async |app| app.ops().messages().archive_attachments().await,
+            );
+            scheduler::spawn_exclusive(
+                self,
+                "abort_stale_uploads",
+                Duration::from_secs(3600 /* 1 hour */),
+                async |app| app.ops().messages().abort_stale_uploads().await,
+            );
+            scheduler::spawn_exclusive(
+                self,
+                "discard_stale_direct_uploads",
+                Duration::from_secs(3600 /* 1 hour */),
+                async |app| app.ops().avatars().discard_stale_direct_uploads().await,
+            );
+        }
+        if self.scanner.is_some() {
+            scheduler::spawn_exclusive(self, "scan_attachments", Duration::from_secs(30), async |app| {
+                app.ops().messages().scan_pending_attachments().await
+            });
+        }
     }
 
     /// The gateway instance of the application.
@@ -180,12 +411,80 @@ impl ApplicationState {
         &self.gateway
     }
 
+    /// Where gateway events emitted while handling requests are sent to, the gateway unless replaced.
+    #[inline]
+    pub fn events(&self) -> &dyn EventSink {
+        self.events.as_deref().unwrap_or(&self.gateway)
+    }
+
     /// The S3 client instance of the application.
     #[inline]
     pub const fn s3(&self) -> Option<&S3Service> {
         self.s3.as_ref()
     }
 
+    /// The external authentication providers users can log in through.
+    #[inline]
+    pub fn auth_providers(&self) -> &[Arc<dyn AuthProvider>] {
+        &self.auth_providers
+    }
+
+    /// The external authentication provider with the given name, if configured.
+    pub fn auth_provider(&self, name: &str) -> Option<&dyn AuthProvider> {
+        self.auth_providers
+            .iter()
+            .find(|provider| provider.name() == name)
+            .map(AsRef::as_ref)
+    }
+
+    /// The limiter shaping the rate at which bots send messages, shared across all channels.
+    #[inline]
+    pub const fn bot_message_limiter(&self) -> &RateLimiter<Snowflake<User>> {
+        &self.bot_message_limiter
+    }
+
+    /// The limiter shaping the rate at which accounts are signed up, per IP address.
+    #[inline]
+    pub const fn signup_limiter(&self) -> &RateLimiter<IpAddr> {
+        &self.signup_limiter
+    }
+
+    /// The rate limiters of requests about individual objects, such as messages sent in a channel.
+    #[inline]
+    pub const fn rate_limits(&self) -> &RateLimitRegistry {
+        &self.rate_limits
+    }
+
+    /// The relay emitting entries of the transactional outbox.
+    #[inline]
+    pub const fn outbox_relay(&self) -> &OutboxRelay {
+        &self.outbox_relay
+    }
+
+    /// The task uploading avatars accepted by the REST API to S3.
+    #[inline]
+    pub const fn avatar_uploader(&self) -> &AvatarUploader {
+        &self.avatar_uploader
+    }
+
+    /// The limiter of queries known to be expensive.
+    #[inline]
+    pub const fn query_limiter(&self) -> &QueryLimiter {
+        &self.query_limiter
+    }
+
+    /// Whether this instance only serves requests that do not modify any data.
+    #[inline]
+    pub const fn read_only(&self) -> &ReadOnlyMode {
+        &self.read_only
+    }
+
+    /// The ID of this instance, used to track which users are connected to its gateway.
+    #[inline]
+    pub const fn instance_id(&self) -> Uuid {
+        self.instance_id
+    }
+
     /// The database instance of the application.
     #[inline]
     pub const fn db(&self) -> &Database {
@@ -199,20 +498,28 @@ impl ApplicationState {
     }
 
     #[inline]
-    pub const fn ops(&self) -> Ops<'_> {
-        Ops::new(
-            &self.db,
-            &self.config,
-            self.s3.as_ref(),
-            Some(&self.gateway),
-            self.fcm.as_ref(),
-        )
+    pub fn ops(&self) -> Ops<'_> {
+        Ops::builder()
+            .db(&self.db)
+            .config(&self.config)
+            .s3(self.s3.as_ref())
+            .gateway(&self.gateway)
+            .fcm(self.fcm.as_ref())
+            .scanner(self.scanner.as_deref())
+            .keyword_matchers(&self.keyword_matchers)
+            .rate_limits(&self.rate_limits)
+            .outbox_relay(&self.outbox_relay)
+            .avatar_uploader(&self.avatar_uploader)
+            .query_limiter(&self.query_limiter)
+            .build()
+            .expect("all required components should be set")
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct S3EnvConfig {
     url: String,
+    public_url: Option<String>,
     region: String,
     access_key: Secret<String>,
     secret_key: Secret<String>,
@@ -221,6 +528,7 @@ impl S3EnvConfig {
     pub const fn new(url: String, region: String, access_key: Secret<String>, secret_key: Secret<String>) -> Self {
         Self {
             url,
+            public_url: None,
             region,
             access_key,
             secret_key,
@@ -232,6 +540,12 @@ impl S3EnvConfig {
         &self.url
     }
 
+    /// The URL clients reach the S3 instance under, if it differs from [`S3EnvConfig::url`].
+    /// Files uploaded directly by clients are sent here.
+    pub fn public_url(&self) -> Option<&str> {
+        self.public_url.as_deref()
+    }
+
     /// The region of the S3 instance to connect to.
     pub fn region(&self) -> &str {
         &self.region
@@ -248,38 +562,458 @@ impl S3EnvConfig {
     }
 
     /// Try to resolve the S3 configuration from environment variables.
-    fn from_env() -> Option<Self> {
-        let get_env_var = |name: &str| -> Option<String> {
-            match std::env::var(name).ok() {
-                None => {
-                    tracing::warn!("{} environment variable not set", name);
-                    None
-                }
-                Some(v) if v.is_empty() => {
-                    tracing::warn!("{} environment variable is not set", name);
-                    None
-                }
-                Some(v) => Some(v),
+    ///
+    /// S3 is considered not configured if none of the variables are set,
+    /// while setting only some of them is reported as a problem.
+    fn from_env(env: &mut EnvReader) -> Option<Self> {
+        const VARS: [&str; 4] = ["S3_URL", "S3_REGION", "S3_ACCESS_KEY", "S3_SECRET_KEY"];
+
+        if !VARS.iter().any(|var| EnvReader::is_set(var)) {
+            return None;
+        }
+
+        let url = env.required("S3_URL", "a valid URL");
+        let region = env.required("S3_REGION", "set");
+        let access_key = env.required("S3_ACCESS_KEY", "set").map(Secret::new);
+        let secret_key = env.required("S3_SECRET_KEY", "set").map(Secret::new);
+        let public_url = env.optional("S3_PUBLIC_URL", "a valid URL");
+
+        Some(Self {
+            url: url?,
+            public_url,
+            region: region?,
+            access_key: access_key?,
+            secret_key: secret_key?,
+        })
+    }
+}
+
+/// Configuration of an `OpenID` Connect provider users can log in through.
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    issuer: String,
+    client_id: String,
+    username_claim: String,
+    display_name_claim: String,
+}
+
+impl OidcConfig {
+    pub const fn new(issuer: String, client_id: String, username_claim: String, display_name_claim: String) -> Self {
+        Self {
+            issuer,
+            client_id,
+            username_claim,
+            display_name_claim,
+        }
+    }
+
+    /// The issuer ID tokens must be issued by, its discovery document is expected under `/.well-known/openid-configuration`.
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// The client ID tokens must be issued to.
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// The claim the username of newly provisioned users is taken from.
+    pub fn username_claim(&self) -> &str {
+        &self.username_claim
+    }
+
+    /// The claim the display name of users is taken from.
+    pub fn display_name_claim(&self) -> &str {
+        &self.display_name_claim
+    }
+
+    /// Try to resolve the OIDC configuration from environment variables.
+    ///
+    /// OIDC is considered not configured if neither the issuer nor the client ID are set.
+    fn from_env(env: &mut EnvReader) -> Option<Self> {
+        if !EnvReader::is_set("OIDC_ISSUER") && !EnvReader::is_set("OIDC_CLIENT_ID") {
+            return None;
+        }
+
+        let issuer = env.required("OIDC_ISSUER", "a valid URL");
+        let client_id = env.required("OIDC_CLIENT_ID", "set");
+
+        Some(Self {
+            issuer: issuer?,
+            client_id: client_id?,
+            username_claim: env
+                .optional("OIDC_USERNAME_CLAIM", "set")
+                .unwrap_or_else(|| "preferred_username".into()),
+            display_name_claim: env
+                .optional("OIDC_DISPLAY_NAME_CLAIM", "set")
+                .unwrap_or_else(|| "name".into()),
+        })
+    }
+}
+
+/// Configuration of the TLS certificate the server terminates connections with.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    redirect_addr: Option<SocketAddr>,
+}
+
+impl TlsConfig {
+    pub const fn new(cert_path: PathBuf, key_path: PathBuf, redirect_addr: Option<SocketAddr>) -> Self {
+        Self {
+            cert_path,
+            key_path,
+            redirect_addr,
+        }
+    }
+
+    /// The PEM file holding the certificate chain, starting with the server's certificate.
+    pub fn cert_path(&self) -> &Path {
+        &self.cert_path
+    }
+
+    /// The PEM file holding the private key of the certificate.
+    pub fn key_path(&self) -> &Path {
+        &self.key_path
+    }
+
+    /// The address plain HTTP requests are accepted on to be redirected to HTTPS, if any.
+    pub const fn redirect_addr(&self) -> Option<SocketAddr> {
+        self.redirect_addr
+    }
+
+    /// Try to resolve the TLS configuration from environment variables.
+    ///
+    /// TLS is considered not configured if neither the certificate nor the key are set,
+    /// in which case a redirect address is reported as a problem.
+    ///
+    /// ## Arguments
+    ///
+    /// * `listen_addr` - The address the server listens on, which the redirect address must differ from.
+    fn from_env(env: &mut EnvReader, listen_addr: SocketAddr) -> Option<Self> {
+        let redirect_addr = env.optional::<SocketAddr>("HTTP_REDIRECT_ADDR", "a valid socket address");
+
+        if !EnvReader::is_set("TLS_CERT_PATH") && !EnvReader::is_set("TLS_KEY_PATH") {
+            if redirect_addr.is_some() {
+                env.problem("HTTP_REDIRECT_ADDR requires TLS_CERT_PATH and TLS_KEY_PATH to be set");
             }
-        };
+            return None;
+        }
 
-        let url = get_env_var("S3_URL")?;
-        let region = get_env_var("S3_REGION")?;
-        let access_key = get_env_var("S3_ACCESS_KEY").map(Secret::new)?;
-        let secret_key = get_env_var("S3_SECRET_KEY").map(Secret::new)?;
+        let cert_path = env.required::<PathBuf>("TLS_CERT_PATH", "a valid path");
+        let key_path = env.required::<PathBuf>("TLS_KEY_PATH", "a valid path");
+        for (name, path) in [("TLS_CERT_PATH", &cert_path), ("TLS_KEY_PATH", &key_path)] {
+            if let Some(path) = path
+                && !path.is_file()
+            {
+                env.problem(format!(
+                    "{name} must point to an existing file, {} does not",
+                    path.display()
+                ));
+            }
+        }
+        if redirect_addr.is_some_and(|addr| addr.port() == listen_addr.port()) {
+            env.problem("HTTP_REDIRECT_ADDR must use a different port than LISTEN_ADDR");
+        }
 
         Some(Self {
-            url,
-            region,
-            access_key,
-            secret_key,
+            cert_path: cert_path?,
+            key_path: key_path?,
+            redirect_addr,
         })
     }
 }
 
+/// Routes whose maximum request body size can be configured separately from the default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitedRoute {
+    /// POST `/channels/{channel_id}/messages`
+    CreateMessage,
+    /// PATCH `/guilds/{guild_id}`
+    UpdateGuild,
+    /// PATCH `/users/@me`
+    UpdateSelf,
+}
+
+impl LimitedRoute {
+    /// All routes that can be configured.
+    pub const ALL: [Self; 3] = [Self::CreateMessage, Self::UpdateGuild, Self::UpdateSelf];
+
+    /// The name of the environment variable the limit of this route is read from.
+    pub const fn env_var(self) -> &'static str {
+        match self {
+            Self::CreateMessage => "MAX_BODY_SIZE_CREATE_MESSAGE",
+            Self::UpdateGuild => "MAX_BODY_SIZE_UPDATE_GUILD",
+            Self::UpdateSelf => "MAX_BODY_SIZE_UPDATE_SELF",
+        }
+    }
+}
+
+/// The maximum request body sizes accepted by the REST API, in bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BodyLimits {
+    default: usize,
+    overrides: HashMap<LimitedRoute, usize>,
+}
+
+impl BodyLimits {
+    /// Create new body limits.
+    ///
+    /// ## Arguments
+    ///
+    /// * `default` - The limit of all routes without an override.
+    /// * `overrides` - The limits of specific routes.
+    pub const fn new(default: usize, overrides: HashMap<LimitedRoute, usize>) -> Self {
+        Self { default, overrides }
+    }
+
+    /// The limit of all routes without an override.
+    pub const fn default_limit(&self) -> usize {
+        self.default
+    }
+
+    /// The limit of the given route.
+    pub fn get(&self, route: LimitedRoute) -> usize {
+        self.overrides.get(&route).copied().unwrap_or(self.default)
+    }
+
+    /// Read the limits from the environment, falling back to the defaults for unset variables.
+    fn from_env(env: &mut EnvReader) -> Self {
+        let mut limits = Self::default();
+
+        if let Some(default) = env.optional("MAX_BODY_SIZE", "a valid number of bytes") {
+            limits.default = default;
+        }
+        for route in LimitedRoute::ALL {
+            if let Some(limit) = env.optional(route.env_var(), "a valid number of bytes") {
+                limits.overrides.insert(route, limit);
+            }
+        }
+        limits
+    }
+}
+
+impl Default for BodyLimits {
+    fn default() -> Self {
+        Self::new(
+            2 * 1024 * 1024, /* 2mb */
+            HashMap::from([
+                (LimitedRoute::CreateMessage, 8 * 1024 * 1024 /* 8mb */),
+                // Animated avatars and banners are base64 encoded in the payload
+                (LimitedRoute::UpdateGuild, 6 * 1024 * 1024 /* 6mb */),
+                (LimitedRoute::UpdateSelf, 12 * 1024 * 1024 /* 12mb */),
+            ]),
+        )
+    }
+}
+
+/// How REST requests are hardened, and which security headers are added to REST responses, see [`crate::rest::hardening`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HardeningConfig {
+    security_headers: bool,
+    hsts_max_age: Option<Duration>,
+    max_header_size: usize,
+    strict_content_type: bool,
+}
+
+impl HardeningConfig {
+    /// Create a new hardening configuration.
+    ///
+    /// ## Arguments
+    ///
+    /// * `security_headers` - Whether security headers are added to responses.
+    /// * `hsts_max_age` - How long browsers should only connect over HTTPS for, if `Strict-Transport-Security` is sent.
+    /// * `max_header_size` - The maximum combined size of the request headers, in bytes.
+    /// * `strict_content_type` - Whether suspicious `Content-Type`s are rejected.
+    pub const fn new(
+        security_headers: bool,
+        hsts_max_age: Option<Duration>,
+        max_header_size: usize,
+        strict_content_type: bool,
+    ) -> Self {
+        Self {
+            security_headers,
+            hsts_max_age,
+            max_header_size,
+            strict_content_type,
+        }
+    }
+
+    /// Whether security headers, such as `X-Content-Type-Options`, are added to responses.
+    pub const fn security_headers(&self) -> bool {
+        self.security_headers
+    }
+
+    /// How long browsers should only connect over HTTPS for, if `Strict-Transport-Security` is sent at all.
+    /// It is only sent along with the other security headers.
+    pub const fn hsts_max_age(&self) -> Option<Duration> {
+        self.hsts_max_age
+    }
+
+    /// The maximum combined size of the names and values of the request headers, in bytes.
+    pub const fn max_header_size(&self) -> usize {
+        self.max_header_size
+    }
+
+    /// Whether requests with a suspicious `Content-Type`, such as `text/plain`, are rejected.
+    pub const fn strict_content_type(&self) -> bool {
+        self.strict_content_type
+    }
+
+    /// Read the configuration from the environment, falling back to the defaults for unset variables.
+    ///
+    /// ## Arguments
+    ///
+    /// * `tls` - Whether the server terminates TLS itself, in which case HSTS is enabled by default.
+    fn from_env(env: &mut EnvReader, tls: bool) -> Self {
+        let mut config = Self::default();
+
+        if let Some(enabled) = env.optional::<bool>("SECURITY_HEADERS", "either true or false") {
+            config.security_headers = enabled;
+        }
+        match env.optional::<u64>("HSTS_MAX_AGE", "a valid number of seconds") {
+            // A max age of 0 disables HSTS
+            Some(secs) => config.hsts_max_age = (secs > 0).then(|| Duration::from_secs(secs)),
+            None if tls => config.hsts_max_age = Some(DEFAULT_HSTS_MAX_AGE),
+            None => {}
+        }
+        if let Some(size) = env.optional::<NonZeroUsize>("MAX_HEADER_SIZE", "a positive number of bytes") {
+            config.max_header_size = size.get();
+        }
+        if let Some(strict) = env.optional::<bool>("STRICT_CONTENT_TYPE", "either true or false") {
+            config.strict_content_type = strict;
+        }
+        config
+    }
+}
+
+impl Default for HardeningConfig {
+    fn default() -> Self {
+        Self::new(true, None, 16 * 1024 /* 16kb */, true)
+    }
+}
+
+/// Operations known to run expensive queries, which are limited separately so that they cannot exhaust the database pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeavyQuery {
+    /// Searching the members of a guild through `REQUEST_GUILD_MEMBERS`
+    MemberSearch,
+    /// GET `/channels/{channel_id}/messages/export`
+    MessageExport,
+    /// GET `/channels/{channel_id}/messages` with `around`
+    MessagesAround,
+}
+
+impl HeavyQuery {
+    /// All queries that can be configured.
+    pub const ALL: [Self; 3] = [Self::MemberSearch, Self::MessageExport, Self::MessagesAround];
+
+    /// The suffix of the environment variables the limits of this query are read from.
+    pub const fn env_suffix(self) -> &'static str {
+        match self {
+            Self::MemberSearch => "MEMBER_SEARCH",
+            Self::MessageExport => "MESSAGE_EXPORT",
+            Self::MessagesAround => "MESSAGES_AROUND",
+        }
+    }
+}
+
+/// The limits a heavy query runs under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryLimit {
+    timeout: Duration,
+    concurrency: usize,
+}
+
+impl QueryLimit {
+    /// Create a new query limit.
+    ///
+    /// ## Arguments
+    ///
+    /// * `timeout` - How long a single statement of the query may run for before it is cancelled.
+    /// * `concurrency` - How many of these queries may run at once on an instance.
+    pub const fn new(timeout: Duration, concurrency: usize) -> Self {
+        Self { timeout, concurrency }
+    }
+
+    /// How long a single statement of the query may run for before it is cancelled.
+    pub const fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// How many of these queries may run at once on an instance.
+    pub const fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+}
+
+/// The limits of all heavy queries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryLimits {
+    limits: HashMap<HeavyQuery, QueryLimit>,
+}
+
+impl QueryLimits {
+    /// Create new query limits, queries without a limit use their default.
+    ///
+    /// ## Arguments
+    ///
+    /// * `limits` - The limits of specific queries.
+    pub fn new(limits: HashMap<HeavyQuery, QueryLimit>) -> Self {
+        let mut defaults = Self::default();
+        defaults.limits.extend(limits);
+        defaults
+    }
+
+    /// The limit of the given query.
+    pub fn get(&self, query: HeavyQuery) -> QueryLimit {
+        self.limits[&query]
+    }
+
+    /// Read the limits from the environment, falling back to the defaults for unset variables.
+    fn from_env(env: &mut EnvReader) -> Self {
+        let mut limits = Self::default();
+
+        for query in HeavyQuery::ALL {
+            let limit = limits
+                .limits
+                .get_mut(&query)
+                .expect("All queries should have a default limit");
+            let suffix = query.env_suffix();
+
+            if let Some(secs) =
+                env.optional::<NonZeroU64>(&format!("QUERY_TIMEOUT_{suffix}"), "a positive number of seconds")
+            {
+                limit.timeout = Duration::from_secs(secs.get());
+            }
+            if let Some(count) =
+                env.optional::<NonZeroUsize>(&format!("QUERY_CONCURRENCY_{suffix}"), "a positive number of queries")
+            {
+                limit.concurrency = count.get();
+            }
+        }
+        limits
+    }
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self {
+            limits: HashMap::from([
+                (HeavyQuery::MemberSearch, QueryLimit::new(Duration::from_secs(5), 16)),
+                // Exports stream whole channels, their single statement runs for as long as the download
+                (HeavyQuery::MessageExport, QueryLimit::new(Duration::from_secs(600), 4)),
+                (HeavyQuery::MessagesAround, QueryLimit::new(Duration::from_secs(5), 16)),
+            ]),
+        }
+    }
+}
+
 /// Application configuration
+#[allow(clippy::struct_excessive_bools)] // Each flag is an independent setting
 #[derive(Debug, Clone, Builder)]
-#[builder(setter(into), build_fn(error = "BuildError"))]
+#[builder(setter(into), build_fn(error = "BuildError", validate = "Self::validate"))]
 pub struct Config {
     database_url: Secret<String>,
     s3: Option<S3EnvConfig>,
@@ -287,6 +1021,102 @@ pub struct Config {
     machine_id: i32,
     process_id: i32,
     app_secret: Secret<String>,
+    #[builder(default = "EPOCH")]
+    snowflake_epoch: i64,
+    #[builder(default)]
+    admins: Vec<Snowflake<User>>,
+    #[builder(default)]
+    bots: Vec<Snowflake<User>>,
+    #[builder(default = "DEFAULT_BOT_MESSAGE_QUOTA")]
+    bot_message_quota: RateQuota,
+    #[builder(default = "DEFAULT_CHANNEL_MESSAGE_QUOTA")]
+    channel_message_quota: RateQuota,
+    #[builder(default = "DEFAULT_TYPING_QUOTA")]
+    typing_quota: RateQuota,
+    #[builder(default = "DEFAULT_SIGNUP_QUOTA")]
+    signup_quota: RateQuota,
+    #[builder(default)]
+    disposable_email_domains: HashSet<String>,
+    #[builder(default)]
+    new_account_restriction: Option<Duration>,
+    #[builder(default = "3")]
+    digest_threshold: u32,
+    #[builder(default = "Duration::from_secs(3600 * 6)")]
+    digest_interval: Duration,
+    #[builder(default = "Duration::from_secs(60 * 15)")]
+    guild_event_reminder_lead: Duration,
+    #[builder(default)]
+    scanner_url: Option<String>,
+    #[builder(default)]
+    otlp_endpoint: Option<String>,
+    #[builder(default)]
+    cdn_url: Option<String>,
+    #[builder(default = "String::from(\"chat-backend\")")]
+    otlp_service_name: String,
+    #[builder(default = "String::from(\"Chat\")")]
+    instance_name: String,
+    #[builder(default)]
+    instance_description: Option<String>,
+    #[builder(default)]
+    instance_contact: Option<String>,
+    #[builder(default = "true")]
+    strip_image_metadata: bool,
+    #[builder(default)]
+    registration_code_required: bool,
+    #[builder(default)]
+    read_only: bool,
+    #[builder(default)]
+    strict_message_limit: bool,
+    #[builder(default)]
+    detect_message_language: bool,
+    #[builder(default = "10")]
+    max_message_attachments: usize,
+    #[builder(default = "8 * 1024 * 1024")]
+    max_message_attachments_size: usize,
+    #[builder(default)]
+    dm_policy: DmPolicy,
+    #[builder(default = "Some(Duration::from_secs(600))")]
+    away_timeout: Option<Duration>,
+    #[builder(default = "100")]
+    guild_create_member_limit: usize,
+    #[builder(default)]
+    guild_member_limit: Option<usize>,
+    #[builder(default = "1024 * 1024")]
+    gateway_max_payload_size: usize,
+    #[builder(default = "16 * 1024")]
+    gateway_max_message_size: usize,
+    #[builder(default = "16 * 1024")]
+    gateway_max_frame_size: usize,
+    #[builder(default)]
+    gateway_dispatch_log_size: usize,
+    #[builder(default = "100")]
+    gateway_inbound_capacity: usize,
+    #[builder(default = "StorageClass::GlacierIr")]
+    attachment_archive_storage_class: StorageClass,
+    #[builder(default)]
+    body_limits: BodyLimits,
+    #[builder(default)]
+    query_limits: QueryLimits,
+    #[builder(default)]
+    hardening: HardeningConfig,
+    #[builder(default)]
+    oidc: Option<OidcConfig>,
+    #[builder(default)]
+    tls: Option<TlsConfig>,
+    #[builder(default)]
+    standing_policy: StandingPolicy,
+}
+
+impl ConfigBuilder {
+    /// Validate the configuration before building it.
+    fn validate(&self) -> Result<(), String> {
+        if let Some(epoch) = self.snowflake_epoch
+            && !(0..=Utc::now().timestamp_millis()).contains(&epoch)
+        {
+            return Err("Snowflake epoch must be a UNIX timestamp in milliseconds that is not in the future".into());
+        }
+        Ok(())
+    }
 }
 
 impl Config {
@@ -325,37 +1155,524 @@ impl Config {
         &self.app_secret
     }
 
-    /// Creates a new config from environment variables
+    /// The epoch snowflakes are generated relative to, as a UNIX timestamp in milliseconds.
+    pub const fn snowflake_epoch(&self) -> i64 {
+        self.snowflake_epoch
+    }
+
+    /// The IDs of the users that have access to the administrative endpoints.
+    pub fn admins(&self) -> &[Snowflake<User>] {
+        &self.admins
+    }
+
+    /// The IDs of the users that are bots, and are subject to the bot message rate.
+    pub fn bots(&self) -> &[Snowflake<User>] {
+        &self.bots
+    }
+
+    /// How many messages each bot may send across all channels.
+    pub const fn bot_message_quota(&self) -> &RateQuota {
+        &self.bot_message_quota
+    }
+
+    /// How many messages each user may send in a single channel.
+    pub const fn channel_message_quota(&self) -> &RateQuota {
+        &self.channel_message_quota
+    }
+
+    /// How many typing indicators each user may send in a single channel.
+    pub const fn typing_quota(&self) -> &RateQuota {
+        &self.typing_quota
+    }
+
+    /// How many accounts may be signed up from a single IP address.
+    pub const fn signup_quota(&self) -> &RateQuota {
+        &self.signup_quota
+    }
+
+    /// Returns whether the domain of the given email address is a disposable email provider,
+    /// with which accounts may not be signed up.
+    pub fn is_disposable_email(&self, email: &str) -> bool {
+        email
+            .rsplit_once('@')
+            .is_some_and(|(_, domain)| self.disposable_email_domains.contains(&domain.to_lowercase()))
+    }
+
+    /// How long new accounts may not create guilds or open direct messages with users who are not their friends for,
+    /// if at all.
+    pub const fn new_account_restriction(&self) -> Option<Duration> {
+        self.new_account_restriction
+    }
+
+    /// Returns whether the given user's account is still within [`Config::new_account_restriction`].
+    pub fn is_new_account(&self, user: impl Into<Snowflake<User>>) -> bool {
+        let Some(restriction) = self.new_account_restriction else {
+            return false;
+        };
+        let created_at = user.into().timestamp_with_epoch(self.snowflake_epoch);
+        let restricted_for = i64::try_from(restriction.as_millis()).unwrap_or(i64::MAX);
+        Utc::now().timestamp_millis() < created_at.saturating_add(restricted_for)
+    }
+
+    /// The number of unanswered push notifications in a channel,
+    /// after which further pushes are collapsed into the periodic digest.
+    pub const fn digest_threshold(&self) -> u32 {
+        self.digest_threshold
+    }
+
+    /// The time between two notification digests.
+    pub const fn digest_interval(&self) -> Duration {
+        self.digest_interval
+    }
+
+    /// How long before a guild event starts its attendees are reminded of it.
+    pub const fn guild_event_reminder_lead(&self) -> Duration {
+        self.guild_event_reminder_lead
+    }
+
+    /// The URL of the external service uploaded attachments are sent to for scanning, if any.
+    pub fn scanner_url(&self) -> Option<&str> {
+        self.scanner_url.as_deref()
+    }
+
+    /// The base URL of the OTLP/HTTP collector traces are exported to, if any.
+    pub fn otlp_endpoint(&self) -> Option<&str> {
+        self.otlp_endpoint.as_deref()
+    }
+
+    /// The public base URL media is served under, if it is served through a CDN in front of the API.
     ///
-    /// ## Panics
+    /// Media paths, such as `/users/{user_id}/avatars/{avatar_hash}`, are appended to this URL.
+    pub fn cdn_url(&self) -> Option<&str> {
+        self.cdn_url.as_deref()
+    }
+
+    /// The service name traces are exported under.
+    pub fn otlp_service_name(&self) -> &str {
+        &self.otlp_service_name
+    }
+
+    /// Whether metadata such as EXIF location data is stripped from uploaded image attachments.
+    pub const fn strip_image_metadata(&self) -> bool {
+        self.strip_image_metadata
+    }
+
+    /// Who users may open direct messages with.
+    pub const fn dm_policy(&self) -> DmPolicy {
+        self.dm_policy
+    }
+
+    /// The name of this instance, shown to clients connecting to it.
+    pub fn instance_name(&self) -> &str {
+        &self.instance_name
+    }
+
+    /// A short description of this instance, shown to clients connecting to it.
+    pub fn instance_description(&self) -> Option<&str> {
+        self.instance_description.as_deref()
+    }
+
+    /// How to reach the operators of this instance, such as an email address or URL.
+    pub fn instance_contact(&self) -> Option<&str> {
+        self.instance_contact.as_deref()
+    }
+
+    /// Whether registering an account requires a registration code handed out by an administrator.
+    pub const fn registration_code_required(&self) -> bool {
+        self.registration_code_required
+    }
+
+    /// Whether the instance starts out read-only, see [`ReadOnlyMode`].
+    pub const fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Whether fetching messages with a limit outside of 1 to 100 is rejected, instead of the limit being clamped.
+    pub const fn strict_message_limit(&self) -> bool {
+        self.strict_message_limit
+    }
+
+    /// Whether the language of new and edited messages is detected, so that they can be filtered by it.
+    pub const fn detect_message_language(&self) -> bool {
+        self.detect_message_language
+    }
+
+    /// The maximum number of attachments a single message may have.
+    pub const fn max_message_attachments(&self) -> usize {
+        self.max_message_attachments
+    }
+
+    /// The maximum combined size in bytes of the attachments of a single message.
+    pub const fn max_message_attachments_size(&self) -> usize {
+        self.max_message_attachments_size
+    }
+
+    /// How long connected users have to be inactive for before they are shown as away, if at all.
+    pub const fn away_timeout(&self) -> Option<Duration> {
+        self.away_timeout
+    }
+
+    /// The S3 storage class attachments are moved to once they are older than their guild's archive period.
+    pub const fn attachment_archive_storage_class(&self) -> &StorageClass {
+        &self.attachment_archive_storage_class
+    }
+
+    /// The number of members above which `GUILD_CREATE` only includes members that are not offline, at most this many.
+    pub const fn guild_create_member_limit(&self) -> usize {
+        self.guild_create_member_limit
+    }
+
+    /// The maximum number of full members a guild may have, if any. Guests do not count towards it.
+    pub const fn guild_member_limit(&self) -> Option<usize> {
+        self.guild_member_limit
+    }
+
+    /// The size in bytes gateway payloads that can be split, such as member chunks, are kept below.
+    pub const fn gateway_max_payload_size(&self) -> usize {
+        self.gateway_max_payload_size
+    }
+
+    /// The largest message in bytes clients may send to the gateway, after reassembling fragmented frames.
+    pub const fn gateway_max_message_size(&self) -> usize {
+        self.gateway_max_message_size
+    }
+
+    /// The largest single frame in bytes clients may send to the gateway.
+    pub const fn gateway_max_frame_size(&self) -> usize {
+        self.gateway_max_frame_size
+    }
+
+    /// The number of most recent gateway dispatches kept for debugging, or 0 if they are not kept.
+    pub const fn gateway_dispatch_log_size(&self) -> usize {
+        self.gateway_dispatch_log_size
+    }
+
+    /// The number of requests from a user's sessions that may wait to be handled, further requests are dropped.
+    pub const fn gateway_inbound_capacity(&self) -> usize {
+        self.gateway_inbound_capacity
+    }
+
+    /// The maximum request body sizes accepted by the REST API.
+    pub const fn body_limits(&self) -> &BodyLimits {
+        &self.body_limits
+    }
+
+    /// How REST requests are hardened, and which security headers are added to REST responses.
+    pub const fn hardening(&self) -> &HardeningConfig {
+        &self.hardening
+    }
+
+    /// The limits of queries known to be expensive.
+    pub const fn query_limits(&self) -> &QueryLimits {
+        &self.query_limits
+    }
+
+    /// The `OpenID` Connect provider users can log in through, if any.
+    pub const fn oidc(&self) -> Option<&OidcConfig> {
+        self.oidc.as_ref()
+    }
+
+    /// How strikes against users escalate into timeouts and suspensions.
+    pub const fn standing_policy(&self) -> &StandingPolicy {
+        &self.standing_policy
+    }
+
+    /// The TLS certificate the server terminates connections with, if it serves HTTPS itself.
+    pub const fn tls(&self) -> Option<&TlsConfig> {
+        self.tls.as_ref()
+    }
+
+    /// Returns whether the given user is an administrator of this instance.
+    pub fn is_admin(&self, user: impl Into<Snowflake<User>>) -> bool {
+        self.admins.contains(&user.into())
+    }
+
+    /// Returns whether the given user is a bot.
+    pub fn is_bot(&self, user: impl Into<Snowflake<User>>) -> bool {
+        self.bots.contains(&user.into())
+    }
+
+    /// Creates a new config from environment variables.
     ///
-    /// Panics if any of the required environment variables are not set
-    /// or if they are not in a valid format.
-    pub fn from_env() -> Self {
+    /// ## Errors
+    ///
+    /// * [`ConfigError`] - If any of the required environment variables are not set,
+    ///   or if any of the variables are not in a valid format. All problems are reported at once.
+    pub fn from_env() -> Result<Self, ConfigError> {
         dotenv().ok();
-        Self::builder()
-            .database_url(std::env::var("DATABASE_URL").expect("DATABASE_URL environment variable must be set"))
-            .s3(S3EnvConfig::from_env())
-            .machine_id(
-                std::env::var("MACHINE_ID")
-                    .expect("MACHINE_ID environment variable must be set")
-                    .parse::<i32>()
-                    .expect("MACHINE_ID must be a valid integer"),
-            )
-            .process_id(
-                std::env::var("PROCESS_ID")
-                    .expect("PROCESS_ID environment variable must be set")
-                    .parse::<i32>()
-                    .expect("PROCESS_ID must be a valid integer"),
-            )
-            .listen_addr(
-                std::env::var("LISTEN_ADDR")
-                    .unwrap_or_else(|_| "127.0.0.1:8080".to_string())
-                    .parse::<SocketAddr>()
-                    .expect("LISTEN_ADDR must be a valid socket address"),
-            )
-            .app_secret(std::env::var("APP_SECRET").expect("APP_SECRET environment variable must be set"))
-            .build()
-            .expect("Failed to create application configuration.")
+        let mut env = EnvReader::default();
+        let mut builder = Self::builder();
+
+        if let Some(url) = env.required::<String>("DATABASE_URL", "a valid URL") {
+            builder.database_url(url);
+        }
+        builder.s3(S3EnvConfig::from_env(&mut env));
+        if let Some(id) = env.required::<i32>("MACHINE_ID", "a valid integer") {
+            builder.machine_id(id);
+        }
+        if let Some(id) = env.required::<i32>("PROCESS_ID", "a valid integer") {
+            builder.process_id(id);
+        }
+        let listen_addr = env
+            .optional::<SocketAddr>("LISTEN_ADDR", "a valid socket address")
+            .unwrap_or_else(|| SocketAddr::from(([127, 0, 0, 1], 8080)));
+        builder.listen_addr(listen_addr);
+        if let Some(secret) = env.required::<String>("APP_SECRET", "set") {
+            builder.app_secret(secret);
+        }
+        if let Some(epoch) = env.optional::<i64>("SNOWFLAKE_EPOCH", "a valid UNIX timestamp in milliseconds") {
+            builder.snowflake_epoch(epoch);
+        }
+        if let Some(admins) = env.user_ids("ADMIN_IDS") {
+            builder.admins(admins);
+        }
+        if let Some(bots) = env.user_ids("BOT_IDS") {
+            builder.bots(bots);
+        }
+        if let Some(threshold) = env.optional::<u32>("NOTIFICATION_DIGEST_THRESHOLD", "a valid integer") {
+            builder.digest_threshold(threshold);
+        }
+        if let Some(interval) = env.optional::<u64>("NOTIFICATION_DIGEST_INTERVAL", "a valid number of seconds") {
+            builder.digest_interval(Duration::from_secs(interval));
+        }
+        if let Some(lead) = env.optional::<u64>("GUILD_EVENT_REMINDER_LEAD", "a valid number of seconds") {
+            builder.guild_event_reminder_lead(Duration::from_secs(lead));
+        }
+        builder
+            .scanner_url(env.optional::<String>("ATTACHMENT_SCANNER_URL", "a valid URL"))
+            .otlp_endpoint(env.optional::<String>("OTLP_ENDPOINT", "a valid URL"));
+        builder.cdn_url(
+            env.optional::<String>("CDN_URL", "a valid URL")
+                .map(|url| url.trim_end_matches('/').to_string()),
+        );
+        if let Some(name) = env.optional::<String>("OTLP_SERVICE_NAME", "set") {
+            builder.otlp_service_name(name);
+        }
+        if let Some(name) = env.optional::<String>("INSTANCE_NAME", "set") {
+            builder.instance_name(name);
+        }
+        builder
+            .instance_description(env.optional::<String>("INSTANCE_DESCRIPTION", "set"))
+            .instance_contact(env.optional::<String>("INSTANCE_CONTACT", "set"));
+        if let Some(strip) = env.optional::<bool>("STRIP_IMAGE_METADATA", "either true or false") {
+            builder.strip_image_metadata(strip);
+        }
+        if let Some(required) = env.optional::<bool>("REGISTRATION_CODE_REQUIRED", "either true or false") {
+            builder.registration_code_required(required);
+        }
+        if let Some(read_only) = env.optional::<bool>("READ_ONLY", "either true or false") {
+            builder.read_only(read_only);
+        }
+        message_settings_from_env(&mut env, &mut builder);
+        if let Some(policy) = env.optional::<DmPolicy>("DM_POLICY", "one of anyone, friends_or_mutual_guild or friends")
+        {
+            builder.dm_policy(policy);
+        }
+        if let Some(secs) = env.optional::<u64>("AWAY_TIMEOUT", "a valid number of seconds") {
+            // A timeout of 0 disables marking users as away
+            builder.away_timeout((secs > 0).then(|| Duration::from_secs(secs)));
+        }
+        if let Some(limit) = env.optional::<usize>("GUILD_MEMBER_LIMIT", "a valid number of members") {
+            // A limit of 0 lets guilds grow without bound
+            builder.guild_member_limit((limit > 0).then_some(limit));
+        }
+        gateway_settings_from_env(&mut env, &mut builder);
+        if let Some(class) = env.optional::<StorageClass>("ATTACHMENT_ARCHIVE_STORAGE_CLASS", "an S3 storage class") {
+            builder.attachment_archive_storage_class(class);
+        }
+        builder.body_limits(BodyLimits::from_env(&mut env));
+        builder.query_limits(QueryLimits::from_env(&mut env));
+        builder.bot_message_quota(quota_from_env(&mut env, "BOT_MESSAGE", DEFAULT_BOT_MESSAGE_QUOTA));
+        builder.channel_message_quota(quota_from_env(
+            &mut env,
+            "CHANNEL_MESSAGE",
+            DEFAULT_CHANNEL_MESSAGE_QUOTA,
+        ));
+        builder.typing_quota(quota_from_env(&mut env, "TYPING", DEFAULT_TYPING_QUOTA));
+        signup_settings_from_env(&mut env, &mut builder);
+        builder.oidc(OidcConfig::from_env(&mut env));
+        let tls = TlsConfig::from_env(&mut env, listen_addr);
+        builder.hardening(HardeningConfig::from_env(&mut env, tls.is_some()));
+        builder.tls(tls);
+        builder.standing_policy(standing_policy_from_env(&mut env));
+
+        if !env.problems.is_empty() {
+            return Err(ConfigError::new(env.problems));
+        }
+        builder.build().map_err(|e| ConfigError::new(vec![e.to_string()]))
+    }
+}
+
+/// Read the settings of the gateway from the environment, leaving unset ones at their defaults.
+fn gateway_settings_from_env(env: &mut EnvReader, builder: &mut ConfigBuilder) {
+    if let Some(limit) = env.optional::<usize>("GUILD_CREATE_MEMBER_LIMIT", "a valid number of members") {
+        builder.guild_create_member_limit(limit);
+    }
+    if let Some(size) = env.optional::<usize>("GATEWAY_MAX_PAYLOAD_SIZE", "a valid number of bytes") {
+        builder.gateway_max_payload_size(size);
+    }
+    if let Some(size) = env.optional::<usize>("GATEWAY_MAX_MESSAGE_SIZE", "a valid number of bytes") {
+        builder.gateway_max_message_size(size);
+    }
+    if let Some(size) = env.optional::<usize>("GATEWAY_MAX_FRAME_SIZE", "a valid number of bytes") {
+        builder.gateway_max_frame_size(size);
+    }
+    if let Some(size) = env.optional::<usize>("GATEWAY_DISPATCH_LOG_SIZE", "a valid number of dispatches") {
+        builder.gateway_dispatch_log_size(size);
+    }
+    if let Some(capacity) = env.optional::<NonZeroUsize>("GATEWAY_INBOUND_CAPACITY", "a positive number of requests") {
+        builder.gateway_inbound_capacity(capacity.get());
+    }
+}
+
+/// Read the settings of how messages are fetched and stored from the environment, leaving unset ones at their defaults.
+fn message_settings_from_env(env: &mut EnvReader, builder: &mut ConfigBuilder) {
+    if let Some(strict) = env.optional::<bool>("STRICT_MESSAGE_LIMIT", "either true or false") {
+        builder.strict_message_limit(strict);
+    }
+    if let Some(detect) = env.optional::<bool>("DETECT_MESSAGE_LANGUAGE", "either true or false") {
+        builder.detect_message_language(detect);
+    }
+    if let Some(count) = env.optional::<usize>("MAX_MESSAGE_ATTACHMENTS", "a valid number of attachments") {
+        builder.max_message_attachments(count);
+    }
+    if let Some(size) = env.optional::<usize>("MAX_MESSAGE_ATTACHMENTS_SIZE", "a valid number of bytes") {
+        builder.max_message_attachments_size(size);
+    }
+}
+
+/// Read the settings protecting against abusive signups from the environment, leaving unset ones at their defaults.
+fn signup_settings_from_env(env: &mut EnvReader, builder: &mut ConfigBuilder) {
+    let interval = env
+        .optional::<u64>("SIGNUP_INTERVAL", "a valid number of seconds")
+        .map_or_else(|| DEFAULT_SIGNUP_QUOTA.period(), Duration::from_secs);
+    let burst = env
+        .optional::<NonZeroU32>("SIGNUP_BURST", "a positive number of signups")
+        .map_or_else(|| DEFAULT_SIGNUP_QUOTA.burst(), NonZeroU32::get);
+    builder.signup_quota(RateQuota::per_period(1, interval, burst, Duration::ZERO));
+
+    if let Some(domains) = env.optional::<String>("DISPOSABLE_EMAIL_DOMAINS", "set") {
+        builder.disposable_email_domains(
+            domains
+                .split(',')
+                .map(|domain| domain.trim().to_lowercase())
+                .filter(|domain| !domain.is_empty())
+                .collect::<HashSet<_>>(),
+        );
+    }
+    if let Some(secs) = env.optional::<u64>("NEW_ACCOUNT_RESTRICTION", "a valid number of seconds") {
+        // A restriction of 0 disables it
+        builder.new_account_restriction((secs > 0).then(|| Duration::from_secs(secs)));
+    }
+}
+
+/// Read a rate limit quota from the environment, falling back to the defaults for unset variables.
+///
+/// ## Arguments
+///
+/// * `env` - The environment reader.
+/// * `prefix` - The prefix of the `_RATE`, `_BURST` and `_MAX_DELAY` variables.
+/// * `default` - The quota to fall back to.
+fn quota_from_env(env: &mut EnvReader, prefix: &str, default: RateQuota) -> RateQuota {
+    let per_second = env
+        .optional::<NonZeroU32>(&format!("{prefix}_RATE"), "a positive number of requests per second")
+        .map_or_else(|| default.requests(), NonZeroU32::get);
+    let burst = env
+        .optional::<NonZeroU32>(&format!("{prefix}_BURST"), "a positive number of requests")
+        .map_or_else(|| default.burst(), NonZeroU32::get);
+    let max_delay = env
+        .optional::<u64>(&format!("{prefix}_MAX_DELAY"), "a valid number of seconds")
+        .map_or_else(|| default.max_delay(), Duration::from_secs);
+
+    RateQuota::new(per_second, burst, max_delay)
+}
+
+/// Read how strikes escalate from the environment, leaving unset values at their defaults.
+fn standing_policy_from_env(env: &mut EnvReader) -> StandingPolicy {
+    let default = StandingPolicy::default();
+    let strike_duration = env
+        .optional::<u64>("STRIKE_DURATION", "a valid number of seconds")
+        .map_or_else(|| default.strike_duration(), Duration::from_secs);
+    let timeout_after = env
+        .optional::<NonZeroUsize>("STRIKE_TIMEOUT_AFTER", "a positive number of strikes")
+        .map_or_else(|| default.timeout_after(), NonZeroUsize::get);
+    let timeout_duration = env
+        .optional::<u64>("STRIKE_TIMEOUT_DURATION", "a valid number of seconds")
+        .map_or_else(|| default.timeout_duration(), Duration::from_secs);
+    let suspension_after = env
+        .optional::<NonZeroUsize>("STRIKE_SUSPENSION_AFTER", "a positive number of strikes")
+        .map_or_else(|| default.suspension_after(), NonZeroUsize::get);
+    let suspension_duration = env
+        .optional::<u64>("STRIKE_SUSPENSION_DURATION", "a valid number of seconds")
+        .map_or_else(|| default.suspension_duration(), Duration::from_secs);
+
+    StandingPolicy::new(
+        strike_duration,
+        timeout_after,
+        timeout_duration,
+        suspension_after,
+        suspension_duration,
+    )
+}
+
+/// Reads configuration values from environment variables, collecting all problems found along the way.
+#[derive(Debug, Default)]
+struct EnvReader {
+    problems: Vec<String>,
+}
+
+impl EnvReader {
+    /// Read and parse a required variable. Empty values count as unset.
+    ///
+    /// ## Arguments
+    ///
+    /// * `name` - The name of the variable.
+    /// * `expected` - A description of valid values, used in the reported problem.
+    fn required<T: FromStr>(&mut self, name: &str, expected: &str) -> Option<T> {
+        let value = self.optional(name, expected);
+        if value.is_none() && !Self::is_set(name) {
+            self.problem(format!("{name} environment variable must be set"));
+        }
+        value
+    }
+
+    /// Read and parse an optional variable. Empty values count as unset.
+    ///
+    /// ## Arguments
+    ///
+    /// * `name` - The name of the variable.
+    /// * `expected` - A description of valid values, used in the reported problem.
+    fn optional<T: FromStr>(&mut self, name: &str, expected: &str) -> Option<T> {
+        let value = std::env::var(name).ok().filter(|v| !v.is_empty())?;
+        let parsed = value.trim().parse().ok();
+        if parsed.is_none() {
+            self.problem(format!("{name} must be {expected}"));
+        }
+        parsed
+    }
+
+    /// Read an optional comma-separated list of user IDs.
+    fn user_ids(&mut self, name: &str) -> Option<Vec<Snowflake<User>>> {
+        let ids = self.optional::<String>(name, "set")?;
+        let parsed = ids
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>();
+        if parsed.is_err() {
+            self.problem(format!("{name} must be a comma-separated list of user IDs"));
+        }
+        parsed.ok()
+    }
+
+    /// Returns whether the variable is set to a non-empty value.
+    fn is_set(name: &str) -> bool {
+        std::env::var(name).is_ok_and(|v| !v.is_empty())
+    }
+
+    /// Record a problem with the configuration.
+    fn problem(&mut self, problem: impl Into<String>) {
+        self.problems.push(problem.into());
     }
 }
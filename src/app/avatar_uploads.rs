@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tracing::Instrument;
+
+use super::{App, ops::AVATAR_UPLOAD_BATCH_SIZE};
+
+/// The time after which pending avatar uploads are checked for, even if the uploader was not woken.
+///
+/// This picks up uploads enqueued right before the application stopped, and uploads that failed and are retried.
+pub const AVATAR_UPLOAD_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Wakes the avatar uploader whenever new avatars were accepted, so that they do not have to wait for the next sweep.
+#[derive(Debug, Default)]
+pub struct AvatarUploader {
+    notify: Notify,
+}
+
+impl AvatarUploader {
+    /// Create a new uploader signal.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wake the uploader, uploading all pending avatars.
+    ///
+    /// If the uploader is busy, it runs again once it finished, as the wakeup is stored until then.
+    pub fn wake(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Wait until the uploader is woken, or the sweep interval has elapsed.
+    async fn woken(&self) {
+        let _ = tokio::time::timeout(AVATAR_UPLOAD_SWEEP_INTERVAL, self.notify.notified()).await;
+    }
+}
+
+/// Spawn the task uploading pending avatars to S3 for the lifetime of the application.
+///
+/// Pending avatars are uploaded on startup, whenever the uploader is woken, and every [`AVATAR_UPLOAD_SWEEP_INTERVAL`].
+/// Avatars are left pending while the instance is read-only.
+pub fn spawn_uploader(app: &App) {
+    let app = app.clone();
+
+    tokio::spawn(async move {
+        loop {
+            if app.read_only().is_enabled() {
+                app.avatar_uploader().woken().await;
+                continue;
+            }
+
+            match app
+                .ops()
+                .avatars()
+                .upload_pending_avatars()
+                .instrument(tracing::debug_span!("avatar_uploader"))
+                .await
+            {
+                // A full batch means more uploads may be waiting
+                Ok(count) if count >= AVATAR_UPLOAD_BATCH_SIZE => continue,
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!("Failed to upload pending avatars: {}", e);
+                }
+            }
+
+            app.avatar_uploader().woken().await;
+        }
+    });
+}
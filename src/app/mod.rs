@@ -1,4 +1,14 @@
 pub mod appstate;
+pub mod avatar_uploads;
 pub mod ops;
+pub mod outbox;
+pub mod query_limiter;
+pub mod read_only;
+pub mod scheduler;
+pub mod startup;
+pub mod telemetry;
 
-pub use appstate::{App, ApplicationState, Config};
+pub use appstate::{
+    App, ApplicationState, BodyLimits, Config, ConfigBuilder, HardeningConfig, HeavyQuery, LimitedRoute, QueryLimit,
+    QueryLimits,
+};
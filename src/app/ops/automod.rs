@@ -0,0 +1,459 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use tracing::field::Empty;
+
+use super::{Ops, record_id};
+use crate::{
+    gateway::SendMode,
+    models::{
+        attachment::AttachmentLike,
+        automod::{AutomodMatch, AutomodRule, AutomodRuleRecord, MAX_AUTOMOD_RULES},
+        channel::{Channel, ChannelLike},
+        errors::OpsError,
+        gateway_event::GatewayEvent,
+        guild::Guild,
+        member::UserLike,
+        message::Message,
+        outbox::OutboxEntry,
+        role::Permissions,
+        snowflake::Snowflake,
+        user::User,
+    },
+};
+
+/// Operations on the auto-moderation rules of guilds and the timeouts they apply.
+#[derive(Clone, Copy)]
+pub struct AutomodOps<'a> {
+    ops: Ops<'a>,
+}
+
+impl<'a> AutomodOps<'a> {
+    /// Create a new [`AutomodOps`] sharing the components of the given [`Ops`].
+    pub const fn new(ops: Ops<'a>) -> Self {
+        Self { ops }
+    }
+
+    /// Fetch all auto-moderation rules of a guild, oldest first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to fetch the rules of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If a stored rule is invalid.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn fetch_rules(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Vec<AutomodRule>, OpsError> {
+        let records = sqlx::query_as!(
+            AutomodRuleRecord,
+            "SELECT id, guild_id, name, enabled, trigger_type, trigger_values, trigger_limit,
+                block, alert_channel_id, timeout_duration
+            FROM automod_rules WHERE guild_id = $1 ORDER BY id",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(records
+            .into_iter()
+            .map(AutomodRule::from_record)
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Fetch a single auto-moderation rule of a guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the rule belongs to.
+    /// * `rule` - The ID of the rule.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If the stored rule is invalid.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, rule_id = Empty))]
+    pub async fn fetch_rule(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        rule: impl Into<Snowflake<AutomodRule>>,
+    ) -> Result<Option<AutomodRule>, OpsError> {
+        let record = sqlx::query_as!(
+            AutomodRuleRecord,
+            "SELECT id, guild_id, name, enabled, trigger_type, trigger_values, trigger_limit,
+                block, alert_channel_id, timeout_duration
+            FROM automod_rules WHERE guild_id = $1 AND id = $2",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            record_id("rule_id", rule) as Snowflake<AutomodRule>,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(record.map(AutomodRule::from_record).transpose()?)
+    }
+
+    /// Store a new auto-moderation rule.
+    ///
+    /// ## Arguments
+    ///
+    /// * `rule` - The rule to store.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::BadRequest`] - If the guild already has [`MAX_AUTOMOD_RULES`] rules.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = %rule.guild_id(), rule_id = %rule.id()))]
+    pub async fn create_rule(&self, rule: &AutomodRule) -> Result<(), OpsError> {
+        let result = sqlx::query!(
+            "INSERT INTO automod_rules (id, guild_id, name, enabled, trigger_type, trigger_values, trigger_limit,
+                block, alert_channel_id, timeout_duration)
+            SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, $10
+            WHERE (SELECT COUNT(*) FROM automod_rules WHERE guild_id = $2) < $11",
+            rule.id() as Snowflake<AutomodRule>,
+            rule.guild_id() as Snowflake<Guild>,
+            rule.name(),
+            rule.enabled(),
+            rule.trigger_type(),
+            rule.trigger_values(),
+            rule.trigger_limit(),
+            rule.blocks(),
+            rule.alert_channel_id() as Option<Snowflake<Channel>>,
+            rule.timeout_duration().and_then(|d| i32::try_from(d).ok()),
+            MAX_AUTOMOD_RULES as i64,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(OpsError::BadRequest(format!(
+                "A guild may have at most {MAX_AUTOMOD_RULES} auto-moderation rules."
+            )));
+        }
+        Ok(())
+    }
+
+    /// Update an auto-moderation rule.
+    ///
+    /// ## Arguments
+    ///
+    /// * `rule` - The rule with the updates applied.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::NotFound`] - If the rule does not exist.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = %rule.guild_id(), rule_id = %rule.id()))]
+    pub async fn update_rule(&self, rule: &AutomodRule) -> Result<(), OpsError> {
+        let result = sqlx::query!(
+            "UPDATE automod_rules
+            SET name = $2, enabled = $3, trigger_type = $4, trigger_values = $5, trigger_limit = $6,
+                block = $7, alert_channel_id = $8, timeout_duration = $9
+            WHERE id = $1",
+            rule.id() as Snowflake<AutomodRule>,
+            rule.name(),
+            rule.enabled(),
+            rule.trigger_type(),
+            rule.trigger_values(),
+            rule.trigger_limit(),
+            rule.blocks(),
+            rule.alert_channel_id() as Option<Snowflake<Channel>>,
+            rule.timeout_duration().and_then(|d| i32::try_from(d).ok()),
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(OpsError::NotFound("Rule not found".into()));
+        }
+        Ok(())
+    }
+
+    /// Delete an auto-moderation rule of a guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the rule belongs to.
+    /// * `rule` - The ID of the rule.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the rule existed.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, rule_id = Empty))]
+    pub async fn delete_rule(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        rule: impl Into<Snowflake<AutomodRule>>,
+    ) -> Result<bool, OpsError> {
+        let result = sqlx::query!(
+            "DELETE FROM automod_rules WHERE guild_id = $1 AND id = $2",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            record_id("rule_id", rule) as Snowflake<AutomodRule>,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Check a sample message against all auto-moderation rules of a guild, including disabled ones,
+    /// without applying any of their actions.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to test the rules of.
+    /// * `content` - The content of the sample message, if any.
+    /// * `content_types` - The content types of the sample message's attachments.
+    ///
+    /// ## Returns
+    ///
+    /// The rules that would fire, oldest first.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If a stored rule is invalid.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn test_rules(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        content: Option<&str>,
+        content_types: &[String],
+    ) -> Result<Vec<AutomodMatch>, OpsError> {
+        let rules = self.fetch_rules(record_id("guild_id", guild)).await?;
+        check_rules(rules, content, content_types)
+    }
+
+    /// Check a new message against the enabled auto-moderation rules of its guild, and apply the actions
+    /// of the rules that fire. Messages of the guild's owner and of members with [`Permissions::MANAGE_MESSAGES`],
+    /// system messages and direct messages are not checked.
+    ///
+    /// Alerts are posted and timeouts applied even if the message is blocked.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The channel the message is sent in.
+    /// * `message` - The message, before it is committed.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Forbidden`] - If a rule blocked the message.
+    /// * [`OpsError::Db`] - If a database query fails.
+    /// * [`OpsError::Build`] - If a stored rule is invalid.
+    #[tracing::instrument(skip_all, fields(channel_id = %channel.id(), message_id = %message.id()))]
+    pub async fn moderate_message(&self, channel: &Channel, message: &Message) -> Result<(), OpsError> {
+        let (Some(author), Some(guild_id)) = (message.author().map(UserLike::id), channel.guild_id()) else {
+            return Ok(());
+        };
+
+        let records = sqlx::query_as!(
+            AutomodRuleRecord,
+            "SELECT r.id, r.guild_id, r.name, r.enabled, r.trigger_type, r.trigger_values, r.trigger_limit,
+                r.block, r.alert_channel_id, r.timeout_duration
+            FROM automod_rules r
+            JOIN guilds g ON g.id = r.guild_id
+            WHERE r.guild_id = $1 AND r.enabled AND g.owner_id <> $2
+            AND (
+                SELECT COALESCE(BIT_OR(ro.permissions), 0)
+                FROM member_roles mr
+                JOIN roles ro ON ro.id = mr.role_id
+                WHERE mr.guild_id = $1 AND mr.user_id = $2
+            ) & $3 = 0
+            ORDER BY r.id",
+            guild_id as Snowflake<Guild>,
+            author as Snowflake<User>,
+            Permissions::ADMINISTRATOR.union(Permissions::MANAGE_MESSAGES).bits() as i64,
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let rules = records
+            .into_iter()
+            .map(AutomodRule::from_record)
+            .collect::<Result<Vec<_>, _>>()?;
+        let content_types = message
+            .attachments()
+            .iter()
+            .map(|a| a.mime().essence_str().to_owned())
+            .collect::<Vec<_>>();
+        let matches = check_rules(rules, message.content(), &content_types)?;
+
+        if let Some(duration) = matches.iter().filter_map(|m| m.rule.timeout_duration()).max() {
+            self.time_out(guild_id, author, duration).await?;
+        }
+
+        for found in &matches {
+            if let Err(e) = self.post_alert(found, author, guild_id, channel.id()).await {
+                tracing::error!(error = ?e, rule_id = %found.rule.id(), "Failed to post auto-moderation alert");
+            }
+        }
+
+        if let Some(found) = matches.iter().find(|m| m.rule.blocks()) {
+            tracing::info!(rule_id = %found.rule.id(), "Message blocked by auto-moderation");
+            return Err(OpsError::Forbidden(format!(
+                "Message was blocked by the auto-moderation rule '{}'.",
+                found.rule.name()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Post a system message describing a match in the alert channel of its rule, if it has one.
+    async fn post_alert(
+        &self,
+        found: &AutomodMatch,
+        author: Snowflake<User>,
+        guild: Snowflake<Guild>,
+        channel: Snowflake<Channel>,
+    ) -> Result<(), OpsError> {
+        let Some(alert_channel) = found.rule.alert_channel_id() else {
+            return Ok(());
+        };
+
+        let mut alert = Message::builder()
+            .id(Snowflake::gen_new(self.ops.config))
+            .channel_id(alert_channel)
+            .content(Some(found.alert_content(author, channel)))
+            .build()?;
+        self.ops
+            .messages()
+            .resolve_channel_mentions(std::slice::from_mut(&mut alert))
+            .await?;
+
+        let announce = OutboxEntry::dispatch(&GatewayEvent::MessageCreate(alert.clone()), SendMode::ToGuild(guild));
+        self.ops.messages().commit_message_with(&alert, &[announce]).await
+    }
+
+    /// Time out a member, keeping any longer timeout already in force.
+    async fn time_out(&self, guild: Snowflake<Guild>, user: Snowflake<User>, duration: u32) -> Result<(), OpsError> {
+        sqlx::query!(
+            "INSERT INTO automod_timeouts (user_id, guild_id, until)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, guild_id) DO UPDATE SET until = GREATEST(automod_timeouts.until, EXCLUDED.until)",
+            user as Snowflake<User>,
+            guild as Snowflake<Guild>,
+            Utc::now() + TimeDelta::seconds(i64::from(duration)),
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        tracing::info!(user_id = %user, duration, "Member timed out by auto-moderation");
+        Ok(())
+    }
+
+    /// When the auto-moderation timeout of a member ends.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to check.
+    /// * `user` - The member to check.
+    ///
+    /// ## Returns
+    ///
+    /// When the timeout ends, or `None` if the member is not timed out.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, user_id = Empty))]
+    pub async fn timed_out_until(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Option<DateTime<Utc>>, OpsError> {
+        let until = sqlx::query_scalar!(
+            "SELECT until FROM automod_timeouts WHERE guild_id = $1 AND user_id = $2 AND until > $3",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            record_id("user_id", user) as Snowflake<User>,
+            Utc::now(),
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(until)
+    }
+
+    /// Fail if a member is timed out in a guild by auto-moderation.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to check.
+    /// * `user` - The member to check.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Forbidden`] - If the member is timed out, with the time the timeout ends at.
+    /// * [`OpsError::Db`] - If the database query fails.
+    pub async fn ensure_not_timed_out(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<(), OpsError> {
+        let Some(until) = self.timed_out_until(guild, user).await? else {
+            return Ok(());
+        };
+
+        Err(OpsError::Forbidden(format!(
+            "You are timed out in this guild until {}.",
+            until.to_rfc3339()
+        )))
+    }
+
+    /// Lift the auto-moderation timeout of a member.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the member is timed out in.
+    /// * `user` - The member to lift the timeout of.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the member was timed out.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, user_id = Empty))]
+    pub async fn remove_timeout(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<bool, OpsError> {
+        let result = sqlx::query!(
+            "DELETE FROM automod_timeouts WHERE guild_id = $1 AND user_id = $2 AND until > $3",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            record_id("user_id", user) as Snowflake<User>,
+            Utc::now(),
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Check a message against rules, regardless of whether they are enabled.
+///
+/// ## Returns
+///
+/// The rules that fired, in the order they were given.
+fn check_rules(
+    rules: Vec<AutomodRule>,
+    content: Option<&str>,
+    content_types: &[String],
+) -> Result<Vec<AutomodMatch>, OpsError> {
+    let mut matches = Vec::new();
+
+    for rule in rules {
+        if let Some(reason) = rule.check(content, content_types)? {
+            matches.push(AutomodMatch { rule, reason });
+        }
+    }
+    Ok(matches)
+}
@@ -0,0 +1,553 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use chrono::Utc;
+use sqlx::PgExecutor;
+use tracing::field::Empty;
+
+use super::{Ops, messages::STALE_UPLOAD_AGE, record_id};
+use crate::{
+    external::S3Service,
+    gateway::SendMode,
+    models::{
+        avatar::{AvatarKind, AvatarLike, AvatarSlot, FullAvatar, GuildAvatar, PartialAvatar, UserAvatar, UserBanner},
+        data_uri::DataUri,
+        direct_upload::{DirectUpload, DirectUploadRecord, PRESIGNED_UPLOAD_EXPIRY, verify_uploaded},
+        errors::{AppError, OpsError},
+        gateway_event::GatewayEvent,
+        snowflake::Snowflake,
+        user::User,
+    },
+};
+
+/// The maximum number of avatars uploaded in a single run of the uploader.
+pub const AVATAR_UPLOAD_BATCH_SIZE: u64 = 10;
+/// For how long an instance may upload an avatar before other instances assume it died and retry the upload.
+pub const AVATAR_UPLOAD_LEASE: Duration = Duration::from_secs(60);
+/// The number of times uploading an avatar may be attempted before it is rolled back.
+pub const MAX_AVATAR_UPLOAD_ATTEMPTS: i32 = 3;
+
+/// An avatar waiting to be uploaded, as stored in the database.
+struct PendingUpload {
+    holder_id: i64,
+    slot: i16,
+    avatar_hash: String,
+    content: Vec<u8>,
+    attempts: i32,
+}
+
+/// Operations on avatars and banners waiting to be uploaded to S3.
+///
+/// New avatars are set on their holder right away, but only replace the previous avatar in S3 once uploaded.
+/// Clients are sent a follow-up update of the holder once the upload finished, or once it was rolled back.
+#[derive(Clone, Copy)]
+pub struct AvatarOps<'a> {
+    ops: Ops<'a>,
+}
+
+impl<'a> AvatarOps<'a> {
+    /// Create a new [`AvatarOps`] sharing the components of the given [`Ops`].
+    pub const fn new(ops: Ops<'a>) -> Self {
+        Self { ops }
+    }
+
+    /// Enqueue an avatar to be uploaded in the background.
+    ///
+    /// An upload already pending for the same slot of the holder is replaced,
+    /// keeping the avatar it was to replace as the one to restore if this upload fails.
+    ///
+    /// ## Arguments
+    ///
+    /// * `executor` - The executor to use, the transaction the holder is updated in.
+    /// * `avatar` - The avatar to upload, already set on its holder.
+    /// * `previous` - The hash of the avatar it replaces, if any.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(holder_id = %avatar.holder_id(), avatar_hash = avatar.avatar_hash()))]
+    pub async fn enqueue_upload<K: AvatarKind>(
+        &self,
+        executor: impl PgExecutor<'_>,
+        avatar: &FullAvatar<K>,
+        previous: Option<&str>,
+    ) -> Result<(), OpsError> {
+        sqlx::query!(
+            "INSERT INTO avatar_uploads (holder_id, slot, avatar_hash, previous_hash, content)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (holder_id, slot)
+            DO UPDATE SET avatar_hash = EXCLUDED.avatar_hash, content = EXCLUDED.content,
+            attempts = 0, available_at = NOW()",
+            i64::from(avatar.holder_id()),
+            avatar.kind().slot() as i16,
+            avatar.avatar_hash(),
+            previous,
+            avatar.content().as_ref(),
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Cancel the upload pending for a slot of a holder, if any.
+    ///
+    /// ## Arguments
+    ///
+    /// * `executor` - The executor to use, the transaction the holder is updated in.
+    /// * `holder` - The holder of the avatar.
+    ///
+    /// ## Returns
+    ///
+    /// The hash of the avatar the cancelled upload was to replace, which is now unused.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(holder_id = %holder))]
+    pub async fn cancel_upload<K: AvatarKind>(
+        &self,
+        executor: impl PgExecutor<'_>,
+        holder: Snowflake<K::HolderType>,
+    ) -> Result<Option<String>, OpsError> {
+        let previous = sqlx::query_scalar!(
+            "DELETE FROM avatar_uploads WHERE holder_id = $1 AND slot = $2 RETURNING previous_hash",
+            i64::from(holder),
+            K::default().slot() as i16,
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        Ok(previous.flatten())
+    }
+
+    /// Wake the avatar uploader, so that freshly enqueued avatars are uploaded right away.
+    pub fn wake(&self) {
+        if let Some(uploader) = self.ops.avatar_uploader {
+            uploader.wake();
+        }
+    }
+
+    /// Start a direct upload, letting the client upload an avatar to S3 without passing it through the API.
+    ///
+    /// ## Arguments
+    ///
+    /// * `upload` - The upload to start. Its upload request will be set, valid for [`PRESIGNED_UPLOAD_EXPIRY`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::BadRequest`] - If S3 is not configured.
+    /// * [`OpsError::S3`] - If the request could not be presigned.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(upload_id = %upload.id()))]
+    pub async fn create_direct_upload(&self, upload: &mut DirectUpload) -> Result<(), OpsError> {
+        let s3 = self
+            .ops
+            .s3
+            .ok_or_else(|| OpsError::BadRequest("Direct uploads are not available on this instance.".into()))?;
+
+        let request = s3
+            .get_bucket(upload.bucket())
+            .presign_put_object(
+                upload.s3_key(),
+                upload.content_type(),
+                upload.size(),
+                PRESIGNED_UPLOAD_EXPIRY,
+            )
+            .await?;
+        upload.set_upload(request);
+
+        sqlx::query!(
+            "INSERT INTO direct_uploads (id, user_id, holder_id, slot, content_type, size)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+            upload.id() as Snowflake<DirectUpload>,
+            upload.user_id() as Snowflake<User>,
+            upload.holder_id_raw(),
+            upload.slot() as i16,
+            upload.content_type(),
+            upload.size() as i64,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetch a direct upload by ID.
+    ///
+    /// ## Returns
+    ///
+    /// The upload if found, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If the stored upload is invalid.
+    #[tracing::instrument(skip_all, fields(upload_id = Empty))]
+    pub async fn fetch_direct_upload(
+        &self,
+        upload: impl Into<Snowflake<DirectUpload>>,
+    ) -> Result<Option<DirectUpload>, OpsError> {
+        let record = sqlx::query_as!(
+            DirectUploadRecord,
+            "SELECT id, user_id, holder_id, slot, content_type, size FROM direct_uploads WHERE id = $1",
+            record_id("upload_id", upload) as Snowflake<DirectUpload>,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(record.map(DirectUpload::from_record).transpose()?)
+    }
+
+    /// Read the file of a direct upload back from S3, after checking it against the declared size and content type.
+    ///
+    /// ## Returns
+    ///
+    /// The uploaded file, to be set like an avatar sent through the API.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::BadRequest`] - If S3 is not configured.
+    /// * [`OpsError::Build`] - If the file was not uploaded, or does not match the upload.
+    /// * [`OpsError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(upload_id = %upload.id()))]
+    pub async fn read_direct_upload(&self, upload: &DirectUpload) -> Result<DataUri, OpsError> {
+        let s3 = self
+            .ops
+            .s3
+            .ok_or_else(|| OpsError::BadRequest("Direct uploads are not available on this instance.".into()))?;
+        let bucket = s3.get_bucket(upload.bucket());
+
+        let metadata = bucket.head_object(upload.s3_key()).await?;
+        verify_uploaded(metadata.as_ref(), upload.size(), upload.content_type())?;
+
+        let mime = upload
+            .content_type()
+            .parse()
+            .map_err(|_| OpsError::BadRequest("Invalid content type".into()))?;
+        Ok(DataUri::new(bucket.get_object(upload.s3_key()).await?, mime))
+    }
+
+    /// Remove a direct upload and its file, once it was finalized.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::S3`] - If deleting the file fails.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(upload_id = %upload.id()))]
+    pub async fn delete_direct_upload(&self, upload: &DirectUpload) -> Result<(), OpsError> {
+        sqlx::query!(
+            "DELETE FROM direct_uploads WHERE id = $1",
+            upload.id() as Snowflake<DirectUpload>
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        if let Some(s3) = self.ops.s3 {
+            s3.get_bucket(upload.bucket()).delete_object(upload.s3_key()).await?;
+        }
+        Ok(())
+    }
+
+    /// Discard direct uploads, and the files uploaded for them, that were started more than [`STALE_UPLOAD_AGE`] ago
+    /// without being finalized.
+    ///
+    /// ## Returns
+    ///
+    /// The number of uploads discarded.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn discard_stale_direct_uploads(&self) -> Result<u64, OpsError> {
+        let expired = sqlx::query!(
+            "DELETE FROM direct_uploads WHERE created_at < NOW() - make_interval(secs => $1)",
+            STALE_UPLOAD_AGE.as_secs_f64(),
+        )
+        .execute(self.ops.db)
+        .await?
+        .rows_affected();
+
+        let Some(s3) = self.ops.s3 else {
+            return Ok(expired);
+        };
+
+        // Files are listed rather than looked up by upload, to also catch those whose upload was never recorded
+        let cutoff = Utc::now() - STALE_UPLOAD_AGE;
+        let mut deleted = 0;
+        for bucket in [s3.users(), s3.guilds()] {
+            deleted += bucket.delete_stale_uploads(cutoff).await?;
+        }
+
+        tracing::info!(deleted, expired, "Discarded stale direct uploads");
+        Ok(expired)
+    }
+
+    /// Upload the avatars that waited the longest, up to [`AVATAR_UPLOAD_BATCH_SIZE`] of them.
+    ///
+    /// Uploads are leased for [`AVATAR_UPLOAD_LEASE`] while they run, so concurrent uploaders never upload
+    /// the same avatar. Failed uploads are retried once their lease expired,
+    /// and rolled back after [`MAX_AVATAR_UPLOAD_ATTEMPTS`] attempts.
+    ///
+    /// ## Returns
+    ///
+    /// The number of uploads attempted.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If a database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn upload_pending_avatars(&self) -> Result<u64, OpsError> {
+        let Some(s3) = self.ops.s3 else {
+            return Ok(0);
+        };
+
+        let mut count = 0;
+
+        while count < AVATAR_UPLOAD_BATCH_SIZE {
+            let Some(upload) = sqlx::query_as!(
+                PendingUpload,
+                "UPDATE avatar_uploads
+                SET attempts = attempts + 1, available_at = NOW() + make_interval(secs => $1)
+                WHERE (holder_id, slot) = (
+                    SELECT holder_id, slot FROM avatar_uploads WHERE available_at <= NOW()
+                    ORDER BY available_at LIMIT 1 FOR UPDATE SKIP LOCKED
+                )
+                RETURNING holder_id, slot, avatar_hash, content, attempts",
+                AVATAR_UPLOAD_LEASE.as_secs_f64(),
+            )
+            .fetch_optional(self.ops.db)
+            .await?
+            else {
+                break;
+            };
+
+            count += 1;
+            match AvatarSlot::try_from(upload.slot)? {
+                AvatarSlot::UserAvatar => self.upload::<UserAvatar>(s3, upload).await?,
+                AvatarSlot::UserBanner => self.upload::<UserBanner>(s3, upload).await?,
+                AvatarSlot::GuildAvatar => self.upload::<GuildAvatar>(s3, upload).await?,
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Upload a pending avatar, then replace the previous avatar with it, or roll it back if it failed too often.
+    #[tracing::instrument(skip_all, fields(holder_id = upload.holder_id, avatar_hash = upload.avatar_hash))]
+    async fn upload<K: AvatarKind>(&self, s3: &S3Service, upload: PendingUpload) -> Result<(), OpsError> {
+        let holder = Snowflake::<K::HolderType>::from(upload.holder_id);
+        let result: Result<(), AppError> = async {
+            let avatar = PartialAvatar::<K>::new(upload.avatar_hash.clone(), holder)?;
+            FullAvatar::<K>::builder()
+                .avatar_hash(upload.avatar_hash.clone())
+                .holder_id(holder)
+                .mime(avatar.mime().clone())
+                .content(Bytes::from(upload.content))
+                .build()?
+                .upload(s3)
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(()) => self.finish_upload::<K>(s3, holder, &upload.avatar_hash).await,
+            Err(e) if upload.attempts >= MAX_AVATAR_UPLOAD_ATTEMPTS => {
+                tracing::error!(error = %e, "Giving up on uploading avatar after {MAX_AVATAR_UPLOAD_ATTEMPTS} attempts");
+                self.roll_back_upload::<K>(s3, holder, &upload.avatar_hash).await
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to upload avatar, retrying later");
+                Ok(())
+            }
+        }
+    }
+
+    /// Replace the previous avatar with a freshly uploaded one, and let clients know it is available.
+    ///
+    /// If the avatar was replaced or removed while it was uploading, it is deleted again instead.
+    async fn finish_upload<K: AvatarKind>(
+        &self,
+        s3: &S3Service,
+        holder: Snowflake<K::HolderType>,
+        avatar_hash: &str,
+    ) -> Result<(), OpsError> {
+        let slot = K::default().slot();
+        let Some(previous) = sqlx::query_scalar!(
+            "DELETE FROM avatar_uploads WHERE holder_id = $1 AND slot = $2 AND avatar_hash = $3
+            RETURNING previous_hash",
+            i64::from(holder),
+            slot as i16,
+            avatar_hash,
+        )
+        .fetch_optional(self.ops.db)
+        .await?
+        else {
+            // A newer avatar was enqueued in the meantime, or this one was removed
+            delete_avatar::<K>(s3, holder, avatar_hash).await;
+            return Ok(());
+        };
+
+        if self.current_hash(slot, i64::from(holder)).await?.as_deref() != Some(avatar_hash) {
+            // The holder was deleted while the avatar was uploading
+            delete_avatar::<K>(s3, holder, avatar_hash).await;
+            return Ok(());
+        }
+
+        if let Some(previous) = previous.filter(|p| p != avatar_hash) {
+            delete_avatar::<K>(s3, holder, &previous).await;
+        }
+
+        tracing::debug!("Uploaded avatar");
+        self.announce(slot, i64::from(holder)).await
+    }
+
+    /// Restore the avatar a failed upload was to replace, and let clients know.
+    async fn roll_back_upload<K: AvatarKind>(
+        &self,
+        s3: &S3Service,
+        holder: Snowflake<K::HolderType>,
+        avatar_hash: &str,
+    ) -> Result<(), OpsError> {
+        let slot = K::default().slot();
+        let mut tx = self.ops.db.begin().await?;
+
+        let previous = sqlx::query_scalar!(
+            "DELETE FROM avatar_uploads WHERE holder_id = $1 AND slot = $2 AND avatar_hash = $3
+            RETURNING previous_hash",
+            i64::from(holder),
+            slot as i16,
+            avatar_hash,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        // Only restore the previous avatar if the failed one was not replaced in the meantime
+        let restored = match previous {
+            Some(previous) => {
+                self.restore_hash(&mut *tx, slot, i64::from(holder), avatar_hash, previous.as_deref())
+                    .await?
+            }
+            None => false,
+        };
+
+        tx.commit().await?;
+
+        // Remove whatever part of the avatar was uploaded before the upload failed
+        delete_avatar::<K>(s3, holder, avatar_hash).await;
+
+        if restored {
+            self.announce(slot, i64::from(holder)).await?;
+        }
+        Ok(())
+    }
+
+    /// The hash of the avatar currently set in a slot of a holder.
+    async fn current_hash(&self, slot: AvatarSlot, holder: i64) -> Result<Option<String>, OpsError> {
+        let hash = match slot {
+            AvatarSlot::UserAvatar => {
+                sqlx::query_scalar!("SELECT avatar_hash FROM users WHERE id = $1", holder)
+                    .fetch_optional(self.ops.db)
+                    .await?
+            }
+            AvatarSlot::UserBanner => {
+                sqlx::query_scalar!("SELECT banner_hash FROM users WHERE id = $1", holder)
+                    .fetch_optional(self.ops.db)
+                    .await?
+            }
+            AvatarSlot::GuildAvatar => {
+                sqlx::query_scalar!("SELECT avatar_hash FROM guilds WHERE id = $1", holder)
+                    .fetch_optional(self.ops.db)
+                    .await?
+            }
+        };
+
+        Ok(hash.flatten())
+    }
+
+    /// Set a slot of a holder back to the given hash, if it is still set to the failed avatar.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the holder was updated.
+    async fn restore_hash(
+        &self,
+        executor: impl PgExecutor<'_>,
+        slot: AvatarSlot,
+        holder: i64,
+        failed: &str,
+        previous: Option<&str>,
+    ) -> Result<bool, OpsError> {
+        let res = match slot {
+            AvatarSlot::UserAvatar => {
+                sqlx::query!(
+                    "UPDATE users SET avatar_hash = $3 WHERE id = $1 AND avatar_hash = $2",
+                    holder,
+                    failed,
+                    previous,
+                )
+                .execute(executor)
+                .await?
+            }
+            AvatarSlot::UserBanner => {
+                sqlx::query!(
+                    "UPDATE users SET banner_hash = $3 WHERE id = $1 AND banner_hash = $2",
+                    holder,
+                    failed,
+                    previous,
+                )
+                .execute(executor)
+                .await?
+            }
+            AvatarSlot::GuildAvatar => {
+                sqlx::query!(
+                    "UPDATE guilds SET avatar_hash = $3 WHERE id = $1 AND avatar_hash = $2",
+                    holder,
+                    failed,
+                    previous,
+                )
+                .execute(executor)
+                .await?
+            }
+        };
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Dispatch the current state of a holder whose avatar finished uploading or was rolled back.
+    async fn announce(&self, slot: AvatarSlot, holder: i64) -> Result<(), OpsError> {
+        let Some(gateway) = self.ops.gateway else {
+            return Ok(());
+        };
+
+        match slot {
+            AvatarSlot::UserAvatar | AvatarSlot::UserBanner => {
+                if let Some(user) = self.ops.users().fetch_user(holder).await? {
+                    gateway.dispatch(
+                        GatewayEvent::UserUpdate(user.to_public()),
+                        SendMode::ToMutualGuilds(user.id()),
+                    );
+                }
+            }
+            AvatarSlot::GuildAvatar => {
+                if let Some(guild) = self.ops.guilds().fetch_guild(holder).await? {
+                    let guild_id = guild.id();
+                    gateway.dispatch(GatewayEvent::GuildUpdate(guild), SendMode::ToGuild(guild_id));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Delete an avatar from S3, only logging failures, as an unused avatar left behind is harmless.
+async fn delete_avatar<K: AvatarKind>(s3: &S3Service, holder: Snowflake<K::HolderType>, avatar_hash: &str) {
+    let Ok(avatar) = PartialAvatar::<K>::new(avatar_hash.to_owned(), holder) else {
+        return;
+    };
+    if let Err(e) = avatar.delete(s3).await {
+        tracing::warn!(error = %e, avatar_hash, "Failed to delete unused avatar");
+    }
+}
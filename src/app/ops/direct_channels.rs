@@ -0,0 +1,161 @@
+use tracing::field::Empty;
+
+use super::{Ops, record_id};
+use crate::models::{
+    channel::{Channel, ChannelLike, ChannelRecord, DirectChannel},
+    errors::OpsError,
+    snowflake::Snowflake,
+    user::User,
+};
+
+/// Operations on direct message channels between two users.
+#[derive(Clone, Copy)]
+pub struct DirectChannelOps<'a> {
+    ops: Ops<'a>,
+}
+
+impl<'a> DirectChannelOps<'a> {
+    /// Create a new [`DirectChannelOps`] sharing the components of the given [`Ops`].
+    pub const fn new(ops: Ops<'a>) -> Self {
+        Self { ops }
+    }
+
+    /// Fetch the direct message channel between two users, if they opened one.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - One of the recipients.
+    /// * `other` - The other recipient.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty, other_id = Empty))]
+    pub async fn fetch_direct_channel(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        other: impl Into<Snowflake<User>>,
+    ) -> Result<Option<Channel>, OpsError> {
+        let record = sqlx::query_as!(
+            ChannelRecord,
+            r#"SELECT c.*, ARRAY(
+                SELECT user_id FROM channel_recipients WHERE channel_id = c.id ORDER BY user_id
+            ) AS "recipient_ids!"
+            FROM channels c
+            JOIN channel_recipients r ON r.channel_id = c.id
+            WHERE r.user_id = $1 AND r.other_id = $2"#,
+            record_id("user_id", user) as Snowflake<User>,
+            record_id("other_id", other) as Snowflake<User>,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(record.map(Channel::from_record))
+    }
+
+    /// Fetch all direct message channels of a user, the most recently active first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The recipient to fetch the channels of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn fetch_direct_channels(&self, user: impl Into<Snowflake<User>>) -> Result<Vec<Channel>, OpsError> {
+        let records = sqlx::query_as!(
+            ChannelRecord,
+            r#"SELECT c.*, ARRAY(
+                SELECT user_id FROM channel_recipients WHERE channel_id = c.id ORDER BY user_id
+            ) AS "recipient_ids!"
+            FROM channels c
+            JOIN channel_recipients r ON r.channel_id = c.id
+            WHERE r.user_id = $1
+            ORDER BY COALESCE(c.last_message_id, c.id) DESC"#,
+            record_id("user_id", user) as Snowflake<User>,
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(records.into_iter().map(Channel::from_record).collect())
+    }
+
+    /// Open a direct message channel between two users, or return the one they already share.
+    ///
+    /// Whether the user may message the other user is not checked here,
+    /// see [`RelationshipOps::can_open_dm`](super::RelationshipOps::can_open_dm).
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user opening the channel.
+    /// * `other` - The other recipient.
+    ///
+    /// ## Returns
+    ///
+    /// The channel, and whether it was created by this call.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::BadRequest`] - If the user tries to message themselves.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty, other_id = Empty))]
+    pub async fn open_direct_channel(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        other: impl Into<Snowflake<User>>,
+    ) -> Result<(Channel, bool), OpsError> {
+        let user_id = record_id("user_id", user);
+        let other_id = record_id("other_id", other);
+
+        if user_id == other_id {
+            return Err(OpsError::BadRequest("Cannot open direct messages with yourself".into()));
+        }
+
+        if let Some(channel) = self.fetch_direct_channel(user_id, other_id).await? {
+            return Ok((channel, false));
+        }
+
+        let channel = Channel::DirectMessage(DirectChannel::new(
+            Snowflake::gen_new(self.ops.config),
+            user_id,
+            other_id,
+        ));
+
+        let mut tx = self.ops.db.begin().await?;
+
+        sqlx::query!(
+            "INSERT INTO channels (id, guild_id, name, channel_type) VALUES ($1, NULL, '', $2)",
+            channel.id() as Snowflake<Channel>,
+            channel.channel_type(),
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // Both rows conflict if the users opened a channel concurrently, the first one to commit is kept
+        let inserted = sqlx::query!(
+            "INSERT INTO channel_recipients (channel_id, user_id, other_id)
+            VALUES ($1, $2, $3), ($1, $3, $2)
+            ON CONFLICT DO NOTHING",
+            channel.id() as Snowflake<Channel>,
+            user_id as Snowflake<User>,
+            other_id as Snowflake<User>,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        if inserted < 2 {
+            tx.rollback().await?;
+            let existing = self
+                .fetch_direct_channel(user_id, other_id)
+                .await?
+                .ok_or(OpsError::NotFound("Direct message channel not found".into()))?;
+            return Ok((existing, false));
+        }
+
+        tx.commit().await?;
+
+        Ok((channel, true))
+    }
+}
@@ -0,0 +1,397 @@
+#![expect(clippy::disallowed_methods)] // Reminders prune the push tokens FCM rejected
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use itertools::Itertools;
+use tracing::field::Empty;
+
+use super::{Ops, record_id};
+use crate::{
+    external::fcm::PushPriority,
+    models::{
+        channel::Channel,
+        errors::OpsError,
+        gateway_event::GatewayEvent,
+        guild::Guild,
+        guild_event::{GuildEvent, GuildEventRecord, GuildEventRsvp, RsvpStatus},
+        snowflake::Snowflake,
+        user::User,
+    },
+};
+
+/// Operations on the events guilds schedule and the answers of their members.
+#[derive(Clone, Copy)]
+pub struct GuildEventOps<'a> {
+    ops: Ops<'a>,
+}
+
+impl<'a> GuildEventOps<'a> {
+    /// Create a new [`GuildEventOps`] sharing the components of the given [`Ops`].
+    pub const fn new(ops: Ops<'a>) -> Self {
+        Self { ops }
+    }
+
+    /// Store a new event.
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The event to store.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(event_id = %event.id()))]
+    pub async fn create_event(&self, event: &GuildEvent) -> Result<(), OpsError> {
+        sqlx::query!(
+            "INSERT INTO guild_events (id, guild_id, creator_id, channel_id, title, description, starts_at, ends_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            event.id() as Snowflake<GuildEvent>,
+            event.guild_id() as Snowflake<Guild>,
+            event.creator_id() as Option<Snowflake<User>>,
+            event.channel_id() as Option<Snowflake<Channel>>,
+            event.title(),
+            event.description(),
+            event.starts_at(),
+            event.ends_at(),
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetch a single event of a guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the event belongs to.
+    /// * `event` - The ID of the event.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, event_id = Empty))]
+    pub async fn fetch_event(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        event: impl Into<Snowflake<GuildEvent>>,
+    ) -> Result<Option<GuildEvent>, OpsError> {
+        let record = sqlx::query_as!(
+            GuildEventRecord,
+            "SELECT id, guild_id, creator_id, channel_id, title, description, starts_at, ends_at
+            FROM guild_events WHERE guild_id = $1 AND id = $2",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            record_id("event_id", event) as Snowflake<GuildEvent>,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(record.map(GuildEvent::from_record))
+    }
+
+    /// Fetch the events of a guild that did not end yet, the ones starting first first.
+    /// Events without an end are included until they start.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to fetch the events of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn fetch_events(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Vec<GuildEvent>, OpsError> {
+        let records = sqlx::query_as!(
+            GuildEventRecord,
+            "SELECT id, guild_id, creator_id, channel_id, title, description, starts_at, ends_at
+            FROM guild_events
+            WHERE guild_id = $1 AND COALESCE(ends_at, starts_at) >= $2
+            ORDER BY starts_at, id",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            Utc::now(),
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(records.into_iter().map(GuildEvent::from_record).collect())
+    }
+
+    /// Update an event. If it was moved to start at a different time, its attendees are reminded of it again.
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The event with the updates applied.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::NotFound`] - If the event does not exist.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(event_id = %event.id()))]
+    pub async fn update_event(&self, event: &GuildEvent) -> Result<(), OpsError> {
+        let result = sqlx::query!(
+            "UPDATE guild_events
+            SET channel_id = $2, title = $3, description = $4, starts_at = $5, ends_at = $6,
+                reminded = reminded AND starts_at = $5
+            WHERE id = $1",
+            event.id() as Snowflake<GuildEvent>,
+            event.channel_id() as Option<Snowflake<Channel>>,
+            event.title(),
+            event.description(),
+            event.starts_at(),
+            event.ends_at(),
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(OpsError::NotFound("Event not found".into()));
+        }
+        Ok(())
+    }
+
+    /// Delete an event of a guild, along with all answers to it.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the event belongs to.
+    /// * `event` - The ID of the event.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the event existed.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, event_id = Empty))]
+    pub async fn delete_event(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        event: impl Into<Snowflake<GuildEvent>>,
+    ) -> Result<bool, OpsError> {
+        let result = sqlx::query!(
+            "DELETE FROM guild_events WHERE guild_id = $1 AND id = $2",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            record_id("event_id", event) as Snowflake<GuildEvent>,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Fetch the answers of members to an event, ordered by user ID.
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The ID of the event.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If a stored answer is invalid.
+    #[tracing::instrument(skip_all, fields(event_id = Empty))]
+    pub async fn fetch_rsvps(&self, event: impl Into<Snowflake<GuildEvent>>) -> Result<Vec<GuildEventRsvp>, OpsError> {
+        let records = sqlx::query!(
+            "SELECT event_id, user_id, status FROM guild_event_rsvps WHERE event_id = $1 ORDER BY user_id",
+            record_id("event_id", event) as Snowflake<GuildEvent>,
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        records
+            .into_iter()
+            .map(|r| {
+                Ok(GuildEventRsvp {
+                    event_id: r.event_id.into(),
+                    user_id: r.user_id.into(),
+                    status: r.status.try_into()?,
+                })
+            })
+            .collect()
+    }
+
+    /// Record a member's answer to an event, replacing their previous one.
+    /// The user is expected to have been checked to be a member of the event's guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The event answered.
+    /// * `user` - The member answering.
+    /// * `status` - The member's answer.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(event_id = %event.id(), user_id = Empty))]
+    pub async fn set_rsvp(
+        &self,
+        event: &GuildEvent,
+        user: impl Into<Snowflake<User>>,
+        status: RsvpStatus,
+    ) -> Result<(), OpsError> {
+        sqlx::query!(
+            "INSERT INTO guild_event_rsvps (event_id, user_id, guild_id, status)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (event_id, user_id) DO UPDATE SET status = $4",
+            event.id() as Snowflake<GuildEvent>,
+            record_id("user_id", user) as Snowflake<User>,
+            event.guild_id() as Snowflake<Guild>,
+            status as i16,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Withdraw a member's answer to an event.
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The ID of the event.
+    /// * `user` - The member withdrawing their answer.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the member had answered the event.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(event_id = Empty, user_id = Empty))]
+    pub async fn remove_rsvp(
+        &self,
+        event: impl Into<Snowflake<GuildEvent>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<bool, OpsError> {
+        let result = sqlx::query!(
+            "DELETE FROM guild_event_rsvps WHERE event_id = $1 AND user_id = $2",
+            record_id("event_id", event) as Snowflake<GuildEvent>,
+            record_id("user_id", user) as Snowflake<User>,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Remind the members going to or interested in events that start soon.
+    ///
+    /// Connected members receive a `GUILD_EVENT_REMINDER` event, the others a push notification.
+    /// Every event is only reminded of once, events that already started are skipped.
+    ///
+    /// ## Returns
+    ///
+    /// The number of events that were reminded of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::FirebaseMulti`] - If sending any of the push notifications fails.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn send_event_reminders(&self) -> Result<u64, OpsError> {
+        let now = Utc::now();
+        let lead = TimeDelta::from_std(self.ops.config.guild_event_reminder_lead()).unwrap_or(TimeDelta::MAX);
+
+        let events: Vec<GuildEvent> = sqlx::query_as!(
+            GuildEventRecord,
+            "UPDATE guild_events SET reminded = TRUE
+            WHERE NOT reminded AND starts_at <= $1
+            RETURNING id, guild_id, creator_id, channel_id, title, description, starts_at, ends_at",
+            now.checked_add_signed(lead).unwrap_or(DateTime::<Utc>::MAX_UTC),
+        )
+        .fetch_all(self.ops.db)
+        .await?
+        .into_iter()
+        .map(GuildEvent::from_record)
+        .filter(|e| e.starts_at() > now)
+        .collect();
+
+        if events.is_empty() {
+            return Ok(0);
+        }
+
+        let event_ids = events.iter().map(GuildEvent::id).collect::<Vec<_>>();
+        let mut attendees = sqlx::query!(
+            "SELECT event_id, user_id FROM guild_event_rsvps WHERE event_id = ANY($1) AND status = ANY($2)",
+            &event_ids as &[Snowflake<GuildEvent>],
+            &[RsvpStatus::Going as i16, RsvpStatus::Interested as i16],
+        )
+        .fetch_all(self.ops.db)
+        .await?
+        .into_iter()
+        .into_grouping_map_by(|r| Snowflake::<GuildEvent>::from(r.event_id))
+        .fold(Vec::new(), |mut acc, _id, r| {
+            acc.push(Snowflake::<User>::from(r.user_id));
+            acc
+        });
+
+        let user_ids = attendees.values().flatten().copied().collect::<HashSet<_>>();
+        let connected = match self.ops.gateway {
+            Some(gateway) => gateway.is_connected_multiple(user_ids.clone()).await,
+            None => HashSet::new(),
+        };
+
+        let tokens: HashMap<Snowflake<User>, Vec<String>> = match self.ops.fcm {
+            Some(_) => {
+                let offline = user_ids.difference(&connected).copied().collect::<Vec<_>>();
+                sqlx::query!(
+                    "SELECT user_id, token FROM fcm_tokens WHERE user_id = ANY($1)",
+                    &offline as &[Snowflake<User>]
+                )
+                .fetch_all(self.ops.db)
+                .await?
+                .into_iter()
+                .into_grouping_map_by(|r| Snowflake::<User>::from(r.user_id))
+                .fold(Vec::new(), |mut acc, _id, r| {
+                    acc.push(r.token);
+                    acc
+                })
+            }
+            None => HashMap::new(),
+        };
+
+        let mut errors = Vec::new();
+
+        for event in &events {
+            let Some(users) = attendees.remove(&event.id()) else {
+                continue;
+            };
+            tracing::debug!(event = %event.id(), attendees = %users.len(), "Reminding attendees of guild event");
+
+            let (online, offline): (Vec<_>, Vec<_>) = users.into_iter().partition(|u| connected.contains(u));
+
+            if let Some(gateway) = self.ops.gateway {
+                for user in online {
+                    gateway.send_to(user, GatewayEvent::GuildEventReminder(event.clone()));
+                }
+            }
+
+            if let Some(fcm) = self.ops.fcm {
+                let event_tokens = offline
+                    .iter()
+                    .filter_map(|u| tokens.get(u))
+                    .flatten()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                // Attendees asked to be reminded, so the reminder wakes their devices
+                let mut data = event.reminder_data();
+                PushPriority::High.extend_data(&mut data);
+                if let Err(e) = fcm
+                    .send_notification_to_multiple(event_tokens, None, Some(data), None, PushPriority::High)
+                    .await
+                {
+                    errors.extend(e);
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            self.ops.notifications().handle_fcm_errors(errors).await?;
+        }
+
+        Ok(events.len() as u64)
+    }
+}
@@ -0,0 +1,1804 @@
+#![expect(clippy::disallowed_methods)] // Audits changes in their transaction, and runs member searches as heavy queries
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use itertools::Itertools;
+use serde_json::json;
+use sqlx::PgExecutor;
+use tracing::{
+    Span,
+    field::{Empty, display},
+};
+
+use super::{Ops, record_id, taken_on, timed_out};
+use crate::{
+    app::HeavyQuery,
+    gateway::SendMode,
+    models::{
+        audit_log::{AuditLogAction, AuditLogEntry},
+        avatar::AvatarLike,
+        channel::{Channel, ChannelLike, ChannelRecord, TextChannel},
+        errors::OpsError,
+        gateway_event::GatewayEvent,
+        guest_link::{GuestLink, GuestLinkRecord},
+        guild::{Guild, GuildFeature, GuildRecord},
+        invite::{Invite, InviteUsage, InviteUse, is_guild_full, validate_vanity_code},
+        keyword_alert::KeywordMatcher,
+        member::{ExtendedMemberRecord, Member, MemberRecord, UserLike},
+        message::Message,
+        onboarding::{Onboarding, OnboardingOption, OnboardingQuestion, OnboardingResponses},
+        request_payloads::{CreateGuild, UpdateGuild},
+        role::Permissions,
+        snowflake::Snowflake,
+        user::User,
+    },
+};
+
+/// The maximum number of members returned when searching members by a query.
+pub const MAX_MEMBER_QUERY_LIMIT: u32 = 100;
+/// The maximum number of days a channel's retention policy may keep messages for,
+/// and a guild may keep attachments in standard storage for before archiving them.
+pub const MAX_RETENTION_DAYS: u32 = 3650;
+/// The maximum number of recent joins included in an invite's usage.
+pub const MAX_RECENT_INVITE_USES: i64 = 100;
+
+/// Operations on guilds, their channels, members, invites and onboarding.
+#[derive(Clone, Copy)]
+pub struct GuildOps<'a> {
+    ops: Ops<'a>,
+}
+
+impl<'a> GuildOps<'a> {
+    /// Create a new [`GuildOps`] sharing the components of the given [`Ops`].
+    pub const fn new(ops: Ops<'a>) -> Self {
+        Self { ops }
+    }
+
+    /// Checks if a given channel exists in the database.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel to check.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the channel exists, otherwise `false`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(channel_id = Empty))]
+    pub async fn is_channel_present(&self, channel: impl Into<Snowflake<Channel>>) -> Result<bool, OpsError> {
+        let res = sqlx::query!(
+            "SELECT EXISTS(SELECT 1 FROM channels WHERE id = $1)",
+            record_id("channel_id", channel) as Snowflake<Channel>
+        )
+        .fetch_one(self.ops.db)
+        .await?;
+
+        Ok(res.exists.unwrap_or(false))
+    }
+
+    /// Fetch a channel from the database by ID.
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the channel to fetch.
+    ///
+    /// ## Returns
+    ///
+    /// The channel if found, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(channel_id = Empty))]
+    pub async fn fetch_channel(&self, id: impl Into<Snowflake<Channel>>) -> Result<Option<Channel>, OpsError> {
+        let record = sqlx::query_as!(
+            ChannelRecord,
+            r#"SELECT *, ARRAY(
+                SELECT user_id FROM channel_recipients WHERE channel_id = channels.id ORDER BY user_id
+            ) AS "recipient_ids!"
+            FROM channels WHERE id = $1"#,
+            record_id("channel_id", id) as Snowflake<Channel>
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(record.map(Channel::from_record))
+    }
+
+    /// Create a new guild channel in the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::AlreadyTaken`] - If another channel in the guild has the same name.
+    /// * [`OpsError::BadRequest`] - If the channel belongs to no guild, or its name is invalid.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(channel_id = %channel.id(), guild_id = channel.guild_id().map(display)))]
+    pub async fn create_channel(&self, channel: &Channel) -> Result<Channel, OpsError> {
+        let Some(guild_id) = channel.guild_id() else {
+            return Err(OpsError::BadRequest("Channel must belong to a guild".into()));
+        };
+        if !(3..=32).contains(&channel.name().len()) {
+            return Err(OpsError::BadRequest(
+                "Channel name must be between 3 and 32 characters".into(),
+            ));
+        }
+
+        sqlx::query_as!(
+            ChannelRecord,
+            r#"INSERT INTO channels (id, guild_id, name, channel_type)
+            VALUES ($1, $2, $3, $4) RETURNING *, ARRAY[]::BIGINT[] AS "recipient_ids!""#,
+            channel.id() as Snowflake<Channel>,
+            guild_id as Snowflake<Guild>,
+            channel.name(),
+            channel.channel_type(),
+        )
+        .fetch_one(self.ops.db)
+        .await
+        .map(Channel::from_record)
+        .map_err(taken_on("channels_guild_id_name_key", "name"))
+    }
+
+    /// Commit this channel to the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::AlreadyTaken`] - If another channel in the guild has the same name.
+    /// * [`OpsError::BadRequest`] - If the channel's name or retention is invalid.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(channel_id = %channel.id(), guild_id = channel.guild_id().map(display)))]
+    pub async fn update_channel(&self, channel: &Channel) -> Result<(), OpsError> {
+        if !(3..=32).contains(&channel.name().len()) {
+            return Err(OpsError::BadRequest(
+                "Channel name must be between 3 and 32 characters".into(),
+            ));
+        }
+        if channel
+            .retention_days()
+            .is_some_and(|d| !(1..=MAX_RETENTION_DAYS).contains(&d))
+        {
+            return Err(OpsError::BadRequest(format!(
+                "Retention must be between 1 and {MAX_RETENTION_DAYS} days"
+            )));
+        }
+
+        sqlx::query!(
+            "UPDATE channels SET name = $2, locked = $3, retention_days = $4 WHERE id = $1",
+            channel.id() as Snowflake<Channel>,
+            channel.name(),
+            channel.locked(),
+            channel.retention_days().and_then(|d| i32::try_from(d).ok()),
+        )
+        .execute(self.ops.db)
+        .await
+        .map_err(taken_on("channels_guild_id_name_key", "name"))?;
+
+        Ok(())
+    }
+
+    /// Checks if a user may post in a channel. Only members of the channel's guild may post,
+    /// or the recipients of a direct message channel while they may still open direct messages with each other,
+    /// see [`RelationshipOps::can_open_dm`](super::RelationshipOps::can_open_dm).
+    ///
+    /// Anyone who can view a channel may post in it, unless the channel is locked.
+    /// Locked channels may only be posted in by the guild's owner and members with [`Permissions::MANAGE_MESSAGES`].
+    /// Guests may only post in their channel if the link they joined through allowed it.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The channel to post in.
+    /// * `user` - The user trying to post.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(channel_id = %channel.id(), guild_id = channel.guild_id().map(display), user_id = Empty))]
+    pub async fn can_post_in(&self, channel: &Channel, user: impl Into<Snowflake<User>>) -> Result<bool, OpsError> {
+        let user_id = record_id("user_id", user);
+        let Some(guild_id) = channel.guild_id() else {
+            if !channel.recipient_ids().contains(&user_id) {
+                return Ok(false);
+            }
+            let Some(&other_id) = channel.recipient_ids().iter().find(|&&id| id != user_id) else {
+                return Ok(false);
+            };
+            // The policy or the relationship may have changed since the channel was opened
+            return self.ops.relationships().can_open_dm(user_id, other_id).await;
+        };
+
+        let can_post = sqlx::query_scalar!(
+            r#"SELECT EXISTS(
+                SELECT 1 FROM members m
+                JOIN guilds g ON g.id = m.guild_id
+                WHERE m.guild_id = $1 AND m.user_id = $2
+                AND (g.owner_id = $2 OR (
+                    NOT $3
+                    AND (m.guest_channel_id IS NULL OR (m.guest_channel_id = $4 AND m.guest_can_post))
+                ) OR (
+                    $3
+                    AND (
+                        SELECT COALESCE(BIT_OR(r.permissions), 0)
+                        FROM member_roles mr
+                        JOIN roles r ON r.id = mr.role_id
+                        WHERE mr.guild_id = $1 AND mr.user_id = $2
+                    ) & $5 <> 0
+                ))
+            ) AS "exists!""#,
+            guild_id as Snowflake<Guild>,
+            user_id as Snowflake<User>,
+            channel.locked(),
+            channel.id() as Snowflake<Channel>,
+            Permissions::ADMINISTRATOR.union(Permissions::MANAGE_MESSAGES).bits() as i64,
+        )
+        .fetch_one(self.ops.db)
+        .await?;
+
+        Ok(can_post)
+    }
+
+    /// Deletes the channel.
+    ///
+    /// ## Locks
+    ///
+    /// * `app().db` (read)
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::S3`] - If the S3 request to delete all attachments fails.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(channel_id = Empty))]
+    pub async fn delete_channel(&self, channel: impl Into<Snowflake<Channel>>) -> Result<(), OpsError> {
+        let channel_id: Snowflake<Channel> = record_id("channel_id", channel);
+
+        self.ops.s3_run(|s3| s3.remove_all_for_channel(channel_id)).await?;
+
+        let mut tx = self.ops.db.begin().await?;
+
+        sqlx::query!(
+            "UPDATE guild_onboarding SET default_channel_ids = array_remove(default_channel_ids, $1)
+            WHERE $1 = ANY(default_channel_ids)",
+            channel_id as Snowflake<Channel>
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!("DELETE FROM channels WHERE id = $1", channel_id as Snowflake<Channel>)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Fetches a guild from the database by ID.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild to fetch.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn fetch_guild(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Option<Guild>, OpsError> {
+        let record = sqlx::query_as!(
+            GuildRecord,
+            "SELECT id, name, owner_id, avatar_hash, features, attachment_archive_days, hide_history_before_join,
+            default_notification_level
+            FROM guilds WHERE id = $1",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(record.map(Guild::from_record))
+    }
+
+    /// Create a new guild
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    ///
+    /// ## Returns
+    ///
+    /// * [`Guild`] - The created guild.
+    /// * [`Channel`] - The general text channel for the guild.
+    /// * [`Member`] - The owner of the guild.
+    ///
+    /// Note: This will also create a general text channel for the guild.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, owner_id = Empty))]
+    pub async fn create_guild(
+        &self,
+        payload: CreateGuild,
+        owner: impl Into<Snowflake<User>>,
+    ) -> Result<(Guild, Channel, Member), OpsError> {
+        if !(3..=32).contains(&payload.name.len()) {
+            return Err(OpsError::BadRequest(
+                "Guild name must be between 3 and 32 characters".to_string(),
+            ));
+        }
+
+        let guild = Guild::from_payload(self.ops.config, payload, owner);
+        Span::current()
+            .record("guild_id", display(guild.id()))
+            .record("owner_id", display(guild.owner_id()));
+        sqlx::query!(
+            "INSERT INTO guilds (id, name, owner_id)
+            VALUES ($1, $2, $3)",
+            guild.id() as Snowflake<Guild>,
+            guild.name(),
+            guild.owner_id() as Snowflake<User>,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        let member = self.create_member(&guild, guild.owner_id()).await?;
+
+        let general = TextChannel::new(guild.id().cast(), &guild, "general").into();
+        self.create_channel(&general).await?;
+        Ok((guild, general, member))
+    }
+
+    /// Commits the current state of this guild object to the database.
+    ///
+    /// A new avatar is uploaded in the background, and only available once a follow-up `GUILD_UPDATE` was dispatched.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::BadRequest`] - If the attachment archive period is out of range.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = %old_guild.id()))]
+    pub async fn update_guild(&self, payload: UpdateGuild, old_guild: &Guild) -> Result<Guild, OpsError> {
+        let mut guild = old_guild.clone();
+        let needs_s3_update = guild.update(payload)?;
+
+        if old_guild == &guild {
+            return Ok(guild);
+        }
+
+        if guild
+            .attachment_archive_days()
+            .is_some_and(|d| !(1..=MAX_RETENTION_DAYS).contains(&d))
+        {
+            return Err(OpsError::BadRequest(format!(
+                "Attachments must be archived after between 1 and {MAX_RETENTION_DAYS} days"
+            )));
+        }
+
+        let mut tx = self.ops.db.begin().await?;
+
+        if needs_s3_update {
+            Ops::check_avatar_size("Avatar", guild.avatar())?;
+            self.ops
+                .replace_avatar(&mut *tx, guild.avatar(), old_guild.avatar())
+                .await?;
+        }
+
+        let record = sqlx::query_as!(
+            GuildRecord,
+            "UPDATE guilds
+            SET name = $2, owner_id = $3, avatar_hash = $4, attachment_archive_days = $5, hide_history_before_join = $6,
+            default_notification_level = $7
+            WHERE id = $1
+            RETURNING id, name, owner_id, avatar_hash, features, attachment_archive_days, hide_history_before_join,
+            default_notification_level",
+            guild.id() as Snowflake<Guild>,
+            guild.name(),
+            guild.owner_id() as Snowflake<User>,
+            guild.avatar().map(AvatarLike::avatar_hash),
+            guild.attachment_archive_days().and_then(|d| i32::try_from(d).ok()),
+            guild.hide_history_before_join(),
+            guild.default_notification_level() as i16,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        if needs_s3_update {
+            self.ops.avatars().wake();
+        }
+        Ok(Guild::from_record(record))
+    }
+
+    /// Grant or revoke a feature of a guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to update.
+    /// * `feature` - The feature to grant or revoke.
+    /// * `enabled` - Whether the feature should be granted.
+    ///
+    /// ## Returns
+    ///
+    /// The updated guild, or `None` if it does not exist.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn set_guild_feature(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        feature: GuildFeature,
+        enabled: bool,
+    ) -> Result<Option<Guild>, OpsError> {
+        let record = sqlx::query_as!(
+            GuildRecord,
+            "UPDATE guilds
+            SET features = CASE WHEN $3 THEN array_append(array_remove(features, $2), $2) ELSE array_remove(features, $2) END
+            WHERE id = $1
+            RETURNING id, name, owner_id, avatar_hash, features, attachment_archive_days, hide_history_before_join,
+            default_notification_level",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            feature.as_str(),
+            enabled,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(record.map(Guild::from_record))
+    }
+
+    /// Deletes the guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::S3`] - If the S3 request to delete all attachments fails.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn delete_guild(&self, guild: impl Into<Snowflake<Guild>>) -> Result<(), OpsError> {
+        let guild_id: Snowflake<Guild> = record_id("guild_id", guild);
+
+        self.ops.s3_run(|s3| s3.remove_all_for_guild(guild_id)).await?;
+
+        sqlx::query!("DELETE FROM guilds WHERE id = $1", guild_id as Snowflake<Guild>)
+            .execute(self.ops.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Fetch the vanity code of a guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild.
+    ///
+    /// ## Returns
+    ///
+    /// The vanity code if the guild has claimed one, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn fetch_vanity_code(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Option<String>, OpsError> {
+        let record = sqlx::query!(
+            "SELECT code FROM guild_vanity_urls WHERE guild_id = $1",
+            record_id("guild_id", guild) as Snowflake<Guild>
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(record.map(|r| r.code))
+    }
+
+    /// Claim, change or release the vanity code of a guild.
+    /// Changes are recorded in the guild's audit log.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild.
+    /// * `user` - The user performing the change.
+    /// * `code` - The new vanity code, or `None` to release the current one.
+    ///
+    /// ## Returns
+    ///
+    /// The guild's vanity code after the update.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::AlreadyTaken`] - If the code is already claimed by another guild.
+    /// * [`OpsError::Build`] - If the code is invalid or reserved.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, user_id = Empty))]
+    pub async fn update_vanity_code(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        code: Option<String>,
+    ) -> Result<Option<String>, OpsError> {
+        let guild_id = record_id("guild_id", guild);
+
+        if let Some(code) = &code {
+            validate_vanity_code(code)?;
+        }
+
+        let mut tx = self.ops.db.begin().await?;
+
+        let old_code = sqlx::query!(
+            "SELECT code FROM guild_vanity_urls WHERE guild_id = $1 FOR UPDATE",
+            guild_id as Snowflake<Guild>
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .map(|r| r.code);
+
+        if old_code == code {
+            return Ok(code);
+        }
+
+        if let Some(code) = &code {
+            sqlx::query!(
+                "INSERT INTO guild_vanity_urls (guild_id, code)
+                VALUES ($1, $2)
+                ON CONFLICT (guild_id) DO UPDATE SET code = $2",
+                guild_id as Snowflake<Guild>,
+                code,
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(taken_on("guild_vanity_urls_code_key", "code"))?;
+        } else {
+            sqlx::query!(
+                "DELETE FROM guild_vanity_urls WHERE guild_id = $1",
+                guild_id as Snowflake<Guild>
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let entry = AuditLogEntry::new(
+            self.ops.config,
+            guild_id,
+            record_id("user_id", user),
+            AuditLogAction::VanityUrlUpdate,
+        )
+        .with_change(old_code, code.clone());
+        Ops::insert_audit_log_entry(&mut *tx, &entry).await?;
+
+        tx.commit().await?;
+
+        Ok(code)
+    }
+
+    /// Resolve an invite code to the guild it belongs to.
+    ///
+    /// ## Arguments
+    ///
+    /// * `code` - The invite or vanity code to resolve.
+    ///
+    /// ## Returns
+    ///
+    /// The invite if the code resolves to a guild, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn fetch_invite(&self, code: &str) -> Result<Option<Invite>, OpsError> {
+        let record = sqlx::query!(
+            r#"SELECT guilds.id, guilds.name, guilds.owner_id, guilds.avatar_hash, guilds.features,
+            guilds.attachment_archive_days, guilds.hide_history_before_join, guilds.default_notification_level,
+            guild_vanity_urls.code,
+            (SELECT COUNT(*) FROM members WHERE members.guild_id = guilds.id AND members.guest_channel_id IS NULL)
+                AS "member_count!"
+            FROM guild_vanity_urls
+            INNER JOIN guilds ON guilds.id = guild_vanity_urls.guild_id
+            WHERE guild_vanity_urls.code = $1"#,
+            code.to_lowercase()
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(record.map(|r| {
+            let guild = Guild::from_record(GuildRecord {
+                id: r.id.into(),
+                name: r.name,
+                owner_id: r.owner_id.into(),
+                avatar_hash: r.avatar_hash,
+                features: r.features,
+                attachment_archive_days: r.attachment_archive_days,
+                hide_history_before_join: r.hide_history_before_join,
+                default_notification_level: r.default_notification_level,
+            });
+            Invite::vanity(r.code, guild, r.member_count, self.ops.config.guild_member_limit())
+        }))
+    }
+
+    /// Store a newly created guest link.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = %link.guild_id(), channel_id = %link.channel_id()))]
+    pub async fn create_guest_link(&self, link: &GuestLink) -> Result<(), OpsError> {
+        sqlx::query!(
+            "INSERT INTO guest_links (code, guild_id, channel_id, creator_id, can_post, access_duration, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            link.code(),
+            link.guild_id() as Snowflake<Guild>,
+            link.channel_id() as Snowflake<Channel>,
+            link.creator_id() as Option<Snowflake<User>>,
+            link.can_post(),
+            i32::try_from(link.access_duration()).unwrap_or(i32::MAX),
+            link.expires_at(),
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetch a guest link by its code.
+    ///
+    /// ## Returns
+    ///
+    /// The guest link if it exists and has not expired, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn fetch_guest_link(&self, code: &str) -> Result<Option<GuestLink>, OpsError> {
+        let record = sqlx::query_as!(
+            GuestLinkRecord,
+            "SELECT * FROM guest_links WHERE code = $1 AND (expires_at IS NULL OR expires_at > $2)",
+            code,
+            Utc::now(),
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(record.map(GuestLink::from_record))
+    }
+
+    /// Fetch all guest links to a channel that have not expired.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(channel_id = Empty))]
+    pub async fn fetch_guest_links_for(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+    ) -> Result<Vec<GuestLink>, OpsError> {
+        let records = sqlx::query_as!(
+            GuestLinkRecord,
+            "SELECT * FROM guest_links WHERE channel_id = $1 AND (expires_at IS NULL OR expires_at > $2)
+            ORDER BY code",
+            record_id("channel_id", channel) as Snowflake<Channel>,
+            Utc::now(),
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(records.into_iter().map(GuestLink::from_record).collect())
+    }
+
+    /// Revoke a guest link. Guests who already joined through it keep their access until it expires.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn delete_guest_link(&self, code: &str) -> Result<(), OpsError> {
+        sqlx::query!("DELETE FROM guest_links WHERE code = $1", code)
+            .execute(self.ops.db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Add a user to the guild of a guest link as a guest.
+    ///
+    /// If the user already is a guest of the guild, their access is replaced by the one granted by the link.
+    ///
+    /// ## Arguments
+    ///
+    /// * `link` - The guest link the user is joining through.
+    /// * `user` - The user joining as a guest.
+    ///
+    /// ## Returns
+    ///
+    /// The guest member.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Conflict`] - If the user is already a full member of the guild.
+    /// * [`OpsError::NotFound`] - If the user does not exist.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = %link.guild_id(), channel_id = %link.channel_id(), user_id = Empty))]
+    pub async fn create_guest(&self, link: &GuestLink, user: impl Into<Snowflake<User>>) -> Result<Member, OpsError> {
+        let user_id = record_id("user_id", user);
+
+        let user = self
+            .ops
+            .users()
+            .fetch_user(user_id)
+            .await?
+            .ok_or(OpsError::NotFound("User does not exist.".into()))?;
+
+        let record = sqlx::query_as!(
+            MemberRecord,
+            "INSERT INTO members (user_id, guild_id, joined_at, guest_channel_id, guest_can_post, guest_expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (user_id, guild_id) DO UPDATE
+            SET guest_channel_id = $4, guest_can_post = $5, guest_expires_at = $6
+            WHERE members.guest_channel_id IS NOT NULL
+            RETURNING *",
+            user_id as Snowflake<User>,
+            link.guild_id() as Snowflake<Guild>,
+            Utc::now(),
+            link.channel_id() as Snowflake<Channel>,
+            link.can_post(),
+            link.access_expires_at(),
+        )
+        .fetch_optional(self.ops.db)
+        .await?
+        .ok_or(OpsError::Conflict("Already a member of this guild.".into()))?;
+
+        Ok(Member::from_record(user, record))
+    }
+
+    /// Remove all guests whose access has expired, and all guest links that can no longer be used.
+    ///
+    /// ## Returns
+    ///
+    /// The number of guests removed.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::GuildRemove`] - For each removed guest
+    /// * [`GatewayEvent::MemberRemove`] - For all members still in the guilds of the removed guests
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn remove_expired_guests(&self) -> Result<u64, OpsError> {
+        let now = Utc::now();
+
+        sqlx::query!("DELETE FROM guest_links WHERE expires_at <= $1", now)
+            .execute(self.ops.db)
+            .await?;
+
+        let records = sqlx::query!(
+            "DELETE FROM members m
+            USING guilds g
+            WHERE g.id = m.guild_id AND m.guest_expires_at <= $1
+            RETURNING m.user_id, g.id, g.name, g.owner_id, g.avatar_hash, g.features, g.attachment_archive_days,
+            g.hide_history_before_join, g.default_notification_level",
+            now
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        let removed = records.len() as u64;
+
+        if let Some(gateway) = self.ops.gateway {
+            for r in records {
+                let user_id: Snowflake<User> = r.user_id.into();
+                let guild = Guild::from_record(GuildRecord {
+                    id: r.id.into(),
+                    name: r.name,
+                    owner_id: r.owner_id.into(),
+                    avatar_hash: r.avatar_hash,
+                    features: r.features,
+                    attachment_archive_days: r.attachment_archive_days,
+                    hide_history_before_join: r.hide_history_before_join,
+                    default_notification_level: r.default_notification_level,
+                });
+                let guild_id = guild.id();
+
+                gateway.remove_member(user_id, guild_id);
+                gateway.send_to(user_id, GatewayEvent::GuildRemove(guild));
+                gateway.dispatch(
+                    GatewayEvent::MemberRemove { id: user_id, guild_id },
+                    SendMode::ToGuild(guild_id),
+                );
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Fetch a guild's onboarding configuration.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to fetch the onboarding of.
+    ///
+    /// ## Returns
+    ///
+    /// The guild's onboarding, which is empty if it was never configured.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn fetch_onboarding(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Onboarding, OpsError> {
+        let guild_id: Snowflake<Guild> = record_id("guild_id", guild);
+
+        let config = sqlx::query!(
+            "SELECT default_channel_ids, system_channel_id, welcome_message
+            FROM guild_onboarding WHERE guild_id = $1",
+            guild_id as Snowflake<Guild>
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        let Some(config) = config else {
+            return Ok(Onboarding::empty(guild_id));
+        };
+
+        let mut options = sqlx::query!(
+            "SELECT o.id, o.question_id, o.label, o.role_ids
+            FROM onboarding_options o
+            JOIN onboarding_questions q ON q.id = o.question_id
+            WHERE q.guild_id = $1
+            ORDER BY o.position",
+            guild_id as Snowflake<Guild>
+        )
+        .fetch_all(self.ops.db)
+        .await?
+        .into_iter()
+        .map(|r| {
+            (
+                r.question_id,
+                OnboardingOption::new(r.id.into(), r.label, r.role_ids.into_iter().map(Into::into).collect()),
+            )
+        })
+        .into_group_map();
+
+        let questions = sqlx::query!(
+            "SELECT id, prompt FROM onboarding_questions WHERE guild_id = $1 ORDER BY position",
+            guild_id as Snowflake<Guild>
+        )
+        .fetch_all(self.ops.db)
+        .await?
+        .into_iter()
+        .map(|r| OnboardingQuestion::new(r.id.into(), r.prompt, options.remove(&r.id).unwrap_or_default()))
+        .collect();
+
+        Ok(Onboarding::new(
+            guild_id,
+            config.default_channel_ids.into_iter().map(Into::into).collect(),
+            config.system_channel_id.map(Into::into),
+            config.welcome_message,
+            questions,
+        ))
+    }
+
+    /// Replace a guild's onboarding configuration.
+    ///
+    /// Answers to removed options are discarded.
+    ///
+    /// ## Arguments
+    ///
+    /// * `onboarding` - The new onboarding configuration.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::BadRequest`] - If a referenced channel is not part of the guild.
+    /// * [`OpsError::Db`] - If a database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = %onboarding.guild_id()))]
+    pub async fn update_onboarding(&self, onboarding: &Onboarding) -> Result<(), OpsError> {
+        let channel_ids: Vec<i64> = onboarding
+            .default_channel_ids()
+            .iter()
+            .chain(onboarding.system_channel_id().as_ref())
+            .map(|&id| id.into())
+            .unique()
+            .collect();
+
+        let found = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM channels WHERE guild_id = $1 AND id = ANY($2)"#,
+            onboarding.guild_id() as Snowflake<Guild>,
+            &channel_ids,
+        )
+        .fetch_one(self.ops.db)
+        .await?;
+
+        if found != channel_ids.len() as i64 {
+            return Err(OpsError::BadRequest(
+                "Onboarding channels must belong to the guild".into(),
+            ));
+        }
+
+        let question_ids: Vec<i64> = onboarding.questions().iter().map(|q| q.id().into()).collect();
+        let option_ids: Vec<i64> = onboarding.options().map(|o| o.id().into()).collect();
+
+        let mut tx = self.ops.db.begin().await?;
+
+        sqlx::query!(
+            "INSERT INTO guild_onboarding (guild_id, default_channel_ids, system_channel_id, welcome_message)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (guild_id) DO UPDATE
+            SET default_channel_ids = $2, system_channel_id = $3, welcome_message = $4",
+            onboarding.guild_id() as Snowflake<Guild>,
+            &onboarding
+                .default_channel_ids()
+                .iter()
+                .map(|&id| id.into())
+                .collect::<Vec<i64>>(),
+            onboarding.system_channel_id() as Option<Snowflake<Channel>>,
+            onboarding.welcome_message(),
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // Removing questions also removes their options, and the answers referring to them
+        sqlx::query!(
+            "DELETE FROM onboarding_questions WHERE guild_id = $1 AND id <> ALL($2)",
+            onboarding.guild_id() as Snowflake<Guild>,
+            &question_ids,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "DELETE FROM onboarding_options o
+            USING onboarding_questions q
+            WHERE q.id = o.question_id AND q.guild_id = $1 AND o.id <> ALL($2)",
+            onboarding.guild_id() as Snowflake<Guild>,
+            &option_ids,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        for (position, question) in (0..).zip(onboarding.questions()) {
+            sqlx::query!(
+                "INSERT INTO onboarding_questions (id, guild_id, prompt, position)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (id) DO UPDATE SET prompt = $3, position = $4",
+                question.id() as Snowflake<OnboardingQuestion>,
+                onboarding.guild_id() as Snowflake<Guild>,
+                question.prompt(),
+                position,
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            for (position, option) in (0..).zip(question.options()) {
+                sqlx::query!(
+                    "INSERT INTO onboarding_options (id, question_id, label, role_ids, position)
+                    VALUES ($1, $2, $3, $4, $5)
+                    ON CONFLICT (id) DO UPDATE SET question_id = $2, label = $3, role_ids = $4, position = $5",
+                    option.id() as Snowflake<OnboardingOption>,
+                    question.id() as Snowflake<OnboardingQuestion>,
+                    option.label(),
+                    &option.role_ids().iter().map(|&id| id.into()).collect::<Vec<i64>>(),
+                    position,
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Record the options a member picked while onboarding, replacing their previous answers.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the member is onboarding in.
+    /// * `user` - The member who answered.
+    /// * `option_ids` - The options the member picked.
+    ///
+    /// ## Returns
+    ///
+    /// The recorded answers, along with the roles they map to.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Build`] - If an option is not part of the guild's onboarding.
+    /// * [`OpsError::Db`] - If a database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, user_id = Empty))]
+    pub async fn set_onboarding_responses(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        option_ids: Vec<Snowflake<OnboardingOption>>,
+    ) -> Result<OnboardingResponses, OpsError> {
+        let guild_id: Snowflake<Guild> = record_id("guild_id", guild);
+        let user_id: Snowflake<User> = record_id("user_id", user);
+        let option_ids: Vec<_> = option_ids.into_iter().unique().collect();
+
+        let role_ids = self.fetch_onboarding(guild_id).await?.resolve_answers(&option_ids)?;
+
+        let mut tx = self.ops.db.begin().await?;
+
+        sqlx::query!(
+            "DELETE FROM onboarding_answers WHERE user_id = $1 AND guild_id = $2",
+            user_id as Snowflake<User>,
+            guild_id as Snowflake<Guild>,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO onboarding_answers (user_id, guild_id, option_id)
+            SELECT $1, $2, UNNEST($3::BIGINT[])",
+            user_id as Snowflake<User>,
+            guild_id as Snowflake<Guild>,
+            &option_ids.iter().map(|&id| id.into()).collect::<Vec<i64>>(),
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(OnboardingResponses::new(option_ids, role_ids))
+    }
+
+    /// Welcome a member who just joined a guild.
+    ///
+    /// The member follows the guild's default channels, starting out with everything sent before they joined read,
+    /// and the welcome message is posted in the guild's system channel, if configured.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the member joined.
+    /// * `user` - The member who joined.
+    ///
+    /// ## Returns
+    ///
+    /// The welcome message, if one was posted.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If a database query fails.
+    /// * [`OpsError::Build`] - If the welcome message could not be built.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, user_id = Empty))]
+    pub async fn onboard_member(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Option<Message>, OpsError> {
+        let user_id: Snowflake<User> = record_id("user_id", user);
+        let onboarding = self.fetch_onboarding(record_id("guild_id", guild)).await?;
+
+        if !onboarding.default_channel_ids().is_empty() {
+            sqlx::query!(
+                "INSERT INTO read_states (user_id, channel_id, message_id)
+                SELECT $1, id, COALESCE(last_message_id, 0)
+                FROM channels
+                WHERE guild_id = $2 AND id = ANY($3)
+                ON CONFLICT DO NOTHING",
+                user_id as Snowflake<User>,
+                onboarding.guild_id() as Snowflake<Guild>,
+                &onboarding
+                    .default_channel_ids()
+                    .iter()
+                    .map(|&id| id.into())
+                    .collect::<Vec<i64>>(),
+            )
+            .execute(self.ops.db)
+            .await?;
+        }
+
+        let (Some(channel_id), Some(content)) = (
+            onboarding.system_channel_id(),
+            onboarding.render_welcome_message(user_id),
+        ) else {
+            return Ok(None);
+        };
+
+        let message = Message::builder()
+            .id(Snowflake::gen_new(self.ops.config))
+            .channel_id(channel_id)
+            .content(Some(content))
+            .build()?;
+        self.ops.messages().commit_message(&message).await?;
+
+        Ok(Some(message))
+    }
+
+    /// Fetch the keywords a guild watches for in new messages.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to fetch the keywords of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn fetch_moderation_keywords(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Vec<String>, OpsError> {
+        let keywords = sqlx::query_scalar!(
+            "SELECT keyword FROM moderation_keywords WHERE guild_id = $1 ORDER BY keyword",
+            record_id("guild_id", guild) as Snowflake<Guild>
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(keywords)
+    }
+
+    /// Replace the keywords a guild watches for in new messages.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to update the keywords of.
+    /// * `keywords` - The new keywords, already normalized.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn set_moderation_keywords(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        keywords: &[String],
+    ) -> Result<(), OpsError> {
+        let guild_id = record_id("guild_id", guild);
+        let mut tx = self.ops.db.begin().await?;
+
+        sqlx::query!(
+            "DELETE FROM moderation_keywords WHERE guild_id = $1",
+            guild_id as Snowflake<Guild>
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO moderation_keywords (guild_id, keyword)
+            SELECT $1, keyword FROM UNNEST($2::TEXT[]) AS keyword",
+            guild_id as Snowflake<Guild>,
+            keywords
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        if let Some(cache) = self.ops.keyword_matchers {
+            cache.invalidate(guild_id);
+        }
+        Ok(())
+    }
+
+    /// Subscribe or unsubscribe a moderator from the keyword alerts of a guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to change the subscription in.
+    /// * `user` - The moderator to change the subscription of.
+    /// * `subscribed` - Whether the moderator should receive keyword alerts.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails, or the user is not a member of the guild.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, user_id = Empty, subscribed))]
+    pub async fn set_keyword_alert_subscription(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        subscribed: bool,
+    ) -> Result<(), OpsError> {
+        let guild_id = record_id("guild_id", guild);
+        let user_id = record_id("user_id", user);
+
+        if subscribed {
+            sqlx::query!(
+                "INSERT INTO keyword_alert_subscribers (guild_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                guild_id as Snowflake<Guild>,
+                user_id as Snowflake<User>,
+            )
+            .execute(self.ops.db)
+            .await?;
+        } else {
+            sqlx::query!(
+                "DELETE FROM keyword_alert_subscribers WHERE guild_id = $1 AND user_id = $2",
+                guild_id as Snowflake<Guild>,
+                user_id as Snowflake<User>,
+            )
+            .execute(self.ops.db)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Fetch the compiled keyword watch list of a guild, compiling and caching it if needed.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If the keywords could not be compiled.
+    async fn keyword_matcher(&self, guild: Snowflake<Guild>) -> Result<Arc<KeywordMatcher>, OpsError> {
+        if let Some(matcher) = self.ops.keyword_matchers.and_then(|cache| cache.get(guild)) {
+            return Ok(matcher);
+        }
+
+        let matcher = Arc::new(KeywordMatcher::new(self.fetch_moderation_keywords(guild).await?)?);
+
+        if let Some(cache) = self.ops.keyword_matchers {
+            cache.insert(guild, Arc::clone(&matcher));
+        }
+        Ok(matcher)
+    }
+
+    /// Match a new message against the keyword watch list of its guild,
+    /// alerting all subscribed moderators who can view the channel if any keyword is found.
+    /// Messages in direct message channels are not matched.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The channel the message was sent in.
+    /// * `message` - The new message.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If a database query fails.
+    /// * [`OpsError::Build`] - If the keywords could not be compiled.
+    #[tracing::instrument(skip_all, fields(channel_id = %channel.id(), message_id = %message.id()))]
+    pub async fn dispatch_keyword_alerts(&self, channel: &Channel, message: &Message) -> Result<(), OpsError> {
+        let (Some(gateway), Some(content), Some(guild_id)) = (self.ops.gateway, message.content(), channel.guild_id())
+        else {
+            return Ok(());
+        };
+
+        let matcher = self.keyword_matcher(guild_id).await?;
+        let keywords = matcher.find(content);
+
+        if keywords.is_empty() {
+            return Ok(());
+        }
+
+        let subscribers = sqlx::query_scalar!(
+            "SELECT s.user_id
+            FROM keyword_alert_subscribers s
+            JOIN members m ON m.user_id = s.user_id AND m.guild_id = s.guild_id
+            WHERE s.guild_id = $1
+                AND (m.guest_channel_id IS NULL OR m.guest_channel_id = $2)
+                AND s.user_id IS DISTINCT FROM $3",
+            guild_id as Snowflake<Guild>,
+            channel.id() as Snowflake<Channel>,
+            message.author().map(UserLike::id) as Option<Snowflake<User>>,
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        let keywords: Vec<String> = keywords.into_iter().map(str::to_string).collect();
+
+        for subscriber in subscribers {
+            gateway.send_to(
+                Snowflake::<User>::from(subscriber),
+                GatewayEvent::KeywordAlert {
+                    guild_id,
+                    channel_id: channel.id(),
+                    message_id: message.id(),
+                    author_id: message.author().map(UserLike::id),
+                    keywords: keywords.clone(),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Fetch the owner of the guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::NotFound`] - If the guild was deleted or changed owners since it was fetched.
+    /// * [`OpsError::Build`] - If the member could not be built.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = %guild.id()))]
+    pub async fn fetch_guild_owner(&self, guild: &Guild) -> Result<Member, OpsError> {
+        self.fetch_member(guild.owner_id(), guild)
+            .await?
+            .ok_or_else(|| OpsError::NotFound("Guild owner not found".into()))
+    }
+
+    /// Fetch all members that are in the guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn fetch_members_for(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Vec<Member>, OpsError> {
+        let records = sqlx::query_as!(
+            ExtendedMemberRecord,
+            "SELECT members.*, users.username, users.display_name, users.avatar_hash, users.banner_hash, users.last_presence
+            FROM members
+            INNER JOIN users ON users.id = members.user_id
+            WHERE members.guild_id = $1",
+            record_id("guild_id", guild) as Snowflake<Guild>
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        records
+            .into_iter()
+            .map(Member::from_extended_record)
+            .collect::<Result<_, _>>()
+            .map_err(Into::into)
+    }
+
+    /// Fetch the members of a guild, optionally filtered by a query, ordered by their user ID.
+    ///
+    /// This is a [`HeavyQuery::MemberSearch`]. If too many searches are running, this waits for one of them to finish.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to fetch the members of.
+    /// * `query` - If set, only members whose username, display name or nickname
+    ///   starts with this (case-insensitively) are returned, at most [`MAX_MEMBER_QUERY_LIMIT`].
+    /// * `after` - Only return members whose user ID is greater than this.
+    /// * `limit` - The maximum number of members to return.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Unavailable`] - If the search exceeded its statement timeout.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn search_members(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        query: Option<&str>,
+        after: Option<Snowflake<User>>,
+        limit: Option<u32>,
+    ) -> Result<Vec<Member>, OpsError> {
+        let limit = match query {
+            Some(_) => Some(limit.unwrap_or(MAX_MEMBER_QUERY_LIMIT).min(MAX_MEMBER_QUERY_LIMIT)),
+            None => limit,
+        };
+        // Escape LIKE wildcards so the query is matched literally
+        let pattern = query.map(|q| format!("{}%", q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")));
+
+        // Searches are cheap with the trigram indexes, so they are queued instead of rejected
+        let _permit = self.ops.acquire_query(HeavyQuery::MemberSearch).await;
+        let mut tx = self.ops.begin_heavy(HeavyQuery::MemberSearch).await?;
+
+        let records = sqlx::query_as!(
+            ExtendedMemberRecord,
+            "SELECT members.*, users.username, users.display_name, users.avatar_hash, users.banner_hash, users.last_presence
+            FROM members
+            INNER JOIN users ON users.id = members.user_id
+            WHERE members.guild_id = $1
+            AND ($2::TEXT IS NULL
+                OR users.username ILIKE $2
+                OR users.display_name ILIKE $2
+                OR members.nickname ILIKE $2)
+            AND members.user_id > $4
+            ORDER BY members.user_id
+            LIMIT $3",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            pattern,
+            limit.map(i64::from),
+            after.map_or(0, i64::from),
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(timed_out)?;
+        tx.commit().await?;
+
+        records
+            .into_iter()
+            .map(Member::from_extended_record)
+            .collect::<Result<_, _>>()
+            .map_err(Into::into)
+    }
+
+    /// Fetch all channels that are in the guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn fetch_channels_for(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Vec<Channel>, OpsError> {
+        let records = sqlx::query_as!(
+            ChannelRecord,
+            r#"SELECT *, ARRAY[]::BIGINT[] AS "recipient_ids!" FROM channels WHERE guild_id = $1"#,
+            record_id("guild_id", guild) as Snowflake<Guild>
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(records.into_iter().map(Channel::from_record).collect())
+    }
+
+    /// Fetch all channels in the guild that the member can view.
+    /// Guests can only view the channel they were invited to.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to fetch the channels of.
+    /// * `user` - The member to fetch the channels for. If they are not a member, no channels are returned.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, user_id = Empty))]
+    pub async fn fetch_channels_visible_to(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Vec<Channel>, OpsError> {
+        let records = sqlx::query_as!(
+            ChannelRecord,
+            r#"SELECT c.*, ARRAY[]::BIGINT[] AS "recipient_ids!" FROM channels c
+            JOIN channel_visibility v ON v.channel_id = c.id AND v.user_id = $2
+            WHERE c.guild_id = $1"#,
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            record_id("user_id", user) as Snowflake<User>,
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(records.into_iter().map(Channel::from_record).collect())
+    }
+
+    /// Adds a member to the guild. If the user is a guest of the guild, they are promoted to a full member.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails, or if the user is already a full member.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, user_id = Empty))]
+    pub async fn create_member(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Member, OpsError> {
+        let user_id = record_id("user_id", user);
+
+        let user = self
+            .ops
+            .users()
+            .fetch_user(user_id)
+            .await?
+            .ok_or(OpsError::NotFound("User does not exist.".into()))?;
+
+        let record = Self::insert_member(self.ops.db, record_id("guild_id", guild), user_id).await?;
+        Ok(Member::from_record(user, record))
+    }
+
+    /// Adds a member to the invite's guild, recording that they joined through it.
+    /// If the user is a guest of the guild, they are promoted to a full member.
+    ///
+    /// The join is also recorded in the guild's audit log, along with the invite's total number of uses.
+    ///
+    /// ## Arguments
+    ///
+    /// * `invite` - The invite the user joined through.
+    /// * `user` - The user joining the guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::NotFound`] - If the user or the guild does not exist.
+    /// * [`OpsError::GuildFull`] - If the guild has reached its member limit.
+    /// * [`OpsError::Db`] - If the database query fails, or if the user is already a full member.
+    #[tracing::instrument(skip_all, fields(guild_id = %invite.guild().id(), user_id = Empty))]
+    pub async fn create_member_via_invite(
+        &self,
+        invite: &Invite,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Member, OpsError> {
+        let user_id = record_id("user_id", user);
+        let guild_id = invite.guild().id();
+
+        let user = self
+            .ops
+            .users()
+            .fetch_user(user_id)
+            .await?
+            .ok_or(OpsError::NotFound("User does not exist.".into()))?;
+
+        let mut tx = self.ops.db.begin().await?;
+
+        if let Some(limit) = self.ops.config.guild_member_limit() {
+            // Lock the guild so that concurrent joins can not push it past its limit
+            sqlx::query!(
+                "SELECT id FROM guilds WHERE id = $1 FOR UPDATE",
+                guild_id as Snowflake<Guild>
+            )
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| OpsError::NotFound("Guild does not exist.".into()))?;
+
+            let member_count = sqlx::query_scalar!(
+                r#"SELECT COUNT(*) AS "count!" FROM members
+                WHERE guild_id = $1 AND guest_channel_id IS NULL AND user_id <> $2"#,
+                guild_id as Snowflake<Guild>,
+                user_id as Snowflake<User>,
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+
+            if is_guild_full(member_count, Some(limit)) {
+                return Err(OpsError::GuildFull);
+            }
+        }
+
+        let record = Self::insert_member(&mut *tx, guild_id, user_id).await?;
+
+        sqlx::query!(
+            "INSERT INTO invite_uses (guild_id, code, user_id, used_at) VALUES ($1, $2, $3, $4)",
+            guild_id as Snowflake<Guild>,
+            invite.code(),
+            user_id as Snowflake<User>,
+            record.joined_at,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let total_uses = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM invite_uses WHERE guild_id = $1 AND code = $2"#,
+            guild_id as Snowflake<Guild>,
+            invite.code(),
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let entry = AuditLogEntry::new(self.ops.config, guild_id, user_id, AuditLogAction::InviteUse)
+            .with_target(user_id)
+            .with_change(
+                None,
+                Some(json!({ "code": invite.code(), "uses": total_uses }).to_string()),
+            );
+        Ops::insert_audit_log_entry(&mut *tx, &entry).await?;
+
+        tx.commit().await?;
+
+        Ok(Member::from_record(user, record))
+    }
+
+    /// Insert a member using the given executor, promoting guests to full members.
+    async fn insert_member(
+        executor: impl PgExecutor<'_>,
+        guild_id: Snowflake<Guild>,
+        user_id: Snowflake<User>,
+    ) -> Result<MemberRecord, OpsError> {
+        let record = sqlx::query_as!(
+            MemberRecord,
+            "INSERT INTO members (user_id, guild_id, joined_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, guild_id) DO UPDATE
+            SET guest_channel_id = NULL, guest_can_post = FALSE, guest_expires_at = NULL
+            WHERE members.guest_channel_id IS NOT NULL
+            RETURNING *",
+            user_id as Snowflake<User>,
+            guild_id as Snowflake<Guild>,
+            Utc::now(),
+        )
+        .fetch_one(executor)
+        .await?;
+        Ok(record)
+    }
+
+    /// Fetch the usage of an invite code in a guild.
+    ///
+    /// Uses are tracked per code, so they are retained if the guild changes or releases its vanity URL.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the code belongs or belonged to.
+    /// * `code` - The invite code.
+    ///
+    /// ## Returns
+    ///
+    /// The usage of the code, or `None` if it was never used in the guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn fetch_invite_usage(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        code: &str,
+    ) -> Result<Option<InviteUsage>, OpsError> {
+        let guild_id = record_id("guild_id", guild);
+        let code = code.to_lowercase();
+
+        let uses = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM invite_uses WHERE guild_id = $1 AND code = $2"#,
+            guild_id as Snowflake<Guild>,
+            code,
+        )
+        .fetch_one(self.ops.db)
+        .await?;
+
+        if uses == 0 {
+            return Ok(None);
+        }
+
+        let recent_uses = sqlx::query!(
+            "SELECT user_id, used_at FROM invite_uses
+            WHERE guild_id = $1 AND code = $2
+            ORDER BY used_at DESC, id DESC
+            LIMIT $3",
+            guild_id as Snowflake<Guild>,
+            code,
+            MAX_RECENT_INVITE_USES,
+        )
+        .fetch_all(self.ops.db)
+        .await?
+        .into_iter()
+        .map(|r| InviteUse {
+            user_id: r.user_id.map(Into::into),
+            used_at: r.used_at,
+        })
+        .collect();
+
+        Ok(Some(InviteUsage {
+            code,
+            guild_id,
+            uses,
+            recent_uses,
+        }))
+    }
+
+    /// Removes a member from a guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Forbidden`] - If the member is the owner of the guild.
+    ///
+    /// Note: If the member is the owner of the guild, this will fail.
+    #[tracing::instrument(skip_all, fields(guild_id = %guild.id(), user_id = Empty))]
+    pub async fn delete_member(&self, guild: &Guild, user: impl Into<Snowflake<User>>) -> Result<(), OpsError> {
+        let user_id = record_id("user_id", user);
+        if guild.owner_id() == user_id {
+            return Err(OpsError::Forbidden("Cannot remove owner from guild".into()));
+        }
+
+        sqlx::query!(
+            "DELETE FROM members WHERE user_id = $1 AND guild_id = $2",
+            user_id as Snowflake<User>,
+            guild.id() as Snowflake<Guild>,
+        )
+        .execute(self.ops.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Fetch a member from the database by id and guild id.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user to fetch.
+    /// * `guild` - The ID of the guild the user is in.
+    ///
+    /// ## Returns
+    ///
+    /// The member if found, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If the member could not be built.
+    #[tracing::instrument(skip_all, fields(user_id = Empty, guild_id = Empty))]
+    pub async fn fetch_member(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        guild: impl Into<Snowflake<Guild>>,
+    ) -> Result<Option<Member>, OpsError> {
+        let record = sqlx::query_as!(
+            ExtendedMemberRecord,
+            "SELECT members.*, users.username, users.display_name, users.avatar_hash, users.banner_hash, users.last_presence
+            FROM members
+            INNER JOIN users ON users.id = members.user_id
+            WHERE members.user_id = $1 AND members.guild_id = $2",
+            record_id("user_id", user) as Snowflake<User>,
+            record_id("guild_id", guild) as Snowflake<Guild>,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        record.map(Member::from_extended_record).transpose().map_err(Into::into)
+    }
+
+    /// Returns whether the user is a member of the guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild.
+    /// * `user` - The ID of the user.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the user is a member of the guild, otherwise `false`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, user_id = Empty))]
+    pub async fn has_member(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<bool, OpsError> {
+        let res = sqlx::query!(
+            "SELECT EXISTS(SELECT 1 FROM members WHERE user_id = $1 AND guild_id = $2)",
+            record_id("user_id", user) as Snowflake<User>,
+            record_id("guild_id", guild) as Snowflake<Guild>,
+        )
+        .fetch_one(self.ops.db)
+        .await?;
+
+        Ok(res.exists.unwrap_or(false))
+    }
+
+    /// Commit the member to the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = %member.user().id(), guild_id = %member.guild_id()))]
+    pub async fn update_member(&self, member: &Member) -> Result<(), OpsError> {
+        sqlx::query!(
+            "INSERT INTO members (user_id, guild_id, nickname, joined_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id, guild_id) DO UPDATE
+            SET nickname = $3, joined_at = $4",
+            member.user().id() as Snowflake<User>,
+            member.guild_id() as Snowflake<Guild>,
+            member.nickname().as_ref(),
+            member.joined_at()
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        //self.app.ops().update_user(member.user()).await?;
+
+        Ok(())
+    }
+
+    /// Fetch all guilds that this user is a member of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn fetch_guilds_for(&self, user: impl Into<Snowflake<User>>) -> Result<Vec<Guild>, OpsError> {
+        let records = sqlx::query_as!(
+            GuildRecord,
+            "SELECT guilds.id, guilds.name, guilds.owner_id, guilds.avatar_hash, guilds.features, guilds.attachment_archive_days,
+            guilds.hide_history_before_join, guilds.default_notification_level
+            FROM guilds
+            INNER JOIN members ON members.guild_id = guilds.id
+            WHERE members.user_id = $1",
+            record_id("user_id", user) as Snowflake<User>
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(records.into_iter().map(Guild::from_record).collect())
+    }
+
+    /// Fetch all guild IDs that this user is a member of.
+    /// This is a more efficient version of [`Self::fetch_guilds_for`] if you only need the IDs.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to fetch guild IDs for.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn fetch_guild_ids_for(
+        &self,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Vec<Snowflake<Guild>>, OpsError> {
+        let records = sqlx::query!(
+            "SELECT guild_id
+            FROM members
+            WHERE user_id = $1",
+            record_id("user_id", user) as Snowflake<User>
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(records.into_iter().map(|r| r.guild_id.into()).collect())
+    }
+}
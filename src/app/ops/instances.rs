@@ -0,0 +1,170 @@
+use std::{collections::HashSet, time::Duration};
+
+use chrono::Utc;
+use tracing::field::Empty;
+use uuid::Uuid;
+
+use super::{Ops, record_id};
+use crate::models::{
+    errors::OpsError,
+    snowflake::Snowflake,
+    user::{Presence, User},
+};
+
+/// The time between two heartbeats of an instance.
+pub const INSTANCE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The time after which an instance that stopped heartbeating is considered dead.
+pub const INSTANCE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Operations tracking which users are connected to the gateway of which instance,
+/// so that users connected to an instance that died are not shown as online forever.
+#[derive(Clone, Copy)]
+pub struct InstanceOps<'a> {
+    ops: Ops<'a>,
+}
+
+impl<'a> InstanceOps<'a> {
+    /// Create a new [`InstanceOps`] sharing the components of the given [`Ops`].
+    pub const fn new(ops: Ops<'a>) -> Self {
+        Self { ops }
+    }
+
+    /// Record that a user is connected to the gateway of an instance.
+    ///
+    /// ## Arguments
+    ///
+    /// * `instance` - The ID of the instance the user is connected to.
+    /// * `user` - The user that connected.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn record_connection(&self, instance: Uuid, user: impl Into<Snowflake<User>>) -> Result<(), OpsError> {
+        sqlx::query!(
+            "INSERT INTO gateway_connections (user_id, instance_id)
+            VALUES ($1, $2)
+            ON CONFLICT DO NOTHING",
+            record_id("user_id", user) as Snowflake<User>,
+            instance.to_string(),
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Mark an instance as alive, and forget the users that are no longer connected to it.
+    ///
+    /// ## Arguments
+    ///
+    /// * `instance` - The ID of the instance.
+    ///
+    /// ## Returns
+    ///
+    /// The number of users that disconnected since the last heartbeat.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip(self))]
+    pub async fn heartbeat(&self, instance: Uuid) -> Result<u64, OpsError> {
+        let instance = instance.to_string();
+
+        sqlx::query!(
+            "INSERT INTO gateway_instances (id, heartbeat_at)
+            VALUES ($1, $2)
+            ON CONFLICT (id) DO UPDATE SET heartbeat_at = EXCLUDED.heartbeat_at",
+            instance,
+            Utc::now().timestamp(),
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        let Some(gateway) = self.ops.gateway else {
+            return Ok(0);
+        };
+
+        let recorded: HashSet<Snowflake<User>> = sqlx::query_scalar!(
+            "SELECT user_id FROM gateway_connections WHERE instance_id = $1",
+            instance,
+        )
+        .fetch_all(self.ops.db)
+        .await?
+        .into_iter()
+        .map(Snowflake::from)
+        .collect();
+
+        let connected = gateway.is_connected_multiple(recorded.clone()).await;
+        let disconnected: Vec<i64> = recorded.difference(&connected).map(|id| (*id).into()).collect();
+
+        let removed = sqlx::query!(
+            "DELETE FROM gateway_connections WHERE instance_id = $1 AND user_id = ANY($2)",
+            instance,
+            &disconnected,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(removed.rows_affected())
+    }
+
+    /// Forget instances that stopped heartbeating, and show the users that were connected to them as offline.
+    ///
+    /// Users that are still connected through another instance, or chose to appear offline, are left alone.
+    ///
+    /// ## Returns
+    ///
+    /// The number of users shown as offline.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip(self))]
+    pub async fn reset_stale_presences(&self) -> Result<u64, OpsError> {
+        let cutoff = Utc::now().timestamp() - INSTANCE_TIMEOUT.as_secs().cast_signed();
+
+        // All parts of the statement see the rows as they were before it, so stale rows are still visible below
+        let stale: HashSet<Snowflake<User>> = sqlx::query_scalar!(
+            r#"WITH stale_instances AS (
+                DELETE FROM gateway_instances WHERE heartbeat_at < $1
+            ), orphaned AS (
+                DELETE FROM gateway_connections c
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM gateway_instances i WHERE i.id = c.instance_id AND i.heartbeat_at >= $1
+                )
+                RETURNING c.user_id
+            )
+            SELECT DISTINCT o.user_id AS "user_id!" FROM orphaned o
+            JOIN users u ON u.id = o.user_id
+            WHERE u.last_presence <> $2
+            AND NOT EXISTS (
+                SELECT 1 FROM gateway_connections c
+                JOIN gateway_instances i ON i.id = c.instance_id
+                WHERE c.user_id = o.user_id AND i.heartbeat_at >= $1
+            )"#,
+            cutoff,
+            Presence::Offline as i16,
+        )
+        .fetch_all(self.ops.db)
+        .await?
+        .into_iter()
+        .map(Snowflake::from)
+        .collect();
+
+        let Some(gateway) = self.ops.gateway else {
+            return Ok(stale.len() as u64);
+        };
+
+        // Users may have reconnected to this instance before it noticed
+        let connected = gateway.is_connected_multiple(stale.clone()).await;
+        let offline: Vec<_> = stale.difference(&connected).copied().collect();
+
+        for user_id in &offline {
+            self.ops.users().dispatch_presence(*user_id, Presence::Offline).await?;
+        }
+
+        Ok(offline.len() as u64)
+    }
+}
@@ -0,0 +1,1935 @@
+#![expect(clippy::disallowed_methods)] // Enqueues events with the messages they follow, and runs jumps to messages as heavy queries
+
+use std::{collections::HashMap, time::Duration};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{Stream, TryStreamExt};
+use itertools::Itertools;
+use sqlx::{PgConnection, PgExecutor};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{Instrument, field::Empty};
+
+use super::{Ops, OutboxOps, record_id, timed_out};
+use crate::{
+    app::HeavyQuery,
+    external::{s3::KEYSPACE_VERSION, scanner::ScanVerdict},
+    gateway::SendMode,
+    models::{
+        active_channel::ActiveChannel,
+        attachment::{Attachment, AttachmentLike, FullAttachment, PartialAttachment},
+        audit_log::{AuditLogAction, AuditLogEntry},
+        channel::{Channel, ChannelRecord},
+        direct_upload::{PRESIGNED_UPLOAD_EXPIRY, verify_uploaded},
+        errors::OpsError,
+        gateway_event::GatewayEvent,
+        guild::Guild,
+        member::UserLike,
+        message::{ChannelMention, ExtendedMessageRecord, Message},
+        outbox::OutboxEntry,
+        request_payloads::UpdateMessage,
+        role::Permissions,
+        snowflake::Snowflake,
+        standing::Strike,
+        upload_session::{PART_RESERVATION, UploadSession, UploadSessionRecord},
+        user::User,
+    },
+};
+
+/// The number of messages returned by a single fetch if the client did not ask for a specific number.
+pub const DEFAULT_MESSAGE_QUERY_LIMIT: u32 = 50;
+/// The maximum number of messages returned by a single fetch.
+pub const MAX_MESSAGE_QUERY_LIMIT: u32 = 100;
+/// The number of times scanning an attachment may fail before it is dropped from the scan queue.
+pub const MAX_SCAN_ATTEMPTS: i32 = 5;
+/// The maximum number of exported messages buffered ahead of a slow consumer.
+pub const EXPORT_BUFFER_SIZE: usize = 64;
+/// The maximum number of messages removed from a channel in a single statement when enforcing retention.
+const RETENTION_BATCH_SIZE: i64 = 500;
+/// The maximum number of attachments fetched at once when moving them to archive storage.
+const ARCHIVE_BATCH_SIZE: i64 = 100;
+
+/// The age after which unfinished upload sessions and multipart uploads are considered abandoned and discarded.
+pub const STALE_UPLOAD_AGE: Duration = Duration::from_secs(3600 * 24 /* 1 day */);
+
+/// Operations on messages, their attachments and upload sessions.
+#[derive(Clone, Copy)]
+pub struct MessageOps<'a> {
+    ops: Ops<'a>,
+}
+
+impl<'a> MessageOps<'a> {
+    /// Create a new [`MessageOps`] sharing the components of the given [`Ops`].
+    pub const fn new(ops: Ops<'a>) -> Self {
+        Self { ops }
+    }
+
+    /// Recompute the message statistics of all channels from the messages they contain.
+    ///
+    /// The statistics are maintained as messages are created and deleted,
+    /// this is only needed to backfill them for existing data or to repair drift.
+    ///
+    /// ## Returns
+    ///
+    /// The number of channels whose statistics were corrected.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn reconcile_channel_stats(&self) -> Result<u64, OpsError> {
+        let res = sqlx::query!(
+            "UPDATE channels c
+            SET message_count = s.message_count, last_message_id = s.last_message_id
+            FROM (
+                SELECT c.id, COUNT(m.id) AS message_count, MAX(m.id) AS last_message_id
+                FROM channels c
+                LEFT JOIN messages m ON m.channel_id = c.id
+                GROUP BY c.id
+            ) s
+            WHERE c.id = s.id
+            AND (c.message_count, c.last_message_id) IS DISTINCT FROM (s.message_count, s.last_message_id)"
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(res.rows_affected())
+    }
+
+    /// Resolve the number of messages a fetch returns at most, see [`Config::strict_message_limit`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `limit` - The number of messages the client asked for, if any.
+    ///
+    /// ## Returns
+    ///
+    /// The limit to apply, between 1 and [`MAX_MESSAGE_QUERY_LIMIT`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::BadRequest`] - If the limit is out of range and out of range limits are not clamped.
+    ///
+    /// [`Config::strict_message_limit`]: crate::app::Config::strict_message_limit
+    pub fn resolve_message_limit(&self, limit: Option<u32>) -> Result<u32, OpsError> {
+        let Some(limit) = limit else {
+            return Ok(DEFAULT_MESSAGE_QUERY_LIMIT);
+        };
+
+        if self.ops.config.strict_message_limit() && !(1..=MAX_MESSAGE_QUERY_LIMIT).contains(&limit) {
+            return Err(OpsError::BadRequest(format!(
+                "Parameter 'limit' must be between 1 and {MAX_MESSAGE_QUERY_LIMIT}."
+            )));
+        }
+        Ok(limit.clamp(1, MAX_MESSAGE_QUERY_LIMIT))
+    }
+
+    /// Fetch messages from this channel.
+    ///
+    /// ## Arguments
+    ///
+    /// * `limit` - The maximum number of messages to fetch, see [`MessageOps::resolve_message_limit`].
+    /// * `before` - Fetch messages before this ID.
+    /// * `after` - Fetch messages after this ID.
+    /// * `around` - Fetch messages around this ID. The message will be included if it still exists.
+    /// * `floor` - Never fetch messages older than this ID, such as messages sent before the member joined.
+    ///
+    /// ## Returns
+    ///
+    /// [`Vec<Message>`] - The messages fetched. The ordering of the returned messages is unspecified.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::BadRequest`] - If more than one of `before`, `after` and `around` are provided,
+    ///   or the limit is out of range.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(channel_id = Empty))]
+    pub async fn fetch_messages_from(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        limit: Option<u32>,
+        before: Option<impl Into<Snowflake<Message>>>,
+        after: Option<impl Into<Snowflake<Message>>>,
+        around: Option<impl Into<Snowflake<Message>>>,
+        floor: Option<Snowflake<Message>>,
+    ) -> Result<Vec<Message>, OpsError> {
+        // TODO: Make this return members for author if possible, instead of users
+
+        // Check if more than one of the before/after/around parameters are provided
+        if [before.is_some(), after.is_some(), around.is_some()]
+            .into_iter()
+            .filter(|&b| b)
+            .count()
+            > 1
+        {
+            return Err(OpsError::BadRequest(
+                "Parameters 'before', 'after', and 'around' are mutually exclusive.".into(),
+            ));
+        }
+        let limit = i64::from(self.resolve_message_limit(limit)?);
+
+        /*
+        Note: The messages are first queried in the inner subquery to ensure
+        the limits are related to the count of fetched messages and not the final join result's row count.
+
+        Note 2: The ordering depends on if after or before are provided.
+        If after is provided, we order by ASC (id) to get the messages right after the `after` message.
+        Otherwise, we order by DESC (-id) to get the messages right before the `before` message.
+        (Or the latest messages if no before is provided)
+        */
+        // SAFETY: sqlx doesn't understand LEFT JOIN properly, so we have to use unchecked here.
+        let records = match around {
+            None => {
+                sqlx::query_as_unchecked!(
+                    ExtendedMessageRecord,
+                    "SELECT m.*, users.username, users.display_name, users.avatar_hash, users.banner_hash,
+                            attachments.id AS attachment_id, attachments.filename AS attachment_filename, attachments.content_type AS attachment_content_type,
+                            attachments.quarantined AS attachment_quarantined, attachments.key_version AS attachment_key_version
+                     FROM (
+                         SELECT *
+                         FROM messages
+                         WHERE channel_id = $1
+                           AND ($2::BIGINT IS NULL OR id < $2)
+                           AND ($3::BIGINT IS NULL OR id > $3)
+                           AND ($5::BIGINT IS NULL OR id >= $5)
+                         ORDER BY CASE WHEN $3 IS NOT NULL THEN id ELSE -id END
+                         LIMIT $4
+                     ) m
+                     LEFT JOIN users ON m.user_id = users.id
+                     LEFT JOIN attachments ON m.id = attachments.message_id",
+                    record_id("channel_id", channel),
+                    before.map(Into::into),
+                    after.map(Into::into),
+                    limit,
+                    floor
+                )
+                .fetch_all(self.ops.db)
+                .await?
+            }
+            Some(around) => {
+                self.fetch_records_around(channel.into(), around.into(), limit, floor)
+                    .await?
+            }
+        };
+
+        let mut messages = Message::from_records(records)?;
+        self.resolve_channel_mentions(&mut messages).await?;
+        Ok(messages)
+    }
+
+    /// Fetch the records of the messages around a message, see [`MessageOps::fetch_messages_from`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The channel to fetch the messages from.
+    /// * `around` - The message to fetch the messages around.
+    /// * `limit` - The number of messages to fetch, including the message itself.
+    /// * `floor` - Never fetch messages older than this ID.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Unavailable`] - If too many of these fetches are running, or the fetch exceeded its statement timeout.
+    /// * [`OpsError::Db`] - If the database query fails.
+    async fn fetch_records_around(
+        &self,
+        channel: Snowflake<Channel>,
+        around: Snowflake<Message>,
+        limit: i64,
+        floor: Option<Snowflake<Message>>,
+    ) -> Result<Vec<ExtendedMessageRecord>, OpsError> {
+        // The anchor message counts towards the messages after it, so a limit of 1 only returns the anchor
+        let before_limit = limit / 2;
+        let after_limit = limit - before_limit;
+
+        let _permit = self.ops.try_acquire_query(HeavyQuery::MessagesAround)?;
+        let mut tx = self.ops.begin_heavy(HeavyQuery::MessagesAround).await?;
+
+        let records = sqlx::query_as_unchecked!(
+            ExtendedMessageRecord,
+            r#"
+                SELECT m.*, u.username, u.display_name, u.avatar_hash, u.banner_hash,
+                       a.id AS attachment_id, a.filename AS attachment_filename, a.content_type AS attachment_content_type,
+                       a.quarantined AS attachment_quarantined, a.key_version AS attachment_key_version
+                FROM (
+                    (SELECT *
+                    FROM messages
+                    WHERE channel_id = $1 AND id < $2 AND ($5::BIGINT IS NULL OR id >= $5)
+                    ORDER BY id DESC
+                    LIMIT $3)
+                UNION ALL
+                    (SELECT *
+                    FROM messages
+                    WHERE channel_id = $1 AND id >= $2 AND ($5::BIGINT IS NULL OR id >= $5)
+                    ORDER BY id ASC
+                    LIMIT $4)
+                ) m
+                LEFT JOIN users u ON m.user_id = u.id
+                LEFT JOIN attachments a ON m.id = a.message_id
+                "#,
+            channel,
+            around,
+            before_limit,
+            after_limit,
+            floor
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(timed_out)?;
+        tx.commit().await?;
+        Ok(records)
+    }
+
+    /// Resolve the channels mentioned in the content of messages, in a single query.
+    ///
+    /// Mentions of channels that do not exist or are in another guild than the message are dropped,
+    /// as are all channel mentions in direct messages.
+    ///
+    /// ## Arguments
+    ///
+    /// * `messages` - The messages to resolve the channel mentions of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    pub async fn resolve_channel_mentions(&self, messages: &mut [Message]) -> Result<(), OpsError> {
+        let mentioned = messages.iter().map(Message::mentioned_channels).collect::<Vec<_>>();
+
+        if mentioned.iter().all(Vec::is_empty) {
+            return Ok(());
+        }
+
+        // The messages' own channels are fetched too, to tell which guild each message is in
+        let ids = mentioned
+            .iter()
+            .flatten()
+            .copied()
+            .chain(messages.iter().map(Message::channel_id))
+            .map(i64::from)
+            .unique()
+            .collect::<Vec<_>>();
+
+        let channels: HashMap<Snowflake<Channel>, (Option<i64>, String)> =
+            sqlx::query!("SELECT id, guild_id, name FROM channels WHERE id = ANY($1)", &ids)
+                .fetch_all(self.ops.db)
+                .await?
+                .into_iter()
+                .map(|r| (r.id.into(), (r.guild_id, r.name)))
+                .collect();
+
+        for (message, mentioned) in messages.iter_mut().zip(mentioned) {
+            let Some((Some(guild_id), _)) = channels.get(&message.channel_id()) else {
+                continue;
+            };
+
+            let mentions = mentioned
+                .into_iter()
+                .filter_map(|id| {
+                    channels
+                        .get(&id)
+                        .filter(|(guild, _)| guild.as_ref() == Some(guild_id))
+                        .map(|(_, name)| ChannelMention::new(id, name.clone()))
+                })
+                .collect();
+            message.set_mention_channels(mentions);
+        }
+        Ok(())
+    }
+
+    /// Stream all messages of a channel, oldest first.
+    ///
+    /// The messages are read from a single query whose rows are streamed from the database as they are consumed,
+    /// so that arbitrarily large channels can be exported without loading them into memory.
+    /// At most [`EXPORT_BUFFER_SIZE`] messages are buffered ahead of the consumer,
+    /// and the export stops once the returned stream is dropped.
+    ///
+    /// This is a [`HeavyQuery::MessageExport`], its slot is held until the export stops.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The channel to export the messages of.
+    ///
+    /// ## Returns
+    ///
+    /// A stream of the channel's messages. Attachment contents are not retrieved from S3.
+    /// If reading a message fails, the error is yielded and the stream ends.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Unavailable`] - If too many exports are running.
+    ///   Exceeding the statement timeout is yielded by the stream instead.
+    #[tracing::instrument(skip_all, fields(channel_id = Empty))]
+    pub fn export_messages(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+    ) -> Result<impl Stream<Item = Result<Message, OpsError>> + Send + 'static, OpsError> {
+        let channel_id: Snowflake<Channel> = record_id("channel_id", channel);
+        let permit = self.ops.try_acquire_query(HeavyQuery::MessageExport)?;
+        let timeout = self.ops.config.query_limits().get(HeavyQuery::MessageExport).timeout();
+        let db = self.ops.db.clone();
+        let (tx, rx) = mpsc::channel(EXPORT_BUFFER_SIZE);
+
+        tokio::spawn(
+            async move {
+                let _permit = permit;
+                let mut conn = match db.begin_with_timeout(timeout).await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        tracing::error!(error = ?e, "Failed to begin message export");
+                        let _ = tx.send(Err(e.into())).await;
+                        return;
+                    }
+                };
+
+                // SAFETY: sqlx doesn't understand LEFT JOIN properly, so we have to use unchecked here.
+                let mut rows = sqlx::query_as_unchecked!(
+                    ExtendedMessageRecord,
+                    "SELECT m.*, users.username, users.display_name, users.avatar_hash, users.banner_hash,
+                            attachments.id AS attachment_id, attachments.filename AS attachment_filename,
+                            attachments.content_type AS attachment_content_type,
+                            attachments.quarantined AS attachment_quarantined, attachments.key_version AS attachment_key_version
+                    FROM messages m
+                    LEFT JOIN users ON m.user_id = users.id
+                    LEFT JOIN attachments ON m.id = attachments.message_id
+                    WHERE m.channel_id = $1
+                    ORDER BY m.id, attachments.id",
+                    channel_id
+                )
+                .fetch(&mut *conn);
+
+                // Rows of the same message are adjacent, one for each of its attachments
+                let mut pending: Vec<ExtendedMessageRecord> = Vec::new();
+
+                loop {
+                    let row = match rows.try_next().await {
+                        Ok(row) => row,
+                        Err(e) => {
+                            tracing::error!(error = ?e, "Failed to read messages for export");
+                            let _ = tx.send(Err(timed_out(e))).await;
+                            return;
+                        }
+                    };
+
+                    // The pending message is complete once a row of another message, or no row, follows
+                    let complete = pending
+                        .first()
+                        .is_some_and(|first| row.as_ref().is_none_or(|row| row.id != first.id));
+
+                    if complete {
+                        let message = Message::from_records(std::mem::take(&mut pending))
+                            .map(|mut messages| messages.pop().expect("Rows should form a message"))
+                            .map_err(OpsError::from)
+                            .inspect_err(|e| tracing::error!(error = ?e, "Failed to build message for export"));
+                        let failed = message.is_err();
+
+                        // The receiver is gone if the client disconnected
+                        if tx.send(message).await.is_err() || failed {
+                            return;
+                        }
+                    }
+
+                    match row {
+                        Some(row) => pending.push(row),
+                        None => return,
+                    }
+                }
+            }
+            .in_current_span(),
+        );
+
+        Ok(ReceiverStream::new(rx))
+    }
+
+    /// Retrieve a message and fetch its author from the database in one query.
+    /// Attachment contents will not be retrieved from S3.
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The ID of the message to retrieve.
+    ///
+    /// ## Returns
+    ///
+    /// The message if found, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If the message is malformed.
+    #[tracing::instrument(skip_all, fields(message_id = Empty))]
+    pub async fn fetch_message(&self, message: impl Into<Snowflake<Message>>) -> Result<Option<Message>, OpsError> {
+        // sqlx cannot handle LEFT JOIN properly, so we have to use unchecked here.
+        let records = sqlx::query_as_unchecked!(
+            ExtendedMessageRecord,
+            "SELECT messages.*, users.username, users.display_name, users.avatar_hash, users.banner_hash, attachments.id AS attachment_id, attachments.filename AS attachment_filename, attachments.content_type AS attachment_content_type, attachments.quarantined AS attachment_quarantined, attachments.key_version AS attachment_key_version
+            FROM messages
+            LEFT JOIN users ON messages.user_id = users.id
+            LEFT JOIN attachments ON messages.id = attachments.message_id
+            WHERE messages.id = $1",
+            record_id("message_id", message) as Snowflake<Message>
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        let mut messages = Message::from_records(records)?;
+        self.resolve_channel_mentions(&mut messages).await?;
+        Ok(messages.pop())
+    }
+
+    /// Retrieve a message from the given channel and fetch its author from the database in one query.
+    /// Attachment contents will not be retrieved from S3.
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The ID of the message to retrieve.
+    ///
+    /// ## Returns
+    ///
+    /// The message if found, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If the message is malformed.
+    #[tracing::instrument(skip_all, fields(channel_id = Empty, message_id = Empty))]
+    pub async fn fetch_message_in(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        message: impl Into<Snowflake<Message>>,
+    ) -> Result<Option<Message>, OpsError> {
+        let channel_id = record_id("channel_id", channel);
+        let message_id = record_id("message_id", message);
+
+        let records = sqlx::query_as_unchecked!(
+            ExtendedMessageRecord,
+            "SELECT messages.*, users.username, users.display_name, users.avatar_hash, users.banner_hash, attachments.id AS attachment_id, attachments.filename AS attachment_filename, attachments.content_type AS attachment_content_type, attachments.quarantined AS attachment_quarantined, attachments.key_version AS attachment_key_version
+            FROM messages
+            LEFT JOIN users ON messages.user_id = users.id
+            LEFT JOIN attachments ON messages.id = attachments.message_id
+            WHERE messages.id = $1 AND messages.channel_id = $2",
+            message_id as Snowflake<Message>,
+            channel_id as Snowflake<Channel>
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        let mut messages = Message::from_records(records)?;
+        self.resolve_channel_mentions(&mut messages).await?;
+        Ok(messages.pop())
+    }
+
+    /// Commit this message to the database. Uploads all attachments to S3.
+    /// It is highly recommended to call [`Message::strip_attachment_contents`] after calling
+    /// this method to remove the attachment contents from memory.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::S3`] - If the S3 request to upload one of the attachments fails.
+    /// * [`OpsError::Db`] - If the database request fails.
+    pub async fn commit_message(&self, message: &Message) -> Result<(), OpsError> {
+        self.commit_message_with(message, &[]).await
+    }
+
+    /// Commit this message to the database along with outbox entries announcing it, in a single transaction.
+    /// Uploads all attachments to S3 beforehand, so that they are available once the message is announced.
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The message to commit.
+    /// * `outbox` - The entries to emit once the message is committed, see [`OutboxEntry`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::S3`] - If the S3 request to upload one of the attachments fails.
+    /// * [`OpsError::Db`] - If the database request fails.
+    #[tracing::instrument(skip_all, fields(message_id = %message.id(), channel_id = %message.channel_id()))]
+    pub async fn commit_message_with(&self, message: &Message, outbox: &[OutboxEntry]) -> Result<(), OpsError> {
+        let attachments: Vec<&FullAttachment> = match self.ops.s3 {
+            Some(s3) => {
+                let attachments: Vec<&FullAttachment> = message
+                    .attachments()
+                    .iter()
+                    .filter_map(|a| match a {
+                        Attachment::Full(f) => Some(f),
+                        Attachment::Partial(_) => None,
+                    })
+                    .collect();
+                for attachment in &attachments {
+                    attachment.upload(s3).await?;
+                }
+                attachments
+            }
+            // Ignore if no S3 is configured
+            None => Vec::new(),
+        };
+
+        let mut tx = self.ops.db.begin().await?;
+
+        Self::write_message(&mut tx, message).await?;
+        for attachment in attachments {
+            self.insert_attachment_record(&mut tx, attachment).await?;
+        }
+        OutboxOps::enqueue(&mut *tx, outbox).await?;
+
+        tx.commit().await?;
+
+        if !outbox.is_empty() {
+            self.ops.outbox().wake();
+        }
+        Ok(())
+    }
+
+    /// Insert or update a message and the users it mentions.
+    ///
+    /// New messages are recorded as activity of their author, and of the recipients if sent in a direct message channel.
+    async fn write_message(conn: &mut PgConnection, message: &Message) -> Result<(), OpsError> {
+        // Only freshly inserted rows count towards the channel's statistics, (xmax = 0) is false for updated rows
+        let inserted = sqlx::query!(
+            "WITH upserted AS (
+                INSERT INTO messages (id, user_id, channel_id, content, edited, lang, created_at, edited_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                ON CONFLICT (id) DO UPDATE
+                SET user_id = $2, channel_id = $3, content = $4, edited = $5, lang = $6, edited_at = $8
+                RETURNING id, channel_id, (xmax = 0) AS inserted
+            )
+            UPDATE channels
+            SET message_count = channels.message_count + 1,
+                last_message_id = GREATEST(channels.last_message_id, upserted.id)
+            FROM upserted
+            WHERE channels.id = upserted.channel_id AND upserted.inserted
+            RETURNING channels.id",
+            message.id() as Snowflake<Message>,
+            message.author().map(UserLike::id) as Option<Snowflake<User>>,
+            message.channel_id() as Snowflake<Channel>,
+            message.content(),
+            message.edited(),
+            message.lang(),
+            message.created_at(),
+            message.edited_at(),
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .is_some();
+
+        if inserted {
+            sqlx::query!(
+                "INSERT INTO channel_activity (user_id, channel_id, last_activity_id)
+                SELECT u.id, $2, $1
+                FROM users u
+                WHERE u.id = $3 OR u.id IN (SELECT user_id FROM channel_recipients WHERE channel_id = $2)
+                ON CONFLICT (user_id, channel_id) DO UPDATE
+                SET last_activity_id = GREATEST(channel_activity.last_activity_id, $1)",
+                message.id() as Snowflake<Message>,
+                message.channel_id() as Snowflake<Channel>,
+                message.author().map(UserLike::id) as Option<Snowflake<User>>,
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        Self::index_mentions(conn, message).await
+    }
+
+    /// Record the users mentioned in a message, replacing any previously recorded mentions.
+    ///
+    /// Only users who can view the channel can be mentioned, and authors never mention themselves.
+    /// Newly mentioned users have the mention recorded as activity in the channel.
+    async fn index_mentions(conn: &mut PgConnection, message: &Message) -> Result<(), OpsError> {
+        let mentions: Vec<i64> = message.mentions().into_iter().map(i64::from).collect();
+
+        sqlx::query!(
+            "DELETE FROM mentions WHERE message_id = $1 AND user_id <> ALL($2)",
+            message.id() as Snowflake<Message>,
+            &mentions,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        if mentions.is_empty() {
+            return Ok(());
+        }
+
+        sqlx::query!(
+            "WITH mentioned AS (
+                INSERT INTO mentions (user_id, message_id, channel_id, guild_id)
+                SELECT v.user_id, $1, v.channel_id, v.guild_id
+                FROM channel_visibility v
+                WHERE v.channel_id = $2 AND v.user_id = ANY($3) AND v.user_id IS DISTINCT FROM $4
+                ON CONFLICT DO NOTHING
+                RETURNING user_id, channel_id, message_id
+            )
+            INSERT INTO channel_activity (user_id, channel_id, last_activity_id, last_mention_id)
+            SELECT user_id, channel_id, message_id, message_id FROM mentioned
+            ON CONFLICT (user_id, channel_id) DO UPDATE
+            SET last_activity_id = GREATEST(channel_activity.last_activity_id, EXCLUDED.last_activity_id),
+                last_mention_id = GREATEST(channel_activity.last_mention_id, EXCLUDED.last_mention_id)",
+            message.id() as Snowflake<Message>,
+            message.channel_id() as Snowflake<Channel>,
+            &mentions,
+            message.author().map(UserLike::id) as Option<Snowflake<User>>,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetch the most recent messages mentioning a user, newest first.
+    ///
+    /// Only messages in channels the user can still view are returned.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user who was mentioned.
+    /// * `guild` - Only return mentions from this guild, if provided.
+    /// * `limit` - The maximum number of messages to fetch. Defaults to 25, capped at 100.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If a message could not be constructed.
+    #[tracing::instrument(skip_all, fields(user_id = Empty, guild_id = guild.map(display)))]
+    pub async fn fetch_mentions(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        guild: Option<Snowflake<Guild>>,
+        limit: Option<u32>,
+    ) -> Result<Vec<Message>, OpsError> {
+        // SAFETY: sqlx doesn't understand LEFT JOIN properly, so we have to use unchecked here.
+        let records = sqlx::query_as_unchecked!(
+            ExtendedMessageRecord,
+            "SELECT m.*, u.username, u.display_name, u.avatar_hash, u.banner_hash,
+                    a.id AS attachment_id, a.filename AS attachment_filename, a.content_type AS attachment_content_type,
+                    a.quarantined AS attachment_quarantined, a.key_version AS attachment_key_version
+            FROM (
+                SELECT msg.*
+                FROM mentions mn
+                JOIN channel_visibility v ON v.user_id = mn.user_id AND v.channel_id = mn.channel_id
+                JOIN messages msg ON msg.id = mn.message_id
+                WHERE mn.user_id = $1 AND ($2::BIGINT IS NULL OR mn.guild_id = $2)
+                ORDER BY mn.message_id DESC
+                LIMIT $3
+            ) m
+            LEFT JOIN users u ON m.user_id = u.id
+            LEFT JOIN attachments a ON m.id = a.message_id",
+            record_id("user_id", user),
+            guild,
+            i64::from(limit.unwrap_or(25).clamp(1, 100))
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        let mut messages = Message::from_records(records)?;
+        messages.sort_unstable_by_key(|m| std::cmp::Reverse(m.id()));
+        self.resolve_channel_mentions(&mut messages).await?;
+        Ok(messages)
+    }
+
+    /// Fetch the channels with recent activity involving a user, the most recently active first.
+    ///
+    /// Activity is recorded as messages are committed, in channels the user posted in,
+    /// was mentioned in, or is a recipient of. Only channels the user can still view are returned.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to fetch the active channels of.
+    /// * `limit` - The maximum number of channels to fetch. Defaults to 25, capped at 100.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn fetch_active_channels(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        limit: Option<u32>,
+    ) -> Result<Vec<ActiveChannel>, OpsError> {
+        let user_id = record_id("user_id", user);
+
+        let activity = sqlx::query!(
+            r#"SELECT a.channel_id, a.last_activity_id, a.last_mention_id, r.message_id AS "last_read_message_id?"
+            FROM channel_activity a
+            JOIN channel_visibility v ON v.user_id = a.user_id AND v.channel_id = a.channel_id
+            LEFT JOIN read_states r ON r.user_id = a.user_id AND r.channel_id = a.channel_id
+            WHERE a.user_id = $1
+            ORDER BY a.last_activity_id DESC
+            LIMIT $2"#,
+            user_id as Snowflake<User>,
+            i64::from(limit.unwrap_or(25).clamp(1, 100)),
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        let channel_ids: Vec<i64> = activity.iter().map(|a| a.channel_id).collect();
+        let mut channels: HashMap<i64, Channel> = sqlx::query_as!(
+            ChannelRecord,
+            r#"SELECT c.*, ARRAY(
+                SELECT user_id FROM channel_recipients WHERE channel_id = c.id ORDER BY user_id
+            ) AS "recipient_ids!"
+            FROM channels c
+            WHERE c.id = ANY($1)"#,
+            &channel_ids,
+        )
+        .fetch_all(self.ops.db)
+        .await?
+        .into_iter()
+        .map(|record| (record.id.into(), Channel::from_record(record)))
+        .collect();
+
+        // Channels removed in the meantime are skipped
+        Ok(activity
+            .into_iter()
+            .filter_map(|a| {
+                Some(ActiveChannel {
+                    channel: channels.remove(&a.channel_id)?,
+                    last_activity_id: a.last_activity_id.into(),
+                    last_mention_id: a.last_mention_id.map(Into::into),
+                    last_read_message_id: a.last_read_message_id.map(Into::into),
+                })
+            })
+            .collect())
+    }
+
+    /// Update a message in the database based on an update payload.
+    /// The update is announced to everyone who can view the channel through the outbox once committed.
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The message to update.
+    /// * `payload` - The update payload.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::NotFound`] - If the message does not exist.
+    ///
+    /// ## Returns
+    ///
+    /// The updated message if the commit was successful.
+    #[tracing::instrument(skip_all, fields(message_id = Empty))]
+    pub async fn update_message(
+        &self,
+        message: impl Into<Snowflake<Message>>,
+        payload: UpdateMessage,
+    ) -> Result<Message, OpsError> {
+        let message_id = record_id("message_id", message);
+
+        let mut message = self
+            .fetch_message(message_id)
+            .await?
+            .ok_or(OpsError::NotFound("Message not found".into()))?;
+
+        message.apply_update(payload);
+        if message.edited() {
+            if self.ops.config.detect_message_language() {
+                message.detect_language();
+            }
+            self.resolve_channel_mentions(std::slice::from_mut(&mut message))
+                .await?;
+        }
+
+        let guild_id = sqlx::query_scalar!(
+            "SELECT guild_id FROM channels WHERE id = $1",
+            message.channel_id() as Snowflake<Channel>,
+        )
+        .fetch_one(self.ops.db)
+        .await?
+        .map(Snowflake::<Guild>::from);
+
+        let update = OutboxEntry::dispatch(
+            &GatewayEvent::MessageUpdate(message.clone()),
+            SendMode::for_channel(guild_id, message.channel_id()),
+        );
+        self.commit_message_with(&message, &[update]).await?;
+
+        Ok(message)
+    }
+
+    /// Delete a message. The removal is announced to the guild, or the recipients of a direct message channel,
+    /// through the outbox once committed.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The channel the message is in.
+    /// * `message` - The message to delete.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(channel_id = Empty, message_id = Empty))]
+    pub async fn delete_message(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        message: impl Into<Snowflake<Message>>,
+    ) -> Result<(), OpsError> {
+        let channel_id = record_id("channel_id", channel);
+        let message_id = record_id("message_id", message);
+
+        let mut tx = self.ops.db.begin().await?;
+
+        // The subquery still sees the deleted message, as all parts of the statement share a snapshot
+        let deleted = sqlx::query_scalar!(
+            "WITH deleted AS (
+                DELETE FROM messages WHERE id = $1 RETURNING id, channel_id
+            )
+            UPDATE channels
+            SET message_count = GREATEST(channels.message_count - 1, 0),
+                last_message_id = CASE
+                    WHEN channels.last_message_id = deleted.id THEN (
+                        SELECT MAX(id) FROM messages WHERE channel_id = deleted.channel_id AND id <> deleted.id
+                    )
+                    ELSE channels.last_message_id
+                END
+            FROM deleted
+            WHERE channels.id = deleted.channel_id
+            RETURNING channels.guild_id",
+            message_id as Snowflake<Message>
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(guild_id) = deleted.map(|g| g.map(Snowflake::<Guild>::from)) {
+            let removal = OutboxEntry::dispatch(
+                &GatewayEvent::MessageRemove {
+                    id: message_id,
+                    channel_id,
+                    guild_id,
+                },
+                SendMode::for_channel(guild_id, channel_id),
+            );
+            OutboxOps::enqueue(&mut *tx, &[removal]).await?;
+        }
+
+        tx.commit().await?;
+        self.ops.outbox().wake();
+
+        self.ops
+            .s3_run(|s3| s3.remove_all_for_message(channel_id, message_id))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Remove all messages older than the retention period of their channel,
+    /// recording an audit log entry for every channel that had messages removed.
+    ///
+    /// Channels without a retention period keep their messages forever.
+    ///
+    /// ## Returns
+    ///
+    /// The number of messages removed.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If a database query fails.
+    /// * [`OpsError::S3`] - If removing the attachments of an expired message fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn sweep_expired_messages(&self) -> Result<u64, OpsError> {
+        let channels = sqlx::query!(
+            r#"SELECT id, guild_id AS "guild_id!", retention_days AS "retention_days!"
+            FROM channels WHERE retention_days IS NOT NULL"#
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        let now = Utc::now().timestamp_millis();
+        let mut total = 0;
+
+        for channel in channels {
+            let channel_id: Snowflake<Channel> = channel.id.into();
+            let cutoff: Snowflake<Message> = Snowflake::from_timestamp_with_epoch(
+                now - i64::from(channel.retention_days) * 24 * 3600 * 1000,
+                self.ops.config.snowflake_epoch(),
+            );
+
+            let count = self.remove_messages_before(channel_id, cutoff).await?;
+            if count == 0 {
+                continue;
+            }
+
+            let entry = AuditLogEntry::system(self.ops.config, channel.guild_id, AuditLogAction::MessageExpire)
+                .with_target(channel_id)
+                .with_change(None, Some(count.to_string()));
+            self.ops.create_audit_log_entry(&entry).await?;
+
+            total += count;
+        }
+
+        Ok(total)
+    }
+
+    /// Remove all messages in a channel that were sent before the given message ID, in batches.
+    ///
+    /// ## Returns
+    ///
+    /// The number of messages removed.
+    #[tracing::instrument(skip_all, fields(channel_id = %channel, before_id = %before))]
+    async fn remove_messages_before(
+        &self,
+        channel: Snowflake<Channel>,
+        before: Snowflake<Message>,
+    ) -> Result<u64, OpsError> {
+        let mut count = 0;
+
+        loop {
+            let mut tx = self.ops.db.begin().await?;
+
+            // The attachments are still visible to the subquery, as all parts of the statement share a snapshot
+            let deleted = sqlx::query!(
+                r#"WITH deleted AS (
+                    DELETE FROM messages WHERE id IN (
+                        SELECT id FROM messages WHERE channel_id = $1 AND id < $2 ORDER BY id LIMIT $3
+                    )
+                    RETURNING id
+                )
+                SELECT id, EXISTS(SELECT 1 FROM attachments a WHERE a.message_id = deleted.id) AS "has_attachments!"
+                FROM deleted"#,
+                channel as Snowflake<Channel>,
+                before as Snowflake<Message>,
+                RETENTION_BATCH_SIZE,
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+
+            if deleted.is_empty() {
+                break;
+            }
+
+            sqlx::query!(
+                "UPDATE channels
+                SET message_count = GREATEST(message_count - $2, 0),
+                    last_message_id = CASE
+                        WHEN last_message_id < $3 THEN (SELECT MAX(id) FROM messages WHERE channel_id = $1)
+                        ELSE last_message_id
+                    END
+                WHERE id = $1",
+                channel as Snowflake<Channel>,
+                deleted.len() as i64,
+                before as Snowflake<Message>,
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            tx.commit().await?;
+
+            for message in deleted.iter().filter(|m| m.has_attachments) {
+                let message_id: Snowflake<Message> = message.id.into();
+                self.ops
+                    .s3_run(|s3| s3.remove_all_for_message(channel, message_id))
+                    .await?;
+            }
+
+            count += deleted.len() as u64;
+            if (deleted.len() as i64) < RETENTION_BATCH_SIZE {
+                break;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Move all attachments older than the archive period of their guild to the configured archive storage class,
+    /// see [`Config::attachment_archive_storage_class`](crate::app::Config::attachment_archive_storage_class).
+    ///
+    /// Attachments that fail to be moved are skipped and retried on the next run.
+    /// Guilds without an archive period keep their attachments in standard storage.
+    ///
+    /// ## Returns
+    ///
+    /// The number of attachments archived.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If a database query fails.
+    /// * [`OpsError::S3`] - If moving an attachment to the current key layout fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn archive_attachments(&self) -> Result<u64, OpsError> {
+        let Some(s3) = self.ops.s3 else {
+            return Ok(0);
+        };
+        let storage_class = self.ops.config.attachment_archive_storage_class();
+
+        let guilds = sqlx::query!(
+            r#"SELECT id, attachment_archive_days AS "attachment_archive_days!"
+            FROM guilds WHERE attachment_archive_days IS NOT NULL"#
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        let now = Utc::now().timestamp_millis();
+        let mut total = 0;
+
+        for guild in guilds {
+            let cutoff: Snowflake<Message> = Snowflake::from_timestamp_with_epoch(
+                now - i64::from(guild.attachment_archive_days) * 24 * 3600 * 1000,
+                self.ops.config.snowflake_epoch(),
+            );
+            // Paginate by key, so attachments that failed to be moved are not fetched again
+            let mut after: (i64, i32) = (0, -1);
+
+            loop {
+                let records = sqlx::query!(
+                    "SELECT a.id, a.filename, a.message_id, a.channel_id, a.content_type, a.key_version
+                    FROM attachments a
+                    JOIN channels c ON c.id = a.channel_id
+                    WHERE c.guild_id = $1 AND a.message_id < $2 AND a.storage_class = 'STANDARD' AND NOT a.quarantined
+                    AND (a.message_id, a.id) > ($3, $4)
+                    ORDER BY a.message_id, a.id
+                    LIMIT $5",
+                    guild.id,
+                    cutoff as Snowflake<Message>,
+                    after.0,
+                    after.1,
+                    ARCHIVE_BATCH_SIZE,
+                )
+                .fetch_all(self.ops.db)
+                .await?;
+
+                let Some(last) = records.last() else {
+                    break;
+                };
+                after = (last.message_id, last.id);
+                let exhausted = (records.len() as i64) < ARCHIVE_BATCH_SIZE;
+
+                let attachments: Vec<PartialAttachment> = records
+                    .into_iter()
+                    .filter_map(|r| {
+                        Some(
+                            PartialAttachment::new(
+                                r.id.try_into().ok()?,
+                                r.filename,
+                                r.content_type,
+                                r.channel_id,
+                                r.message_id,
+                            )
+                            .with_key_version(r.key_version),
+                        )
+                    })
+                    .collect();
+
+                // Archived objects may not be readable, so they are moved to the current key layout beforehand
+                self.ops.migrate_s3_keys(&attachments).await?;
+
+                for attachment in attachments {
+                    let attachment = attachment.with_key_version(KEYSPACE_VERSION);
+
+                    if let Err(e) = s3
+                        .attachments()
+                        .set_storage_class(attachment.s3_key(), storage_class.clone())
+                        .await
+                    {
+                        tracing::warn!(
+                            error = %e,
+                            message_id = %attachment.message_id(),
+                            "Failed to move attachment to archive storage"
+                        );
+                        continue;
+                    }
+
+                    sqlx::query!(
+                        "UPDATE attachments SET storage_class = $3 WHERE id = $1 AND message_id = $2",
+                        i32::from(attachment.id()),
+                        attachment.message_id() as Snowflake<Message>,
+                        storage_class.as_str(),
+                    )
+                    .execute(self.ops.db)
+                    .await?;
+
+                    total += 1;
+                }
+
+                if exhausted {
+                    break;
+                }
+            }
+        }
+
+        Ok(total)
+    }
+
+    /// Commit the attachment to the database. Uploads the contents to S3 implicitly.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(attachment_id = %attachment.id(), message_id = %attachment.message_id()))]
+    pub async fn create_attachment(&self, attachment: &FullAttachment) -> Result<(), OpsError> {
+        let Some(s3) = self.ops.s3 else {
+            // Ignore if no S3 is configured
+            return Ok(());
+        };
+
+        attachment.upload(s3).await?;
+
+        let mut tx = self.ops.db.begin().await?;
+        self.insert_attachment_record(&mut tx, attachment).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Insert the database record of an attachment whose contents are already stored in S3.
+    /// If an attachment scanner is configured, the attachment is also enqueued to be scanned.
+    #[tracing::instrument(skip_all, fields(attachment_id = %attachment.id(), message_id = %attachment.message_id()))]
+    async fn insert_attachment_record(
+        &self,
+        conn: &mut PgConnection,
+        attachment: &impl AttachmentLike,
+    ) -> Result<(), OpsError> {
+        sqlx::query!(
+            "INSERT INTO attachments (id, filename, message_id, channel_id, content_type, key_version)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (id, message_id)
+            DO UPDATE SET filename = $2, content_type = $5, quarantined = FALSE, key_version = $6",
+            i32::from(attachment.id()),
+            attachment.filename(),
+            attachment.message_id() as Snowflake<Message>,
+            attachment.channel_id() as Snowflake<Channel>,
+            attachment.mime().to_string(),
+            attachment.key_version(),
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        if self.ops.scanner.is_some() {
+            Self::insert_attachment_scan(&mut *conn, attachment).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Enqueue an attachment to be scanned by the attachment scanner.
+    ///
+    /// ## Arguments
+    ///
+    /// * `attachment` - The attachment to scan.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(attachment_id = %attachment.id(), message_id = %attachment.message_id()))]
+    pub async fn enqueue_attachment_scan(&self, attachment: &impl AttachmentLike) -> Result<(), OpsError> {
+        Self::insert_attachment_scan(self.ops.db, attachment).await
+    }
+
+    /// Insert an attachment into the scan queue, resetting its failed attempts if it is already queued.
+    async fn insert_attachment_scan(
+        executor: impl PgExecutor<'_>,
+        attachment: &impl AttachmentLike,
+    ) -> Result<(), OpsError> {
+        sqlx::query!(
+            "INSERT INTO attachment_scans (attachment_id, message_id)
+            VALUES ($1, $2)
+            ON CONFLICT (attachment_id, message_id)
+            DO UPDATE SET attempts = 0, enqueued_at = NOW()",
+            i32::from(attachment.id()),
+            attachment.message_id() as Snowflake<Message>,
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Scan the oldest attachments waiting in the scan queue, quarantining the ones flagged by the scanner.
+    ///
+    /// Attachments that fail to be scanned are retried on the next run,
+    /// and dropped from the queue after [`MAX_SCAN_ATTEMPTS`] failures.
+    ///
+    /// ## Returns
+    ///
+    /// The number of attachments successfully scanned.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If a database query fails.
+    /// * [`OpsError::S3`] - If quarantining a flagged attachment fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn scan_pending_attachments(&self) -> Result<u64, OpsError> {
+        let (Some(scanner), Some(s3)) = (self.ops.scanner, self.ops.s3) else {
+            return Ok(0);
+        };
+
+        let pending: Vec<PartialAttachment> = sqlx::query!(
+            "SELECT a.id, a.filename, a.message_id, a.channel_id, a.content_type, a.key_version
+            FROM attachment_scans s
+            JOIN attachments a ON a.id = s.attachment_id AND a.message_id = s.message_id
+            ORDER BY s.enqueued_at
+            LIMIT 50"
+        )
+        .fetch_all(self.ops.db)
+        .await?
+        .into_iter()
+        .map(|r| {
+            PartialAttachment::new(r.id as u8, r.filename, r.content_type, r.channel_id, r.message_id)
+                .with_key_version(r.key_version)
+        })
+        .collect();
+
+        let mut count = 0;
+
+        for attachment in pending {
+            let content_type = attachment.mime().to_string();
+            let verdict = match s3.attachments().get_object(attachment.s3_key()).await {
+                Ok(content) => scanner
+                    .scan(attachment.filename(), &content_type, content)
+                    .await
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+
+            match verdict {
+                Ok(verdict) if verdict.is_flagged() => {
+                    self.quarantine_attachment(&attachment, verdict).await?;
+                    count += 1;
+                }
+                Ok(_) => {
+                    sqlx::query!(
+                        "DELETE FROM attachment_scans WHERE attachment_id = $1 AND message_id = $2",
+                        i32::from(attachment.id()),
+                        attachment.message_id() as Snowflake<Message>,
+                    )
+                    .execute(self.ops.db)
+                    .await?;
+                    count += 1;
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, "Failed to scan attachment {}", attachment.s3_key());
+                    self.record_failed_scan(&attachment).await?;
+                }
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Move a failed scan to the back of the queue, dropping it once it failed [`MAX_SCAN_ATTEMPTS`] times.
+    #[tracing::instrument(skip_all, fields(attachment_id = %attachment.id(), message_id = %attachment.message_id()))]
+    async fn record_failed_scan(&self, attachment: &PartialAttachment) -> Result<(), OpsError> {
+        let attempts = sqlx::query_scalar!(
+            "UPDATE attachment_scans SET attempts = attempts + 1, enqueued_at = NOW()
+            WHERE attachment_id = $1 AND message_id = $2
+            RETURNING attempts",
+            i32::from(attachment.id()),
+            attachment.message_id() as Snowflake<Message>,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        if attempts.is_some_and(|a| a >= MAX_SCAN_ATTEMPTS) {
+            tracing::error!(
+                "Giving up on scanning attachment {} after {MAX_SCAN_ATTEMPTS} attempts",
+                attachment.s3_key()
+            );
+            sqlx::query!(
+                "DELETE FROM attachment_scans WHERE attachment_id = $1 AND message_id = $2",
+                i32::from(attachment.id()),
+                attachment.message_id() as Snowflake<Message>,
+            )
+            .execute(self.ops.db)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Quarantine an attachment flagged by the attachment scanner.
+    ///
+    /// The file is moved out of the public attachments bucket so it can no longer be downloaded,
+    /// the message is flagged, and the moderators of the guild are notified.
+    ///
+    /// ## Arguments
+    ///
+    /// * `attachment` - The attachment to quarantine.
+    /// * `verdict` - The verdict the scanner returned for the attachment.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::S3`] - If moving the file fails.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(attachment_id = %attachment.id(), message_id = %attachment.message_id()))]
+    pub async fn quarantine_attachment(
+        &self,
+        attachment: &PartialAttachment,
+        verdict: ScanVerdict,
+    ) -> Result<(), OpsError> {
+        self.ops
+            .s3_run(|s3| async move {
+                s3.attachments()
+                    .move_object(attachment.s3_key(), &s3.quarantine())
+                    .await
+            })
+            .await?;
+
+        // Returns the guild the attachment was sent in, whose moderators are notified, and the author to strike
+        let record = sqlx::query!(
+            r#"WITH quarantined AS (
+                UPDATE attachments SET quarantined = TRUE WHERE id = $1 AND message_id = $2
+            ), flagged AS (
+                UPDATE messages SET flagged = TRUE WHERE id = $2
+            ), dequeued AS (
+                DELETE FROM attachment_scans WHERE attachment_id = $1 AND message_id = $2
+            )
+            SELECT c.guild_id, m.user_id AS author_id
+            FROM channels c
+            LEFT JOIN messages m ON m.id = $2
+            WHERE c.id = $3"#,
+            i32::from(attachment.id()),
+            attachment.message_id() as Snowflake<Message>,
+            attachment.channel_id() as Snowflake<Channel>,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        tracing::info!(?verdict, "Quarantined attachment {}", attachment.s3_key());
+
+        if let Some(author_id) = record.as_ref().and_then(|r| r.author_id) {
+            self.strike_uploader(author_id.into()).await;
+        }
+
+        let (Some(gateway), Some(record)) = (self.ops.gateway, record) else {
+            return Ok(());
+        };
+
+        let guild_id = record.guild_id.map(Snowflake::from);
+
+        // Direct message channels have no moderators to notify
+        if let Some(guild_id) = guild_id {
+            let moderators = self
+                .ops
+                .roles()
+                .fetch_users_with_permission(guild_id, Permissions::MODERATE_GUILD)
+                .await?;
+            for moderator in moderators {
+                gateway.send_to(
+                    moderator,
+                    GatewayEvent::AttachmentQuarantine {
+                        attachment_id: attachment.id(),
+                        message_id: attachment.message_id(),
+                        channel_id: attachment.channel_id(),
+                        guild_id,
+                        verdict,
+                    },
+                );
+            }
+        }
+
+        // Let clients know the attachment is gone
+        if let Some(message) = self.fetch_message(attachment.message_id()).await? {
+            gateway.dispatch(
+                GatewayEvent::MessageUpdate(message),
+                SendMode::for_channel(guild_id, attachment.channel_id()),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Issue an automated strike against the author of a quarantined attachment.
+    ///
+    /// Failures are only logged, as the attachment is already quarantined.
+    async fn strike_uploader(&self, author: Snowflake<User>) {
+        let result = match Strike::new(
+            self.ops.config,
+            author,
+            None,
+            "Uploaded an attachment flagged by the attachment scanner",
+        ) {
+            Ok(mut strike) => self.ops.standing().issue_strike(&mut strike).await,
+            Err(e) => Err(e.into()),
+        };
+
+        if let Err(e) = result {
+            tracing::warn!(error = %e, user_id = %author, "Failed to strike author of quarantined attachment");
+        }
+    }
+
+    /// Start a new upload session, creating the backing S3 multipart upload if S3 is configured.
+    /// Direct sessions are given the request to upload their file with instead.
+    ///
+    /// ## Arguments
+    ///
+    /// * `session` - The session to start. Its S3 upload ID or upload request will be set if applicable.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::BadRequest`] - If the session is direct, but S3 is not configured.
+    /// * [`OpsError::S3`] - If the S3 request fails.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(session_id = %session.id()))]
+    pub async fn create_upload_session(&self, session: &mut UploadSession) -> Result<(), OpsError> {
+        if session.is_direct() {
+            self.presign_upload_session(session).await?;
+        } else if let Some(s3) = self.ops.s3 {
+            let attachment = session.attachment();
+            let upload_id = s3
+                .attachments()
+                .create_multipart_upload(attachment.s3_key(), &attachment.mime())
+                .await?;
+            session.set_s3_upload_id(Some(upload_id));
+        }
+
+        sqlx::query!(
+            "INSERT INTO upload_sessions (id, user_id, channel_id, message_id, filename, content_type, size, s3_upload_id, key_version, direct)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            session.id() as Snowflake<UploadSession>,
+            session.user_id() as Snowflake<User>,
+            session.channel_id() as Snowflake<Channel>,
+            session.message_id() as Snowflake<Message>,
+            session.filename(),
+            session.content_type(),
+            session.size() as i64,
+            session.s3_upload_id(),
+            session.key_version(),
+            session.is_direct(),
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Set the request to upload the file of a direct session with, valid for [`PRESIGNED_UPLOAD_EXPIRY`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `session` - The direct session to upload the file of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::BadRequest`] - If S3 is not configured.
+    /// * [`OpsError::S3`] - If the request could not be presigned.
+    pub async fn presign_upload_session(&self, session: &mut UploadSession) -> Result<(), OpsError> {
+        let s3 = self
+            .ops
+            .s3
+            .ok_or_else(|| OpsError::BadRequest("Direct uploads are not available on this instance.".into()))?;
+
+        let upload = s3
+            .attachments()
+            .presign_put_object(
+                session.s3_key(),
+                session.content_type(),
+                session.size(),
+                PRESIGNED_UPLOAD_EXPIRY,
+            )
+            .await?;
+        session.set_upload(upload);
+        Ok(())
+    }
+
+    /// Fetch an upload session by ID.
+    ///
+    /// ## Arguments
+    ///
+    /// * `session` - The ID of the session to fetch.
+    ///
+    /// ## Returns
+    ///
+    /// The session if found, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(session_id = Empty))]
+    pub async fn fetch_upload_session(
+        &self,
+        session: impl Into<Snowflake<UploadSession>>,
+    ) -> Result<Option<UploadSession>, OpsError> {
+        let record = sqlx::query_as!(
+            UploadSessionRecord,
+            "SELECT id, user_id, channel_id, message_id, filename, content_type, size, uploaded, s3_upload_id, key_version, direct
+            FROM upload_sessions WHERE id = $1",
+            record_id("session_id", session) as Snowflake<UploadSession>,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(record.map(UploadSession::from_record))
+    }
+
+    /// Append the next part to an upload session.
+    ///
+    /// The part is reserved for [`PART_RESERVATION`] before it is uploaded, so concurrent parts are assigned
+    /// distinct part numbers without the session staying locked during the upload. Parts that fail to upload
+    /// are released again, and so are parts whose reservation expired, for example because the instance
+    /// uploading them stopped. The next part fills the lowest released part number first,
+    /// so a part sent again after failing keeps its place in the file.
+    ///
+    /// ## Arguments
+    ///
+    /// * `session` - The ID of the session to append to.
+    /// * `data` - The contents of the part.
+    ///
+    /// ## Returns
+    ///
+    /// The session with its progress updated.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::UploadProgress`] - To the uploader, once the part has been stored.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::NotFound`] - If the session does not exist, or was removed while the part was uploading.
+    /// * [`OpsError::BadRequest`] - If the session is direct, and thus not uploaded in parts.
+    /// * [`OpsError::Conflict`] - If the reservation of the part expired before it was stored.
+    /// * [`OpsError::Build`] - If the part is too small or overflows the declared size.
+    /// * [`OpsError::S3`] - If the S3 request fails.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(session_id = Empty))]
+    pub async fn upload_session_part(
+        &self,
+        session: impl Into<Snowflake<UploadSession>>,
+        data: Bytes,
+    ) -> Result<UploadSession, OpsError> {
+        let session_id = record_id("session_id", session);
+        let (session, part_number, reserved_until) = self.reserve_session_part(session_id, data.len()).await?;
+
+        let etag = if let (Some(s3), Some(upload_id)) = (self.ops.s3, session.s3_upload_id()) {
+            match s3
+                .attachments()
+                .upload_part(session.attachment().s3_key(), upload_id, part_number, data)
+                .await
+            {
+                Ok(etag) => etag,
+                Err(e) => {
+                    self.release_session_part(session_id, part_number, reserved_until).await;
+                    return Err(e.into());
+                }
+            }
+        } else {
+            None
+        };
+
+        // The part is only stored if it still holds its reservation
+        let record = sqlx::query_as!(
+            UploadSessionRecord,
+            "WITH part AS (
+                UPDATE upload_session_parts SET etag = $3, reserved_until = NULL
+                WHERE session_id = $1 AND part_number = $2 AND reserved_until = $4
+                RETURNING size AS part_size
+            )
+            UPDATE upload_sessions SET uploaded = uploaded + part.part_size FROM part WHERE id = $1
+            RETURNING id, user_id, channel_id, message_id, filename, content_type, size, uploaded, s3_upload_id, key_version, direct",
+            session_id as Snowflake<UploadSession>,
+            part_number,
+            etag,
+            reserved_until,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        let Some(record) = record else {
+            if self.fetch_upload_session(session_id).await?.is_none() {
+                return Err(OpsError::NotFound(
+                    "Upload session does not exist or has expired.".into(),
+                ));
+            }
+            return Err(OpsError::Conflict(
+                "The part took too long to upload and was released, send it again.".into(),
+            ));
+        };
+
+        let session = UploadSession::from_record(record);
+
+        if let Some(g) = self.ops.gateway {
+            g.send_to(
+                session.user_id(),
+                GatewayEvent::UploadProgress {
+                    upload_id: session.id(),
+                    channel_id: session.channel_id(),
+                    bytes: session.uploaded(),
+                    total: session.size(),
+                    percentage: session.percentage(),
+                },
+            );
+        }
+
+        Ok(session)
+    }
+
+    /// Validate the next part of an upload session, and reserve a part number and its bytes for it.
+    ///
+    /// The session is only locked while the part is reserved, parts count towards the declared size
+    /// from then on, but only towards the uploaded bytes once stored.
+    ///
+    /// ## Returns
+    ///
+    /// The session, the number of the reserved part, and when its reservation expires.
+    async fn reserve_session_part(
+        &self,
+        session_id: Snowflake<UploadSession>,
+        len: usize,
+    ) -> Result<(UploadSession, i32, DateTime<Utc>), OpsError> {
+        let mut tx = self.ops.db.begin().await?;
+
+        // Lock the session so concurrent parts cannot be assigned the same part number
+        let session = sqlx::query_as!(
+            UploadSessionRecord,
+            "SELECT id, user_id, channel_id, message_id, filename, content_type, size, uploaded, s3_upload_id, key_version, direct
+            FROM upload_sessions WHERE id = $1 FOR UPDATE",
+            session_id as Snowflake<UploadSession>,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .map(UploadSession::from_record)
+        .ok_or_else(|| OpsError::NotFound("Upload session does not exist or has expired.".into()))?;
+
+        if session.is_direct() {
+            return Err(OpsError::BadRequest(
+                "Direct upload sessions are uploaded through their upload URL.".into(),
+            ));
+        }
+
+        sqlx::query!(
+            "DELETE FROM upload_session_parts WHERE session_id = $1 AND reserved_until < NOW()",
+            session_id as Snowflake<UploadSession>,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // Released parts leave gaps in the part numbers, which are filled first to keep the parts in order
+        let reserved = sqlx::query!(
+            r#"WITH parts AS (SELECT part_number, size FROM upload_session_parts WHERE session_id = $1)
+            SELECT n AS "part_number!",
+                (SELECT COALESCE(SUM(size), 0) FROM parts)::BIGINT AS "reserved!",
+                EXISTS (SELECT 1 FROM parts WHERE part_number > n) AS "followed!"
+            FROM generate_series(1, (SELECT COUNT(*) FROM parts)::INTEGER + 1) n
+            WHERE n NOT IN (SELECT part_number FROM parts)
+            ORDER BY n LIMIT 1"#,
+            session_id as Snowflake<UploadSession>,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        session.validate_part(reserved.reserved as u64, len, reserved.followed)?;
+
+        let reserved_until = sqlx::query_scalar!(
+            "INSERT INTO upload_session_parts (session_id, part_number, size, reserved_until)
+            VALUES ($1, $2, $3, NOW() + make_interval(secs => $4))
+            RETURNING reserved_until AS \"reserved_until!\"",
+            session_id as Snowflake<UploadSession>,
+            reserved.part_number,
+            len as i64,
+            PART_RESERVATION.as_secs_f64(),
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok((session, reserved.part_number, reserved_until))
+    }
+
+    /// Release a part of an upload session that failed to upload, so that it can be sent again.
+    ///
+    /// Failures are only logged, as the upload already failed. The part is released
+    /// once its reservation expires in that case.
+    async fn release_session_part(
+        &self,
+        session_id: Snowflake<UploadSession>,
+        part_number: i32,
+        reserved_until: DateTime<Utc>,
+    ) {
+        let result = sqlx::query!(
+            "DELETE FROM upload_session_parts WHERE session_id = $1 AND part_number = $2 AND reserved_until = $3",
+            session_id as Snowflake<UploadSession>,
+            part_number,
+            reserved_until,
+        )
+        .execute(self.ops.db)
+        .await;
+
+        if let Err(e) = result {
+            tracing::warn!(error = %e, part_number, "Failed to release upload session part");
+        }
+    }
+
+    /// Complete an upload session, assembling the uploaded parts and committing the message
+    /// that the file is attached to. The session is removed afterwards.
+    ///
+    /// The file of a direct session is checked against the declared size and content type,
+    /// then moved to the attachment's key.
+    ///
+    /// ## Arguments
+    ///
+    /// * `session` - The session to complete.
+    /// * `message` - The message to commit, built from the session.
+    /// * `outbox` - The entries to emit once the message is committed, see [`OutboxEntry`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::BadRequest`] - If not all bytes of the file have been received yet.
+    /// * [`OpsError::Build`] - If the file of a direct session was not uploaded, or does not match the session.
+    /// * [`OpsError::S3`] - If the S3 request fails.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(session_id = %session.id(), message_id = %message.id(), channel_id = %message.channel_id()))]
+    pub async fn complete_upload_session(
+        &self,
+        session: &UploadSession,
+        message: &Message,
+        outbox: &[OutboxEntry],
+    ) -> Result<(), OpsError> {
+        if !session.is_direct() && !session.is_complete() {
+            return Err(OpsError::BadRequest(format!(
+                "Upload is incomplete, received {} out of {} bytes.",
+                session.uploaded(),
+                session.size()
+            )));
+        }
+
+        let attachment = session.attachment();
+
+        if let (Some(s3), Some(upload_id)) = (self.ops.s3, session.s3_upload_id()) {
+            let parts = sqlx::query!(
+                "SELECT part_number, etag, reserved_until FROM upload_session_parts WHERE session_id = $1 ORDER BY part_number",
+                session.id() as Snowflake<UploadSession>,
+            )
+            .fetch_all(self.ops.db)
+            .await?;
+
+            if parts.iter().any(|p| p.reserved_until.is_some()) {
+                return Err(OpsError::BadRequest(
+                    "Upload is incomplete, parts are still uploading.".into(),
+                ));
+            }
+            let parts = parts.into_iter().map(|r| (r.part_number, r.etag)).collect();
+
+            s3.attachments()
+                .complete_multipart_upload(attachment.s3_key(), upload_id, parts)
+                .await?;
+        } else if session.is_direct() {
+            let s3 = self
+                .ops
+                .s3
+                .ok_or_else(|| OpsError::BadRequest("Direct uploads are not available on this instance.".into()))?;
+            let bucket = s3.attachments();
+
+            let metadata = bucket.head_object(session.s3_key()).await?;
+            verify_uploaded(metadata.as_ref(), session.size(), session.content_type())?;
+            bucket.rename_object(session.s3_key(), attachment.s3_key()).await?;
+        }
+
+        let mut tx = self.ops.db.begin().await?;
+
+        Self::write_message(&mut tx, message).await?;
+
+        if self.ops.s3.is_some() {
+            self.insert_attachment_record(&mut tx, &attachment).await?;
+        }
+
+        sqlx::query!(
+            "DELETE FROM upload_sessions WHERE id = $1",
+            session.id() as Snowflake<UploadSession>
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        OutboxOps::enqueue(&mut *tx, outbox).await?;
+
+        tx.commit().await?;
+
+        if !outbox.is_empty() {
+            self.ops.outbox().wake();
+        }
+        Ok(())
+    }
+
+    /// Abort an upload session, discarding all uploaded parts.
+    ///
+    /// ## Arguments
+    ///
+    /// * `session` - The session to abort.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::S3`] - If the S3 request fails.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(session_id = %session.id()))]
+    pub async fn abort_upload_session(&self, session: &UploadSession) -> Result<(), OpsError> {
+        if let (Some(s3), Some(upload_id)) = (self.ops.s3, session.s3_upload_id()) {
+            s3.attachments()
+                .abort_multipart_upload(session.attachment().s3_key(), upload_id)
+                .await?;
+        } else if let Some(s3) = self.ops.s3
+            && session.is_direct()
+        {
+            s3.attachments().delete_object(session.s3_key()).await?;
+        }
+
+        sqlx::query!(
+            "DELETE FROM upload_sessions WHERE id = $1",
+            session.id() as Snowflake<UploadSession>
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Discard upload sessions and S3 multipart uploads that were started more than [`STALE_UPLOAD_AGE`] ago.
+    ///
+    /// Interrupted uploads keep their parts in S3 until they are aborted, which is billed as regular storage.
+    /// This includes multipart uploads of sessions that were never recorded, for example due to a crash.
+    /// Files of direct sessions that were never completed are deleted as well.
+    /// Uploads that fail to be aborted are retried on the next run.
+    ///
+    /// ## Returns
+    ///
+    /// The number of multipart uploads aborted.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::S3`] - If listing the multipart uploads fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn abort_stale_uploads(&self) -> Result<u64, OpsError> {
+        let cutoff = Utc::now() - STALE_UPLOAD_AGE;
+
+        // Their multipart uploads are aborted below, as they were started before the session was recorded
+        let expired_sessions = sqlx::query!(
+            "DELETE FROM upload_sessions WHERE created_at < NOW() - make_interval(secs => $1)",
+            STALE_UPLOAD_AGE.as_secs_f64(),
+        )
+        .execute(self.ops.db)
+        .await?
+        .rows_affected();
+
+        let Some(s3) = self.ops.s3 else {
+            return Ok(0);
+        };
+
+        let bucket = s3.attachments();
+        let (mut aborted, mut failed) = (0, 0);
+
+        for upload in bucket.list_multipart_uploads().await? {
+            let (Some(key), Some(upload_id), Some(initiated)) = (upload.key(), upload.upload_id(), upload.initiated())
+            else {
+                continue;
+            };
+            if initiated.secs() >= cutoff.timestamp() {
+                continue;
+            }
+
+            match bucket.abort_multipart_upload(key, upload_id).await {
+                Ok(()) => aborted += 1,
+                Err(e) => {
+                    tracing::warn!(error = %e, key, "Failed to abort stale multipart upload");
+                    failed += 1;
+                }
+            }
+        }
+
+        let deleted = bucket.delete_stale_uploads(cutoff).await?;
+
+        tracing::info!(
+            bucket = bucket.name(),
+            aborted,
+            failed,
+            deleted,
+            expired_sessions,
+            "Discarded stale uploads"
+        );
+        Ok(aborted)
+    }
+}
@@ -0,0 +1,856 @@
+use std::time::Duration;
+
+use derive_builder::Builder;
+use futures::future::join_all;
+use sqlx::{PgExecutor, Postgres, Transaction};
+use tokio::sync::OwnedSemaphorePermit;
+use tracing::{
+    Span,
+    field::{Empty, display},
+};
+
+use crate::{
+    app::{
+        Config, HeavyQuery,
+        avatar_uploads::AvatarUploader,
+        outbox::OutboxRelay,
+        query_limiter::{HEAVY_QUERY_RETRY_AFTER, QueryLimiter},
+    },
+    external::{AttachmentScanner, Database, FirebaseMessaging, S3Service, s3::KEYSPACE_VERSION},
+    gateway::{ConnectionId, Gateway, GatewayCloseCode, SendMode},
+    models::{
+        attachment::{AttachmentLike, PartialAttachment},
+        audit_log::AuditLogEntry,
+        avatar::{Avatar, AvatarKind, AvatarLike, PartialAvatar},
+        capability::Capability,
+        channel::Channel,
+        errors::{AppError, BuildError, GatewayError, OpsError},
+        gateway_event::{GatewayEvent, GatewayMessage, guild_members_chunks},
+        guild::Guild,
+        keyword_alert::KeywordMatcherCache,
+        message::Message,
+        outbox::OutboxEntry,
+        role::Permissions,
+        snowflake::Snowflake,
+        user::User,
+    },
+    rest::rate_limit::{RateGrant, RateLimitBucket, RateLimitRegistry},
+};
+
+mod automod;
+mod avatars;
+mod direct_channels;
+mod guild_events;
+mod guilds;
+mod instances;
+mod messages;
+mod notifications;
+mod outbox;
+mod relationships;
+mod reports;
+mod roles;
+mod standing;
+mod users;
+
+pub use automod::AutomodOps;
+pub use avatars::{AVATAR_UPLOAD_BATCH_SIZE, AVATAR_UPLOAD_LEASE, AvatarOps, MAX_AVATAR_UPLOAD_ATTEMPTS};
+pub use direct_channels::DirectChannelOps;
+pub use guild_events::GuildEventOps;
+pub use guilds::{GuildOps, MAX_MEMBER_QUERY_LIMIT, MAX_RETENTION_DAYS};
+pub use instances::{INSTANCE_HEARTBEAT_INTERVAL, INSTANCE_TIMEOUT, InstanceOps};
+pub use messages::{
+    DEFAULT_MESSAGE_QUERY_LIMIT, EXPORT_BUFFER_SIZE, MAX_MESSAGE_QUERY_LIMIT, MAX_SCAN_ATTEMPTS, MessageOps,
+    STALE_UPLOAD_AGE,
+};
+pub use notifications::{MAX_UNREAD_COUNT, NotificationOps};
+pub use outbox::{OUTBOX_BATCH_SIZE, OUTBOX_LEASE, OutboxOps};
+pub use relationships::RelationshipOps;
+pub use reports::{MAX_REPORT_QUERY_LIMIT, ReportOps};
+pub use roles::RoleOps;
+pub use standing::StandingOps;
+pub use users::UserOps;
+
+/// The maximum number of members sent in a single `GUILD_MEMBERS_CHUNK` event.
+pub const MEMBER_CHUNK_SIZE: usize = 1000;
+
+/// Record an ID on the current span, returning it.
+///
+/// Arguments taking `impl Into<Snowflake<T>>` can only be recorded by the instrumented function itself,
+/// once they have been converted.
+fn record_id<T>(field: &'static str, id: impl Into<Snowflake<T>>) -> Snowflake<T> {
+    let id = id.into();
+    Span::current().record(field, display(id));
+    id
+}
+
+/// Translate a violation of the given unique constraint into [`OpsError::AlreadyTaken`].
+///
+/// Inserts rely on the constraint instead of checking for existing values beforehand,
+/// so that concurrent requests cannot both claim the same value.
+///
+/// ## Arguments
+///
+/// * `constraint` - The name of the unique constraint or index.
+/// * `field` - The field the constrained value was submitted as.
+fn taken_on(constraint: &'static str, field: &'static str) -> impl FnOnce(sqlx::Error) -> OpsError {
+    move |e| {
+        if e.as_database_error()
+            .is_some_and(|db| db.is_unique_violation() && db.constraint() == Some(constraint))
+        {
+            OpsError::AlreadyTaken { field }
+        } else {
+            e.into()
+        }
+    }
+}
+
+/// The SQLSTATE of a statement cancelled by its `statement_timeout`.
+const QUERY_CANCELED: &str = "57014";
+
+/// Translate a statement cancelled by its timeout into [`OpsError::Unavailable`].
+///
+/// Heavy queries run with a statement timeout, see [`Database::begin_with_timeout`].
+fn timed_out(e: sqlx::Error) -> OpsError {
+    if e.as_database_error()
+        .and_then(sqlx::error::DatabaseError::code)
+        .is_some_and(|code| code == QUERY_CANCELED)
+    {
+        OpsError::Unavailable {
+            reason: "The request took too long to serve, try again later.".into(),
+            retry_after: HEAVY_QUERY_RETRY_AFTER,
+        }
+    } else {
+        e.into()
+    }
+}
+
+/// Contains all operations that affect or rely on external state.
+///
+/// Operations are grouped into domain services, which are obtained through this facade
+/// and share its components:
+///
+/// * [`GuildOps`] - Guilds, channels, members, invites and onboarding
+/// * [`GuildEventOps`] - Events scheduled by guilds and the answers of their members
+/// * [`RoleOps`] - Roles of guilds, their assignment to members and the permissions they grant
+/// * [`AutomodOps`] - Auto-moderation rules of guilds and the timeouts they apply
+/// * [`MessageOps`] - Messages, attachments and upload sessions
+/// * [`UserOps`] - Users and their accounts
+/// * [`RelationshipOps`] - Friendships and friend requests between users
+/// * [`DirectChannelOps`] - Direct message channels between two users
+/// * [`ReportOps`] - Reports filed by users and their triage by administrators
+/// * [`NotificationOps`] - Read states and push notifications
+/// * [`OutboxOps`] - The transactional outbox of gateway events and push notifications
+///
+/// Internals shared between services are listed as disallowed methods in `clippy.toml`,
+/// modules using them have to expect `clippy::disallowed_methods`.
+#[derive(Builder, Clone, Copy)]
+#[builder(setter(into), build_fn(error = "BuildError"))]
+pub struct Ops<'a> {
+    /// The database connection.
+    db: &'a Database,
+    /// The main application configuration.
+    config: &'a Config,
+    /// The S3 service to use for file storage.
+    /// If not provided, file storage operations will be skipped.
+    #[builder(default)]
+    s3: Option<&'a S3Service>,
+    /// The gateway connection to use for sending and receiving events.
+    /// If not provided, gateway operations will be skipped.
+    #[builder(default)]
+    gateway: Option<&'a Gateway>,
+
+    /// The Firebase Cloud Messaging service to use for push notifications.
+    /// If not provided, push notification operations will be skipped.
+    #[builder(default)]
+    fcm: Option<&'a FirebaseMessaging>,
+
+    /// The scanner to inspect uploaded attachments with.
+    /// If not provided, attachments will not be scanned.
+    #[builder(default)]
+    scanner: Option<&'a dyn AttachmentScanner>,
+
+    /// The cache of compiled keyword watch lists.
+    /// If not provided, watch lists are compiled every time they are used.
+    #[builder(default)]
+    keyword_matchers: Option<&'a KeywordMatcherCache>,
+
+    /// The rate limiters of requests about individual objects.
+    /// If not provided, these requests are not rate limited.
+    #[builder(default)]
+    rate_limits: Option<&'a RateLimitRegistry>,
+
+    /// The relay emitting entries of the transactional outbox, woken once entries were committed.
+    /// If not provided, entries are emitted whenever the outbox is relayed next.
+    #[builder(default)]
+    outbox_relay: Option<&'a OutboxRelay>,
+
+    /// The task uploading accepted avatars to S3, woken once avatars were enqueued.
+    /// If not provided, avatars are uploaded whenever pending uploads are swept next.
+    #[builder(default)]
+    avatar_uploader: Option<&'a AvatarUploader>,
+
+    /// The limiter of queries known to be expensive.
+    /// If not provided, any number of them may run at once, but their statement timeouts still apply.
+    #[builder(default)]
+    query_limiter: Option<&'a QueryLimiter>,
+}
+
+impl<'a> Ops<'a> {
+    /// Create a new builder to construct an [`Ops`].
+    pub fn builder() -> OpsBuilder<'a> {
+        OpsBuilder::default()
+    }
+
+    /// Operations on avatars and banners waiting to be uploaded to S3.
+    pub const fn avatars(&self) -> AvatarOps<'a> {
+        AvatarOps::new(*self)
+    }
+
+    /// Operations on guilds, their channels, members, invites and onboarding.
+    pub const fn guilds(&self) -> GuildOps<'a> {
+        GuildOps::new(*self)
+    }
+
+    /// Operations on the events guilds schedule and the answers of their members.
+    pub const fn guild_events(&self) -> GuildEventOps<'a> {
+        GuildEventOps::new(*self)
+    }
+
+    /// Operations on the roles of guilds, their assignment to members and the permissions they grant.
+    pub const fn roles(&self) -> RoleOps<'a> {
+        RoleOps::new(*self)
+    }
+
+    /// Operations on the auto-moderation rules of guilds and the timeouts they apply.
+    pub const fn automod(&self) -> AutomodOps<'a> {
+        AutomodOps::new(*self)
+    }
+
+    /// Operations on messages, their attachments and upload sessions.
+    pub const fn messages(&self) -> MessageOps<'a> {
+        MessageOps::new(*self)
+    }
+
+    /// Operations on users and their accounts.
+    pub const fn users(&self) -> UserOps<'a> {
+        UserOps::new(*self)
+    }
+
+    /// Operations on friendships and friend requests between users.
+    pub const fn relationships(&self) -> RelationshipOps<'a> {
+        RelationshipOps::new(*self)
+    }
+
+    /// Operations on direct message channels between two users.
+    pub const fn direct_channels(&self) -> DirectChannelOps<'a> {
+        DirectChannelOps::new(*self)
+    }
+
+    /// Operations on reports filed by users and their triage by the instance's administrators.
+    pub const fn reports(&self) -> ReportOps<'a> {
+        ReportOps::new(*self)
+    }
+
+    /// Operations on the strikes issued against users and the standing of their accounts.
+    pub const fn standing(&self) -> StandingOps<'a> {
+        StandingOps::new(*self)
+    }
+
+    /// Operations on read states and push notifications.
+    pub const fn notifications(&self) -> NotificationOps<'a> {
+        NotificationOps::new(*self)
+    }
+
+    /// Operations on the transactional outbox of gateway events and push notifications.
+    pub const fn outbox(&self) -> OutboxOps<'a> {
+        OutboxOps::new(*self)
+    }
+
+    /// Operations tracking the instances of the application and the users connected to them.
+    pub const fn instances(&self) -> InstanceOps<'a> {
+        InstanceOps::new(*self)
+    }
+
+    /// Take a slot to run a heavy query in, rejecting the query if all slots are taken.
+    ///
+    /// ## Returns
+    ///
+    /// A permit holding the slot until it is dropped, or `None` if no limiter is configured.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Unavailable`] - If the maximum number of these queries are already running.
+    fn try_acquire_query(&self, query: HeavyQuery) -> Result<Option<OwnedSemaphorePermit>, OpsError> {
+        self.query_limiter.map(|l| l.try_acquire(query)).transpose()
+    }
+
+    /// Wait for a slot to run a heavy query in.
+    ///
+    /// ## Returns
+    ///
+    /// A permit holding the slot until it is dropped, or `None` if no limiter is configured.
+    async fn acquire_query(&self, query: HeavyQuery) -> Option<OwnedSemaphorePermit> {
+        match self.query_limiter {
+            Some(limiter) => Some(limiter.acquire(query).await),
+            None => None,
+        }
+    }
+
+    /// Begin a transaction to run a heavy query in, whose statements are cancelled once they exceed its timeout.
+    ///
+    /// Statements cancelled this way should be translated with [`timed_out`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the transaction could not be started.
+    async fn begin_heavy(&self, query: HeavyQuery) -> Result<Transaction<'static, Postgres>, OpsError> {
+        let timeout = self.config.query_limits().get(query).timeout();
+        Ok(self.db.begin_with_timeout(timeout).await?)
+    }
+
+    /// Run op on S3 if the S3 service is available.
+    async fn s3_run<'s, F: Future<Output = Result<(), AppError>>>(
+        &'s self,
+        f: impl FnOnce(&'s S3Service) -> F,
+    ) -> Result<(), OpsError> {
+        if let Some(s3) = self.s3 {
+            Ok(f(s3).await?)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check that a newly set avatar is within the size limits of its kind.
+    ///
+    /// ## Arguments
+    ///
+    /// * `name` - What the avatar is called in the error message, e.g. `Avatar`.
+    /// * `avatar` - The new avatar, partial avatars are already stored and always pass.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::PayloadTooLarge`] - If the avatar is too large.
+    fn check_avatar_size<K: AvatarKind>(name: &str, avatar: Option<&Avatar<K>>) -> Result<(), OpsError> {
+        match avatar {
+            Some(Avatar::Full(f)) if f.is_too_large() => Err(OpsError::PayloadTooLarge(format!(
+                "{name} too large, must be {} MiB or smaller.",
+                f.kind().max_size(f.is_animated()) / (1024 * 1024)
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Enqueue a new avatar to be uploaded in the background, or delete a removed one, if it changed.
+    ///
+    /// New avatars only replace the previous one in S3 once uploaded, see [`AvatarOps::upload_pending_avatars`].
+    /// Nothing is stored if S3 is not configured.
+    ///
+    /// ## Arguments
+    ///
+    /// * `executor` - The transaction the holder is updated in. This must be called before the holder is updated,
+    ///   so that pending uploads are locked before their holder, like the uploader does.
+    /// * `new` - The avatar after the update.
+    /// * `old` - The avatar before the update.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Build`] - If the new avatar changed, but is partial.
+    /// * [`OpsError::Db`] - If enqueueing the upload fails.
+    /// * [`OpsError::S3`] - If deleting the removed avatar fails.
+    async fn replace_avatar<K: AvatarKind>(
+        &self,
+        executor: impl PgExecutor<'_>,
+        new: Option<&Avatar<K>>,
+        old: Option<&Avatar<K>>,
+    ) -> Result<(), OpsError> {
+        if new == old {
+            return Ok(());
+        }
+        if let Some(Avatar::Partial(_)) = new {
+            return Err(BuildError::IllegalState("Cannot upload partial avatar".into()).into());
+        }
+        if self.s3.is_none() {
+            return Ok(());
+        }
+
+        match (new, old) {
+            (Some(Avatar::Full(f)), old) => {
+                self.avatars()
+                    .enqueue_upload(executor, f, old.map(AvatarLike::avatar_hash))
+                    .await
+            }
+            (None, Some(old)) => {
+                // The removed avatar may still be pending, so the avatar it was to replace is unused as well
+                let previous = self.avatars().cancel_upload::<K>(executor, old.holder_id()).await?;
+                self.s3_run(|s3| old.delete(s3)).await?;
+                if let Some(previous) = previous {
+                    let previous = PartialAvatar::<K>::new(previous, old.holder_id())?;
+                    self.s3_run(|s3| previous.delete(s3)).await?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn get_capabilities(&self) -> Capability {
+        let mut capabilities = Capability::empty();
+
+        if self.s3.is_some() {
+            capabilities |= Capability::S3;
+        }
+
+        if self.fcm.is_some() {
+            capabilities |= Capability::PUSH_NOTIFICATIONS;
+        }
+
+        capabilities
+    }
+
+    /// Called when a message is received from a gateway connection.
+    ///
+    /// ## Arguments
+    ///
+    /// * `connection_id` - The ID of the connection that received the message.
+    /// * `message` - The message that was received.
+    pub async fn handle_inbound_gateway_message(&self, connection_id: ConnectionId, message: GatewayMessage) {
+        if message.is_activity()
+            && let Some(g) = self.gateway
+        {
+            g.record_activity(connection_id);
+        }
+
+        let res = match message {
+            GatewayMessage::StartTyping { channel_id } => self.trigger_typing(channel_id, connection_id.0).await,
+            GatewayMessage::Identify { .. } | GatewayMessage::Resume { .. } => {
+                Err(GatewayError::AuthError("Already identified".into()))
+            }
+            GatewayMessage::Heartbeat | GatewayMessage::Activity => Ok(()),
+            GatewayMessage::Ping { nonce, latency } => {
+                if let Some(g) = self.gateway {
+                    if let Some(latency) = latency {
+                        g.record_latency(connection_id, Duration::from_millis(latency));
+                    }
+                    g.send_to_session(
+                        connection_id,
+                        GatewayEvent::Pong {
+                            nonce,
+                            server_time: chrono::Utc::now().timestamp_millis(),
+                        },
+                    );
+                }
+                Ok(())
+            }
+            GatewayMessage::Ack { seq } => {
+                if let Some(g) = self.gateway {
+                    g.ack_session(connection_id, seq);
+                }
+                Ok(())
+            }
+            GatewayMessage::RequestGuildMembers {
+                guild_id,
+                query,
+                limit,
+                nonce,
+            } => {
+                self.request_guild_members(connection_id, guild_id, query, limit, nonce)
+                    .await
+            }
+        };
+
+        if let Err(e) = res
+            && let Some(g) = self.gateway
+        {
+            g.close_session(connection_id, e.close_code(), e.to_string());
+        }
+    }
+
+    /// Serve a `REQUEST_GUILD_MEMBERS` request by sending the matching members
+    /// to the requesting session in `GUILD_MEMBERS_CHUNK` events.
+    ///
+    /// ## Arguments
+    ///
+    /// * `connection_id` - The session that sent the request.
+    /// * `guild` - The guild to send the members of.
+    /// * `query` - If set, only members whose name starts with this are sent.
+    /// * `limit` - The maximum number of members to send.
+    /// * `nonce` - Echoed back in every chunk.
+    ///
+    /// ## Errors
+    ///
+    /// * [`GatewayError::RateLimited`] - If the session sent too many requests.
+    /// * [`GatewayError::Forbidden`] - If the user is not a member of the guild.
+    /// * [`GatewayError::App`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(connection_id = %connection_id, guild_id = Empty))]
+    async fn request_guild_members(
+        &self,
+        connection_id: ConnectionId,
+        guild: impl Into<Snowflake<Guild>>,
+        query: Option<String>,
+        limit: Option<u32>,
+        nonce: Option<String>,
+    ) -> Result<(), GatewayError> {
+        let Some(gateway) = self.gateway else {
+            return Ok(());
+        };
+        let guild_id = record_id("guild_id", guild);
+
+        if !gateway.try_acquire_member_request(connection_id).await {
+            return Err(GatewayError::RateLimited(
+                "Too many REQUEST_GUILD_MEMBERS requests".into(),
+            ));
+        }
+        if self.guilds().fetch_member(connection_id.0, guild_id).await?.is_none() {
+            return Err(GatewayError::Forbidden("Cannot access resource".into()));
+        }
+
+        let members = join_all(
+            self.guilds()
+                .search_members(guild_id, query.as_deref(), None, limit)
+                .await?
+                .into_iter()
+                .map(|m| m.include_presence(gateway, connection_id.0)),
+        )
+        .await;
+
+        for chunk in guild_members_chunks(
+            guild_id,
+            members,
+            nonce.as_deref(),
+            MEMBER_CHUNK_SIZE,
+            self.config.gateway_max_payload_size(),
+        ) {
+            gateway.send_to_session(connection_id, chunk);
+        }
+        Ok(())
+    }
+
+    /// Triggers a typing start event for a given user in a channel.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The channel to trigger the typing event in.
+    /// * `user` - The user to trigger the typing event for.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(channel_id = Empty, user_id = Empty))]
+    async fn trigger_typing(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<(), GatewayError> {
+        let channel_id = record_id("channel_id", channel);
+        let user_id = record_id("user_id", user);
+
+        let record = sqlx::query!(
+            r#"SELECT c.guild_id as channel_guild_id,
+            EXISTS(SELECT 1 FROM channel_visibility v WHERE v.channel_id = c.id AND v.user_id = $2) AS "can_view!",
+            (SELECT r.other_id FROM channel_recipients r WHERE r.channel_id = c.id AND r.user_id = $2) AS recipient_id,
+            (c.guild_id IS NULL OR g.owner_id = $2
+                OR (NOT c.locked AND (m.guest_channel_id IS NULL OR m.guest_can_post))
+                OR (c.locked AND m.user_id IS NOT NULL AND (
+                    SELECT COALESCE(BIT_OR(r.permissions), 0)
+                    FROM member_roles mr
+                    JOIN roles r ON r.id = mr.role_id
+                    WHERE mr.guild_id = c.guild_id AND mr.user_id = $2
+                ) & $3 <> 0)) AS "can_post!"
+            FROM channels c
+            LEFT JOIN guilds g ON g.id = c.guild_id
+            LEFT JOIN members m ON m.guild_id = c.guild_id AND m.user_id = $2
+            WHERE c.id = $1"#,
+            channel_id as Snowflake<Channel>,
+            user_id as Snowflake<User>,
+            Permissions::ADMINISTRATOR.union(Permissions::MANAGE_MESSAGES).bits() as i64,
+        )
+        .fetch_optional(self.db)
+        .await?;
+
+        let record = record.ok_or_else(|| OpsError::NotFound("Channel not found".into()))?;
+        if !record.can_view {
+            return Err(GatewayError::Forbidden("Cannot access resource".into()));
+        }
+
+        // Typing in a channel one cannot post in is ignored, instead of closing the session
+        if !record.can_post {
+            return Ok(());
+        }
+        if let Some(recipient_id) = record.recipient_id
+            && !self.relationships().can_open_dm(user_id, recipient_id).await?
+        {
+            return Ok(());
+        }
+
+        // Typing indicators exceeding the rate limit are dropped, the user is warned through the gateway
+        if self
+            .acquire_rate_limit(RateLimitBucket::Typing, user_id, channel_id)
+            .await
+            .is_err()
+        {
+            return Ok(());
+        }
+
+        let channel_guild_id = record.channel_guild_id.map(Snowflake::<Guild>::from);
+
+        if let Some(g) = self.gateway {
+            g.dispatch(
+                GatewayEvent::TypingStart { user_id, channel_id },
+                SendMode::for_channel(channel_guild_id, channel_id),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Acquire a request from one of the user's rate limit buckets.
+    ///
+    /// The user is sent a `RATE_LIMIT` event once the bucket is exhausted,
+    /// so that clients can slow down before requests are rejected.
+    ///
+    /// ## Arguments
+    ///
+    /// * `bucket` - The bucket to acquire the request from.
+    /// * `user` - The user making the request.
+    /// * `object` - The object the request is about, such as a channel.
+    ///
+    /// ## Returns
+    ///
+    /// The state of the bucket, or `None` if requests are not rate limited.
+    ///
+    /// ## Errors
+    ///
+    /// The time after which the request should be retried, if it exceeds the rate limit.
+    pub async fn acquire_rate_limit<T>(
+        &self,
+        bucket: RateLimitBucket,
+        user: Snowflake<User>,
+        object: Snowflake<T>,
+    ) -> Result<Option<RateGrant>, Duration> {
+        let Some(rate_limits) = self.rate_limits else {
+            return Ok(None);
+        };
+        let result = rate_limits.acquire(bucket, user, object).await;
+
+        let retry_after = match &result {
+            Ok(grant) if grant.remaining() == 0 => grant.replenish_after(),
+            Ok(_) => return result.map(Some),
+            Err(retry_after) => *retry_after,
+        };
+        if let Some(gateway) = self.gateway {
+            gateway.send_to(
+                user,
+                GatewayEvent::RateLimit {
+                    bucket,
+                    object_id: object.cast(),
+                    limit: rate_limits.limiter(bucket).quota().burst(),
+                    retry_after: retry_after.as_secs_f64(),
+                    rejected: result.is_err(),
+                },
+            );
+        }
+        result.map(Some)
+    }
+
+    /// Record an entry in a guild's audit log.
+    ///
+    /// ## Arguments
+    ///
+    /// * `entry` - The entry to record.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = %entry.guild_id()))]
+    #[expect(clippy::disallowed_methods)] // The entry is recorded on its own
+    pub async fn create_audit_log_entry(&self, entry: &AuditLogEntry) -> Result<(), OpsError> {
+        Self::insert_audit_log_entry(self.db, entry).await
+    }
+
+    /// Insert an audit log entry using the given executor, so that it may take part in a transaction.
+    #[tracing::instrument(skip_all, fields(guild_id = %entry.guild_id()))]
+    async fn insert_audit_log_entry(executor: impl PgExecutor<'_>, entry: &AuditLogEntry) -> Result<(), OpsError> {
+        sqlx::query!(
+            "INSERT INTO audit_log_entries (id, guild_id, user_id, target_id, action, old_value, new_value)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            entry.id() as Snowflake<AuditLogEntry>,
+            entry.guild_id() as Snowflake<Guild>,
+            entry.user_id() as Option<Snowflake<User>>,
+            entry.target_id() as Option<Snowflake<()>>,
+            entry.action().as_str(),
+            entry.old_value(),
+            entry.new_value(),
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    /// Terminate a user's account instance-wide, cutting off all of their access at once.
+    ///
+    /// The account is marked as terminated, which revokes all of its tokens and prevents further logins.
+    /// Its push notification tokens and memberships are removed, except in guilds it owns,
+    /// then all of its gateway sessions are closed and the affected guilds are notified.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to terminate.
+    ///
+    /// ## Returns
+    ///
+    /// The number of guilds the user was removed from.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::NotFound`] - If the user does not exist or was already terminated.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    #[expect(clippy::disallowed_methods)] // Member removals are dispatched once the termination is committed
+    pub async fn terminate_user(&self, user: impl Into<Snowflake<User>>) -> Result<u64, OpsError> {
+        let user_id: Snowflake<User> = record_id("user_id", user);
+        let mut tx = self.db.begin().await?;
+
+        let res = sqlx::query!(
+            "UPDATE users SET terminated_at = $1 WHERE id = $2 AND terminated_at IS NULL",
+            chrono::Utc::now(),
+            user_id as Snowflake<User>,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if res.rows_affected() == 0 {
+            return Err(OpsError::NotFound(
+                "User does not exist or was already terminated".into(),
+            ));
+        }
+
+        sqlx::query!("DELETE FROM fcm_tokens WHERE user_id = $1", user_id as Snowflake<User>)
+            .execute(&mut *tx)
+            .await?;
+
+        let guild_ids: Vec<Snowflake<Guild>> = sqlx::query_scalar!(
+            "DELETE FROM members m
+            USING guilds g
+            WHERE g.id = m.guild_id AND m.user_id = $1 AND g.owner_id != $1
+            RETURNING m.guild_id",
+            user_id as Snowflake<User>,
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+
+        let removals: Vec<OutboxEntry> = guild_ids
+            .iter()
+            .map(|&guild_id| {
+                OutboxEntry::dispatch(
+                    &GatewayEvent::MemberRemove { id: user_id, guild_id },
+                    SendMode::ToGuild(guild_id),
+                )
+            })
+            .collect();
+        OutboxOps::enqueue(&mut *tx, &removals).await?;
+
+        tx.commit().await?;
+
+        if let Some(gateway) = self.gateway {
+            gateway.close_all_user_sessions(
+                user_id,
+                GatewayCloseCode::AccountTerminated,
+                "Account terminated".into(),
+            );
+            for guild_id in &guild_ids {
+                gateway.remove_member(user_id, *guild_id);
+            }
+        }
+        self.outbox().wake();
+
+        Ok(guild_ids.len() as u64)
+    }
+
+    /// Move attachments stored under an older S3 key layout to the current one, see [`KEYSPACE_VERSION`].
+    ///
+    /// Objects are moved lazily as they are accessed, so attachments nobody requests keep their old keys.
+    /// Attachments already stored under the current layout, or moved concurrently, are skipped.
+    ///
+    /// ## Arguments
+    ///
+    /// * `attachments` - The attachments to move.
+    ///
+    /// ## Returns
+    ///
+    /// The number of attachments moved.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::S3`] - If moving an object fails.
+    /// * [`OpsError::Db`] - If a database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn migrate_s3_keys(&self, attachments: &[PartialAttachment]) -> Result<u64, OpsError> {
+        let mut count = 0;
+
+        for attachment in attachments.iter().filter(|a| a.key_version() < KEYSPACE_VERSION) {
+            let mut tx = self.db.begin().await?;
+
+            // The row stays locked while the object is moved, so concurrent requests wait and then skip it
+            let res = sqlx::query!(
+                "UPDATE attachments SET key_version = $1
+                WHERE id = $2 AND message_id = $3 AND key_version = $4",
+                KEYSPACE_VERSION,
+                i32::from(attachment.id()),
+                attachment.message_id() as Snowflake<Message>,
+                attachment.key_version(),
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            if res.rows_affected() == 0 {
+                continue;
+            }
+
+            if let Some(s3) = self.s3 {
+                let migrated = attachment.clone().with_key_version(KEYSPACE_VERSION);
+                s3.attachments()
+                    .rename_object(attachment.s3_key(), migrated.s3_key())
+                    .await?;
+            }
+
+            tx.commit().await?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Verify that the configured snowflake epoch matches the one existing data was generated with.
+    ///
+    /// If no epoch has been recorded yet, the configured one is persisted.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Build`] - If the configured epoch differs from the recorded one.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn verify_snowflake_epoch(&self) -> Result<(), OpsError> {
+        sqlx::query!(
+            "INSERT INTO snowflake_epoch (epoch) VALUES ($1) ON CONFLICT (id) DO NOTHING",
+            self.config.snowflake_epoch()
+        )
+        .execute(self.db)
+        .await?;
+
+        let recorded = sqlx::query_scalar!("SELECT epoch FROM snowflake_epoch")
+            .fetch_one(self.db)
+            .await?;
+
+        if recorded != self.config.snowflake_epoch() {
+            return Err(BuildError::IllegalState(format!(
+                "Configured snowflake epoch {} does not match the epoch {recorded} existing data was generated with",
+                self.config.snowflake_epoch()
+            ))
+            .into());
+        }
+        Ok(())
+    }
+}
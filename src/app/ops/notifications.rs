@@ -0,0 +1,807 @@
+#![expect(clippy::disallowed_methods)] // Owns the pruning of rejected push tokens
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+use itertools::Itertools;
+use sqlx::error::DatabaseError;
+use tracing::field::{Empty, display};
+
+use super::{Ops, record_id};
+use crate::{
+    external::fcm::{FCMErrorCode, FirebaseError, FirebaseErrorKind, FirebaseMessaging, Notification, PushPriority},
+    gateway::SendMode,
+    models::{
+        channel::Channel,
+        errors::OpsError,
+        gateway_event::{GatewayEvent, ReadStateEntry},
+        guild::Guild,
+        message::Message,
+        notification_digest::{GuildUnreadCount, NotificationDigest},
+        notification_level::{NotificationLevel, NotificationOverride},
+        outbox::PushedMessage,
+        request_payloads::UpdateFCMToken,
+        snowflake::Snowflake,
+        user::User,
+    },
+};
+
+/// The highest unread count reported for a channel, counts above it are reported as this value.
+pub const MAX_UNREAD_COUNT: i64 = 100;
+
+/// Operations on read states and push notifications.
+#[derive(Clone, Copy)]
+pub struct NotificationOps<'a> {
+    ops: Ops<'a>,
+}
+
+impl<'a> NotificationOps<'a> {
+    /// Create a new [`NotificationOps`] sharing the components of the given [`Ops`].
+    pub const fn new(ops: Ops<'a>) -> Self {
+        Self { ops }
+    }
+
+    /// Update the read state for a given user in a channel.
+    /// The read state's last viewed time is set to the current time.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to update the read state for.
+    /// * `channel` - The channel to update the read state for.
+    /// * `message` - The last read message in the channel.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty, channel_id = Empty, message_id = Empty))]
+    pub async fn update_read_state(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        channel: impl Into<Snowflake<Channel>>,
+        last_message: impl Into<Snowflake<Message>>,
+    ) -> Result<(), OpsError> {
+        let user_id = record_id("user_id", user);
+        let channel_id = record_id("channel_id", channel);
+        let message_id = record_id("message_id", last_message);
+
+        // Reading the channel also answers any pushes sent about it
+        sqlx::query!(
+            "WITH answered AS (
+                DELETE FROM notification_states WHERE user_id = $1 AND channel_id = $2
+            )
+            INSERT INTO read_states (user_id, channel_id, message_id, last_viewed_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id, channel_id) DO UPDATE
+            SET message_id = GREATEST(read_states.message_id, $3), last_viewed_at = $4",
+            user_id as Snowflake<User>,
+            channel_id as Snowflake<Channel>,
+            message_id as Snowflake<Message>,
+            Utc::now(),
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetch all read states for a given user.
+    /// Only channels the user can currently view are included, even if they have read them before.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to fetch the read states for.
+    ///
+    /// ## Returns
+    ///
+    /// A map of channel IDs to the last read message in that channel.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn fetch_read_states(&self, user: impl Into<Snowflake<User>>) -> Result<Vec<ReadStateEntry>, OpsError> {
+        // We want to get info on all channels the member can see, so we resolve them through the visibility view,
+        // then left join read states (if they exist) to that. Read states of hidden channels are never returned.
+        let records = sqlx::query!(
+            r#"SELECT c.id AS channel_id,
+            r.message_id AS "last_read_message_id?",
+            r.last_viewed_at AS "last_viewed_at?",
+            c.last_message_id
+            FROM channel_visibility v
+            JOIN channels c ON c.id = v.channel_id
+            LEFT JOIN read_states r ON r.channel_id = c.id AND r.user_id = $1
+            WHERE v.user_id = $1"#,
+            record_id("user_id", user) as Snowflake<User>
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(records
+            .into_iter()
+            .map(|r| ReadStateEntry {
+                channel_id: r.channel_id.into(),
+                last_read_message_id: r.last_read_message_id.map(Into::into),
+                last_message_id: r.last_message_id.map(Into::into),
+                last_viewed_at: r.last_viewed_at,
+            })
+            .collect())
+    }
+
+    /// Count the messages in a channel that are newer than the user's last read message.
+    /// Channels the user never read count all of their messages as unread.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to count the unread messages for.
+    /// * `channel` - The channel to count the unread messages in.
+    ///
+    /// ## Returns
+    ///
+    /// The number of unread messages, at most [`MAX_UNREAD_COUNT`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty, channel_id = Empty))]
+    pub async fn fetch_unread_count(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        channel: impl Into<Snowflake<Channel>>,
+    ) -> Result<i64, OpsError> {
+        // The limit keeps the count cheap in channels with a large backlog
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM (
+                SELECT 1 FROM messages
+                WHERE channel_id = $2 AND id > COALESCE(
+                    (SELECT message_id FROM read_states WHERE user_id = $1 AND channel_id = $2), 0
+                )
+                LIMIT $3
+            ) AS unread"#,
+            record_id("user_id", user) as Snowflake<User>,
+            record_id("channel_id", channel) as Snowflake<Channel>,
+            MAX_UNREAD_COUNT,
+        )
+        .fetch_one(self.ops.db)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Count the unread messages mentioning each of the given users, shown as a badge by clients.
+    /// Only mentions in channels the users can still view count.
+    ///
+    /// ## Arguments
+    ///
+    /// * `users` - The users to count the unread mentions of.
+    ///
+    /// ## Returns
+    ///
+    /// The number of unread mentions of each user, at most [`MAX_UNREAD_COUNT`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_count = users.len()))]
+    pub async fn fetch_badges(&self, users: &[Snowflake<User>]) -> Result<HashMap<Snowflake<User>, i64>, OpsError> {
+        let records = sqlx::query!(
+            r#"SELECT u.id AS "user_id!", LEAST(COUNT(mn.message_id), $2) AS "badge!"
+            FROM unnest($1::BIGINT[]) AS u(id)
+            LEFT JOIN mentions mn ON mn.user_id = u.id
+                AND EXISTS (SELECT 1 FROM channel_visibility v WHERE v.user_id = u.id AND v.channel_id = mn.channel_id)
+                AND mn.message_id > COALESCE(
+                    (SELECT message_id FROM read_states r WHERE r.user_id = u.id AND r.channel_id = mn.channel_id), 0
+                )
+            GROUP BY u.id"#,
+            users as &[Snowflake<User>],
+            MAX_UNREAD_COUNT,
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(records.into_iter().map(|r| (r.user_id.into(), r.badge)).collect())
+    }
+
+    /// Count the unread messages mentioning a user, see [`NotificationOps::fetch_badges`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to count the unread mentions of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    pub async fn fetch_badge(&self, user: impl Into<Snowflake<User>>) -> Result<i64, OpsError> {
+        let user_id = user.into();
+        Ok(self
+            .fetch_badges(&[user_id])
+            .await?
+            .get(&user_id)
+            .copied()
+            .unwrap_or_default())
+    }
+
+    /// Send the unread badge of every connected user a message mentions to their sessions.
+    /// This function is a no-op if the gateway is not running.
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The message mentioning the users.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(message_id = Empty))]
+    pub async fn send_badge_updates(&self, message: impl Into<Snowflake<Message>>) -> Result<(), OpsError> {
+        let Some(gateway) = self.ops.gateway else {
+            return Ok(());
+        };
+
+        // Mentions are only indexed for users who can view the channel
+        let mentioned = sqlx::query_scalar!(
+            "SELECT user_id FROM mentions WHERE message_id = $1",
+            record_id("message_id", message) as Snowflake<Message>,
+        )
+        .fetch_all(self.ops.db)
+        .await?
+        .into_iter()
+        .map(Snowflake::<User>::from)
+        .collect::<HashSet<_>>();
+
+        let connected = gateway.is_connected_multiple(mentioned).await;
+        if connected.is_empty() {
+            return Ok(());
+        }
+
+        let users = connected.into_iter().collect::<Vec<_>>();
+        for (user_id, badge) in self.fetch_badges(&users).await? {
+            gateway.dispatch(GatewayEvent::UnreadUpdate { badge }, SendMode::ToUser(user_id));
+        }
+        Ok(())
+    }
+
+    /// Send a push notification to all inactive users who can view the channel.
+    /// This function is a no-op if FCM is not configured.
+    ///
+    /// Users who left more than [`Config::digest_threshold`](crate::app::Config::digest_threshold) pushes in the channel unanswered
+    /// do not receive the push, the channel is instead included in their next notification digest.
+    /// Users who muted a word contained in the message do not receive the push either.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild_id` - The guild the channel is in, or `None` for direct message channels.
+    /// * `originating_channel` - The channel the notification originated from.
+    /// * `notification` - The notification to send.
+    /// * `content` - The content of the message the notification is about, if any.
+    /// * `message` - The message the notification is about, if any, so that clients can link to it.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Firebase`] - If the FCM request fails.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, channel_id = Empty))]
+    pub async fn send_push_notif_to_inactives(
+        &self,
+        guild_id: Option<Snowflake<Guild>>,
+        originating_channel: impl Into<Snowflake<Channel>>,
+        notification: Notification,
+        content: Option<&str>,
+        message: Option<&PushedMessage>,
+    ) -> Result<(), OpsError> {
+        let Some(fcm) = self.ops.fcm else {
+            // Ignore if no FCM is configured
+            return Ok(());
+        };
+
+        let channel_id = record_id("channel_id", originating_channel);
+        tracing::Span::current().record("guild_id", guild_id.map(display));
+
+        // Get the notification tokens and level of all users who can view the channel and did not mute the message,
+        // direct messages notify about every message unless overridden
+        let records = sqlx::query!(
+            r#"SELECT fcm_tokens.user_id, fcm_tokens.token, COALESCE(o.level, g.default_notification_level, 0::SMALLINT) AS "level!"
+            FROM fcm_tokens
+            JOIN channel_visibility v ON v.user_id = fcm_tokens.user_id
+            LEFT JOIN guilds g ON g.id = v.guild_id
+            LEFT JOIN notification_overrides o ON o.user_id = fcm_tokens.user_id AND o.channel_id = v.channel_id
+            LEFT JOIN prefs p ON p.user_id = fcm_tokens.user_id
+            WHERE v.channel_id = $1
+            AND NOT EXISTS (SELECT 1 FROM unnest(p.muted_words) w WHERE strpos(lower($2), w) > 0)"#,
+            channel_id as Snowflake<Channel>,
+            content,
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        // Mentions wake the devices of the users they mention, unless they silenced the channel
+        let mentions = message.map(|m| m.mentions.as_slice()).unwrap_or_default();
+        let priorities = records
+            .iter()
+            .map(|r| {
+                let user_id = Snowflake::<User>::from(r.user_id);
+                let level = NotificationLevel::from(r.level);
+                (user_id, level.push_priority(mentions.contains(&user_id)))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut tokens = records
+            .into_iter()
+            .into_grouping_map_by(|r| Snowflake::<User>::from(r.user_id))
+            .fold(Vec::new(), |mut acc, _id, val| {
+                acc.push(val.token);
+                acc
+            });
+
+        // Remove users that are currently connected
+        if let Some(gateway) = self.ops.gateway.as_ref() {
+            let user_ids = tokens.keys().copied().collect::<HashSet<_>>();
+            let connected = gateway.is_connected_multiple(user_ids).await;
+            tokens.retain(|id, _v| !connected.contains(id));
+        }
+
+        if tokens.is_empty() {
+            return Ok(());
+        }
+
+        let user_ids = tokens.keys().copied().collect::<Vec<_>>();
+
+        // Record the push for each user, and collapse it into a digest if they have not answered the previous ones
+        let digested = sqlx::query!(
+            "INSERT INTO notification_states (user_id, channel_id, unanswered)
+            SELECT unnest($1::BIGINT[]), $2, 1
+            ON CONFLICT (user_id, channel_id) DO UPDATE
+            SET unanswered = notification_states.unanswered + 1,
+                pending_digest = notification_states.unanswered >= $3
+            RETURNING user_id, pending_digest",
+            &user_ids as &[Snowflake<User>],
+            channel_id as Snowflake<Channel>,
+            i32::try_from(self.ops.config.digest_threshold()).unwrap_or(i32::MAX),
+        )
+        .fetch_all(self.ops.db)
+        .await?
+        .into_iter()
+        .filter(|r| r.pending_digest)
+        .map(|r| Snowflake::<User>::from(r.user_id))
+        .collect::<HashSet<_>>();
+
+        tokens.retain(|id, _v| !digested.contains(id));
+
+        if tokens.is_empty() {
+            return Ok(());
+        }
+
+        tracing::debug!(
+            guild = guild_id.map(display),
+            user_count = %tokens.len(),
+            notification = ?notification,
+            "Sending push notification to inactive users",
+        );
+
+        // Pushes about the same channel replace each other on the device, instead of piling up
+        let collapse_key = format!("channel_{channel_id}");
+        let mut data = HashMap::from([
+            ("type".to_string(), "notification".to_string()),
+            ("title".to_string(), notification.title),
+            ("body".to_string(), notification.body),
+            ("channel_id".to_string(), channel_id.to_string()),
+            ("collapse_key".to_string(), collapse_key.clone()),
+        ]);
+        if let Some(guild_id) = guild_id {
+            data.insert("guild_id".to_string(), guild_id.to_string());
+            if let Some(guild) = self.ops.guilds().fetch_guild(guild_id).await? {
+                data.insert("guild_name".to_string(), guild.name().to_string());
+            }
+        }
+        if let Some(message) = message {
+            message.extend_data(&mut data);
+        }
+
+        self.send_badged_pushes(fcm, tokens, &priorities, data, &collapse_key)
+            .await
+    }
+
+    /// Send a push to the given users, adding each user's unread mention badge and priority hints to its data.
+    /// Users sharing the same badge and priority are sent a single request.
+    ///
+    /// ## Arguments
+    ///
+    /// * `fcm` - The FCM client to send the pushes with.
+    /// * `tokens` - The notification tokens of each user.
+    /// * `priorities` - The priority of the push for each user, users without one are sent a normal priority push.
+    /// * `data` - The data payload shared by all pushes.
+    /// * `collapse_key` - The key pushes replace each other under on the device.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Firebase`] - If the FCM request fails.
+    /// * [`OpsError::Db`] - If the database query fails.
+    async fn send_badged_pushes(
+        &self,
+        fcm: &FirebaseMessaging,
+        tokens: HashMap<Snowflake<User>, Vec<String>>,
+        priorities: &HashMap<Snowflake<User>, PushPriority>,
+        data: HashMap<String, String>,
+        collapse_key: &str,
+    ) -> Result<(), OpsError> {
+        // Each user's badge is the same number UNREAD_UPDATE reports
+        let user_ids = tokens.keys().copied().collect::<Vec<_>>();
+        let badges = self.fetch_badges(&user_ids).await?;
+        let groups = tokens.into_iter().into_group_map_by(|(id, _)| {
+            (
+                badges.get(id).copied().unwrap_or_default(),
+                priorities.get(id).copied().unwrap_or_default(),
+            )
+        });
+
+        let mut errors = Vec::new();
+        for ((badge, priority), group) in groups {
+            let mut data = data.clone();
+            data.insert("badge".to_string(), badge.to_string());
+            priority.extend_data(&mut data);
+            if let Err(e) = fcm
+                .send_notification_to_multiple(
+                    group.into_iter().flat_map(|(_, tokens)| tokens),
+                    None,
+                    Some(data),
+                    Some(collapse_key),
+                    priority,
+                )
+                .await
+            {
+                errors.extend(e);
+            }
+        }
+
+        if !errors.is_empty() {
+            return self.handle_fcm_errors(errors).await;
+        }
+
+        Ok(())
+    }
+
+    /// Send a digest to every inactive user with channels pending in their notification state,
+    /// summarizing their unread messages per guild.
+    /// This function only clears the pending digests if FCM is not configured.
+    ///
+    /// ## Returns
+    ///
+    /// The number of users a digest was sent to.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Firebase`] - If the FCM request fails.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn send_notification_digests(&self) -> Result<u64, OpsError> {
+        let mut tx = self.ops.db.begin().await?;
+
+        let records = sqlx::query!(
+            r#"UPDATE notification_states SET pending_digest = FALSE
+            WHERE pending_digest
+            RETURNING user_id, channel_id"#
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let (user_ids, channel_ids): (Vec<Snowflake<User>>, Vec<Snowflake<Channel>>) = records
+            .into_iter()
+            .map(|r| (Snowflake::from(r.user_id), Snowflake::from(r.channel_id)))
+            .unzip();
+
+        let Some(fcm) = self.ops.fcm else {
+            tx.commit().await?;
+            return Ok(0);
+        };
+
+        // Count the unread messages in the pending channels, grouped by guild
+        let mut digests = sqlx::query!(
+            r#"SELECT p.user_id AS "user_id!", g.id AS guild_id, g.name AS guild_name, COUNT(m.id) AS "unread!"
+            FROM unnest($1::BIGINT[], $2::BIGINT[]) AS p(user_id, channel_id)
+            JOIN channels c ON c.id = p.channel_id
+            JOIN guilds g ON g.id = c.guild_id
+            LEFT JOIN read_states r ON r.user_id = p.user_id AND r.channel_id = p.channel_id
+            JOIN messages m ON m.channel_id = p.channel_id AND m.id > COALESCE(r.message_id, 0)
+            GROUP BY p.user_id, g.id, g.name"#,
+            &user_ids as &[Snowflake<User>],
+            &channel_ids as &[Snowflake<Channel>],
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .into_grouping_map_by(|r| Snowflake::<User>::from(r.user_id))
+        .fold(Vec::new(), |mut acc, _id, r| {
+            acc.push(GuildUnreadCount {
+                guild_id: r.guild_id.into(),
+                guild_name: r.guild_name,
+                unread: r.unread,
+            });
+            acc
+        });
+
+        tx.commit().await?;
+
+        // Users who came back online in the meantime will see their unreads on their own
+        if let Some(gateway) = self.ops.gateway.as_ref() {
+            let connected = gateway.is_connected_multiple(digests.keys().copied().collect()).await;
+            digests.retain(|id, _v| !connected.contains(id));
+        }
+
+        let recipients = digests.keys().copied().collect::<Vec<_>>();
+
+        let mut tokens = sqlx::query!(
+            "SELECT user_id, token FROM fcm_tokens WHERE user_id = ANY($1)",
+            &recipients as &[Snowflake<User>]
+        )
+        .fetch_all(self.ops.db)
+        .await?
+        .into_iter()
+        .into_grouping_map_by(|r| Snowflake::<User>::from(r.user_id))
+        .fold(Vec::new(), |mut acc, _id, r| {
+            acc.push(r.token);
+            acc
+        });
+
+        let mut sent = 0;
+        let mut errors = Vec::new();
+
+        for (user_id, guilds) in digests {
+            let digest = NotificationDigest::new(guilds);
+            let Some(user_tokens) = tokens.remove(&user_id) else {
+                continue;
+            };
+            if digest.is_empty() {
+                continue;
+            }
+
+            tracing::debug!(user = %user_id, unread = %digest.total_unread(), "Sending notification digest");
+
+            // Digests summarize messages the user was already notified about, so they never wake the device
+            let mut data = digest.data();
+            PushPriority::Normal.extend_data(&mut data);
+            match fcm
+                .send_notification_to_multiple(user_tokens, None, Some(data), None, PushPriority::Normal)
+                .await
+            {
+                Ok(()) => sent += 1,
+                Err(e) => errors.extend(e),
+            }
+        }
+
+        if !errors.is_empty() {
+            self.handle_fcm_errors(errors).await?;
+        }
+
+        Ok(sent)
+    }
+
+    /// Drop all tokens FCM reported as unregistered, and return the remaining errors, if any.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::FirebaseMulti`] - If any of the errors are not caused by an unregistered token.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub(super) async fn handle_fcm_errors(&self, errors: Vec<FirebaseError>) -> Result<(), OpsError> {
+        let mut invalid_tokens = Vec::new();
+
+        let actual_errors: Vec<_> = errors
+            .into_iter()
+            .filter(|error| {
+                if let (FirebaseErrorKind::Api(api_error), Some(token)) = (error.kind(), error.token())
+                    && api_error.get_fcm_error_code() == Some(FCMErrorCode::Unregistered)
+                {
+                    invalid_tokens.push(token.to_string());
+                    return false; // Filter out unregistered errors
+                }
+                true // Keep all other errors
+            })
+            .collect();
+
+        // Drop all invalid tokens
+        if !invalid_tokens.is_empty() {
+            sqlx::query!("DELETE FROM fcm_tokens WHERE token = ANY($1)", &invalid_tokens)
+                .execute(self.ops.db)
+                .await?;
+        }
+
+        tracing::debug!(invalid_token_count = %invalid_tokens.len(), "Removed {} invalid FCM tokens", invalid_tokens.len());
+
+        if !actual_errors.is_empty() {
+            return Err(OpsError::FirebaseMulti(actual_errors));
+        }
+
+        Ok(())
+    }
+
+    /// Fetch the notification levels a user set for individual channels.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to fetch the overrides of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn fetch_notification_overrides(
+        &self,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Vec<NotificationOverride>, OpsError> {
+        let records = sqlx::query!(
+            "SELECT channel_id, level FROM notification_overrides WHERE user_id = $1 ORDER BY channel_id",
+            record_id("user_id", user) as Snowflake<User>,
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(records
+            .into_iter()
+            .map(|r| NotificationOverride {
+                channel_id: r.channel_id.into(),
+                level: NotificationLevel::from(r.level),
+            })
+            .collect())
+    }
+
+    /// Set the notification level of a channel for a user, replacing the previous one if any.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to set the level for.
+    /// * `channel` - The channel to set the level of.
+    /// * `level` - The level to set.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty, channel_id = Empty))]
+    pub async fn set_notification_override(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        channel: impl Into<Snowflake<Channel>>,
+        level: NotificationLevel,
+    ) -> Result<NotificationOverride, OpsError> {
+        let channel_id = record_id("channel_id", channel);
+
+        sqlx::query!(
+            "INSERT INTO notification_overrides (user_id, channel_id, level)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, channel_id) DO UPDATE SET level = $3",
+            record_id("user_id", user) as Snowflake<User>,
+            channel_id as Snowflake<Channel>,
+            level as i16,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(NotificationOverride { channel_id, level })
+    }
+
+    /// Remove the notification level a user set for a channel, falling back to the default level of its guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to remove the level of.
+    /// * `channel` - The channel to remove the level of.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the user had set a level for the channel.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty, channel_id = Empty))]
+    pub async fn remove_notification_override(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        channel: impl Into<Snowflake<Channel>>,
+    ) -> Result<bool, OpsError> {
+        let res = sqlx::query!(
+            "DELETE FROM notification_overrides WHERE user_id = $1 AND channel_id = $2",
+            record_id("user_id", user) as Snowflake<User>,
+            record_id("channel_id", channel) as Snowflake<Channel>,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Update the FCM token for a user. If a previous token is provided, it will be removed.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to update the FCM token for.
+    /// * `payload` - The update payload.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::NotFound`] - If the user is not found.
+    /// * [`OpsError::Conflict`] - If the token already exists.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn update_fcm_token(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        payload: UpdateFCMToken,
+    ) -> Result<(), OpsError> {
+        let user_id = record_id("user_id", user);
+
+        let mut tx = self.ops.db.begin().await?;
+
+        if let Err(e) = sqlx::query!(
+            "INSERT INTO fcm_tokens (user_id, token)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id, token) DO UPDATE SET last_refresh = NOW()",
+            user_id as Snowflake<User>,
+            payload.token,
+        )
+        .execute(&mut *tx)
+        .await
+        {
+            if e.as_database_error()
+                .is_some_and(DatabaseError::is_foreign_key_violation)
+            {
+                return Err(OpsError::NotFound("User not found".into()));
+            }
+
+            if e.as_database_error().is_some_and(DatabaseError::is_unique_violation) {
+                return Err(OpsError::Conflict("Token already exists".into()));
+            }
+        }
+
+        if let Some(prev_token) = payload.previous_token {
+            sqlx::query!(
+                "DELETE FROM fcm_tokens WHERE user_id = $1 AND token = $2",
+                user_id as Snowflake<User>,
+                prev_token,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Remove an FCM token for a user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to remove the FCM token for.
+    /// * `token` - The token to remove.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn remove_fcm_token(&self, user: impl Into<Snowflake<User>>, token: &str) -> Result<(), OpsError> {
+        sqlx::query!(
+            "DELETE FROM fcm_tokens WHERE user_id = $1 AND token = $2",
+            record_id("user_id", user) as Snowflake<User>,
+            token,
+        )
+        .execute(self.ops.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Clear all FCM tokens that have not been refreshed in the last 30 days.
+    ///
+    /// ## Returns
+    ///
+    /// The number of stale FCM tokens removed.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn clear_stale_fcm_tokens(&self) -> Result<u64, OpsError> {
+        let res = sqlx::query!("DELETE FROM fcm_tokens WHERE last_refresh < NOW() - INTERVAL '30 days'")
+            .execute(self.ops.db)
+            .await?;
+
+        Ok(res.rows_affected())
+    }
+}
@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use sqlx::PgExecutor;
+
+use super::Ops;
+use crate::{
+    gateway::EventTrace,
+    models::{
+        errors::OpsError,
+        gateway_event::GatewayEvent,
+        outbox::{MAX_OUTBOX_ATTEMPTS, OutboxEntry},
+    },
+};
+
+/// The maximum number of outbox entries emitted in one go.
+pub const OUTBOX_BATCH_SIZE: i64 = 100;
+
+/// For how long a relay may emit the entries it took before other relays assume it died and emit them again.
+pub const OUTBOX_LEASE: Duration = Duration::from_secs(60);
+
+/// Operations on the transactional outbox, which emits the side effects of changes once they are committed.
+#[derive(Clone, Copy)]
+pub struct OutboxOps<'a> {
+    ops: Ops<'a>,
+}
+
+impl<'a> OutboxOps<'a> {
+    /// Create a new [`OutboxOps`] sharing the components of the given [`Ops`].
+    pub const fn new(ops: Ops<'a>) -> Self {
+        Self { ops }
+    }
+
+    /// Write entries to the outbox. They are emitted once the surrounding transaction commits,
+    /// after which [`OutboxOps::wake`] should be called.
+    ///
+    /// ## Arguments
+    ///
+    /// * `executor` - The transaction making the change the entries belong to.
+    /// * `entries` - The entries to write, in the order they should be emitted.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    pub async fn enqueue(executor: impl PgExecutor<'_>, entries: &[OutboxEntry]) -> Result<(), OpsError> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let entries: Vec<String> = entries
+            .iter()
+            .map(|e| serde_json::to_string(e).expect("outbox entry should serialize"))
+            .collect();
+
+        sqlx::query!(
+            "INSERT INTO event_outbox (entry)
+            SELECT e.entry FROM UNNEST($1::TEXT[]) WITH ORDINALITY AS e (entry, position)
+            ORDER BY e.position",
+            &entries,
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Wake the outbox relay, so that freshly committed entries are emitted right away.
+    pub fn wake(&self) {
+        if let Some(relay) = self.ops.outbox_relay {
+            relay.wake();
+        }
+    }
+
+    /// Emit the oldest entries waiting in the outbox, removing them once emitted.
+    ///
+    /// Entries are leased for [`OUTBOX_LEASE`] while they are emitted, so concurrent relays never emit
+    /// the same entry, and no rows stay locked while waiting on push notifications.
+    /// Entries of a relay that stopped while emitting are emitted again once their lease expired.
+    /// Entries that fail to be emitted are released to be retried on the next run,
+    /// and dropped after [`MAX_OUTBOX_ATTEMPTS`] failures.
+    ///
+    /// ## Returns
+    ///
+    /// The number of entries emitted.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If a database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn relay(&self) -> Result<u64, OpsError> {
+        let mut pending = sqlx::query!(
+            "UPDATE event_outbox SET leased_until = NOW() + make_interval(secs => $2)
+            WHERE id IN (
+                SELECT id FROM event_outbox
+                WHERE leased_until IS NULL OR leased_until < NOW()
+                ORDER BY id LIMIT $1 FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, entry",
+            OUTBOX_BATCH_SIZE,
+            OUTBOX_LEASE.as_secs_f64(),
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+        // RETURNING does not preserve the order of the subquery
+        pending.sort_unstable_by_key(|record| record.id);
+
+        let mut emitted = Vec::with_capacity(pending.len());
+        let mut failed = Vec::new();
+
+        for record in pending {
+            let entry = match serde_json::from_str::<OutboxEntry>(&record.entry) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    tracing::error!(error = %e, "Dropping malformed outbox entry {}", record.id);
+                    emitted.push(record.id);
+                    continue;
+                }
+            };
+
+            match self.emit(entry).await {
+                Ok(()) => emitted.push(record.id),
+                Err(e) => {
+                    tracing::warn!(error = %e, "Failed to emit outbox entry {}", record.id);
+                    failed.push(record.id);
+                }
+            }
+        }
+
+        let mut tx = self.ops.db.begin().await?;
+
+        sqlx::query!("DELETE FROM event_outbox WHERE id = ANY($1)", &emitted)
+            .execute(&mut *tx)
+            .await?;
+
+        if !failed.is_empty() {
+            sqlx::query!(
+                "UPDATE event_outbox SET attempts = attempts + 1, leased_until = NULL WHERE id = ANY($1)",
+                &failed
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            let dropped = sqlx::query_scalar!(
+                "DELETE FROM event_outbox WHERE id = ANY($1) AND attempts >= $2 RETURNING id",
+                &failed,
+                MAX_OUTBOX_ATTEMPTS,
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+
+            for id in dropped {
+                tracing::error!("Giving up on emitting outbox entry {id} after {MAX_OUTBOX_ATTEMPTS} attempts");
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(emitted.len() as u64)
+    }
+
+    /// Emit a single outbox entry.
+    async fn emit(&self, entry: OutboxEntry) -> Result<(), OpsError> {
+        match entry {
+            OutboxEntry::Dispatch {
+                event,
+                send_mode,
+                trace,
+            } => {
+                if let Some(gateway) = self.ops.gateway {
+                    // Entries written before events were traced are traced from here on
+                    let trace = trace.unwrap_or_else(EventTrace::current);
+                    gateway.dispatch_traced(GatewayEvent::Relayed(event), send_mode, trace);
+                }
+                Ok(())
+            }
+            OutboxEntry::Badge { message_id } => self.ops.notifications().send_badge_updates(message_id).await,
+            OutboxEntry::Push {
+                guild_id,
+                channel_id,
+                notification,
+                content,
+                message,
+            } => {
+                self.ops
+                    .notifications()
+                    .send_push_notif_to_inactives(
+                        guild_id,
+                        channel_id,
+                        notification,
+                        content.as_deref(),
+                        message.as_ref(),
+                    )
+                    .await
+            }
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use chrono::Utc;
+use tracing::field::Empty;
+
+use super::{Ops, record_id};
+use crate::models::{
+    errors::OpsError,
+    relationship::{DmPolicy, ExtendedRelationshipRecord, Relationship, RelationshipType},
+    snowflake::Snowflake,
+    user::User,
+};
+
+/// Operations on friendships and friend requests between users.
+#[derive(Clone, Copy)]
+pub struct RelationshipOps<'a> {
+    ops: Ops<'a>,
+}
+
+impl<'a> RelationshipOps<'a> {
+    /// Create a new [`RelationshipOps`] sharing the components of the given [`Ops`].
+    pub const fn new(ops: Ops<'a>) -> Self {
+        Self { ops }
+    }
+
+    /// Fetch all relationships of a user, oldest first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to fetch the relationships of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If a stored relationship is invalid.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn fetch_relationships(&self, user: impl Into<Snowflake<User>>) -> Result<Vec<Relationship>, OpsError> {
+        let records = sqlx::query_as!(
+            ExtendedRelationshipRecord,
+            "SELECT r.other_id, r.relationship_type, r.created_at,
+            u.username, u.display_name, u.avatar_hash, u.banner_hash, u.last_presence
+            FROM relationships r
+            JOIN users u ON u.id = r.other_id
+            WHERE r.user_id = $1
+            ORDER BY r.created_at, r.other_id",
+            record_id("user_id", user) as Snowflake<User>
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        records
+            .into_iter()
+            .map(Relationship::from_record)
+            .collect::<Result<_, _>>()
+            .map_err(Into::into)
+    }
+
+    /// Fetch the relationship of a user with another user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user whose perspective the relationship is returned from.
+    /// * `other` - The other user.
+    ///
+    /// ## Returns
+    ///
+    /// The kind of relationship, or `None` if there is none.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If the stored relationship is invalid.
+    #[tracing::instrument(skip_all, fields(user_id = Empty, other_id = Empty))]
+    pub async fn fetch_relationship(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        other: impl Into<Snowflake<User>>,
+    ) -> Result<Option<RelationshipType>, OpsError> {
+        let kind = sqlx::query_scalar!(
+            "SELECT relationship_type FROM relationships WHERE user_id = $1 AND other_id = $2",
+            record_id("user_id", user) as Snowflake<User>,
+            record_id("other_id", other) as Snowflake<User>,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(kind.map(RelationshipType::try_from).transpose()?)
+    }
+
+    /// Send a friend request to another user, or accept their friend request if they already sent one.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user sending or accepting the request.
+    /// * `other` - The user the request is sent to or was received from.
+    ///
+    /// ## Returns
+    ///
+    /// The new relationship from the perspective of `user` if it changed,
+    /// or `None` if the request was already sent or the users are already friends.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::BadRequest`] - If the user tries to befriend themselves.
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If the stored relationship is invalid.
+    #[tracing::instrument(skip_all, fields(user_id = Empty, other_id = Empty))]
+    pub async fn add_relationship(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        other: impl Into<Snowflake<User>>,
+    ) -> Result<Option<RelationshipType>, OpsError> {
+        let user_id = record_id("user_id", user);
+        let other_id = record_id("other_id", other);
+
+        if user_id == other_id {
+            return Err(OpsError::BadRequest("Cannot befriend yourself".into()));
+        }
+
+        let mut tx = self.ops.db.begin().await?;
+
+        // Lock the existing relationship, so accepting a request cannot race with it being cancelled
+        let current = sqlx::query_scalar!(
+            "SELECT relationship_type FROM relationships
+            WHERE (user_id = $1 AND other_id = $2) OR (user_id = $2 AND other_id = $1)
+            ORDER BY user_id = $1 DESC
+            FOR UPDATE",
+            user_id as Snowflake<User>,
+            other_id as Snowflake<User>,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .map(RelationshipType::try_from)
+        .transpose()?;
+
+        let new = match current {
+            None => RelationshipType::OutgoingRequest,
+            Some(RelationshipType::IncomingRequest) => RelationshipType::Friend,
+            Some(RelationshipType::OutgoingRequest | RelationshipType::Friend) => return Ok(None),
+        };
+
+        sqlx::query!(
+            "INSERT INTO relationships (user_id, other_id, relationship_type, created_at)
+            VALUES ($1, $2, $3, $5), ($2, $1, $4, $5)
+            ON CONFLICT (user_id, other_id) DO UPDATE
+            SET relationship_type = EXCLUDED.relationship_type, created_at = EXCLUDED.created_at",
+            user_id as Snowflake<User>,
+            other_id as Snowflake<User>,
+            new as i16,
+            new.counterpart() as i16,
+            Utc::now(),
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(new))
+    }
+
+    /// Remove the relationship between two users.
+    /// This declines or cancels a pending friend request, or ends a friendship.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user removing the relationship.
+    /// * `other` - The other user.
+    ///
+    /// ## Returns
+    ///
+    /// Whether there was a relationship to remove.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty, other_id = Empty))]
+    pub async fn remove_relationship(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        other: impl Into<Snowflake<User>>,
+    ) -> Result<bool, OpsError> {
+        let result = sqlx::query!(
+            "DELETE FROM relationships
+            WHERE (user_id = $1 AND other_id = $2) OR (user_id = $2 AND other_id = $1)",
+            record_id("user_id", user) as Snowflake<User>,
+            record_id("other_id", other) as Snowflake<User>,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Check if a user may open direct messages with another user, according to
+    /// [`Config::dm_policy`](crate::app::Config::dm_policy).
+    /// Accounts within [`Config::new_account_restriction`](crate::app::Config::new_account_restriction)
+    /// may only open direct messages with their friends, so that they cannot be used to mass message users.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user opening the direct messages.
+    /// * `other` - The recipient.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty, other_id = Empty))]
+    pub async fn can_open_dm(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        other: impl Into<Snowflake<User>>,
+    ) -> Result<bool, OpsError> {
+        let user: Snowflake<User> = record_id("user_id", user);
+        let policy = if self.ops.config.is_new_account(user) {
+            DmPolicy::Friends
+        } else {
+            self.ops.config.dm_policy()
+        };
+        if policy == DmPolicy::Anyone {
+            return Ok(true);
+        }
+
+        let allowed = sqlx::query_scalar!(
+            r#"SELECT EXISTS (
+                SELECT 1 FROM relationships
+                WHERE user_id = $1 AND other_id = $2 AND relationship_type = $3
+            ) OR ($4 AND EXISTS (
+                SELECT 1 FROM members a
+                JOIN members b ON b.guild_id = a.guild_id
+                WHERE a.user_id = $1 AND b.user_id = $2
+            )) AS "allowed!""#,
+            user as Snowflake<User>,
+            record_id("other_id", other) as Snowflake<User>,
+            RelationshipType::Friend as i16,
+            policy == DmPolicy::FriendsOrMutualGuild,
+        )
+        .fetch_one(self.ops.db)
+        .await?;
+
+        Ok(allowed)
+    }
+}
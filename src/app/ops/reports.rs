@@ -0,0 +1,310 @@
+use tracing::field::Empty;
+
+use super::{Ops, record_id};
+use crate::models::{
+    errors::OpsError,
+    gateway_event::GatewayEvent,
+    guild::Guild,
+    report::{Report, ReportAction, ReportRecord, ReportStatus},
+    role::Permissions,
+    snowflake::Snowflake,
+    user::User,
+};
+
+/// The maximum number of reports returned by a single query.
+pub const MAX_REPORT_QUERY_LIMIT: u32 = 100;
+
+/// Operations on reports filed by users and their triage by the instance's administrators.
+#[derive(Clone, Copy)]
+pub struct ReportOps<'a> {
+    ops: Ops<'a>,
+}
+
+impl<'a> ReportOps<'a> {
+    /// Create a new [`ReportOps`] sharing the components of the given [`Ops`].
+    pub const fn new(ops: Ops<'a>) -> Self {
+        Self { ops }
+    }
+
+    /// Store a new report, and forward it to the members with [`Permissions::MODERATE_GUILD`] in its guild if requested.
+    ///
+    /// The reported object is expected to have been checked to exist and be visible to the reporter.
+    ///
+    /// ## Arguments
+    ///
+    /// * `report` - The report to store.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Conflict`] - If the reporter already has an unresolved report about the same object.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(report_id = %report.id()))]
+    pub async fn create_report(&self, report: &Report) -> Result<(), OpsError> {
+        let forwarded_to = sqlx::query_scalar!(
+            "WITH inserted AS (
+                INSERT INTO reports (id, reporter_id, target_type, target_id, guild_id, category, details, forwarded)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                ON CONFLICT (reporter_id, target_type, target_id) WHERE status <> 3 DO NOTHING
+                RETURNING guild_id, forwarded
+            )
+            SELECT CASE WHEN forwarded THEN guild_id END AS \"guild_id?\" FROM inserted",
+            report.id() as Snowflake<Report>,
+            report.reporter_id() as Option<Snowflake<User>>,
+            report.target_type() as i16,
+            report.target_id() as Snowflake<()>,
+            report.guild_id() as Option<Snowflake<Guild>>,
+            report.category() as i16,
+            report.details(),
+            report.is_forwarded(),
+        )
+        .fetch_optional(self.ops.db)
+        .await?
+        .ok_or_else(|| OpsError::Conflict("You already reported this".into()))?;
+
+        if let (Some(gateway), Some(guild_id)) = (self.ops.gateway, forwarded_to) {
+            let moderators = self
+                .ops
+                .roles()
+                .fetch_users_with_permission(guild_id, Permissions::MODERATE_GUILD)
+                .await?;
+            for moderator in moderators {
+                gateway.send_to(moderator, GatewayEvent::ReportCreate(report.clone().anonymized()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fetch a single report.
+    ///
+    /// ## Arguments
+    ///
+    /// * `report` - The ID of the report.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If the stored report is invalid.
+    #[tracing::instrument(skip_all, fields(report_id = Empty))]
+    pub async fn fetch_report(&self, report: impl Into<Snowflake<Report>>) -> Result<Option<Report>, OpsError> {
+        let record = sqlx::query_as!(
+            ReportRecord,
+            "SELECT id, reporter_id, target_type, target_id, guild_id, category, details, forwarded,
+            status, claimed_by, action_taken, resolved_at
+            FROM reports WHERE id = $1",
+            record_id("report_id", report) as Snowflake<Report>,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(record.map(Report::from_record).transpose()?)
+    }
+
+    /// Fetch the reports in the administrators' queue, oldest first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `status` - Only return reports with this status, or all reports if `None`.
+    /// * `after` - Only return reports filed after this one.
+    /// * `limit` - The maximum number of reports to return, at most [`MAX_REPORT_QUERY_LIMIT`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If a stored report is invalid.
+    #[tracing::instrument(skip(self))]
+    pub async fn fetch_reports(
+        &self,
+        status: Option<ReportStatus>,
+        after: Option<Snowflake<Report>>,
+        limit: Option<u32>,
+    ) -> Result<Vec<Report>, OpsError> {
+        let records = sqlx::query_as!(
+            ReportRecord,
+            "SELECT id, reporter_id, target_type, target_id, guild_id, category, details, forwarded,
+            status, claimed_by, action_taken, resolved_at
+            FROM reports
+            WHERE ($1::SMALLINT IS NULL OR status = $1) AND id > $2
+            ORDER BY id
+            LIMIT $3",
+            status.map(|s| s as i16),
+            after.map_or(0, i64::from),
+            i64::from(limit.unwrap_or(MAX_REPORT_QUERY_LIMIT).min(MAX_REPORT_QUERY_LIMIT)),
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        records
+            .into_iter()
+            .map(Report::from_record)
+            .collect::<Result<_, _>>()
+            .map_err(Into::into)
+    }
+
+    /// Fetch the reports forwarded to the moderators of a guild, newest first.
+    /// The identities of the reporters and administrators are not included.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to fetch the reports of.
+    /// * `before` - Only return reports filed before this one.
+    /// * `limit` - The maximum number of reports to return, at most [`MAX_REPORT_QUERY_LIMIT`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If a stored report is invalid.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn fetch_guild_reports(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        before: Option<Snowflake<Report>>,
+        limit: Option<u32>,
+    ) -> Result<Vec<Report>, OpsError> {
+        let records = sqlx::query_as!(
+            ReportRecord,
+            "SELECT id, reporter_id, target_type, target_id, guild_id, category, details, forwarded,
+            status, claimed_by, action_taken, resolved_at
+            FROM reports
+            WHERE guild_id = $1 AND forwarded AND id < $2
+            ORDER BY id DESC
+            LIMIT $3",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            before.map_or(i64::MAX, i64::from),
+            i64::from(limit.unwrap_or(MAX_REPORT_QUERY_LIMIT).min(MAX_REPORT_QUERY_LIMIT)),
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        records
+            .into_iter()
+            .map(|r| Report::from_record(r).map(Report::anonymized))
+            .collect::<Result<_, _>>()
+            .map_err(Into::into)
+    }
+
+    /// Claim a report, signalling to other administrators that it is being looked into.
+    ///
+    /// ## Arguments
+    ///
+    /// * `report` - The ID of the report.
+    /// * `admin` - The administrator claiming the report.
+    ///
+    /// ## Returns
+    ///
+    /// The claimed report.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::NotFound`] - If the report does not exist.
+    /// * [`OpsError::Conflict`] - If the report was claimed by another administrator or is already resolved.
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If the stored report is invalid.
+    #[tracing::instrument(skip_all, fields(report_id = Empty, user_id = Empty))]
+    pub async fn claim_report(
+        &self,
+        report: impl Into<Snowflake<Report>>,
+        admin: impl Into<Snowflake<User>>,
+    ) -> Result<Report, OpsError> {
+        let report_id = record_id("report_id", report);
+
+        let record = sqlx::query_as!(
+            ReportRecord,
+            "UPDATE reports SET status = $3, claimed_by = $2
+            WHERE id = $1 AND (status = $4 OR (status = $3 AND (claimed_by = $2 OR claimed_by IS NULL)))
+            RETURNING id, reporter_id, target_type, target_id, guild_id, category, details, forwarded,
+            status, claimed_by, action_taken, resolved_at",
+            report_id as Snowflake<Report>,
+            record_id("user_id", admin) as Snowflake<User>,
+            ReportStatus::Claimed as i16,
+            ReportStatus::Open as i16,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        if let Some(record) = record {
+            return Ok(Report::from_record(record)?);
+        }
+
+        match self.fetch_report(report_id).await? {
+            Some(report) if report.status() == ReportStatus::Resolved => {
+                Err(OpsError::Conflict("The report is already resolved".into()))
+            }
+            Some(_) => Err(OpsError::Conflict(
+                "The report was claimed by another administrator".into(),
+            )),
+            None => Err(OpsError::NotFound("Report not found".into())),
+        }
+    }
+
+    /// Resolve a report, recording what was done about it.
+    /// Open reports can be resolved without claiming them first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `report` - The ID of the report.
+    /// * `admin` - The administrator resolving the report.
+    /// * `action` - What was done about the report.
+    ///
+    /// ## Returns
+    ///
+    /// The resolved report.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::NotFound`] - If the report does not exist.
+    /// * [`OpsError::Conflict`] - If the report was claimed by another administrator or is already resolved.
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If the stored report is invalid.
+    #[tracing::instrument(skip_all, fields(report_id = Empty, user_id = Empty))]
+    pub async fn resolve_report(
+        &self,
+        report: impl Into<Snowflake<Report>>,
+        admin: impl Into<Snowflake<User>>,
+        action: ReportAction,
+    ) -> Result<Report, OpsError> {
+        let report_id = record_id("report_id", report);
+        let admin_id = record_id("user_id", admin);
+
+        let mut report = self
+            .fetch_report(report_id)
+            .await?
+            .ok_or_else(|| OpsError::NotFound("Report not found".into()))?;
+
+        match (report.status(), report.claimed_by()) {
+            (ReportStatus::Resolved, _) => return Err(OpsError::Conflict("The report is already resolved".into())),
+            (ReportStatus::Claimed, Some(claimed_by)) if claimed_by != admin_id => {
+                return Err(OpsError::Conflict(
+                    "The report was claimed by another administrator".into(),
+                ));
+            }
+            _ => {}
+        }
+
+        let (previous_status, previous_claimed_by) = (report.status(), report.claimed_by());
+        report.resolve(admin_id, action);
+
+        // Only resolve the report if nobody else claimed or resolved it in the meantime
+        let result = sqlx::query!(
+            "UPDATE reports SET status = $2, claimed_by = $3, action_taken = $4, resolved_at = $5
+            WHERE id = $1 AND status = $6 AND claimed_by IS NOT DISTINCT FROM $7",
+            report_id as Snowflake<Report>,
+            report.status() as i16,
+            admin_id as Snowflake<User>,
+            action as i16,
+            report.resolved_at(),
+            previous_status as i16,
+            previous_claimed_by as Option<Snowflake<User>>,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(OpsError::Conflict(
+                "The report was changed by another administrator".into(),
+            ));
+        }
+
+        Ok(report)
+    }
+}
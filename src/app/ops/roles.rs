@@ -0,0 +1,314 @@
+use tracing::field::Empty;
+
+use super::{Ops, record_id};
+use crate::models::{
+    errors::OpsError,
+    guild::Guild,
+    role::{Permissions, Role, RoleRecord},
+    snowflake::Snowflake,
+    user::User,
+};
+
+/// Operations on the roles of guilds and their assignment to members.
+#[derive(Clone, Copy)]
+pub struct RoleOps<'a> {
+    ops: Ops<'a>,
+}
+
+impl<'a> RoleOps<'a> {
+    /// Create a new [`RoleOps`] sharing the components of the given [`Ops`].
+    pub const fn new(ops: Ops<'a>) -> Self {
+        Self { ops }
+    }
+
+    /// Store a new role.
+    ///
+    /// ## Arguments
+    ///
+    /// * `role` - The role to store.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = %role.guild_id(), role_id = %role.id()))]
+    pub async fn create_role(&self, role: &Role) -> Result<(), OpsError> {
+        sqlx::query!(
+            "INSERT INTO roles (id, guild_id, name, permissions) VALUES ($1, $2, $3, $4)",
+            role.id() as Snowflake<Role>,
+            role.guild_id() as Snowflake<Guild>,
+            role.name(),
+            role.permissions().bits() as i64,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetch a single role of a guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the role belongs to.
+    /// * `role` - The ID of the role.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, role_id = Empty))]
+    pub async fn fetch_role(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        role: impl Into<Snowflake<Role>>,
+    ) -> Result<Option<Role>, OpsError> {
+        let record = sqlx::query_as!(
+            RoleRecord,
+            "SELECT id, guild_id, name, permissions FROM roles WHERE guild_id = $1 AND id = $2",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            record_id("role_id", role) as Snowflake<Role>,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(record.map(Role::from_record))
+    }
+
+    /// Fetch all roles of a guild, the oldest first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to fetch the roles of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn fetch_roles(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Vec<Role>, OpsError> {
+        let records = sqlx::query_as!(
+            RoleRecord,
+            "SELECT id, guild_id, name, permissions FROM roles WHERE guild_id = $1 ORDER BY id",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(records.into_iter().map(Role::from_record).collect())
+    }
+
+    /// Update a role.
+    ///
+    /// ## Arguments
+    ///
+    /// * `role` - The role with the updates applied.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::NotFound`] - If the role does not exist.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = %role.guild_id(), role_id = %role.id()))]
+    pub async fn update_role(&self, role: &Role) -> Result<(), OpsError> {
+        let result = sqlx::query!(
+            "UPDATE roles SET name = $2, permissions = $3 WHERE id = $1",
+            role.id() as Snowflake<Role>,
+            role.name(),
+            role.permissions().bits() as i64,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(OpsError::NotFound("Role does not exist.".into()));
+        }
+        Ok(())
+    }
+
+    /// Delete a role of a guild, unassigning it from all members.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the role belongs to.
+    /// * `role` - The ID of the role.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the role existed.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, role_id = Empty))]
+    pub async fn delete_role(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        role: impl Into<Snowflake<Role>>,
+    ) -> Result<bool, OpsError> {
+        let result = sqlx::query!(
+            "DELETE FROM roles WHERE guild_id = $1 AND id = $2",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            record_id("role_id", role) as Snowflake<Role>,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Fetch the roles assigned to a member, ordered by ID.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild of the member.
+    /// * `user` - The member to fetch the roles of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty, user_id = Empty))]
+    pub async fn fetch_member_role_ids(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Vec<Snowflake<Role>>, OpsError> {
+        let role_ids = sqlx::query_scalar!(
+            "SELECT role_id FROM member_roles WHERE guild_id = $1 AND user_id = $2 ORDER BY role_id",
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            record_id("user_id", user) as Snowflake<User>,
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(role_ids.into_iter().map(Into::into).collect())
+    }
+
+    /// Assign a role to a full member of its guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `role` - The role to assign.
+    /// * `user` - The member to assign the role to.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the role was assigned, `false` if the member already had it or is not a full member.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = %role.guild_id(), role_id = %role.id(), user_id = Empty))]
+    pub async fn add_member_role(&self, role: &Role, user: impl Into<Snowflake<User>>) -> Result<bool, OpsError> {
+        let result = sqlx::query!(
+            "INSERT INTO member_roles (user_id, guild_id, role_id)
+            SELECT user_id, guild_id, $3 FROM members
+            WHERE user_id = $1 AND guild_id = $2 AND guest_channel_id IS NULL
+            ON CONFLICT DO NOTHING",
+            record_id("user_id", user) as Snowflake<User>,
+            role.guild_id() as Snowflake<Guild>,
+            role.id() as Snowflake<Role>,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Unassign a role from a member.
+    ///
+    /// ## Arguments
+    ///
+    /// * `role` - The role to unassign.
+    /// * `user` - The member to unassign the role from.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the member had the role.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = %role.guild_id(), role_id = %role.id(), user_id = Empty))]
+    pub async fn remove_member_role(&self, role: &Role, user: impl Into<Snowflake<User>>) -> Result<bool, OpsError> {
+        let result = sqlx::query!(
+            "DELETE FROM member_roles WHERE user_id = $1 AND guild_id = $2 AND role_id = $3",
+            record_id("user_id", user) as Snowflake<User>,
+            role.guild_id() as Snowflake<Guild>,
+            role.id() as Snowflake<Role>,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Resolve the permissions a user has in a guild, granted by the roles assigned to them.
+    /// The owner of the guild has every permission, users who are not members have none.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to resolve the permissions in.
+    /// * `user` - The user to resolve the permissions of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = %guild.id(), user_id = Empty))]
+    pub async fn fetch_permissions(
+        &self,
+        guild: &Guild,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Permissions, OpsError> {
+        let user_id = record_id("user_id", user);
+        if guild.owner_id() == user_id {
+            return Ok(Permissions::all());
+        }
+
+        let bits = sqlx::query_scalar!(
+            r#"SELECT COALESCE(BIT_OR(r.permissions), 0) AS "permissions!"
+            FROM member_roles mr
+            JOIN roles r ON r.id = mr.role_id
+            WHERE mr.guild_id = $1 AND mr.user_id = $2"#,
+            guild.id() as Snowflake<Guild>,
+            user_id as Snowflake<User>,
+        )
+        .fetch_one(self.ops.db)
+        .await?;
+
+        Ok(Permissions::from_bits_truncate(bits as u64))
+    }
+
+    /// Fetch the users who have a permission in a guild: its owner, and the members whose roles grant it.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to resolve the permission in.
+    /// * `required` - The permission the users must have. [`Permissions::ADMINISTRATOR`] always grants it.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(guild_id = Empty))]
+    pub async fn fetch_users_with_permission(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        required: Permissions,
+    ) -> Result<Vec<Snowflake<User>>, OpsError> {
+        let users = sqlx::query_scalar!(
+            r#"SELECT owner_id AS "user_id!" FROM guilds WHERE id = $1
+            UNION
+            SELECT mr.user_id
+            FROM member_roles mr
+            JOIN roles r ON r.id = mr.role_id
+            WHERE mr.guild_id = $1
+            GROUP BY mr.user_id
+            HAVING BIT_OR(r.permissions) & $2 <> 0"#,
+            record_id("guild_id", guild) as Snowflake<Guild>,
+            required.union(Permissions::ADMINISTRATOR).bits() as i64,
+        )
+        .fetch_all(self.ops.db)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+
+        Ok(users)
+    }
+}
@@ -0,0 +1,234 @@
+use chrono::{DateTime, Utc};
+use tracing::field::Empty;
+
+use super::{Ops, record_id};
+use crate::{
+    gateway::GatewayCloseCode,
+    models::{
+        errors::OpsError,
+        gateway_event::GatewayEvent,
+        snowflake::Snowflake,
+        standing::{Standing, Strike, StrikePenalty, StrikeRecord},
+        user::User,
+    },
+};
+
+/// Operations on the strikes issued against users and the standing of their accounts.
+#[derive(Clone, Copy)]
+pub struct StandingOps<'a> {
+    ops: Ops<'a>,
+}
+
+impl<'a> StandingOps<'a> {
+    /// Create a new [`StandingOps`] sharing the components of the given [`Ops`].
+    pub const fn new(ops: Ops<'a>) -> Self {
+        Self { ops }
+    }
+
+    /// Fetch the standing of a user, derived from their strikes that were neither revoked nor expired.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to fetch the standing of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If a stored strike is invalid.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn fetch_standing(&self, user: impl Into<Snowflake<User>>) -> Result<Standing, OpsError> {
+        let now = Utc::now();
+        let records = sqlx::query_as!(
+            StrikeRecord,
+            "SELECT id, user_id, issuer_id, automated, reason, penalty, penalty_ends_at, expires_at
+            FROM strikes
+            WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > $2
+            ORDER BY id",
+            record_id("user_id", user) as Snowflake<User>,
+            now,
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        let strikes = records.into_iter().map(Strike::from_record).collect::<Result<_, _>>()?;
+        Ok(Standing::from_strikes(strikes, now))
+    }
+
+    /// Issue a strike against a user, escalating its penalty according to
+    /// [`Config::standing_policy`](crate::app::Config::standing_policy).
+    ///
+    /// The user is sent their new standing, and their gateway sessions are closed if they were suspended.
+    ///
+    /// ## Arguments
+    ///
+    /// * `strike` - The strike to issue. Its penalty is set according to the user's active strikes.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::NotFound`] - If the user does not exist or was terminated.
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If a stored strike is invalid.
+    #[tracing::instrument(skip_all, fields(strike_id = %strike.id(), user_id = %strike.user_id()))]
+    pub async fn issue_strike(&self, strike: &mut Strike) -> Result<(), OpsError> {
+        let mut tx = self.ops.db.begin().await?;
+
+        // Lock the user, so that concurrent strikes escalate one after another
+        sqlx::query!(
+            "SELECT id FROM users WHERE id = $1 AND terminated_at IS NULL FOR UPDATE",
+            strike.user_id() as Snowflake<User>,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| OpsError::NotFound("User does not exist or was terminated".into()))?;
+
+        let active = sqlx::query_scalar!(
+            "SELECT COUNT(*) AS \"count!\" FROM strikes
+            WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > $2",
+            strike.user_id() as Snowflake<User>,
+            Utc::now(),
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        strike.escalate(
+            self.ops.config.standing_policy(),
+            usize::try_from(active).unwrap_or(usize::MAX).saturating_add(1),
+        );
+
+        sqlx::query!(
+            "INSERT INTO strikes (id, user_id, issuer_id, automated, reason, penalty, penalty_ends_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            strike.id() as Snowflake<Strike>,
+            strike.user_id() as Snowflake<User>,
+            strike.issuer_id() as Option<Snowflake<User>>,
+            strike.is_automated(),
+            strike.reason(),
+            strike.penalty() as i16,
+            strike.penalty_ends_at(),
+            strike.expires_at(),
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        tracing::info!(penalty = ?strike.penalty(), "Issued strike against user");
+        self.notify(strike.user_id(), strike.penalty() == StrikePenalty::Suspension)
+            .await
+    }
+
+    /// Revoke a strike, lifting its penalty and no longer counting it towards the user's standing.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user the strike was issued against.
+    /// * `strike` - The ID of the strike.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::NotFound`] - If the user has no such strike, or it was already revoked.
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::Build`] - If a stored strike is invalid.
+    #[tracing::instrument(skip_all, fields(user_id = Empty, strike_id = Empty))]
+    pub async fn revoke_strike(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        strike: impl Into<Snowflake<Strike>>,
+    ) -> Result<(), OpsError> {
+        let user_id = record_id("user_id", user);
+
+        let res = sqlx::query!(
+            "UPDATE strikes SET revoked_at = $1 WHERE id = $2 AND user_id = $3 AND revoked_at IS NULL",
+            Utc::now(),
+            record_id("strike_id", strike) as Snowflake<Strike>,
+            user_id as Snowflake<User>,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        if res.rows_affected() == 0 {
+            return Err(OpsError::NotFound(
+                "Strike does not exist or was already revoked".into(),
+            ));
+        }
+
+        self.notify(user_id, false).await
+    }
+
+    /// Fail if a timeout or suspension of at least the given severity is in force for a user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to check.
+    /// * `penalty` - The least severe penalty that restricts the action, a timeout or a suspension.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Forbidden`] - If the user is restricted, with the time the restriction ends at.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn ensure_unrestricted(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        penalty: StrikePenalty,
+    ) -> Result<(), OpsError> {
+        let Some(until) = self.restricted_until(record_id("user_id", user), penalty).await? else {
+            return Ok(());
+        };
+
+        Err(OpsError::Forbidden(format!(
+            "Your account is restricted until {}.",
+            until.to_rfc3339()
+        )))
+    }
+
+    /// When the latest timeout or suspension of at least the given severity in force for a user ends.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to check.
+    /// * `penalty` - The least severe penalty to consider.
+    ///
+    /// ## Returns
+    ///
+    /// When the restriction ends, or `None` if the user is not restricted.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn restricted_until(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        penalty: StrikePenalty,
+    ) -> Result<Option<DateTime<Utc>>, OpsError> {
+        let now = Utc::now();
+        let until = sqlx::query_scalar!(
+            "SELECT MAX(penalty_ends_at) FROM strikes
+            WHERE user_id = $1 AND penalty >= $2 AND penalty_ends_at > $3
+            AND revoked_at IS NULL AND expires_at > $3",
+            record_id("user_id", user) as Snowflake<User>,
+            penalty as i16,
+            now,
+        )
+        .fetch_one(self.ops.db)
+        .await?;
+
+        Ok(until)
+    }
+
+    /// Send a user their current standing, and close their gateway sessions if they were suspended.
+    async fn notify(&self, user: Snowflake<User>, suspended: bool) -> Result<(), OpsError> {
+        let Some(gateway) = self.ops.gateway else {
+            return Ok(());
+        };
+
+        let standing = self.fetch_standing(user).await?.anonymized();
+        gateway.send_to(user, GatewayEvent::StandingUpdate(standing));
+
+        if suspended {
+            gateway.close_all_user_sessions(user, GatewayCloseCode::AccountSuspended, "Account suspended".into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,632 @@
+use sqlx::PgConnection;
+use tracing::field::Empty;
+
+use super::{Ops, record_id, taken_on};
+use crate::{
+    external::auth_provider::ExternalIdentity,
+    gateway::{PresenceAudience, SendMode},
+    models::{
+        avatar::AvatarLike,
+        errors::OpsError,
+        gateway_event::GatewayEvent,
+        omittableoption::OmittableOption,
+        prefs::PresenceSharing,
+        registration_code::{RegistrationCode, RegistrationCodeRecord},
+        relationship::RelationshipType,
+        request_payloads::{CreateUser, UpdateUser},
+        snowflake::Snowflake,
+        user::{Presence, User, UserRecord, is_valid_display_name, normalize_username},
+    },
+};
+
+/// Operations on users and their accounts.
+#[derive(Clone, Copy)]
+pub struct UserOps<'a> {
+    ops: Ops<'a>,
+}
+
+impl<'a> UserOps<'a> {
+    /// Create a new [`UserOps`] sharing the components of the given [`Ops`].
+    pub const fn new(ops: Ops<'a>) -> Self {
+        Self { ops }
+    }
+
+    /// Retrieve a user from the database by their ID.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user to retrieve.
+    ///
+    /// ## Returns
+    ///
+    /// The user if found, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn fetch_user(&self, user: impl Into<Snowflake<User>>) -> Result<Option<User>, OpsError> {
+        let row = sqlx::query_as!(
+            UserRecord,
+            "SELECT id, username, display_name, avatar_hash, banner_hash, last_presence
+            FROM users
+            WHERE id = $1",
+            record_id("user_id", user) as Snowflake<User>
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(row.map(User::from_record))
+    }
+
+    /// Fetch the presence of a user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user to retrieve the presence of.
+    ///
+    /// ## Returns
+    ///
+    /// The presence of the user if found, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn fetch_presence(&self, user: impl Into<Snowflake<User>>) -> Result<Option<Presence>, OpsError> {
+        let row = sqlx::query!(
+            "SELECT last_presence
+            FROM users
+            WHERE id = $1",
+            record_id("user_id", user) as Snowflake<User>
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(row.map(|r| Presence::from(r.last_presence)))
+    }
+
+    /// Fetch the users a user shares their presence with, according to their preferences.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user to retrieve the presence audience of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn fetch_presence_audience(
+        &self,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<PresenceAudience, OpsError> {
+        let row = sqlx::query!(
+            r#"SELECT
+                COALESCE((SELECT presence_sharing FROM prefs WHERE user_id = $1), 0::SMALLINT) AS "presence_sharing!",
+                ARRAY(
+                    SELECT other_id FROM relationships WHERE user_id = $1 AND relationship_type = $2
+                ) AS "friends!""#,
+            record_id("user_id", user) as Snowflake<User>,
+            RelationshipType::Friend as i16,
+        )
+        .fetch_one(self.ops.db)
+        .await?;
+
+        Ok(PresenceAudience::new(
+            PresenceSharing::from(row.presence_sharing),
+            row.friends.into_iter().map(Snowflake::from),
+        ))
+    }
+
+    /// Send the users a user shares their presence with to the gateway, after their preferences or friends changed.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user whose presence audience changed.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    pub async fn refresh_presence_audience(&self, user: impl Into<Snowflake<User>>) -> Result<(), OpsError> {
+        let Some(gateway) = self.ops.gateway else {
+            return Ok(());
+        };
+        let user_id = user.into();
+
+        if gateway.is_connected(user_id).await {
+            gateway.set_presence_audience(user_id, self.fetch_presence_audience(user_id).await?);
+        }
+        Ok(())
+    }
+
+    /// Dispatch a `PRESENCE_UPDATE` for a user to everyone they share their presence with.
+    ///
+    /// Users who do not share their presence with everyone may no longer be connected when they go offline,
+    /// so the event is sent to each user in their audience instead of relying on the gateway to filter it.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user whose presence changed.
+    /// * `presence` - The presence to dispatch.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    pub async fn dispatch_presence(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        presence: Presence,
+    ) -> Result<(), OpsError> {
+        let Some(gateway) = self.ops.gateway else {
+            return Ok(());
+        };
+        let user_id = user.into();
+        let event = || GatewayEvent::PresenceUpdate { user_id, presence };
+
+        match self.fetch_presence_audience(user_id).await? {
+            PresenceAudience::Everyone => gateway.dispatch(event(), SendMode::ToMutualGuilds(user_id)),
+            PresenceAudience::Only(viewers) => {
+                for viewer in viewers.into_iter().chain([user_id]) {
+                    gateway.send_to(viewer, event());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Retrieve a user from the database by their username, regardless of case.
+    ///
+    /// ## Arguments
+    ///
+    /// * `username` - The username of the user to retrieve.
+    ///
+    /// ## Returns
+    ///
+    /// The user if found, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn fetch_user_by_username(&self, username: &str) -> Result<Option<User>, OpsError> {
+        let row = sqlx::query_as!(
+            UserRecord,
+            "SELECT id, username, display_name, avatar_hash, banner_hash, last_presence
+            FROM users
+            WHERE lower(username) = $1
+            LIMIT 1",
+            normalize_username(username)
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(row.map(User::from_record))
+    }
+
+    /// Check if a username is taken, regardless of case.
+    ///
+    /// ## Arguments
+    ///
+    /// * `username` - The username to check.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the username is taken, otherwise `false`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn is_username_taken(&self, username: &str) -> Result<bool, OpsError> {
+        let res = sqlx::query!(
+            "SELECT EXISTS(SELECT 1 FROM users WHERE lower(username) = $1)",
+            normalize_username(username)
+        )
+        .fetch_one(self.ops.db)
+        .await?;
+
+        Ok(res.exists.unwrap_or(false))
+    }
+
+    /// Create a new user in the database.
+    ///
+    /// If the payload includes a registration code, one of its uses is consumed along with creating the user.
+    /// Instances requiring registration codes reject payloads without one.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Forbidden`] - If a registration code is required but missing,
+    ///   or the code is invalid, was revoked or has no uses left.
+    /// * [`OpsError::AlreadyTaken`] - If the username is already taken.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn create_user(&self, payload: CreateUser) -> Result<User, OpsError> {
+        let user = User::from_payload(self.ops.config, &payload)?;
+        let mut tx = self.ops.db.begin().await?;
+
+        self.redeem_registration_code(&mut tx, payload.registration_code.as_deref())
+            .await?;
+
+        sqlx::query!(
+            "INSERT INTO users (id, username)
+            VALUES ($1, $2)",
+            user.id() as Snowflake<User>,
+            user.username(),
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(taken_on("users_username_lower_key", "username"))?;
+
+        tx.commit().await?;
+
+        Ok(user)
+    }
+
+    /// Consume one use of the registration code a new account is created with.
+    /// Instances requiring registration codes reject accounts created without one.
+    ///
+    /// ## Arguments
+    ///
+    /// * `conn` - The transaction the account is created in, so that the use is only consumed if it is.
+    /// * `code` - The registration code the account is created with, if any.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Forbidden`] - If a registration code is required but missing,
+    ///   or the code is invalid, was revoked or has no uses left.
+    /// * [`OpsError::Db`] - If the database query fails.
+    async fn redeem_registration_code(&self, conn: &mut PgConnection, code: Option<&str>) -> Result<(), OpsError> {
+        let Some(code) = code else {
+            if self.ops.config.registration_code_required() {
+                return Err(OpsError::Forbidden(
+                    "A registration code is required to register on this instance".into(),
+                ));
+            }
+            return Ok(());
+        };
+
+        let redeemed = sqlx::query_scalar!(
+            "UPDATE registration_codes SET uses = uses + 1
+            WHERE code = $1 AND (max_uses IS NULL OR uses < max_uses)
+            RETURNING code",
+            code,
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        if redeemed.is_none() {
+            return Err(match self.fetch_registration_code(code).await? {
+                Some(_) => OpsError::Forbidden("Registration code has no uses left".into()),
+                None => OpsError::Forbidden("Registration code is invalid or was revoked".into()),
+            });
+        }
+        Ok(())
+    }
+
+    /// Store a newly created registration code.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn create_registration_code(&self, code: &RegistrationCode) -> Result<(), OpsError> {
+        sqlx::query!(
+            "INSERT INTO registration_codes (code, creator_id, max_uses, uses, created_at)
+            VALUES ($1, $2, $3, $4, $5)",
+            code.code(),
+            code.creator_id() as Option<Snowflake<User>>,
+            code.max_uses().map(|m| i32::try_from(m).unwrap_or(i32::MAX)),
+            i32::try_from(code.uses()).unwrap_or(i32::MAX),
+            code.created_at(),
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetch a registration code, including codes with no uses left.
+    ///
+    /// ## Returns
+    ///
+    /// The registration code if it exists and was not revoked, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn fetch_registration_code(&self, code: &str) -> Result<Option<RegistrationCode>, OpsError> {
+        let record = sqlx::query_as!(
+            RegistrationCodeRecord,
+            "SELECT * FROM registration_codes WHERE code = $1",
+            code
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        Ok(record.map(RegistrationCode::from_record))
+    }
+
+    /// Fetch all registration codes that were not revoked, newest first.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn fetch_registration_codes(&self) -> Result<Vec<RegistrationCode>, OpsError> {
+        let records = sqlx::query_as!(
+            RegistrationCodeRecord,
+            "SELECT * FROM registration_codes ORDER BY created_at DESC, code"
+        )
+        .fetch_all(self.ops.db)
+        .await?;
+
+        Ok(records.into_iter().map(RegistrationCode::from_record).collect())
+    }
+
+    /// Revoke a registration code. Accounts already registered with it are not affected.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the code existed, otherwise `false`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all)]
+    pub async fn delete_registration_code(&self, code: &str) -> Result<bool, OpsError> {
+        let res = sqlx::query!("DELETE FROM registration_codes WHERE code = $1", code)
+            .execute(self.ops.db)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Log in a user through an external authentication provider.
+    ///
+    /// Returning users have their display name updated if it changed at the provider.
+    /// Users logging in for the first time have no account yet, see [`UserOps::provision_external`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `provider` - The name of the provider that validated the identity.
+    /// * `identity` - The identity asserted by the provider.
+    ///
+    /// ## Returns
+    ///
+    /// The user the identity belongs to and whether the user was updated, or `None` if the identity has no account yet.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Forbidden`] - If the account the identity belongs to was terminated.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(provider))]
+    pub async fn login_external(
+        &self,
+        provider: &str,
+        identity: &ExternalIdentity,
+    ) -> Result<Option<(User, bool)>, OpsError> {
+        let existing = sqlx::query!(
+            "SELECT u.id, u.username, u.display_name, u.avatar_hash, u.banner_hash, u.last_presence,
+            u.terminated_at IS NOT NULL AS \"terminated!\"
+            FROM external_identities e
+            JOIN users u ON u.id = e.user_id
+            WHERE e.provider = $1 AND e.subject = $2",
+            provider,
+            identity.subject,
+        )
+        .fetch_optional(self.ops.db)
+        .await?;
+
+        let Some(record) = existing else {
+            return Ok(None);
+        };
+        if record.terminated {
+            return Err(OpsError::Forbidden("Account has been terminated".into()));
+        }
+        let mut user = User::from_record(UserRecord {
+            id: record.id.into(),
+            username: record.username,
+            display_name: record.display_name,
+            avatar_hash: record.avatar_hash,
+            banner_hash: record.banner_hash,
+            last_presence: record.last_presence,
+        });
+        let Some(display_name) = identity
+            .display_name
+            .as_deref()
+            .filter(|name| is_valid_display_name(name) && user.display_name() != Some(name))
+        else {
+            return Ok(Some((user, false)));
+        };
+
+        sqlx::query!(
+            "UPDATE users SET display_name = $1 WHERE id = $2",
+            display_name,
+            user.id() as Snowflake<User>,
+        )
+        .execute(self.ops.db)
+        .await?;
+
+        user.update(UpdateUser {
+            username: None,
+            display_name: OmittableOption::Some(display_name.to_string()),
+            avatar: OmittableOption::Omitted,
+            banner: OmittableOption::Omitted,
+        })?;
+        Ok(Some((user, true)))
+    }
+
+    /// Create the account of a user logging in through an external authentication provider for the first time.
+    ///
+    /// The account takes over the username suggested by the provider if it is valid and available.
+    /// Like accounts created with a password, it requires a registration code if the instance requires them.
+    ///
+    /// ## Arguments
+    ///
+    /// * `provider` - The name of the provider that validated the identity.
+    /// * `identity` - The identity asserted by the provider, which has no account yet.
+    /// * `registration_code` - The registration code to create the account with, if any.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Forbidden`] - If the account would be created with a disposable email address,
+    ///   or a registration code is required but missing, or the code is invalid, was revoked or has no uses left.
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(provider))]
+    pub async fn provision_external(
+        &self,
+        provider: &str,
+        identity: &ExternalIdentity,
+        registration_code: Option<&str>,
+    ) -> Result<User, OpsError> {
+        if identity
+            .email
+            .as_deref()
+            .is_some_and(|email| self.ops.config.is_disposable_email(email))
+        {
+            return Err(OpsError::Forbidden(
+                "Accounts cannot be signed up with disposable email addresses".into(),
+            ));
+        }
+
+        let mut user = User::from_external_identity(self.ops.config, identity);
+        let mut tx = self.ops.db.begin().await?;
+        self.redeem_registration_code(&mut tx, registration_code).await?;
+
+        // The IDs are new, so only the username can conflict
+        loop {
+            let inserted = sqlx::query!(
+                "INSERT INTO users (id, username, display_name)
+                VALUES ($1, $2, $3)
+                ON CONFLICT DO NOTHING",
+                user.id() as Snowflake<User>,
+                user.username(),
+                user.display_name(),
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            if inserted.rows_affected() > 0 {
+                break;
+            }
+
+            let fallback = User::fallback_username(user.id());
+            if user.username() == fallback {
+                return Err(OpsError::AlreadyTaken { field: "username" });
+            }
+            user.set_username(&fallback)?;
+        }
+
+        sqlx::query!(
+            "INSERT INTO external_identities (provider, subject, user_id)
+            VALUES ($1, $2, $3)",
+            provider,
+            identity.subject,
+            user.id() as Snowflake<User>,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(user)
+    }
+
+    /// Check if a user logs in through an external authentication provider.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user to check.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn has_external_identity(&self, user: impl Into<Snowflake<User>>) -> Result<bool, OpsError> {
+        let res = sqlx::query!(
+            "SELECT EXISTS(
+                SELECT 1 FROM external_identities e JOIN users u ON u.id = e.user_id
+                WHERE e.user_id = $1 AND u.terminated_at IS NULL
+            )",
+            record_id("user_id", user) as Snowflake<User>
+        )
+        .fetch_one(self.ops.db)
+        .await?;
+
+        Ok(res.exists.unwrap_or(false))
+    }
+
+    /// Apply an update payload to the user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to update.
+    /// * `payload` - The update payload.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Db`] - If the database query fails.
+    /// * [`OpsError::NotFound`] - If the user does not exist.
+    /// * [`OpsError::AlreadyTaken`] - If the new username is already taken.
+    /// * [`OpsError::Build`] - If the avatar or banner is partial.
+    /// * [`OpsError::PayloadTooLarge`] - If the avatar or banner is too large.
+    /// * [`OpsError::S3`] - If deleting a removed avatar or banner fails.
+    ///
+    /// ## Returns
+    ///
+    /// The user if the commit was successful. A new avatar or banner is uploaded in the background,
+    /// and only available once a follow-up `USER_UPDATE` was dispatched.
+    #[tracing::instrument(skip_all, fields(user_id = Empty))]
+    pub async fn update_user(&self, user: impl Into<Snowflake<User>>, payload: UpdateUser) -> Result<User, OpsError> {
+        let user_id = record_id("user_id", user);
+
+        let old_user = self
+            .fetch_user(user_id)
+            .await?
+            .ok_or(OpsError::NotFound("User not found".into()))?;
+
+        let mut user = old_user.clone();
+        let needs_s3_update = user.update(payload)?;
+
+        if old_user == user {
+            return Ok(user);
+        }
+
+        let mut tx = self.ops.db.begin().await?;
+
+        if needs_s3_update {
+            Ops::check_avatar_size("Avatar", user.avatar())?;
+            Ops::check_avatar_size("Banner", user.banner())?;
+
+            self.ops
+                .replace_avatar(&mut *tx, user.avatar(), old_user.avatar())
+                .await?;
+            self.ops
+                .replace_avatar(&mut *tx, user.banner(), old_user.banner())
+                .await?;
+        }
+
+        let record = sqlx::query_as!(
+            UserRecord,
+            "UPDATE users SET username = $2, display_name = $3, last_presence = $4, avatar_hash = $5, banner_hash = $6
+            WHERE id = $1 RETURNING id, username, display_name, avatar_hash, banner_hash, last_presence",
+            user_id as Snowflake<User>,
+            user.username(),
+            user.display_name(),
+            *user.last_presence() as i16,
+            user.avatar().map(AvatarLike::avatar_hash),
+            user.banner().map(AvatarLike::avatar_hash),
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(taken_on("users_username_lower_key", "username"))?;
+
+        tx.commit().await?;
+
+        if needs_s3_update {
+            self.ops.avatars().wake();
+        }
+        Ok(User::from_record(record))
+    }
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tracing::Instrument;
+
+use super::{App, ops::OUTBOX_BATCH_SIZE};
+
+/// The time after which the outbox is checked for entries, even if the relay was not woken.
+///
+/// This picks up entries committed right before the application stopped, and entries that failed to be emitted.
+pub const OUTBOX_SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Wakes the outbox relay whenever new entries were committed to the transactional outbox,
+/// so that they do not have to wait for the next sweep.
+#[derive(Debug, Default)]
+pub struct OutboxRelay {
+    notify: Notify,
+}
+
+impl OutboxRelay {
+    /// Create a new relay signal.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wake the relay, emitting all committed entries.
+    ///
+    /// If the relay is busy, it runs again once it finished, as the wakeup is stored until then.
+    pub fn wake(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Wait until the relay is woken, or the sweep interval has elapsed.
+    async fn woken(&self) {
+        let _ = tokio::time::timeout(OUTBOX_SWEEP_INTERVAL, self.notify.notified()).await;
+    }
+}
+
+/// Spawn the task emitting entries of the transactional outbox for the lifetime of the application.
+///
+/// The outbox is drained on startup, whenever the relay is woken, and every [`OUTBOX_SWEEP_INTERVAL`].
+/// Entries are left in the outbox while the instance is read-only.
+pub fn spawn_relay(app: &App) {
+    let app = app.clone();
+
+    tokio::spawn(async move {
+        loop {
+            if app.read_only().is_enabled() {
+                app.outbox_relay().woken().await;
+                continue;
+            }
+
+            match app
+                .ops()
+                .outbox()
+                .relay()
+                .instrument(tracing::debug_span!("outbox_relay"))
+                .await
+            {
+                // A full batch means more entries may be waiting
+                Ok(count) if count >= OUTBOX_BATCH_SIZE as u64 => continue,
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!("Failed to relay outbox entries: {}", e);
+                }
+            }
+
+            app.outbox_relay().woken().await;
+        }
+    });
+}
@@ -0,0 +1,107 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::appstate::{HeavyQuery, QueryLimits};
+use crate::models::errors::OpsError;
+
+/// How long clients are told to wait for before retrying a heavy query that was rejected.
+pub const HEAVY_QUERY_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Limits how many heavy queries of each kind run at once on this instance.
+///
+/// Without it, a burst of expensive requests could take up every connection of the database pool,
+/// starving cheap but important requests such as message sends.
+#[derive(Debug)]
+pub struct QueryLimiter {
+    semaphores: HashMap<HeavyQuery, Arc<Semaphore>>,
+}
+
+impl QueryLimiter {
+    /// Create a new limiter.
+    ///
+    /// ## Arguments
+    ///
+    /// * `limits` - The limits of the queries, of which only the concurrency is enforced here.
+    pub fn new(limits: &QueryLimits) -> Self {
+        Self {
+            semaphores: HeavyQuery::ALL
+                .into_iter()
+                .map(|query| (query, Arc::new(Semaphore::new(limits.get(query).concurrency()))))
+                .collect(),
+        }
+    }
+
+    /// Take a slot to run a query in, rejecting the query if all slots are taken.
+    ///
+    /// ## Returns
+    ///
+    /// A permit holding the slot until it is dropped.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OpsError::Unavailable`] - If the maximum number of these queries are already running.
+    pub fn try_acquire(&self, query: HeavyQuery) -> Result<OwnedSemaphorePermit, OpsError> {
+        self.semaphores[&query]
+            .clone()
+            .try_acquire_owned()
+            .map_err(|_| OpsError::Unavailable {
+                reason: "Too many expensive requests are being served, try again later.".into(),
+                retry_after: HEAVY_QUERY_RETRY_AFTER,
+            })
+    }
+
+    /// Wait for a slot to run a query in.
+    ///
+    /// ## Returns
+    ///
+    /// A permit holding the slot until it is dropped.
+    pub async fn acquire(&self, query: HeavyQuery) -> OwnedSemaphorePermit {
+        self.semaphores[&query]
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Query semaphores are never closed")
+    }
+
+    /// The number of slots that are currently free for the given query.
+    pub fn available(&self, query: HeavyQuery) -> usize {
+        self.semaphores[&query].available_permits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::appstate::QueryLimit;
+
+    #[tokio::test]
+    async fn test_query_limiter() {
+        let limits = QueryLimits::new(HashMap::from([(
+            HeavyQuery::MessageExport,
+            QueryLimit::new(Duration::from_secs(1), 2),
+        )]));
+        let limiter = QueryLimiter::new(&limits);
+
+        let first = limiter
+            .try_acquire(HeavyQuery::MessageExport)
+            .expect("a slot should be free");
+        let _second = limiter
+            .try_acquire(HeavyQuery::MessageExport)
+            .expect("a slot should be free");
+        assert_eq!(limiter.available(HeavyQuery::MessageExport), 0);
+        assert!(matches!(
+            limiter.try_acquire(HeavyQuery::MessageExport),
+            Err(OpsError::Unavailable { retry_after, .. }) if retry_after == HEAVY_QUERY_RETRY_AFTER
+        ));
+
+        // Other queries have their own slots
+        let default = QueryLimits::default().get(HeavyQuery::MessagesAround).concurrency();
+        assert_eq!(limiter.available(HeavyQuery::MessagesAround), default);
+
+        // Slots are freed once their permit is dropped
+        drop(first);
+        let _third = limiter.acquire(HeavyQuery::MessageExport).await;
+        assert_eq!(limiter.available(HeavyQuery::MessageExport), 0);
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::RwLock;
+
+use serde::Serialize;
+
+/// Why and since when the instance has been read-only.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReadOnlyState {
+    /// Why the instance is read-only, shown to clients whose requests are rejected.
+    reason: Option<String>,
+    /// When the instance became read-only, as a UNIX timestamp in milliseconds.
+    since: i64,
+}
+
+impl ReadOnlyState {
+    /// Why the instance is read-only, if a reason was given.
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    /// When the instance became read-only, as a UNIX timestamp in milliseconds.
+    pub const fn since(&self) -> i64 {
+        self.since
+    }
+}
+
+/// Whether the instance only serves requests that do not modify any data.
+///
+/// Operators enable this while the primary database is unavailable for writes, such as during a failover or a restore,
+/// so that users can keep reading instead of the whole service being taken down.
+/// The mode is local to each instance.
+#[derive(Debug, Default)]
+pub struct ReadOnlyMode {
+    state: RwLock<Option<ReadOnlyState>>,
+}
+
+impl ReadOnlyMode {
+    /// Create a new read-only mode.
+    ///
+    /// ## Arguments
+    ///
+    /// * `enabled` - Whether the instance starts out read-only.
+    pub fn new(enabled: bool) -> Self {
+        let mode = Self::default();
+        if enabled {
+            mode.enable(None);
+        }
+        mode
+    }
+
+    /// The current state of the mode, or `None` if the instance is writable.
+    pub fn get(&self) -> Option<ReadOnlyState> {
+        self.state
+            .read()
+            .expect("Read-only state should not be poisoned")
+            .clone()
+    }
+
+    /// Whether the instance is currently read-only.
+    pub fn is_enabled(&self) -> bool {
+        self.state
+            .read()
+            .expect("Read-only state should not be poisoned")
+            .is_some()
+    }
+
+    /// Make the instance read-only. If it already is, only the reason is updated.
+    ///
+    /// ## Arguments
+    ///
+    /// * `reason` - Why the instance is read-only, shown to clients.
+    ///
+    /// ## Returns
+    ///
+    /// The new state of the mode.
+    pub fn enable(&self, reason: Option<String>) -> ReadOnlyState {
+        let mut state = self.state.write().expect("Read-only state should not be poisoned");
+        let since = state
+            .as_ref()
+            .map_or_else(|| chrono::Utc::now().timestamp_millis(), ReadOnlyState::since);
+
+        state.insert(ReadOnlyState { reason, since }).clone()
+    }
+
+    /// Make the instance writable again.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the instance was read-only before.
+    pub fn disable(&self) -> bool {
+        self.state
+            .write()
+            .expect("Read-only state should not be poisoned")
+            .take()
+            .is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_only_mode() {
+        let mode = ReadOnlyMode::new(false);
+        assert!(!mode.is_enabled());
+        assert!(!mode.disable());
+
+        let state = mode.enable(Some("Database failover".into()));
+        assert!(mode.is_enabled());
+        assert_eq!(mode.get(), Some(state.clone()));
+
+        // Updating the reason keeps the time the instance became read-only
+        let updated = mode.enable(None);
+        assert_eq!(updated.since(), state.since());
+        assert_eq!(updated.reason(), None);
+
+        assert!(mode.disable());
+        assert!(mode.get().is_none());
+        assert!(ReadOnlyMode::new(true).is_enabled());
+    }
+}
@@ -0,0 +1,94 @@
+use std::{fmt::Display, time::Duration};
+
+use tokio::time::MissedTickBehavior;
+use tracing::Instrument;
+
+use super::App;
+
+/// Spawn a job that runs periodically in the background for the lifetime of the application.
+///
+/// The first run happens immediately. If a run takes longer than the interval,
+/// the next one is delayed instead of being run back-to-back. Runs are skipped while the instance is read-only.
+///
+/// ## Arguments
+///
+/// * `app` - The application state, passed to every run of the job.
+/// * `name` - A human-readable name of the job, used for logging.
+/// * `period` - The time between two runs of the job.
+/// * `job` - The job to run, returning the number of items it processed.
+pub fn spawn_periodic<F, Fut, E>(app: &App, name: &'static str, period: Duration, job: F)
+where
+    F: Fn(App) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<u64, E>> + Send,
+    E: Display,
+{
+    spawn(app, name, period, false, job);
+}
+
+/// Spawn a job that runs periodically on a single instance, for jobs that work on data shared by all instances.
+///
+/// Behaves like [`spawn_periodic`], except that runs are skipped unless this instance was elected to run the job,
+/// see [`Database::try_lead_job`](crate::external::Database::try_lead_job).
+/// If the instance running the job dies, another instance takes over at its next run.
+///
+/// ## Arguments
+///
+/// * `app` - The application state, passed to every run of the job.
+/// * `name` - The name of the job, used for logging. It must be the same on all instances.
+/// * `period` - The time between two runs of the job.
+/// * `job` - The job to run, returning the number of items it processed.
+pub fn spawn_exclusive<F, Fut, E>(app: &App, name: &'static str, period: Duration, job: F)
+where
+    F: Fn(App) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<u64, E>> + Send,
+    E: Display,
+{
+    spawn(app, name, period, true, job);
+}
+
+fn spawn<F, Fut, E>(app: &App, name: &'static str, period: Duration, exclusive: bool, job: F)
+where
+    F: Fn(App) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<u64, E>> + Send,
+    E: Display,
+{
+    let app = app.clone();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            if app.read_only().is_enabled() {
+                tracing::info!(job = name, "Skipping scheduled job, the instance is read-only.");
+                continue;
+            }
+            if exclusive {
+                match app.db().try_lead_job(name).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        tracing::debug!(job = name, "Skipping scheduled job, another instance runs it.");
+                        continue;
+                    }
+                    Err(e) => {
+                        tracing::error!(job = name, "Failed to check which instance runs scheduled job: {}", e);
+                        continue;
+                    }
+                }
+            }
+            tracing::info!(job = name, "Running scheduled job...");
+            match job(app.clone())
+                .instrument(tracing::info_span!("job", job = name))
+                .await
+            {
+                Ok(count) => {
+                    tracing::info!(job = name, processed = count, "Scheduled job finished.");
+                }
+                Err(e) => {
+                    tracing::error!(job = name, "Scheduled job failed: {}", e);
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,110 @@
+use std::fmt;
+
+/// The outcome of a single startup check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckOutcome {
+    /// The check succeeded.
+    Passed(String),
+    /// The check was not performed, because the component is not configured or depends on a failed check.
+    Skipped(String),
+    /// The check failed, and the application cannot start.
+    Failed(String),
+}
+
+/// A summary of the checks performed while starting the application.
+///
+/// All checks are collected before the application gives up,
+/// so that every problem is reported at once instead of one per restart.
+#[derive(Debug, Clone, Default)]
+pub struct StartupReport {
+    checks: Vec<(&'static str, CheckOutcome)>,
+}
+
+impl StartupReport {
+    /// Create a new empty report.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a successful check.
+    pub fn pass(&mut self, check: &'static str, detail: impl Into<String>) {
+        self.checks.push((check, CheckOutcome::Passed(detail.into())));
+    }
+
+    /// Record a check that was not performed.
+    pub fn skip(&mut self, check: &'static str, reason: impl Into<String>) {
+        self.checks.push((check, CheckOutcome::Skipped(reason.into())));
+    }
+
+    /// Record a failed check.
+    pub fn fail(&mut self, check: &'static str, error: impl fmt::Display) {
+        self.checks.push((check, CheckOutcome::Failed(error.to_string())));
+    }
+
+    /// All checks performed so far, in order.
+    pub fn checks(&self) -> &[(&'static str, CheckOutcome)] {
+        &self.checks
+    }
+
+    /// Returns whether the given check failed.
+    pub fn has_failed(&self, check: &str) -> bool {
+        self.checks
+            .iter()
+            .any(|(name, outcome)| *name == check && matches!(outcome, CheckOutcome::Failed(_)))
+    }
+
+    /// Returns whether none of the checks failed.
+    pub fn is_ok(&self) -> bool {
+        !self
+            .checks
+            .iter()
+            .any(|(_, outcome)| matches!(outcome, CheckOutcome::Failed(_)))
+    }
+
+    /// Log the report as a single summary.
+    pub fn log(&self) {
+        if self.is_ok() {
+            tracing::info!("Startup self-check passed:\n{self}");
+        } else {
+            tracing::error!("Startup self-check failed:\n{self}");
+        }
+    }
+}
+
+impl fmt::Display for StartupReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (check, outcome)) in self.checks.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            match outcome {
+                CheckOutcome::Passed(detail) => write!(f, "  [ OK ] {check}: {detail}")?,
+                CheckOutcome::Skipped(reason) => write!(f, "  [SKIP] {check}: {reason}")?,
+                CheckOutcome::Failed(error) => write!(f, "  [FAIL] {check}: {error}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut report = StartupReport::new();
+        report.pass("database", "connected");
+        report.skip("s3", "not configured");
+        assert!(report.is_ok());
+
+        report.fail("snowflake epoch", "epoch changed");
+        assert!(!report.is_ok());
+        assert!(report.has_failed("snowflake epoch"));
+        assert!(!report.has_failed("database"));
+        assert_eq!(
+            report.to_string(),
+            "  [ OK ] database: connected\n  [SKIP] s3: not configured\n  [FAIL] snowflake epoch: epoch changed"
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    sync::{
+        OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{body::Body, extract::Request};
+use opentelemetry::{global, trace::TracerProvider as _};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{Resource, propagation::TraceContextPropagator, trace::SdkTracerProvider};
+use thiserror::Error;
+use tracing::{Span, level_filters::LevelFilter};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    EnvFilter, Registry,
+    filter::ParseError,
+    layer::SubscriberExt,
+    reload::{self, Handle},
+    util::SubscriberInitExt,
+};
+
+use super::Config;
+
+#[cfg(debug_assertions)]
+const LEVEL: LevelFilter = LevelFilter::DEBUG;
+#[cfg(not(debug_assertions))]
+const LEVEL: LevelFilter = LevelFilter::INFO;
+
+/// The filter of the global subscriber, set once it is installed
+static LOG_FILTER: OnceLock<LogFilter> = OnceLock::new();
+
+/// An error that occurred while changing the log filter.
+#[derive(Debug, Error)]
+pub enum LogFilterError {
+    #[error("Invalid filter directives: {0}")]
+    Invalid(#[from] ParseError),
+    #[error("Failed to reload filter: {0}")]
+    Reload(#[from] reload::Error),
+}
+
+/// The filter deciding which spans and events are recorded, changeable at runtime.
+///
+/// Filters are made up of comma-separated directives in the format used by `RUST_LOG`,
+/// for example `info,chat_backend::gateway::actor=debug`.
+#[derive(Debug)]
+pub struct LogFilter {
+    handle: Handle<EnvFilter, Registry>,
+    /// The directives the filter starts with, and is reset to
+    default: String,
+    /// Incremented on every change, so that a scheduled reset does not undo a newer change
+    generation: AtomicU64,
+}
+
+impl LogFilter {
+    /// Create a new filter, returning it along with the layer it controls.
+    ///
+    /// ## Arguments
+    ///
+    /// * `default` - The directives the filter starts with
+    ///
+    /// ## Errors
+    ///
+    /// * [`ParseError`] - If the directives are invalid.
+    pub fn new(default: impl Into<String>) -> Result<(Self, reload::Layer<EnvFilter, Registry>), ParseError> {
+        let default = default.into();
+        let (layer, handle) = reload::Layer::new(EnvFilter::try_new(&default)?);
+
+        Ok((
+            Self {
+                handle,
+                default,
+                generation: AtomicU64::new(0),
+            },
+            layer,
+        ))
+    }
+
+    /// The directives currently in effect.
+    pub fn current(&self) -> String {
+        self.handle
+            .with_current(ToString::to_string)
+            .unwrap_or_else(|_| self.default.clone())
+    }
+
+    /// The directives the filter started with.
+    pub fn default_directives(&self) -> &str {
+        &self.default
+    }
+
+    /// Replace the directives of the filter.
+    ///
+    /// ## Arguments
+    ///
+    /// * `directives` - The new directives
+    /// * `reset_after` - If provided, the filter is reset to its default after this long,
+    ///   unless it was changed again in the meantime. Must be called within a Tokio runtime if set.
+    ///
+    /// ## Errors
+    ///
+    /// * [`LogFilterError::Invalid`] - If the directives are invalid, the current filter is kept.
+    /// * [`LogFilterError::Reload`] - If the subscriber the filter belongs to no longer exists.
+    pub fn set(&'static self, directives: &str, reset_after: Option<Duration>) -> Result<(), LogFilterError> {
+        let filter = EnvFilter::try_new(directives)?;
+        self.handle.reload(filter)?;
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+
+        if let Some(reset_after) = reset_after {
+            tokio::spawn(async move {
+                tokio::time::sleep(reset_after).await;
+                if self.generation.load(Ordering::SeqCst) == generation {
+                    tracing::info!(directives = self.default, "Log filter change expired, resetting");
+                    if let Err(e) = self.reset() {
+                        tracing::error!(error = %e, "Failed to reset log filter");
+                    }
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Reset the filter to the directives it started with.
+    ///
+    /// ## Errors
+    ///
+    /// * [`LogFilterError`] - If the filter could not be reloaded.
+    pub fn reset(&self) -> Result<(), LogFilterError> {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.handle.reload(EnvFilter::try_new(&self.default)?)?;
+        Ok(())
+    }
+}
+
+/// The filter of the global subscriber, if [`init`] installed it.
+pub fn log_filter() -> Option<&'static LogFilter> {
+    LOG_FILTER.get()
+}
+
+/// Keeps trace export running, and flushes the remaining spans when shut down.
+#[must_use = "Traces are only exported until the telemetry guard is shut down"]
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+    /// Flush all pending spans and stop exporting traces.
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider
+            && let Err(e) = provider.shutdown()
+        {
+            tracing::warn!(error = %e, "Failed to flush pending traces");
+        }
+    }
+}
+
+/// Install the global tracing subscriber.
+///
+/// Events are always logged to stdout. If an OTLP endpoint is configured,
+/// spans are additionally exported to it, and incoming W3C trace context is honored.
+/// The filter applied to both can be changed at runtime through [`log_filter`].
+///
+/// ## Arguments
+///
+/// * `config` - The application configuration
+///
+/// ## Returns
+///
+/// A guard that must be shut down on exit to flush pending spans.
+///
+/// ## Errors
+///
+/// * [`ExporterBuildError`] - If the OTLP exporter could not be created.
+///
+/// ## Panics
+///
+/// Panics if a global subscriber was already installed.
+pub fn init(config: &Config) -> Result<Telemetry, ExporterBuildError> {
+    let provider = config
+        .otlp_endpoint()
+        .map(|endpoint| {
+            let exporter = SpanExporter::builder()
+                .with_http()
+                .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+                .build()?;
+
+            Ok::<_, ExporterBuildError>(
+                SdkTracerProvider::builder()
+                    .with_batch_exporter(exporter)
+                    .with_resource(
+                        Resource::builder()
+                            .with_service_name(config.otlp_service_name().to_string())
+                            .build(),
+                    )
+                    .build(),
+            )
+        })
+        .transpose()?;
+
+    let otel = provider.as_ref().map(|provider| {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        tracing_opentelemetry::layer().with_tracer(provider.tracer("chat-backend"))
+    });
+
+    let fmt = tracing_subscriber::fmt::layer()
+        .compact()
+        .with_target(false)
+        .without_time();
+
+    let (filter, filter_layer) = LogFilter::new(LEVEL.to_string()).expect("Default log level should be a valid filter");
+
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(fmt)
+        .with(otel)
+        .try_init()
+        .expect("Failed to set subscriber");
+    LOG_FILTER.set(filter).expect("Log filter should only be set once");
+
+    if let Some(endpoint) = config.otlp_endpoint() {
+        tracing::info!(endpoint, "Exporting traces via OTLP");
+    }
+
+    Ok(Telemetry { provider })
+}
+
+/// Create the root span of an HTTP request.
+///
+/// If the request carries a W3C `traceparent` header, the span continues the caller's trace.
+pub fn make_request_span(request: &Request<Body>) -> Span {
+    let span = tracing::info_span!(
+        "request",
+        otel.name = %format_args!("{} {}", request.method(), request.uri().path()),
+        otel.kind = "server",
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+
+    let parent = global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(request.headers())));
+    // Only fails if the span is disabled, in which case there is nothing to link
+    span.set_parent(parent).ok();
+
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::TraceContextExt;
+
+    use super::*;
+
+    #[test]
+    fn test_log_filter_reload() {
+        let (filter, layer) = LogFilter::new("info").expect("Default filter should be valid");
+        let filter: &'static LogFilter = Box::leak(Box::new(filter));
+        let subscriber = tracing_subscriber::registry().with(layer);
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert!(!tracing::enabled!(target: "chat_backend::gateway::actor", tracing::Level::DEBUG));
+
+            filter
+                .set("info,chat_backend::gateway::actor=debug", None)
+                .expect("Directives should be valid");
+            assert!(tracing::enabled!(target: "chat_backend::gateway::actor", tracing::Level::DEBUG));
+            assert!(!tracing::enabled!(target: "chat_backend::rest", tracing::Level::DEBUG));
+
+            assert!(matches!(
+                filter.set("chat_backend=loud", None),
+                Err(LogFilterError::Invalid(_))
+            ));
+            // The invalid directives are rejected without touching the current filter
+            assert_eq!(filter.current(), "chat_backend::gateway::actor=debug,info");
+
+            filter.reset().expect("Filter should reset");
+            assert!(!tracing::enabled!(target: "chat_backend::gateway::actor", tracing::Level::DEBUG));
+            assert_eq!(filter.current(), filter.default_directives());
+        });
+    }
+
+    #[test]
+    fn test_request_span_continues_trace() {
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber =
+            tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let request = Request::builder()
+            .uri("/api/v1/users/@me")
+            .header("traceparent", "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+            .body(Body::empty())
+            .expect("Failed to build request");
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = make_request_span(&request);
+            let context = span.context();
+            let span_context = context.span().span_context().clone();
+
+            assert_eq!(span_context.trace_id().to_string(), "4bf92f3577b34da6a3ce929d0e0e4736");
+            assert_ne!(span_context.span_id().to_string(), "00f067aa0ba902b7");
+        });
+    }
+}
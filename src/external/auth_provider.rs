@@ -0,0 +1,452 @@
+use std::{
+    fmt::Debug,
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+use http::StatusCode;
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header,
+    jwk::{Jwk, JwkSet},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+use tokio::sync::{Mutex, RwLock};
+
+use crate::app::appstate::OidcConfig;
+
+/// How long to wait after fetching the signing keys of a provider before fetching them again for an unknown key.
+const KEY_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// How long to wait after failing to fetch the signing keys of a provider before trying again.
+const KEY_REFRESH_FAILURE_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// An identity asserted by an external authentication provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalIdentity {
+    /// The identifier of the user at the provider, unique and stable for the provider.
+    pub subject: String,
+    /// The username the provider suggests for the user, if any.
+    pub username: Option<String>,
+    /// The name the user should be displayed as, if any.
+    pub display_name: Option<String>,
+    /// The email address of the user, if the provider shares it.
+    pub email: Option<String>,
+}
+
+/// Public information about a configured provider, used by clients to start the login flow.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthProviderInfo {
+    /// The name of the provider, used in the login endpoint.
+    pub name: String,
+    /// The kind of provider, e.g. `oidc`.
+    pub kind: &'static str,
+    /// The issuer clients should authenticate against.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// The client ID clients should authenticate as.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum AuthProviderError {
+    #[error("Identity was rejected: {0}")]
+    Rejected(String),
+    #[error("Failed to reach identity provider: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Identity provider returned status {0}")]
+    Status(StatusCode),
+    #[error("Identity provider is misconfigured: {0}")]
+    Misconfigured(String),
+    #[error("Identity provider is unavailable, retry in {0:?}")]
+    Unavailable(Duration),
+}
+
+impl AuthProviderError {
+    pub const fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Rejected(_) => StatusCode::UNAUTHORIZED,
+            Self::Request(_) | Self::Status(_) | Self::Misconfigured(_) | Self::Unavailable(_) => {
+                StatusCode::BAD_GATEWAY
+            }
+        }
+    }
+}
+
+/// A service that validates identities of users, as an alternative to native accounts.
+///
+/// Users logging in through a provider for the first time are provisioned automatically,
+/// see `UserOps::provision_external`.
+pub trait AuthProvider: Debug + Send + Sync {
+    /// The name of the provider. Identities are only unique within a provider.
+    fn name(&self) -> &str;
+
+    /// Public information clients need to authenticate with the provider.
+    fn info(&self) -> AuthProviderInfo;
+
+    /// Validate a token issued by the provider, and resolve the identity it belongs to.
+    ///
+    /// ## Arguments
+    ///
+    /// * `token` - The token the client obtained from the provider.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AuthProviderError::Rejected`] - If the token is not valid.
+    /// * [`AuthProviderError`] - If the provider could not be reached.
+    fn authenticate<'a>(&'a self, token: &'a str) -> BoxFuture<'a, Result<ExternalIdentity, AuthProviderError>>;
+
+    /// Verify that the provider is reachable and usable, used in the startup self-check.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AuthProviderError`] - If the provider is not usable.
+    fn check(&self) -> BoxFuture<'_, Result<(), AuthProviderError>>;
+}
+
+/// When the signing keys of a provider were last fetched, and whether that failed.
+#[derive(Debug, Clone, Copy, Default)]
+struct KeyRefresh {
+    at: Option<Instant>,
+    failed: bool,
+}
+
+impl KeyRefresh {
+    /// How long to wait before the keys may be fetched again, if at all.
+    fn wait(self, now: Instant) -> Option<Duration> {
+        let cooldown = if self.failed {
+            KEY_REFRESH_FAILURE_COOLDOWN
+        } else {
+            KEY_REFRESH_INTERVAL
+        };
+        cooldown
+            .checked_sub(now.saturating_duration_since(self.at?))
+            .filter(|wait| !wait.is_zero())
+    }
+}
+
+#[derive(Deserialize)]
+struct DiscoveryDocument {
+    jwks_uri: String,
+}
+
+/// A provider validating ID tokens issued by an `OpenID` Connect provider.
+///
+/// Clients perform the authorization code flow with the provider themselves,
+/// and exchange the resulting ID token for a session token.
+/// Tokens are verified against the provider's published signing keys,
+/// and must be issued by the configured issuer to the configured client.
+#[derive(Debug)]
+pub struct OidcProvider {
+    config: OidcConfig,
+    http: reqwest::Client,
+    /// The signing keys of the provider, fetched on first use and refreshed when an unknown key is seen.
+    keys: RwLock<Option<JwkSet>>,
+    /// The last fetch of the signing keys, held while fetching them so that concurrent refreshes are not repeated.
+    refresh: Mutex<KeyRefresh>,
+}
+
+impl OidcProvider {
+    /// Create a new provider from the given configuration.
+    pub fn new(config: OidcConfig) -> Self {
+        Self {
+            config,
+            http: reqwest::Client::new(),
+            keys: RwLock::new(None),
+            refresh: Mutex::new(KeyRefresh::default()),
+        }
+    }
+
+    /// Fetch the current signing keys of the provider via its discovery document.
+    async fn fetch_keys(&self) -> Result<JwkSet, AuthProviderError> {
+        let discovery_url = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer().trim_end_matches('/')
+        );
+        let resp = self.http.get(discovery_url).send().await?;
+        if !resp.status().is_success() {
+            return Err(AuthProviderError::Status(resp.status()));
+        }
+        let discovery = resp.json::<DiscoveryDocument>().await?;
+
+        let resp = self.http.get(discovery.jwks_uri).send().await?;
+        if !resp.status().is_success() {
+            return Err(AuthProviderError::Status(resp.status()));
+        }
+        Ok(resp.json::<JwkSet>().await?)
+    }
+
+    /// Fetch the signing keys of the provider and store them, recording the attempt in `refresh`.
+    async fn refresh_keys(&self, refresh: &mut KeyRefresh) -> Result<(), AuthProviderError> {
+        let keys = self.fetch_keys().await;
+        *refresh = KeyRefresh {
+            at: Some(Instant::now()),
+            failed: keys.is_err(),
+        };
+        *self.keys.write().await = Some(keys?);
+        Ok(())
+    }
+
+    /// Look up a signing key among the keys fetched so far.
+    async fn cached_key(&self, kid: &str) -> Option<Jwk> {
+        self.keys.read().await.as_ref().and_then(|keys| keys.find(kid)).cloned()
+    }
+
+    /// Find the signing key with the given ID, refreshing the keys if it is unknown.
+    ///
+    /// Tokens with made up key IDs would otherwise trigger a fetch each, so the keys are refreshed
+    /// at most once per [`KEY_REFRESH_INTERVAL`], or [`KEY_REFRESH_FAILURE_COOLDOWN`] after a failed fetch.
+    async fn find_key(&self, kid: &str) -> Result<Jwk, AuthProviderError> {
+        if let Some(key) = self.cached_key(kid).await {
+            return Ok(key);
+        }
+
+        let mut refresh = self.refresh.lock().await;
+        // The keys may have been refreshed while waiting for the lock
+        if let Some(key) = self.cached_key(kid).await {
+            return Ok(key);
+        }
+        if let Some(wait) = refresh.wait(Instant::now()) {
+            return Err(if self.keys.read().await.is_some() {
+                AuthProviderError::Rejected(format!("Unknown signing key {kid}"))
+            } else {
+                AuthProviderError::Unavailable(wait)
+            });
+        }
+
+        // The provider may have rotated its keys
+        self.refresh_keys(&mut refresh).await?;
+        self.cached_key(kid)
+            .await
+            .ok_or_else(|| AuthProviderError::Rejected(format!("Unknown signing key {kid}")))
+    }
+
+    /// Map the claims of a validated ID token to an identity.
+    fn identity_from_claims(&self, mut claims: Map<String, Value>) -> Result<ExternalIdentity, AuthProviderError> {
+        let mut take_string = |claim: &str| match claims.remove(claim) {
+            Some(Value::String(s)) if !s.trim().is_empty() => Some(s.trim().to_string()),
+            _ => None,
+        };
+
+        Ok(ExternalIdentity {
+            subject: take_string("sub").ok_or_else(|| AuthProviderError::Rejected("Missing sub claim".into()))?,
+            username: take_string(self.config.username_claim()),
+            display_name: take_string(self.config.display_name_claim()),
+            email: take_string("email"),
+        })
+    }
+}
+
+impl AuthProvider for OidcProvider {
+    fn name(&self) -> &'static str {
+        "oidc"
+    }
+
+    fn info(&self) -> AuthProviderInfo {
+        AuthProviderInfo {
+            name: self.name().into(),
+            kind: "oidc",
+            issuer: Some(self.config.issuer().into()),
+            client_id: Some(self.config.client_id().into()),
+        }
+    }
+
+    fn authenticate<'a>(&'a self, token: &'a str) -> BoxFuture<'a, Result<ExternalIdentity, AuthProviderError>> {
+        Box::pin(async move {
+            let header = decode_header(token).map_err(|e| AuthProviderError::Rejected(e.to_string()))?;
+
+            // Only asymmetric algorithms can be verified with the provider's public keys
+            if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+                return Err(AuthProviderError::Rejected(format!(
+                    "Unsupported signing algorithm {:?}",
+                    header.alg
+                )));
+            }
+            let kid = header
+                .kid
+                .ok_or_else(|| AuthProviderError::Rejected("Missing key ID".into()))?;
+            let jwk = self.find_key(&kid).await?;
+            let key = DecodingKey::from_jwk(&jwk).map_err(|e| AuthProviderError::Misconfigured(e.to_string()))?;
+
+            let mut validation = Validation::new(header.alg);
+            validation.set_issuer(&[self.config.issuer()]);
+            validation.set_audience(&[self.config.client_id()]);
+            validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+            let claims = decode::<Map<String, Value>>(token, &key, &validation)
+                .map_err(|e| AuthProviderError::Rejected(e.to_string()))?
+                .claims;
+
+            self.identity_from_claims(claims)
+        })
+    }
+
+    fn check(&self) -> BoxFuture<'_, Result<(), AuthProviderError>> {
+        Box::pin(async move {
+            self.refresh_keys(&mut *self.refresh.lock().await).await?;
+            if self.keys.read().await.as_ref().is_none_or(|keys| keys.keys.is_empty()) {
+                return Err(AuthProviderError::Misconfigured(
+                    "Provider publishes no signing keys".into(),
+                ));
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn provider() -> OidcProvider {
+        OidcProvider::new(OidcConfig::new(
+            "https://sso.example.com".into(),
+            "chat".into(),
+            "preferred_username".into(),
+            "name".into(),
+        ))
+    }
+
+    #[test]
+    fn test_identity_from_claims() {
+        let claims = json!({
+            "sub": "1234",
+            "preferred_username": "Alice",
+            "name": " Alice Smith ",
+            "email": "alice@example.com",
+        });
+        let Value::Object(claims) = claims else { unreachable!() };
+
+        assert_eq!(
+            provider().identity_from_claims(claims).expect("valid claims"),
+            ExternalIdentity {
+                subject: "1234".into(),
+                username: Some("Alice".into()),
+                display_name: Some("Alice Smith".into()),
+                email: Some("alice@example.com".into()),
+            }
+        );
+
+        let Value::Object(claims) = json!({"sub": "", "name": 5}) else {
+            unreachable!()
+        };
+        assert!(matches!(
+            provider().identity_from_claims(claims),
+            Err(AuthProviderError::Rejected(_))
+        ));
+    }
+
+    #[test]
+    fn test_key_refresh_wait() {
+        let now = Instant::now();
+        assert_eq!(KeyRefresh::default().wait(now), None);
+
+        let refreshed = KeyRefresh {
+            at: Some(now),
+            failed: false,
+        };
+        assert_eq!(refreshed.wait(now), Some(KEY_REFRESH_INTERVAL));
+        assert_eq!(
+            refreshed.wait(now + Duration::from_secs(45)),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(refreshed.wait(now + KEY_REFRESH_INTERVAL), None);
+
+        let failed = KeyRefresh {
+            at: Some(now),
+            failed: true,
+        };
+        assert_eq!(failed.wait(now), Some(KEY_REFRESH_FAILURE_COOLDOWN));
+        assert_eq!(failed.wait(now + KEY_REFRESH_FAILURE_COOLDOWN), None);
+    }
+
+    /// Serve a discovery document and an empty key set, counting how often the keys were fetched.
+    async fn serve_keys(status: StatusCode) -> (String, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind listener");
+        let issuer = format!(
+            "http://{}",
+            listener.local_addr().expect("Listener should have an address")
+        );
+        let fetches = Arc::new(AtomicUsize::new(0));
+
+        let jwks_uri = format!("{issuer}/jwks");
+        let counter = fetches.clone();
+        let router = axum::Router::new()
+            .route(
+                "/.well-known/openid-configuration",
+                axum::routing::get(move || async move { axum::Json(json!({ "jwks_uri": jwks_uri })) }),
+            )
+            .route(
+                "/jwks",
+                axum::routing::get(move || async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    (status, axum::Json(json!({ "keys": [] })))
+                }),
+            );
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        (issuer, fetches)
+    }
+
+    fn provider_for(issuer: String) -> OidcProvider {
+        OidcProvider::new(OidcConfig::new(
+            issuer,
+            "chat".into(),
+            "preferred_username".into(),
+            "name".into(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_unknown_keys_refresh_rate_limited() {
+        let (issuer, fetches) = serve_keys(StatusCode::OK).await;
+        let provider = provider_for(issuer);
+
+        for kid in ["a", "b", "c"] {
+            assert!(matches!(
+                provider.find_key(kid).await,
+                Err(AuthProviderError::Rejected(_))
+            ));
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // Failed fetches are not retried until the cooldown passed either
+        let (issuer, fetches) = serve_keys(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let provider = provider_for(issuer);
+
+        assert!(matches!(
+            provider.find_key("a").await,
+            Err(AuthProviderError::Status(StatusCode::INTERNAL_SERVER_ERROR))
+        ));
+        assert!(matches!(
+            provider.find_key("a").await,
+            Err(AuthProviderError::Unavailable(_))
+        ));
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_symmetric_tokens() {
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(Algorithm::HS256),
+            &json!({"sub": "1234"}),
+            &jsonwebtoken::EncodingKey::from_secret(b"secret"),
+        )
+        .expect("Failed to encode token");
+
+        assert!(matches!(
+            provider().authenticate(&token).await,
+            Err(AuthProviderError::Rejected(_))
+        ));
+    }
+}
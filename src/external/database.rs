@@ -1,23 +1,47 @@
-use std::sync::{Arc, Weak};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Weak},
+    time::Duration,
+};
 
-use sqlx::{Executor, migrate, pool::PoolOptions, postgres::PgPool};
+use sqlx::{
+    Connection, Executor, migrate,
+    pool::PoolOptions,
+    postgres::{PgConnection, PgPool},
+};
+use tokio::sync::Mutex;
 
 use crate::app::ApplicationState;
 
+/// The first key of the advisory locks taken for periodic jobs, the second key is derived from the job's name
+const JOB_LOCK_NAMESPACE: i32 = 0x6a6f_6273;
+
 #[derive(Clone, Debug)]
 pub struct Database {
     pool: Option<PgPool>,
     app: Weak<ApplicationState>,
+    leases: Arc<JobLeases>,
+}
+
+/// The advisory locks this instance holds, electing it to run periodic jobs
+///
+/// The locks are held by a dedicated connection. Postgres releases them once that connection is closed,
+/// so if the instance holding them dies, another instance takes over the next time it tries to acquire them.
+#[derive(Debug, Default)]
+struct JobLeases {
+    /// The connection holding the locks, and the names of the jobs they were taken for
+    conn: Mutex<Option<(PgConnection, HashSet<String>)>>,
 }
 
 impl Database {
     /// Creates a new database instance
     ///
     /// Note: The database is not connected by default
-    pub const fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             pool: None,
             app: Weak::new(),
+            leases: Arc::default(),
         }
     }
 
@@ -26,10 +50,11 @@ impl Database {
     /// ## Arguments
     ///
     /// * `pool` - The connected database pool
-    pub const fn from_pool(pool: PgPool) -> Self {
+    pub fn from_pool(pool: PgPool) -> Self {
         Self {
             pool: Some(pool),
             app: Weak::new(),
+            leases: Arc::default(),
         }
     }
 
@@ -89,11 +114,71 @@ impl Database {
         Ok(())
     }
 
-    /// Closes the database connection
+    /// Closes the database connection, handing all periodic jobs this instance runs over to other instances
     pub async fn close(&self) {
+        self.resign_jobs().await;
         self.pool().close().await;
     }
 
+    /// Checks if this instance should run the given periodic job, so that only a single instance runs it.
+    ///
+    /// The first instance to call this for a job becomes its runner, and stays it until it resigns or dies.
+    /// Runs of other instances should be skipped in the meantime.
+    ///
+    /// ## Arguments
+    ///
+    /// * `job` - The name of the job, shared by all instances
+    ///
+    /// ## Returns
+    ///
+    /// `true` if this instance runs the job
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the lock could not be acquired, or the connection holding it was lost
+    pub async fn try_lead_job(&self, job: &str) -> Result<bool, sqlx::Error> {
+        let mut guard = self.leases.conn.lock().await;
+
+        if let Some((conn, jobs)) = guard.as_mut() {
+            // If the connection was lost, so were the locks it held
+            if let Err(e) = conn.ping().await {
+                *guard = None;
+                return Err(e);
+            }
+            if jobs.contains(job) {
+                return Ok(true);
+            }
+        }
+
+        let (conn, jobs) = match guard.as_mut() {
+            Some(leases) => leases,
+            // The connection is kept out of the pool, so that the locks are held for as long as it is open
+            None => guard.insert((self.pool().acquire().await?.detach(), HashSet::new())),
+        };
+
+        let locked = sqlx::query_scalar!(
+            r#"SELECT pg_try_advisory_lock($1, hashtext($2)) AS "locked!""#,
+            JOB_LOCK_NAMESPACE,
+            job
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        if locked {
+            jobs.insert(job.to_owned());
+        }
+        Ok(locked)
+    }
+
+    /// Stop running all periodic jobs this instance runs, letting other instances take them over immediately.
+    pub async fn resign_jobs(&self) {
+        if let Some((conn, _)) = self.leases.conn.lock().await.take()
+            && let Err(e) = conn.close().await
+        {
+            tracing::warn!(error = %e, "Failed to close the connection holding job locks");
+        }
+    }
+
     /// Begin a new transaction.
     ///
     /// ## Returns
@@ -106,6 +191,34 @@ impl Database {
     pub async fn begin(&self) -> Result<sqlx::Transaction<'static, sqlx::Postgres>, sqlx::Error> {
         self.pool().begin().await
     }
+
+    /// Begin a new transaction whose statements are cancelled once they run for longer than the given timeout.
+    ///
+    /// ## Arguments
+    ///
+    /// * `timeout` - How long each statement may run for, rounded to milliseconds.
+    ///
+    /// ## Returns
+    ///
+    /// A new transaction
+    ///
+    /// ## Errors
+    ///
+    /// If the transaction could not be started
+    pub async fn begin_with_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<sqlx::Transaction<'static, sqlx::Postgres>, sqlx::Error> {
+        let mut tx = self.begin().await?;
+        // Local to the transaction, so the setting does not leak into other users of the connection
+        sqlx::query!(
+            "SELECT set_config('statement_timeout', $1, true)",
+            format!("{}ms", timeout.as_millis())
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        Ok(tx)
+    }
 }
 
 // Allow the Database instance to be used as an executor directly
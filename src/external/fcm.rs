@@ -11,6 +11,7 @@ use http::StatusCode;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use thiserror::Error;
+use tracing::Instrument;
 
 /// See: <https://firebase.google.com/docs/cloud-messaging/auth-server#use-credentials-to-mint-access-tokens>
 static FCM_SCOPES: [&str; 1] = ["https://www.googleapis.com/auth/firebase.messaging"];
@@ -22,9 +23,101 @@ struct FirebaseMessage<'a> {
     notification: Option<&'a Notification>,
     #[serde(skip_serializing_if = "Option::is_none")]
     data: Option<&'a HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    android: Option<AndroidConfig<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    apns: Option<ApnsConfig<'a>>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+/// See: <https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages#androidconfig>
+#[derive(Serialize)]
+struct AndroidConfig<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    collapse_key: Option<&'a str>,
+    priority: &'static str,
+}
+
+/// See: <https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages#apnsconfig>
+#[derive(Serialize)]
+struct ApnsConfig<'a> {
+    headers: ApnsHeaders<'a>,
+}
+
+#[derive(Serialize)]
+struct ApnsHeaders<'a> {
+    #[serde(rename = "apns-collapse-id", skip_serializing_if = "Option::is_none")]
+    collapse_id: Option<&'a str>,
+    #[serde(rename = "apns-priority")]
+    priority: &'static str,
+}
+
+/// How urgently a push notification is delivered to the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PushPriority {
+    /// Delivered whenever convenient for the device, and shown without sound.
+    #[default]
+    Normal,
+    /// Delivered immediately, waking the device and playing a sound.
+    High,
+}
+
+impl PushPriority {
+    /// The priority of the message on Android.
+    ///
+    /// See: <https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages#androidmessagepriority>
+    const fn android_priority(self) -> &'static str {
+        match self {
+            Self::Normal => "NORMAL",
+            Self::High => "HIGH",
+        }
+    }
+
+    /// The value of the `apns-priority` header, `10` delivers immediately while `5` lets the device save power.
+    const fn apns_priority(self) -> &'static str {
+        match self {
+            Self::Normal => "5",
+            Self::High => "10",
+        }
+    }
+
+    /// The Android notification channel clients show the notification in,
+    /// letting users pick a different sound for each.
+    pub const fn android_channel_id(self) -> &'static str {
+        match self {
+            Self::Normal => "messages",
+            Self::High => "mentions",
+        }
+    }
+
+    /// The interruption level of the notification on iOS.
+    pub const fn interruption_level(self) -> &'static str {
+        match self {
+            Self::Normal => "passive",
+            Self::High => "time-sensitive",
+        }
+    }
+
+    /// Add the hints clients render the notification with to the data payload of a push notification,
+    /// since data messages are displayed by the clients themselves.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data payload to add the hints to
+    pub fn extend_data(self, data: &mut HashMap<String, String>) {
+        let (priority, sound) = match self {
+            Self::Normal => ("normal", "none"),
+            Self::High => ("high", "default"),
+        };
+        data.extend([
+            ("priority".to_string(), priority.to_string()),
+            ("sound".to_string(), sound.to_string()),
+            ("android_channel_id".to_string(), self.android_channel_id().to_string()),
+            ("interruption_level".to_string(), self.interruption_level().to_string()),
+        ]);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Notification {
     pub title: String,
     pub body: String,
@@ -232,6 +325,16 @@ impl FirebaseMessaging {
         })
     }
 
+    /// Verify that the configured credentials are valid by fetching an access token.
+    ///
+    /// # Errors
+    ///
+    /// If the token cannot be fetched.
+    pub async fn verify_credentials(&self) -> Result<(), FirebaseErrorKind> {
+        self.get_token().await?;
+        Ok(())
+    }
+
     /// Get a new access token for the FCM API.
     ///
     /// Calling this function multiple times will not result in multiple requests, as
@@ -262,6 +365,8 @@ impl FirebaseMessaging {
             )
     }
 
+    #[tracing::instrument(skip_all)]
+    #[allow(clippy::too_many_arguments)] // Shared by both public senders, which pass their arguments through
     async fn perform_send(
         http: &reqwest::Client,
         auth_token: impl Into<&str>,
@@ -269,6 +374,8 @@ impl FirebaseMessaging {
         to: impl Into<&str>,
         notification: Option<&Notification>,
         data: Option<&HashMap<String, String>>,
+        collapse_key: Option<&str>,
+        priority: PushPriority,
     ) -> Result<(), FirebaseError> {
         let auth_token = auth_token.into();
         let project_id = project_id.into();
@@ -277,6 +384,16 @@ impl FirebaseMessaging {
             token,
             notification,
             data,
+            android: Some(AndroidConfig {
+                collapse_key,
+                priority: priority.android_priority(),
+            }),
+            apns: Some(ApnsConfig {
+                headers: ApnsHeaders {
+                    collapse_id: collapse_key,
+                    priority: priority.apns_priority(),
+                },
+            }),
         };
 
         let mut attempts = 0;
@@ -359,6 +476,8 @@ impl FirebaseMessaging {
     /// * `token` - The device token to send the notification to
     /// * `notification` - The notification to send
     /// * `data` - Additional data to send with the notification
+    /// * `collapse_key` - Replaces undelivered or displayed notifications with the same key, if any
+    /// * `priority` - How urgently the notification is delivered
     ///
     /// # Errors
     ///
@@ -369,6 +488,8 @@ impl FirebaseMessaging {
         token: impl Into<String>,
         notification: Option<Notification>,
         data: Option<HashMap<String, String>>,
+        collapse_key: Option<&str>,
+        priority: PushPriority,
     ) -> Result<(), FirebaseError> {
         let token = token.into();
 
@@ -382,6 +503,8 @@ impl FirebaseMessaging {
             &*token,
             notification.as_ref(),
             data.as_ref(),
+            collapse_key,
+            priority,
         )
         .await?;
 
@@ -397,6 +520,8 @@ impl FirebaseMessaging {
     /// * `tokens` - A list of device tokens to send the notification to
     /// * `notification` - The notification to send
     /// * `data` - Additional data to send with the notification
+    /// * `collapse_key` - Replaces undelivered or displayed notifications with the same key, if any
+    /// * `priority` - How urgently the notification is delivered
     ///
     /// # Panics
     ///
@@ -407,11 +532,14 @@ impl FirebaseMessaging {
     /// Returns a list of errors for each token that failed to receive the notification.
     ///
     /// You can use [`FirebaseError::token()`] to get the token that caused the error, if any.
+    #[tracing::instrument(skip_all)]
     pub async fn send_notification_to_multiple(
         &self,
         tokens: impl IntoIterator<Item = impl Into<String>>,
         notification: Option<Notification>,
         data: Option<HashMap<String, String>>,
+        collapse_key: Option<&str>,
+        priority: PushPriority,
     ) -> Result<(), Vec<FirebaseError>> {
         let mut peekable = tokens.into_iter().peekable();
 
@@ -429,26 +557,33 @@ impl FirebaseMessaging {
         let project_id: Arc<str> = Arc::from(self.project_id.clone());
         let notification: Arc<Option<Notification>> = Arc::new(notification);
         let data: Option<Arc<HashMap<String, String>>> = data.map(Arc::new);
+        let collapse_key: Option<Arc<str>> = collapse_key.map(Arc::from);
 
         let tasks = peekable.map(|token| {
             let auth_token = auth_token.clone();
             let project_id = project_id.clone();
             let notification = notification.clone();
             let data = data.clone();
+            let collapse_key = collapse_key.clone();
             let token = token.into();
             let http = self.http.clone();
 
-            tokio::spawn(async move {
-                Self::perform_send(
-                    &http,
-                    &*auth_token,
-                    &*project_id,
-                    &*token,
-                    notification.as_ref().as_ref(),
-                    data.as_deref(),
-                )
-                .await
-            })
+            tokio::spawn(
+                async move {
+                    Self::perform_send(
+                        &http,
+                        &*auth_token,
+                        &*project_id,
+                        &*token,
+                        notification.as_ref().as_ref(),
+                        data.as_deref(),
+                        collapse_key.as_deref(),
+                        priority,
+                    )
+                    .await
+                }
+                .in_current_span(),
+            )
         });
 
         // Wait for all tasks to complete
@@ -1,8 +1,12 @@
 /// A module for all external services the application uses.
+pub mod auth_provider;
 pub mod database;
 pub mod fcm;
 pub mod s3;
+pub mod scanner;
 
+pub use auth_provider::{AuthProvider, OidcProvider};
 pub use database::Database;
 pub use fcm::FirebaseMessaging;
 pub use s3::S3Service;
+pub use scanner::{AttachmentScanner, HttpScanner};
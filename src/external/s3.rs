@@ -1,22 +1,126 @@
-use std::sync::{Arc, Weak};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    time::Duration,
+};
 
 use aws_sdk_s3::{
     Client,
     error::SdkError,
-    operation::head_bucket::HeadBucketError,
+    operation::{
+        get_object::GetObjectError, head_bucket::HeadBucketError, head_object::HeadObjectError,
+        restore_object::RestoreObjectError,
+    },
+    presigning::PresigningConfig,
     primitives::ByteStream,
-    types::{Delete, Object, ObjectIdentifier},
+    types::{
+        CompletedMultipartUpload, CompletedPart, Delete, GlacierJobParameters, MetadataDirective, MultipartUpload,
+        Object, ObjectIdentifier, RestoreRequest, StorageClass, Tier,
+    },
 };
 use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Utc};
 use mime::Mime;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
 
 use crate::{
-    app::ApplicationState, models::channel::Channel, models::errors::AppError, models::guild::Guild,
-    models::message::Message, models::snowflake::Snowflake,
+    app::ApplicationState, models::byte_range::ByteRange, models::channel::Channel,
+    models::direct_upload::PresignedUpload, models::errors::AppError, models::guild::Guild, models::message::Message,
+    models::snowflake::Snowflake,
 };
 
 pub type S3Client = Client;
 
+/// Characters that must be escaped in the `x-amz-copy-source` header, key separators are kept as-is.
+const COPY_SOURCE_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// The maximum number of objects S3 deletes in a single request.
+const MAX_DELETE_BATCH: usize = 1000;
+
+/// The key of the object written to each bucket to verify it is writable.
+const PROBE_KEY: &str = ".self-check";
+
+/// The version of the key layout new objects are stored under.
+///
+/// * Version 1 keys carry no prefix, for example `{channel_id}/{message_id}/{attachment_id}/{filename}`.
+/// * Version 2 keys are prefixed with the version and the [`ObjectClass`] of the object,
+///   for example `v2/images/{channel_id}/{message_id}/{attachment_id}/{filename}`.
+///
+/// Objects stored under an older layout are moved by [`Ops::migrate_s3_keys`](crate::app::ops::Ops::migrate_s3_keys).
+pub const KEYSPACE_VERSION: i16 = 2;
+
+/// The classes objects are partitioned into, so that lifecycle policies can target each class separately,
+/// for example to expire old files while keeping avatars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectClass {
+    /// Attachments that are images
+    Images,
+    /// All other attachments
+    Files,
+    /// User and guild avatars
+    Avatars,
+    /// User profile banners
+    Banners,
+    /// Files clients uploaded directly to S3, until they are moved to their final key or discarded
+    Uploads,
+}
+
+impl ObjectClass {
+    /// All classes attachments may be stored under.
+    pub const ATTACHMENTS: [Self; 2] = [Self::Images, Self::Files];
+
+    /// The class of an attachment with the given MIME type.
+    pub fn of_attachment(mime: &Mime) -> Self {
+        if mime.type_() == mime::IMAGE {
+            Self::Images
+        } else {
+            Self::Files
+        }
+    }
+
+    /// The prefix of keys in this class.
+    pub const fn prefix(self) -> &'static str {
+        match self {
+            Self::Images => "images",
+            Self::Files => "files",
+            Self::Avatars => "avatars",
+            Self::Banners => "banners",
+            Self::Uploads => "uploads",
+        }
+    }
+}
+
+/// Build the key of an object under the given version of the key layout.
+///
+/// ## Arguments
+///
+/// * `version` - The version of the key layout, see [`KEYSPACE_VERSION`].
+/// * `class` - The class of the object.
+/// * `path` - The path of the object, which is its full key under the original layout.
+pub fn versioned_key(version: i16, class: ObjectClass, path: &str) -> String {
+    if version <= 1 {
+        path.to_string()
+    } else {
+        format!("v{version}/{}/{path}", class.prefix())
+    }
+}
+
+/// The prefixes attachments under the given path may be stored under, in all layouts currently in use.
+fn attachment_prefixes(path: &str) -> Vec<String> {
+    std::iter::once(versioned_key(1, ObjectClass::Files, path))
+        .chain(
+            ObjectClass::ATTACHMENTS
+                .into_iter()
+                .map(|class| versioned_key(KEYSPACE_VERSION, class, path)),
+        )
+        .collect()
+}
+
 const ALLOW_ALL_DOWNLOADS_POLICY: &str = r#"{
     "Version": "2012-10-17",
     "Statement": [
@@ -35,12 +139,21 @@ const ALLOW_ALL_DOWNLOADS_POLICY: &str = r#"{
 pub struct S3Service {
     app: Weak<ApplicationState>,
     client: S3Client,
+    /// The client presigned requests are signed with, addressing S3 as clients reach it.
+    presigner: S3Client,
 }
 
 impl S3Service {
     /// Create all buckets from the given config.
-    pub const fn new(client: S3Client) -> Self {
+    ///
+    /// ## Arguments
+    ///
+    /// * `client` - The client used to access S3.
+    /// * `presigner` - The client used to presign requests clients send to S3 themselves,
+    ///   if S3 is reachable under a different URL by them.
+    pub fn new(client: S3Client, presigner: Option<S3Client>) -> Self {
         Self {
+            presigner: presigner.unwrap_or_else(|| client.clone()),
             client,
             app: Weak::new(),
         }
@@ -76,6 +189,12 @@ impl S3Service {
         self.get_bucket("guilds")
     }
 
+    /// The quarantine bucket.
+    /// It holds attachments flagged by the attachment scanner, and unlike other buckets, is not publicly readable.
+    pub const fn quarantine(&self) -> Bucket<'_> {
+        self.get_bucket("quarantine")
+    }
+
     fn get_policy_string(bucket: &str) -> String {
         ALLOW_ALL_DOWNLOADS_POLICY.replace("{bucketName}", bucket)
     }
@@ -86,19 +205,24 @@ impl S3Service {
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
     pub async fn create_buckets(&self) -> Result<(), AppError> {
-        for bucket in [self.attachments().name(), self.users().name(), self.guilds().name()] {
+        let public_buckets = [self.attachments().name(), self.users().name(), self.guilds().name()];
+
+        for bucket in public_buckets.into_iter().chain([self.quarantine().name()]) {
             match self.client.head_bucket().bucket(bucket).send().await {
                 Ok(_) => {
                     tracing::info!("S3 Bucket {} already exists, skipping creation.", bucket);
                 }
                 Err(SdkError::ServiceError(e)) if matches!(e.err(), HeadBucketError::NotFound(_)) => {
                     self.client.create_bucket().bucket(bucket).send().await?;
-                    self.client
-                        .put_bucket_policy()
-                        .bucket(bucket)
-                        .policy(Self::get_policy_string(bucket))
-                        .send()
-                        .await?;
+
+                    if public_buckets.contains(&bucket) {
+                        self.client
+                            .put_bucket_policy()
+                            .bucket(bucket)
+                            .policy(Self::get_policy_string(bucket))
+                            .send()
+                            .await?;
+                    }
 
                     tracing::info!("Created S3 bucket: {}", bucket);
                 }
@@ -109,6 +233,21 @@ impl S3Service {
         Ok(())
     }
 
+    /// Verify that all buckets are writable by uploading and removing a probe object in each.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If any of the buckets cannot be written to.
+    pub async fn probe_buckets(&self) -> Result<(), AppError> {
+        for bucket in [self.attachments(), self.users(), self.guilds(), self.quarantine()] {
+            bucket
+                .put_object(PROBE_KEY, Bytes::from_static(b"ok"), &mime::TEXT_PLAIN)
+                .await?;
+            bucket.delete_object(PROBE_KEY).await?;
+        }
+        Ok(())
+    }
+
     /// Remove all S3 data for the given message.
     ///
     /// ## Arguments
@@ -119,27 +258,15 @@ impl S3Service {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all)]
     pub async fn remove_all_for_message(
         &self,
         channel: impl Into<Snowflake<Channel>>,
         message: impl Into<Snowflake<Message>>,
     ) -> Result<(), AppError> {
-        let bucket = self.attachments();
         let channel_id: Snowflake<Channel> = channel.into();
         let message_id: Snowflake<Message> = message.into();
-        let attachments = bucket.list_objects(format!("{channel_id}/{message_id}"), None).await?;
-
-        if attachments.is_empty() {
-            return Ok(());
-        }
-
-        bucket
-            .delete_objects(
-                attachments
-                    .into_iter()
-                    .map(|o| o.key.unwrap_or_else(|| message_id.to_string()))
-                    .collect(),
-            )
+        self.remove_all_attachments_under(&format!("{channel_id}/{message_id}/"))
             .await
     }
 
@@ -152,23 +279,30 @@ impl S3Service {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all)]
     pub async fn remove_all_for_channel(&self, channel: impl Into<Snowflake<Channel>>) -> Result<(), AppError> {
-        let bucket = self.attachments();
         let channel_id: Snowflake<Channel> = channel.into();
-        let attachments = bucket.list_objects(channel_id.to_string(), None).await?;
+        self.remove_all_attachments_under(&format!("{channel_id}/")).await
+    }
+
+    /// Remove all attachments stored under the given path, in any layout of the keyspace.
+    async fn remove_all_attachments_under(&self, path: &str) -> Result<(), AppError> {
+        let bucket = self.attachments();
 
-        if attachments.is_empty() {
-            return Ok(());
+        for prefix in attachment_prefixes(path) {
+            let keys: Vec<String> = bucket
+                .list_objects(prefix, None)
+                .await?
+                .into_iter()
+                .filter_map(|o| o.key)
+                .collect();
+
+            if !keys.is_empty() {
+                bucket.delete_objects(keys).await?;
+            }
         }
 
-        bucket
-            .delete_objects(
-                attachments
-                    .into_iter()
-                    .map(|o| o.key.unwrap_or_else(|| channel_id.to_string()))
-                    .collect(),
-            )
-            .await
+        Ok(())
     }
 
     /// Remove all S3 data for the given guild.
@@ -180,6 +314,7 @@ impl S3Service {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all)]
     pub async fn remove_all_for_guild(&self, guild: impl Into<Snowflake<Guild>>) -> Result<(), AppError> {
         let guild_id: i64 = guild.into().into();
 
@@ -198,6 +333,29 @@ impl S3Service {
     }
 }
 
+/// An object, or a range of it, streamed from a bucket.
+#[derive(Debug)]
+pub struct ObjectStream {
+    /// The contents of the object, or of the requested range
+    pub body: ByteStream,
+    /// The length of the body in bytes
+    pub content_length: Option<u64>,
+    /// The range of the object contained in the body, as a `Content-Range` header value.
+    /// Only set if a range was requested.
+    pub content_range: Option<String>,
+    /// The storage class of the object, only set if it is not stored under the standard class
+    pub storage_class: Option<StorageClass>,
+}
+
+/// The metadata of an object, as returned by a `HEAD` request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectMetadata {
+    /// The size of the object in bytes
+    pub content_length: Option<u64>,
+    /// The content type the object was uploaded with
+    pub content_type: Option<String>,
+}
+
 /// An abstraction for S3 buckets.
 #[derive(Clone, Debug)]
 pub struct Bucket<'a> {
@@ -211,7 +369,7 @@ impl<'a> Bucket<'a> {
     }
 
     /// The name of this bucket.
-    pub const fn name(&self) -> &str {
+    pub const fn name(&self) -> &'static str {
         self.name
     }
 
@@ -229,6 +387,7 @@ impl<'a> Bucket<'a> {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
     pub async fn get_object(&self, key: impl Into<String>) -> Result<Bytes, AppError> {
         let mut resp = self.s3.client().get_object().bucket(self.name).key(key).send().await?;
 
@@ -240,6 +399,61 @@ impl<'a> Bucket<'a> {
         Ok(bytes.freeze())
     }
 
+    /// Stream an object, or a range of it, from this bucket.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The key of the object to fetch.
+    /// * `range` - If set, only this range of the object is fetched.
+    ///
+    /// ## Returns
+    ///
+    /// [`ObjectStream`] - The object data and its metadata.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the object does not exist.
+    /// * [`AppError::RangeNotSatisfiable`] - If the range lies outside of the object.
+    /// * [`AppError::Archived`] - If the object is archived and has to be restored before it can be read.
+    /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
+    pub async fn stream_object(
+        &self,
+        key: impl Into<String>,
+        range: Option<ByteRange>,
+    ) -> Result<ObjectStream, AppError> {
+        let resp = self
+            .s3
+            .client()
+            .get_object()
+            .bucket(self.name)
+            .key(key)
+            .set_range(range.map(|r| r.to_string()))
+            .send()
+            .await
+            .map_err(|e| {
+                if e.as_service_error().is_some_and(GetObjectError::is_no_such_key) {
+                    AppError::NotFound("Object does not exist".into())
+                } else if e.raw_response().is_some_and(|r| r.status().as_u16() == 416) {
+                    AppError::RangeNotSatisfiable("Requested range lies outside of the object".into())
+                } else if e
+                    .as_service_error()
+                    .is_some_and(GetObjectError::is_invalid_object_state)
+                {
+                    AppError::Archived("Object has to be restored from archive storage".into())
+                } else {
+                    e.into()
+                }
+            })?;
+
+        Ok(ObjectStream {
+            content_length: resp.content_length.and_then(|l| u64::try_from(l).ok()),
+            content_range: resp.content_range,
+            storage_class: resp.storage_class,
+            body: resp.body,
+        })
+    }
+
     /// Upload an object to this bucket.
     ///
     /// ## Arguments
@@ -251,6 +465,7 @@ impl<'a> Bucket<'a> {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
     pub async fn put_object(
         &self,
         key: impl Into<String>,
@@ -270,6 +485,282 @@ impl<'a> Bucket<'a> {
         Ok(())
     }
 
+    /// Fetch the metadata of an object in this bucket.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The key of the object.
+    ///
+    /// ## Returns
+    ///
+    /// [`ObjectMetadata`] - The metadata of the object, or `None` if it does not exist.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
+    pub async fn head_object(&self, key: impl Into<String>) -> Result<Option<ObjectMetadata>, AppError> {
+        match self.s3.client().head_object().bucket(self.name).key(key).send().await {
+            Ok(resp) => Ok(Some(ObjectMetadata {
+                content_length: resp.content_length.and_then(|l| u64::try_from(l).ok()),
+                content_type: resp.content_type,
+            })),
+            Err(e) if e.as_service_error().is_some_and(HeadObjectError::is_not_found) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Presign a request uploading an object to this bucket, so that clients can upload it to S3 directly.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The key of the object to upload.
+    /// * `content_type` - The content type the object has to be uploaded with.
+    /// * `size` - The size of the object in bytes.
+    /// * `expires_in` - For how long the request stays valid.
+    ///
+    /// ## Returns
+    ///
+    /// [`PresignedUpload`] - The request clients have to send.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the request could not be presigned.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
+    pub async fn presign_put_object(
+        &self,
+        key: impl Into<String>,
+        content_type: &str,
+        size: u64,
+        expires_in: Duration,
+    ) -> Result<PresignedUpload, AppError> {
+        let config = PresigningConfig::expires_in(expires_in).map_err(|e| AppError::S3(e.to_string()))?;
+
+        let request = self
+            .s3
+            .presigner
+            .put_object()
+            .bucket(self.name)
+            .key(key)
+            .content_type(content_type)
+            .content_length(size as i64)
+            .presigned(config)
+            .await?;
+
+        let headers: BTreeMap<String, String> = request
+            .headers()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        Ok(PresignedUpload::new(
+            request.uri().to_string(),
+            request.method().to_string(),
+            headers,
+            expires_in,
+        ))
+    }
+
+    /// Delete the files clients uploaded directly to this bucket before the given time,
+    /// that were never moved to their final key.
+    ///
+    /// ## Arguments
+    ///
+    /// * `cutoff` - Files uploaded before this are deleted.
+    ///
+    /// ## Returns
+    ///
+    /// The number of files deleted.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
+    pub async fn delete_stale_uploads(&self, cutoff: DateTime<Utc>) -> Result<usize, AppError> {
+        let keys: Vec<String> = self
+            .list_objects(versioned_key(KEYSPACE_VERSION, ObjectClass::Uploads, ""), None)
+            .await?
+            .into_iter()
+            .filter(|o| o.last_modified().is_some_and(|t| t.secs() < cutoff.timestamp()))
+            .filter_map(|o| o.key)
+            .collect();
+
+        for batch in keys.chunks(MAX_DELETE_BATCH) {
+            self.delete_objects(batch.to_vec()).await?;
+        }
+        Ok(keys.len())
+    }
+
+    /// Start a multipart upload in this bucket.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The key of the object to upload.
+    /// * `content_type` - The content type of the object.
+    ///
+    /// ## Returns
+    ///
+    /// [`String`] - The ID of the multipart upload.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails or returns no upload ID.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
+    pub async fn create_multipart_upload(
+        &self,
+        key: impl Into<String>,
+        content_type: &Mime,
+    ) -> Result<String, AppError> {
+        let resp = self
+            .s3
+            .client()
+            .create_multipart_upload()
+            .bucket(self.name)
+            .content_type(content_type.to_string())
+            .key(key)
+            .send()
+            .await?;
+
+        resp.upload_id
+            .ok_or_else(|| AppError::S3("Multipart upload was created without an upload ID".into()))
+    }
+
+    /// Upload a single part of a multipart upload.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The key of the object being uploaded.
+    /// * `upload_id` - The ID of the multipart upload.
+    /// * `part_number` - The number of the part, starting from 1.
+    /// * `data` - The data of the part.
+    ///
+    /// ## Returns
+    ///
+    /// [`Option<String>`] - The `ETag` of the uploaded part, required to complete the upload.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
+    pub async fn upload_part(
+        &self,
+        key: impl Into<String>,
+        upload_id: impl Into<String>,
+        part_number: i32,
+        data: impl Into<ByteStream>,
+    ) -> Result<Option<String>, AppError> {
+        let resp = self
+            .s3
+            .client()
+            .upload_part()
+            .bucket(self.name)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(data.into())
+            .send()
+            .await?;
+
+        Ok(resp.e_tag)
+    }
+
+    /// Complete a multipart upload, assembling the object from its parts.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The key of the object being uploaded.
+    /// * `upload_id` - The ID of the multipart upload.
+    /// * `parts` - The part numbers and `ETag`s of all uploaded parts, in order.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
+    pub async fn complete_multipart_upload(
+        &self,
+        key: impl Into<String>,
+        upload_id: impl Into<String>,
+        parts: Vec<(i32, Option<String>)>,
+    ) -> Result<(), AppError> {
+        let parts = parts
+            .into_iter()
+            .map(|(number, etag)| CompletedPart::builder().part_number(number).set_e_tag(etag).build())
+            .collect();
+
+        self.s3
+            .client()
+            .complete_multipart_upload()
+            .bucket(self.name)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    /// Abort a multipart upload, discarding all uploaded parts.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The key of the object being uploaded.
+    /// * `upload_id` - The ID of the multipart upload.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
+    pub async fn abort_multipart_upload(
+        &self,
+        key: impl Into<String>,
+        upload_id: impl Into<String>,
+    ) -> Result<(), AppError> {
+        self.s3
+            .client()
+            .abort_multipart_upload()
+            .bucket(self.name)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    /// List the multipart uploads in this bucket that were started, but neither completed nor aborted.
+    ///
+    /// ## Returns
+    ///
+    /// [`Vec<MultipartUpload>`] - The in-progress uploads, ordered by key.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
+    pub async fn list_multipart_uploads(&self) -> Result<Vec<MultipartUpload>, AppError> {
+        let mut uploads = Vec::new();
+        let mut markers: Option<(Option<String>, Option<String>)> = None;
+
+        // There is no paginator for this operation, so follow the markers manually
+        loop {
+            let mut req = self.s3.client().list_multipart_uploads().bucket(self.name);
+            if let Some((key_marker, upload_id_marker)) = markers {
+                req = req.set_key_marker(key_marker).set_upload_id_marker(upload_id_marker);
+            }
+
+            let resp = req.send().await?;
+            uploads.extend_from_slice(resp.uploads());
+
+            if !resp.is_truncated().unwrap_or(false) {
+                return Ok(uploads);
+            }
+            markers = Some((
+                resp.next_key_marker().map(ToOwned::to_owned),
+                resp.next_upload_id_marker().map(ToOwned::to_owned),
+            ));
+        }
+    }
+
     /// List objects in this bucket.
     ///
     /// ## Arguments
@@ -285,6 +776,7 @@ impl<'a> Bucket<'a> {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
     pub async fn list_objects(&self, prefix: impl Into<String>, limit: Option<i32>) -> Result<Vec<Object>, AppError> {
         let mut objects = Vec::new();
 
@@ -316,6 +808,7 @@ impl<'a> Bucket<'a> {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
     pub async fn delete_object(&self, key: impl Into<String>) -> Result<(), AppError> {
         self.s3
             .client()
@@ -328,6 +821,135 @@ impl<'a> Bucket<'a> {
         Ok(())
     }
 
+    /// Move an object from this bucket to another one, keeping its key.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The key of the object to move.
+    /// * `destination` - The bucket to move the object to.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
+    pub async fn move_object(&self, key: impl Into<String>, destination: &Bucket<'_>) -> Result<(), AppError> {
+        let key = key.into();
+        self.copy_then_delete(key.clone(), destination, key).await
+    }
+
+    /// Move an object to a different key within this bucket.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The current key of the object.
+    /// * `new_key` - The key to move the object to.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
+    pub async fn rename_object(&self, key: impl Into<String>, new_key: impl Into<String>) -> Result<(), AppError> {
+        self.copy_then_delete(key.into(), self, new_key.into()).await
+    }
+
+    /// Copy an object to the given key of a bucket, then delete the original.
+    async fn copy_then_delete(&self, key: String, destination: &Bucket<'_>, new_key: String) -> Result<(), AppError> {
+        self.s3
+            .client()
+            .copy_object()
+            .copy_source(format!(
+                "{}/{}",
+                self.name,
+                utf8_percent_encode(&key, COPY_SOURCE_ENCODE_SET)
+            ))
+            .bucket(destination.name)
+            .key(new_key)
+            .send()
+            .await?;
+
+        self.delete_object(key).await
+    }
+
+    /// Change the storage class of an object in this bucket, by copying it onto itself.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The key of the object.
+    /// * `storage_class` - The storage class to move the object to.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name, storage_class = storage_class.as_str()))]
+    pub async fn set_storage_class(&self, key: impl Into<String>, storage_class: StorageClass) -> Result<(), AppError> {
+        let key = key.into();
+
+        self.s3
+            .client()
+            .copy_object()
+            .copy_source(format!(
+                "{}/{}",
+                self.name,
+                utf8_percent_encode(&key, COPY_SOURCE_ENCODE_SET)
+            ))
+            .bucket(self.name)
+            .key(key)
+            .storage_class(storage_class)
+            .metadata_directive(MetadataDirective::Copy)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    /// Request a temporary copy of an archived object to be restored, so that it can be read.
+    ///
+    /// Requesting a restore of an object that is already being restored, or that does not need one, has no effect.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The key of the object to restore.
+    /// * `days` - The number of days the restored copy is kept for.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
+    pub async fn restore_object(&self, key: impl Into<String>, days: i32) -> Result<(), AppError> {
+        let request = RestoreRequest::builder()
+            .days(days)
+            .glacier_job_parameters(
+                GlacierJobParameters::builder()
+                    .tier(Tier::Standard)
+                    .build()
+                    .expect("Failed to build GlacierJobParameters"),
+            )
+            .build();
+
+        let result = self
+            .s3
+            .client()
+            .restore_object()
+            .bucket(self.name)
+            .key(key)
+            .restore_request(request)
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(RestoreObjectError::is_object_already_in_active_tier_error)
+                    // A restore that is already in progress is reported as a conflict
+                    || e.raw_response().is_some_and(|r| r.status().as_u16() == 409) =>
+            {
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
     // TODO: Maybe use S3 lifecycles for this to mark objects for deletion instead?
     // The idea is to use a batch job that tags objects for deletion and then the lifecycle policy
     // will delete them after a certain period of time.
@@ -345,6 +967,7 @@ impl<'a> Bucket<'a> {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(skip_all, fields(bucket = self.name))]
     pub async fn delete_objects(&self, keys: Vec<impl Into<String>>) -> Result<(), AppError> {
         let objects: Vec<ObjectIdentifier> = keys
             .into_iter()
@@ -0,0 +1,128 @@
+use std::fmt::Debug;
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use http::StatusCode;
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// The outcome of scanning a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanVerdict {
+    /// Nothing objectionable was found.
+    Clean,
+    /// The file contains malware.
+    Malware,
+    /// The file contains explicit content.
+    Nsfw,
+}
+
+impl ScanVerdict {
+    /// Whether the file should be quarantined.
+    pub const fn is_flagged(self) -> bool {
+        !matches!(self, Self::Clean)
+    }
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ScannerError {
+    #[error("Failed to reach attachment scanner: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Attachment scanner returned status {0}")]
+    Status(StatusCode),
+}
+
+/// A service that inspects uploaded attachments for malware or explicit content.
+///
+/// Scanning happens asynchronously after the attachment was uploaded,
+/// see `MessageOps::scan_pending_attachments`.
+pub trait AttachmentScanner: Debug + Send + Sync {
+    /// Scan a single file.
+    ///
+    /// ## Arguments
+    ///
+    /// * `filename` - The name of the file, including the file extension.
+    /// * `content_type` - The MIME type of the file, as declared by the uploader.
+    /// * `content` - The contents of the file.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ScannerError`] - If the file could not be scanned.
+    fn scan<'a>(
+        &'a self,
+        filename: &'a str,
+        content_type: &'a str,
+        content: Bytes,
+    ) -> BoxFuture<'a, Result<ScanVerdict, ScannerError>>;
+}
+
+#[derive(Deserialize)]
+struct ScanResponse {
+    verdict: ScanVerdict,
+}
+
+/// A scanner backed by an external HTTP service.
+///
+/// The file is `POST`ed as the raw request body, with its percent-encoded name in the `X-Filename` header.
+/// The service is expected to respond with `{"verdict": "clean" | "malware" | "nsfw"}`.
+#[derive(Debug, Clone)]
+pub struct HttpScanner {
+    url: String,
+    http: reqwest::Client,
+}
+
+impl HttpScanner {
+    /// Create a new scanner that sends files to the given URL.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            http: reqwest::Client::new(),
+        }
+    }
+}
+
+impl AttachmentScanner for HttpScanner {
+    fn scan<'a>(
+        &'a self,
+        filename: &'a str,
+        content_type: &'a str,
+        content: Bytes,
+    ) -> BoxFuture<'a, Result<ScanVerdict, ScannerError>> {
+        Box::pin(async move {
+            let resp = self
+                .http
+                .post(&self.url)
+                .header(http::header::CONTENT_TYPE, content_type)
+                .header(
+                    "X-Filename",
+                    utf8_percent_encode(filename, NON_ALPHANUMERIC).to_string(),
+                )
+                .body(content)
+                .send()
+                .await?;
+
+            if !resp.status().is_success() {
+                return Err(ScannerError::Status(resp.status()));
+            }
+
+            Ok(resp.json::<ScanResponse>().await?.verdict)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verdict_deserialization() {
+        let resp: ScanResponse = serde_json::from_str(r#"{"verdict": "nsfw"}"#).expect("valid response");
+        assert_eq!(resp.verdict, ScanVerdict::Nsfw);
+        assert!(resp.verdict.is_flagged());
+        assert!(!ScanVerdict::Clean.is_flagged());
+        assert!(serde_json::from_str::<ScanResponse>(r#"{"verdict": "unknown"}"#).is_err());
+    }
+}
@@ -1,7 +1,11 @@
 use std::{
-    collections::{HashMap, HashSet, hash_map::Entry},
+    collections::{HashMap, HashSet, VecDeque, hash_map::Entry},
     fmt::{self, Display, Formatter},
-    sync::{Arc, Weak},
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
 use http::StatusCode;
@@ -13,23 +17,94 @@ use tokio::sync::{
 };
 use uuid::Uuid;
 
+use super::{
+    fanout::{Delivery, FANOUT_WORKERS, FanoutBatch, FanoutLane, FanoutPool, PreparedEvent},
+    identify_limiter::{IdentifyKey, IdentifyLimiter},
+    metrics::{DispatchMetrics, DispatchSample},
+    poll::PollSessions,
+    queue::{Priority, PriorityQueue, STARVATION_LIMIT},
+    replay::{REPLAY_BUFFER_SIZE, ReplayBuffer},
+    trace::{DeliveryLog, DispatchLog, DispatchRecord, EventTrace},
+    typing::{TYPING_CHECK_INTERVAL, TypingTracker},
+};
 use crate::{
     app::{App, ApplicationState},
     models::{
-        gateway_event::{GatewayEvent, GatewayMessage},
+        capability::ClientCapability,
+        channel::Channel,
+        errors::InstructionError,
+        gateway_event::{GatewayEvent, GatewayMessage, TypingEntry},
         guild::Guild,
+        keyword_alert::KeywordMatcher,
+        prefs::{PresenceSharing, muted_word_matcher},
         snowflake::Snowflake,
-        user::User,
+        user::{Presence, User},
     },
     utils::join_handle::{AbortingJoinHandle, JoinHandleExt},
 };
 
+/// How long a disconnected session is retained for, waiting to be resumed
+pub const RESUME_TIMEOUT: Duration = Duration::from_secs(60);
+/// The maximum amount of `REQUEST_GUILD_MEMBERS` requests a session may send per [`MEMBER_REQUEST_WINDOW`]
+pub const MEMBER_REQUEST_LIMIT: usize = 30;
+/// The window [`MEMBER_REQUEST_LIMIT`] applies to
+pub const MEMBER_REQUEST_WINDOW: Duration = Duration::from_secs(60);
+/// How often users are checked for inactivity, to be marked as away
+pub const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// The number of requests from a user's sessions that may wait to be handled, unless configured otherwise
+pub const DEFAULT_INBOUND_CAPACITY: usize = 100;
+/// The number of requests a session may have dropped before it is closed
+pub const INBOUND_DROP_LIMIT: u64 = 50;
+
+/// An event sent to a client, along with its per-session sequence number
+///
+/// Events that are not retained for replay (such as `HEARTBEAT_ACK`) have no sequence number.
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct SequencedEvent {
+    #[serde(flatten)]
+    event: Arc<GatewayEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seq: Option<u64>,
+    /// The trace of the event, logged when it is sent. Replayed events are not traced.
+    #[serde(skip)]
+    trace: Option<EventTrace>,
+}
+
+impl SequencedEvent {
+    pub const fn new(event: Arc<GatewayEvent>, seq: Option<u64>) -> Self {
+        Self {
+            event,
+            seq,
+            trace: None,
+        }
+    }
+
+    /// Attach the trace of the event
+    pub const fn with_trace(mut self, trace: EventTrace) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// The sequence number of the event in the session, if it is sequenced
+    pub const fn seq(&self) -> Option<u64> {
+        self.seq
+    }
+
+    /// The trace of the event, if it is traced
+    pub const fn trace(&self) -> Option<EventTrace> {
+        self.trace
+    }
+}
+
 /// Possible responses issued by the server to a client
 #[derive(Debug, Clone, Serialize)]
 #[serde(untagged)]
 pub(super) enum GatewayResponse {
     // If sent through a connection handle, the payload should be sent to the client
-    Event(Arc<GatewayEvent>),
+    Event(SequencedEvent),
+    // An event that was already serialized, to be sent to the client with its sequence number
+    #[serde(skip)]
+    Prepared(PreparedEvent, Option<u64>),
     // If sent through a connection handle, the connection should be closed
     Close(GatewayCloseCode, String),
 }
@@ -75,6 +150,21 @@ pub enum GatewayCloseCode {
     BadGateway = 1014,
     /// Transport Layer Security handshake failure
     TLSHandshakeFail = 1015,
+    /// The session requested in RESUME could not be resumed, a new session should be started with IDENTIFY
+    InvalidSession = 4000,
+    /// The client sent requests faster than it is allowed to
+    RateLimited = 4001,
+    /// The user's account was terminated, the client must not reconnect
+    AccountTerminated = 4002,
+    /// The user's account was suspended, the client may reconnect once the suspension ends
+    AccountSuspended = 4003,
+}
+
+impl GatewayCloseCode {
+    /// Whether a client closed with this code may reconnect.
+    pub const fn allows_reconnect(self) -> bool {
+        !matches!(self, Self::AccountTerminated)
+    }
 }
 
 impl From<GatewayCloseCode> for u16 {
@@ -100,6 +190,10 @@ impl From<u16> for GatewayCloseCode {
             1013 => Self::TryAgainLater,
             1014 => Self::BadGateway,
             1015 => Self::TLSHandshakeFail,
+            4000 => Self::InvalidSession,
+            4001 => Self::RateLimited,
+            4002 => Self::AccountTerminated,
+            4003 => Self::AccountSuspended,
             _ => Self::ServerError,
         }
     }
@@ -160,7 +254,7 @@ impl Serialize for GatewayCloseCode {
 }
 
 /// A connection ID uniquely identifying a [`SessionHandle`] and the user it belongs to
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ConnectionId(pub Snowflake<User>, pub Uuid);
 
 impl Display for ConnectionId {
@@ -169,42 +263,175 @@ impl Display for ConnectionId {
     }
 }
 
+/// Information about a gateway session of a user, as shown to the user themselves
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionInfo {
+    /// The ID of the session
+    pub session_id: Uuid,
+    /// Whether the session currently has a connection, `false` while it waits to be resumed
+    pub connected: bool,
+    /// The round-trip time last reported by the client through `PING`, in milliseconds
+    pub latency: Option<u64>,
+}
+
+/// The users a user's presence is shown to, besides the user themselves
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PresenceAudience {
+    /// Members of mutual guilds see the user's presence.
+    #[default]
+    Everyone,
+    /// Only the given users see the user's presence.
+    Only(HashSet<Snowflake<User>>),
+}
+
+impl PresenceAudience {
+    /// The audience for the given sharing preference.
+    ///
+    /// ## Arguments
+    ///
+    /// * `sharing` - Who the user shares their presence with
+    /// * `friends` - The friends of the user, only kept if they share their presence with friends
+    pub fn new(sharing: PresenceSharing, friends: impl IntoIterator<Item = Snowflake<User>>) -> Self {
+        match sharing {
+            PresenceSharing::Everyone => Self::Everyone,
+            PresenceSharing::Friends => Self::Only(friends.into_iter().collect()),
+            PresenceSharing::Nobody => Self::Only(HashSet::new()),
+        }
+    }
+
+    /// Whether the given user is part of the audience.
+    pub fn includes(&self, viewer: Snowflake<User>) -> bool {
+        match self {
+            Self::Everyone => true,
+            Self::Only(viewers) => viewers.contains(&viewer),
+        }
+    }
+}
+
 /// A set of session handles for a given user
 ///
 /// ## Fields
 ///
 /// * `user_id` - The ID of the user
 /// * `guild_ids` - The guilds the user is a member of
+/// * `guest_channels` - The only channel the user may view in each guild they are a guest of
+/// * `direct_channel_ids` - The direct message channels the user is a recipient of
 /// * `handles` - The session handles for the user
 /// * `broadcast` - The broadcast channel for incoming messages coming from sessions. Session handles will forward messages to this channel.
+/// * `inbound_capacity` - The number of messages that may wait in `broadcast`, sessions drop further messages
+/// * `presence` - The presence the user picked
+/// * `is_idle` - Whether none of the user's sessions were active recently
+/// * `muted_words` - The words the user muted, messages containing them are marked as muted
 #[derive(Debug)]
 pub(super) struct UserHandle {
     user_id: Snowflake<User>,
     guild_ids: HashSet<Snowflake<Guild>>,
+    guest_channels: HashMap<Snowflake<Guild>, Snowflake<Channel>>,
+    direct_channel_ids: HashSet<Snowflake<Channel>>,
     handles: HashMap<Uuid, SessionHandle>,
     broadcast: Arc<broadcast::Sender<(ConnectionId, GatewayMessage)>>,
+    inbound_capacity: usize,
+    presence: Presence,
+    is_idle: bool,
+    muted_words: Option<Arc<KeywordMatcher>>,
+    presence_audience: PresenceAudience,
 }
 
 impl UserHandle {
-    pub fn new(user: impl Into<Snowflake<User>>, guild_ids: HashSet<Snowflake<Guild>>) -> Self {
-        let (sender, _) = broadcast::channel(100);
+    pub fn new(
+        user: impl Into<Snowflake<User>>,
+        guild_ids: HashSet<Snowflake<Guild>>,
+        guest_channels: HashMap<Snowflake<Guild>, Snowflake<Channel>>,
+        presence: Presence,
+    ) -> Self {
+        let (sender, _) = broadcast::channel(DEFAULT_INBOUND_CAPACITY);
 
         Self {
             user_id: user.into(),
             guild_ids,
+            guest_channels,
+            direct_channel_ids: HashSet::new(),
             handles: HashMap::new(),
             broadcast: Arc::new(sender),
+            inbound_capacity: DEFAULT_INBOUND_CAPACITY,
+            presence,
+            is_idle: false,
+            muted_words: None,
+            presence_audience: PresenceAudience::default(),
         }
     }
 
+    /// Change the number of messages from the user's sessions that may wait to be handled.
+    /// This must be called before any sessions are added.
+    ///
+    /// ## Arguments
+    ///
+    /// * `capacity` - The number of messages that may wait, must not be zero
+    #[must_use]
+    pub fn with_inbound_capacity(mut self, capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        self.broadcast = Arc::new(sender);
+        self.inbound_capacity = capacity;
+        self
+    }
+
     /// Get the guilds the user is a member of
     pub const fn guild_ids(&self) -> &HashSet<Snowflake<Guild>> {
         &self.guild_ids
     }
 
-    /// Get a mutable handle to the guilds the user is a member of
-    pub const fn guild_ids_mut(&mut self) -> &mut HashSet<Snowflake<Guild>> {
-        &mut self.guild_ids
+    /// Whether the user may receive an event about the given channel of a guild they are in.
+    /// Guests may only receive events about the channel they were invited to.
+    fn can_view(&self, guild: Snowflake<Guild>, channel: Option<Snowflake<Channel>>) -> bool {
+        match (self.guest_channels.get(&guild), channel) {
+            (Some(guest_channel), Some(channel)) => *guest_channel == channel,
+            _ => true,
+        }
+    }
+
+    /// Replace the direct message channels the user is a recipient of
+    ///
+    /// ## Arguments
+    ///
+    /// * `channels` - The IDs of the channels
+    fn set_direct_channels(&mut self, channels: HashSet<Snowflake<Channel>>) {
+        self.direct_channel_ids = channels;
+    }
+
+    /// Replace the words the user muted
+    ///
+    /// ## Arguments
+    ///
+    /// * `muted_words` - The compiled muted words, `None` if the user did not mute any
+    fn set_muted_words(&mut self, muted_words: Option<Arc<KeywordMatcher>>) {
+        self.muted_words = muted_words;
+    }
+
+    /// Replace the users the user's presence is shown to
+    ///
+    /// ## Arguments
+    ///
+    /// * `audience` - The new audience
+    fn set_presence_audience(&mut self, audience: PresenceAudience) {
+        self.presence_audience = audience;
+    }
+
+    /// Whether the user's presence is shown to the given user. Users always see their own presence.
+    ///
+    /// ## Arguments
+    ///
+    /// * `viewer` - The user viewing the presence
+    fn shares_presence_with(&self, viewer: Snowflake<User>) -> bool {
+        viewer == self.user_id || self.presence_audience.includes(viewer)
+    }
+
+    /// Whether the given text contains any of the words the user muted
+    ///
+    /// ## Arguments
+    ///
+    /// * `lowered` - The text to check, already lowercased
+    fn has_muted_words(&self, lowered: &str) -> bool {
+        self.muted_words.as_ref().is_some_and(|m| m.is_match_lowercase(lowered))
     }
 
     /// Subscribe to receive gateway messages from the user
@@ -229,13 +456,18 @@ impl UserHandle {
         self.handles.get(&id)
     }
 
-    /// Iterate over all connection handles belonging to the user
+    /// The number of sessions the user has on this instance, including detached ones
+    pub fn session_count(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Iterate mutably over all connection handles belonging to the user
     ///
     /// ## Returns
     ///
     /// An iterator over the connection handles
-    pub fn iter_handles(&self) -> impl Iterator<Item = (&Uuid, &SessionHandle)> {
-        self.handles.iter()
+    pub fn iter_handles_mut(&mut self) -> impl Iterator<Item = (&Uuid, &mut SessionHandle)> {
+        self.handles.iter_mut()
     }
 
     /// Get a mutable handle to the connection handle with the given ID
@@ -253,7 +485,7 @@ impl UserHandle {
     pub fn get_or_add(&mut self, id: Uuid, mut default: SessionHandle) -> &mut SessionHandle {
         match self.handles.entry(id) {
             Entry::Vacant(v) => {
-                default.bind_to(ConnectionId(self.user_id, id), &self.broadcast);
+                default.bind_to(ConnectionId(self.user_id, id), &self.broadcast, self.inbound_capacity);
                 v.insert(default)
             }
             Entry::Occupied(o) => o.into_mut(),
@@ -283,7 +515,7 @@ impl UserHandle {
     ///
     /// The ID of the inserted connection handle
     pub fn add_session(&mut self, id: Uuid, mut handle: SessionHandle) -> Uuid {
-        handle.bind_to(ConnectionId(self.user_id, id), &self.broadcast);
+        handle.bind_to(ConnectionId(self.user_id, id), &self.broadcast, self.inbound_capacity);
         self.handles.insert(id, handle);
         id
     }
@@ -334,6 +566,74 @@ impl UserHandle {
     pub fn is_empty(&self) -> bool {
         self.handles.is_empty()
     }
+
+    /// Check if the user has at least one session with a live connection
+    ///
+    /// ## Returns
+    ///
+    /// `true` if any of the user's sessions are not detached, `false` otherwise
+    pub fn is_connected(&self) -> bool {
+        self.handles.values().any(|h| !h.is_detached())
+    }
+
+    /// The presence shown to other users.
+    /// Users who picked [`Presence::Online`] are shown as [`Presence::Away`] while idle.
+    pub fn displayed_presence(&self) -> Presence {
+        match self.presence {
+            Presence::Online if self.is_idle => Presence::Away,
+            presence => presence,
+        }
+    }
+
+    /// Set the presence the user picked. This counts as activity on all of the user's sessions.
+    ///
+    /// ## Arguments
+    ///
+    /// * `presence` - The new presence
+    pub fn set_presence(&mut self, presence: Presence) {
+        self.presence = presence;
+        self.mark_active(None);
+    }
+
+    /// Record activity on one or all of the user's sessions
+    ///
+    /// ## Arguments
+    ///
+    /// * `session` - The session the activity happened on, or `None` if it cannot be attributed to a session
+    ///
+    /// ## Returns
+    ///
+    /// The presence to dispatch, if the displayed presence changed
+    pub fn mark_active(&mut self, session: Option<Uuid>) -> Option<Presence> {
+        let now = Instant::now();
+        match session {
+            Some(id) => self.handles.get_mut(&id).into_iter().for_each(|h| h.last_active = now),
+            None => self.handles.values_mut().for_each(|h| h.last_active = now),
+        }
+
+        let before = self.displayed_presence();
+        self.is_idle = false;
+        (before != self.displayed_presence()).then(|| self.displayed_presence())
+    }
+
+    /// Mark the user as idle if none of their sessions were active within the given timeout
+    ///
+    /// ## Arguments
+    ///
+    /// * `timeout` - How long the user has to be inactive for
+    ///
+    /// ## Returns
+    ///
+    /// The presence to dispatch, if the displayed presence changed
+    pub fn mark_idle_if_inactive(&mut self, timeout: Duration) -> Option<Presence> {
+        if self.is_idle || self.handles.values().any(|h| h.last_active.elapsed() < timeout) {
+            return None;
+        }
+
+        let before = self.displayed_presence();
+        self.is_idle = true;
+        (before != self.displayed_presence()).then(|| self.displayed_presence())
+    }
 }
 
 /// A struct representing a single session of a user
@@ -342,7 +642,11 @@ impl UserHandle {
 ///
 /// * `sender` - The sender for sending messages to the client
 /// * `receiver` - The receiver for receiving messages from the client
-/// * `guild_ids` - The guilds the user is a member of, this is used to filter events
+/// * `buffer` - The events sent to the client that were not acknowledged yet
+///
+/// A session outlives the connection it was created by: once the connection drops,
+/// the session is detached and keeps buffering events for [`RESUME_TIMEOUT`],
+/// so that a new connection may resume it without missing any events.
 #[derive(Debug)]
 pub(super) struct SessionHandle {
     /// Send messages to the client
@@ -351,10 +655,28 @@ pub(super) struct SessionHandle {
     receiver: Arc<broadcast::Sender<GatewayMessage>>,
     /// Use to forward events to the parent `ConnectionInfo`
     user_forwarder: Weak<broadcast::Sender<(ConnectionId, GatewayMessage)>>,
+    /// The number of events that may wait in the user forwarder, further events from this session are dropped
+    inbound_capacity: usize,
     /// The ID of this handle
     conn_id: Option<ConnectionId>,
     /// Handle to the forwarder task
     forwarder_task: Option<AbortingJoinHandle<()>>,
+    /// Sequenced events that were not acknowledged by the client yet
+    buffer: ReplayBuffer,
+    /// The time the connection of this session was lost, if it is currently detached
+    detached_at: Option<Instant>,
+    /// Incremented each time the session is resumed by a new connection
+    attachment: u64,
+    /// The times of the `REQUEST_GUILD_MEMBERS` requests sent within the last [`MEMBER_REQUEST_WINDOW`]
+    member_requests: VecDeque<Instant>,
+    /// The last time the client did something on behalf of the user, such as sending a message
+    last_active: Instant,
+    /// The round-trip time last reported by the client
+    latency: Option<Duration>,
+    /// The fan-out worker responses are delivered through, if assigned by the gateway actor
+    lane: Option<FanoutLane>,
+    /// The features the client declared to support, events for other features are not sent
+    capabilities: ClientCapability,
 }
 
 impl SessionHandle {
@@ -364,7 +686,7 @@ impl SessionHandle {
     ///
     /// * `sender` - The sender for sending messages to the client
     /// * `receiver` - The receiver for receiving messages from the client
-    pub const fn new(
+    pub fn new(
         sender: mpsc::UnboundedSender<GatewayResponse>,
         receiver: Arc<broadcast::Sender<GatewayMessage>>,
     ) -> Self {
@@ -374,27 +696,62 @@ impl SessionHandle {
             conn_id: None,
             forwarder_task: None,
             user_forwarder: Weak::new(),
+            inbound_capacity: DEFAULT_INBOUND_CAPACITY,
+            buffer: ReplayBuffer::new(REPLAY_BUFFER_SIZE),
+            detached_at: None,
+            attachment: 0,
+            member_requests: VecDeque::new(),
+            last_active: Instant::now(),
+            latency: None,
+            lane: None,
+            capabilities: ClientCapability::default(),
         }
     }
 
+    /// Only send the events the client declared to support
+    ///
+    /// ## Arguments
+    ///
+    /// * `capabilities` - The capabilities the client declared when identifying
+    #[must_use]
+    pub const fn with_capabilities(mut self, capabilities: ClientCapability) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// The features the client declared to support
+    pub const fn capabilities(&self) -> ClientCapability {
+        self.capabilities
+    }
+
     /// Bind the connection handle to a `ConnectionInfo` and start forwarding messages to it.
     ///
     /// ## Arguments
     ///
     /// * `conn_id` - The ID this handle was assigned
     /// * `user_forwarder` - The forwarder to send messages to
+    /// * `capacity` - The number of messages that may wait in the forwarder
     fn bind_to(
         &mut self,
         conn_id: ConnectionId,
         user_forwarder: &Arc<broadcast::Sender<(ConnectionId, GatewayMessage)>>,
+        capacity: usize,
     ) {
         self.user_forwarder = Arc::downgrade(user_forwarder);
+        self.inbound_capacity = capacity;
         self.conn_id = Some(conn_id);
         self.start_forwarding();
     }
 
+    /// Start forwarding messages from the client to the user forwarder.
+    ///
+    /// If the user forwarder is full, messages are dropped instead of pushing out the messages of other sessions,
+    /// and the client is told about it through a `REQUEST_DROPPED` event.
+    /// The connection is closed once [`INBOUND_DROP_LIMIT`] messages were dropped.
     fn start_forwarding(&mut self) {
         let mut receiver = self.receiver.subscribe();
+        let sender = self.sender.clone();
+        let capacity = self.inbound_capacity;
         let Some(user_forwarder) = self.user_forwarder.upgrade() else {
             tracing::warn!("User forwarder is unavailable, requests from user will not be forwarded");
             return;
@@ -407,31 +764,265 @@ impl SessionHandle {
 
         self.forwarder_task = Some(
             tokio::spawn(async move {
+                let mut total = 0;
                 loop {
-                    match receiver.recv().await {
+                    let count = match receiver.recv().await {
+                        Ok(_) if user_forwarder.len() >= capacity => 1,
                         Ok(msg) => {
                             // Ignore potentially not having any receivers
                             user_forwarder.send((conn_id, msg)).ok();
+                            continue;
                         }
-                        Err(RecvError::Lagged(e)) => {
-                            tracing::warn!(count = %e, "Forwarder is lagging, dropping messages");
-                        }
+                        Err(RecvError::Lagged(count)) => count,
                         Err(_) => break,
+                    };
+
+                    total += count;
+                    tracing::warn!(
+                        count,
+                        total,
+                        "Dropping messages from {conn_id}, too many are waiting to be handled"
+                    );
+                    if total >= INBOUND_DROP_LIMIT {
+                        let reason = "Too many requests were dropped".to_owned();
+                        sender
+                            .send(GatewayResponse::Close(GatewayCloseCode::RateLimited, reason))
+                            .ok();
+                        break;
                     }
+                    let event = GatewayEvent::RequestDropped {
+                        count,
+                        total,
+                        limit: INBOUND_DROP_LIMIT,
+                    };
+                    sender
+                        .send(GatewayResponse::Event(SequencedEvent::new(Arc::new(event), None)))
+                        .ok();
                 }
             })
             .abort_on_drop(), // We don't want the task to leak if the handle is dropped */
         );
     }
 
+    /// Deliver all further responses through the given fan-out worker
+    ///
+    /// ## Arguments
+    ///
+    /// * `lane` - The lane of the worker
+    fn set_lane(&mut self, lane: FanoutLane) {
+        self.lane = Some(lane);
+    }
+
+    /// Pass a response on to the connection, through the fan-out worker of the session if it has one
+    ///
+    /// ## Arguments
+    ///
+    /// * `response` - The response to deliver
+    fn deliver(&self, response: GatewayResponse) -> Result<(), SendError<GatewayResponse>> {
+        match (&self.lane, self.conn_id) {
+            (Some(lane), Some(conn_id)) => lane
+                .submit(Delivery::new(conn_id, self.attachment, self.sender.clone(), response))
+                .map_err(|d| SendError(d.into_response())),
+            _ => self.sender.send(response),
+        }
+    }
+
+    /// Whether the client declared support for the given event
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The event to check
+    pub fn accepts(&self, event: &GatewayEvent) -> bool {
+        self.capabilities.contains(event.required_capabilities())
+    }
+
     /// Send a message to the client
     ///
+    /// Sequenced events are assigned the next sequence number and retained until acknowledged.
+    /// If the session is detached, they are only retained, to be sent once the session is resumed.
+    ///
     /// ## Arguments
     ///
     /// * `message` - The message to send
-    pub fn send(&self, message: Arc<GatewayEvent>) -> Result<(), SendError<GatewayResponse>> {
-        let resp = GatewayResponse::Event(message);
-        self.sender.send(resp)
+    /// * `trace` - The trace of the message
+    pub fn send(&mut self, message: Arc<GatewayEvent>, trace: EventTrace) -> Result<(), SendError<GatewayResponse>> {
+        if !self.accepts(&message) {
+            return Ok(());
+        }
+        let message = if self.capabilities.contains(ClientCapability::PRESENCE) {
+            message
+        } else {
+            message.without_presences().map_or(message, Arc::new)
+        };
+        let seq = message.is_sequenced().then(|| self.buffer.push(message.clone()));
+
+        if self.is_detached() {
+            return Ok(());
+        }
+
+        self.deliver(GatewayResponse::Event(
+            SequencedEvent::new(message, seq).with_trace(trace),
+        ))
+    }
+
+    /// Send an event that was already serialized to the client, as part of dispatching it to many sessions
+    ///
+    /// The event is sequenced and retained like in [`Self::send`], and then queued in the batch
+    /// if the session has a fan-out worker, or sent directly otherwise.
+    /// Events the client does not support are skipped, but the caller is responsible for
+    /// trimming the event to the capabilities of the session.
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The event to send
+    /// * `prepared` - The serialized event
+    /// * `batch` - The batch to queue the event in
+    pub fn send_prepared(
+        &mut self,
+        message: Arc<GatewayEvent>,
+        prepared: &PreparedEvent,
+        batch: &mut FanoutBatch,
+    ) -> Result<(), SendError<GatewayResponse>> {
+        if !self.accepts(&message) {
+            return Ok(());
+        }
+        let seq = message.is_sequenced().then(|| self.buffer.push(message));
+
+        if self.is_detached() {
+            return Ok(());
+        }
+
+        let response = GatewayResponse::Prepared(prepared.clone(), seq);
+        match (&self.lane, self.conn_id) {
+            (Some(lane), Some(conn_id)) => {
+                batch.push(
+                    lane,
+                    Delivery::new(conn_id, self.attachment, self.sender.clone(), response),
+                );
+                Ok(())
+            }
+            _ => self.sender.send(response),
+        }
+    }
+
+    /// Drop all retained events up to and including the given sequence number
+    ///
+    /// ## Arguments
+    ///
+    /// * `seq` - The last sequence number acknowledged by the client
+    pub fn ack(&mut self, seq: u64) {
+        self.buffer.ack(seq);
+    }
+
+    /// Record a `REQUEST_GUILD_MEMBERS` request, if the session has not exceeded its rate limit
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the request may be served, `false` if the session is rate limited
+    pub fn try_acquire_member_request(&mut self) -> bool {
+        let now = Instant::now();
+        while self
+            .member_requests
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= MEMBER_REQUEST_WINDOW)
+        {
+            self.member_requests.pop_front();
+        }
+
+        if self.member_requests.len() >= MEMBER_REQUEST_LIMIT {
+            return false;
+        }
+        self.member_requests.push_back(now);
+        true
+    }
+
+    /// Record the round-trip time the client measured through `PING`
+    ///
+    /// ## Arguments
+    ///
+    /// * `latency` - The round-trip time reported by the client
+    pub const fn record_latency(&mut self, latency: Duration) {
+        self.latency = Some(latency);
+    }
+
+    /// Information about this session, as shown to its user
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the session
+    pub fn info(&self, id: Uuid) -> SessionInfo {
+        SessionInfo {
+            session_id: id,
+            connected: !self.is_detached(),
+            latency: self.latency.map(|l| l.as_millis() as u64),
+        }
+    }
+
+    /// Whether the connection of this session was lost
+    pub const fn is_detached(&self) -> bool {
+        self.detached_at.is_some()
+    }
+
+    /// The time the connection of this session was lost, if it is currently detached
+    pub const fn detached_at(&self) -> Option<Instant> {
+        self.detached_at
+    }
+
+    /// The attachment counter of the session, incremented each time it is resumed
+    pub const fn attachment(&self) -> u64 {
+        self.attachment
+    }
+
+    /// Mark the connection of this session as lost. Events will be retained until the session is resumed.
+    fn detach(&mut self) {
+        self.detached_at = Some(Instant::now());
+    }
+
+    /// Resume this session through the connection of another, freshly created session handle
+    ///
+    /// The events the client missed after `seq` are re-sent through the new connection, followed by `RESUMED`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `other` - The handle of the new connection
+    /// * `seq` - The last sequence number the client received
+    ///
+    /// ## Returns
+    ///
+    /// `false` if the missing events are no longer available, in which case the session is left untouched
+    fn resume_with(&mut self, other: Self, seq: u64) -> bool {
+        let Some(missed) = self.buffer.replay_after(seq) else {
+            return false;
+        };
+
+        // The previous connection may not have noticed it is dead yet
+        if !self.is_detached() {
+            self.close(
+                GatewayCloseCode::Normal,
+                "Session was resumed by another connection".into(),
+            )
+            .ok();
+        }
+
+        self.sender = other.sender;
+        self.receiver = other.receiver;
+        self.detached_at = None;
+        self.attachment += 1;
+        self.last_active = Instant::now();
+        self.start_forwarding();
+
+        for (seq, event) in missed {
+            self.sender
+                .send(GatewayResponse::Event(SequencedEvent::new(event, Some(seq))))
+                .ok();
+        }
+        self.sender
+            .send(GatewayResponse::Event(SequencedEvent::new(
+                Arc::new(GatewayEvent::Resumed),
+                None,
+            )))
+            .ok();
+        true
     }
 
     /// Close the connection with the given code and reason
@@ -442,8 +1033,7 @@ impl SessionHandle {
     /// * `code` - The close code to send
     /// * `reason` - The reason for closing the connection
     pub fn close(&self, code: GatewayCloseCode, reason: String) -> Result<(), SendError<GatewayResponse>> {
-        let resp = GatewayResponse::Close(code, reason);
-        self.sender.send(resp)
+        self.deliver(GatewayResponse::Close(code, reason))
     }
 
     /// Subscribe to messages coming from the client
@@ -456,8 +1046,17 @@ impl SessionHandle {
     }
 }
 
+/// Serialize an event to be dispatched, adding the time it took to `serialization`
+fn prepare_timed(event: &GatewayEvent, trace: EventTrace, serialization: &mut Duration) -> PreparedEvent {
+    let started = Instant::now();
+    let prepared = PreparedEvent::new(event, trace);
+    *serialization += started.elapsed();
+    prepared
+}
+
 /// Defines the possible modes for sending a message
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "type", content = "id", rename_all = "snake_case")]
 pub enum SendMode {
     /// Send the event to a specific user
     ToUser(Snowflake<User>),
@@ -465,28 +1064,81 @@ pub enum SendMode {
     ToMutualGuilds(Snowflake<User>),
     /// Send the event to all users in a guild
     ToGuild(Snowflake<Guild>),
+    /// Send the event to the recipients of a direct message channel
+    ToChannelRecipients(Snowflake<Channel>),
+}
+
+impl SendMode {
+    /// Send an event about a channel to everyone who can view it: the members of its guild,
+    /// or the recipients of a direct message channel if it belongs to no guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the channel belongs to, if any
+    /// * `channel` - The channel the event is about
+    pub const fn for_channel(guild: Option<Snowflake<Guild>>, channel: Snowflake<Channel>) -> Self {
+        match guild {
+            Some(guild) => Self::ToGuild(guild),
+            None => Self::ToChannelRecipients(channel),
+        }
+    }
 }
 
 /// An instruction sent to the gateway actor
+#[derive(Debug)]
 enum Instruction {
     /// Dispatch a new event with the given send mode
-    Dispatch(GatewayEvent, SendMode),
+    Dispatch(GatewayEvent, SendMode, EventTrace),
     /// Send an event to a specific user
-    SendTo(Snowflake<User>, GatewayEvent),
+    SendTo(Snowflake<User>, GatewayEvent, EventTrace),
     /// Send an event to a specific session of a user
-    SendToSession(ConnectionId, GatewayEvent),
+    SendToSession(ConnectionId, GatewayEvent, EventTrace),
     /// Close a session with the given code and reason
     CloseSession(ConnectionId, GatewayCloseCode, String),
     /// Close all sessions from a user with the given code and reason
     CloseUser(Snowflake<User>, GatewayCloseCode, String),
     /// Close all sessions, shutting down the gateway
     CloseAll(oneshot::Sender<()>),
-    /// Remove a session handle from the gateway state (This does not send a close frame)
-    RemoveSession(ConnectionId),
-    /// Add a new connection handle to the gateway state
-    NewSession(ConnectionId, SessionHandle),
+    /// Close the connection of a session with the given code and reason, but keep it resumable
+    DisconnectSession(ConnectionId, GatewayCloseCode, String),
+    /// Mark a session as having lost its connection (This does not send a close frame)
+    /// The second field is the attachment the connection belonged to
+    DetachSession(ConnectionId, u64),
+    /// Remove a detached session if it was not resumed in time
+    ExpireSession(ConnectionId),
+    /// Add a new connection handle to the gateway state, along with the presence the user picked
+    NewSession(ConnectionId, SessionHandle, Presence),
+    /// Resume a detached session through a new connection handle from the given sequence number.
+    /// Responds with the new attachment of the session, or `None` if it cannot be resumed.
+    ResumeSession(ConnectionId, SessionHandle, u64, oneshot::Sender<Option<u64>>),
+    /// Acknowledge all events sent to a session up to and including the given sequence number
+    AckSession(ConnectionId, u64),
+    /// Record the round-trip time a client measured for a session
+    RecordLatency(ConnectionId, Duration),
+    /// Record activity of a user, on the given session if it is known
+    RecordActivity(Snowflake<User>, Option<Uuid>),
+    /// Update the presence a user picked
+    SetPresence(Snowflake<User>, Presence),
+    /// Replace the words a user muted
+    SetMutedWords(Snowflake<User>, Option<Arc<KeywordMatcher>>),
+    /// Replace the users a user's presence is shown to
+    SetPresenceAudience(Snowflake<User>, PresenceAudience),
+    /// Mark users that were not active within the configured timeout as away
+    SweepIdle,
+    /// Stop the typing indicators of users that did not start typing again in time
+    SweepTyping,
+    /// Record a `REQUEST_GUILD_MEMBERS` request of a session.
+    /// Responds with whether the request is within the session's rate limit.
+    AcquireMemberRequest(ConnectionId, oneshot::Sender<bool>),
+    /// Record a handshake attempt.
+    /// Responds with the delay the client should wait for before retrying, if it is rate limited.
+    AcquireIdentify(IdentifyKey, oneshot::Sender<Result<(), Duration>>),
     /// Add a new guild member instance to an existing connection, if it exists
     AddMember(Snowflake<User>, Snowflake<Guild>),
+    /// Add a new guest instance restricted to a single channel to an existing connection, if it exists
+    AddGuest(Snowflake<User>, Snowflake<Guild>, Snowflake<Channel>),
+    /// Register a new direct message channel to a user's connections
+    AddRecipient(Snowflake<User>, Snowflake<Channel>),
     /// Remove a guild member instance from an existing connection, if it exists
     RemoveMember(Snowflake<User>, Snowflake<Guild>),
     /// Subscribe to receive messages from a specific user
@@ -504,20 +1156,118 @@ enum Instruction {
     /// Query the connected status of multiple users
     /// The response will contain a set of users that are connected
     QueryMultiConnectedStatus(HashSet<Snowflake<User>>, oneshot::Sender<HashSet<Snowflake<User>>>),
+    /// Query the presence shown for a user to another user, `None` if they are not connected
+    QueryPresence(Snowflake<User>, Snowflake<User>, oneshot::Sender<Option<Presence>>),
+    /// Query information about all sessions of a user
+    QuerySessions(Snowflake<User>, oneshot::Sender<Vec<SessionInfo>>),
+    /// Query the users typing in each channel a user can view
+    QueryTyping(Snowflake<User>, oneshot::Sender<Vec<TypingEntry>>),
+    /// Query the users typing in a channel
+    QueryChannelTyping(Snowflake<Channel>, oneshot::Sender<Vec<Snowflake<User>>>),
+}
+
+impl Instruction {
+    /// The name of the instruction, used when logging dropped instructions.
+    const fn kind(&self) -> &'static str {
+        match self {
+            Self::Dispatch(..) => "Dispatch",
+            Self::SendTo(..) => "SendTo",
+            Self::SendToSession(..) => "SendToSession",
+            Self::CloseSession(..) => "CloseSession",
+            Self::CloseUser(..) => "CloseUser",
+            Self::CloseAll(..) => "CloseAll",
+            Self::DisconnectSession(..) => "DisconnectSession",
+            Self::DetachSession(..) => "DetachSession",
+            Self::ExpireSession(..) => "ExpireSession",
+            Self::NewSession(..) => "NewSession",
+            Self::ResumeSession(..) => "ResumeSession",
+            Self::AckSession(..) => "AckSession",
+            Self::RecordLatency(..) => "RecordLatency",
+            Self::RecordActivity(..) => "RecordActivity",
+            Self::SetPresence(..) => "SetPresence",
+            Self::SetMutedWords(..) => "SetMutedWords",
+            Self::SetPresenceAudience(..) => "SetPresenceAudience",
+            Self::SweepIdle => "SweepIdle",
+            Self::SweepTyping => "SweepTyping",
+            Self::AcquireMemberRequest(..) => "AcquireMemberRequest",
+            Self::AcquireIdentify(..) => "AcquireIdentify",
+            Self::AddMember(..) => "AddMember",
+            Self::AddGuest(..) => "AddGuest",
+            Self::AddRecipient(..) => "AddRecipient",
+            Self::RemoveMember(..) => "RemoveMember",
+            Self::SubscribeToUser(..) => "SubscribeToUser",
+            Self::SubscribeToSession(..) => "SubscribeToSession",
+            Self::QueryConnectedStatus(..) => "QueryConnectedStatus",
+            Self::QueryMultiConnectedStatus(..) => "QueryMultiConnectedStatus",
+            Self::QueryPresence(..) => "QueryPresence",
+            Self::QuerySessions(..) => "QuerySessions",
+            Self::QueryTyping(..) => "QueryTyping",
+            Self::QueryChannelTyping(..) => "QueryChannelTyping",
+        }
+    }
+
+    /// The priority class the instruction is processed in.
+    ///
+    /// Membership changes share a class with dispatches, so that they are not reordered
+    /// around events that depend on them, such as a `GUILD_REMOVE` sent before removing the member.
+    const fn priority(&self) -> Priority {
+        match self {
+            Self::Dispatch(event, ..) | Self::SendTo(_, event, _) | Self::SendToSession(_, event, _) => match event {
+                GatewayEvent::TypingStart { .. }
+                | GatewayEvent::TypingStop { .. }
+                | GatewayEvent::PresenceUpdate { .. }
+                | GatewayEvent::UploadProgress { .. }
+                | GatewayEvent::RateLimit { .. } => Priority::Ambient,
+                // Queueing a pong behind other events would skew the latency measured by the client
+                GatewayEvent::Pong { .. } => Priority::Control,
+                _ => Priority::Messages,
+            },
+            Self::AddMember(..) | Self::AddGuest(..) | Self::AddRecipient(..) | Self::RemoveMember(..) => {
+                Priority::Messages
+            }
+            Self::RecordActivity(..) | Self::RecordLatency(..) | Self::SweepIdle | Self::SweepTyping => {
+                Priority::Ambient
+            }
+            _ => Priority::Control,
+        }
+    }
 }
 
 #[derive(Debug)]
 struct GatewayActor {
     receiver: mpsc::UnboundedReceiver<Instruction>,
+    /// Instructions that were received but not processed yet, along with when they were received
+    queue: PriorityQueue<(Instant, Instruction)>,
+    /// When the instruction being processed was received, before it waited in the queue
+    received_at: Instant,
     peermap: HashMap<Snowflake<User>, UserHandle>,
+    identify_limiter: IdentifyLimiter,
+    /// Who is typing in which channel
+    typing: TypingTracker,
+    /// Delivers events to sessions, so that dispatching to large guilds does not block the actor
+    fanout: FanoutPool,
     app: Weak<ApplicationState>,
 }
 
 impl GatewayActor {
     fn new(app: Weak<ApplicationState>, receiver: mpsc::UnboundedReceiver<Instruction>) -> Self {
+        let maybe_app = app.clone();
+        // Sessions whose connection was closed while an event was underway are detached by the actor
+        let fanout = FanoutPool::new(FANOUT_WORKERS, move |conn, attachment| {
+            let Some(app) = maybe_app.upgrade() else { return };
+            if let Some(sender) = &app.gateway().sender {
+                sender.send(Instruction::DetachSession(conn, attachment)).ok();
+            }
+        });
+
         Self {
             app,
             peermap: HashMap::new(),
+            identify_limiter: IdentifyLimiter::new(),
+            typing: TypingTracker::new(),
+            queue: PriorityQueue::new(STARVATION_LIMIT),
+            received_at: Instant::now(),
+            fanout,
             receiver,
         }
     }
@@ -532,20 +1282,60 @@ impl GatewayActor {
             .expect("GatewayActor is not bound to an ApplicationState")
     }
 
+    /// Get the next instruction to process, waiting for one if none are pending.
+    ///
+    /// All instructions received so far are moved into the priority queue first,
+    /// so that a backlog of low priority instructions cannot delay more important ones.
+    async fn next_instruction(&mut self) -> Option<Instruction> {
+        while let Ok(instruction) = self.receiver.try_recv() {
+            self.queue.push(instruction.priority(), (Instant::now(), instruction));
+        }
+
+        let (received_at, instruction) = if let Some(queued) = self.queue.pop() {
+            queued
+        } else {
+            let instruction = self.receiver.recv().await?;
+            (Instant::now(), instruction)
+        };
+        self.received_at = received_at;
+        Some(instruction)
+    }
+
     pub async fn run(&mut self) {
-        while let Some(instruction) = self.receiver.recv().await {
+        while let Some(instruction) = self.next_instruction().await {
             if !self.is_ready() && !matches!(instruction, Instruction::CloseAll(_)) {
                 tracing::warn!("App is not ready, ignoring instruction");
                 continue;
             }
 
             match instruction {
-                Instruction::NewSession(id, handle) => self.add_session(id, handle).await,
-                Instruction::RemoveSession(id) => self.drop_session(id),
-                Instruction::Dispatch(event, send_mode) => self.dispatch(event, send_mode),
-                Instruction::SendTo(user, event) => self.send_to(user, event),
-                Instruction::SendToSession(id, event) => self.send_to_session(id, event),
+                Instruction::NewSession(id, handle, presence) => self.add_session(id, handle, presence).await,
+                Instruction::DetachSession(id, attachment) => self.detach_session(id, attachment),
+                Instruction::DisconnectSession(id, code, reason) => self.disconnect_session(id, code, reason),
+                Instruction::ExpireSession(id) => self.expire_session(id),
+                Instruction::ResumeSession(id, handle, seq, tx) => {
+                    let _ = tx.send(self.resume_session(id, handle, seq));
+                }
+                Instruction::AckSession(id, seq) => self.ack_session(id, seq),
+                Instruction::RecordLatency(id, latency) => self.record_latency(id, latency),
+                Instruction::RecordActivity(user, session) => self.record_activity(user, session),
+                Instruction::SetPresence(user, presence) => self.set_presence(user, presence),
+                Instruction::SetMutedWords(user, muted_words) => self.set_muted_words(user, muted_words),
+                Instruction::SetPresenceAudience(user, audience) => self.set_presence_audience(user, audience),
+                Instruction::SweepIdle => self.sweep_idle(),
+                Instruction::SweepTyping => self.sweep_typing(Instant::now()),
+                Instruction::AcquireMemberRequest(id, tx) => {
+                    let _ = tx.send(self.acquire_member_request(id));
+                }
+                Instruction::AcquireIdentify(key, tx) => {
+                    let _ = tx.send(self.identify_limiter.acquire(key));
+                }
+                Instruction::Dispatch(event, send_mode, trace) => self.dispatch(event, send_mode, trace),
+                Instruction::SendTo(user, event, trace) => self.send_to(user, event, trace),
+                Instruction::SendToSession(id, event, trace) => self.send_to_session(id, event, trace),
                 Instruction::AddMember(user, guild) => self.add_member(user, guild),
+                Instruction::AddGuest(user, guild, channel) => self.add_guest(user, guild, channel),
+                Instruction::AddRecipient(user, channel) => self.add_recipient(user, channel),
                 Instruction::RemoveMember(user, guild) => self.remove_member(user, guild),
                 Instruction::CloseSession(conn, code, reason) => self.close_session(conn, code, reason),
                 Instruction::CloseUser(user, code, reason) => self.close_user_sessions(user, code, &reason),
@@ -561,6 +1351,18 @@ impl GatewayActor {
                 Instruction::QueryMultiConnectedStatus(ids, tx) => {
                     let _ = tx.send(self.is_connected_multiple(ids));
                 }
+                Instruction::QueryPresence(id, viewer, tx) => {
+                    let _ = tx.send(self.presence_of(id, viewer));
+                }
+                Instruction::QuerySessions(id, tx) => {
+                    let _ = tx.send(self.sessions_of(id));
+                }
+                Instruction::QueryTyping(id, tx) => {
+                    let _ = tx.send(self.typing_visible_to(id));
+                }
+                Instruction::QueryChannelTyping(channel, tx) => {
+                    let _ = tx.send(self.typing.typing_in(channel));
+                }
                 Instruction::CloseAll(tx) => {
                     self.close();
                     let _ = tx.send(()); // Signal that the gateway has been closed
@@ -576,26 +1378,69 @@ impl GatewayActor {
     ///
     /// * `user_id` - The ID of the user to add
     /// * `session` - The session handle to add
+    /// * `presence` - The presence the user picked
     ///
     /// ## Locks
     ///
     /// * `peers` (write)
-    async fn add_session(&mut self, id: ConnectionId, session: SessionHandle) {
+    async fn add_session(&mut self, id: ConnectionId, mut session: SessionHandle, presence: Presence) {
+        session.set_lane(self.fanout.lane_of(id));
+
         if let Some(user_handle) = self.peermap.get_mut(&id.0) {
             user_handle.add_session(id.1, session);
+            // The new connection announces the user's presence itself
+            user_handle.set_presence(presence);
         } else {
-            let guild_ids = sqlx::query!(
-                "SELECT guild_id FROM members WHERE user_id = $1",
+            let memberships = sqlx::query!(
+                "SELECT guild_id, guest_channel_id FROM members WHERE user_id = $1",
+                id.0 as Snowflake<User>
+            )
+            .fetch_all(self.app().db())
+            .await
+            .expect("Failed to fetch guilds during socket connection handling");
+
+            let guild_ids = memberships
+                .iter()
+                .map(|row| row.guild_id.into())
+                .collect::<HashSet<Snowflake<Guild>>>();
+            let guest_channels = memberships
+                .iter()
+                .filter_map(|row| Some((row.guild_id.into(), row.guest_channel_id?.into())))
+                .collect::<HashMap<Snowflake<Guild>, Snowflake<Channel>>>();
+
+            let direct_channels = sqlx::query_scalar!(
+                "SELECT channel_id FROM channel_recipients WHERE user_id = $1",
                 id.0 as Snowflake<User>
             )
             .fetch_all(self.app().db())
             .await
-            .expect("Failed to fetch guilds during socket connection handling")
+            .expect("Failed to fetch direct message channels during socket connection handling")
             .into_iter()
-            .map(|row| row.guild_id.into())
-            .collect::<HashSet<Snowflake<Guild>>>();
+            .map(Snowflake::from)
+            .collect::<HashSet<Snowflake<Channel>>>();
 
-            let mut handle = UserHandle::new(id.0, guild_ids);
+            let muted_words = sqlx::query_scalar!(
+                "SELECT muted_words FROM prefs WHERE user_id = $1",
+                id.0 as Snowflake<User>
+            )
+            .fetch_optional(self.app().db())
+            .await
+            .expect("Failed to fetch muted words during socket connection handling")
+            .unwrap_or_default();
+
+            let presence_audience = self
+                .app()
+                .ops()
+                .users()
+                .fetch_presence_audience(id.0)
+                .await
+                .expect("Failed to fetch presence audience during socket connection handling");
+
+            let mut handle = UserHandle::new(id.0, guild_ids, guest_channels, presence)
+                .with_inbound_capacity(self.app().config.gateway_inbound_capacity());
+            handle.set_direct_channels(direct_channels);
+            handle.set_muted_words(muted_word_matcher(muted_words));
+            handle.set_presence_audience(presence_audience);
             handle.add_session(id.1, session);
             let mut receiver = handle.subscribe();
             let maybe_app = self.app.clone();
@@ -606,6 +1451,10 @@ impl GatewayActor {
                     match receiver.recv().await {
                         Ok((id, msg)) => {
                             let Some(app) = maybe_app.upgrade() else { break };
+                            if msg.is_mutation() && app.read_only().is_enabled() {
+                                tracing::debug!(?msg, "Refused message from {id}, the instance is read-only");
+                                continue;
+                            }
                             tokio::spawn(async move {
                                 app.ops().handle_inbound_gateway_message(id, msg).await;
                             });
@@ -622,24 +1471,332 @@ impl GatewayActor {
         }
     }
 
-    /// Remove a session handle from the gateway state
+    /// Mark a session as having lost its connection, and schedule its removal
+    /// if it is not resumed within [`RESUME_TIMEOUT`]
     ///
     /// ## Arguments
     ///
-    /// * `user_id` - The ID of the user to remove
-    ///
-    /// ## Locks
-    ///
-    /// * `peers` (write)
-    fn drop_session(&mut self, id: ConnectionId) {
-        if let Some(conn) = self.peermap.get_mut(&id.0) {
-            conn.drop_session(id.1);
+    /// * `id` - The ID of the session to detach
+    /// * `attachment` - The attachment the lost connection belonged to.
+    ///   If the session was resumed by another connection since, this is a no-op.
+    fn detach_session(&mut self, id: ConnectionId, attachment: u64) {
+        let Some(handle) = self.peermap.get_mut(&id.0).and_then(|c| c.get_handle_mut(id.1)) else {
+            return;
+        };
 
-            if conn.is_empty() {
-                self.peermap.remove(&id.0);
-            }
+        if handle.attachment() != attachment || handle.is_detached() {
+            return;
         }
-    }
+        handle.detach();
+
+        let maybe_app = self.app.clone();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(RESUME_TIMEOUT).await;
+            let Some(app) = maybe_app.upgrade() else { return };
+            // The gateway may have been stopped in the meantime
+            if let Some(sender) = &app.gateway().sender {
+                sender.send(Instruction::ExpireSession(id)).ok();
+            }
+        });
+    }
+
+    /// Close the connection of a session, but keep the session around to be resumed
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the session to disconnect
+    /// * `code` - The close code to send
+    /// * `reason` - The reason for closing the connection
+    fn disconnect_session(&mut self, id: ConnectionId, code: GatewayCloseCode, reason: String) {
+        let Some(handle) = self.peermap.get_mut(&id.0).and_then(|c| c.get_handle_mut(id.1)) else {
+            return;
+        };
+
+        if let Err(e) = handle.close(code, reason) {
+            tracing::warn!(error = %e, "Failed to close connection handle");
+        }
+        let attachment = handle.attachment();
+        self.detach_session(id, attachment);
+    }
+
+    /// Remove a detached session if it was not resumed within [`RESUME_TIMEOUT`]
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the session to expire
+    fn expire_session(&mut self, id: ConnectionId) {
+        let Some(conn) = self.peermap.get_mut(&id.0) else {
+            return;
+        };
+
+        let is_expired = conn
+            .get_handle(id.1)
+            .and_then(SessionHandle::detached_at)
+            .is_some_and(|t| t.elapsed() >= RESUME_TIMEOUT);
+
+        if is_expired {
+            conn.drop_session(id.1);
+
+            if conn.is_empty() {
+                self.peermap.remove(&id.0);
+            }
+        }
+    }
+
+    /// Resume a session through a new connection
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the session to resume
+    /// * `handle` - The handle of the new connection
+    /// * `seq` - The last sequence number the client received
+    ///
+    /// ## Returns
+    ///
+    /// The new attachment of the session, or `None` if the session does not exist
+    /// or the events missed by the client are no longer available
+    fn resume_session(&mut self, id: ConnectionId, handle: SessionHandle, seq: u64) -> Option<u64> {
+        let user_handle = self.peermap.get_mut(&id.0)?;
+        let session = user_handle.get_handle_mut(id.1)?;
+
+        let attachment = session.resume_with(handle, seq).then(|| session.attachment())?;
+        // The resumed connection announces the user's presence itself
+        user_handle.mark_active(Some(id.1));
+        Some(attachment)
+    }
+
+    /// Acknowledge all events sent to a session up to and including the given sequence number
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the session
+    /// * `seq` - The last sequence number acknowledged by the client
+    fn ack_session(&mut self, id: ConnectionId, seq: u64) {
+        if let Some(handle) = self.peermap.get_mut(&id.0).and_then(|c| c.get_handle_mut(id.1)) {
+            handle.ack(seq);
+        }
+    }
+
+    /// Record the round-trip time a client measured for a session
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the session
+    /// * `latency` - The round-trip time reported by the client
+    fn record_latency(&mut self, id: ConnectionId, latency: Duration) {
+        if let Some(handle) = self.peermap.get_mut(&id.0).and_then(|c| c.get_handle_mut(id.1)) {
+            handle.record_latency(latency);
+        }
+    }
+
+    /// Record a `REQUEST_GUILD_MEMBERS` request of a session
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the session
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the request is within the session's rate limit, `false` if it is not or the session does not exist
+    fn acquire_member_request(&mut self, id: ConnectionId) -> bool {
+        self.peermap
+            .get_mut(&id.0)
+            .and_then(|c| c.get_handle_mut(id.1))
+            .is_some_and(SessionHandle::try_acquire_member_request)
+    }
+
+    /// Record activity of a user, marking them as online again if they were away
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user that was active
+    /// * `session` - The session the activity happened on, if known
+    fn record_activity(&mut self, user: Snowflake<User>, session: Option<Uuid>) {
+        let Some(presence) = self.peermap.get_mut(&user).and_then(|h| h.mark_active(session)) else {
+            return;
+        };
+
+        self.dispatch(
+            GatewayEvent::PresenceUpdate {
+                user_id: user,
+                presence,
+            },
+            SendMode::ToMutualGuilds(user),
+            EventTrace::new(None),
+        );
+    }
+
+    /// Update the presence a user picked
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user that changed their presence
+    /// * `presence` - The new presence
+    fn set_presence(&mut self, user: Snowflake<User>, presence: Presence) {
+        if let Some(handle) = self.peermap.get_mut(&user) {
+            handle.set_presence(presence);
+        }
+    }
+
+    /// Replace the words a connected user muted
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user that changed their muted words
+    /// * `muted_words` - The compiled muted words, `None` if the user did not mute any
+    fn set_muted_words(&mut self, user: Snowflake<User>, muted_words: Option<Arc<KeywordMatcher>>) {
+        if let Some(handle) = self.peermap.get_mut(&user) {
+            handle.set_muted_words(muted_words);
+        }
+    }
+
+    /// The user a `PRESENCE_UPDATE` is about, along with the users their presence is shown to
+    ///
+    /// ## Returns
+    ///
+    /// `None` if the event is not a `PRESENCE_UPDATE`, or its user is not connected
+    fn presence_audience_of(&self, event: &GatewayEvent) -> Option<(Snowflake<User>, PresenceAudience)> {
+        let GatewayEvent::PresenceUpdate { user_id, .. } = event else {
+            return None;
+        };
+        self.peermap
+            .get(user_id)
+            .map(|h| (*user_id, h.presence_audience.clone()))
+    }
+
+    /// Replace the users a connected user's presence is shown to,
+    /// telling users who gained or lost sight of it about the user's presence
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user that changed who they share their presence with
+    /// * `audience` - The new audience
+    fn set_presence_audience(&mut self, user: Snowflake<User>, audience: PresenceAudience) {
+        let Some(handle) = self.peermap.get_mut(&user) else {
+            return;
+        };
+        let previous = std::mem::replace(&mut handle.presence_audience, audience);
+        let audience = handle.presence_audience.clone();
+        let presence = handle.displayed_presence();
+        if !handle.is_connected() || presence == Presence::Offline {
+            return;
+        }
+
+        let guild_ids = handle.guild_ids().clone();
+        let changed: Vec<(Snowflake<User>, Presence)> = self
+            .peermap
+            .iter()
+            .filter(|(id, h)| **id != user && h.guild_ids().intersection(&guild_ids).next().is_some())
+            .filter_map(|(id, _)| match (previous.includes(*id), audience.includes(*id)) {
+                (true, false) => Some((*id, Presence::Offline)),
+                (false, true) => Some((*id, presence)),
+                _ => None,
+            })
+            .collect();
+
+        for (viewer, presence) in changed {
+            self.send_to(
+                viewer,
+                GatewayEvent::PresenceUpdate {
+                    user_id: user,
+                    presence,
+                },
+                EventTrace::new(None),
+            );
+        }
+    }
+
+    /// Mark users that were not active within the configured away timeout as away
+    fn sweep_idle(&mut self) {
+        let Some(timeout) = self.app().config.away_timeout() else {
+            return;
+        };
+
+        let changed: Vec<(Snowflake<User>, Presence)> = self
+            .peermap
+            .iter_mut()
+            .filter_map(|(id, handle)| Some((*id, handle.mark_idle_if_inactive(timeout)?)))
+            .collect();
+
+        for (user_id, presence) in changed {
+            self.dispatch(
+                GatewayEvent::PresenceUpdate { user_id, presence },
+                SendMode::ToMutualGuilds(user_id),
+                EventTrace::new(None),
+            );
+        }
+    }
+
+    /// Stop the typing indicators of users that did not start typing again within the timeout
+    ///
+    /// ## Arguments
+    ///
+    /// * `now` - The current time
+    fn sweep_typing(&mut self, now: Instant) {
+        for (user_id, channel_id, send_mode) in self.typing.expire(now) {
+            self.dispatch(
+                GatewayEvent::TypingStop { user_id, channel_id },
+                send_mode,
+                EventTrace::new(None),
+            );
+        }
+    }
+
+    /// Update the typing state with an event that is being dispatched.
+    /// Users stop typing once they send a message, clients hide their indicator on `MESSAGE_CREATE` already.
+    ///
+    /// Typing indicators wait behind messages in the queue, so one that was received before the author's
+    /// last message in the channel can be dispatched after it. Those would show the author as typing again.
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The event being dispatched
+    /// * `send_mode` - Who the event is dispatched to
+    ///
+    /// ## Returns
+    ///
+    /// Whether the event should be dispatched, false for typing indicators received before the author's last message
+    fn track_typing(&mut self, event: &GatewayEvent, send_mode: SendMode) -> bool {
+        match event {
+            GatewayEvent::TypingStart { user_id, channel_id } => {
+                return self.typing.start(*user_id, *channel_id, send_mode, self.received_at);
+            }
+            GatewayEvent::MessageCreate(message) => {
+                if let Some(author) = message.author() {
+                    self.typing.stop(author.id(), message.channel_id(), self.received_at);
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Returns the users typing in each channel the given user can view, if they are connected
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user viewing the channels
+    fn typing_visible_to(&self, user: Snowflake<User>) -> Vec<TypingEntry> {
+        let Some(handle) = self.peermap.get(&user) else {
+            return Vec::new();
+        };
+
+        self.typing
+            .channels()
+            .filter(|(channel, send_mode)| match send_mode {
+                SendMode::ToGuild(guild) => {
+                    handle.guild_ids().contains(guild) && handle.can_view(*guild, Some(*channel))
+                }
+                SendMode::ToChannelRecipients(channel) => handle.direct_channel_ids.contains(channel),
+                _ => false,
+            })
+            .map(|(channel_id, _)| TypingEntry {
+                channel_id,
+                user_ids: self.typing.typing_in(channel_id),
+            })
+            .collect()
+    }
 
     /// Get a receiver for receiving messages from a specific connection
     ///
@@ -667,59 +1824,183 @@ impl GatewayActor {
         Some(self.peermap.get(&user_id)?.subscribe())
     }
 
+    /// Start recording the sessions an event is sent to. Only sequenced events are traced,
+    /// and nothing is recorded while the actor is not bound to an application.
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The event being sent
+    /// * `trace` - The trace of the event
+    fn register_trace(&self, event: &GatewayEvent, trace: EventTrace) {
+        if event.is_sequenced()
+            && let Some(app) = self.app.upgrade()
+        {
+            app.gateway().deliveries().register(trace);
+        }
+    }
+
+    /// Record a dispatch in the dispatch log, if it is enabled. Only sequenced events are recorded.
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The event that was dispatched
+    /// * `record` - Creates the record of the dispatch, only called if it is recorded
+    fn record_dispatch(&self, event: &GatewayEvent, record: impl FnOnce() -> DispatchRecord) {
+        if !event.is_sequenced() {
+            return;
+        }
+        let Some(app) = self.app.upgrade() else {
+            return;
+        };
+        let capacity = app.config.gateway_dispatch_log_size();
+        if capacity > 0 {
+            app.gateway().dispatches().record(record(), capacity);
+        }
+    }
+
+    /// Add a dispatch to the counters of its event
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The event that was dispatched
+    /// * `send_mode` - Who the event was dispatched to
+    /// * `sample` - What dispatching the event took
+    fn record_metrics(&self, event: &GatewayEvent, send_mode: SendMode, sample: DispatchSample) {
+        if let Some(app) = self.app.upgrade() {
+            app.gateway().metrics().record(event.name(), send_mode, sample);
+        }
+    }
+
     /// Dispatch a new event originating from the given user to all other users
     ///
     /// ## Arguments
     ///
     /// * `payload` - The event payload
-    fn dispatch(&mut self, event: GatewayEvent, send_mode: SendMode) {
+    /// * `send_mode` - Who to dispatch the event to
+    /// * `trace` - The trace of the event
+    fn dispatch(&mut self, event: GatewayEvent, send_mode: SendMode, trace: EventTrace) {
         if let SendMode::ToUser(user_id) = send_mode {
-            self.send_to(user_id, event);
+            self.send_to(user_id, event, trace);
             return;
         }
+        if !self.track_typing(&event, send_mode) {
+            return;
+        }
+        let started = Instant::now();
 
-        tracing::debug!(?event, "Dispatching");
-
-        let mut to_drop: Vec<ConnectionId> = Vec::new();
-
-        // Avoid cloning the event for each user
+        tracing::debug!(
+            ?event,
+            event_id = %trace.event_id(),
+            correlation_id = trace.correlation_id().map(tracing::field::display),
+            "Dispatching"
+        );
+        self.register_trace(&event, trace);
+
+        let mut to_detach: Vec<(ConnectionId, u64)> = Vec::new();
+        let (mut recipients, mut skipped) = (0, 0);
+
+        // Serialize the event once, instead of once per session, and hand the deliveries to the fan-out workers
+        let (mut serialization, mut bytes) = (Duration::ZERO, 0);
+        let prepared = prepare_timed(&event, trace, &mut serialization);
+        let mut batch = self.fanout.batch();
+        // New messages are checked against the muted words of each recipient
+        let content = event.created_message_content().map(str::to_lowercase);
+        let mut muted: Option<Option<(Arc<GatewayEvent>, PreparedEvent)>> = None;
+        // As are copies without presences for sessions that do not display them,
+        // these never overlap with muted copies, as new messages include no members
+        let mut trimmed: Option<Option<(Arc<GatewayEvent>, PreparedEvent)>> = None;
         let event: Arc<GatewayEvent> = Arc::new(event);
 
         // Compute mutual guilds if the event is for mutual guilds
-        let event_user_id = if let SendMode::ToMutualGuilds(user_id) = send_mode {
-            Some(user_id)
-        } else {
-            None
+        let event_user_guilds = match send_mode {
+            SendMode::ToMutualGuilds(user_id) => self.peermap.get(&user_id).map(|a| a.guild_ids().clone()),
+            _ => None,
         };
+        let event_channel = event.channel_id();
 
-        let event_user_guilds = event_user_id.and_then(|uid| self.peermap.get(&uid).map(|a| a.guild_ids().clone()));
+        // Presences are only sent to the users they are shared with
+        let presence_audience = self.presence_audience_of(&event);
 
         // TODO: if event is a GUILD_REMOVE, remove the guild from guild sets
 
         for (uid, conninfo) in &mut self.peermap {
-            for (handle_id, handle) in conninfo.iter_handles() {
+            let in_audience = match send_mode {
                 // If the event is guild-specific, only send it to users that are members of that guild
-                if let SendMode::ToGuild(event_guild) = send_mode {
-                    if !conninfo.guild_ids().contains(&event_guild) {
-                        continue;
-                    }
+                SendMode::ToGuild(event_guild) => {
+                    conninfo.guild_ids().contains(&event_guild) && conninfo.can_view(event_guild, event_channel)
                 }
+                // Events about direct message channels are only sent to their recipients
+                SendMode::ToChannelRecipients(channel) => conninfo.direct_channel_ids.contains(&channel),
                 // Avoid sending events to users that don't share any guilds with the event originator
-                else if let Some(ref guild_ids) = event_user_guilds
-                    && guild_ids.intersection(conninfo.guild_ids()).next().is_none()
-                {
+                _ => event_user_guilds
+                    .as_ref()
+                    .is_none_or(|guild_ids| guild_ids.intersection(conninfo.guild_ids()).next().is_some()),
+            };
+            if !in_audience {
+                skipped += conninfo.session_count();
+                continue;
+            }
+
+            if let Some((subject, audience)) = &presence_audience
+                && uid != subject
+                && !audience.includes(*uid)
+            {
+                skipped += conninfo.session_count();
+                continue;
+            }
+
+            // Recipients who muted a word in the message receive a copy marked as muted, prepared once as well
+            let (event, prepared) = content
+                .as_deref()
+                .filter(|content| conninfo.has_muted_words(content))
+                .and_then(|_| {
+                    muted
+                        .get_or_insert_with(|| {
+                            event.to_muted().map(|muted| {
+                                let prepared = prepare_timed(&muted, trace, &mut serialization);
+                                (Arc::new(muted), prepared)
+                            })
+                        })
+                        .as_ref()
+                })
+                .map_or((&event, &prepared), |(event, prepared)| (event, prepared));
+
+            for (handle_id, handle) in conninfo.iter_handles_mut() {
+                if !handle.accepts(event) {
+                    skipped += 1;
                     continue;
                 }
-
-                if let Err(err) = handle.send(event.clone()) {
+                let (event, prepared) = if handle.capabilities().contains(ClientCapability::PRESENCE) {
+                    (event, prepared)
+                } else {
+                    trimmed
+                        .get_or_insert_with(|| {
+                            event.without_presences().map(|trimmed| {
+                                let prepared = prepare_timed(&trimmed, trace, &mut serialization);
+                                (Arc::new(trimmed), prepared)
+                            })
+                        })
+                        .as_ref()
+                        .map_or((event, prepared), |(event, prepared)| (event, prepared))
+                };
+                if let Err(err) = handle.send_prepared(event.clone(), prepared, &mut batch) {
                     tracing::warn!(error = %err, "Error dispatching event to user: {uid}");
-                    to_drop.push(ConnectionId(*uid, *handle_id));
+                    to_detach.push((ConnectionId(*uid, *handle_id), handle.attachment()));
+                } else {
+                    recipients += 1;
+                    bytes += prepared.len();
                 }
             }
         }
 
-        for conn in to_drop {
-            self.drop_session(conn);
+        self.fanout.submit(batch);
+        self.record_metrics(&event, send_mode, DispatchSample::new(recipients, bytes, serialization));
+        self.record_dispatch(&event, || {
+            DispatchRecord::new(&event, send_mode, trace, started).with_counts(recipients, skipped, to_detach.len())
+        });
+
+        for (conn, attachment) in to_detach {
+            self.detach_session(conn, attachment);
         }
     }
 
@@ -729,25 +2010,47 @@ impl GatewayActor {
     ///
     /// * `user` - The user to send the event to
     /// * `event` - The event to send
+    /// * `trace` - The trace of the event
     ///
     /// ## Locks
     ///
     /// * `peers` (write)
-    fn send_to(&mut self, user: impl Into<Snowflake<User>>, event: GatewayEvent) {
+    fn send_to(&mut self, user: impl Into<Snowflake<User>>, event: GatewayEvent, trace: EventTrace) {
+        let started = Instant::now();
         let user_id = user.into();
+        self.register_trace(&event, trace);
         let event = Arc::new(event);
-        let mut to_drop: Vec<ConnectionId> = Vec::new();
-        let Some(conn) = self.peermap.get(&user_id) else { return };
+        let mut to_detach: Vec<(ConnectionId, u64)> = Vec::new();
+        let (mut recipients, mut skipped) = (0, 0);
 
-        for (handle_id, handle) in conn.iter_handles() {
-            if let Err(err) = handle.send(event.clone()) {
-                tracing::warn!(error = %err, "Error sending event to session: {}-{}", &user_id, handle_id);
-                to_drop.push(ConnectionId(user_id, *handle_id));
+        if let Some(conn) = self.peermap.get_mut(&user_id) {
+            for (handle_id, handle) in conn.iter_handles_mut() {
+                if !handle.accepts(&event) {
+                    skipped += 1;
+                } else if let Err(err) = handle.send(event.clone(), trace) {
+                    tracing::warn!(error = %err, "Error sending event to session: {}-{}", &user_id, handle_id);
+                    to_detach.push((ConnectionId(user_id, *handle_id), handle.attachment()));
+                } else {
+                    recipients += 1;
+                }
             }
         }
 
-        for conn in to_drop {
-            self.drop_session(conn);
+        self.record_metrics(
+            &event,
+            SendMode::ToUser(user_id),
+            DispatchSample::new(recipients, 0, Duration::ZERO),
+        );
+        self.record_dispatch(&event, || {
+            DispatchRecord::new(&event, SendMode::ToUser(user_id), trace, started).with_counts(
+                recipients,
+                skipped,
+                to_detach.len(),
+            )
+        });
+
+        for (conn, attachment) in to_detach {
+            self.detach_session(conn, attachment);
         }
     }
 
@@ -757,18 +2060,39 @@ impl GatewayActor {
     ///
     /// * `id` - The ID of the session to send the event to
     /// * `event` - The event to send
-    fn send_to_session(&mut self, id: ConnectionId, event: GatewayEvent) {
+    /// * `trace` - The trace of the event
+    fn send_to_session(&mut self, id: ConnectionId, event: GatewayEvent, trace: EventTrace) {
+        let started = Instant::now();
+        self.register_trace(&event, trace);
         let event = Arc::new(event);
-        let Some(conn) = self.peermap.get_mut(&id.0) else {
-            return;
-        };
-        let Some(handle) = conn.get_handle_mut(id.1) else {
-            return;
-        };
+        let handle = self.peermap.get_mut(&id.0).and_then(|conn| conn.get_handle_mut(id.1));
+        let (mut recipients, mut skipped) = (0, 0);
+        let mut failed = None;
+
+        if let Some(handle) = handle {
+            if !handle.accepts(&event) {
+                skipped += 1;
+            } else if let Err(err) = handle.send(event.clone(), trace) {
+                tracing::warn!(error = %err, "Error sending event to session: {id}");
+                failed = Some(handle.attachment());
+            } else {
+                recipients += 1;
+            }
+        }
 
-        if let Err(err) = handle.send(event) {
-            tracing::warn!(error = %err, "Error sending event to session: {id}");
-            self.drop_session(id);
+        self.record_metrics(
+            &event,
+            SendMode::ToUser(id.0),
+            DispatchSample::new(recipients, 0, Duration::ZERO),
+        );
+        self.record_dispatch(&event, || {
+            DispatchRecord::new(&event, SendMode::ToUser(id.0), trace, started)
+                .with_session(id.1)
+                .with_counts(recipients, skipped, usize::from(failed.is_some()))
+        });
+
+        if let Some(attachment) = failed {
+            self.detach_session(id, attachment);
         }
     }
 
@@ -821,7 +2145,36 @@ impl GatewayActor {
     ///
     /// `true` if the user is connected, `false` otherwise
     fn is_connected(&self, user: impl Into<Snowflake<User>>) -> bool {
-        self.peermap.get(&user.into()).is_some_and(|conn| !conn.is_empty())
+        self.peermap.get(&user.into()).is_some_and(UserHandle::is_connected)
+    }
+
+    /// The presence shown for the given user to another user
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to get the presence of
+    /// * `viewer` - The user the presence is shown to
+    ///
+    /// ## Returns
+    ///
+    /// The presence shown to the viewer, or `None` if the user is not connected or does not share it with them
+    fn presence_of(&self, user: Snowflake<User>, viewer: Snowflake<User>) -> Option<Presence> {
+        self.peermap
+            .get(&user)
+            .filter(|h| h.is_connected() && h.shares_presence_with(viewer))
+            .map(UserHandle::displayed_presence)
+    }
+
+    /// Information about all sessions of the given user, including those waiting to be resumed
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to get the sessions of
+    fn sessions_of(&self, user: Snowflake<User>) -> Vec<SessionInfo> {
+        self.peermap
+            .get(&user)
+            .map(|h| h.handles.iter().map(|(id, handle)| handle.info(*id)).collect())
+            .unwrap_or_default()
     }
 
     /// Filter out users that are not connected
@@ -836,7 +2189,7 @@ impl GatewayActor {
     fn is_connected_multiple(&self, users: HashSet<Snowflake<User>>) -> HashSet<Snowflake<User>> {
         users
             .into_iter()
-            .filter(|u| self.peermap.get(u).is_some_and(|conn| !conn.is_empty()))
+            .filter(|u| self.peermap.get(u).is_some_and(UserHandle::is_connected))
             .collect()
     }
 
@@ -852,7 +2205,50 @@ impl GatewayActor {
     /// * `peers` (write)
     fn add_member(&mut self, user: impl Into<Snowflake<User>>, guild: impl Into<Snowflake<Guild>>) {
         if let Some(handle) = self.peermap.get_mut(&user.into()) {
-            handle.guild_ids_mut().insert(guild.into());
+            let guild = guild.into();
+            handle.guild_ids.insert(guild);
+            // Guests promoted to full members may view all channels
+            handle.guest_channels.remove(&guild);
+        }
+    }
+
+    /// Registers a new guest instance to an existing connection, restricting it to a single channel of the guild
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to add to the connection
+    /// * `guild` - The guild to add the user to
+    /// * `channel` - The only channel the user may view in the guild
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (write)
+    fn add_guest(
+        &mut self,
+        user: impl Into<Snowflake<User>>,
+        guild: impl Into<Snowflake<Guild>>,
+        channel: impl Into<Snowflake<Channel>>,
+    ) {
+        if let Some(handle) = self.peermap.get_mut(&user.into()) {
+            let guild = guild.into();
+            handle.guild_ids.insert(guild);
+            handle.guest_channels.insert(guild, channel.into());
+        }
+    }
+
+    /// Registers a direct message channel to an existing connection of one of its recipients
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The recipient
+    /// * `channel` - The direct message channel
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (write)
+    fn add_recipient(&mut self, user: impl Into<Snowflake<User>>, channel: impl Into<Snowflake<Channel>>) {
+        if let Some(handle) = self.peermap.get_mut(&user.into()) {
+            handle.direct_channel_ids.insert(channel.into());
         }
     }
 
@@ -868,7 +2264,9 @@ impl GatewayActor {
     /// * `peers` (write)
     fn remove_member(&mut self, user: impl Into<Snowflake<User>>, guild: impl Into<Snowflake<Guild>>) {
         if let Some(handle) = self.peermap.get_mut(&user.into()) {
-            handle.guild_ids_mut().remove(&guild.into());
+            let guild = guild.into();
+            handle.guild_ids.remove(&guild);
+            handle.guest_channels.remove(&guild);
         }
     }
 }
@@ -877,12 +2275,24 @@ impl GatewayActor {
 ///
 /// All operations are queued to be executed on the internal gateway actor,
 /// and thus are not async, unless they require a response.
+/// If the actor is not running, operations are skipped instead of failing:
+/// the instruction is dropped and logged, and queries return an empty response.
 #[derive(Debug)]
 pub struct Gateway {
     sender: Option<mpsc::UnboundedSender<Instruction>>,
     task: Option<tokio::task::JoinHandle<()>>,
     app: Weak<ApplicationState>,
     is_bound: bool,
+    /// Instructions dropped because the gateway actor was not running
+    dropped: AtomicU64,
+    /// The sessions recently dispatched events were sent to
+    deliveries: DeliveryLog,
+    /// The most recent dispatches, if enabled
+    dispatches: DispatchLog,
+    /// Counters of the events dispatched, per event name
+    metrics: DispatchMetrics,
+    /// The sessions connected to this instance through long-polling
+    polls: PollSessions,
 }
 
 impl Gateway {
@@ -892,6 +2302,11 @@ impl Gateway {
             task: None,
             app: Weak::new(),
             is_bound: false,
+            dropped: AtomicU64::new(0),
+            deliveries: DeliveryLog::new(),
+            dispatches: DispatchLog::new(),
+            metrics: DispatchMetrics::new(),
+            polls: PollSessions::new(),
         }
     }
 
@@ -917,6 +2332,28 @@ impl Gateway {
         self.task = Some(tokio::spawn(async move {
             inner.run().await;
         }));
+
+        // Stops once the actor is gone and the instruction cannot be delivered
+        let ticker = sender.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(IDLE_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                if ticker.send(Instruction::SweepIdle).is_err() {
+                    break;
+                }
+            }
+        });
+        let ticker = sender.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TYPING_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                if ticker.send(Instruction::SweepTyping).is_err() {
+                    break;
+                }
+            }
+        });
         self.sender = Some(sender);
     }
 
@@ -950,17 +2387,71 @@ impl Gateway {
         self.sender.is_some() && self.task.is_some()
     }
 
+    /// The amount of instructions dropped because the gateway actor was not running
+    pub fn dropped_instructions(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The sessions recently dispatched events were sent to by this instance
+    pub const fn deliveries(&self) -> &DeliveryLog {
+        &self.deliveries
+    }
+
+    /// The most recent dispatches of events by this instance, empty unless `GATEWAY_DISPATCH_LOG_SIZE` is set
+    pub const fn dispatches(&self) -> &DispatchLog {
+        &self.dispatches
+    }
+
+    /// Counters of the events dispatched by this instance since it started, per event name
+    pub const fn metrics(&self) -> &DispatchMetrics {
+        &self.metrics
+    }
+
+    /// The sessions connected to this instance through long-polling
+    pub(super) const fn polls(&self) -> &PollSessions {
+        &self.polls
+    }
+
     /// Send an instruction to the inner actor
     ///
+    /// Instructions that cannot be delivered are dead-lettered: they are dropped, logged and counted
+    /// in [`Gateway::dropped_instructions`].
+    ///
     /// ## Arguments
     ///
     /// * `instruction` - The instruction to send
-    fn send_instruction(&self, instruction: Instruction) {
-        if let Some(sender) = &self.sender {
-            sender.send(instruction).expect("Failed to send instruction to gateway");
-        } else {
-            panic!("Gateway is not running");
+    ///
+    /// ## Errors
+    ///
+    /// * [`InstructionError::NotRunning`] - If the gateway was never started
+    /// * [`InstructionError::Stopped`] - If the gateway actor has stopped
+    fn send_instruction(&self, instruction: Instruction) -> Result<(), InstructionError> {
+        let kind = instruction.kind();
+
+        let result = self
+            .sender
+            .as_ref()
+            .ok_or(InstructionError::NotRunning)
+            .and_then(|sender| sender.send(instruction).map_err(|_| InstructionError::Stopped));
+
+        if let Err(e) = &result {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(instruction = kind, error = %e, "Dropping gateway instruction");
         }
+
+        result
+    }
+
+    /// Send an instruction to the inner actor, dropping it if the gateway is not running
+    ///
+    /// Used by fire-and-forget operations, which are skipped rather than failing the caller.
+    ///
+    /// ## Arguments
+    ///
+    /// * `instruction` - The instruction to send
+    fn send_or_drop(&self, instruction: Instruction) {
+        // Already logged and counted by send_instruction
+        let _ = self.send_instruction(instruction);
     }
 
     /// Get the connection receiver for the given connection ID
@@ -975,7 +2466,7 @@ impl Gateway {
     /// or `None` if the connection does not exist
     pub async fn get_conn_recv(&self, id: ConnectionId) -> Option<broadcast::Receiver<GatewayMessage>> {
         let (tx, rx) = oneshot::channel();
-        self.send_instruction(Instruction::SubscribeToSession(id, tx));
+        self.send_instruction(Instruction::SubscribeToSession(id, tx)).ok()?;
 
         match rx.await {
             Ok(Some(receiver)) => Some(receiver),
@@ -1002,7 +2493,7 @@ impl Gateway {
         user_id: Snowflake<User>,
     ) -> Option<broadcast::Receiver<(ConnectionId, GatewayMessage)>> {
         let (tx, rx) = oneshot::channel();
-        self.send_instruction(Instruction::SubscribeToUser(user_id, tx));
+        self.send_instruction(Instruction::SubscribeToUser(user_id, tx)).ok()?;
 
         match rx.await {
             Ok(Some(receiver)) => Some(receiver),
@@ -1022,23 +2513,120 @@ impl Gateway {
     ///   The first part of the ID should be the user it belongs to,
     ///   the second part should be a unique identifier of this session.
     /// * `handle` - The connection handle to add
+    /// * `presence` - The presence the user picked
     ///
     /// ## Locks
     ///
     /// * `peers` (write)
-    pub(super) fn create_session(&self, id: ConnectionId, handle: SessionHandle) {
-        self.send_instruction(Instruction::NewSession(id, handle));
+    pub(super) fn create_session(&self, id: ConnectionId, handle: SessionHandle, presence: Presence) {
+        self.send_or_drop(Instruction::NewSession(id, handle, presence));
     }
 
-    /// Removes a session with the given ID
+    /// Marks a session with the given ID as having lost its connection
     ///
-    /// Note that this does not close the connection, it only removes the session from the gateway state.
+    /// Note that this does not close the connection, the session is kept around for [`RESUME_TIMEOUT`]
+    /// to be resumed, after which it is removed from the gateway state.
     ///
     /// ## Arguments
     ///
-    /// * `id` - The ID of the session to remove
-    pub(super) fn remove_session(&self, id: ConnectionId) {
-        self.send_instruction(Instruction::RemoveSession(id));
+    /// * `id` - The ID of the session to detach
+    /// * `attachment` - The attachment of the session the lost connection belonged to
+    pub(super) fn detach_session(&self, id: ConnectionId, attachment: u64) {
+        self.send_or_drop(Instruction::DetachSession(id, attachment));
+    }
+
+    /// Resume a session through a new connection, re-sending all events the client missed after `seq`
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the session to resume
+    /// * `handle` - The handle of the new connection
+    /// * `seq` - The last sequence number the client received
+    ///
+    /// ## Returns
+    ///
+    /// The new attachment of the session, or `None` if the session cannot be resumed
+    pub(super) async fn resume_session(&self, id: ConnectionId, handle: SessionHandle, seq: u64) -> Option<u64> {
+        let (tx, rx) = oneshot::channel();
+        self.send_instruction(Instruction::ResumeSession(id, handle, seq, tx))
+            .ok()?;
+
+        match rx.await {
+            Ok(attachment) => attachment,
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to resume session");
+                None
+            }
+        }
+    }
+
+    /// Acknowledge all events sent to a session up to and including the given sequence number
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the session
+    /// * `seq` - The last sequence number acknowledged by the client
+    pub fn ack_session(&self, id: ConnectionId, seq: u64) {
+        self.send_or_drop(Instruction::AckSession(id, seq));
+    }
+
+    /// Record the round-trip time a client measured for a session, as reported through `PING`
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the session
+    /// * `latency` - The round-trip time reported by the client
+    pub fn record_latency(&self, id: ConnectionId, latency: Duration) {
+        self.send_or_drop(Instruction::RecordLatency(id, latency));
+    }
+
+    /// Record a `REQUEST_GUILD_MEMBERS` request of a session, enforcing the per-session rate limit
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the session
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the request may be served, `false` if the session is rate limited or does not exist
+    pub async fn try_acquire_member_request(&self, id: ConnectionId) -> bool {
+        let (tx, rx) = oneshot::channel();
+        if self
+            .send_instruction(Instruction::AcquireMemberRequest(id, tx))
+            .is_err()
+        {
+            return false;
+        }
+        rx.await.unwrap_or(false)
+    }
+
+    /// Record a handshake attempt, enforcing the per-user and per-IP handshake rate limits
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The user or address the handshake is attributed to
+    ///
+    /// ## Errors
+    ///
+    /// The delay the client should wait for before retrying, if it is rate limited
+    pub async fn try_acquire_identify(&self, key: IdentifyKey) -> Result<(), Duration> {
+        let (tx, rx) = oneshot::channel();
+        if self.send_instruction(Instruction::AcquireIdentify(key, tx)).is_err() {
+            return Ok(());
+        }
+        rx.await.unwrap_or(Ok(()))
+    }
+
+    /// Close the connection of a session with the given code and reason,
+    /// keeping the session itself around to be resumed
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the session to disconnect
+    /// * `code` - The close code to send
+    /// * `reason` - The reason for closing the connection
+    pub fn disconnect_session(&self, id: ConnectionId, code: GatewayCloseCode, reason: String) {
+        self.send_or_drop(Instruction::DisconnectSession(id, code, reason));
     }
 
     /// Dispatch a new event originating from the given user to all other users
@@ -1051,7 +2639,18 @@ impl Gateway {
     ///
     /// * `peers` (write)
     pub fn dispatch(&self, event: GatewayEvent, send_mode: SendMode) {
-        self.send_instruction(Instruction::Dispatch(event, send_mode));
+        self.dispatch_traced(event, send_mode, EventTrace::current());
+    }
+
+    /// Dispatch an event that was traced earlier, such as when it was written to the outbox
+    ///
+    /// ## Arguments
+    ///
+    /// * `payload` - The event payload
+    /// * `send_mode` - Who to dispatch the event to
+    /// * `trace` - The trace of the event
+    pub fn dispatch_traced(&self, event: GatewayEvent, send_mode: SendMode, trace: EventTrace) {
+        self.send_or_drop(Instruction::Dispatch(event, send_mode, trace));
     }
 
     /// Close a user session with the given code and reason
@@ -1067,7 +2666,7 @@ impl Gateway {
     ///
     /// * `peers` (write)
     pub fn close_session(&self, conn: ConnectionId, code: GatewayCloseCode, reason: String) {
-        self.send_instruction(Instruction::CloseSession(conn, code, reason));
+        self.send_or_drop(Instruction::CloseSession(conn, code, reason));
     }
 
     /// Close all user sessions with the given code and reason
@@ -1078,7 +2677,7 @@ impl Gateway {
     /// * `code` - The close code to send
     /// * `reason` - The reason for closing the connection
     pub fn close_all_user_sessions(&self, user: impl Into<Snowflake<User>>, code: GatewayCloseCode, reason: String) {
-        self.send_instruction(Instruction::CloseUser(user.into(), code, reason));
+        self.send_or_drop(Instruction::CloseUser(user.into(), code, reason));
     }
 
     /// Registers a new guild member instance to an existing connection
@@ -1092,7 +2691,42 @@ impl Gateway {
     ///
     /// * `peers` (write)
     pub fn add_member(&self, user: impl Into<Snowflake<User>>, guild: impl Into<Snowflake<Guild>>) {
-        self.send_instruction(Instruction::AddMember(user.into(), guild.into()));
+        self.send_or_drop(Instruction::AddMember(user.into(), guild.into()));
+    }
+
+    /// Registers a new guest instance to an existing connection, restricting it to a single channel of the guild
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to add to the connection
+    /// * `guild` - The guild to add the user to
+    /// * `channel` - The only channel the user may view in the guild
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (write)
+    pub fn add_guest(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        guild: impl Into<Snowflake<Guild>>,
+        channel: impl Into<Snowflake<Channel>>,
+    ) {
+        self.send_or_drop(Instruction::AddGuest(user.into(), guild.into(), channel.into()));
+    }
+
+    /// Registers a direct message channel to an existing connection of one of its recipients,
+    /// so that events about the channel are sent to it
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The recipient
+    /// * `channel` - The direct message channel
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (write)
+    pub fn add_recipient(&self, user: impl Into<Snowflake<User>>, channel: impl Into<Snowflake<Channel>>) {
+        self.send_or_drop(Instruction::AddRecipient(user.into(), channel.into()));
     }
 
     /// Removes a guild member instance from an existing connection
@@ -1106,7 +2740,7 @@ impl Gateway {
     ///
     /// * `peers` (write)
     pub fn remove_member(&self, user: impl Into<Snowflake<User>>, guild: impl Into<Snowflake<Guild>>) {
-        self.send_instruction(Instruction::RemoveMember(user.into(), guild.into()));
+        self.send_or_drop(Instruction::RemoveMember(user.into(), guild.into()));
     }
 
     /// Send an event to a specific user. If they are not connected, the event is dropped.
@@ -1120,7 +2754,7 @@ impl Gateway {
     ///
     /// * `peers` (write)
     pub fn send_to(&self, user: impl Into<Snowflake<User>>, event: GatewayEvent) {
-        self.send_instruction(Instruction::SendTo(user.into(), event));
+        self.send_or_drop(Instruction::SendTo(user.into(), event, EventTrace::current()));
     }
 
     /// Send an event to a specific session. If the session is not connected, the event is dropped.
@@ -1130,7 +2764,149 @@ impl Gateway {
     /// * `id` - The ID of the session to send the event to
     /// * `event` - The event to send
     pub fn send_to_session(&self, id: ConnectionId, event: GatewayEvent) {
-        self.send_instruction(Instruction::SendToSession(id, event));
+        self.send_or_drop(Instruction::SendToSession(id, event, EventTrace::current()));
+    }
+
+    /// Record activity on a session, such as the client acknowledging events.
+    /// Users who were away because of inactivity are marked as online again.
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The session that was active
+    pub fn record_activity(&self, id: ConnectionId) {
+        self.send_or_drop(Instruction::RecordActivity(id.0, Some(id.1)));
+    }
+
+    /// Record activity of a user that cannot be attributed to a session, such as sending a message over REST.
+    /// Users who were away because of inactivity are marked as online again.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user that was active
+    pub fn record_user_activity(&self, user: impl Into<Snowflake<User>>) {
+        self.send_or_drop(Instruction::RecordActivity(user.into(), None));
+    }
+
+    /// Replace the words a connected user muted.
+    /// New messages containing any of them are marked as muted in the `MESSAGE_CREATE` sent to the user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user that changed their muted words
+    /// * `muted_words` - The compiled muted words, `None` if the user did not mute any
+    pub fn set_muted_words(&self, user: impl Into<Snowflake<User>>, muted_words: Option<Arc<KeywordMatcher>>) {
+        self.send_or_drop(Instruction::SetMutedWords(user.into(), muted_words));
+    }
+
+    /// Replace the users a connected user's presence is shown to.
+    /// Users who gained or lost sight of the presence receive a `PRESENCE_UPDATE`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user whose audience changed
+    /// * `audience` - The new audience, see [`UserOps::fetch_presence_audience`](crate::app::ops::UserOps::fetch_presence_audience)
+    pub fn set_presence_audience(&self, user: impl Into<Snowflake<User>>, audience: PresenceAudience) {
+        self.send_or_drop(Instruction::SetPresenceAudience(user.into(), audience));
+    }
+
+    /// Update the presence a connected user picked. This does not dispatch a `PRESENCE_UPDATE`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user that changed their presence
+    /// * `presence` - The new presence
+    pub fn set_presence(&self, user: impl Into<Snowflake<User>>, presence: Presence) {
+        self.send_or_drop(Instruction::SetPresence(user.into(), presence));
+    }
+
+    /// Returns the presence shown for the given user to another user
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to get the presence of
+    /// * `viewer` - The user the presence is shown to
+    ///
+    /// ## Returns
+    ///
+    /// The presence shown to the viewer, or `None` if the user is not connected or does not share it with them
+    pub async fn presence_of(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        viewer: impl Into<Snowflake<User>>,
+    ) -> Option<Presence> {
+        let (tx, rx) = oneshot::channel();
+        self.send_instruction(Instruction::QueryPresence(user.into(), viewer.into(), tx))
+            .ok()?;
+
+        rx.await.unwrap_or_else(|e| {
+            tracing::error!(error = %e, "Failed to query presence");
+            None
+        })
+    }
+
+    /// Returns information about all sessions of the given user
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to get the sessions of
+    ///
+    /// ## Returns
+    ///
+    /// The user's sessions, including those waiting to be resumed
+    pub async fn sessions_of(&self, user: impl Into<Snowflake<User>>) -> Vec<SessionInfo> {
+        let (tx, rx) = oneshot::channel();
+        if self
+            .send_instruction(Instruction::QuerySessions(user.into(), tx))
+            .is_err()
+        {
+            return Vec::new();
+        }
+        rx.await.unwrap_or_else(|e| {
+            tracing::error!(error = %e, "Failed to query sessions");
+            Vec::new()
+        })
+    }
+
+    /// Returns the users typing in each channel the given user can view
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user viewing the channels
+    ///
+    /// ## Returns
+    ///
+    /// The users typing in each channel, empty if the user is not connected
+    pub async fn typing_visible_to(&self, user: impl Into<Snowflake<User>>) -> Vec<TypingEntry> {
+        let (tx, rx) = oneshot::channel();
+        if self
+            .send_instruction(Instruction::QueryTyping(user.into(), tx))
+            .is_err()
+        {
+            return Vec::new();
+        }
+        rx.await.unwrap_or_else(|e| {
+            tracing::error!(error = %e, "Failed to query typing users");
+            Vec::new()
+        })
+    }
+
+    /// Returns the users typing in the given channel
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The channel to check
+    pub async fn typing_in(&self, channel: impl Into<Snowflake<Channel>>) -> Vec<Snowflake<User>> {
+        let (tx, rx) = oneshot::channel();
+        if self
+            .send_instruction(Instruction::QueryChannelTyping(channel.into(), tx))
+            .is_err()
+        {
+            return Vec::new();
+        }
+        rx.await.unwrap_or_else(|e| {
+            tracing::error!(error = %e, "Failed to query typing users");
+            Vec::new()
+        })
     }
 
     /// Returns whether the given user is connected
@@ -1144,8 +2920,16 @@ impl Gateway {
     /// `true` if the user is connected, `false` otherwise
     pub async fn is_connected(&self, user: impl Into<Snowflake<User>>) -> bool {
         let (tx, rx) = oneshot::channel();
-        self.send_instruction(Instruction::QueryConnectedStatus(user.into(), tx));
-        rx.await.expect("Failed to query connection status")
+        if self
+            .send_instruction(Instruction::QueryConnectedStatus(user.into(), tx))
+            .is_err()
+        {
+            return false;
+        }
+        rx.await.unwrap_or_else(|e| {
+            tracing::error!(error = %e, "Failed to query connection status");
+            false
+        })
     }
 
     /// Returns a set of users that are connected
@@ -1163,8 +2947,16 @@ impl Gateway {
         }
 
         let (tx, rx) = oneshot::channel();
-        self.send_instruction(Instruction::QueryMultiConnectedStatus(users, tx));
-        rx.await.expect("Failed to query connection status")
+        if self
+            .send_instruction(Instruction::QueryMultiConnectedStatus(users, tx))
+            .is_err()
+        {
+            return HashSet::new();
+        }
+        rx.await.unwrap_or_else(|e| {
+            tracing::error!(error = %e, "Failed to query connection status");
+            HashSet::new()
+        })
     }
 }
 
@@ -1173,3 +2965,628 @@ impl Default for Gateway {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gateway::typing::TYPING_TIMEOUT,
+        models::{
+            member::{Member, UserLike},
+            message::Message,
+        },
+    };
+
+    #[test]
+    fn test_sequenced_event_serialization() {
+        let event = SequencedEvent::new(Arc::new(GatewayEvent::Resumed), None);
+        assert_eq!(
+            serde_json::to_value(&event).expect("event should serialize"),
+            serde_json::json!({ "event": "RESUMED" })
+        );
+
+        let event = SequencedEvent::new(
+            Arc::new(GatewayEvent::TypingStart {
+                user_id: Snowflake::new(1),
+                channel_id: Snowflake::new(2),
+            }),
+            Some(5),
+        );
+        assert_eq!(
+            serde_json::to_value(&event).expect("event should serialize"),
+            serde_json::json!({ "event": "TYPING_START", "data": { "user_id": "1", "channel_id": "2" }, "seq": 5 })
+        );
+    }
+
+    fn user_handle(presence: Presence) -> (UserHandle, Uuid) {
+        let (sender, _) = mpsc::unbounded_channel();
+        let (receiver, _) = broadcast::channel(1);
+        let mut handle = UserHandle::new(Snowflake::new(1), HashSet::new(), HashMap::new(), presence);
+        let id = handle.add_session(Uuid::new_v4(), SessionHandle::new(sender, Arc::new(receiver)));
+        (handle, id)
+    }
+
+    #[tokio::test]
+    async fn test_idle_user_is_away() {
+        let (mut handle, id) = user_handle(Presence::Online);
+
+        assert_eq!(handle.mark_idle_if_inactive(Duration::from_secs(60)), None);
+        assert_eq!(handle.displayed_presence(), Presence::Online);
+
+        assert_eq!(handle.mark_idle_if_inactive(Duration::ZERO), Some(Presence::Away));
+        assert_eq!(handle.displayed_presence(), Presence::Away);
+        // Already away, nothing to dispatch
+        assert_eq!(handle.mark_idle_if_inactive(Duration::ZERO), None);
+
+        assert_eq!(handle.mark_active(Some(id)), Some(Presence::Online));
+        assert_eq!(handle.mark_active(Some(id)), None);
+        assert_eq!(handle.displayed_presence(), Presence::Online);
+    }
+
+    #[tokio::test]
+    async fn test_idle_keeps_picked_presence() {
+        let (mut handle, _) = user_handle(Presence::Busy);
+
+        assert_eq!(handle.mark_idle_if_inactive(Duration::ZERO), None);
+        assert_eq!(handle.displayed_presence(), Presence::Busy);
+
+        // Picking online while idle counts as activity
+        handle.set_presence(Presence::Online);
+        assert_eq!(handle.displayed_presence(), Presence::Online);
+    }
+
+    #[tokio::test]
+    async fn test_instructions_dropped_when_not_running() {
+        let gateway = Gateway::new();
+        let user = Snowflake::new(1);
+
+        gateway.dispatch(GatewayEvent::Resumed, SendMode::ToUser(user));
+        gateway.send_to(user, GatewayEvent::Resumed);
+        assert!(!gateway.is_connected(user).await);
+        assert!(gateway.get_user_recv(user).await.is_none());
+        assert_eq!(
+            gateway.send_instruction(Instruction::AddMember(user, Snowflake::new(2))),
+            Err(InstructionError::NotRunning)
+        );
+        assert_eq!(gateway.dropped_instructions(), 5);
+    }
+
+    #[test]
+    fn test_instruction_priority() {
+        let typing = GatewayEvent::TypingStart {
+            user_id: Snowflake::new(1),
+            channel_id: Snowflake::new(2),
+        };
+        assert_eq!(
+            Instruction::Dispatch(typing, SendMode::ToUser(Snowflake::new(1)), EventTrace::new(None)).priority(),
+            Priority::Ambient
+        );
+        assert_eq!(
+            Instruction::SendTo(Snowflake::new(1), GatewayEvent::Resumed, EventTrace::new(None)).priority(),
+            Priority::Messages
+        );
+        assert_eq!(
+            Instruction::RemoveMember(Snowflake::new(1), Snowflake::new(2)).priority(),
+            Priority::Messages
+        );
+        assert_eq!(
+            Instruction::AckSession(ConnectionId(Snowflake::new(1), Uuid::new_v4()), 1).priority(),
+            Priority::Control
+        );
+        let pong = GatewayEvent::Pong {
+            nonce: "1".into(),
+            server_time: 0,
+        };
+        assert_eq!(
+            Instruction::SendToSession(
+                ConnectionId(Snowflake::new(1), Uuid::new_v4()),
+                pong,
+                EventTrace::new(None)
+            )
+            .priority(),
+            Priority::Control
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_info() {
+        let (mut handle, id) = user_handle(Presence::Online);
+        let session = handle.get_handle_mut(id).expect("session should exist");
+
+        assert_eq!(
+            session.info(id),
+            SessionInfo {
+                session_id: id,
+                connected: true,
+                latency: None,
+            }
+        );
+
+        session.record_latency(Duration::from_millis(42));
+        session.detach();
+        assert_eq!(
+            session.info(id),
+            SessionInfo {
+                session_id: id,
+                connected: false,
+                latency: Some(42),
+            }
+        );
+    }
+
+    /// Dispatch events to a large guild of synthetic sessions, interleaved with events sent to a single user,
+    /// and check that every session receives all of its events exactly once and in order.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fanout_to_large_guild() {
+        const SESSIONS: i64 = 50_000;
+        const EVENTS: u64 = 10;
+
+        let (_instructions, instruction_rx) = mpsc::unbounded_channel();
+        let mut actor = GatewayActor::new(Weak::new(), instruction_rx);
+        let guild: Snowflake<Guild> = Snowflake::new(1);
+        let mut receivers = Vec::new();
+
+        for user in 1..=SESSIONS {
+            let user_id: Snowflake<User> = Snowflake::new(user);
+            let id = Uuid::new_v4();
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let mut session = SessionHandle::new(sender, Arc::new(broadcast::channel(1).0));
+            session.set_lane(actor.fanout.lane_of(ConnectionId(user_id, id)));
+
+            let mut handle = UserHandle::new(user_id, HashSet::from([guild]), HashMap::new(), Presence::Online);
+            handle.add_session(id, session);
+            actor.peermap.insert(user_id, handle);
+            receivers.push(receiver);
+        }
+
+        let typing = || GatewayEvent::TypingStart {
+            user_id: Snowflake::new(1),
+            channel_id: Snowflake::new(2),
+        };
+        for _ in 0..EVENTS {
+            actor.dispatch(typing(), SendMode::ToGuild(guild), EventTrace::new(None));
+            actor.send_to(Snowflake::new(1), typing(), EventTrace::new(None));
+        }
+
+        for (index, mut receiver) in receivers.into_iter().enumerate() {
+            // The first user also received every event sent to them directly
+            let expected = if index == 0 { EVENTS * 2 } else { EVENTS };
+
+            for seq in 1..=expected {
+                let response = tokio::time::timeout(Duration::from_secs(10), receiver.recv())
+                    .await
+                    .expect("event should be delivered in time");
+                let actual = match response {
+                    Some(GatewayResponse::Prepared(_, seq) | GatewayResponse::Event(SequencedEvent { seq, .. })) => seq,
+                    other => panic!("Expected an event, got {other:?}"),
+                };
+                assert_eq!(actual, Some(seq));
+            }
+            assert!(receiver.try_recv().is_err(), "no events should be delivered twice");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_marks_muted_messages() {
+        let (_instructions, instruction_rx) = mpsc::unbounded_channel();
+        let mut actor = GatewayActor::new(Weak::new(), instruction_rx);
+        let guild: Snowflake<Guild> = Snowflake::new(1);
+        let mut receivers = Vec::new();
+
+        for (user, muted_words) in [(1, vec![]), (2, vec!["spoiler".to_owned()])] {
+            let user_id: Snowflake<User> = Snowflake::new(user);
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let mut handle = UserHandle::new(user_id, HashSet::from([guild]), HashMap::new(), Presence::Online);
+            handle.set_muted_words(muted_word_matcher(muted_words));
+            handle.add_session(
+                Uuid::new_v4(),
+                SessionHandle::new(sender, Arc::new(broadcast::channel(1).0)),
+            );
+            actor.peermap.insert(user_id, handle);
+            receivers.push(receiver);
+        }
+
+        for content in ["Huge SPOILER ahead", "Nothing to see here"] {
+            let message = Message::builder()
+                .id(Snowflake::new(3))
+                .channel_id(Snowflake::new(2))
+                .content(Some(content.to_owned()))
+                .build()
+                .expect("Should successfully build a test message");
+            actor.dispatch(
+                GatewayEvent::MessageCreate(message),
+                SendMode::ToGuild(guild),
+                EventTrace::new(None),
+            );
+        }
+
+        let mut muted_flags = |user: usize| {
+            (0..2)
+                .map(|_| match receivers[user].try_recv() {
+                    Ok(GatewayResponse::Prepared(event, seq)) => {
+                        let payload: serde_json::Value =
+                            serde_json::from_str(&event.to_text(seq)).expect("event should be valid JSON");
+                        payload["data"]["muted"].as_bool().unwrap_or(false)
+                    }
+                    other => panic!("Expected a prepared event, got {other:?}"),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(muted_flags(0), vec![false, false]);
+        assert_eq!(muted_flags(1), vec![true, false]);
+    }
+
+    #[test]
+    fn test_member_request_rate_limit() {
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let mut handle = SessionHandle::new(sender, Arc::new(broadcast::channel(1).0));
+
+        for _ in 0..MEMBER_REQUEST_LIMIT {
+            assert!(handle.try_acquire_member_request());
+        }
+        assert!(!handle.try_acquire_member_request());
+
+        // Requests older than the window no longer count towards the limit
+        handle.member_requests[0] -= MEMBER_REQUEST_WINDOW;
+        assert!(handle.try_acquire_member_request());
+        assert!(!handle.try_acquire_member_request());
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_respects_capabilities() {
+        let (_instructions, instruction_rx) = mpsc::unbounded_channel();
+        let mut actor = GatewayActor::new(Weak::new(), instruction_rx);
+        let guild: Snowflake<Guild> = Snowflake::new(1);
+        let mut receivers = Vec::new();
+
+        for (user, capabilities) in [(1, ClientCapability::all()), (2, ClientCapability::empty())] {
+            let user_id: Snowflake<User> = Snowflake::new(user);
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let mut handle = UserHandle::new(user_id, HashSet::from([guild]), HashMap::new(), Presence::Online);
+            handle.add_session(
+                Uuid::new_v4(),
+                SessionHandle::new(sender, Arc::new(broadcast::channel(1).0)).with_capabilities(capabilities),
+            );
+            actor.peermap.insert(user_id, handle);
+            receivers.push(receiver);
+        }
+
+        let user = User::builder()
+            .id(Snowflake::new(3))
+            .username("testuser")
+            .build()
+            .expect("Should successfully build a test user");
+        let member_create = || {
+            GatewayEvent::MemberCreate(Member::new(
+                user.to_private(),
+                guild,
+                None,
+                chrono::DateTime::UNIX_EPOCH,
+            ))
+        };
+        let events = [
+            GatewayEvent::TypingStart {
+                user_id: Snowflake::new(3),
+                channel_id: Snowflake::new(2),
+            },
+            GatewayEvent::PresenceUpdate {
+                user_id: Snowflake::new(3),
+                presence: Presence::Online,
+            },
+            member_create(),
+        ];
+        for event in events {
+            actor.dispatch(event, SendMode::ToGuild(guild), EventTrace::new(None));
+        }
+        actor.send_to(Snowflake::new(2), member_create(), EventTrace::new(None));
+
+        let mut received = |user: usize| {
+            std::iter::from_fn(|| receivers[user].try_recv().ok())
+                .map(|response| {
+                    let text = match response {
+                        GatewayResponse::Prepared(event, seq) => event.to_text(seq),
+                        GatewayResponse::Event(event) => serde_json::to_string(&event).expect("event should serialize"),
+                        other @ GatewayResponse::Close(..) => panic!("Expected an event, got {other:?}"),
+                    };
+                    serde_json::from_str::<serde_json::Value>(&text).expect("event should be valid JSON")
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let full = received(0);
+        assert_eq!(full.len(), 3);
+        assert_eq!(full[2]["data"]["user"]["presence"], "ONLINE");
+
+        // Typing and presence updates are skipped, members are sent without presences
+        let trimmed = received(1);
+        assert_eq!(trimmed.len(), 2);
+        for (seq, event) in (1..).zip(&trimmed) {
+            assert_eq!(event["event"], "MEMBER_CREATE");
+            assert_eq!(event["seq"], seq);
+            assert!(event["data"]["user"].get("presence").is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_typing_expires() {
+        let (_instructions, instruction_rx) = mpsc::unbounded_channel();
+        let mut actor = GatewayActor::new(Weak::new(), instruction_rx);
+        let guild: Snowflake<Guild> = Snowflake::new(1);
+        let channel: Snowflake<Channel> = Snowflake::new(2);
+        let member: Snowflake<User> = Snowflake::new(1);
+        let guest: Snowflake<User> = Snowflake::new(2);
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut handle = UserHandle::new(member, HashSet::from([guild]), HashMap::new(), Presence::Online);
+        handle.add_session(
+            Uuid::new_v4(),
+            SessionHandle::new(sender, Arc::new(broadcast::channel(1).0)).with_capabilities(ClientCapability::all()),
+        );
+        actor.peermap.insert(member, handle);
+        // A guest of another channel in the same guild cannot see who is typing
+        let handle = UserHandle::new(
+            guest,
+            HashSet::from([guild]),
+            HashMap::from([(guild, Snowflake::new(9))]),
+            Presence::Online,
+        );
+        actor.peermap.insert(guest, handle);
+
+        actor.dispatch(
+            GatewayEvent::TypingStart {
+                user_id: Snowflake::new(3),
+                channel_id: channel,
+            },
+            SendMode::ToGuild(guild),
+            EventTrace::new(None),
+        );
+
+        let entries = actor.typing_visible_to(member);
+        assert_eq!(
+            entries,
+            vec![TypingEntry {
+                channel_id: channel,
+                user_ids: vec![Snowflake::new(3)],
+            }]
+        );
+        assert!(actor.typing_visible_to(guest).is_empty());
+        assert!(actor.typing_visible_to(Snowflake::new(4)).is_empty());
+
+        actor.sweep_typing(Instant::now());
+        assert_eq!(actor.typing.typing_in(channel), vec![Snowflake::new(3)]);
+        actor.sweep_typing(Instant::now() + TYPING_TIMEOUT);
+        assert!(actor.typing.typing_in(channel).is_empty());
+
+        let events = std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|response| match response {
+                GatewayResponse::Prepared(event, seq) => event.to_text(seq),
+                other => panic!("Expected a prepared event, got {other:?}"),
+            })
+            .map(|text| serde_json::from_str::<serde_json::Value>(&text).expect("event should be valid JSON"))
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["event"], "TYPING_START");
+        assert_eq!(events[1]["event"], "TYPING_STOP");
+        assert_eq!(events[1]["data"]["user_id"], "3");
+        assert_eq!(events[1]["data"]["channel_id"], "2");
+    }
+
+    #[tokio::test]
+    async fn test_message_stops_typing() {
+        let (_instructions, instruction_rx) = mpsc::unbounded_channel();
+        let mut actor = GatewayActor::new(Weak::new(), instruction_rx);
+        let channel: Snowflake<Channel> = Snowflake::new(2);
+        let user = User::builder()
+            .id(Snowflake::new(3))
+            .username("testuser")
+            .build()
+            .expect("Should successfully build a test user");
+
+        actor.dispatch(
+            GatewayEvent::TypingStart {
+                user_id: user.id(),
+                channel_id: channel,
+            },
+            SendMode::ToChannelRecipients(channel),
+            EventTrace::new(None),
+        );
+        assert_eq!(actor.typing.typing_in(channel), vec![user.id()]);
+
+        let message = Message::builder()
+            .id(Snowflake::new(4))
+            .channel_id(channel)
+            .author(UserLike::User(user))
+            .content(Some("hello".into()))
+            .build()
+            .expect("Should successfully build a test message");
+        actor.dispatch(
+            GatewayEvent::MessageCreate(message),
+            SendMode::ToChannelRecipients(channel),
+            EventTrace::new(None),
+        );
+        assert!(actor.typing.typing_in(channel).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_typing_queued_before_message() {
+        let (instructions, instruction_rx) = mpsc::unbounded_channel();
+        let mut actor = GatewayActor::new(Weak::new(), instruction_rx);
+        let guild: Snowflake<Guild> = Snowflake::new(1);
+        let channel: Snowflake<Channel> = Snowflake::new(2);
+        let user = User::builder()
+            .id(Snowflake::new(3))
+            .username("testuser")
+            .build()
+            .expect("Should successfully build a test user");
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut handle = UserHandle::new(
+            Snowflake::new(1),
+            HashSet::from([guild]),
+            HashMap::new(),
+            Presence::Online,
+        );
+        handle.add_session(
+            Uuid::new_v4(),
+            SessionHandle::new(sender, Arc::new(broadcast::channel(1).0)).with_capabilities(ClientCapability::all()),
+        );
+        actor.peermap.insert(Snowflake::new(1), handle);
+
+        // The user starts typing, then sends a message before the typing indicator was dispatched
+        let typing = GatewayEvent::TypingStart {
+            user_id: user.id(),
+            channel_id: channel,
+        };
+        let message = Message::builder()
+            .id(Snowflake::new(4))
+            .channel_id(channel)
+            .author(UserLike::User(user))
+            .content(Some("hello".into()))
+            .build()
+            .expect("Should successfully build a test message");
+        for event in [typing, GatewayEvent::MessageCreate(message)] {
+            instructions
+                .send(Instruction::Dispatch(
+                    event,
+                    SendMode::ToGuild(guild),
+                    EventTrace::new(None),
+                ))
+                .expect("Actor should be receiving");
+        }
+
+        // The message takes priority over the typing indicator, which is then outdated
+        for expected in ["MessageCreate", "TypingStart"] {
+            let Some(Instruction::Dispatch(event, send_mode, trace)) = actor.next_instruction().await else {
+                panic!("Expected a dispatch");
+            };
+            assert_eq!(format!("{event:?}").split([' ', '(']).next(), Some(expected));
+            actor.dispatch(event, send_mode, trace);
+        }
+        assert!(actor.typing.typing_in(channel).is_empty());
+
+        let events = std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|response| match response {
+                GatewayResponse::Prepared(event, seq) => event.to_text(seq),
+                other => panic!("Expected a prepared event, got {other:?}"),
+            })
+            .map(|text| serde_json::from_str::<serde_json::Value>(&text).expect("event should be valid JSON"))
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["event"], "MESSAGE_CREATE");
+    }
+
+    #[tokio::test]
+    async fn test_presence_audience() {
+        let friend: Snowflake<User> = Snowflake::new(2);
+        let stranger: Snowflake<User> = Snowflake::new(3);
+
+        let everyone = PresenceAudience::new(PresenceSharing::Everyone, [friend]);
+        assert!(everyone.includes(friend) && everyone.includes(stranger));
+
+        let friends = PresenceAudience::new(PresenceSharing::Friends, [friend]);
+        assert!(friends.includes(friend) && !friends.includes(stranger));
+
+        let nobody = PresenceAudience::new(PresenceSharing::Nobody, [friend]);
+        assert!(!nobody.includes(friend) && !nobody.includes(stranger));
+
+        // Users always see their own presence
+        let (mut handle, _) = user_handle(Presence::Online);
+        handle.set_presence_audience(nobody);
+        assert!(handle.shares_presence_with(Snowflake::new(1)));
+        assert!(!handle.shares_presence_with(friend));
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_respects_presence_audience() {
+        let (_instructions, instruction_rx) = mpsc::unbounded_channel();
+        let mut actor = GatewayActor::new(Weak::new(), instruction_rx);
+        let guild: Snowflake<Guild> = Snowflake::new(1);
+        let subject: Snowflake<User> = Snowflake::new(3);
+        let mut receivers = Vec::new();
+
+        for user in [1, 2, 3] {
+            let user_id: Snowflake<User> = Snowflake::new(user);
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let mut handle = UserHandle::new(user_id, HashSet::from([guild]), HashMap::new(), Presence::Online);
+            handle.add_session(
+                Uuid::new_v4(),
+                SessionHandle::new(sender, Arc::new(broadcast::channel(1).0)),
+            );
+            actor.peermap.insert(user_id, handle);
+            receivers.push(receiver);
+        }
+        actor.set_presence_audience(
+            subject,
+            PresenceAudience::new(PresenceSharing::Friends, [Snowflake::new(1)]),
+        );
+
+        let mut received = |user: usize| {
+            std::iter::from_fn(|| receivers[user].try_recv().ok())
+                .map(|response| {
+                    let text = match response {
+                        GatewayResponse::Prepared(event, seq) => event.to_text(seq),
+                        GatewayResponse::Event(event) => serde_json::to_string(&event).expect("event should serialize"),
+                        other @ GatewayResponse::Close(..) => panic!("Expected an event, got {other:?}"),
+                    };
+                    serde_json::from_str::<serde_json::Value>(&text).expect("event should be valid JSON")["data"]
+                        ["presence"]
+                        .clone()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The stranger is told the user went offline when they stop sharing with them
+        assert!(received(0).is_empty());
+        assert_eq!(received(1), vec!["OFFLINE"]);
+
+        actor.dispatch(
+            GatewayEvent::PresenceUpdate {
+                user_id: subject,
+                presence: Presence::Busy,
+            },
+            SendMode::ToGuild(guild),
+            EventTrace::new(None),
+        );
+        assert_eq!(received(0), vec!["BUSY"]);
+        assert!(received(1).is_empty());
+        assert_eq!(received(2), vec!["BUSY"]);
+
+        actor.set_presence_audience(subject, PresenceAudience::Everyone);
+        assert!(received(0).is_empty());
+        assert_eq!(received(1), vec!["ONLINE"]);
+    }
+
+    #[tokio::test]
+    async fn test_inbound_overflow_is_dropped() {
+        let (sender, mut responses) = mpsc::unbounded_channel();
+        let (requests, _) = broadcast::channel(64);
+        let requests = Arc::new(requests);
+        let mut handle = UserHandle::new(Snowflake::new(1), HashSet::new(), HashMap::new(), Presence::Online)
+            .with_inbound_capacity(1);
+        // Never handled, so that the first request fills the forwarder
+        let _inbound = handle.subscribe();
+        handle.add_session(Uuid::new_v4(), SessionHandle::new(sender, requests.clone()));
+
+        for _ in 0..=INBOUND_DROP_LIMIT {
+            requests
+                .send(GatewayMessage::Heartbeat)
+                .expect("the forwarder should be subscribed");
+        }
+
+        for expected in 1..INBOUND_DROP_LIMIT {
+            match responses.recv().await {
+                Some(GatewayResponse::Event(event)) => {
+                    let payload = serde_json::to_value(&event).expect("event should serialize");
+                    assert_eq!(payload["event"], "REQUEST_DROPPED");
+                    assert_eq!(payload["data"]["total"], expected);
+                    assert!(payload.get("seq").is_none());
+                }
+                other => panic!("Expected a REQUEST_DROPPED event, got {other:?}"),
+            }
+        }
+        assert!(matches!(
+            responses.recv().await,
+            Some(GatewayResponse::Close(GatewayCloseCode::RateLimited, _))
+        ));
+    }
+}
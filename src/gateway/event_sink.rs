@@ -0,0 +1,36 @@
+use std::fmt::Debug;
+
+use super::actor::{Gateway, SendMode};
+use crate::models::{gateway_event::GatewayEvent, snowflake::Snowflake, user::User};
+
+/// A destination for the gateway events emitted while handling requests.
+///
+/// This is the [`Gateway`] itself in production, but can be replaced when constructing the application
+/// to record emitted events instead, such as in tests that should not depend on the gateway actor.
+pub trait EventSink: Debug + Send + Sync {
+    /// Dispatch an event to all users covered by the send mode
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The event to dispatch
+    /// * `send_mode` - Who to dispatch the event to
+    fn dispatch(&self, event: GatewayEvent, send_mode: SendMode);
+
+    /// Send an event to a specific user
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to send the event to
+    /// * `event` - The event to send
+    fn send_to(&self, user: Snowflake<User>, event: GatewayEvent);
+}
+
+impl EventSink for Gateway {
+    fn dispatch(&self, event: GatewayEvent, send_mode: SendMode) {
+        Self::dispatch(self, event, send_mode);
+    }
+
+    fn send_to(&self, user: Snowflake<User>, event: GatewayEvent) {
+        Self::send_to(self, user, event);
+    }
+}
@@ -0,0 +1,297 @@
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+
+use super::{
+    actor::{ConnectionId, GatewayResponse},
+    trace::EventTrace,
+};
+use crate::models::gateway_event::GatewayEvent;
+
+/// The number of workers delivering events to sessions on behalf of the gateway actor
+pub const FANOUT_WORKERS: usize = 4;
+
+/// An event serialized once, to be sent to any number of sessions
+///
+/// Sessions number their events separately, so the sequence number is only added when the event is sent.
+#[derive(Debug, Clone)]
+pub(super) struct PreparedEvent {
+    payload: Arc<str>,
+    trace: EventTrace,
+}
+
+impl PreparedEvent {
+    /// Serialize an event to be sent to many sessions
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The event to serialize
+    /// * `trace` - The trace of the event, logged when it is sent
+    pub fn new(event: &GatewayEvent, trace: EventTrace) -> Self {
+        let payload = serde_json::to_string(event).expect("Expected GatewayEvent to not fail serialization");
+        Self {
+            payload: payload.into(),
+            trace,
+        }
+    }
+
+    /// The size of the serialized event in bytes, not counting the sequence number
+    pub fn len(&self) -> usize {
+        self.payload.len()
+    }
+
+    /// The trace of the event
+    pub const fn trace(&self) -> EventTrace {
+        self.trace
+    }
+
+    /// The payload sent to a session, including the sequence number the session assigned to the event, if any
+    ///
+    /// ## Arguments
+    ///
+    /// * `seq` - The sequence number of the event in the session
+    pub fn to_text(&self, seq: Option<u64>) -> String {
+        match (seq, self.payload.strip_suffix('}')) {
+            (Some(seq), Some(fields)) => format!("{fields},\"seq\":{seq}}}"),
+            _ => self.payload.to_string(),
+        }
+    }
+}
+
+/// A response to deliver to the connection of a single session
+#[derive(Debug)]
+pub(super) struct Delivery {
+    /// The session the response is for
+    conn: ConnectionId,
+    /// The attachment of the session when the response was queued
+    attachment: u64,
+    /// The sender of the session's connection, at the time the response was queued
+    sender: mpsc::UnboundedSender<GatewayResponse>,
+    response: GatewayResponse,
+}
+
+impl Delivery {
+    pub const fn new(
+        conn: ConnectionId,
+        attachment: u64,
+        sender: mpsc::UnboundedSender<GatewayResponse>,
+        response: GatewayResponse,
+    ) -> Self {
+        Self {
+            conn,
+            attachment,
+            sender,
+            response,
+        }
+    }
+
+    /// Take the response back out of the delivery
+    pub fn into_response(self) -> GatewayResponse {
+        self.response
+    }
+}
+
+/// The worker all responses to a session are delivered through
+///
+/// Each worker processes its deliveries in order, so that a session receives its responses
+/// in the order they were queued, no matter if they were part of a batch or not.
+#[derive(Debug, Clone)]
+pub(super) struct FanoutLane {
+    index: usize,
+    sender: mpsc::UnboundedSender<Vec<Delivery>>,
+}
+
+impl FanoutLane {
+    /// Queue a single delivery
+    ///
+    /// ## Errors
+    ///
+    /// Returns the delivery if the worker is no longer running
+    pub fn submit(&self, delivery: Delivery) -> Result<(), Box<Delivery>> {
+        self.sender.send(vec![delivery]).map_err(|e| {
+            Box::new(
+                e.0.into_iter()
+                    .next()
+                    .expect("A single delivery was sent to the worker"),
+            )
+        })
+    }
+}
+
+/// Deliveries queued while dispatching a single event, grouped by the lane they are delivered through
+#[derive(Debug)]
+pub(super) struct FanoutBatch(Vec<Vec<Delivery>>);
+
+impl FanoutBatch {
+    /// Queue a delivery through the given lane
+    ///
+    /// ## Arguments
+    ///
+    /// * `lane` - The lane of the session the delivery is for
+    /// * `delivery` - The delivery to queue
+    pub fn push(&mut self, lane: &FanoutLane, delivery: Delivery) {
+        self.0[lane.index].push(delivery);
+    }
+}
+
+/// A small pool of workers that deliver events to sessions, so that sending an event to a large guild
+/// does not hold up the gateway actor.
+///
+/// The actor assigns sequence numbers and retains events for replay, then hands the deliveries to the pool.
+/// Sessions are pinned to a lane by their ID, which keeps their responses in order.
+#[derive(Debug)]
+pub(super) struct FanoutPool {
+    lanes: Vec<FanoutLane>,
+}
+
+impl FanoutPool {
+    /// Start a pool with the given amount of workers
+    ///
+    /// ## Arguments
+    ///
+    /// * `workers` - The amount of workers to start, at least one is always started
+    /// * `on_failure` - Called with the session and its attachment when a response could not be delivered,
+    ///   because its connection was closed
+    pub fn new(workers: usize, on_failure: impl Fn(ConnectionId, u64) + Send + Sync + 'static) -> Self {
+        let on_failure = Arc::new(on_failure);
+
+        let lanes = (0..workers.max(1))
+            .map(|index| {
+                let (sender, mut receiver) = mpsc::unbounded_channel::<Vec<Delivery>>();
+                let on_failure = on_failure.clone();
+
+                // Stops once the pool and all sessions holding its lanes are gone
+                tokio::spawn(async move {
+                    while let Some(batch) = receiver.recv().await {
+                        for delivery in batch {
+                            if delivery.sender.send(delivery.response).is_err() {
+                                on_failure(delivery.conn, delivery.attachment);
+                            }
+                        }
+                    }
+                });
+
+                FanoutLane { index, sender }
+            })
+            .collect();
+
+        Self { lanes }
+    }
+
+    /// The lane responses to the given session are delivered through
+    ///
+    /// ## Arguments
+    ///
+    /// * `conn` - The ID of the session
+    pub fn lane_of(&self, conn: ConnectionId) -> FanoutLane {
+        let index = conn.1.as_u128() % self.lanes.len() as u128;
+        self.lanes[index as usize].clone()
+    }
+
+    /// Start a new, empty batch of deliveries
+    pub fn batch(&self) -> FanoutBatch {
+        FanoutBatch(self.lanes.iter().map(|_| Vec::new()).collect())
+    }
+
+    /// Hand a batch of deliveries to the workers
+    ///
+    /// ## Arguments
+    ///
+    /// * `batch` - The batch to deliver
+    pub fn submit(&self, batch: FanoutBatch) {
+        for (lane, deliveries) in self.lanes.iter().zip(batch.0) {
+            if deliveries.is_empty() {
+                continue;
+            }
+            if lane.sender.send(deliveries).is_err() {
+                tracing::error!(lane = lane.index, "Fan-out worker is not running, dropping deliveries");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        gateway::actor::{GatewayCloseCode, SequencedEvent},
+        models::snowflake::Snowflake,
+    };
+
+    #[test]
+    fn test_prepared_event_matches_sequenced_event() {
+        let event = Arc::new(GatewayEvent::TypingStart {
+            user_id: Snowflake::new(1),
+            channel_id: Snowflake::new(2),
+        });
+        let prepared = PreparedEvent::new(&event, EventTrace::new(None));
+
+        for seq in [None, Some(7)] {
+            let expected =
+                serde_json::to_value(SequencedEvent::new(event.clone(), seq)).expect("event should serialize");
+            let actual: serde_json::Value =
+                serde_json::from_str(&prepared.to_text(seq)).expect("prepared event should be valid JSON");
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lane_preserves_order() {
+        let pool = FanoutPool::new(FANOUT_WORKERS, |_, _| {});
+        let conn = ConnectionId(Snowflake::new(1), Uuid::new_v4());
+        let lane = pool.lane_of(conn);
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let event = PreparedEvent::new(&GatewayEvent::Resumed, EventTrace::new(None));
+
+        for seq in 1..=100 {
+            let delivery = Delivery::new(
+                conn,
+                0,
+                sender.clone(),
+                GatewayResponse::Prepared(event.clone(), Some(seq)),
+            );
+            // Alternate between single deliveries and batches, both go through the same worker
+            if seq % 2 == 0 {
+                lane.submit(delivery).expect("worker should be running");
+            } else {
+                let mut batch = pool.batch();
+                batch.push(&lane, delivery);
+                pool.submit(batch);
+            }
+        }
+
+        for expected in 1..=100 {
+            let Some(GatewayResponse::Prepared(_, seq)) = receiver.recv().await else {
+                panic!("Expected a prepared event");
+            };
+            assert_eq!(seq, Some(expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_reported() {
+        let (failed_tx, mut failed_rx) = mpsc::unbounded_channel();
+        let pool = FanoutPool::new(1, move |conn, attachment| {
+            failed_tx.send((conn, attachment)).ok();
+        });
+        let conn = ConnectionId(Snowflake::new(1), Uuid::new_v4());
+        let (sender, receiver) = mpsc::unbounded_channel();
+        drop(receiver);
+
+        let mut batch = pool.batch();
+        batch.push(
+            &pool.lane_of(conn),
+            Delivery::new(
+                conn,
+                3,
+                sender,
+                GatewayResponse::Close(GatewayCloseCode::Normal, String::new()),
+            ),
+        );
+        pool.submit(batch);
+
+        assert_eq!(failed_rx.recv().await, Some((conn, 3)));
+    }
+}
@@ -1,16 +1,23 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    error::Error as _,
+    fmt::Display,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
 
 use axum::{
-    Router,
+    Extension, Router,
     extract::{
-        State,
+        ConnectInfo, State,
         ws::{CloseFrame, Message, Utf8Bytes, WebSocket, WebSocketUpgrade},
     },
     response::IntoResponse,
     routing::any,
 };
+use chrono::Utc;
 use futures_util::{
-    SinkExt, StreamExt,
+    Sink, SinkExt, Stream, StreamExt,
     stream::{SplitSink, SplitStream},
 };
 use secrecy::ExposeSecret;
@@ -20,21 +27,28 @@ use tokio::{
     time::timeout,
 };
 use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::{Instrument, Span};
 use uuid::Uuid;
 
 use crate::{
     app::App,
     models::{
         auth::Token,
+        capability::ClientCapability,
         errors::GatewayError,
         gateway_event::{GatewayEvent, GatewayMessage, GuildCreatePayload},
-        snowflake::Snowflake,
+        standing::StrikePenalty,
         user::{Presence, User},
     },
     utils::join_handle::JoinHandleExt,
 };
 
-use super::actor::{ConnectionId, GatewayCloseCode, GatewayRequest, GatewayResponse, SendMode, SessionHandle};
+use super::{
+    actor::{ConnectionId, GatewayCloseCode, GatewayRequest, GatewayResponse, SessionHandle},
+    identify_limiter::IdentifyKey,
+    poll,
+    trace::EventTrace,
+};
 
 /// Default heartbeat interval in milliseconds
 const HEARTBEAT_INTERVAL: u64 = 45000;
@@ -45,11 +59,27 @@ const HEARTBEAT_INTERVAL: u64 = 45000;
 ///
 /// A filter that can be used to handle the gateway
 pub fn get_router() -> Router<App> {
-    Router::new().route("/", any(websocket_handler))
+    Router::new()
+        .route("/", any(websocket_handler))
+        .merge(poll::get_router())
 }
 
-async fn websocket_handler(State(app): State<App>, ws: WebSocketUpgrade) -> impl IntoResponse {
-    ws.on_upgrade(|socket| async move { handle_connection(app, socket).await })
+async fn websocket_handler(
+    State(app): State<App>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let ip = connect_info.map(|Extension(ConnectInfo(addr))| addr.ip());
+    let span = tracing::info_span!(
+        "gateway.connection",
+        ip = ip.map(tracing::field::display),
+        user_id = tracing::field::Empty,
+        session_id = tracing::field::Empty,
+    );
+    let ws = ws
+        .max_message_size(app.config.gateway_max_message_size())
+        .max_frame_size(app.config.gateway_max_frame_size());
+    ws.on_upgrade(move |socket| handle_connection(app, socket, ip).instrument(span))
 }
 
 /// Send a serializable object to the client
@@ -74,22 +104,62 @@ async fn send_serializable(
     ws_sink.send(Message::Text(message.into())).await
 }
 
-/// Send a close frame to the client
+/// Send a close frame to the client, preceded by a `CLOSING` event
 ///
 /// ## Arguments
 ///
 /// * `ws_sink` - The sink for sending messages to the client
 ///
 /// This fails silently if the close frame could not be sent, logging a warning
-async fn send_close_frame(
-    ws_sink: &mut SplitSink<WebSocket, Message>,
+async fn send_close_frame<S>(ws_sink: &mut S, code: GatewayCloseCode, reason: impl Into<Utf8Bytes>)
+where
+    S: Sink<Message> + Unpin,
+    S::Error: Display,
+{
+    send_closing(ws_sink, code, reason, code.allows_reconnect(), None).await;
+}
+
+/// Send a `CLOSING` event, then a close frame to the client
+///
+/// Proxies may drop or rewrite close frames, the `CLOSING` event lets clients behind them
+/// still learn why they were disconnected and whether to reconnect.
+///
+/// ## Arguments
+///
+/// * `ws_sink` - The sink for sending messages to the client
+/// * `code` - The close code to close the connection with
+/// * `reason` - The reason to close the connection for
+/// * `reconnect` - Whether the client may reconnect
+/// * `retry_after` - How long the client should wait for before reconnecting, if at all
+///
+/// This fails silently if either message could not be sent, logging a warning
+async fn send_closing<S>(
+    ws_sink: &mut S,
     code: GatewayCloseCode,
     reason: impl Into<Utf8Bytes>,
-) {
+    reconnect: bool,
+    retry_after: Option<Duration>,
+) where
+    S: Sink<Message> + Unpin,
+    S::Error: Display,
+{
+    let reason = reason.into();
+    let closing = GatewayEvent::Closing {
+        code,
+        reason: reason.to_string(),
+        reconnect,
+        retry_after: retry_after.map(|d| d.as_secs_f64()),
+    };
+    let payload = serde_json::to_string(&closing).expect("Failed to serialize CLOSING payload");
+
+    if let Err(e) = ws_sink.send(Message::Text(payload.into())).await {
+        tracing::debug!(error = %e, "Failed to send CLOSING event");
+    }
+
     if let Err(e) = ws_sink
         .send(Message::Close(Some(CloseFrame {
             code: code.into(),
-            reason: reason.into(),
+            reason,
         })))
         .await
     {
@@ -97,21 +167,205 @@ async fn send_close_frame(
     }
 }
 
-/// Send HELLO, then wait for and validate the IDENTIFY payload
+/// Determine the close code for an error raised while reading from the socket
+///
+/// ## Returns
+///
+/// The close code and reason the connection should be closed with,
+/// or `None` if the connection was lost and no close frame can be sent
+fn read_error_close_code(error: &axum::Error) -> Option<(GatewayCloseCode, &'static str)> {
+    match error.source()?.downcast_ref::<tungstenite::Error>()? {
+        tungstenite::Error::Capacity(_) => Some((GatewayCloseCode::TooLarge, "Message too large")),
+        tungstenite::Error::Utf8(_) => Some((GatewayCloseCode::InvalidPayload, "Invalid UTF-8")),
+        tungstenite::Error::Protocol(tungstenite::error::ProtocolError::ResetWithoutClosingHandshake) => None,
+        tungstenite::Error::Protocol(_) => Some((GatewayCloseCode::ProtocolError, "Protocol violation")),
+        _ => None,
+    }
+}
+
+/// The outcome of a successful handshake
+enum Handshake {
+    /// The client identified, a new session with the given capabilities should be started
+    Identify { user: User, capabilities: ClientCapability },
+    /// The client wants to resume an existing session, from after the given sequence number
+    Resume { user: User, session_id: Uuid, seq: u64 },
+}
+
+/// A handshake the server refused, along with the `CLOSING` event the client should be told about it with
+#[derive(Debug)]
+pub(super) struct Rejection {
+    code: GatewayCloseCode,
+    reason: String,
+    reconnect: bool,
+    retry_after: Option<Duration>,
+    error: GatewayError,
+}
+
+impl Rejection {
+    /// Create a new rejection, the client may reconnect if the close code allows it
+    ///
+    /// ## Arguments
+    ///
+    /// * `code` - The close code to close the session with
+    /// * `reason` - The reason the session is closed for
+    /// * `error` - The error to log the rejection as
+    pub(super) fn new(code: GatewayCloseCode, reason: impl Into<String>, error: GatewayError) -> Self {
+        Self {
+            code,
+            reason: reason.into(),
+            reconnect: code.allows_reconnect(),
+            retry_after: None,
+            error,
+        }
+    }
+
+    /// Tell the client not to reconnect, regardless of the close code
+    #[must_use]
+    const fn without_reconnect(mut self) -> Self {
+        self.reconnect = false;
+        self
+    }
+
+    /// Tell the client how long to wait for before reconnecting
+    #[must_use]
+    const fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+
+    /// The close code the session is closed with
+    pub(super) const fn code(&self) -> GatewayCloseCode {
+        self.code
+    }
+
+    /// The `CLOSING` event telling the client about the rejection
+    pub(super) fn closing(&self) -> GatewayEvent {
+        GatewayEvent::Closing {
+            code: self.code,
+            reason: self.reason.clone(),
+            reconnect: self.reconnect,
+            retry_after: self.retry_after.map(|d| d.as_secs_f64()),
+        }
+    }
+
+    /// Close the connection with the rejection's `CLOSING` event and close frame
+    ///
+    /// ## Returns
+    ///
+    /// The error the rejection should be logged as
+    async fn send(self, ws_sink: &mut SplitSink<WebSocket, Message>) -> GatewayError {
+        send_closing(ws_sink, self.code, self.reason, self.reconnect, self.retry_after).await;
+        self.error
+    }
+}
+
+/// Check the handshake rate limit for the given key
+///
+/// The rejection's reason is a JSON object with a `retry_after` field,
+/// the amount of seconds the client should wait for before reconnecting.
+async fn check_identify_limit(app: &App, key: IdentifyKey) -> Result<(), Rejection> {
+    let Err(retry_after) = app.gateway().try_acquire_identify(key).await else {
+        return Ok(());
+    };
+
+    let reason = serde_json::json!({ "retry_after": retry_after.as_secs_f64() }).to_string();
+    Err(Rejection::new(
+        GatewayCloseCode::RateLimited,
+        reason.clone(),
+        GatewayError::RateLimited(reason),
+    )
+    .with_retry_after(retry_after))
+}
+
+/// Validate the token sent in `IDENTIFY` or `RESUME`, and resolve the user it belongs to
+///
+/// Both the address and the user are subject to the handshake rate limits,
+/// and suspended users are refused until their suspension ends.
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `token` - The token sent by the client
+/// * `ip` - The address the client connected from, if known
+///
+/// ## Returns
+///
+/// The user the token belongs to, or why the handshake was refused
+pub(super) async fn authorize(app: &App, token: &str, ip: Option<IpAddr>) -> Result<User, Rejection> {
+    if let Some(ip) = ip {
+        check_identify_limit(app, IdentifyKey::Ip(ip)).await?;
+    }
+
+    let Ok(token) = Token::validate(app.clone(), token).await else {
+        return Err(Rejection::new(
+            GatewayCloseCode::PolicyViolation,
+            "Invalid token",
+            GatewayError::AuthError("Invalid token".into()),
+        )
+        .without_reconnect());
+    };
+
+    check_identify_limit(app, IdentifyKey::User(token.data().user_id())).await?;
+
+    let user = match app.ops().users().fetch_user(token.data().user_id()).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(Rejection::new(
+                GatewayCloseCode::ServerError,
+                "No user belongs to token",
+                GatewayError::InternalServerError("No user belongs to token".into()),
+            ));
+        }
+        Err(e) => {
+            return Err(Rejection::new(
+                GatewayCloseCode::ServerError,
+                "Failed to fetch user",
+                e.into(),
+            ));
+        }
+    };
+
+    match app
+        .ops()
+        .standing()
+        .restricted_until(user.id(), StrikePenalty::Suspension)
+        .await
+    {
+        Ok(None) => Ok(user),
+        Ok(Some(until)) => {
+            let retry_after = u64::try_from((until - Utc::now()).num_seconds()).unwrap_or_default();
+            Err(Rejection::new(
+                GatewayCloseCode::AccountSuspended,
+                "Account suspended",
+                GatewayError::AuthError("Account suspended".into()),
+            )
+            .with_retry_after(Duration::from_secs(retry_after)))
+        }
+        Err(e) => Err(Rejection::new(
+            GatewayCloseCode::ServerError,
+            "Failed to fetch account standing",
+            e.into(),
+        )),
+    }
+}
+
+/// Send HELLO, then wait for and validate the IDENTIFY or RESUME payload
 ///
 /// ## Arguments
 ///
 /// * `ws_sink` - The sink for sending messages to the client
 /// * `ws_stream` - The stream for receiving messages from the client
+/// * `ip` - The address the client connected from, if known
 ///
 /// ## Returns
 ///
-/// The resolved user if the handshake was successful
+/// The resolved user, along with the session to resume if the handshake was successful
 async fn handle_handshake(
     app: App,
     ws_sink: &mut SplitSink<WebSocket, Message>,
     ws_stream: &mut SplitStream<WebSocket>,
-) -> Result<User, GatewayError> {
+    ip: Option<IpAddr>,
+) -> Result<Handshake, GatewayError> {
     // Send HELLO with the heartbeat interval
     ws_sink
         .send(Message::Text(
@@ -125,9 +379,18 @@ async fn handle_handshake(
         .ok();
 
     // IDENTIFY should be the first message sent
-    let Ok(Some(Ok(ident))) = timeout(Duration::from_secs(5), ws_stream.next()).await else {
-        send_close_frame(ws_sink, GatewayCloseCode::PolicyViolation, "IDENTIFY expected").await;
-        return Err(GatewayError::HandshakeFailure("IDENTIFY expected".into()));
+    let ident = match timeout(Duration::from_secs(5), ws_stream.next()).await {
+        Ok(Some(Ok(ident))) => ident,
+        Ok(Some(Err(e))) => {
+            if let Some((code, reason)) = read_error_close_code(&e) {
+                send_close_frame(ws_sink, code, reason).await;
+            }
+            return Err(GatewayError::MalformedFrame(e.to_string()));
+        }
+        _ => {
+            send_close_frame(ws_sink, GatewayCloseCode::PolicyViolation, "IDENTIFY expected").await;
+            return Err(GatewayError::HandshakeFailure("IDENTIFY expected".into()));
+        }
     };
 
     let Message::Text(text) = ident else {
@@ -135,22 +398,27 @@ async fn handle_handshake(
         return Err(GatewayError::MalformedFrame("Unsupported message encoding".into()));
     };
 
-    let Ok(GatewayMessage::Identify { token }) = serde_json::from_str(&text) else {
-        send_close_frame(ws_sink, GatewayCloseCode::InvalidPayload, "Invalid IDENTIFY payload").await;
-        return Err(GatewayError::MalformedFrame("Invalid IDENTIFY payload".into()));
-    };
-
-    let Ok(token) = Token::validate(app.clone(), token.expose_secret()).await else {
-        send_close_frame(ws_sink, GatewayCloseCode::PolicyViolation, "Invalid token").await;
-        return Err(GatewayError::AuthError("Invalid token".into()));
+    let (token, resume, capabilities) = match serde_json::from_str(&text) {
+        Ok(GatewayMessage::Identify { token, capabilities }) => (token, None, capabilities),
+        // Resumed sessions keep the capabilities they were identified with
+        Ok(GatewayMessage::Resume { token, session_id, seq }) => {
+            (token, Some((session_id, seq)), ClientCapability::default())
+        }
+        _ => {
+            send_close_frame(ws_sink, GatewayCloseCode::InvalidPayload, "Invalid IDENTIFY payload").await;
+            return Err(GatewayError::MalformedFrame("Invalid IDENTIFY payload".into()));
+        }
     };
 
-    let Some(user) = app.ops().fetch_user(token.data().user_id()).await else {
-        send_close_frame(ws_sink, GatewayCloseCode::ServerError, "No user belongs to token").await;
-        return Err(GatewayError::InternalServerError("No user belongs to token".into()));
+    let user = match authorize(&app, token.expose_secret(), ip).await {
+        Ok(user) => user,
+        Err(rejection) => return Err(rejection.send(ws_sink).await),
     };
 
-    Ok(user)
+    Ok(match resume {
+        Some((session_id, seq)) => Handshake::Resume { user, session_id, seq },
+        None => Handshake::Identify { user, capabilities },
+    })
 }
 
 /// Handle the heartbeat mechanism for a given user
@@ -195,7 +463,12 @@ async fn handle_heartbeating(
                 _ => "Unknown error".into(),
             };
 
-            app.gateway().close_session(id, close_code, reason);
+            // A missed heartbeat likely means the connection was lost, so the session is kept resumable
+            if matches!(close_code, GatewayCloseCode::PolicyViolation) {
+                app.gateway().disconnect_session(id, close_code, reason);
+            } else {
+                app.gateway().close_session(id, close_code, reason);
+            }
             // We must not break here as that causes a race condition where the heartbeat
             // task may abort the sender task before the close message is sent
             // The sender task will stop when seeing the close anyway and
@@ -212,85 +485,181 @@ async fn handle_heartbeating(
 ///
 /// * `app` - The shared application state
 /// * `user` - The user to send the `READY` event to
-/// * `ws_sink` - The sink for sending messages to the user
-async fn send_onboarding_payloads(
+/// * `session_id` - The ID of the newly created session
+/// * `capabilities` - The capabilities the session was identified with
+/// * `send` - Sends an event to the user, through the transport the session is connected with
+pub(super) async fn send_onboarding_payloads<F, Fut, E>(
     app: App,
     user: User,
-    ws_sink: Arc<Mutex<SplitSink<WebSocket, Message>>>,
-) -> Result<(), axum::Error> {
+    session_id: Uuid,
+    capabilities: ClientCapability,
+    mut send: F,
+) -> Result<(), E>
+where
+    F: FnMut(GatewayEvent) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+{
     let guilds = app
         .ops()
+        .guilds()
         .fetch_guilds_for(&user)
         .await
         .expect("Failed to fetch guilds during socket connection handling");
 
     let read_states = app
         .ops()
+        .notifications()
         .fetch_read_states(user.id())
         .await
         .expect("Failed to fetch read states during socket connection handling");
 
+    let relationships = app
+        .ops()
+        .relationships()
+        .fetch_relationships(user.id())
+        .await
+        .expect("Failed to fetch relationships during socket connection handling");
+
+    // Users already typing are only included for sessions that display typing indicators
+    let typing = if capabilities.contains(ClientCapability::TYPING) {
+        app.gateway().typing_visible_to(user.id()).await
+    } else {
+        Vec::new()
+    };
+
     // Send READY
-    send_serializable(
-        &mut *ws_sink.lock().await,
-        GatewayEvent::Ready {
-            user: user.clone(),
-            guilds: guilds.clone(),
-            read_states,
-        },
-    )
+    send(GatewayEvent::Ready {
+        session_id,
+        user: user.to_private(),
+        guilds: guilds.clone(),
+        read_states,
+        relationships,
+        typing,
+    })
     .await?;
 
     // Send GUILD_CREATE events for all guilds the user is in
     for guild in guilds {
-        let payload = GuildCreatePayload::from_guild(&app, guild)
+        let mut payload = GuildCreatePayload::from_guild(&app, guild, &user)
             .await
             .expect("Failed to fetch guild payload data");
+        if !capabilities.contains(ClientCapability::PRESENCE) {
+            payload = payload.without_presences();
+        }
 
-        send_serializable(&mut *ws_sink.lock().await, GatewayEvent::GuildCreate(payload)).await?;
+        send(GatewayEvent::GuildCreate(payload)).await?;
     }
 
-    // Send the presence update for the user if they were not invisible when last logging off
-    match user.last_presence() {
-        Presence::Offline => {}
-        _ => {
-            app.gateway().dispatch(
-                GatewayEvent::PresenceUpdate {
-                    user_id: user.id(),
-                    presence: *user.last_presence(),
-                },
-                SendMode::ToMutualGuilds(user.id()),
-            );
+    dispatch_presence(&app, &user).await;
+    Ok(())
+}
+
+/// Dispatch a `PRESENCE_UPDATE` event for this user if they were not invisible when last logging off
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `user` - The user that connected
+pub(super) async fn dispatch_presence(app: &App, user: &User) {
+    if *user.last_presence() == Presence::Offline {
+        return;
+    }
+
+    if let Err(e) = app.ops().users().dispatch_presence(user, *user.last_presence()).await {
+        tracing::error!(error = %e, "Failed to dispatch presence of connected user");
+    }
+}
+
+/// Dispatch a `PRESENCE_UPDATE` event marking this user as offline, unless they were already invisible
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `user` - The user that disconnected
+pub(super) async fn dispatch_offline(app: &App, user: &User) {
+    // Refetch presence in case it changed, to ensure we don't accidentally reveal the user's presence
+    let presence = match app.ops().users().fetch_presence(user).await {
+        Ok(presence) => presence,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to fetch presence of disconnected user");
+            return;
+        }
+    };
+
+    match presence {
+        // The user was deleted while connected, or never revealed their presence
+        None | Some(Presence::Offline) => {}
+        Some(_) => {
+            if let Err(e) = app.ops().users().dispatch_presence(user, Presence::Offline).await {
+                tracing::error!(error = %e, "Failed to dispatch presence of disconnected user");
+            }
         }
     }
-    Ok(())
+}
+
+/// Log that a traced event was sent to a session, and record the delivery for diagnostics
+///
+/// ## Arguments
+///
+/// * `app` - The application state
+/// * `conn_id` - The session the event was sent to
+/// * `trace` - The trace of the event, if it is traced
+/// * `seq` - The sequence number of the event, unsequenced events are not recorded
+pub(super) fn record_delivery(app: &App, conn_id: ConnectionId, trace: Option<EventTrace>, seq: Option<u64>) {
+    let (Some(trace), Some(seq)) = (trace, seq) else {
+        return;
+    };
+
+    tracing::debug!(
+        event_id = %trace.event_id(),
+        correlation_id = trace.correlation_id().map(tracing::field::display),
+        seq,
+        "Transmitted event"
+    );
+    app.gateway().deliveries().record(trace, conn_id, seq);
 }
 
 /// Forward events received through the `ConnectionHandle` receiver to the user
 ///
 /// ## Arguments
 ///
-/// * `user_id` - The ID of the user to send events to
+/// * `app` - The application state
+/// * `conn_id` - The ID of the session to send events to
 /// * `receiver` - The receiver for incoming gateway responses to send
 /// * `ws_sink` - The sink for sending messages to the user
 async fn send_events(
-    user_id: Snowflake<User>,
+    app: App,
+    conn_id: ConnectionId,
     mut receiver: UnboundedReceiverStream<GatewayResponse>,
     ws_sink: Arc<Mutex<SplitSink<WebSocket, Message>>>,
 ) -> Result<GatewayCloseCode, axum::Error> {
     while let Some(payload) = receiver.next().await {
         match payload {
             GatewayResponse::Close(code, reason) => {
-                tracing::debug!(?code, ?reason, "Closing connection for user {user_id}");
+                tracing::debug!(?code, ?reason, "Closing connection for {conn_id}");
                 send_close_frame(&mut *ws_sink.lock().await, code, reason).await;
                 return Ok(code);
             }
             GatewayResponse::Event(event) => {
+                let (trace, seq) = (event.trace(), event.seq());
                 let res = send_serializable(&mut *ws_sink.lock().await, event).await;
                 if let Err(e) = res {
-                    tracing::warn!(error = %e, "Error sending event to user {user_id}: {e}");
+                    tracing::warn!(error = %e, "Error sending event to {conn_id}: {e}");
                     return Err(e);
                 }
+                record_delivery(&app, conn_id, trace, seq);
+            }
+            GatewayResponse::Prepared(event, seq) => {
+                let res = ws_sink
+                    .lock()
+                    .await
+                    .send(Message::Text(event.to_text(seq).into()))
+                    .await;
+                if let Err(e) = res {
+                    tracing::warn!(error = %e, "Error sending event to {conn_id}: {e}");
+                    return Err(e);
+                }
+                record_delivery(&app, conn_id, Some(event.trace()), seq);
             }
         }
     }
@@ -299,32 +668,49 @@ async fn send_events(
 
 /// Parse & forward events received through the socket to the `ConnectionHandle` sender
 ///
+/// The session is closed on the first message that is not valid text JSON,
+/// including messages exceeding [`Config::gateway_max_message_size`](crate::app::Config::gateway_max_message_size).
+///
 /// ## Arguments
 ///
 /// * `user_id` - The ID of the user to receive events for
 /// * `ws_stream` - The stream for receiving messages from the user
 /// * `ws_sink` - The sink for sending messages to the user
-async fn receive_events(
+async fn receive_events<R, S>(
     conn_id: ConnectionId,
-    mut ws_stream: SplitStream<WebSocket>,
-    ws_sink: Arc<Mutex<SplitSink<WebSocket, Message>>>,
+    mut ws_stream: R,
+    ws_sink: Arc<Mutex<S>>,
     broadcaster: Arc<broadcast::Sender<GatewayMessage>>,
-) {
+) where
+    R: Stream<Item = Result<Message, axum::Error>> + Unpin,
+    S: Sink<Message> + Unpin,
+    S::Error: Display,
+{
     while let Some(msg) = ws_stream.next().await {
-        // Close if the user sends a close frame
-        if let Ok(Message::Close(f)) = msg {
-            tracing::debug!(close_frame = ?f, "Gateway stream closed by {conn_id}: {f:?}");
-            break;
-        }
-        // Otherwise attempt to parse the message and send it
-        let Ok(Message::Text(text)) = msg else {
-            send_close_frame(
-                &mut *ws_sink.lock().await,
-                GatewayCloseCode::Unsupported,
-                "Unsupported message encoding",
-            )
-            .await;
-            break;
+        let text = match msg {
+            Ok(Message::Text(text)) => text,
+            // Pings are answered by axum itself
+            Ok(Message::Ping(_) | Message::Pong(_)) => continue,
+            Ok(Message::Close(f)) => {
+                tracing::debug!(close_frame = ?f, "Gateway stream closed by {conn_id}: {f:?}");
+                break;
+            }
+            Ok(Message::Binary(_)) => {
+                send_close_frame(
+                    &mut *ws_sink.lock().await,
+                    GatewayCloseCode::Unsupported,
+                    "Unsupported message encoding",
+                )
+                .await;
+                break;
+            }
+            Err(e) => {
+                tracing::debug!(error = %e, "Failed to read from gateway stream of {conn_id}: {e}");
+                if let Some((code, reason)) = read_error_close_code(&e) {
+                    send_close_frame(&mut *ws_sink.lock().await, code, reason).await;
+                }
+                break;
+            }
         };
 
         match serde_json::from_str::<GatewayRequest>(&text) {
@@ -360,7 +746,8 @@ async fn receive_events(
 ///
 /// * `app` - The shared application state
 /// * `socket` - The websocket connection to handle
-async fn handle_connection(app: App, socket: WebSocket) {
+/// * `ip` - The address the client connected from, if known
+async fn handle_connection(app: App, socket: WebSocket, ip: Option<IpAddr>) {
     let (mut ws_sink, mut ws_stream) = socket.split();
 
     if !app.gateway().is_started() {
@@ -369,7 +756,7 @@ async fn handle_connection(app: App, socket: WebSocket) {
     }
 
     // Handle handshake and get user
-    let Ok(user) = handle_handshake(app.clone(), &mut ws_sink, &mut ws_stream).await else {
+    let Ok(handshake) = handle_handshake(app.clone(), &mut ws_sink, &mut ws_stream, ip).await else {
         ws_sink
             .reunite(ws_stream)
             .expect("WS sink and stream should be reuniteable")
@@ -379,42 +766,87 @@ async fn handle_connection(app: App, socket: WebSocket) {
         return;
     };
 
-    let conn_id = ConnectionId(user.id(), Uuid::new_v4());
+    let (user, resume, capabilities) = match handshake {
+        Handshake::Identify { user, capabilities } => (user, None, capabilities),
+        Handshake::Resume { user, session_id, seq } => (user, Some((session_id, seq)), ClientCapability::default()),
+    };
+
+    let conn_id = ConnectionId(
+        user.id(),
+        resume.map_or_else(Uuid::new_v4, |(session_id, _)| session_id),
+    );
 
+    Span::current()
+        .record("user_id", tracing::field::display(conn_id.0))
+        .record("session_id", tracing::field::display(conn_id.1));
     tracing::debug!(?user, "Connected: {} ({})", user.username(), conn_id);
 
     let (sender, receiver) = mpsc::unbounded_channel::<GatewayResponse>();
     let (broadcaster, _) = broadcast::channel::<GatewayMessage>(8);
     let broadcaster = Arc::new(broadcaster);
 
-    let handle = SessionHandle::new(sender, broadcaster.clone());
+    let handle = SessionHandle::new(sender, broadcaster.clone()).with_capabilities(capabilities);
 
-    // Add user to peermap
-    app.gateway().create_session(conn_id, handle);
+    // Add user to peermap, or take over the session being resumed
+    let attachment = if let Some((_, seq)) = resume {
+        let Some(attachment) = app.gateway().resume_session(conn_id, handle, seq).await else {
+            send_close_frame(
+                &mut ws_sink,
+                GatewayCloseCode::InvalidSession,
+                "Session cannot be resumed",
+            )
+            .await;
+            return;
+        };
+        attachment
+    } else {
+        app.gateway().create_session(conn_id, handle, *user.last_presence());
+        0
+    };
 
-    let user = user.include_presence(app.gateway()).await;
-    let user_id = user.id();
+    if let Err(e) = app
+        .ops()
+        .instances()
+        .record_connection(app.instance_id(), user.id())
+        .await
+    {
+        tracing::error!(error = %e, "Failed to record gateway connection");
+    }
 
     // We want to use the same sink in multiple tasks
     let ws_sink = Arc::new(Mutex::new(ws_sink));
 
-    // Send READY and guild creates to user
-    let send_onboarding = tokio::spawn(send_onboarding_payloads(app.clone(), user.clone(), ws_sink.clone()));
+    // Send READY and guild creates to user, resumed sessions already have this state
+    let send_onboarding = if resume.is_some() {
+        dispatch_presence(&app, &user).await;
+        None
+    } else {
+        let ws_sink = ws_sink.clone();
+        let send = move |event| {
+            let ws_sink = ws_sink.clone();
+            async move { send_serializable(&mut *ws_sink.lock().await, event).await }
+        };
+        Some(tokio::spawn(
+            send_onboarding_payloads(app.clone(), user.clone(), conn_id.1, capabilities, send).in_current_span(),
+        ))
+    };
 
     // The tasks need to be dropped when their joinhandles are dropped by select!
-    let send_events = tokio::spawn(send_events(
-        user_id,
-        UnboundedReceiverStream::new(receiver),
-        ws_sink.clone(),
-    ))
-    .abort_on_drop();
-    let receive_events = tokio::spawn(receive_events(conn_id, ws_stream, ws_sink, broadcaster.clone())).abort_on_drop();
-    let handle_heartbeat = tokio::spawn(handle_heartbeating(
-        broadcaster.clone(),
-        app.clone(),
-        conn_id,
-        Duration::from_millis(HEARTBEAT_INTERVAL),
-    ))
+    let responses = UnboundedReceiverStream::new(receiver);
+    let send_events =
+        tokio::spawn(send_events(app.clone(), conn_id, responses, ws_sink.clone()).in_current_span()).abort_on_drop();
+    let receive_events =
+        tokio::spawn(receive_events(conn_id, ws_stream, ws_sink, broadcaster.clone()).in_current_span())
+            .abort_on_drop();
+    let handle_heartbeat = tokio::spawn(
+        handle_heartbeating(
+            broadcaster.clone(),
+            app.clone(),
+            conn_id,
+            Duration::from_millis(HEARTBEAT_INTERVAL),
+        )
+        .in_current_span(),
+    )
     .abort_on_drop();
 
     let is_server_shutting_down = tokio::select! {
@@ -423,9 +855,11 @@ async fn handle_connection(app: App, socket: WebSocket) {
         _ = handle_heartbeat => { false },
     };
 
-    send_onboarding.abort();
+    if let Some(send_onboarding) = send_onboarding {
+        send_onboarding.abort();
+    }
 
-    app.gateway().remove_session(conn_id);
+    app.gateway().detach_session(conn_id, attachment);
 
     // If we're shutting down, don't spam out presence updates
     if is_server_shutting_down {
@@ -434,19 +868,242 @@ async fn handle_connection(app: App, socket: WebSocket) {
 
     tracing::debug!(?user, "Disconnected: {} ({})", user.username(), conn_id);
 
-    // Refetch presence in case it changed, to ensure we don't accidentally reveal the user's presence
-    let presence = app.ops().fetch_presence(&user).await.expect("Failed to fetch presence");
+    dispatch_offline(&app, &user).await;
+}
 
-    match presence {
-        Presence::Offline => {}
-        _ => {
-            app.gateway().dispatch(
-                GatewayEvent::PresenceUpdate {
-                    user_id: user.id(),
-                    presence: Presence::Offline,
-                },
-                SendMode::ToMutualGuilds(user.id()),
-            );
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read, Write};
+
+    use futures::channel::mpsc as futures_mpsc;
+    use tungstenite::protocol::{Role, WebSocket as RawWebSocket, WebSocketConfig};
+
+    use super::*;
+    use crate::models::snowflake::Snowflake;
+
+    const MAX_MESSAGE_SIZE: usize = 64;
+    const MAX_FRAME_SIZE: usize = 32;
+
+    const TEXT: u8 = 0x1;
+    const CONTINUATION: u8 = 0x0;
+    const BINARY: u8 = 0x2;
+    const CLOSE: u8 = 0x8;
+    const PING: u8 = 0x9;
+
+    /// The bytes sent by a client, anything the server writes back is discarded
+    struct ClientBytes(Cursor<Vec<u8>>);
+
+    impl Read for ClientBytes {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for ClientBytes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Encode a masked client frame
+    fn frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![(u8::from(fin) << 7) | opcode];
+        match u16::try_from(payload.len()) {
+            Ok(len @ 0..=125) => out.push(0x80 | len as u8),
+            Ok(len) => {
+                out.push(0x80 | 0x7E);
+                out.extend(len.to_be_bytes());
+            }
+            Err(_) => {
+                out.push(0x80 | 0x7F);
+                out.extend((payload.len() as u64).to_be_bytes());
+            }
+        }
+        // An all-zero mask leaves the payload as is
+        out.extend([0; 4]);
+        out.extend(payload);
+        out
+    }
+
+    /// Decode the bytes sent by a client into the messages axum would yield, with the gateway's limits applied
+    fn decode(bytes: Vec<u8>) -> Vec<Result<Message, axum::Error>> {
+        let config = WebSocketConfig::default()
+            .max_message_size(Some(MAX_MESSAGE_SIZE))
+            .max_frame_size(Some(MAX_FRAME_SIZE));
+        let mut socket = RawWebSocket::from_raw_socket(ClientBytes(Cursor::new(bytes)), Role::Server, Some(config));
+        let mut messages = Vec::new();
+
+        loop {
+            let message = match socket.read() {
+                Ok(tungstenite::Message::Text(text)) => Message::Text(text.as_str().into()),
+                Ok(tungstenite::Message::Binary(data)) => Message::Binary(data),
+                Ok(tungstenite::Message::Ping(data)) => Message::Ping(data),
+                Ok(tungstenite::Message::Pong(data)) => Message::Pong(data),
+                Ok(tungstenite::Message::Close(frame)) => Message::Close(frame.map(|f| CloseFrame {
+                    code: f.code.into(),
+                    reason: f.reason.as_str().into(),
+                })),
+                Ok(tungstenite::Message::Frame(_)) => unreachable!("Raw frames are only yielded when writing"),
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => break,
+                Err(e) => {
+                    messages.push(Err(axum::Error::new(e)));
+                    break;
+                }
+            };
+            messages.push(Ok(message));
+        }
+        messages
+    }
+
+    /// Run `receive_events` over the bytes sent by a client
+    ///
+    /// ## Returns
+    ///
+    /// The forwarded requests, and the code the session was closed with, if a close frame was sent
+    async fn receive(bytes: Vec<u8>) -> (Vec<GatewayMessage>, Option<u16>) {
+        let conn_id = ConnectionId(Snowflake::new(1), Uuid::new_v4());
+        let (broadcaster, mut requests) = broadcast::channel(64);
+        let (sink, replies) = futures_mpsc::unbounded();
+
+        receive_events(
+            conn_id,
+            futures_util::stream::iter(decode(bytes)),
+            Arc::new(Mutex::new(sink)),
+            Arc::new(broadcaster),
+        )
+        .await;
+
+        let mut forwarded = Vec::new();
+        while let Ok(request) = requests.try_recv() {
+            forwarded.push(request);
+        }
+        let replies = replies.collect::<Vec<_>>().await;
+        let close = match replies.as_slice() {
+            [] => None,
+            [Message::Text(closing), Message::Close(Some(frame))] => {
+                // The CLOSING event mirrors the close frame that follows it
+                let closing: serde_json::Value = serde_json::from_str(closing).expect("CLOSING should be valid JSON");
+                assert_eq!(closing["event"], "CLOSING");
+                assert_eq!(closing["data"]["code"], frame.code);
+                assert_eq!(closing["data"]["reason"], frame.reason.as_str());
+                Some(frame.code)
+            }
+            other => panic!("Expected a CLOSING event and a close frame, got {other:?}"),
+        };
+        (forwarded, close)
+    }
+
+    #[tokio::test]
+    async fn test_receive_events_forwards_requests() {
+        let heartbeat = br#"{"event":"HEARTBEAT"}"#;
+        let mut bytes = frame(true, TEXT, heartbeat);
+        // Fragments may be interleaved with control frames, and split UTF-8 sequences
+        let activity = r#"{"event":"ACTIVITY","x":"é"}"#.as_bytes();
+        let split = activity.len() - 3;
+        bytes.extend(frame(false, TEXT, &activity[..split]));
+        bytes.extend(frame(true, PING, b"ping"));
+        bytes.extend(frame(true, CONTINUATION, &activity[split..]));
+        bytes.extend(frame(true, CLOSE, &1000u16.to_be_bytes()));
+        // Anything after the close frame is ignored
+        bytes.extend(frame(true, TEXT, heartbeat));
+
+        let (forwarded, close) = receive(bytes).await;
+        assert!(matches!(
+            forwarded.as_slice(),
+            [GatewayMessage::Heartbeat, GatewayMessage::Activity]
+        ));
+        assert!(close.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_receive_events_rejects_invalid_frames() {
+        let oversized_frame = frame(true, TEXT, &[b' '; MAX_FRAME_SIZE + 1]);
+        let mut oversized_message = Vec::new();
+        for i in 0..3 {
+            let opcode = if i == 0 { TEXT } else { CONTINUATION };
+            oversized_message.extend(frame(i == 2, opcode, &[b' '; MAX_FRAME_SIZE]));
+        }
+        let mut split_invalid_utf8 = frame(false, TEXT, b"\"\xC3");
+        split_invalid_utf8.extend(frame(true, CONTINUATION, b"\x28\""));
+        let mut reserved_bits = frame(true, TEXT, b"{}");
+        reserved_bits[0] |= 0x40;
+
+        let cases = [
+            (oversized_frame, GatewayCloseCode::TooLarge),
+            (oversized_message, GatewayCloseCode::TooLarge),
+            (frame(true, TEXT, b"\xFF\xFE"), GatewayCloseCode::InvalidPayload),
+            (split_invalid_utf8, GatewayCloseCode::InvalidPayload),
+            (
+                frame(true, TEXT, b"{\"event\":\"NOPE\"}"),
+                GatewayCloseCode::InvalidPayload,
+            ),
+            (
+                frame(true, BINARY, br#"{"event":"HEARTBEAT"}"#),
+                GatewayCloseCode::Unsupported,
+            ),
+            (frame(true, CONTINUATION, b"{}"), GatewayCloseCode::ProtocolError),
+            (frame(false, PING, b""), GatewayCloseCode::ProtocolError),
+            (reserved_bits, GatewayCloseCode::ProtocolError),
+        ];
+
+        for (i, (mut bytes, expected)) in cases.into_iter().enumerate() {
+            // The session must not keep reading after an invalid message
+            bytes.extend(frame(true, TEXT, br#"{"event":"HEARTBEAT"}"#));
+            let (forwarded, close) = receive(bytes).await;
+            assert!(forwarded.is_empty(), "Case {i} forwarded {forwarded:?}");
+            assert_eq!(close, Some(expected.into()), "Case {i}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_receive_events_lost_connection() {
+        // A connection dropped mid-message cannot be sent a close frame
+        let (forwarded, close) = receive(frame(false, TEXT, b"{")).await;
+        assert!(forwarded.is_empty());
+        assert!(close.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_receive_events_fuzz() {
+        // xorshift64, seeded for reproducible runs
+        let mut state = 0x9E37_79B9_7F4A_7C15_u64;
+        let mut next = move |bound: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % bound
+        };
+        let allowed = [
+            GatewayCloseCode::ProtocolError,
+            GatewayCloseCode::Unsupported,
+            GatewayCloseCode::InvalidPayload,
+            GatewayCloseCode::TooLarge,
+        ]
+        .map(u16::from);
+
+        for _ in 0..2000 {
+            let mut bytes = Vec::new();
+            for _ in 0..=next(6) {
+                let opcode = [TEXT, CONTINUATION, BINARY, CLOSE, PING, 0xA, 0x3][next(7) as usize];
+                let payload: Vec<u8> = match next(4) {
+                    0 => br#"{"event":"HEARTBEAT"}"#.to_vec(),
+                    1 => vec![b' '; next(MAX_MESSAGE_SIZE as u64 * 2) as usize],
+                    _ => (0..next(48)).map(|_| next(256) as u8).collect(),
+                };
+                bytes.extend(frame(next(3) != 0, opcode, &payload));
+            }
+            if next(4) == 0 {
+                bytes.truncate(next(bytes.len() as u64 + 1) as usize);
+            }
+
+            let (_, close) = receive(bytes.clone()).await;
+            if let Some(code) = close {
+                assert!(allowed.contains(&code), "Unexpected close code {code} for {bytes:?}");
+            }
         }
     }
 }
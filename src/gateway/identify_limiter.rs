@@ -0,0 +1,247 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use crate::models::{snowflake::Snowflake, user::User};
+
+/// The amount of handshakes a user may perform in a burst
+pub const USER_IDENTIFY_BURST: f64 = 5.0;
+/// The time it takes for a user to regain a single handshake
+pub const USER_IDENTIFY_REFILL: Duration = Duration::from_secs(12);
+/// The amount of handshakes a single IP address may perform in a burst.
+/// This is more generous than the per-user limit, as many users may share an address.
+pub const IP_IDENTIFY_BURST: f64 = 30.0;
+/// The time it takes for an IP address to regain a single handshake
+pub const IP_IDENTIFY_REFILL: Duration = Duration::from_secs(2);
+/// The delay imposed the first time a bucket runs dry, doubling with every consecutive violation
+pub const IDENTIFY_BACKOFF_BASE: Duration = Duration::from_secs(5);
+/// The maximum delay imposed on a bucket that keeps running dry
+pub const IDENTIFY_BACKOFF_MAX: Duration = Duration::from_secs(300);
+/// How often buckets that are full again are dropped to reclaim memory
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What a handshake is rate limited by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IdentifyKey {
+    /// The user the client identified as
+    User(Snowflake<User>),
+    /// The address the client connected from
+    Ip(IpAddr),
+}
+
+/// A token bucket that imposes an exponentially growing delay on clients that keep exhausting it.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    refill: Duration,
+    tokens: f64,
+    updated_at: Instant,
+    /// The amount of consecutive times the bucket ran dry without fully refilling in between
+    strikes: u32,
+    blocked_until: Option<Instant>,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, refill: Duration, now: Instant) -> Self {
+        Self {
+            capacity,
+            refill,
+            tokens: capacity,
+            updated_at: now,
+            strikes: 0,
+            blocked_until: None,
+        }
+    }
+
+    fn replenish(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.tokens = elapsed
+            .as_secs_f64()
+            .mul_add(1.0 / self.refill.as_secs_f64(), self.tokens)
+            .min(self.capacity);
+        self.updated_at = now;
+
+        // A client that behaved long enough to refill the bucket is forgiven
+        if self.tokens >= self.capacity {
+            self.strikes = 0;
+        }
+    }
+
+    /// Take a token from the bucket.
+    ///
+    /// ## Returns
+    ///
+    /// The delay the client should wait for before trying again, if the bucket is exhausted
+    fn acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        self.replenish(now);
+
+        if let Some(until) = self.blocked_until {
+            if now < until {
+                return Err(until - now);
+            }
+            self.blocked_until = None;
+        }
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let delay = IDENTIFY_BACKOFF_BASE
+            .saturating_mul(2u32.saturating_pow(self.strikes))
+            .min(IDENTIFY_BACKOFF_MAX);
+        self.strikes = self.strikes.saturating_add(1);
+        self.blocked_until = Some(now + delay);
+        Err(delay)
+    }
+
+    /// Whether the bucket is in the same state as a freshly created one
+    fn is_idle(&mut self, now: Instant) -> bool {
+        self.replenish(now);
+        self.tokens >= self.capacity && self.blocked_until.is_none_or(|until| until <= now)
+    }
+}
+
+/// Rate limits gateway handshakes per user and per IP address,
+/// protecting the gateway from clients stuck in a reconnect loop.
+#[derive(Debug)]
+pub(super) struct IdentifyLimiter {
+    users: HashMap<Snowflake<User>, TokenBucket>,
+    ips: HashMap<IpAddr, TokenBucket>,
+    last_prune: Instant,
+}
+
+impl IdentifyLimiter {
+    /// Create a new limiter with no recorded handshakes.
+    pub fn new() -> Self {
+        Self {
+            users: HashMap::new(),
+            ips: HashMap::new(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    /// Record a handshake attempt.
+    ///
+    /// ## Returns
+    ///
+    /// The delay the client should wait for before trying again, if it is rate limited
+    pub fn acquire(&mut self, key: IdentifyKey) -> Result<(), Duration> {
+        self.acquire_at(key, Instant::now())
+    }
+
+    fn acquire_at(&mut self, key: IdentifyKey, now: Instant) -> Result<(), Duration> {
+        if now.saturating_duration_since(self.last_prune) >= PRUNE_INTERVAL {
+            self.users.retain(|_, b| !b.is_idle(now));
+            self.ips.retain(|_, b| !b.is_idle(now));
+            self.last_prune = now;
+        }
+
+        match key {
+            IdentifyKey::User(user) => {
+                Self::bucket(&mut self.users, user, USER_IDENTIFY_BURST, USER_IDENTIFY_REFILL, now).acquire(now)
+            }
+            IdentifyKey::Ip(ip) => {
+                Self::bucket(&mut self.ips, ip, IP_IDENTIFY_BURST, IP_IDENTIFY_REFILL, now).acquire(now)
+            }
+        }
+    }
+
+    fn bucket<K: Eq + Hash>(
+        buckets: &mut HashMap<K, TokenBucket>,
+        key: K,
+        capacity: f64,
+        refill: Duration,
+        now: Instant,
+    ) -> &mut TokenBucket {
+        buckets
+            .entry(key)
+            .or_insert_with(|| TokenBucket::new(capacity, refill, now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const USER: IdentifyKey = IdentifyKey::User(Snowflake::new(1));
+
+    #[test]
+    fn test_burst_then_limited() {
+        let mut limiter = IdentifyLimiter::new();
+        let now = Instant::now();
+
+        for _ in 0..5 {
+            assert!(limiter.acquire_at(USER, now).is_ok());
+        }
+        assert_eq!(limiter.acquire_at(USER, now), Err(IDENTIFY_BACKOFF_BASE));
+
+        // Other users and addresses are unaffected
+        assert!(limiter.acquire_at(IdentifyKey::User(Snowflake::new(2)), now).is_ok());
+        assert!(
+            limiter
+                .acquire_at(IdentifyKey::Ip(Ipv4Addr::LOCALHOST.into()), now)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_backoff_escalates() {
+        let mut bucket = TokenBucket::new(1.0, Duration::from_secs(3600), Instant::now());
+        let mut now = Instant::now();
+        assert!(bucket.acquire(now).is_ok());
+
+        let mut expected = IDENTIFY_BACKOFF_BASE;
+        for _ in 0..10 {
+            assert_eq!(bucket.acquire(now), Err(expected));
+            // Retrying while blocked only reports the remaining delay
+            assert_eq!(
+                bucket.acquire(now + Duration::from_secs(1)),
+                Err(expected.saturating_sub(Duration::from_secs(1)))
+            );
+            now += expected;
+            expected = (expected * 2).min(IDENTIFY_BACKOFF_MAX);
+        }
+        assert_eq!(expected, IDENTIFY_BACKOFF_MAX);
+    }
+
+    #[test]
+    fn test_refill_forgives() {
+        let mut bucket = TokenBucket::new(2.0, Duration::from_secs(10), Instant::now());
+        let now = Instant::now();
+        assert!(bucket.acquire(now).is_ok());
+        assert!(bucket.acquire(now).is_ok());
+        assert_eq!(bucket.acquire(now), Err(IDENTIFY_BACKOFF_BASE));
+
+        // One token is back after the backoff expired, but the strike is kept
+        let later = now + Duration::from_secs(10);
+        assert!(bucket.acquire(later).is_ok());
+        assert_eq!(bucket.acquire(later), Err(IDENTIFY_BACKOFF_BASE * 2));
+
+        // Once the bucket is full again, the backoff starts over
+        let much_later = later + Duration::from_secs(60);
+        assert!(bucket.is_idle(much_later));
+        assert!(bucket.acquire(much_later).is_ok());
+        assert!(bucket.acquire(much_later).is_ok());
+        assert_eq!(bucket.acquire(much_later), Err(IDENTIFY_BACKOFF_BASE));
+    }
+
+    #[test]
+    fn test_prune_idle_buckets() {
+        let mut limiter = IdentifyLimiter::new();
+        let now = limiter.last_prune;
+        limiter.acquire_at(USER, now).ok();
+        assert_eq!(limiter.users.len(), 1);
+
+        limiter
+            .acquire_at(IdentifyKey::Ip(Ipv4Addr::LOCALHOST.into()), now + PRUNE_INTERVAL)
+            .ok();
+        assert!(limiter.users.is_empty());
+        assert_eq!(limiter.ips.len(), 1);
+    }
+}
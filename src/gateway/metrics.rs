@@ -0,0 +1,153 @@
+use std::{collections::BTreeMap, sync::Mutex, time::Duration};
+
+use serde::Serialize;
+
+use super::actor::SendMode;
+
+/// The number of times an event was dispatched with each send mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SendModeCounts {
+    pub to_user: u64,
+    pub to_mutual_guilds: u64,
+    pub to_guild: u64,
+    pub to_channel_recipients: u64,
+}
+
+impl SendModeCounts {
+    fn increment(&mut self, send_mode: SendMode) {
+        let count = match send_mode {
+            SendMode::ToUser(_) => &mut self.to_user,
+            SendMode::ToMutualGuilds(_) => &mut self.to_mutual_guilds,
+            SendMode::ToGuild(_) => &mut self.to_guild,
+            SendMode::ToChannelRecipients(_) => &mut self.to_channel_recipients,
+        };
+        *count += 1;
+    }
+}
+
+/// Counters of a single kind of event dispatched by this instance
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct EventMetrics {
+    /// The number of times the event was dispatched
+    pub dispatches: u64,
+    /// The number of dispatches per send mode
+    pub send_modes: SendModeCounts,
+    /// The number of sessions the event was sent to
+    pub recipients: u64,
+    /// The number of bytes handed to sessions, not counting sequence numbers.
+    /// Only measured for events serialized by the gateway, not for those sent to a single user.
+    pub bytes: u64,
+    /// How long serializing the event took in total, in microseconds.
+    /// Only measured for events serialized by the gateway, not for those sent to a single user.
+    pub serialization_us: u64,
+}
+
+/// A single dispatch of an event, to be added to [`DispatchMetrics`]
+#[derive(Debug, Clone, Copy)]
+pub struct DispatchSample {
+    /// The number of sessions the event was sent to
+    recipients: usize,
+    /// The number of bytes handed to sessions
+    bytes: usize,
+    /// How long serializing the event took
+    serialization: Duration,
+}
+
+impl DispatchSample {
+    pub const fn new(recipients: usize, bytes: usize, serialization: Duration) -> Self {
+        Self {
+            recipients,
+            bytes,
+            serialization,
+        }
+    }
+}
+
+/// Counters of the events dispatched by this instance since it started, per event name.
+///
+/// Lets operators see which events dominate gateway traffic, such as typing indicators compared to messages.
+#[derive(Debug)]
+pub struct DispatchMetrics {
+    events: Mutex<BTreeMap<String, EventMetrics>>,
+}
+
+impl DispatchMetrics {
+    pub const fn new() -> Self {
+        Self {
+            events: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Add a dispatch of an event to the counters
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The name of the event, such as `MESSAGE_CREATE`
+    /// * `send_mode` - Who the event was dispatched to
+    /// * `sample` - What dispatching the event took
+    pub fn record(&self, event: &str, send_mode: SendMode, sample: DispatchSample) {
+        let mut events = self.events.lock().expect("Dispatch metrics should not be poisoned");
+        if !events.contains_key(event) {
+            events.insert(event.to_owned(), EventMetrics::default());
+        }
+        let metrics = events.get_mut(event).expect("Metrics were just inserted");
+
+        metrics.dispatches += 1;
+        metrics.send_modes.increment(send_mode);
+        metrics.recipients += sample.recipients as u64;
+        metrics.bytes += sample.bytes as u64;
+        metrics.serialization_us += u64::try_from(sample.serialization.as_micros()).unwrap_or(u64::MAX);
+    }
+
+    /// Get the counters of all events dispatched so far, by event name
+    pub fn snapshot(&self) -> BTreeMap<String, EventMetrics> {
+        self.events
+            .lock()
+            .expect("Dispatch metrics should not be poisoned")
+            .clone()
+    }
+}
+
+impl Default for DispatchMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::snowflake::Snowflake;
+
+    #[test]
+    fn test_dispatch_metrics() {
+        let metrics = DispatchMetrics::new();
+        let sample = DispatchSample::new(3, 300, Duration::from_micros(20));
+        metrics.record("TYPING_START", SendMode::ToGuild(Snowflake::new(1)), sample);
+        metrics.record("TYPING_START", SendMode::ToChannelRecipients(Snowflake::new(2)), sample);
+        metrics.record(
+            "USER_UPDATE",
+            SendMode::ToUser(Snowflake::new(3)),
+            DispatchSample::new(1, 0, Duration::ZERO),
+        );
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(
+            snapshot["TYPING_START"],
+            EventMetrics {
+                dispatches: 2,
+                send_modes: SendModeCounts {
+                    to_guild: 1,
+                    to_channel_recipients: 1,
+                    ..Default::default()
+                },
+                recipients: 6,
+                bytes: 600,
+                serialization_us: 40,
+            }
+        );
+        assert_eq!(snapshot["USER_UPDATE"].send_modes.to_user, 1);
+        assert_eq!(snapshot["USER_UPDATE"].bytes, 0);
+    }
+}
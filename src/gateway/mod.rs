@@ -1,4 +1,17 @@
 pub mod actor;
+mod event_sink;
+mod fanout;
 pub mod handler;
+mod identify_limiter;
+mod metrics;
+mod poll;
+mod queue;
+mod replay;
+mod trace;
+mod typing;
 
-pub use actor::{ConnectionId, Gateway, GatewayCloseCode, SendMode};
+pub use actor::{ConnectionId, Gateway, GatewayCloseCode, PresenceAudience, SendMode, SessionInfo};
+pub use event_sink::EventSink;
+pub use identify_limiter::IdentifyKey;
+pub use metrics::{DispatchMetrics, EventMetrics, SendModeCounts};
+pub use trace::{CorrelationId, DeliveryLog, DispatchLog, DispatchRecord, EventTrace, TracedEvent};
@@ -0,0 +1,585 @@
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Extension, Json, Router,
+    extract::{ConnectInfo, Path, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use secrecy::ExposeSecret;
+use tokio::sync::{broadcast, mpsc};
+use uuid::Uuid;
+
+use super::{
+    actor::{ConnectionId, GatewayCloseCode, GatewayResponse, SequencedEvent, SessionHandle},
+    handler::{self, Rejection},
+    trace::EventTrace,
+};
+use crate::{
+    app::App,
+    models::{
+        auth::Token,
+        capability::ClientCapability,
+        errors::{GatewayError, RESTError},
+        gateway_event::{GatewayEvent, GatewayMessage},
+        user::User,
+    },
+};
+
+/// How long a poll waits for events to arrive before returning without any
+pub const POLL_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a session stays connected after it was last polled.
+///
+/// Once it elapses, the session is detached as if its connection was lost, and may still be resumed.
+pub const POLL_EXPIRY: Duration = Duration::from_secs(45);
+
+/// Get router for the long-polling transport, used by clients that cannot open a websocket
+pub fn get_router() -> Router<App> {
+    Router::new().route("/poll", post(open_session)).route(
+        "/poll/{session_id}",
+        post(send_requests).get(poll_events).delete(close_session),
+    )
+}
+
+/// A gateway session connected through long-polling
+///
+/// The session is fed by the gateway actor like any other, its responses wait in `receiver` until polled.
+#[derive(Debug)]
+pub(super) struct PollSession {
+    /// The ID of the session
+    conn_id: ConnectionId,
+    /// The user the session belongs to
+    user: User,
+    /// The attachment of the session when it was connected through this poller
+    attachment: u64,
+    /// The responses waiting to be polled, locked by the poll in progress
+    receiver: tokio::sync::Mutex<mpsc::UnboundedReceiver<GatewayResponse>>,
+    /// Broadcasts requests sent by the client to the gateway actor
+    broadcaster: Arc<broadcast::Sender<GatewayMessage>>,
+    /// The last time the client polled or sent requests
+    last_seen: Mutex<Instant>,
+}
+
+impl PollSession {
+    /// Mark the session as still being polled, postponing its expiry
+    fn touch(&self) {
+        *self.last_seen.lock().expect("Poll session should not be poisoned") = Instant::now();
+    }
+
+    /// The time since the client last polled or sent requests
+    fn idle_for(&self) -> Duration {
+        self.last_seen
+            .lock()
+            .expect("Poll session should not be poisoned")
+            .elapsed()
+    }
+}
+
+/// The sessions connected to an instance through long-polling
+#[derive(Debug)]
+pub(super) struct PollSessions {
+    sessions: Mutex<BTreeMap<Uuid, Arc<PollSession>>>,
+}
+
+impl PollSessions {
+    pub const fn new() -> Self {
+        Self {
+            sessions: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Get a session, if it belongs to the given user
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user polling the session
+    /// * `id` - The ID of the session
+    fn get(&self, user: &Token, id: Uuid) -> Option<Arc<PollSession>> {
+        self.sessions
+            .lock()
+            .expect("Poll sessions should not be poisoned")
+            .get(&id)
+            .filter(|s| s.conn_id.0 == user.data().user_id())
+            .cloned()
+    }
+
+    /// Register a session, replacing the poller of the session if it was resumed through long-polling again
+    fn insert(&self, session: Arc<PollSession>) {
+        self.sessions
+            .lock()
+            .expect("Poll sessions should not be poisoned")
+            .insert(session.conn_id.1, session);
+    }
+
+    /// Remove a session, unless it was replaced by another poller since
+    ///
+    /// ## Returns
+    ///
+    /// Whether the session was removed. Only the caller that removed it should tear it down.
+    fn remove(&self, session: &Arc<PollSession>) -> bool {
+        let mut sessions = self.sessions.lock().expect("Poll sessions should not be poisoned");
+
+        if sessions
+            .get(&session.conn_id.1)
+            .is_some_and(|s| Arc::ptr_eq(s, session))
+        {
+            sessions.remove(&session.conn_id.1);
+            return true;
+        }
+        false
+    }
+}
+
+/// The responses collected by a poll, serialized the same way they are sent over a websocket
+#[derive(Debug, Default)]
+struct PollBatch {
+    events: Vec<String>,
+    /// The traced events in the batch, along with their sequence number
+    deliveries: Vec<(EventTrace, u64)>,
+    /// The code and reason the session was closed with, if it was
+    close: Option<(GatewayCloseCode, String)>,
+}
+
+impl PollBatch {
+    /// Add a response to the batch
+    ///
+    /// ## Arguments
+    ///
+    /// * `response` - The response to add
+    fn push(&mut self, response: GatewayResponse) {
+        match response {
+            GatewayResponse::Event(event) => {
+                if let (Some(trace), Some(seq)) = (event.trace(), event.seq()) {
+                    self.deliveries.push((trace, seq));
+                }
+                self.events.push(
+                    serde_json::to_string(&event).expect("Expected Serializable object to not fail serialization"),
+                );
+            }
+            GatewayResponse::Prepared(event, seq) => {
+                if let Some(seq) = seq {
+                    self.deliveries.push((event.trace(), seq));
+                }
+                self.events.push(event.to_text(seq));
+            }
+            GatewayResponse::Close(code, reason) => {
+                self.close = Some((code, reason));
+            }
+        }
+    }
+
+    /// Whether the session was closed, no further responses should be added
+    const fn is_closed(&self) -> bool {
+        self.close.is_some()
+    }
+
+    /// The response body, a JSON array of the events, ending with a `CLOSING` event if the session was closed
+    fn into_body(mut self) -> String {
+        if let Some((code, reason)) = self.close {
+            let closing = GatewayEvent::Closing {
+                code,
+                reason,
+                reconnect: code.allows_reconnect(),
+                retry_after: None,
+            };
+            self.events
+                .push(serde_json::to_string(&closing).expect("Failed to serialize CLOSING payload"));
+        }
+        format!("[{}]", self.events.join(","))
+    }
+}
+
+/// A JSON array of events, as returned by all long-polling endpoints
+fn events_response(status: StatusCode, body: String) -> Response {
+    (status, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+/// Respond to a refused handshake with its `CLOSING` event
+fn rejection_response(rejection: &Rejection) -> Response {
+    let status = match rejection.code() {
+        GatewayCloseCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+        GatewayCloseCode::PolicyViolation => StatusCode::UNAUTHORIZED,
+        GatewayCloseCode::AccountSuspended | GatewayCloseCode::AccountTerminated => StatusCode::FORBIDDEN,
+        GatewayCloseCode::InvalidSession => StatusCode::NOT_FOUND,
+        GatewayCloseCode::InvalidPayload => StatusCode::BAD_REQUEST,
+        GatewayCloseCode::ServiceRestart => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    let closing = serde_json::to_string(&rejection.closing()).expect("Failed to serialize CLOSING payload");
+    events_response(status, format!("[{closing}]"))
+}
+
+/// Collect the responses waiting for a session
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `session` - The session to collect the responses of
+/// * `wait` - How long to wait for the first response, if none are waiting yet
+///
+/// ## Errors
+///
+/// * [`RESTError::Conflict`] - If the session is already being polled
+async fn collect(app: &App, session: &Arc<PollSession>, wait: Duration) -> Result<PollBatch, RESTError> {
+    let Ok(mut receiver) = session.receiver.try_lock() else {
+        return Err(RESTError::Conflict("The session is already being polled.".into()));
+    };
+    let mut batch = PollBatch::default();
+
+    // The session handle is gone if all of its senders were dropped
+    match tokio::time::timeout(wait, receiver.recv()).await {
+        Ok(Some(response)) => batch.push(response),
+        Ok(None) => batch.push(GatewayResponse::Close(
+            GatewayCloseCode::InvalidSession,
+            "Session no longer exists".into(),
+        )),
+        Err(_) => {}
+    }
+    while !batch.is_closed() {
+        match receiver.try_recv() {
+            Ok(response) => batch.push(response),
+            Err(mpsc::error::TryRecvError::Empty) => break,
+            Err(mpsc::error::TryRecvError::Disconnected) => batch.push(GatewayResponse::Close(
+                GatewayCloseCode::InvalidSession,
+                "Session no longer exists".into(),
+            )),
+        }
+    }
+    drop(receiver);
+
+    for (trace, seq) in &batch.deliveries {
+        handler::record_delivery(app, session.conn_id, Some(*trace), Some(*seq));
+    }
+    if let Some((code, _)) = &batch.close {
+        disconnect(app, session, *code).await;
+    }
+    session.touch();
+    Ok(batch)
+}
+
+/// Tear down the poller of a session, detaching the session as if its connection was lost
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `session` - The session to tear down
+/// * `code` - The code the session was closed with
+async fn disconnect(app: &App, session: &Arc<PollSession>, code: GatewayCloseCode) {
+    if !app.gateway().polls().remove(session) {
+        return;
+    }
+
+    app.gateway().detach_session(session.conn_id, session.attachment);
+
+    // If we're shutting down, don't spam out presence updates
+    if matches!(code, GatewayCloseCode::GoingAway) {
+        return;
+    }
+
+    tracing::debug!(user = ?session.user, "Stopped polling: {} ({})", session.user.username(), session.conn_id);
+    handler::dispatch_offline(app, &session.user).await;
+}
+
+/// Detach the session once the client stops polling it for [`POLL_EXPIRY`]
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `session` - The session to watch, the watcher stops once it is torn down
+fn spawn_expiry(app: App, session: &Arc<PollSession>) {
+    let session = Arc::downgrade(session);
+
+    tokio::spawn(async move {
+        let mut remaining = POLL_EXPIRY;
+        loop {
+            tokio::time::sleep(remaining).await;
+            let Some(session) = Weak::upgrade(&session) else {
+                return;
+            };
+
+            let idle_for = session.idle_for();
+            // A poll in progress always ends before the session expires
+            if idle_for >= POLL_EXPIRY && session.receiver.try_lock().is_ok() {
+                tracing::debug!("Session {} was not polled within {:?}", session.conn_id, POLL_EXPIRY);
+                disconnect(&app, &session, GatewayCloseCode::PolicyViolation).await;
+                return;
+            }
+            remaining = POLL_EXPIRY.saturating_sub(idle_for).max(Duration::from_secs(1));
+        }
+    });
+}
+
+/// Start or resume a session through long-polling
+///
+/// ## Arguments
+///
+/// * `payload` - An `IDENTIFY` or `RESUME` request, as sent over a websocket
+///
+/// ## Returns
+///
+/// A JSON array of the events sent to the session so far: `READY` and `GUILD_CREATE` if it was identified,
+/// or the missed events followed by `RESUMED` if it was resumed.
+/// If the handshake was refused, the array only contains the `CLOSING` event a websocket would have been sent.
+///
+/// ## Endpoint
+///
+/// POST `/gateway/v1/poll`
+async fn open_session(
+    State(app): State<App>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    Json(payload): Json<GatewayMessage>,
+) -> Result<Response, RESTError> {
+    let ip = connect_info.map(|Extension(ConnectInfo(addr))| addr.ip());
+
+    if !app.gateway().is_started() {
+        return Ok(rejection_response(&Rejection::new(
+            GatewayCloseCode::ServiceRestart,
+            "Gateway is restarting",
+            GatewayError::InternalServerError("Gateway is restarting".into()),
+        )));
+    }
+
+    let (token, resume, capabilities) = match payload {
+        GatewayMessage::Identify { token, capabilities } => (token, None, capabilities),
+        // Resumed sessions keep the capabilities they were identified with
+        GatewayMessage::Resume { token, session_id, seq } => {
+            (token, Some((session_id, seq)), ClientCapability::default())
+        }
+        _ => {
+            return Ok(rejection_response(&Rejection::new(
+                GatewayCloseCode::InvalidPayload,
+                "Invalid IDENTIFY payload",
+                GatewayError::MalformedFrame("Invalid IDENTIFY payload".into()),
+            )));
+        }
+    };
+
+    let user = match handler::authorize(&app, token.expose_secret(), ip).await {
+        Ok(user) => user,
+        Err(rejection) => {
+            tracing::debug!(?rejection, "Refused long-polling handshake");
+            return Ok(rejection_response(&rejection));
+        }
+    };
+
+    let conn_id = ConnectionId(
+        user.id(),
+        resume.map_or_else(Uuid::new_v4, |(session_id, _)| session_id),
+    );
+
+    let (sender, receiver) = mpsc::unbounded_channel::<GatewayResponse>();
+    let (broadcaster, _) = broadcast::channel::<GatewayMessage>(8);
+    let broadcaster = Arc::new(broadcaster);
+
+    let handle = SessionHandle::new(sender.clone(), broadcaster.clone()).with_capabilities(capabilities);
+
+    // Add user to peermap, or take over the session being resumed
+    let attachment = if let Some((_, seq)) = resume {
+        let Some(attachment) = app.gateway().resume_session(conn_id, handle, seq).await else {
+            return Ok(rejection_response(&Rejection::new(
+                GatewayCloseCode::InvalidSession,
+                "Session cannot be resumed",
+                GatewayError::HandshakeFailure("Session cannot be resumed".into()),
+            )));
+        };
+        attachment
+    } else {
+        app.gateway().create_session(conn_id, handle, *user.last_presence());
+        0
+    };
+
+    if let Err(e) = app
+        .ops()
+        .instances()
+        .record_connection(app.instance_id(), user.id())
+        .await
+    {
+        tracing::error!(error = %e, "Failed to record gateway connection");
+    }
+
+    tracing::debug!(?user, "Started polling: {} ({})", user.username(), conn_id);
+
+    let session = Arc::new(PollSession {
+        conn_id,
+        user: user.clone(),
+        attachment,
+        receiver: tokio::sync::Mutex::new(receiver),
+        broadcaster,
+        last_seen: Mutex::new(Instant::now()),
+    });
+    app.gateway().polls().insert(session.clone());
+    spawn_expiry(app.clone(), &session);
+
+    // Send READY and guild creates to user, resumed sessions already have this state
+    if resume.is_some() {
+        handler::dispatch_presence(&app, &user).await;
+    } else {
+        let send = |event| {
+            let response = GatewayResponse::Event(SequencedEvent::new(Arc::new(event), None));
+            std::future::ready(sender.send(response))
+        };
+        if let Err(e) = handler::send_onboarding_payloads(app.clone(), user, conn_id.1, capabilities, send).await {
+            tracing::warn!(error = %e, "Error sending onboarding payloads to {conn_id}");
+        }
+    }
+    drop(sender);
+
+    let batch = collect(&app, &session, Duration::ZERO).await?;
+    Ok(events_response(StatusCode::OK, batch.into_body()))
+}
+
+/// Wait for events sent to a long-polling session
+///
+/// Returns as soon as any events are available, or after [`POLL_TIMEOUT`] without any.
+/// A session may only be polled by one request at a time.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `session_id` - The ID of the session to poll
+///
+/// ## Returns
+///
+/// A JSON array of the events sent to the session since the last poll.
+/// If the session was closed, the last event is `CLOSING`, and the session can no longer be polled.
+///
+/// ## Endpoint
+///
+/// GET `/gateway/v1/poll/{session_id}`
+async fn poll_events(
+    Path(session_id): Path<Uuid>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Response, RESTError> {
+    let session = app
+        .gateway()
+        .polls()
+        .get(&token, session_id)
+        .ok_or(RESTError::NotFound("Session not found.".into()))?;
+    session.touch();
+
+    let batch = collect(&app, &session, POLL_TIMEOUT).await?;
+    Ok(events_response(StatusCode::OK, batch.into_body()))
+}
+
+/// Send requests to the gateway through a long-polling session
+///
+/// Requests are handled the same way as if they were sent over a websocket, and their responses
+/// (such as `HEARTBEAT_ACK`) are returned by the next poll. Sending requests also keeps the session from expiring.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `session_id` - The ID of the session to send the requests through
+/// * `payload` - The requests to send, in order
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// POST `/gateway/v1/poll/{session_id}`
+async fn send_requests(
+    Path(session_id): Path<Uuid>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<Vec<GatewayMessage>>,
+) -> Result<StatusCode, RESTError> {
+    let session = app
+        .gateway()
+        .polls()
+        .get(&token, session_id)
+        .ok_or(RESTError::NotFound("Session not found.".into()))?;
+    session.touch();
+
+    for msg in payload {
+        tracing::debug!(?msg, "Received message from {}", session.conn_id);
+        // Polling already proves the client is alive, heartbeats are only acknowledged
+        if matches!(msg, GatewayMessage::Heartbeat) {
+            app.gateway()
+                .send_to_session(session.conn_id, GatewayEvent::HeartbeatAck);
+        }
+        if session.broadcaster.send(msg).is_err() {
+            return Err(RESTError::NotFound("Session not found.".into()));
+        }
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Close a long-polling session, it can no longer be resumed
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `session_id` - The ID of the session to close
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/gateway/v1/poll/{session_id}`
+async fn close_session(
+    Path(session_id): Path<Uuid>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    let session = app
+        .gateway()
+        .polls()
+        .get(&token, session_id)
+        .ok_or(RESTError::NotFound("Session not found.".into()))?;
+
+    app.gateway().close_session(
+        session.conn_id,
+        GatewayCloseCode::Normal,
+        "Session closed by client".into(),
+    );
+    disconnect(&app, &session, GatewayCloseCode::Normal).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway::fanout::PreparedEvent;
+
+    #[test]
+    fn test_poll_batch_body() {
+        let mut batch = PollBatch::default();
+        batch.push(GatewayResponse::Event(SequencedEvent::new(
+            Arc::new(GatewayEvent::HeartbeatAck),
+            None,
+        )));
+        let trace = EventTrace::new(None);
+        batch.push(GatewayResponse::Prepared(
+            PreparedEvent::new(&GatewayEvent::Resumed, trace),
+            Some(3),
+        ));
+        assert!(!batch.is_closed());
+        batch.push(GatewayResponse::Close(
+            GatewayCloseCode::RateLimited,
+            "Too many requests were dropped".into(),
+        ));
+        assert!(batch.is_closed());
+        assert_eq!(batch.deliveries.len(), 1);
+
+        let body: serde_json::Value = serde_json::from_str(&batch.into_body()).expect("body should be valid JSON");
+        let events = body.as_array().expect("body should be an array");
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0]["event"], "HEARTBEAT_ACK");
+        assert_eq!(events[1]["event"], "RESUMED");
+        assert_eq!(events[1]["seq"], 3);
+        assert_eq!(events[2]["event"], "CLOSING");
+        assert_eq!(events[2]["data"]["code"], 4001);
+        assert_eq!(events[2]["data"]["reconnect"], true);
+    }
+}
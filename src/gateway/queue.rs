@@ -0,0 +1,174 @@
+use std::collections::VecDeque;
+
+/// The amount of consecutive items served from higher priority classes
+/// before a waiting lower priority item is served anyway.
+pub const STARVATION_LIMIT: usize = 32;
+
+/// The priority class of a gateway instruction. Lower classes are served first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum Priority {
+    /// Session lifecycle and queries that callers are actively waiting on
+    Control = 0,
+    /// Events that carry content, such as messages
+    Messages = 1,
+    /// High-volume, short-lived events such as typing and presence updates
+    Ambient = 2,
+}
+
+impl Priority {
+    const COUNT: usize = 3;
+}
+
+/// A queue serving items by priority class, and in FIFO order within a class.
+///
+/// To prevent starvation, a waiting item is served regardless of its priority
+/// once [`STARVATION_LIMIT`] items from higher classes were served ahead of it.
+#[derive(Debug)]
+pub(super) struct PriorityQueue<T> {
+    queues: [VecDeque<T>; Priority::COUNT],
+    /// How many items were served ahead of each class while it had items waiting
+    skipped: [usize; Priority::COUNT],
+    starvation_limit: usize,
+}
+
+impl<T> PriorityQueue<T> {
+    /// Create a new empty queue with the given starvation limit.
+    pub fn new(starvation_limit: usize) -> Self {
+        Self {
+            queues: Default::default(),
+            skipped: [0; Priority::COUNT],
+            starvation_limit,
+        }
+    }
+
+    /// Add an item to the back of its priority class.
+    pub fn push(&mut self, priority: Priority, item: T) {
+        self.queues[priority as usize].push_back(item);
+    }
+
+    /// Remove the next item to be served.
+    pub fn pop(&mut self) -> Option<T> {
+        // Starved classes are served first, lowest priority first as it has likely waited the longest
+        let index = (0..Priority::COUNT)
+            .rev()
+            .find(|&i| !self.queues[i].is_empty() && self.skipped[i] >= self.starvation_limit)
+            .or_else(|| (0..Priority::COUNT).find(|&i| !self.queues[i].is_empty()))?;
+
+        for (i, skipped) in self.skipped.iter_mut().enumerate() {
+            if i == index || self.queues[i].is_empty() {
+                *skipped = 0;
+            } else if i > index {
+                *skipped += 1;
+            }
+        }
+
+        self.queues[index].pop_front()
+    }
+
+    /// The amount of items waiting in the given priority class.
+    #[cfg(test)]
+    pub fn len_of(&self, priority: Priority) -> usize {
+        self.queues[priority as usize].len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_order() {
+        let mut queue = PriorityQueue::new(STARVATION_LIMIT);
+        queue.push(Priority::Ambient, "typing");
+        queue.push(Priority::Messages, "message 1");
+        queue.push(Priority::Control, "close");
+        queue.push(Priority::Messages, "message 2");
+
+        assert_eq!(queue.pop(), Some("close"));
+        assert_eq!(queue.pop(), Some("message 1"));
+        assert_eq!(queue.pop(), Some("message 2"));
+        assert_eq!(queue.pop(), Some("typing"));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn test_starvation_protection() {
+        let mut queue = PriorityQueue::new(3);
+        queue.push(Priority::Ambient, 0);
+        for i in 1..=10 {
+            queue.push(Priority::Messages, i);
+        }
+
+        // The ambient item is served after 3 message items were served ahead of it
+        let served: Vec<_> = std::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(served, vec![1, 2, 3, 0, 4, 5, 6, 7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn test_skipped_resets_when_drained() {
+        let mut queue = PriorityQueue::new(2);
+        queue.push(Priority::Ambient, 0);
+        queue.push(Priority::Messages, 1);
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(0));
+
+        // The ambient class was drained, so it starts waiting from scratch
+        queue.push(Priority::Ambient, 2);
+        queue.push(Priority::Messages, 3);
+        queue.push(Priority::Messages, 4);
+        queue.push(Priority::Messages, 5);
+        assert_eq!(queue.len_of(Priority::Messages), 3);
+        let served: Vec<_> = std::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(served, vec![3, 4, 2, 5]);
+    }
+
+    /// Simulates a burst of typing events interleaved with a trickle of messages,
+    /// processed at a fixed rate, and compares the queueing delay of messages
+    /// against plain FIFO processing.
+    #[test]
+    fn test_message_tail_latency_under_typing_burst() {
+        const TICKS: usize = 10_000;
+        const TYPING_PER_TICK: usize = 4;
+        const PROCESSED_PER_TICK: usize = 3;
+        const MESSAGE_EVERY: usize = 10;
+
+        fn p99(mut latencies: Vec<usize>) -> usize {
+            latencies.sort_unstable();
+            latencies[latencies.len() * 99 / 100]
+        }
+
+        let mut fifo = VecDeque::new();
+        let mut queue = PriorityQueue::new(STARVATION_LIMIT);
+        let (mut fifo_latencies, mut queue_latencies) = (Vec::new(), Vec::new());
+        let mut typing_served = 0;
+
+        for tick in 0..TICKS {
+            for _ in 0..TYPING_PER_TICK {
+                fifo.push_back((Priority::Ambient, tick));
+                queue.push(Priority::Ambient, (Priority::Ambient, tick));
+            }
+            if tick % MESSAGE_EVERY == 0 {
+                fifo.push_back((Priority::Messages, tick));
+                queue.push(Priority::Messages, (Priority::Messages, tick));
+            }
+
+            for _ in 0..PROCESSED_PER_TICK {
+                if let Some((Priority::Messages, sent)) = fifo.pop_front() {
+                    fifo_latencies.push(tick - sent);
+                }
+                match queue.pop() {
+                    Some((Priority::Messages, sent)) => queue_latencies.push(tick - sent),
+                    Some(_) => typing_served += 1,
+                    None => {}
+                }
+            }
+        }
+
+        // The gateway is overloaded, so FIFO delivery falls further and further behind
+        assert!(p99(fifo_latencies) > 1000);
+        // Messages skip the typing backlog and are delivered immediately
+        assert_eq!(p99(queue_latencies), 0);
+        // Typing events still make progress
+        assert!(typing_served > TICKS * 2);
+    }
+}
@@ -0,0 +1,152 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use crate::models::gateway_event::GatewayEvent;
+
+/// The maximum amount of un-acknowledged events retained per session.
+pub const REPLAY_BUFFER_SIZE: usize = 1000;
+
+/// A bounded buffer of sequenced events that have not been acknowledged by the client yet.
+///
+/// Every sequenced event sent to a session is assigned the next sequence number and retained here
+/// until the client acknowledges it, so that it can be re-delivered if the session is resumed.
+#[derive(Debug)]
+pub(super) struct ReplayBuffer {
+    /// The sequence number of the last event pushed to the buffer
+    last_seq: u64,
+    /// The events that have not been acknowledged yet, in ascending order of sequence numbers
+    events: VecDeque<(u64, Arc<GatewayEvent>)>,
+    /// The maximum amount of events retained
+    capacity: usize,
+}
+
+impl ReplayBuffer {
+    /// Create a new empty buffer retaining at most `capacity` events.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            last_seq: 0,
+            events: VecDeque::with_capacity(capacity.min(64)),
+            capacity,
+        }
+    }
+
+    /// Assign the next sequence number to the given event and retain it.
+    /// If the buffer is full, the oldest event is evicted.
+    ///
+    /// ## Returns
+    ///
+    /// The sequence number assigned to the event
+    pub fn push(&mut self, event: Arc<GatewayEvent>) -> u64 {
+        self.last_seq += 1;
+
+        if self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back((self.last_seq, event));
+        self.last_seq
+    }
+
+    /// Drop all events up to and including the given sequence number.
+    pub fn ack(&mut self, seq: u64) {
+        while self.events.front().is_some_and(|(s, _)| *s <= seq) {
+            self.events.pop_front();
+        }
+    }
+
+    /// Get all events the client has missed after the given sequence number.
+    ///
+    /// ## Returns
+    ///
+    /// The missing events in order, or `None` if `seq` is ahead of the session,
+    /// or if some of the missing events were already evicted or acknowledged.
+    pub fn replay_after(&self, seq: u64) -> Option<Vec<(u64, Arc<GatewayEvent>)>> {
+        if seq > self.last_seq {
+            return None;
+        }
+        if seq == self.last_seq {
+            return Some(Vec::new());
+        }
+        // The first missing event must still be retained
+        if self.events.front().is_none_or(|(first, _)| *first > seq + 1) {
+            return None;
+        }
+
+        Some(self.events.iter().filter(|(s, _)| *s > seq).cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event() -> Arc<GatewayEvent> {
+        Arc::new(GatewayEvent::HeartbeatAck)
+    }
+
+    fn seqs(events: &[(u64, Arc<GatewayEvent>)]) -> Vec<u64> {
+        events.iter().map(|(s, _)| *s).collect()
+    }
+
+    #[test]
+    fn test_push_assigns_sequence_numbers() {
+        let mut buffer = ReplayBuffer::new(10);
+        assert_eq!(buffer.last_seq, 0);
+        assert_eq!(buffer.push(event()), 1);
+        assert_eq!(buffer.push(event()), 2);
+        assert_eq!(buffer.last_seq, 2);
+    }
+
+    #[test]
+    fn test_replay_after() {
+        let mut buffer = ReplayBuffer::new(10);
+        for _ in 0..5 {
+            buffer.push(event());
+        }
+
+        assert_eq!(
+            seqs(&buffer.replay_after(2).expect("window should be available")),
+            vec![3, 4, 5]
+        );
+        assert_eq!(
+            seqs(&buffer.replay_after(0).expect("window should be available")),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert!(buffer.replay_after(5).expect("nothing is missing").is_empty());
+        // The client can't be ahead of the server
+        assert!(buffer.replay_after(6).is_none());
+    }
+
+    #[test]
+    fn test_ack() {
+        let mut buffer = ReplayBuffer::new(10);
+        for _ in 0..5 {
+            buffer.push(event());
+        }
+
+        buffer.ack(3);
+        assert_eq!(
+            seqs(&buffer.replay_after(3).expect("window should be available")),
+            vec![4, 5]
+        );
+        // Acknowledged events can no longer be replayed
+        assert!(buffer.replay_after(2).is_none());
+
+        buffer.ack(5);
+        assert!(buffer.replay_after(5).expect("nothing is missing").is_empty());
+        assert!(buffer.replay_after(4).is_none());
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut buffer = ReplayBuffer::new(3);
+        for _ in 0..5 {
+            buffer.push(event());
+        }
+
+        assert_eq!(
+            seqs(&buffer.replay_after(2).expect("window should be available")),
+            vec![3, 4, 5]
+        );
+        // Event 2 was evicted, so the window starting after 1 is incomplete
+        assert!(buffer.replay_after(1).is_none());
+    }
+}
@@ -0,0 +1,447 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display, Formatter},
+    sync::Mutex,
+    time::Instant,
+};
+
+use opentelemetry::trace::{TraceContextExt, TraceId};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use uuid::Uuid;
+
+use super::actor::{ConnectionId, SendMode};
+use crate::models::{gateway_event::GatewayEvent, snowflake::Snowflake, user::User};
+
+/// The number of most recently dispatched events whose deliveries are retained
+pub const DELIVERY_LOG_SIZE: usize = 1024;
+/// The maximum number of deliveries retained per event, further deliveries are only counted
+pub const MAX_TRACED_DELIVERIES: usize = 1000;
+
+/// The ID of the distributed trace an event originated from, such as the REST request that caused it
+///
+/// Serialized as 32 lowercase hex characters, the same format as the trace ID of a W3C `traceparent` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CorrelationId(TraceId);
+
+impl CorrelationId {
+    /// The trace of the current span, if it is part of one
+    pub fn current() -> Option<Self> {
+        let context = tracing::Span::current().context();
+        let span = context.span();
+        let span_context = span.span_context();
+        span_context.is_valid().then(|| Self(span_context.trace_id()))
+    }
+}
+
+impl Display for CorrelationId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Serialize for CorrelationId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for CorrelationId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        TraceId::from_hex(&hex).map(Self).map_err(de::Error::custom)
+    }
+}
+
+/// Identifies an event from the moment it is dispatched until it is sent to each session
+///
+/// Captured when the event is dispatched, and carried through the outbox and the gateway actor,
+/// so that the frames sent to sessions can be tied back to the request that caused them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventTrace {
+    /// Unique to each dispatch of an event
+    event_id: Uuid,
+    /// The trace the event originated from, if the dispatch happened within one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<CorrelationId>,
+}
+
+impl EventTrace {
+    /// Create a trace for a new event
+    ///
+    /// ## Arguments
+    ///
+    /// * `correlation_id` - The trace the event originated from, if any
+    pub fn new(correlation_id: Option<CorrelationId>) -> Self {
+        Self {
+            event_id: Uuid::new_v4(),
+            correlation_id,
+        }
+    }
+
+    /// Create a trace for a new event, originating from the trace of the current span
+    pub fn current() -> Self {
+        Self::new(CorrelationId::current())
+    }
+
+    /// The ID of the event
+    pub const fn event_id(&self) -> Uuid {
+        self.event_id
+    }
+
+    /// The trace the event originated from, if any
+    pub const fn correlation_id(&self) -> Option<CorrelationId> {
+        self.correlation_id
+    }
+}
+
+/// A single delivery of a traced event to a session
+#[derive(Debug, Clone, Serialize)]
+pub struct TracedDelivery {
+    /// The user the session belongs to
+    pub user_id: Snowflake<User>,
+    /// The session the event was sent to
+    pub session_id: Uuid,
+    /// The sequence number the session assigned to the event
+    pub seq: u64,
+    /// When the event was sent to the session, as a UNIX timestamp in milliseconds
+    pub delivered_at: i64,
+}
+
+/// A dispatched event along with the sessions it was sent to so far
+#[derive(Debug, Clone, Serialize)]
+pub struct TracedEvent {
+    #[serde(flatten)]
+    trace: EventTrace,
+    /// When the gateway started dispatching the event, as a UNIX timestamp in milliseconds
+    dispatched_at: i64,
+    deliveries: Vec<TracedDelivery>,
+    /// Deliveries that were not retained because the event exceeded [`MAX_TRACED_DELIVERIES`]
+    omitted_deliveries: usize,
+}
+
+impl TracedEvent {
+    /// The sessions the event was sent to so far
+    pub fn deliveries(&self) -> &[TracedDelivery] {
+        &self.deliveries
+    }
+}
+
+/// The sessions the most recently dispatched events were sent to, for diagnosing missed events
+///
+/// Only sequenced events are traced. Each instance only knows about the sessions connected to it.
+#[derive(Debug)]
+pub struct DeliveryLog {
+    events: Mutex<VecDeque<TracedEvent>>,
+}
+
+impl DeliveryLog {
+    pub const fn new() -> Self {
+        Self {
+            events: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Start tracing an event that is being dispatched, evicting the oldest event if the log is full
+    ///
+    /// ## Arguments
+    ///
+    /// * `trace` - The trace of the event
+    pub fn register(&self, trace: EventTrace) {
+        let mut events = self.events.lock().expect("Delivery log should not be poisoned");
+        if events.len() >= DELIVERY_LOG_SIZE {
+            events.pop_front();
+        }
+        events.push_back(TracedEvent {
+            trace,
+            dispatched_at: chrono::Utc::now().timestamp_millis(),
+            deliveries: Vec::new(),
+            omitted_deliveries: 0,
+        });
+    }
+
+    /// Record that an event was sent to a session. Events that are no longer retained are ignored.
+    ///
+    /// ## Arguments
+    ///
+    /// * `trace` - The trace of the event
+    /// * `conn` - The session the event was sent to
+    /// * `seq` - The sequence number the session assigned to the event
+    pub fn record(&self, trace: EventTrace, conn: ConnectionId, seq: u64) {
+        let mut events = self.events.lock().expect("Delivery log should not be poisoned");
+        // Events are sent shortly after being registered, so they are usually found near the end
+        let Some(event) = events.iter_mut().rev().find(|e| e.trace.event_id == trace.event_id) else {
+            return;
+        };
+
+        if event.deliveries.len() >= MAX_TRACED_DELIVERIES {
+            event.omitted_deliveries += 1;
+            return;
+        }
+        event.deliveries.push(TracedDelivery {
+            user_id: conn.0,
+            session_id: conn.1,
+            seq,
+            delivered_at: chrono::Utc::now().timestamp_millis(),
+        });
+    }
+
+    /// Get a traced event along with the sessions it was sent to so far
+    ///
+    /// ## Arguments
+    ///
+    /// * `event_id` - The ID of the event
+    ///
+    /// ## Returns
+    ///
+    /// The traced event, or `None` if it is not retained
+    pub fn get(&self, event_id: Uuid) -> Option<TracedEvent> {
+        self.events
+            .lock()
+            .expect("Delivery log should not be poisoned")
+            .iter()
+            .rev()
+            .find(|e| e.trace.event_id == event_id)
+            .cloned()
+    }
+}
+
+impl Default for DeliveryLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A dispatch of an event to the sessions connected to this instance
+#[derive(Debug, Clone, Serialize)]
+pub struct DispatchRecord {
+    #[serde(flatten)]
+    trace: EventTrace,
+    /// The name of the event, such as `MESSAGE_CREATE`
+    event: String,
+    /// Who the event was dispatched to
+    send_mode: SendMode,
+    /// The session the event was sent to, if it was only sent to a single session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_id: Option<Uuid>,
+    /// The number of sessions the event was sent to
+    recipients: usize,
+    /// The number of sessions connected to this instance the event was not sent to,
+    /// because they are not addressed by the send mode, cannot view the channel, or do not support the event
+    skipped: usize,
+    /// The number of sessions sending the event to failed, which were detached
+    failed: usize,
+    /// When the gateway started dispatching the event, as a UNIX timestamp in milliseconds
+    dispatched_at: i64,
+    /// How long it took to hand the event to all sessions, in microseconds
+    duration_us: u64,
+}
+
+impl DispatchRecord {
+    /// Create a record of an event that was just dispatched
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The event that was dispatched
+    /// * `send_mode` - Who the event was dispatched to
+    /// * `trace` - The trace of the event
+    /// * `started` - When the gateway started dispatching the event
+    pub fn new(event: &GatewayEvent, send_mode: SendMode, trace: EventTrace, started: Instant) -> Self {
+        let elapsed = started.elapsed();
+        let dispatched_at = chrono::Utc::now() - chrono::TimeDelta::from_std(elapsed).unwrap_or_default();
+        Self {
+            trace,
+            event: event.name().to_owned(),
+            send_mode,
+            session_id: None,
+            recipients: 0,
+            skipped: 0,
+            failed: 0,
+            dispatched_at: dispatched_at.timestamp_millis(),
+            duration_us: u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX),
+        }
+    }
+
+    /// Set the single session the event was sent to
+    #[must_use]
+    pub const fn with_session(mut self, session_id: Uuid) -> Self {
+        self.session_id = Some(session_id);
+        self
+    }
+
+    /// Set the number of sessions the event was sent to, skipped for, and failed to be sent to
+    #[must_use]
+    pub const fn with_counts(mut self, recipients: usize, skipped: usize, failed: usize) -> Self {
+        self.recipients = recipients;
+        self.skipped = skipped;
+        self.failed = failed;
+        self
+    }
+
+    /// The trace of the event
+    pub const fn trace(&self) -> EventTrace {
+        self.trace
+    }
+
+    /// The name of the event
+    pub fn event(&self) -> &str {
+        &self.event
+    }
+
+    /// The number of sessions the event was sent to
+    pub const fn recipients(&self) -> usize {
+        self.recipients
+    }
+
+    /// The number of sessions the event was not sent to
+    pub const fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+/// The most recent dispatches of events by the gateway actor, for diagnosing missed events
+/// without enabling debug logging
+///
+/// Disabled unless `GATEWAY_DISPATCH_LOG_SIZE` is set. Only sequenced events are recorded.
+#[derive(Debug)]
+pub struct DispatchLog {
+    records: Mutex<VecDeque<DispatchRecord>>,
+}
+
+impl DispatchLog {
+    pub const fn new() -> Self {
+        Self {
+            records: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Record a dispatch, evicting the oldest records beyond the given capacity
+    ///
+    /// ## Arguments
+    ///
+    /// * `record` - The dispatch to record
+    /// * `capacity` - The number of dispatches to retain
+    pub fn record(&self, record: DispatchRecord, capacity: usize) {
+        let mut records = self.records.lock().expect("Dispatch log should not be poisoned");
+        while records.len() >= capacity.max(1) {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Get the retained dispatches, newest first
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - Only return dispatches of events with this name
+    /// * `limit` - The maximum number of dispatches to return
+    pub fn recent(&self, event: Option<&str>, limit: usize) -> Vec<DispatchRecord> {
+        self.records
+            .lock()
+            .expect("Dispatch log should not be poisoned")
+            .iter()
+            .rev()
+            .filter(|r| event.is_none_or(|event| r.event == event))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Default for DispatchLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delivery_log() {
+        let log = DeliveryLog::new();
+        let trace = EventTrace::new(None);
+        let conn = ConnectionId(Snowflake::new(1), Uuid::new_v4());
+
+        // Deliveries of events that were not registered are not retained
+        log.record(trace, conn, 1);
+        assert!(log.get(trace.event_id()).is_none());
+
+        log.register(trace);
+        log.record(trace, conn, 1);
+        let traced = log.get(trace.event_id()).expect("event should be retained");
+        assert_eq!(traced.deliveries().len(), 1);
+        assert_eq!(traced.deliveries()[0].session_id, conn.1);
+        assert_eq!(traced.deliveries()[0].seq, 1);
+
+        for seq in 0..MAX_TRACED_DELIVERIES as u64 {
+            log.record(trace, conn, seq);
+        }
+        let traced = log.get(trace.event_id()).expect("event should be retained");
+        assert_eq!(traced.deliveries().len(), MAX_TRACED_DELIVERIES);
+        assert_eq!(traced.omitted_deliveries, 1);
+
+        // The oldest events are evicted once the log is full
+        for _ in 0..DELIVERY_LOG_SIZE {
+            log.register(EventTrace::new(None));
+        }
+        assert!(log.get(trace.event_id()).is_none());
+    }
+
+    #[test]
+    fn test_dispatch_log() {
+        let log = DispatchLog::new();
+        let event = GatewayEvent::HeartbeatAck;
+
+        for i in 0..5 {
+            let record = DispatchRecord::new(
+                &event,
+                SendMode::ToGuild(Snowflake::new(1)),
+                EventTrace::new(None),
+                Instant::now(),
+            )
+            .with_counts(i, 1, 0);
+            log.record(record, 3);
+        }
+
+        let records = log.recent(None, 10);
+        assert_eq!(records.len(), 3);
+        // Newest first, the oldest records were evicted
+        assert_eq!(records[0].recipients(), 4);
+        assert_eq!(records[2].recipients(), 2);
+        assert_eq!(records[0].event(), "HEARTBEAT_ACK");
+        assert_eq!(records[0].skipped(), 1);
+
+        assert_eq!(log.recent(None, 1).len(), 1);
+        assert_eq!(log.recent(Some("HEARTBEAT_ACK"), 10).len(), 3);
+        assert!(log.recent(Some("MESSAGE_CREATE"), 10).is_empty());
+
+        let value = serde_json::to_value(&records[0]).expect("record should serialize");
+        assert_eq!(value["send_mode"]["type"], "to_guild");
+        assert!(value.get("session_id").is_none());
+        assert!(value.get("event_id").is_some());
+    }
+
+    #[test]
+    fn test_event_trace_serde() {
+        let correlation_id =
+            CorrelationId(TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").expect("trace ID should be valid hex"));
+        let trace = EventTrace::new(Some(correlation_id));
+
+        let value = serde_json::to_value(trace).expect("trace should serialize");
+        assert_eq!(value["correlation_id"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(
+            serde_json::from_value::<EventTrace>(value).expect("trace should deserialize"),
+            trace
+        );
+
+        let untraced = EventTrace::new(None);
+        let value = serde_json::to_value(untraced).expect("trace should serialize");
+        assert!(value.get("correlation_id").is_none());
+        assert_eq!(
+            serde_json::from_value::<EventTrace>(value).expect("trace should deserialize"),
+            untraced
+        );
+    }
+}
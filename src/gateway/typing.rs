@@ -0,0 +1,209 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use super::actor::SendMode;
+use crate::models::{channel::Channel, snowflake::Snowflake, user::User};
+
+/// How long a user is considered to be typing after their last `START_TYPING` request
+pub const TYPING_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often typing indicators are checked for expiry
+pub const TYPING_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The users typing in a single channel
+#[derive(Debug)]
+struct TypingChannel {
+    /// Who events about the channel are sent to
+    send_mode: SendMode,
+    /// When each user last started typing
+    users: HashMap<Snowflake<User>, Instant>,
+}
+
+/// Keeps track of who is typing in which channel, so that indicators can be expired
+/// and shown to clients that connect while someone is already typing.
+#[derive(Debug, Default)]
+pub(super) struct TypingTracker {
+    channels: HashMap<Snowflake<Channel>, TypingChannel>,
+    /// When the last message of each user in each channel was received,
+    /// kept for [`TYPING_TIMEOUT`] to tell typing indicators sent before it apart
+    last_messages: HashMap<(Snowflake<User>, Snowflake<Channel>), Instant>,
+}
+
+impl TypingTracker {
+    /// Create a new tracker with nobody typing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that a user started typing in a channel, or is still typing in it.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user that is typing
+    /// * `channel` - The channel the user is typing in
+    /// * `send_mode` - Who events about the channel are sent to
+    /// * `now` - When the user started typing
+    ///
+    /// ## Returns
+    ///
+    /// Whether the user started typing after their last message in the channel.
+    /// If not, the user is not recorded as typing, as the message already ended it.
+    pub fn start(
+        &mut self,
+        user: Snowflake<User>,
+        channel: Snowflake<Channel>,
+        send_mode: SendMode,
+        now: Instant,
+    ) -> bool {
+        if self
+            .last_messages
+            .get(&(user, channel))
+            .is_some_and(|sent| now <= *sent)
+        {
+            return false;
+        }
+        let typing = self.channels.entry(channel).or_insert_with(|| TypingChannel {
+            send_mode,
+            users: HashMap::new(),
+        });
+        typing.send_mode = send_mode;
+        typing.users.insert(user, now);
+        true
+    }
+
+    /// Record that a user sent a message in a channel, which stops them typing in it.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The author of the message
+    /// * `channel` - The channel the message was sent in
+    /// * `now` - When the message was sent
+    ///
+    /// ## Returns
+    ///
+    /// Whether the user was typing in the channel
+    pub fn stop(&mut self, user: Snowflake<User>, channel: Snowflake<Channel>, now: Instant) -> bool {
+        self.last_messages.insert((user, channel), now);
+        let Some(typing) = self.channels.get_mut(&channel) else {
+            return false;
+        };
+        let was_typing = typing.users.remove(&user).is_some();
+        if typing.users.is_empty() {
+            self.channels.remove(&channel);
+        }
+        was_typing
+    }
+
+    /// Remove all users that did not start typing again within [`TYPING_TIMEOUT`].
+    ///
+    /// ## Returns
+    ///
+    /// The users that stopped typing, along with the channel and who events about it are sent to
+    pub fn expire(&mut self, now: Instant) -> Vec<(Snowflake<User>, Snowflake<Channel>, SendMode)> {
+        let mut expired = Vec::new();
+        // Typing indicators sent before a message that is older than the timeout would have expired by now anyway
+        self.last_messages
+            .retain(|_, sent| now.saturating_duration_since(*sent) < TYPING_TIMEOUT);
+
+        self.channels.retain(|channel, typing| {
+            typing.users.retain(|user, started| {
+                let is_typing = now.saturating_duration_since(*started) < TYPING_TIMEOUT;
+                if !is_typing {
+                    expired.push((*user, *channel, typing.send_mode));
+                }
+                is_typing
+            });
+            !typing.users.is_empty()
+        });
+        expired
+    }
+
+    /// The users currently typing in a channel.
+    pub fn typing_in(&self, channel: Snowflake<Channel>) -> Vec<Snowflake<User>> {
+        self.channels
+            .get(&channel)
+            .map(|typing| typing.users.keys().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Iterate over all channels someone is typing in, along with who events about them are sent to.
+    pub fn channels(&self) -> impl Iterator<Item = (Snowflake<Channel>, SendMode)> + '_ {
+        self.channels
+            .iter()
+            .map(|(channel, typing)| (*channel, typing.send_mode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANNEL: Snowflake<Channel> = Snowflake::new(10);
+    const MODE: SendMode = SendMode::ToChannelRecipients(CHANNEL);
+
+    #[test]
+    fn test_typing_expires() {
+        let mut tracker = TypingTracker::new();
+        let now = Instant::now();
+        tracker.start(Snowflake::new(1), CHANNEL, MODE, now);
+        tracker.start(Snowflake::new(2), CHANNEL, MODE, now + Duration::from_secs(5));
+
+        assert!(tracker.expire(now + Duration::from_secs(9)).is_empty());
+        assert_eq!(tracker.typing_in(CHANNEL).len(), 2);
+
+        let expired = tracker.expire(now + TYPING_TIMEOUT);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, Snowflake::new(1));
+        assert_eq!(expired[0].1, CHANNEL);
+        assert_eq!(tracker.typing_in(CHANNEL), vec![Snowflake::new(2)]);
+
+        tracker.expire(now + Duration::from_secs(15));
+        assert!(tracker.typing_in(CHANNEL).is_empty());
+        assert_eq!(tracker.channels().count(), 0);
+    }
+
+    #[test]
+    fn test_typing_refreshed() {
+        let mut tracker = TypingTracker::new();
+        let now = Instant::now();
+        tracker.start(Snowflake::new(1), CHANNEL, MODE, now);
+        tracker.start(Snowflake::new(1), CHANNEL, MODE, now + Duration::from_secs(8));
+
+        assert!(tracker.expire(now + TYPING_TIMEOUT).is_empty());
+        assert_eq!(tracker.typing_in(CHANNEL), vec![Snowflake::new(1)]);
+    }
+
+    #[test]
+    fn test_typing_stopped() {
+        let mut tracker = TypingTracker::new();
+        let now = Instant::now();
+        tracker.start(Snowflake::new(1), CHANNEL, MODE, now);
+
+        assert!(tracker.stop(Snowflake::new(1), CHANNEL, now));
+        assert!(!tracker.stop(Snowflake::new(1), CHANNEL, now));
+        assert!(tracker.expire(now + TYPING_TIMEOUT).is_empty());
+        assert_eq!(tracker.channels().count(), 0);
+    }
+
+    #[test]
+    fn test_typing_before_message() {
+        let mut tracker = TypingTracker::new();
+        let now = Instant::now();
+        let sent = now + Duration::from_secs(1);
+        assert!(!tracker.stop(Snowflake::new(1), CHANNEL, sent));
+
+        // Typing that started before the message was sent is outdated
+        assert!(!tracker.start(Snowflake::new(1), CHANNEL, MODE, now));
+        assert!(tracker.typing_in(CHANNEL).is_empty());
+        // Other users and channels are unaffected
+        assert!(tracker.start(Snowflake::new(2), CHANNEL, MODE, now));
+        assert!(tracker.start(Snowflake::new(1), Snowflake::new(11), MODE, now));
+
+        assert!(tracker.start(Snowflake::new(1), CHANNEL, MODE, sent + Duration::from_secs(1)));
+        assert_eq!(tracker.typing_in(CHANNEL).len(), 2);
+        assert_eq!(tracker.last_messages.len(), 1);
+        tracker.expire(sent + TYPING_TIMEOUT);
+        assert!(tracker.last_messages.is_empty());
+    }
+}
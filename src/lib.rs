@@ -19,7 +19,7 @@ pub mod utils;
 pub fn main_router(state: App) -> Router {
     Router::new()
         .nest("/gateway/v1", gateway::handler::get_router())
-        .nest("/api/v1", rest::routes::get_router())
-        .layer(TraceLayer::new_for_http())
+        .nest("/api/v1", rest::routes::get_router(&state))
+        .layer(TraceLayer::new_for_http().make_span_with(app::telemetry::make_request_span))
         .with_state(state)
 }
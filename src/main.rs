@@ -1,19 +1,19 @@
 #![allow(async_fn_in_trait)]
+use std::net::SocketAddr;
 
 use axum::{ServiceExt, extract::Request};
+use axum_server::{Handle, tls_rustls::RustlsConfig};
 use chat_backend::{
-    app::{App, ApplicationState},
+    app::{App, ApplicationState, Config, telemetry},
     main_router,
+    rest::https_redirect,
 };
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{Result, WrapErr};
 use mimalloc::MiMalloc;
 use tokio::signal::ctrl_c;
 use tower::Layer;
 use tower_http::normalize_path::NormalizePathLayer;
 
-#[cfg(debug_assertions)]
-use tracing::level_filters::LevelFilter;
-
 #[cfg(unix)]
 use tokio::signal::unix::{SignalKind, signal};
 
@@ -46,23 +46,17 @@ async fn handle_signals(state: App) {
 async fn main() -> Result<()> {
     color_eyre::install()?;
 
-    #[cfg(debug_assertions)]
-    let subscriber = tracing_subscriber::fmt()
-        .compact()
-        .with_target(false)
-        .with_max_level(LevelFilter::DEBUG)
-        .without_time()
-        .finish();
-
-    #[cfg(not(debug_assertions))]
-    let subscriber = tracing_subscriber::fmt()
-        .compact()
-        .with_target(false)
-        .without_time()
-        .finish();
-
-    /* console_subscriber::init(); */
-    tracing::subscriber::set_global_default(subscriber).expect("Failed to set subscriber");
+    // Loading the config may log warnings, which would be lost before the global subscriber is installed
+    // All problems with the configuration are reported at once, before anything is started
+    let config = tracing::subscriber::with_default(
+        tracing_subscriber::fmt()
+            .compact()
+            .with_target(false)
+            .without_time()
+            .finish(),
+        Config::from_env,
+    )?;
+    let telemetry = telemetry::init(&config)?;
 
     // gcp_auth requires a TLS provider to be installed
     rustls::crypto::aws_lc_rs::default_provider()
@@ -70,25 +64,63 @@ async fn main() -> Result<()> {
         .expect("Failed to install default TLS crypto provider.");
 
     // Initialize the application state
-    let app = ApplicationState::from_env().await?;
+    let app = ApplicationState::from_config(config).await?;
     app.spawn_background_tasks();
 
     let router = main_router(app.clone());
+    // voodoo magic to make trailing slashes go away from URLs
+    let service = ServiceExt::<Request>::into_make_service_with_connect_info::<SocketAddr>(
+        NormalizePathLayer::trim_trailing_slash().layer(router),
+    );
+
+    if let Some(tls) = app.config.tls() {
+        let rustls = RustlsConfig::from_pem_file(tls.cert_path(), tls.key_path())
+            .await
+            .wrap_err("Failed to load the TLS certificate")?;
+        let redirect_addr = tls.redirect_addr();
+
+        let handle = Handle::new();
+        let shutdown = handle.clone();
+        let state = app.clone();
+        tokio::spawn(async move {
+            handle_signals(state).await;
+            shutdown.graceful_shutdown(None);
+        });
+
+        if let Some(addr) = redirect_addr {
+            let redirect = https_redirect::router(app.config.listen_addr().port());
+            let handle = handle.clone();
+            tokio::spawn(async move {
+                tracing::info!("Redirecting HTTP requests on {addr} to HTTPS");
+                if let Err(e) = axum_server::bind(addr)
+                    .handle(handle)
+                    .serve(redirect.into_make_service())
+                    .await
+                {
+                    tracing::error!(error = %e, "Failed serving HTTP redirects");
+                }
+            });
+        }
+
+        tracing::info!("Listening on {} (HTTPS)", app.config.listen_addr());
 
-    let listener = tokio::net::TcpListener::bind(app.config.listen_addr())
-        .await
-        .expect("Failed to bind address");
+        let mut server = axum_server::bind_rustls(app.config.listen_addr(), rustls).handle(handle);
+        // Allow websockets over HTTP/2, like axum::serve does
+        server.http_builder().http2().enable_connect_protocol();
+        server.serve(service).await.expect("Failed creating server");
+    } else {
+        let listener = tokio::net::TcpListener::bind(app.config.listen_addr())
+            .await
+            .expect("Failed to bind address");
 
-    tracing::info!("Listening on {}", app.config.listen_addr());
+        tracing::info!("Listening on {}", app.config.listen_addr());
 
-    axum::serve(
-        listener,
-        // voodoo magic to make trailing slashes go away from URLs
-        ServiceExt::<Request>::into_make_service(NormalizePathLayer::trim_trailing_slash().layer(router)),
-    )
-    .with_graceful_shutdown(handle_signals(app))
-    .await
-    .expect("Failed creating server");
+        axum::serve(listener, service)
+            .with_graceful_shutdown(handle_signals(app))
+            .await
+            .expect("Failed creating server");
+    }
 
+    telemetry.shutdown();
     Ok(())
 }
@@ -0,0 +1,16 @@
+use serde::Serialize;
+
+use super::{channel::Channel, message::Message, snowflake::Snowflake};
+
+/// A channel with recent activity involving a user, such as messages they sent,
+/// messages mentioning them, or messages in their direct message channels.
+#[derive(Serialize, Debug, Clone)]
+pub struct ActiveChannel {
+    pub channel: Channel,
+    /// The most recent message involving the user in the channel.
+    pub last_activity_id: Snowflake<Message>,
+    /// The most recent message mentioning the user in the channel, if any.
+    pub last_mention_id: Option<Snowflake<Message>>,
+    /// The last message the user read in the channel, if any.
+    pub last_read_message_id: Option<Snowflake<Message>>,
+}
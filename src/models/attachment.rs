@@ -1,12 +1,13 @@
 use std::sync::LazyLock;
 
 use super::{
+    byte_range::ByteRange,
     channel::Channel,
     errors::{AppError, BuildError, RESTError},
     message::{ExtendedMessageRecord, Message},
 };
 use axum::extract::multipart::Field;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use derive_builder::Builder;
 use enum_dispatch::enum_dispatch;
 use mime::Mime;
@@ -15,7 +16,14 @@ use serde::Serialize;
 
 use super::snowflake::Snowflake;
 use crate::app::App;
-use crate::external::S3Service;
+use crate::external::{
+    S3Service,
+    s3::{KEYSPACE_VERSION, ObjectClass, ObjectStream, versioned_key},
+};
+use crate::utils::image_metadata;
+
+/// The number of days a restored copy of an archived attachment is kept for.
+pub const RESTORED_ATTACHMENT_DAYS: i32 = 7;
 
 static ATTACH_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"attachment-(?P<id>[0-9])").expect("Failed to compile attachment regex"));
@@ -34,14 +42,20 @@ pub trait AttachmentLike {
     fn channel_id(&self) -> Snowflake<Channel>;
     /// The MIME-type of the file.
     fn mime(&self) -> Mime;
+    /// The version of the key layout the attachment is stored under in S3, see [`KEYSPACE_VERSION`].
+    fn key_version(&self) -> i16;
     /// The path to the attachment in S3.
     fn s3_key(&self) -> String {
-        format!(
-            "{}/{}/{}/{}",
-            self.channel_id(),
-            self.message_id(),
-            self.id(),
-            self.filename()
+        versioned_key(
+            self.key_version(),
+            ObjectClass::of_attachment(&self.mime()),
+            &format!(
+                "{}/{}/{}/{}",
+                self.channel_id(),
+                self.message_id(),
+                self.id(),
+                self.filename()
+            ),
         )
     }
 }
@@ -101,6 +115,11 @@ impl FullAttachment {
         FullAttachmentBuilder::default()
     }
 
+    /// The size of the attachment's contents, in bytes.
+    pub fn size(&self) -> usize {
+        self.content.len()
+    }
+
     /// Try to build a new [`Attachment`] from a multipart/form-data field.
     ///
     /// ## Arguments
@@ -108,6 +127,8 @@ impl FullAttachment {
     /// * `field` - The field to build from.
     /// * `channel` - The ID of the channel the message was sent to.
     /// * `message` - The ID of the message this attachment belongs to.
+    /// * `used_size` - The combined size of the attachments of the message read so far, in bytes.
+    /// * `max_size` - The maximum combined size of the attachments of the message, in bytes.
     ///
     /// ## Returns
     ///
@@ -117,11 +138,15 @@ impl FullAttachment {
     ///
     /// * [`RESTError::MissingField`] - If a required field is missing.
     /// * [`RESTError::MalformedField`] - If the attachment ID could not be parsed from the field name.
+    /// * [`RESTError::AttachmentsTooLarge`] - If the attachment would exceed `max_size`,
+    ///   reading is stopped as soon as it does.
     /// * [`RESTError::App`] - If the field contents could not be read.
     pub async fn try_from_field(
-        field: Field<'_>,
+        mut field: Field<'_>,
         channel: impl Into<Snowflake<Channel>> + Send,
         message: impl Into<Snowflake<Message>> + Send,
+        used_size: usize,
+        max_size: usize,
     ) -> Result<Self, RESTError> {
         let mut builder = Self::builder();
 
@@ -151,14 +176,40 @@ impl FullAttachment {
             .parse::<Mime>()
             .map_err(|_| RESTError::MalformedField("content type could not be parsed".into()))?;
 
+        builder.content_type(content_type);
+
+        let mut content = BytesMut::new();
+        while let Some(chunk) = field.chunk().await? {
+            if used_size + content.len() + chunk.len() > max_size {
+                return Err(RESTError::AttachmentsTooLarge(max_size));
+            }
+            content.extend_from_slice(&chunk);
+        }
+
         Ok(builder
             .channel_id(channel)
             .message_id(message)
-            .content_type(content_type)
-            .content(field.bytes().await?)
+            .content(content.freeze())
             .build()?)
     }
 
+    /// Strip metadata such as EXIF location data from the attachment, if it is a JPEG, PNG or WebP image.
+    ///
+    /// The image is re-encoded with its EXIF orientation applied.
+    /// If it cannot be processed, the content is left unchanged.
+    pub async fn strip_image_metadata(&mut self) {
+        if !image_metadata::is_supported(&self.mime()) {
+            return;
+        }
+
+        let content = self.content.clone();
+        match tokio::task::spawn_blocking(move || image_metadata::strip(&content)).await {
+            Ok(Ok(stripped)) => self.content = stripped,
+            Ok(Err(e)) => tracing::warn!(error = %e, filename = self.filename, "Failed to strip image metadata"),
+            Err(e) => tracing::error!(error = %e, "Image metadata stripping task failed"),
+        }
+    }
+
     /// Upload the attachment content to S3. This function is called implicitly by <code>[Attachment]::commit</code>.
     ///
     /// ## Errors
@@ -211,6 +262,11 @@ impl AttachmentLike for FullAttachment {
     fn mime(&self) -> Mime {
         self.content_type.parse().expect("Invalid MIME type")
     }
+
+    /// Attachments are always uploaded under the current key layout.
+    fn key_version(&self) -> i16 {
+        KEYSPACE_VERSION
+    }
 }
 
 /// A partial attachment, as stored in the database.
@@ -220,6 +276,8 @@ pub struct PartialAttachmentRecord {
     message_id: Snowflake<Message>,
     channel_id: Snowflake<Channel>,
     content_type: String,
+    quarantined: bool,
+    key_version: i16,
 }
 
 /// A partial attachment, with the binary content not loaded.
@@ -238,6 +296,14 @@ pub struct PartialAttachment {
     /// The ID of the channel the message was sent to.
     #[serde(skip)]
     channel_id: Snowflake<Channel>,
+    /// If true, the file was flagged by the attachment scanner and can no longer be downloaded.
+    #[builder(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    quarantined: bool,
+    /// The version of the key layout the file is stored under in S3.
+    #[builder(default = "KEYSPACE_VERSION")]
+    #[serde(skip)]
+    key_version: i16,
 }
 
 impl PartialAttachment {
@@ -255,25 +321,66 @@ impl PartialAttachment {
             content_type,
             channel_id: channel.into(),
             message_id: message.into(),
+            quarantined: false,
+            key_version: KEYSPACE_VERSION,
         }
     }
 
+    /// Set the version of the key layout the file is stored under in S3.
+    #[must_use]
+    pub const fn with_key_version(mut self, key_version: i16) -> Self {
+        self.key_version = key_version;
+        self
+    }
+
+    /// If true, the file was flagged by the attachment scanner and can no longer be downloaded.
+    pub const fn quarantined(&self) -> bool {
+        self.quarantined
+    }
+
     /// Download the attachment content from S3, turning this into a full attachment.
     ///
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
     pub async fn download(self, s3: &S3Service) -> Result<FullAttachment, AppError> {
-        let mut attachment = FullAttachment::new(
+        let content = s3.attachments().get_object(self.s3_key()).await?;
+        Ok(FullAttachment::new(
             self.id,
             self.filename,
-            Vec::new(),
+            content,
             self.content_type,
             self.channel_id,
             self.message_id,
-        );
-        attachment.download(s3).await?;
-        Ok(attachment)
+        ))
+    }
+
+    /// Stream the attachment content, or a range of it, from S3.
+    ///
+    /// ## Arguments
+    ///
+    /// * `s3` - The S3 service to stream from.
+    /// * `range` - If set, only this range of the file is streamed.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the file does not exist.
+    /// * [`AppError::RangeNotSatisfiable`] - If the range lies outside of the file.
+    /// * [`AppError::S3`] - If the S3 request fails.
+    pub async fn stream(&self, s3: &S3Service, range: Option<ByteRange>) -> Result<ObjectStream, AppError> {
+        s3.attachments().stream_object(self.s3_key(), range).await
+    }
+
+    /// Request a temporary copy of the attachment to be restored from archive storage,
+    /// kept for [`RESTORED_ATTACHMENT_DAYS`] days.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    pub async fn restore(&self, s3: &S3Service) -> Result<(), AppError> {
+        s3.attachments()
+            .restore_object(self.s3_key(), RESTORED_ATTACHMENT_DAYS)
+            .await
     }
 
     /// Fetches a single attachment from the database.
@@ -291,7 +398,7 @@ impl PartialAttachment {
 
         Ok(sqlx::query_as!(
             PartialAttachmentRecord,
-            "SELECT id, filename, message_id, channel_id, content_type
+            "SELECT id, filename, message_id, channel_id, content_type, quarantined, key_version
             FROM attachments
             WHERE id = $1 AND message_id = $2",
             i32::from(id),
@@ -316,7 +423,7 @@ impl PartialAttachment {
 
         Ok(sqlx::query_as!(
             PartialAttachmentRecord,
-            "SELECT id, filename, message_id, channel_id, content_type
+            "SELECT id, filename, message_id, channel_id, content_type, quarantined, key_version
             FROM attachments
             WHERE message_id = $1",
             message_id
@@ -337,6 +444,8 @@ impl From<FullAttachment> for PartialAttachment {
             channel_id: attachment.channel_id,
             message_id: attachment.message_id,
             content_type: attachment.content_type,
+            quarantined: false,
+            key_version: KEYSPACE_VERSION,
         }
     }
 }
@@ -349,6 +458,8 @@ impl From<PartialAttachmentRecord> for PartialAttachment {
             channel_id: record.channel_id,
             message_id: record.message_id,
             content_type: record.content_type,
+            quarantined: record.quarantined,
+            key_version: record.key_version,
         }
     }
 }
@@ -371,6 +482,8 @@ impl TryFrom<&ExtendedMessageRecord> for PartialAttachment {
                 .attachment_content_type
                 .clone()
                 .unwrap_or_else(|| "application/octet-stream".into()),
+            quarantined: record.attachment_quarantined.unwrap_or(false),
+            key_version: record.attachment_key_version.unwrap_or(KEYSPACE_VERSION),
         })
     }
 }
@@ -395,6 +508,8 @@ impl TryFrom<ExtendedMessageRecord> for PartialAttachment {
             content_type: record
                 .attachment_content_type
                 .unwrap_or_else(|| "application/octet-stream".into()),
+            quarantined: record.attachment_quarantined.unwrap_or(false),
+            key_version: record.attachment_key_version.unwrap_or(KEYSPACE_VERSION),
         })
     }
 }
@@ -421,4 +536,8 @@ impl AttachmentLike for PartialAttachment {
             .parse()
             .expect("Invalid MIME type stored in content_type")
     }
+
+    fn key_version(&self) -> i16 {
+        self.key_version
+    }
 }
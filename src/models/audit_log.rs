@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+
+use crate::app::Config;
+
+use super::{guild::Guild, snowflake::Snowflake, user::User};
+
+/// The kind of change an audit log entry records.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AuditLogAction {
+    /// The guild's vanity URL was claimed, changed or removed.
+    VanityUrlUpdate,
+    /// Messages in a channel were removed by its retention policy.
+    MessageExpire,
+    /// A member joined through an invite.
+    InviteUse,
+}
+
+impl AuditLogAction {
+    /// The string representation of the action, as stored in the database.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::VanityUrlUpdate => "VANITY_URL_UPDATE",
+            Self::MessageExpire => "MESSAGE_EXPIRE",
+            Self::InviteUse => "INVITE_USE",
+        }
+    }
+}
+
+/// Represents a single entry in a guild's audit log.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditLogEntry {
+    id: Snowflake<Self>,
+    guild_id: Snowflake<Guild>,
+    user_id: Option<Snowflake<User>>,
+    target_id: Option<Snowflake<()>>,
+    action: AuditLogAction,
+    old_value: Option<String>,
+    new_value: Option<String>,
+}
+
+impl AuditLogEntry {
+    /// Create a new audit log entry with a freshly generated ID.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration, used to generate the ID.
+    /// * `guild` - The guild the change was made in.
+    /// * `user` - The user who made the change.
+    /// * `action` - The kind of change made.
+    pub fn new(
+        config: &Config,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        action: AuditLogAction,
+    ) -> Self {
+        Self {
+            id: Snowflake::gen_new(config),
+            guild_id: guild.into(),
+            user_id: Some(user.into()),
+            target_id: None,
+            action,
+            old_value: None,
+            new_value: None,
+        }
+    }
+
+    /// Create a new audit log entry for a change made by the system, rather than a user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration, used to generate the ID.
+    /// * `guild` - The guild the change was made in.
+    /// * `action` - The kind of change made.
+    pub fn system(config: &Config, guild: impl Into<Snowflake<Guild>>, action: AuditLogAction) -> Self {
+        Self {
+            id: Snowflake::gen_new(config),
+            guild_id: guild.into(),
+            user_id: None,
+            target_id: None,
+            action,
+            old_value: None,
+            new_value: None,
+        }
+    }
+
+    /// Set the object the change was made to, such as a channel.
+    #[must_use]
+    pub const fn with_target<T>(mut self, target: Snowflake<T>) -> Self {
+        self.target_id = Some(target.cast());
+        self
+    }
+
+    /// Set the value before and after the change.
+    #[must_use]
+    pub fn with_change(mut self, old_value: Option<String>, new_value: Option<String>) -> Self {
+        self.old_value = old_value;
+        self.new_value = new_value;
+        self
+    }
+
+    /// The entry's ID.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The ID of the guild the change was made in.
+    pub const fn guild_id(&self) -> Snowflake<Guild> {
+        self.guild_id
+    }
+
+    /// The ID of the user who made the change, if they still exist.
+    pub const fn user_id(&self) -> Option<Snowflake<User>> {
+        self.user_id
+    }
+
+    /// The ID of the object the change was made to, if any.
+    pub const fn target_id(&self) -> Option<Snowflake<()>> {
+        self.target_id
+    }
+
+    /// The kind of change made.
+    pub const fn action(&self) -> AuditLogAction {
+        self.action
+    }
+
+    /// The value before the change.
+    pub fn old_value(&self) -> Option<&str> {
+        self.old_value.as_deref()
+    }
+
+    /// The value after the change.
+    pub fn new_value(&self) -> Option<&str> {
+        self.new_value.as_deref()
+    }
+}
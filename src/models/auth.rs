@@ -18,7 +18,7 @@ use crate::app::App;
 use super::{
     errors::{AuthError, RESTError},
     snowflake::Snowflake,
-    user::User,
+    user::{User, normalize_username},
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -146,9 +146,13 @@ impl Token {
     /// [`RESTError::NotFound`] - If the user entry for the token could not be found.
     pub async fn validate(app: App, token: &str) -> Result<Self, RESTError> {
         let token = Self::decode(app.config.app_secret(), token)?;
-        let stored_creds = StoredCredentials::fetch(app, token.data().user_id())
-            .await
-            .ok_or(RESTError::NotFound("User entry for token not found".into()))?;
+        let Some(stored_creds) = StoredCredentials::fetch(app.clone(), token.data().user_id()).await else {
+            // Users of external authentication providers have no credentials that could invalidate the token
+            if app.ops().users().has_external_identity(token.data().user_id()).await? {
+                return Ok(token);
+            }
+            return Err(RESTError::NotFound("User entry for token not found".into()));
+        };
         // Check that the token's iat is after the last changed time of the stored credentials
         if token.data().iat() < stored_creds.last_changed.timestamp() as usize {
             return Err(AuthError::InvalidToken.into());
@@ -192,6 +196,35 @@ impl FromRequestParts<App> for Token {
     }
 }
 
+/// A validated token belonging to one of the instance's administrators.
+#[derive(Clone, Debug)]
+pub struct AdminToken(Token);
+
+impl AdminToken {
+    /// Returns the token data
+    pub const fn data(&self) -> &TokenData {
+        self.0.data()
+    }
+}
+
+/// Admin token extractor for axum.
+///
+/// Rejects the request with `403 Forbidden` if the token owner is not an administrator.
+impl FromRequestParts<App> for AdminToken {
+    type Rejection = RESTError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &App) -> Result<Self, Self::Rejection> {
+        let token = Token::from_request_parts(parts, state).await?;
+
+        if !state.config.is_admin(token.data().user_id()) {
+            return Err(RESTError::Forbidden(
+                "This endpoint is restricted to administrators".into(),
+            ));
+        }
+        Ok(Self(token))
+    }
+}
+
 /// An incoming set of credentials.
 #[derive(Debug, Clone)]
 pub struct Credentials {
@@ -275,9 +308,9 @@ impl StoredCredentials {
         let user_id: i64 = user.into().into();
 
         let result = sqlx::query!(
-            "SELECT user_id, password, last_changed
-            FROM secrets
-            WHERE user_id = $1",
+            "SELECT s.user_id, s.password, s.last_changed
+            FROM secrets s JOIN users u ON u.id = s.user_id
+            WHERE s.user_id = $1 AND u.terminated_at IS NULL",
             user_id
         )
         .fetch_optional(app.db())
@@ -305,8 +338,8 @@ impl StoredCredentials {
         let result = sqlx::query!(
             "SELECT users.id, secrets.password, secrets.last_changed
             FROM users JOIN secrets ON users.id = secrets.user_id
-            WHERE users.username = $1",
-            username
+            WHERE lower(users.username) = $1 AND users.terminated_at IS NULL",
+            normalize_username(&username)
         )
         .fetch_optional(app.db())
         .await
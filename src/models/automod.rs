@@ -0,0 +1,651 @@
+use std::sync::LazyLock;
+
+use itertools::Itertools;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    channel::Channel,
+    errors::BuildError,
+    guild::Guild,
+    keyword_alert::{KeywordMatcher, normalize_keywords},
+    message::mentioned_users,
+    request_payloads::{CreateAutomodRule, UpdateAutomodRule},
+    snowflake::Snowflake,
+};
+use crate::app::Config;
+
+/// The maximum number of auto-moderation rules a guild may have.
+pub const MAX_AUTOMOD_RULES: usize = 25;
+/// The maximum length of an auto-moderation rule's name.
+pub const MAX_AUTOMOD_RULE_NAME_LENGTH: usize = 100;
+/// The highest number of mentions or links a trigger may allow in a single message.
+pub const MAX_AUTOMOD_TRIGGER_LIMIT: u32 = 50;
+/// The maximum number of content types an attachment type trigger may match.
+pub const MAX_AUTOMOD_CONTENT_TYPES: usize = 20;
+/// The longest timeout an auto-moderation rule may apply, in seconds.
+pub const MAX_AUTOMOD_TIMEOUT: u32 = 3600 * 24 * 28; // 28 days
+
+/// Matches links in message content.
+static LINK_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)https?://[^\s<>]+").expect("Failed to compile link regex"));
+
+/// What makes an auto-moderation rule fire.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AutomodTrigger {
+    /// The content contains one of the keywords, matched case-insensitively.
+    Keyword { keywords: Vec<String> },
+    /// The content mentions more than `limit` distinct users.
+    MentionCount { limit: u32 },
+    /// The content contains more than `limit` links.
+    LinkSpam { limit: u32 },
+    /// An attachment has one of the content types. `type/*` matches all subtypes of a type.
+    AttachmentType { content_types: Vec<String> },
+}
+
+impl AutomodTrigger {
+    /// The type of the trigger, as stored in the database.
+    const fn kind(&self) -> i16 {
+        match self {
+            Self::Keyword { .. } => 1,
+            Self::MentionCount { .. } => 2,
+            Self::LinkSpam { .. } => 3,
+            Self::AttachmentType { .. } => 4,
+        }
+    }
+
+    /// The keywords or content types the trigger matches, as stored in the database.
+    fn values(&self) -> &[String] {
+        match self {
+            Self::Keyword { keywords } => keywords,
+            Self::AttachmentType { content_types } => content_types,
+            Self::MentionCount { .. } | Self::LinkSpam { .. } => &[],
+        }
+    }
+
+    /// The number of mentions or links the trigger allows, as stored in the database.
+    const fn limit(&self) -> Option<u32> {
+        match self {
+            Self::MentionCount { limit } | Self::LinkSpam { limit } => Some(*limit),
+            Self::Keyword { .. } | Self::AttachmentType { .. } => None,
+        }
+    }
+
+    /// Normalize and validate the trigger's keywords, content types and limits.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the trigger matches nothing, or its limit is out of range.
+    fn normalize(self) -> Result<Self, BuildError> {
+        match self {
+            Self::Keyword { keywords } => {
+                let keywords = normalize_keywords(keywords)?;
+                if keywords.is_empty() {
+                    return Err(BuildError::ValidationError(
+                        "Keyword triggers must match at least one keyword".into(),
+                    ));
+                }
+                Ok(Self::Keyword { keywords })
+            }
+            Self::MentionCount { limit } | Self::LinkSpam { limit }
+                if !(1..=MAX_AUTOMOD_TRIGGER_LIMIT).contains(&limit) =>
+            {
+                Err(BuildError::ValidationError(format!(
+                    "Trigger limits must be between 1 and {MAX_AUTOMOD_TRIGGER_LIMIT}"
+                )))
+            }
+            Self::AttachmentType { content_types } => {
+                let content_types: Vec<String> = content_types
+                    .into_iter()
+                    .map(|c| c.trim().to_lowercase())
+                    .sorted()
+                    .dedup()
+                    .collect();
+
+                if content_types.is_empty() || content_types.len() > MAX_AUTOMOD_CONTENT_TYPES {
+                    return Err(BuildError::ValidationError(format!(
+                        "Attachment type triggers must match between 1 and {MAX_AUTOMOD_CONTENT_TYPES} content types"
+                    )));
+                }
+                if let Some(invalid) = content_types.iter().find(|c| !is_content_type_pattern(c)) {
+                    return Err(BuildError::ValidationError(format!("Invalid content type: {invalid}")));
+                }
+                Ok(Self::AttachmentType { content_types })
+            }
+            trigger => Ok(trigger),
+        }
+    }
+
+    /// Check a message against the trigger.
+    ///
+    /// ## Arguments
+    ///
+    /// * `content` - The content of the message, if any.
+    /// * `content_types` - The content types of the message's attachments.
+    ///
+    /// ## Returns
+    ///
+    /// Why the trigger fired, or `None` if it did not.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the keywords could not be compiled.
+    fn check(&self, content: Option<&str>, content_types: &[String]) -> Result<Option<String>, BuildError> {
+        let content = content.unwrap_or_default();
+
+        let reason = match self {
+            Self::Keyword { keywords } => {
+                let matcher = KeywordMatcher::new(keywords.clone())?;
+                let found = matcher.find(content);
+                (!found.is_empty()).then(|| format!("Contains the keywords {}", found.join(", ")))
+            }
+            Self::MentionCount { limit } => {
+                let count = mentioned_users(content).len();
+                (count > *limit as usize).then(|| format!("Mentions {count} users, more than {limit}"))
+            }
+            Self::LinkSpam { limit } => {
+                let count = LINK_REGEX.find_iter(content).count();
+                (count > *limit as usize).then(|| format!("Contains {count} links, more than {limit}"))
+            }
+            Self::AttachmentType {
+                content_types: patterns,
+            } => content_types
+                .iter()
+                .map(|c| c.to_lowercase())
+                .find(|c| patterns.iter().any(|p| content_type_matches(p, c)))
+                .map(|c| format!("Has an attachment of type {c}")),
+        };
+        Ok(reason)
+    }
+}
+
+/// Whether the given string is a content type, or a `type/*` wildcard.
+fn is_content_type_pattern(pattern: &str) -> bool {
+    let Some((kind, subtype)) = pattern.split_once('/') else {
+        return false;
+    };
+    let is_token = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "!#$&^_.+-".contains(c));
+    is_token(kind) && (subtype == "*" || is_token(subtype))
+}
+
+/// Whether a lowercased content type matches a pattern, ignoring any parameters of the content type.
+fn content_type_matches(pattern: &str, content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+
+    pattern.strip_suffix("/*").map_or(pattern == essence, |kind| {
+        essence.split_once('/').is_some_and(|(k, _)| k == kind)
+    })
+}
+
+/// What happens when an auto-moderation rule fires.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AutomodAction {
+    /// The message is rejected and never sent.
+    Block,
+    /// A system message describing what happened is posted in the channel.
+    Alert { channel_id: Snowflake<Channel> },
+    /// The author may not send messages in the guild for `duration` seconds.
+    Timeout { duration: u32 },
+}
+
+/// Represents an auto-moderation rule stored in the database.
+pub struct AutomodRuleRecord {
+    pub id: i64,
+    pub guild_id: i64,
+    pub name: String,
+    pub enabled: bool,
+    pub trigger_type: i16,
+    pub trigger_values: Vec<String>,
+    pub trigger_limit: Option<i32>,
+    pub block: bool,
+    pub alert_channel_id: Option<i64>,
+    pub timeout_duration: Option<i32>,
+}
+
+/// A rule checked against every new message sent in a guild.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AutomodRule {
+    id: Snowflake<Self>,
+    guild_id: Snowflake<Guild>,
+    name: String,
+    /// Disabled rules are not enforced, but can still be tested.
+    enabled: bool,
+    trigger: AutomodTrigger,
+    actions: Vec<AutomodAction>,
+}
+
+impl AutomodRule {
+    /// Create a new rule from a creation payload, with a freshly generated ID.
+    /// Alert channels are expected to have been checked to belong to the guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration, used to generate the ID.
+    /// * `guild` - The guild the rule applies to.
+    /// * `payload` - The rule's details.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the details are invalid.
+    pub fn from_payload(
+        config: &Config,
+        guild: impl Into<Snowflake<Guild>>,
+        payload: CreateAutomodRule,
+    ) -> Result<Self, BuildError> {
+        let rule = Self {
+            id: Snowflake::gen_new(config),
+            guild_id: guild.into(),
+            name: payload.name.trim().to_owned(),
+            enabled: payload.enabled.unwrap_or(true),
+            trigger: payload.trigger.normalize()?,
+            actions: payload.actions,
+        };
+        rule.validate()?;
+        Ok(rule)
+    }
+
+    /// Apply an update payload to the rule.
+    /// New alert channels are expected to have been checked to belong to the guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the updated details are invalid.
+    pub fn apply_update(&mut self, payload: UpdateAutomodRule) -> Result<(), BuildError> {
+        if let Some(name) = payload.name {
+            name.trim().clone_into(&mut self.name);
+        }
+        if let Some(enabled) = payload.enabled {
+            self.enabled = enabled;
+        }
+        if let Some(trigger) = payload.trigger {
+            self.trigger = trigger.normalize()?;
+        }
+        if let Some(actions) = payload.actions {
+            self.actions = actions;
+        }
+        self.validate()
+    }
+
+    /// Ensure the rule's name and actions are within their limits.
+    fn validate(&self) -> Result<(), BuildError> {
+        let name_length = self.name.chars().count();
+        if !(1..=MAX_AUTOMOD_RULE_NAME_LENGTH).contains(&name_length) {
+            return Err(BuildError::ValidationError(format!(
+                "Rule name must be between 1 and {MAX_AUTOMOD_RULE_NAME_LENGTH} characters long"
+            )));
+        }
+        if self.actions.is_empty() {
+            return Err(BuildError::ValidationError(
+                "Rules must have at least one action".into(),
+            ));
+        }
+        if !self.actions.iter().map(std::mem::discriminant).all_unique() {
+            return Err(BuildError::ValidationError(
+                "Rules may have at most one action of each type".into(),
+            ));
+        }
+        if self
+            .actions
+            .iter()
+            .any(|a| matches!(a, AutomodAction::Timeout { duration } if !(1..=MAX_AUTOMOD_TIMEOUT).contains(duration)))
+        {
+            return Err(BuildError::ValidationError(format!(
+                "Timeouts must be between 1 and {MAX_AUTOMOD_TIMEOUT} seconds long"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Build a rule from a database record.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the stored trigger is invalid.
+    pub fn from_record(record: AutomodRuleRecord) -> Result<Self, BuildError> {
+        let limit = || {
+            record
+                .trigger_limit
+                .and_then(|l| u32::try_from(l).ok())
+                .ok_or_else(|| BuildError::ValidationError("Trigger limit is missing".into()))
+        };
+
+        let trigger = match record.trigger_type {
+            1 => AutomodTrigger::Keyword {
+                keywords: record.trigger_values,
+            },
+            2 => AutomodTrigger::MentionCount { limit: limit()? },
+            3 => AutomodTrigger::LinkSpam { limit: limit()? },
+            4 => AutomodTrigger::AttachmentType {
+                content_types: record.trigger_values,
+            },
+            other => {
+                return Err(BuildError::ValidationError(format!("Unknown trigger type: {other}")));
+            }
+        };
+
+        let mut actions = Vec::new();
+        if record.block {
+            actions.push(AutomodAction::Block);
+        }
+        // The alert is dropped along with the channel it was posted in
+        if let Some(channel_id) = record.alert_channel_id {
+            actions.push(AutomodAction::Alert {
+                channel_id: channel_id.into(),
+            });
+        }
+        if let Some(duration) = record.timeout_duration.and_then(|d| u32::try_from(d).ok()) {
+            actions.push(AutomodAction::Timeout { duration });
+        }
+
+        Ok(Self {
+            id: record.id.into(),
+            guild_id: record.guild_id.into(),
+            name: record.name,
+            enabled: record.enabled,
+            trigger,
+            actions,
+        })
+    }
+
+    /// The ID of the rule.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The guild the rule applies to.
+    pub const fn guild_id(&self) -> Snowflake<Guild> {
+        self.guild_id
+    }
+
+    /// The name of the rule, shown to moderators.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the rule is enforced on new messages.
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// What makes the rule fire.
+    pub const fn trigger(&self) -> &AutomodTrigger {
+        &self.trigger
+    }
+
+    /// What happens when the rule fires.
+    pub fn actions(&self) -> &[AutomodAction] {
+        &self.actions
+    }
+
+    /// The type of the rule's trigger, as stored in the database.
+    pub const fn trigger_type(&self) -> i16 {
+        self.trigger.kind()
+    }
+
+    /// The keywords or content types the rule's trigger matches, as stored in the database.
+    pub fn trigger_values(&self) -> &[String] {
+        self.trigger.values()
+    }
+
+    /// The number of mentions or links the rule's trigger allows, as stored in the database.
+    pub fn trigger_limit(&self) -> Option<i32> {
+        self.trigger.limit().and_then(|l| i32::try_from(l).ok())
+    }
+
+    /// Whether the rule blocks the messages it fires on.
+    pub fn blocks(&self) -> bool {
+        self.actions.contains(&AutomodAction::Block)
+    }
+
+    /// The channel the rule posts alerts in, if any.
+    pub fn alert_channel_id(&self) -> Option<Snowflake<Channel>> {
+        self.actions.iter().find_map(|a| match a {
+            AutomodAction::Alert { channel_id } => Some(*channel_id),
+            _ => None,
+        })
+    }
+
+    /// How long the rule times out the authors of the messages it fires on, in seconds.
+    pub fn timeout_duration(&self) -> Option<u32> {
+        self.actions.iter().find_map(|a| match a {
+            AutomodAction::Timeout { duration } => Some(*duration),
+            _ => None,
+        })
+    }
+
+    /// Check a message against the rule, regardless of whether it is enabled.
+    ///
+    /// ## Arguments
+    ///
+    /// * `content` - The content of the message, if any.
+    /// * `content_types` - The content types of the message's attachments.
+    ///
+    /// ## Returns
+    ///
+    /// Why the rule fired, or `None` if it did not.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the rule's keywords could not be compiled.
+    pub fn check(&self, content: Option<&str>, content_types: &[String]) -> Result<Option<String>, BuildError> {
+        self.trigger.check(content, content_types)
+    }
+}
+
+/// A rule that fired on a message, and why.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AutomodMatch {
+    pub rule: AutomodRule,
+    pub reason: String,
+}
+
+impl AutomodMatch {
+    /// The content of the system message alerting moderators of the match.
+    ///
+    /// ## Arguments
+    ///
+    /// * `author` - The ID of the message's author, rendered as a mention.
+    /// * `channel` - The channel the message was sent in, rendered as a mention.
+    pub fn alert_content(&self, author: impl std::fmt::Display, channel: Snowflake<Channel>) -> String {
+        let outcome = if self.rule.blocks() { "blocked" } else { "flagged" };
+        format!(
+            "Auto-moderation rule **{}** {outcome} a message by <@{author}> in <#{channel}>: {}.",
+            self.rule.name, self.reason
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(trigger: AutomodTrigger) -> AutomodRule {
+        AutomodRule {
+            id: Snowflake::new(1),
+            guild_id: Snowflake::new(2),
+            name: "Test".into(),
+            enabled: true,
+            trigger,
+            actions: vec![AutomodAction::Block],
+        }
+    }
+
+    #[test]
+    fn test_keyword_trigger() {
+        let rule = rule(
+            AutomodTrigger::Keyword {
+                keywords: vec!["Free Nitro ".into(), "scam".into()],
+            }
+            .normalize()
+            .expect("trigger should be valid"),
+        );
+
+        assert_eq!(
+            rule.check(Some("Get FREE NITRO here"), &[]).expect("rule should check"),
+            Some("Contains the keywords free nitro".into())
+        );
+        assert_eq!(rule.check(Some("Hello there"), &[]).expect("rule should check"), None);
+        assert_eq!(rule.check(None, &[]).expect("rule should check"), None);
+        assert!(AutomodTrigger::Keyword { keywords: Vec::new() }.normalize().is_err());
+    }
+
+    #[test]
+    fn test_limit_triggers() {
+        let mentions = rule(AutomodTrigger::MentionCount { limit: 2 });
+        assert_eq!(
+            mentions.check(Some("<@1> <@2> <@1>"), &[]).expect("rule should check"),
+            None
+        );
+        assert!(
+            mentions
+                .check(Some("<@1> <@2> <@3>"), &[])
+                .expect("rule should check")
+                .is_some()
+        );
+
+        let links = rule(AutomodTrigger::LinkSpam { limit: 1 });
+        assert_eq!(
+            links
+                .check(Some("see https://example.com"), &[])
+                .expect("rule should check"),
+            None
+        );
+        assert_eq!(
+            links
+                .check(Some("HTTPS://a.example http://b.example"), &[])
+                .expect("rule should check"),
+            Some("Contains 2 links, more than 1".into())
+        );
+
+        assert!(AutomodTrigger::LinkSpam { limit: 0 }.normalize().is_err());
+        assert!(
+            AutomodTrigger::MentionCount {
+                limit: MAX_AUTOMOD_TRIGGER_LIMIT + 1
+            }
+            .normalize()
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_attachment_type_trigger() {
+        let rule = rule(
+            AutomodTrigger::AttachmentType {
+                content_types: vec!["Application/X-Msdownload".into(), "video/*".into()],
+            }
+            .normalize()
+            .expect("trigger should be valid"),
+        );
+
+        assert!(
+            rule.check(None, &["video/mp4".into()])
+                .expect("rule should check")
+                .is_some()
+        );
+        assert!(
+            rule.check(None, &["application/x-msdownload; charset=binary".into()])
+                .expect("rule should check")
+                .is_some()
+        );
+        assert_eq!(
+            rule.check(None, &["image/png".into(), "videos/mp4".into()])
+                .expect("rule should check"),
+            None
+        );
+
+        for invalid in ["video", "*/*", "video/", "vid eo/mp4"] {
+            assert!(
+                AutomodTrigger::AttachmentType {
+                    content_types: vec![invalid.into()]
+                }
+                .normalize()
+                .is_err()
+            );
+        }
+        assert!(
+            AutomodTrigger::AttachmentType {
+                content_types: Vec::new()
+            }
+            .normalize()
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_validate_actions() {
+        let mut rule = rule(AutomodTrigger::LinkSpam { limit: 3 });
+        assert!(rule.validate().is_ok());
+
+        rule.actions = Vec::new();
+        assert!(rule.validate().is_err());
+
+        rule.actions = vec![AutomodAction::Block, AutomodAction::Block];
+        assert!(rule.validate().is_err());
+
+        rule.actions = vec![AutomodAction::Timeout {
+            duration: MAX_AUTOMOD_TIMEOUT + 1,
+        }];
+        assert!(rule.validate().is_err());
+
+        rule.actions = vec![
+            AutomodAction::Timeout { duration: 60 },
+            AutomodAction::Alert {
+                channel_id: Snowflake::new(3),
+            },
+        ];
+        assert!(rule.validate().is_ok());
+        assert_eq!(rule.timeout_duration(), Some(60));
+        assert_eq!(rule.alert_channel_id(), Some(Snowflake::new(3)));
+        assert!(!rule.blocks());
+    }
+
+    #[test]
+    fn test_record_roundtrip() {
+        let mut rule = rule(AutomodTrigger::MentionCount { limit: 5 });
+        rule.actions = vec![
+            AutomodAction::Block,
+            AutomodAction::Alert {
+                channel_id: Snowflake::new(3),
+            },
+            AutomodAction::Timeout { duration: 600 },
+        ];
+
+        let record = AutomodRuleRecord {
+            id: rule.id().into(),
+            guild_id: rule.guild_id().into(),
+            name: rule.name().to_owned(),
+            enabled: rule.enabled(),
+            trigger_type: rule.trigger_type(),
+            trigger_values: rule.trigger_values().to_vec(),
+            trigger_limit: rule.trigger_limit(),
+            block: rule.blocks(),
+            alert_channel_id: rule.alert_channel_id().map(Into::into),
+            timeout_duration: rule.timeout_duration().and_then(|d| i32::try_from(d).ok()),
+        };
+        assert_eq!(AutomodRule::from_record(record).expect("record should be valid"), rule);
+    }
+
+    #[test]
+    fn test_serialization() {
+        let trigger: AutomodTrigger =
+            serde_json::from_str(r#"{"type": "LINK_SPAM", "limit": 3}"#).expect("trigger should deserialize");
+        assert_eq!(trigger, AutomodTrigger::LinkSpam { limit: 3 });
+
+        let actions: Vec<AutomodAction> = serde_json::from_str(
+            r#"[{"type": "BLOCK"}, {"type": "ALERT", "channel_id": "3"}, {"type": "TIMEOUT", "duration": 60}]"#,
+        )
+        .expect("actions should deserialize");
+        assert_eq!(
+            actions,
+            vec![
+                AutomodAction::Block,
+                AutomodAction::Alert {
+                    channel_id: Snowflake::new(3)
+                },
+                AutomodAction::Timeout { duration: 60 }
+            ]
+        );
+    }
+}
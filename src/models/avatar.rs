@@ -1,7 +1,10 @@
 use std::fmt::Debug;
 use std::hash::{DefaultHasher, Hash, Hasher};
 
-use crate::external::s3::{Bucket, S3Service};
+use crate::{
+    external::s3::{Bucket, KEYSPACE_VERSION, ObjectClass, ObjectStream, S3Service, versioned_key},
+    utils::{animation::frame_count, image_metadata},
+};
 
 use super::{
     data_uri::DataUri,
@@ -16,6 +19,17 @@ use serde::{Deserialize, Serialize};
 
 use super::snowflake::Snowflake;
 
+/// The maximum size of a still avatar, in bytes.
+pub const MAX_AVATAR_SIZE: usize = 2 * 1024 * 1024;
+/// The maximum size of an animated avatar or a banner, in bytes.
+pub const MAX_ANIMATED_AVATAR_SIZE: usize = 4 * 1024 * 1024;
+/// The maximum number of frames of an animated avatar or banner.
+pub const MAX_ANIMATION_FRAMES: u32 = 250;
+/// The sizes still avatars are downscaled to on upload, in pixels.
+pub const AVATAR_SIZES: [u32; 3] = [32, 128, 512];
+/// The prefix of the hashes of animated avatars.
+const ANIMATED_PREFIX: &str = "a_";
+
 /// The file extension of the avatar.
 fn mime_to_img_ext(mime: &Mime) -> String {
     let mime_str = mime.to_string();
@@ -34,6 +48,50 @@ fn is_mime_image(mime: &Mime) -> bool {
     mime.type_() == "image"
 }
 
+/// The MIME type of an avatar with the given file extension, if avatars may be stored in that format.
+fn img_ext_to_mime(ext: &str) -> Option<Mime> {
+    match ext {
+        "png" => Some(mime::IMAGE_PNG),
+        "jpg" | "jpeg" => Some(mime::IMAGE_JPEG),
+        "gif" => Some(mime::IMAGE_GIF),
+        "bmp" => Some(mime::IMAGE_BMP),
+        "webp" => Some("image/webp".parse().expect("image/webp should be a valid MIME")),
+        _ => None,
+    }
+}
+
+/// The slot of a user or guild an avatar is set in, identifying the avatar's pending upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i16)]
+pub enum AvatarSlot {
+    UserAvatar = 1,
+    UserBanner = 2,
+    GuildAvatar = 3,
+}
+
+impl AvatarSlot {
+    /// The bucket avatars set in this slot are stored in.
+    pub const fn bucket(self) -> &'static str {
+        match self {
+            Self::UserAvatar | Self::UserBanner => "users",
+            Self::GuildAvatar => "guilds",
+        }
+    }
+}
+
+impl TryFrom<i16> for AvatarSlot {
+    type Error = BuildError;
+
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::UserAvatar),
+            2 => Ok(Self::UserBanner),
+            3 => Ok(Self::GuildAvatar),
+            _ => Err(BuildError::ValidationError(format!("Unknown avatar slot: {value}"))),
+        }
+    }
+}
+
 /// Represents the kind of avatar resource.
 pub trait AvatarKind: Debug + Default + Clone + Copy + PartialEq + Eq
 where
@@ -44,6 +102,37 @@ where
 
     /// The bucket this kind of avatar is stored in.
     fn bucket(&self) -> &'static str;
+
+    /// The slot of its holder this kind of avatar is set in.
+    fn slot(&self) -> AvatarSlot;
+
+    /// The class of object this kind of avatar is stored as.
+    fn object_class(&self) -> ObjectClass {
+        ObjectClass::Avatars
+    }
+
+    /// Whether this kind of avatar may still be stored under the original key layout.
+    fn has_legacy_keys(&self) -> bool {
+        true
+    }
+
+    /// The maximum size of an avatar of this kind, in bytes.
+    ///
+    /// ## Arguments
+    ///
+    /// * `animated` - Whether the avatar is animated.
+    fn max_size(&self, animated: bool) -> usize {
+        if animated {
+            MAX_ANIMATED_AVATAR_SIZE
+        } else {
+            MAX_AVATAR_SIZE
+        }
+    }
+
+    /// The sizes avatars of this kind are downscaled to on upload, in pixels.
+    fn sizes(&self) -> &'static [u32] {
+        &AVATAR_SIZES
+    }
 }
 
 /// Represents a guild's icon
@@ -57,6 +146,11 @@ impl AvatarKind for GuildAvatar {
     fn bucket(&self) -> &'static str {
         "guilds"
     }
+
+    #[inline]
+    fn slot(&self) -> AvatarSlot {
+        AvatarSlot::GuildAvatar
+    }
 }
 
 /// Represents a user's profile picture
@@ -70,6 +164,51 @@ impl AvatarKind for UserAvatar {
     fn bucket(&self) -> &'static str {
         "users"
     }
+
+    #[inline]
+    fn slot(&self) -> AvatarSlot {
+        AvatarSlot::UserAvatar
+    }
+}
+
+/// Represents a user's profile banner
+#[derive(Debug, Clone, Serialize, Deserialize, Default, Copy, PartialEq, Eq)]
+pub struct UserBanner;
+
+impl AvatarKind for UserBanner {
+    type HolderType = User;
+
+    #[inline]
+    fn bucket(&self) -> &'static str {
+        "users"
+    }
+
+    #[inline]
+    fn slot(&self) -> AvatarSlot {
+        AvatarSlot::UserBanner
+    }
+
+    #[inline]
+    fn object_class(&self) -> ObjectClass {
+        ObjectClass::Banners
+    }
+
+    // Banners were introduced after the key layout was versioned
+    #[inline]
+    fn has_legacy_keys(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn max_size(&self, _animated: bool) -> usize {
+        MAX_ANIMATED_AVATAR_SIZE
+    }
+
+    // Banners are displayed at full width, so they are only stored as uploaded
+    #[inline]
+    fn sizes(&self) -> &'static [u32] {
+        &[]
+    }
 }
 
 pub trait AvatarLike<K: AvatarKind> {
@@ -85,13 +224,24 @@ pub trait AvatarLike<K: AvatarKind> {
         K::default()
     }
 
+    /// Whether the avatar is animated.
+    fn is_animated(&self) -> bool {
+        self.avatar_hash().starts_with(ANIMATED_PREFIX)
+    }
+
     /// The bucket this avatar is stored in S3.
     fn bucket<'a>(&self, s3: &'a S3Service) -> Bucket<'a> {
         s3.get_bucket(self.kind().bucket())
     }
 
-    /// The path to the attachment in S3.
+    /// The path to the avatar in S3.
     fn s3_key(&self) -> String {
+        versioned_key(KEYSPACE_VERSION, self.kind().object_class(), &self.legacy_s3_key())
+    }
+
+    /// The path to the avatar in S3 under the original key layout, which avatars uploaded before
+    /// the keyspace was versioned may still be stored under.
+    fn legacy_s3_key(&self) -> String {
         format!(
             "{}/{}.{}",
             self.holder_id(),
@@ -100,14 +250,84 @@ pub trait AvatarLike<K: AvatarKind> {
         )
     }
 
-    /// Delete the contents of the attachment from S3.
+    /// The path to the avatar downscaled to the given size in S3.
+    /// Downscaled avatars are only stored under the current key layout.
+    ///
+    /// ## Arguments
+    ///
+    /// * `size` - The size of the avatar, one of the sizes of its kind.
+    fn sized_s3_key(&self, size: u32) -> String {
+        let path = format!(
+            "{}/{}_{size}.{}",
+            self.holder_id(),
+            self.avatar_hash(),
+            mime_to_img_ext(self.mime())
+        );
+        versioned_key(KEYSPACE_VERSION, self.kind().object_class(), &path)
+    }
+
+    /// Stream the contents of the avatar from S3.
+    ///
+    /// Avatars still stored under the original key layout are moved to the current one first.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the avatar does not exist, for example because it was replaced.
+    /// * [`AppError::S3`] - If the S3 request fails.
+    async fn stream(&self, s3: &S3Service) -> Result<ObjectStream, AppError> {
+        let bucket = self.bucket(s3);
+
+        match bucket.stream_object(self.s3_key(), None).await {
+            Err(AppError::NotFound(_)) if self.kind().has_legacy_keys() => {
+                // Another request may have moved the avatar in the meantime, so try the current key regardless
+                if let Err(e) = bucket.rename_object(self.legacy_s3_key(), self.s3_key()).await {
+                    tracing::debug!(error = %e, "Failed to move avatar to the current key layout");
+                }
+                bucket.stream_object(self.s3_key(), None).await
+            }
+            result => result,
+        }
+    }
+
+    /// Stream the contents of the avatar downscaled to the given size from S3.
+    ///
+    /// The original is streamed instead if the avatar is animated, was not larger than the size,
+    /// or was uploaded before avatars were downscaled.
+    ///
+    /// ## Arguments
+    ///
+    /// * `size` - The size of the avatar, one of the sizes of its kind.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the avatar does not exist, for example because it was replaced.
+    /// * [`AppError::S3`] - If the S3 request fails.
+    async fn stream_sized(&self, s3: &S3Service, size: u32) -> Result<ObjectStream, AppError> {
+        if !self.is_animated() && self.kind().sizes().contains(&size) {
+            match self.bucket(s3).stream_object(self.sized_s3_key(size), None).await {
+                Err(AppError::NotFound(_)) => {}
+                result => return result,
+            }
+        }
+        self.stream(s3).await
+    }
+
+    /// Delete the contents of the attachment from S3, including all of its downscaled sizes.
     /// This should be called after the attachment is deleted from the database.
     ///
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
     async fn delete(&self, s3: &S3Service) -> Result<(), AppError> {
-        self.bucket(s3).delete_object(self.s3_key()).await
+        let bucket = self.bucket(s3);
+        if self.kind().has_legacy_keys() {
+            bucket.delete_object(self.legacy_s3_key()).await?;
+        }
+
+        let keys: Vec<String> = std::iter::once(self.s3_key())
+            .chain(self.kind().sizes().iter().map(|&size| self.sized_s3_key(size)))
+            .collect();
+        bucket.delete_objects(keys).await
     }
 }
 
@@ -174,6 +394,9 @@ impl<K: AvatarKind> FullAvatar<K> {
 
     /// Build a new avatar from a data URI.
     ///
+    /// Animated GIF and PNG images are accepted with up to [`MAX_ANIMATION_FRAMES`] frames,
+    /// the hashes of animated avatars are prefixed with `a_`.
+    ///
     /// ## Arguments
     ///
     /// * `holder` - The ID of the object that holds this avatar.
@@ -181,30 +404,93 @@ impl<K: AvatarKind> FullAvatar<K> {
     ///
     /// ## Errors
     ///
-    /// * If the MIME type is not an image.
+    /// * If the MIME type is not an image in one of the supported formats.
+    /// * If the image is malformed, or has too many frames.
     pub fn from_data_uri(holder: impl Into<Snowflake<K::HolderType>>, uri: DataUri) -> Result<Self, BuildError> {
-        let mime = uri.mime().clone();
+        // APNGs are stored as regular PNGs, which they are compatible with
+        let mime = if uri.mime().essence_str() == "image/apng" {
+            mime::IMAGE_PNG
+        } else {
+            uri.mime().clone()
+        };
+        let ext = mime_to_img_ext(&mime);
+        if img_ext_to_mime(&ext).is_none() {
+            return Err(BuildError::ValidationError(
+                "Avatar must be a PNG, JPEG, GIF, BMP or WebP image".into(),
+            ));
+        }
+
+        let frames = frame_count(&Bytes::from(uri.clone())).map_err(BuildError::ValidationError)?;
+        if frames > MAX_ANIMATION_FRAMES {
+            return Err(BuildError::ValidationError(format!(
+                "Animated images may have at most {MAX_ANIMATION_FRAMES} frames"
+            )));
+        }
+
         let mut hasher = DefaultHasher::new();
         uri.hash(&mut hasher);
-        let avatar_hash = format!("{}_{}", hasher.finish(), mime_to_img_ext(&mime));
+        let prefix = if frames > 1 { ANIMATED_PREFIX } else { "" };
+        let avatar_hash = format!("{prefix}{}_{ext}", hasher.finish());
 
         Self::builder()
             .holder_id(holder)
-            .mime(uri.mime().clone())
+            .mime(mime)
             .content(uri)
             .avatar_hash(avatar_hash)
             .build()
     }
 
-    /// Upload the avatar content to S3. This function is called implicitly if the user is updated.
+    /// Upload the avatar content to S3, along with the sizes it is downscaled to.
+    /// This function is called implicitly if the user is updated.
     ///
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
     pub async fn upload(&self, s3: &S3Service) -> Result<(), AppError> {
-        self.bucket(s3)
+        let bucket = self.bucket(s3);
+        bucket
             .put_object(self.s3_key(), self.content.clone(), self.mime())
-            .await
+            .await?;
+
+        for (size, content) in self.downscale().await {
+            bucket.put_object(self.sized_s3_key(size), content, self.mime()).await?;
+        }
+        Ok(())
+    }
+
+    /// Downscale the avatar to each of the sizes of its kind that it is larger than.
+    ///
+    /// Animated avatars and avatars in formats that cannot be re-encoded are not downscaled.
+    /// If the avatar cannot be processed, no sizes are returned and the original is served in their place.
+    async fn downscale(&self) -> Vec<(u32, Bytes)> {
+        let sizes = self.kind().sizes();
+        if sizes.is_empty() || self.is_animated() || !image_metadata::is_supported(self.mime()) {
+            return Vec::new();
+        }
+
+        let content = self.content.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            sizes
+                .iter()
+                .filter_map(|&size| {
+                    image_metadata::downscale(&content, size)
+                        .transpose()
+                        .map(|r| r.map(|c| (size, c)))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        });
+
+        match task.await {
+            Ok(Ok(sizes)) => sizes,
+            Ok(Err(e)) => {
+                tracing::warn!(error = %e, avatar_hash = self.avatar_hash, "Failed to downscale avatar");
+                Vec::new()
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Avatar downscaling task failed");
+                Vec::new()
+            }
+        }
     }
 
     /// Download the avatar content from S3.
@@ -213,14 +499,30 @@ impl<K: AvatarKind> FullAvatar<K> {
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
     pub async fn download(&mut self, s3: &S3Service) -> Result<(), AppError> {
-        self.content = self.bucket(s3).get_object(self.s3_key()).await?;
+        let bucket = self.bucket(s3);
+
+        self.content = match bucket.get_object(self.s3_key()).await {
+            Ok(content) => content,
+            Err(e) if self.kind().has_legacy_keys() => bucket.get_object(self.legacy_s3_key()).await.map_err(|_| e)?,
+            Err(e) => return Err(e),
+        };
         Ok(())
     }
 
+    /// The contents of the avatar.
+    pub const fn content(&self) -> &Bytes {
+        &self.content
+    }
+
     /// Returns the filesize of this avatar in bytes.
     pub const fn size(&self) -> usize {
         self.content.len()
     }
+
+    /// Whether the avatar is larger than avatars of its kind may be.
+    pub fn is_too_large(&self) -> bool {
+        self.size() > self.kind().max_size(self.is_animated())
+    }
 }
 
 impl<K: AvatarKind> Serialize for FullAvatar<K> {
@@ -265,19 +567,12 @@ impl<K: AvatarKind> PartialAvatar<K> {
     ///
     /// * If the MIME type is not an image.
     pub fn new(avatar_hash: String, holder_id: impl Into<Snowflake<K::HolderType>>) -> Result<Self, BuildError> {
-        let mime = {
-            avatar_hash.split('_').next_back().map_or_else(
-                || Err(BuildError::ValidationError("no MIME type at end of avatar hash".into())),
-                |file_ext| match file_ext {
-                    "png" => Ok(mime::IMAGE_PNG),
-                    "jpg" | "jpeg" => Ok(mime::IMAGE_JPEG),
-                    "gif" => Ok(mime::IMAGE_GIF),
-                    "bmp" => Ok(mime::IMAGE_BMP),
-                    "webp" => Ok("image/webp".parse().expect("image/webp should be a valid MIME")),
-                    _ => Err(BuildError::ValidationError("invalid file extension".into())),
-                },
-            )
-        }?;
+        let mime = avatar_hash.split('_').next_back().map_or_else(
+            || Err(BuildError::ValidationError("no MIME type at end of avatar hash".into())),
+            |file_ext| {
+                img_ext_to_mime(file_ext).ok_or_else(|| BuildError::ValidationError("invalid file extension".into()))
+            },
+        )?;
 
         Ok(Self {
             avatar_hash,
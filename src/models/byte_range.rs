@@ -0,0 +1,104 @@
+use std::{fmt, str::FromStr};
+
+use super::errors::BuildError;
+
+/// A single range of bytes requested through the HTTP `Range` header.
+///
+/// Only single ranges in `bytes` units are supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// All bytes starting from the given offset, e.g. `bytes=100-`
+    From(u64),
+    /// The bytes between the two offsets, both inclusive, e.g. `bytes=100-199`
+    Inclusive(u64, u64),
+    /// The given amount of bytes at the end of the file, e.g. `bytes=-100`
+    Suffix(u64),
+}
+
+impl FromStr for ByteRange {
+    type Err = BuildError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BuildError::ValidationError(format!("Invalid or unsupported range: {s}"));
+
+        let spec = s.trim().strip_prefix("bytes=").ok_or_else(invalid)?;
+        if spec.contains(',') {
+            return Err(invalid());
+        }
+        let (start, end) = spec.trim().split_once('-').ok_or_else(invalid)?;
+
+        match (start.trim(), end.trim()) {
+            ("", "") => Err(invalid()),
+            ("", suffix) => {
+                let suffix = suffix.parse().map_err(|_| invalid())?;
+                if suffix == 0 {
+                    return Err(invalid());
+                }
+                Ok(Self::Suffix(suffix))
+            }
+            (start, "") => Ok(Self::From(start.parse().map_err(|_| invalid())?)),
+            (start, end) => {
+                let (start, end) = (
+                    start.parse().map_err(|_| invalid())?,
+                    end.parse().map_err(|_| invalid())?,
+                );
+                if start > end {
+                    return Err(invalid());
+                }
+                Ok(Self::Inclusive(start, end))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::From(start) => write!(f, "bytes={start}-"),
+            Self::Inclusive(start, end) => write!(f, "bytes={start}-{end}"),
+            Self::Suffix(len) => write!(f, "bytes=-{len}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "bytes=0-499".parse::<ByteRange>().ok(),
+            Some(ByteRange::Inclusive(0, 499))
+        );
+        assert_eq!("bytes=500-".parse::<ByteRange>().ok(), Some(ByteRange::From(500)));
+        assert_eq!("bytes=-500".parse::<ByteRange>().ok(), Some(ByteRange::Suffix(500)));
+        assert_eq!(
+            " bytes= 1 - 2 ".parse::<ByteRange>().ok(),
+            Some(ByteRange::Inclusive(1, 2))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for range in [
+            "bytes=",
+            "bytes=-",
+            "bytes=-0",
+            "bytes=10-5",
+            "bytes=0-1,5-10",
+            "bytes=a-b",
+            "items=0-10",
+            "0-10",
+        ] {
+            assert!(range.parse::<ByteRange>().is_err(), "{range} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_display_roundtrip() {
+        for range in [ByteRange::From(5), ByteRange::Inclusive(0, 0), ByteRange::Suffix(10)] {
+            assert_eq!(range.to_string().parse::<ByteRange>().ok(), Some(range));
+        }
+    }
+}
@@ -28,3 +28,31 @@ impl<'de> Deserialize<'de> for Capability {
         Ok(Self::from_bits(flags).unwrap_or_default())
     }
 }
+
+bitflags! {
+    /// Features a gateway client declares to support when identifying
+    ///
+    /// Payloads for features a client does not support are left out, to save bandwidth.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ClientCapability: u64 {
+        /// The client displays presences. Without it, members are sent without their presence,
+        /// and `PRESENCE_UPDATE` events are not sent.
+        const PRESENCE = 1;
+        /// The client displays typing indicators. Without it, `TYPING_START` events are not sent.
+        const TYPING = 1 << 1;
+    }
+}
+
+impl Default for ClientCapability {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl<'de> Deserialize<'de> for ClientCapability {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let flags = u64::deserialize(deserializer)?;
+        // Capabilities introduced later are unknown to older servers, and ignored
+        Ok(Self::from_bits_truncate(flags))
+    }
+}
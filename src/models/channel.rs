@@ -1,31 +1,49 @@
 use enum_dispatch::enum_dispatch;
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
-use crate::app::Config;
+use crate::{app::Config, gateway::SendMode};
 
 use super::snowflake::Snowflake;
-use super::{guild::Guild, request_payloads::CreateChannel};
+use super::{
+    guild::Guild,
+    message::Message,
+    request_payloads::{CreateChannel, UpdateChannel},
+    user::User,
+};
 
 #[enum_dispatch(Channel)]
 pub trait ChannelLike {
     /// The Snowflake ID of a channel.
     fn id(&self) -> Snowflake<Channel>;
-    /// The Snowflake ID of the guild this channel belongs to.
-    fn guild_id(&self) -> Snowflake<Guild>;
-    /// The name of the channel.
+    /// The Snowflake ID of the guild this channel belongs to, `None` for direct message channels.
+    fn guild_id(&self) -> Option<Snowflake<Guild>>;
+    /// The name of the channel, empty for direct message channels.
     fn name(&self) -> &str;
-    /// The name of the channel.
-    fn name_mut(&mut self) -> &mut String;
     /// The type of channel.
     fn channel_type(&self) -> &'static str;
+    /// Whether the channel is locked, only allowing privileged members to post in it.
+    fn locked(&self) -> bool;
+    /// The ID of the most recent message sent in the channel, if any.
+    fn last_message_id(&self) -> Option<Snowflake<Message>>;
+    /// The number of messages in the channel.
+    fn message_count(&self) -> i64;
+    /// The amount of days messages are kept in the channel for, `None` if they are kept forever.
+    fn retention_days(&self) -> Option<u32>;
 }
 
 /// Represents a row representing a channel.
 pub struct ChannelRecord {
     pub id: Snowflake<Channel>,
-    pub guild_id: Snowflake<Guild>,
+    pub guild_id: Option<i64>,
     pub name: String,
     pub channel_type: String,
+    pub locked: bool,
+    pub last_message_id: Option<i64>,
+    pub message_count: i64,
+    pub retention_days: Option<i32>,
+    /// The recipients of a direct message channel, empty for guild channels.
+    pub recipient_ids: Vec<i64>,
 }
 
 #[non_exhaustive]
@@ -34,12 +52,27 @@ pub struct ChannelRecord {
 #[enum_dispatch]
 pub enum Channel {
     GuildText(TextChannel),
+    DirectMessage(DirectChannel),
 }
 
 impl Channel {
     pub fn from_record(record: ChannelRecord) -> Self {
-        match record.channel_type.as_str() {
-            "TEXT_CHANNEL" => Self::GuildText(TextChannel::new(record.id, record.guild_id, record.name)),
+        match (record.channel_type.as_str(), record.guild_id) {
+            ("TEXT_CHANNEL", Some(guild_id)) => Self::GuildText(TextChannel {
+                id: record.id,
+                guild_id: guild_id.into(),
+                name: record.name,
+                locked: record.locked,
+                last_message_id: record.last_message_id.map(Into::into),
+                message_count: record.message_count,
+                retention_days: record.retention_days.and_then(|d| d.try_into().ok()),
+            }),
+            ("DIRECT_MESSAGE", None) => Self::DirectMessage(DirectChannel {
+                id: record.id,
+                recipient_ids: record.recipient_ids.into_iter().map(Into::into).collect(),
+                last_message_id: record.last_message_id.map(Into::into),
+                message_count: record.message_count,
+            }),
             _ => panic!("Invalid channel type"),
         }
     }
@@ -51,6 +84,38 @@ impl Channel {
             }
         }
     }
+
+    /// Update the channel with the given payload.
+    /// The new name is normalized, and validated when the channel is committed.
+    /// Direct message channels cannot be updated, the payload is ignored for them.
+    pub fn update(&mut self, payload: UpdateChannel) {
+        let Self::GuildText(channel) = self else {
+            return;
+        };
+        if let Some(name) = payload.name {
+            channel.name = normalize_name(&name);
+        }
+        if let Some(locked) = payload.locked {
+            channel.locked = locked;
+        }
+        if let Ok(retention_days) = payload.retention_days.try_into() {
+            channel.retention_days = retention_days;
+        }
+    }
+
+    /// The users who may view the channel if it is a direct message channel, empty for guild channels.
+    pub fn recipient_ids(&self) -> &[Snowflake<User>] {
+        match self {
+            Self::GuildText(_) => &[],
+            Self::DirectMessage(channel) => &channel.recipient_ids,
+        }
+    }
+
+    /// Who events about the channel are dispatched to: the members of its guild,
+    /// or the recipients of a direct message channel.
+    pub fn send_mode(&self) -> SendMode {
+        SendMode::for_channel(self.guild_id(), self.id())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -58,14 +123,34 @@ pub struct TextChannel {
     id: Snowflake<Channel>,
     guild_id: Snowflake<Guild>,
     name: String,
+    #[serde(default)]
+    locked: bool,
+    #[serde(default)]
+    last_message_id: Option<Snowflake<Message>>,
+    #[serde(default)]
+    message_count: i64,
+    #[serde(default)]
+    retention_days: Option<u32>,
+}
+
+/// Normalize the name of a text channel, lowercasing it and joining its words with dashes.
+///
+/// Names are unique within a guild after normalization, so `"General"` and `" general "` are the same name.
+pub fn normalize_name(name: &str) -> String {
+    name.split_whitespace().join("-").to_lowercase()
 }
 
 impl TextChannel {
-    pub fn new(id: Snowflake<Channel>, guild: impl Into<Snowflake<Guild>>, name: String) -> Self {
+    /// Create a new text channel, the name is normalized with [`normalize_name`].
+    pub fn new(id: Snowflake<Channel>, guild: impl Into<Snowflake<Guild>>, name: impl AsRef<str>) -> Self {
         Self {
             id,
             guild_id: guild.into(),
-            name,
+            name: normalize_name(name.as_ref()),
+            locked: false,
+            last_message_id: None,
+            message_count: 0,
+            retention_days: None,
         }
     }
 }
@@ -75,20 +160,94 @@ impl ChannelLike for TextChannel {
         self.id
     }
 
-    fn guild_id(&self) -> Snowflake<Guild> {
-        self.guild_id
+    fn guild_id(&self) -> Option<Snowflake<Guild>> {
+        Some(self.guild_id)
     }
 
     fn name(&self) -> &str {
         &self.name
     }
 
-    fn name_mut(&mut self) -> &mut String {
-        &mut self.name
+    fn channel_type(&self) -> &'static str {
+        "TEXT_CHANNEL"
+    }
+
+    fn locked(&self) -> bool {
+        self.locked
+    }
+
+    fn last_message_id(&self) -> Option<Snowflake<Message>> {
+        self.last_message_id
+    }
+
+    fn message_count(&self) -> i64 {
+        self.message_count
+    }
+
+    fn retention_days(&self) -> Option<u32> {
+        self.retention_days
+    }
+}
+
+/// A channel between two users, outside of any guild.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DirectChannel {
+    id: Snowflake<Channel>,
+    /// The users who may view the channel, ordered by ID.
+    recipient_ids: Vec<Snowflake<User>>,
+    #[serde(default)]
+    last_message_id: Option<Snowflake<Message>>,
+    #[serde(default)]
+    message_count: i64,
+}
+
+impl DirectChannel {
+    pub fn new(id: Snowflake<Channel>, user: Snowflake<User>, other: Snowflake<User>) -> Self {
+        Self {
+            id,
+            recipient_ids: vec![user.min(other), user.max(other)],
+            last_message_id: None,
+            message_count: 0,
+        }
+    }
+
+    /// The users who may view the channel, ordered by ID.
+    pub fn recipient_ids(&self) -> &[Snowflake<User>] {
+        &self.recipient_ids
+    }
+}
+
+impl ChannelLike for DirectChannel {
+    fn id(&self) -> Snowflake<Channel> {
+        self.id
+    }
+
+    fn guild_id(&self) -> Option<Snowflake<Guild>> {
+        None
+    }
+
+    fn name(&self) -> &'static str {
+        ""
     }
 
     fn channel_type(&self) -> &'static str {
-        "TEXT_CHANNEL"
+        "DIRECT_MESSAGE"
+    }
+
+    fn locked(&self) -> bool {
+        false
+    }
+
+    fn last_message_id(&self) -> Option<Snowflake<Message>> {
+        self.last_message_id
+    }
+
+    fn message_count(&self) -> i64 {
+        self.message_count
+    }
+
+    fn retention_days(&self) -> Option<u32> {
+        None
     }
 }
 
@@ -127,3 +286,29 @@ impl From<&mut TextChannel> for Snowflake<Channel> {
         channel.id()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::omittableoption::OmittableOption;
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("general"), "general");
+        assert_eq!(normalize_name(" General "), "general");
+        assert_eq!(normalize_name("Off  Topic\tChat"), "off-topic-chat");
+    }
+
+    #[test]
+    fn test_update_normalizes_name() {
+        let mut channel = Channel::GuildText(TextChannel::new(Snowflake::new(1), Snowflake::new(2), "Old Name"));
+        assert_eq!(channel.name(), "old-name");
+
+        channel.update(UpdateChannel {
+            name: Some("New Name".into()),
+            locked: None,
+            retention_days: OmittableOption::Omitted,
+        });
+        assert_eq!(channel.name(), "new-name");
+    }
+}
@@ -0,0 +1,312 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use mime::Mime;
+use serde::Serialize;
+
+use super::{
+    avatar::{AvatarKind, AvatarSlot},
+    errors::BuildError,
+    request_payloads::CreateDirectUpload,
+    snowflake::Snowflake,
+    user::User,
+};
+use crate::{
+    app::Config,
+    external::s3::{KEYSPACE_VERSION, ObjectClass, ObjectMetadata, versioned_key},
+};
+
+/// For how long the URLs clients upload files to S3 with stay valid.
+pub const PRESIGNED_UPLOAD_EXPIRY: Duration = Duration::from_secs(15 * 60);
+
+/// A request a client sends to upload a file to S3 directly, without passing it through the API.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PresignedUpload {
+    url: String,
+    method: String,
+    /// The headers the request has to be sent with.
+    headers: BTreeMap<String, String>,
+    /// When the URL stops being valid.
+    expires_at: DateTime<Utc>,
+}
+
+impl PresignedUpload {
+    /// Create a new presigned upload.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - The URL to send the file to.
+    /// * `method` - The HTTP method to send the file with.
+    /// * `headers` - The headers the request has to be sent with.
+    /// * `expires_in` - For how long the URL stays valid, starting now.
+    pub fn new(url: String, method: String, headers: BTreeMap<String, String>, expires_in: Duration) -> Self {
+        Self {
+            url,
+            method,
+            headers,
+            expires_at: TimeDelta::from_std(expires_in)
+                .ok()
+                .and_then(|delta| Utc::now().checked_add_signed(delta))
+                .unwrap_or(DateTime::<Utc>::MAX_UTC),
+        }
+    }
+
+    /// The URL to send the file to.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// When the URL stops being valid.
+    pub const fn expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
+}
+
+/// Check that a file a client uploaded directly to S3 matches what it declared when starting the upload.
+///
+/// ## Arguments
+///
+/// * `metadata` - The metadata of the uploaded object, `None` if it does not exist.
+/// * `size` - The declared size of the file in bytes.
+/// * `content_type` - The declared content type of the file.
+///
+/// ## Errors
+///
+/// * [`BuildError::ValidationError`] - If the file was not uploaded yet, or its size or content type differ.
+pub fn verify_uploaded(metadata: Option<&ObjectMetadata>, size: u64, content_type: &str) -> Result<(), BuildError> {
+    let Some(metadata) = metadata else {
+        return Err(BuildError::ValidationError("The file has not been uploaded yet".into()));
+    };
+    if metadata.content_length != Some(size) {
+        return Err(BuildError::ValidationError(format!(
+            "The uploaded file must be exactly {size} bytes large"
+        )));
+    }
+    if metadata.content_type.as_deref() != Some(content_type) {
+        return Err(BuildError::ValidationError(format!(
+            "The uploaded file must have a content type of '{content_type}'"
+        )));
+    }
+    Ok(())
+}
+
+/// Represents a direct upload record stored in the database.
+pub struct DirectUploadRecord {
+    pub id: Snowflake<DirectUpload>,
+    pub user_id: Snowflake<User>,
+    pub holder_id: i64,
+    pub slot: i16,
+    pub content_type: String,
+    pub size: i64,
+}
+
+/// An avatar or banner uploaded by a client directly to S3.
+///
+/// The file is held under a temporary key until the upload is finalized,
+/// at which point it is validated and set like an avatar sent through the API.
+/// Attachments are uploaded directly through upload sessions instead.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DirectUpload {
+    id: Snowflake<Self>,
+    user_id: Snowflake<User>,
+    /// The ID of the user or guild the file will be set on.
+    #[serde(skip)]
+    holder_id: i64,
+    #[serde(skip)]
+    slot: AvatarSlot,
+    content_type: String,
+    size: u64,
+    /// The request to upload the file with, only included when the upload is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upload: Option<PresignedUpload>,
+}
+
+impl DirectUpload {
+    /// Create a new direct upload from a request payload.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration, used to generate IDs.
+    /// * `user` - The user uploading the file.
+    /// * `holder` - The user or guild the file will be set on.
+    /// * `payload` - The request payload describing the file.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the content type is not an image, or the size is invalid.
+    pub fn from_payload<K: AvatarKind>(
+        config: &Config,
+        user: impl Into<Snowflake<User>>,
+        holder: impl Into<Snowflake<K::HolderType>>,
+        payload: CreateDirectUpload,
+    ) -> Result<Self, BuildError> {
+        let kind = K::default();
+
+        let is_image = payload
+            .content_type
+            .parse::<Mime>()
+            .is_ok_and(|mime| mime.type_() == mime::IMAGE);
+        if payload.content_type.len() > 255 || !is_image {
+            return Err(BuildError::ValidationError("Content type must be an image".into()));
+        }
+
+        // The exact limit depends on whether the image is animated, which is only known once it was uploaded
+        let max_size = kind.max_size(true) as u64;
+        if payload.size == 0 || payload.size > max_size {
+            return Err(BuildError::ValidationError(format!(
+                "Invalid size, must be between 1 and {max_size} bytes"
+            )));
+        }
+
+        Ok(Self {
+            id: Snowflake::gen_new(config),
+            user_id: user.into(),
+            holder_id: holder.into().into(),
+            slot: kind.slot(),
+            content_type: payload.content_type,
+            size: payload.size,
+            upload: None,
+        })
+    }
+
+    /// Create a new direct upload from a database record.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the slot is unknown.
+    pub fn from_record(record: DirectUploadRecord) -> Result<Self, BuildError> {
+        Ok(Self {
+            id: record.id,
+            user_id: record.user_id,
+            holder_id: record.holder_id,
+            slot: AvatarSlot::try_from(record.slot)?,
+            content_type: record.content_type,
+            size: record.size as u64,
+            upload: None,
+        })
+    }
+
+    /// The upload's ID.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The ID of the user uploading the file.
+    pub const fn user_id(&self) -> Snowflake<User> {
+        self.user_id
+    }
+
+    /// The ID of the user or guild the file will be set on, if it holds avatars of the given kind.
+    pub fn holder_id<K: AvatarKind>(&self) -> Option<Snowflake<K::HolderType>> {
+        (self.slot == K::default().slot()).then(|| Snowflake::<K::HolderType>::new(self.holder_id))
+    }
+
+    /// The ID of the user or guild the file will be set on, regardless of which kind of avatar it is.
+    pub const fn holder_id_raw(&self) -> i64 {
+        self.holder_id
+    }
+
+    /// The slot of the holder the file will be set in.
+    pub const fn slot(&self) -> AvatarSlot {
+        self.slot
+    }
+
+    /// The content type the file has to be uploaded with.
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// The size of the file in bytes.
+    pub const fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The bucket the file is uploaded to.
+    pub const fn bucket(&self) -> &'static str {
+        self.slot.bucket()
+    }
+
+    /// The key the file is uploaded under, until the upload is finalized.
+    pub fn s3_key(&self) -> String {
+        versioned_key(
+            KEYSPACE_VERSION,
+            ObjectClass::Uploads,
+            &format!("{}/{}", self.holder_id, self.id),
+        )
+    }
+
+    /// Set the request to upload the file with.
+    pub fn set_upload(&mut self, upload: PresignedUpload) {
+        self.upload = Some(upload);
+    }
+}
+
+impl From<DirectUpload> for Snowflake<DirectUpload> {
+    fn from(upload: DirectUpload) -> Self {
+        upload.id()
+    }
+}
+
+impl From<&DirectUpload> for Snowflake<DirectUpload> {
+    fn from(upload: &DirectUpload) -> Self {
+        upload.id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::*;
+    use crate::models::avatar::{GuildAvatar, UserAvatar};
+
+    fn config() -> Config {
+        Config::builder()
+            .database_url(Secret::new(String::new()))
+            .s3(None)
+            .listen_addr(([127, 0, 0, 1], 8080))
+            .machine_id(0)
+            .process_id(0)
+            .app_secret(Secret::new(String::new()))
+            .build()
+            .expect("config should be valid")
+    }
+
+    fn payload(content_type: &str, size: u64) -> CreateDirectUpload {
+        CreateDirectUpload {
+            content_type: content_type.into(),
+            size,
+        }
+    }
+
+    #[test]
+    fn test_from_payload() {
+        let config = config();
+
+        let upload = DirectUpload::from_payload::<UserAvatar>(&config, 1, 2, payload("image/png", 1024))
+            .expect("a PNG should be accepted");
+        assert_eq!(upload.slot(), AvatarSlot::UserAvatar);
+        assert_eq!(upload.holder_id::<UserAvatar>(), Some(Snowflake::new(2)));
+        assert_eq!(upload.holder_id::<GuildAvatar>(), None);
+        assert_eq!(upload.s3_key(), format!("v2/uploads/2/{}", upload.id()));
+
+        assert!(DirectUpload::from_payload::<UserAvatar>(&config, 1, 2, payload("video/mp4", 1024)).is_err());
+        assert!(DirectUpload::from_payload::<UserAvatar>(&config, 1, 2, payload("image/png", 0)).is_err());
+        assert!(
+            DirectUpload::from_payload::<UserAvatar>(&config, 1, 2, payload("image/png", 64 * 1024 * 1024)).is_err()
+        );
+    }
+
+    #[test]
+    fn test_verify_uploaded() {
+        let metadata = ObjectMetadata {
+            content_length: Some(10),
+            content_type: Some("image/png".into()),
+        };
+
+        assert!(verify_uploaded(Some(&metadata), 10, "image/png").is_ok());
+        assert!(verify_uploaded(None, 10, "image/png").is_err());
+        assert!(verify_uploaded(Some(&metadata), 11, "image/png").is_err());
+        assert!(verify_uploaded(Some(&metadata), 10, "image/gif").is_err());
+    }
+}
@@ -1,10 +1,10 @@
-use std::num::ParseIntError;
+use std::{num::ParseIntError, time::Duration};
 
 use aws_sdk_s3::error::{DisplayErrorContext, SdkError};
 use axum::{
     Json,
     extract::multipart::MultipartError,
-    http::StatusCode,
+    http::{HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
 use derive_builder::UninitializedFieldError;
@@ -14,7 +14,18 @@ use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use thiserror::Error;
 
-use crate::{external::fcm::FirebaseError, gateway::GatewayCloseCode};
+use crate::{
+    app::{read_only::ReadOnlyState, startup::StartupReport},
+    external::{auth_provider::AuthProviderError, fcm::FirebaseError},
+    gateway::GatewayCloseCode,
+    rest::rate_limit::RateLimitBucket,
+};
+
+/// Marks an error response caused by the request body exceeding the limit of the route.
+///
+/// See [`crate::rest::body_limit::BodyLimitLayer`].
+#[derive(Debug, Clone, Copy)]
+pub struct BodyLimitExceeded;
 
 /// An error response returned by the REST API.
 #[derive(Debug, Clone)]
@@ -156,6 +167,9 @@ pub enum AuthError {
     /// Sent when the server fails to hash a password.
     #[error("Failed to generate password hash: {0}")]
     PasswordHash(#[from] argon2::password_hash::Error),
+    /// Sent when an external authentication provider rejects the identity or cannot be reached.
+    #[error(transparent)]
+    Provider(#[from] AuthProviderError),
 }
 
 impl AuthError {
@@ -165,6 +179,7 @@ impl AuthError {
                 StatusCode::UNAUTHORIZED
             }
             Self::PasswordHash(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Provider(e) => e.status_code(),
         }
     }
 }
@@ -175,6 +190,26 @@ impl IntoResponse for AuthError {
     }
 }
 
+/// The configuration could not be read from the environment.
+///
+/// Contains every problem found, instead of only the first one.
+#[derive(Debug, Error)]
+#[error("Invalid configuration:\n{}", .problems.iter().map(|p| format!("  - {p}")).collect::<Vec<_>>().join("\n"))]
+pub struct ConfigError {
+    problems: Vec<String>,
+}
+
+impl ConfigError {
+    pub const fn new(problems: Vec<String>) -> Self {
+        Self { problems }
+    }
+
+    /// The problems found in the configuration.
+    pub fn problems(&self) -> &[String] {
+        &self.problems
+    }
+}
+
 /// Errors that can occur during either the gateway or REST API execution.
 #[derive(Error, Debug)]
 #[non_exhaustive]
@@ -209,11 +244,22 @@ pub enum AppError {
     FirebaseMulti(Vec<FirebaseError>),
     #[error("Internal Server Error: {0}")]
     Unexpected(String),
+    #[error("Range Not Satisfiable: {0}")]
+    RangeNotSatisfiable(String),
+    #[error("Service Unavailable: {0}")]
+    Archived(String),
+    #[error(transparent)]
+    Ops(#[from] OpsError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("Startup self-check failed:\n{0}")]
+    Startup(StartupReport),
 }
 
 impl AppError {
     pub fn status_code(&self) -> StatusCode {
         match self {
+            Self::Multipart(e) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => StatusCode::PAYLOAD_TOO_LARGE,
             Self::Multipart(_) => StatusCode::UNPROCESSABLE_ENTITY,
             Self::JWT(e) => {
                 if matches!(e.kind(), ErrorKind::ExpiredSignature) {
@@ -229,9 +275,14 @@ impl AppError {
             | Self::S3(_)
             | Self::Firebase(_)
             | Self::FirebaseMulti(_)
-            | Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            | Self::Unexpected(_)
+            | Self::Config(_)
+            | Self::Startup(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::Auth(e) => e.status_code(),
             Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::RangeNotSatisfiable(_) => StatusCode::RANGE_NOT_SATISFIABLE,
+            Self::Archived(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Ops(e) => e.status_code(),
         }
     }
 }
@@ -242,7 +293,11 @@ impl IntoResponse for AppError {
         if status == StatusCode::INTERNAL_SERVER_ERROR {
             tracing::error!(error = %self);
         }
-        ErrResponse::new(status, self.to_string()).into_response()
+        let mut response = ErrResponse::new(status, self.to_string()).into_response();
+        if matches!(self, Self::Multipart(_)) && status == StatusCode::PAYLOAD_TOO_LARGE {
+            response.extensions_mut().insert(BodyLimitExceeded);
+        }
+        response
     }
 }
 
@@ -257,6 +312,101 @@ where
     }
 }
 
+/// Errors returned by the application's operations, see [`crate::app::Ops`].
+///
+/// Failures of the database or file storage are kept apart from expected outcomes,
+/// such as a resource not existing or conflicting with an existing one.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum OpsError {
+    #[error("Not Found: {0}")]
+    NotFound(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    /// A unique value, such as a username, is already used by another resource.
+    /// The field is included in REST responses, so clients can point users at it.
+    #[error("Conflict: The {field} is already taken")]
+    AlreadyTaken { field: &'static str },
+    #[error("Bad Request: {0}")]
+    BadRequest(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    /// The guild has reached its member limit, see [`crate::app::Config::guild_member_limit`].
+    /// REST responses include the code `GUILD_FULL`, so clients can tell it apart from other rejections.
+    #[error("Forbidden: The guild has reached its member limit")]
+    GuildFull,
+    #[error("Payload Too Large: {0}")]
+    PayloadTooLarge(String),
+    #[error("Database transaction failed: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("S3 service returned error: {0}")]
+    S3(String),
+    #[error("Failed to build object: {0}")]
+    Build(#[from] BuildError),
+    #[error("Messaging Service Error: {0}")]
+    Firebase(#[from] FirebaseError),
+    #[error("Messaging Service Error: {0:?}")]
+    FirebaseMulti(Vec<FirebaseError>),
+    #[error("Internal Server Error: {0}")]
+    Unexpected(String),
+    /// The operation was rejected to protect the database, and should be retried after the given duration.
+    #[error("Service Unavailable: {reason}")]
+    Unavailable { reason: String, retry_after: Duration },
+}
+
+impl OpsError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict(_) | Self::AlreadyTaken { .. } => StatusCode::CONFLICT,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Forbidden(_) | Self::GuildFull => StatusCode::FORBIDDEN,
+            Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Build(e) => e.status_code(),
+            Self::Db(_) | Self::S3(_) | Self::Firebase(_) | Self::FirebaseMulti(_) | Self::Unexpected(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+// Errors of the lower layers, such as models and the S3 service, are reported as application errors
+impl From<AppError> for OpsError {
+    fn from(e: AppError) -> Self {
+        match e {
+            AppError::Ops(e) => e,
+            AppError::Database(e) => Self::Db(e),
+            AppError::S3(e) => Self::S3(e),
+            AppError::Build(e) => Self::Build(e),
+            AppError::NotFound(e) => Self::NotFound(e),
+            AppError::IllegalArgument(e) => Self::BadRequest(e),
+            AppError::Firebase(e) => Self::Firebase(e),
+            AppError::FirebaseMulti(e) => Self::FirebaseMulti(e),
+            e => Self::Unexpected(e.to_string()),
+        }
+    }
+}
+
+impl<E, R> From<SdkError<E, R>> for OpsError
+where
+    E: std::error::Error + Send + Sync + 'static,
+    R: std::fmt::Debug,
+{
+    fn from(e: SdkError<E, R>) -> Self {
+        Self::S3(DisplayErrorContext(e).to_string())
+    }
+}
+
+/// Errors that can occur when handing an instruction to the gateway actor.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionError {
+    #[error("Gateway is not running")]
+    NotRunning,
+    #[error("Gateway actor has stopped")]
+    Stopped,
+}
+
 /// Errors that can occur during the gateway execution.
 #[derive(Debug, Error)]
 #[non_exhaustive]
@@ -275,6 +425,8 @@ pub enum GatewayError {
     HandshakeFailure(String),
     #[error("Forbidden: {0}")]
     Forbidden(String),
+    #[error("Rate Limited: {0}")]
+    RateLimited(String),
 }
 
 // Anything that can be converted into an AppError can be converted into a GatewayError
@@ -294,6 +446,7 @@ impl GatewayError {
             }
             Self::MalformedFrame(_) => GatewayCloseCode::InvalidPayload,
             Self::Forbidden(_) => GatewayCloseCode::PolicyViolation,
+            Self::RateLimited(_) => GatewayCloseCode::RateLimited,
         }
     }
 }
@@ -322,6 +475,31 @@ pub enum RESTError {
     PayloadTooLarge(String),
     #[error("Conflict: {0}")]
     Conflict(String),
+    /// The request body's `Content-Type` is not accepted, see [`crate::rest::hardening`].
+    #[error("Unsupported Media Type: {0}")]
+    UnsupportedMediaType(String),
+    /// The combined size of the request headers exceeds the given limit, in bytes.
+    #[error("Request Header Fields Too Large: The request headers must be {0} bytes or smaller in total.")]
+    HeadersTooLarge(usize),
+    /// The message has more attachments than the given limit.
+    #[error("Bad Request: A message may have at most {0} attachments.")]
+    TooManyAttachments(usize),
+    /// The combined size of the attachments of the message exceeds the given limit, in bytes.
+    #[error("Payload Too Large: The attachments of a message must be {0} bytes or smaller in total.")]
+    AttachmentsTooLarge(usize),
+    /// The request exceeded a rate limit, and should be retried after the given duration.
+    /// The bucket is `None` if the limit is global.
+    #[error("Too Many Requests: Retry after {:.3} seconds.", .retry_after.as_secs_f64())]
+    TooManyRequests {
+        retry_after: Duration,
+        bucket: Option<RateLimitBucket>,
+    },
+    /// The resource is temporarily unavailable, and should be requested again after the given duration.
+    #[error("Service Unavailable: {reason}")]
+    ServiceUnavailable { reason: String, retry_after: Duration },
+    /// The request would modify data while the instance is read-only.
+    #[error("Service Unavailable: The instance is read-only")]
+    ReadOnly(ReadOnlyState),
 }
 
 impl RESTError {
@@ -329,13 +507,19 @@ impl RESTError {
         match self {
             Self::App(e) => e.status_code(),
             Self::InternalServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            Self::MissingField(_) | Self::MalformedField(_) | Self::DuplicateField(_) | Self::BadRequest(_) => {
-                StatusCode::BAD_REQUEST
-            }
+            Self::MissingField(_)
+            | Self::MalformedField(_)
+            | Self::DuplicateField(_)
+            | Self::BadRequest(_)
+            | Self::TooManyAttachments(_) => StatusCode::BAD_REQUEST,
             Self::NotFound(_) => StatusCode::NOT_FOUND,
             Self::Conflict(_) => StatusCode::CONFLICT,
             Self::Forbidden(_) => StatusCode::FORBIDDEN,
-            Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::PayloadTooLarge(_) | Self::AttachmentsTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::HeadersTooLarge(_) => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            Self::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::ServiceUnavailable { .. } | Self::ReadOnly(_) => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
 }
@@ -349,6 +533,67 @@ impl<T: Into<AppError>> From<T> for RESTError {
 
 impl IntoResponse for RESTError {
     fn into_response(self) -> Response {
-        ErrResponse::new(self.status_code(), self.to_string()).into_response()
+        match self {
+            Self::App(e @ AppError::Multipart(_)) => e.into_response(),
+            Self::App(AppError::Ops(OpsError::Unavailable { reason, retry_after })) => {
+                Self::ServiceUnavailable { reason, retry_after }.into_response()
+            }
+            Self::App(AppError::Ops(ref e @ OpsError::AlreadyTaken { field })) => (
+                e.status_code(),
+                Json(json!({
+                    "error": e.to_string(),
+                    "field": field,
+                })),
+            )
+                .into_response(),
+            Self::App(AppError::Ops(ref e @ OpsError::GuildFull)) => (
+                e.status_code(),
+                Json(json!({
+                    "error": e.to_string(),
+                    "code": "GUILD_FULL",
+                })),
+            )
+                .into_response(),
+            Self::TooManyAttachments(limit) | Self::AttachmentsTooLarge(limit) | Self::HeadersTooLarge(limit) => (
+                self.status_code(),
+                Json(json!({
+                    "error": self.to_string(),
+                    "limit": limit,
+                })),
+            )
+                .into_response(),
+            Self::TooManyRequests { retry_after, bucket } => {
+                let mut response = ErrResponse::new(self.status_code(), self.to_string()).into_response();
+                let headers = response.headers_mut();
+                headers.insert(
+                    header::RETRY_AFTER,
+                    HeaderValue::from(retry_after.as_secs_f64().ceil() as u64),
+                );
+                match bucket {
+                    Some(bucket) => headers.insert("X-RateLimit-Bucket", HeaderValue::from_static(bucket.as_str())),
+                    None => headers.insert("X-RateLimit-Global", HeaderValue::from_static("true")),
+                };
+                response
+            }
+            Self::ServiceUnavailable { retry_after, .. } => {
+                let mut response = ErrResponse::new(self.status_code(), self.to_string()).into_response();
+                response.headers_mut().insert(
+                    header::RETRY_AFTER,
+                    HeaderValue::from(retry_after.as_secs_f64().ceil() as u64),
+                );
+                response
+            }
+            Self::ReadOnly(ref state) => (
+                self.status_code(),
+                Json(json!({
+                    "error": self.to_string(),
+                    "code": "READ_ONLY",
+                    "reason": state.reason(),
+                    "since": state.since(),
+                })),
+            )
+                .into_response(),
+            _ => ErrResponse::new(self.status_code(), self.to_string()).into_response(),
+        }
     }
 }
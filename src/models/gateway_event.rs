@@ -1,16 +1,27 @@
+use chrono::{DateTime, Utc};
 use futures::future::join_all;
 use secrecy::Secret;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::app::ApplicationState;
+use crate::{
+    app::ApplicationState, external::scanner::ScanVerdict, gateway::GatewayCloseCode, rest::rate_limit::RateLimitBucket,
+};
 
 use super::{
-    channel::Channel,
+    capability::ClientCapability,
+    channel::{Channel, ChannelLike},
     errors::AppError,
     guild::Guild,
+    guild_event::{GuildEvent, RsvpStatus},
     member::Member,
     message::Message,
+    relationship::Relationship,
+    report::Report,
+    role::Role,
     snowflake::Snowflake,
+    standing::Standing,
+    upload_session::UploadSession,
     user::{Presence, User},
 };
 
@@ -24,6 +35,35 @@ pub enum GatewayEvent {
     Hello { heartbeat_interval: u64 },
     /// A heartbeat acknowledgement.
     HeartbeatAck,
+    /// The answer to a `PING`, sent as soon as it was received.
+    Pong {
+        /// The nonce of the `PING` this answers.
+        nonce: String,
+        /// The time the `PING` was answered at, in milliseconds since the Unix epoch.
+        server_time: i64,
+    },
+    /// A session was resumed, and all missed events were re-sent.
+    Resumed,
+    /// The connection is about to be closed, sent right before the close frame.
+    Closing {
+        /// The close code the connection is closed with.
+        code: GatewayCloseCode,
+        /// The reason the connection is closed for.
+        reason: String,
+        /// Whether the client may reconnect.
+        reconnect: bool,
+        /// The amount of seconds the client should wait for before reconnecting, if any.
+        retry_after: Option<f64>,
+    },
+    /// Requests sent by the session were dropped, because too many of the user's requests were waiting to be handled.
+    RequestDropped {
+        /// The number of requests that were dropped.
+        count: u64,
+        /// The number of requests dropped since the session connected.
+        total: u64,
+        /// The session is closed once this many of its requests were dropped.
+        limit: u64,
+    },
     /// A chat message.
     MessageCreate(Message),
     /// A chat message was updated.
@@ -49,6 +89,8 @@ pub enum GatewayEvent {
     GuildRemove(Guild),
     /// A channel was created.
     ChannelCreate(Channel),
+    /// A channel was updated.
+    ChannelUpdate(Channel),
     /// A channel was deleted.
     ChannelRemove(Channel),
     /// A message was acknowledged by another session.
@@ -56,6 +98,12 @@ pub enum GatewayEvent {
         channel_id: Snowflake<Channel>,
         message_id: Snowflake<Message>,
     },
+    /// The number of unread messages mentioning the user changed,
+    /// because they were mentioned or read a channel they were mentioned in.
+    UnreadUpdate {
+        /// The number of unread messages mentioning the user, the same number push notifications report.
+        badge: i64,
+    },
     /// A user's presence was updated.
     PresenceUpdate {
         user_id: Snowflake<User>,
@@ -66,14 +114,340 @@ pub enum GatewayEvent {
         user_id: Snowflake<User>,
         channel_id: Snowflake<Channel>,
     },
+    /// A user has stopped typing in a channel, because they did not start typing again in time.
+    TypingStop {
+        user_id: Snowflake<User>,
+        channel_id: Snowflake<Channel>,
+    },
     /// The server is ready to accept messages.
     Ready {
+        /// The ID of the session, used to resume it after a disconnect.
+        session_id: Uuid,
         user: User,
         guilds: Vec<Guild>,
         read_states: Vec<ReadStateEntry>,
+        relationships: Vec<Relationship>,
+        /// The users typing in each channel the user can view, empty unless the session displays typing indicators.
+        typing: Vec<TypingEntry>,
     },
     /// A user's data was updated.
     UserUpdate(User),
+    /// A relationship with another user was created or changed, such as a friend request being accepted.
+    RelationshipAdd(Relationship),
+    /// A relationship with another user was removed.
+    RelationshipRemove { user_id: Snowflake<User> },
+    /// A part of an upload session has been received.
+    UploadProgress {
+        upload_id: Snowflake<UploadSession>,
+        channel_id: Snowflake<Channel>,
+        bytes: u64,
+        total: u64,
+        percentage: u8,
+    },
+    /// An attachment was flagged by the attachment scanner and quarantined.
+    /// This is only sent to the moderators of the guild.
+    AttachmentQuarantine {
+        attachment_id: u8,
+        message_id: Snowflake<Message>,
+        channel_id: Snowflake<Channel>,
+        guild_id: Snowflake<Guild>,
+        verdict: ScanVerdict,
+    },
+    /// A chunk of the members requested through `REQUEST_GUILD_MEMBERS`.
+    GuildMembersChunk {
+        guild_id: Snowflake<Guild>,
+        members: Vec<Member>,
+        /// The index of this chunk, starting from 0.
+        chunk_index: u32,
+        /// The total amount of chunks sent in response to the request.
+        chunk_count: u32,
+        nonce: Option<String>,
+    },
+    /// A new message contained keywords the guild watches for.
+    /// This is only sent to the moderators subscribed to the guild's keyword alerts.
+    KeywordAlert {
+        guild_id: Snowflake<Guild>,
+        channel_id: Snowflake<Channel>,
+        message_id: Snowflake<Message>,
+        author_id: Option<Snowflake<User>>,
+        /// The matched keywords.
+        keywords: Vec<String>,
+    },
+    /// A member reported something in the guild and asked for the report to be forwarded.
+    /// This is only sent to the moderators of the guild, without the identity of the reporter.
+    ReportCreate(Report),
+    /// The user's account standing changed, because a strike was issued against them or revoked.
+    /// This is only sent to the user, without the administrators who issued the strikes.
+    StandingUpdate(Standing),
+    /// A guild scheduled an event.
+    GuildEventCreate(GuildEvent),
+    /// A guild event was updated.
+    GuildEventUpdate(GuildEvent),
+    /// A guild event was cancelled.
+    GuildEventRemove {
+        id: Snowflake<GuildEvent>,
+        guild_id: Snowflake<Guild>,
+    },
+    /// A member answered a guild event, or withdrew their answer.
+    GuildEventRsvpUpdate {
+        event_id: Snowflake<GuildEvent>,
+        guild_id: Snowflake<Guild>,
+        user_id: Snowflake<User>,
+        /// The member's answer, `None` if they withdrew it.
+        status: Option<RsvpStatus>,
+    },
+    /// A guild event is about to start.
+    /// This is only sent to the members who answered that they are going or interested.
+    GuildEventReminder(GuildEvent),
+    /// A role was created in a guild.
+    RoleCreate(Role),
+    /// A role of a guild was updated.
+    RoleUpdate(Role),
+    /// A role of a guild was deleted, and unassigned from all members.
+    RoleRemove {
+        id: Snowflake<Role>,
+        guild_id: Snowflake<Guild>,
+    },
+    /// A role was assigned to or unassigned from a member.
+    MemberRolesUpdate {
+        guild_id: Snowflake<Guild>,
+        user_id: Snowflake<User>,
+        /// All roles now assigned to the member.
+        role_ids: Vec<Snowflake<Role>>,
+    },
+    /// The user exhausted one of their rate limit buckets.
+    /// Further requests in the bucket are rejected, or dropped if sent over the gateway, until it replenishes.
+    RateLimit {
+        bucket: RateLimitBucket,
+        /// The object the bucket is tracked for, such as a channel.
+        object_id: Snowflake<()>,
+        /// The number of requests that can be made at once.
+        limit: u32,
+        /// The amount of seconds until the next request can be made.
+        retry_after: f64,
+        /// Whether the request that exhausted the bucket was rejected.
+        rejected: bool,
+    },
+    /// An event emitted by the outbox relay, sent exactly as it was serialized when it was enqueued.
+    #[serde(untagged, serialize_with = "RelayedEvent::serialize_payload")]
+    Relayed(RelayedEvent),
+}
+
+impl GatewayEvent {
+    /// Whether the event is assigned a sequence number and retained until acknowledged by the client.
+    ///
+    /// Connection-level events are not replayed when resuming a session.
+    pub const fn is_sequenced(&self) -> bool {
+        !matches!(
+            self,
+            Self::Hello { .. }
+                | Self::HeartbeatAck
+                | Self::Pong { .. }
+                | Self::Resumed
+                | Self::Closing { .. }
+                | Self::RequestDropped { .. }
+        )
+    }
+
+    /// The name of the event, such as `MESSAGE_CREATE`, as sent in the `event` field of its payload.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Hello { .. } => "HELLO",
+            Self::HeartbeatAck => "HEARTBEAT_ACK",
+            Self::Pong { .. } => "PONG",
+            Self::Resumed => "RESUMED",
+            Self::Closing { .. } => "CLOSING",
+            Self::RequestDropped { .. } => "REQUEST_DROPPED",
+            Self::MessageCreate(..) => "MESSAGE_CREATE",
+            Self::MessageUpdate(..) => "MESSAGE_UPDATE",
+            Self::MessageRemove { .. } => "MESSAGE_REMOVE",
+            Self::MemberCreate(..) => "MEMBER_CREATE",
+            Self::MemberRemove { .. } => "MEMBER_REMOVE",
+            Self::GuildCreate(..) => "GUILD_CREATE",
+            Self::GuildUpdate(..) => "GUILD_UPDATE",
+            Self::GuildRemove(..) => "GUILD_REMOVE",
+            Self::ChannelCreate(..) => "CHANNEL_CREATE",
+            Self::ChannelUpdate(..) => "CHANNEL_UPDATE",
+            Self::ChannelRemove(..) => "CHANNEL_REMOVE",
+            Self::MessageAck { .. } => "MESSAGE_ACK",
+            Self::UnreadUpdate { .. } => "UNREAD_UPDATE",
+            Self::PresenceUpdate { .. } => "PRESENCE_UPDATE",
+            Self::TypingStart { .. } => "TYPING_START",
+            Self::TypingStop { .. } => "TYPING_STOP",
+            Self::Ready { .. } => "READY",
+            Self::UserUpdate(..) => "USER_UPDATE",
+            Self::RelationshipAdd(..) => "RELATIONSHIP_ADD",
+            Self::RelationshipRemove { .. } => "RELATIONSHIP_REMOVE",
+            Self::UploadProgress { .. } => "UPLOAD_PROGRESS",
+            Self::AttachmentQuarantine { .. } => "ATTACHMENT_QUARANTINE",
+            Self::GuildMembersChunk { .. } => "GUILD_MEMBERS_CHUNK",
+            Self::KeywordAlert { .. } => "KEYWORD_ALERT",
+            Self::ReportCreate(..) => "REPORT_CREATE",
+            Self::StandingUpdate(..) => "STANDING_UPDATE",
+            Self::GuildEventCreate(..) => "GUILD_EVENT_CREATE",
+            Self::GuildEventUpdate(..) => "GUILD_EVENT_UPDATE",
+            Self::GuildEventRemove { .. } => "GUILD_EVENT_REMOVE",
+            Self::GuildEventRsvpUpdate { .. } => "GUILD_EVENT_RSVP_UPDATE",
+            Self::GuildEventReminder(..) => "GUILD_EVENT_REMINDER",
+            Self::RoleCreate(..) => "ROLE_CREATE",
+            Self::RoleUpdate(..) => "ROLE_UPDATE",
+            Self::RoleRemove { .. } => "ROLE_REMOVE",
+            Self::MemberRolesUpdate { .. } => "MEMBER_ROLES_UPDATE",
+            Self::RateLimit { .. } => "RATE_LIMIT",
+            Self::Relayed(event) => event.name(),
+        }
+    }
+
+    /// The channel the event is about, if it concerns a single channel.
+    pub fn channel_id(&self) -> Option<Snowflake<Channel>> {
+        match self {
+            Self::MessageCreate(message) | Self::MessageUpdate(message) => Some(message.channel_id()),
+            Self::ChannelCreate(channel) | Self::ChannelUpdate(channel) | Self::ChannelRemove(channel) => {
+                Some(channel.id())
+            }
+            Self::MessageRemove { channel_id, .. }
+            | Self::MessageAck { channel_id, .. }
+            | Self::TypingStart { channel_id, .. }
+            | Self::TypingStop { channel_id, .. }
+            | Self::UploadProgress { channel_id, .. }
+            | Self::AttachmentQuarantine { channel_id, .. }
+            | Self::KeywordAlert { channel_id, .. } => Some(*channel_id),
+            Self::Relayed(event) => event.channel_id,
+            _ => None,
+        }
+    }
+
+    /// The content of the new message, if this is a `MESSAGE_CREATE` event for a message with content.
+    pub fn created_message_content(&self) -> Option<&str> {
+        match self {
+            Self::MessageCreate(message) => message.content(),
+            Self::Relayed(event) => event.created_message_content(),
+            _ => None,
+        }
+    }
+
+    /// A copy of a `MESSAGE_CREATE` event with `muted` set on the message,
+    /// sent to users who muted a word contained in it, so that their clients can suppress notifications.
+    ///
+    /// ## Returns
+    ///
+    /// `None` if this is not a `MESSAGE_CREATE` event for a message with content.
+    pub fn to_muted(&self) -> Option<Self> {
+        self.created_message_content()?;
+
+        let mut event = match self {
+            Self::Relayed(event) => event.clone(),
+            _ => RelayedEvent::new(self),
+        };
+        event
+            .payload
+            .get_mut("data")
+            .and_then(serde_json::Value::as_object_mut)?
+            .insert("muted".into(), true.into());
+
+        Some(Self::Relayed(event))
+    }
+
+    /// The capabilities a session must have declared to receive this event at all
+    pub fn required_capabilities(&self) -> ClientCapability {
+        match self {
+            Self::PresenceUpdate { .. } => ClientCapability::PRESENCE,
+            Self::TypingStart { .. } | Self::TypingStop { .. } => ClientCapability::TYPING,
+            Self::Relayed(event) => event.required_capabilities(),
+            _ => ClientCapability::empty(),
+        }
+    }
+
+    /// A copy of this event with the presence left out of all members,
+    /// for sessions that did not declare [`ClientCapability::PRESENCE`].
+    ///
+    /// Returns `None` if the event includes no members.
+    pub fn without_presences(&self) -> Option<Self> {
+        match self {
+            Self::GuildCreate(payload) => Some(Self::GuildCreate(payload.clone().without_presences())),
+            Self::MemberCreate(member) => Some(Self::MemberCreate(member.clone().without_presence())),
+            Self::GuildMembersChunk {
+                guild_id,
+                members,
+                chunk_index,
+                chunk_count,
+                nonce,
+            } => Some(Self::GuildMembersChunk {
+                guild_id: *guild_id,
+                members: members.iter().cloned().map(Member::without_presence).collect(),
+                chunk_index: *chunk_index,
+                chunk_count: *chunk_count,
+                nonce: nonce.clone(),
+            }),
+            Self::Relayed(event) => event.without_presences().map(Self::Relayed),
+            _ => None,
+        }
+    }
+}
+
+/// A serialized [`GatewayEvent`], stored in the transactional outbox until it is relayed.
+///
+/// The payload is not parsed again when relayed, only the channel the event is about is kept alongside it,
+/// as the gateway needs it to decide who may receive the event.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RelayedEvent {
+    payload: serde_json::Value,
+    channel_id: Option<Snowflake<Channel>>,
+}
+
+impl RelayedEvent {
+    /// Serialize an event so that it can be relayed later.
+    pub fn new(event: &GatewayEvent) -> Self {
+        Self {
+            payload: serde_json::to_value(event).expect("event should serialize"),
+            channel_id: event.channel_id(),
+        }
+    }
+
+    /// The name of the relayed event, as found in its payload.
+    fn name(&self) -> &str {
+        self.payload
+            .get("event")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or_default()
+    }
+
+    /// The content of the new message, if the payload is a `MESSAGE_CREATE` event for a message with content.
+    fn created_message_content(&self) -> Option<&str> {
+        if self.payload.get("event")?.as_str()? != "MESSAGE_CREATE" {
+            return None;
+        }
+        self.payload.get("data")?.get("content")?.as_str()
+    }
+
+    /// Serialize only the payload, so that the event is sent to clients just like the original.
+    fn required_capabilities(&self) -> ClientCapability {
+        match self.payload.get("event").and_then(serde_json::Value::as_str) {
+            Some("PRESENCE_UPDATE") => ClientCapability::PRESENCE,
+            Some("TYPING_START") => ClientCapability::TYPING,
+            _ => ClientCapability::empty(),
+        }
+    }
+
+    fn without_presences(&self) -> Option<Self> {
+        let mut event = self.clone();
+        let data = event.payload.get_mut("data")?;
+        let members = match self.payload.get("event")?.as_str()? {
+            "GUILD_CREATE" | "GUILD_MEMBERS_CHUNK" => data.get_mut("members")?.as_array_mut()?.iter_mut().collect(),
+            "MEMBER_CREATE" => vec![data],
+            _ => return None,
+        };
+        for member in members {
+            if let Some(user) = member.get_mut("user").and_then(serde_json::Value::as_object_mut) {
+                user.remove("presence");
+            }
+        }
+        Some(event)
+    }
+
+    fn serialize_payload<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.payload.serialize(serializer)
+    }
 }
 
 /// A JSON payload that can be sent over the websocket by clients.
@@ -85,14 +459,67 @@ pub enum GatewayMessage {
     Identify {
         /// The token to authenticate with.
         token: Secret<String>,
+        /// The features the client supports, all of them if omitted.
+        #[serde(default)]
+        capabilities: ClientCapability,
+    },
+    /// Resume a previous session after a disconnect. This may be sent instead of `Identify`.
+    Resume {
+        /// The token to authenticate with.
+        token: Secret<String>,
+        /// The ID of the session to resume, as received in `READY`.
+        session_id: Uuid,
+        /// The sequence number of the last event the client received.
+        seq: u64,
     },
-    /// A heartbeat message to indicate that the client is still active.
+    /// A heartbeat message to indicate that the client is still connected.
     Heartbeat,
+    /// Measure the round-trip time to the server and the offset of the client's clock, answered with `PONG`.
+    Ping {
+        /// Echoed back in the `PONG`, to match it to this request.
+        nonce: String,
+        /// The round-trip time the client measured for its previous `PING`, in milliseconds.
+        latency: Option<u64>,
+    },
+    /// Indicate that the user is interacting with the client, marking them as online again if they were away.
+    Activity,
+    /// Acknowledge all events up to and including the given sequence number.
+    Ack {
+        /// The sequence number of the last event the client processed.
+        seq: u64,
+    },
     /// A message to start typing in a channel.
     StartTyping {
         /// The channel to start typing in.
         channel_id: Snowflake<Channel>,
     },
+    /// Request the members of a guild, answered with `GUILD_MEMBERS_CHUNK` events.
+    RequestGuildMembers {
+        /// The guild to request the members of.
+        guild_id: Snowflake<Guild>,
+        /// Only return members whose username, display name or nickname starts with this.
+        query: Option<String>,
+        /// The maximum amount of members to return.
+        limit: Option<u32>,
+        /// Echoed back in the response chunks.
+        nonce: Option<String>,
+    },
+}
+
+impl GatewayMessage {
+    /// Whether the message was sent because of the user interacting with the client.
+    /// Heartbeats are sent automatically, and do not keep the user from going away.
+    pub const fn is_activity(&self) -> bool {
+        !matches!(
+            self,
+            Self::Identify { .. } | Self::Resume { .. } | Self::Heartbeat | Self::Ping { .. }
+        )
+    }
+
+    /// Whether the message acts on behalf of the user towards others, and is refused while the instance is read-only.
+    pub const fn is_mutation(&self) -> bool {
+        matches!(self, Self::StartTyping { .. })
+    }
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -100,6 +527,15 @@ pub struct ReadStateEntry {
     pub channel_id: Snowflake<Channel>,
     pub last_read_message_id: Option<Snowflake<Message>>,
     pub last_message_id: Option<Snowflake<Message>>,
+    /// When the user last advanced the read state.
+    pub last_viewed_at: Option<DateTime<Utc>>,
+}
+
+/// The users currently typing in a channel.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TypingEntry {
+    pub channel_id: Snowflake<Channel>,
+    pub user_ids: Vec<Snowflake<User>>,
 }
 
 /// Represents a `GUILD_CREATE` payload.
@@ -109,13 +545,16 @@ pub struct ReadStateEntry {
 pub struct GuildCreatePayload {
     pub guild: Guild,
     pub members: Vec<Member>,
+    /// The total amount of members in the guild, which may be more than the members included.
+    pub member_count: usize,
     pub channels: Vec<Channel>,
 }
 
 impl GuildCreatePayload {
-    pub const fn new(guild: Guild, members: Vec<Member>, channels: Vec<Channel>) -> Self {
+    pub fn new(guild: Guild, members: Vec<Member>, channels: Vec<Channel>) -> Self {
         Self {
             guild,
+            member_count: members.len(),
             members,
             channels,
         }
@@ -123,21 +562,303 @@ impl GuildCreatePayload {
 
     /// Create a new guild create payload by fetching all relevant data from the database.
     ///
+    /// Guilds with more members than [`Config::guild_create_member_limit`](crate::app::Config::guild_create_member_limit)
+    /// only include the receiving member and as many of the members not offline as fit,
+    /// the rest have to be requested with `REQUEST_GUILD_MEMBERS`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `app` - The application state.
+    /// * `guild` - The guild to create the payload for.
+    /// * `user` - The member receiving the payload. Only the channels they can view are included.
+    ///
     /// ## Errors
     ///
     /// * [`sqlx::Error`] - If the database query fails.
-    pub async fn from_guild(app: &ApplicationState, guild: Guild) -> Result<Self, AppError> {
+    pub async fn from_guild(
+        app: &ApplicationState,
+        guild: Guild,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Self, AppError> {
+        let user_id = user.into();
+
         // Presences need to be included in the payload
-        let members = join_all(
+        let mut members = join_all(
             app.ops()
+                .guilds()
                 .fetch_members_for(&guild)
                 .await?
                 .into_iter()
-                .map(|m| m.include_presence(app.gateway())),
+                .map(|m| m.include_presence(app.gateway(), user_id)),
         )
         .await;
+        let member_count = members.len();
+
+        let limit = app.config.guild_create_member_limit();
+        if member_count > limit {
+            members.retain(|m| m.user().id() == user_id || m.user().displayed_presence() != Some(Presence::Offline));
+            // The receiving member always comes first, so it is never cut off
+            members.sort_by_key(|m| m.user().id() != user_id);
+            members.truncate(limit.max(1));
+        }
+
+        let channels = app.ops().guilds().fetch_channels_visible_to(&guild, user_id).await?;
+        Ok(Self {
+            guild,
+            members,
+            member_count,
+            channels,
+        })
+    }
+
+    /// Leave out the presence of all members, for sessions that did not declare [`ClientCapability::PRESENCE`].
+    #[must_use]
+    pub fn without_presences(self) -> Self {
+        Self {
+            members: self.members.into_iter().map(Member::without_presence).collect(),
+            ..self
+        }
+    }
+}
+
+/// Split the members answering a `REQUEST_GUILD_MEMBERS` request into `GUILD_MEMBERS_CHUNK` events.
+///
+/// Each chunk holds at most `max_members` members, and is kept below `max_size` bytes when serialized,
+/// unless a single member does not fit on its own. An empty list results in a single, empty chunk.
+///
+/// ## Arguments
+///
+/// * `guild_id` - The guild the members belong to.
+/// * `members` - The members to send.
+/// * `nonce` - The nonce of the request, echoed back in every chunk.
+/// * `max_members` - The maximum number of members in a chunk.
+/// * `max_size` - The maximum size of a serialized chunk, in bytes.
+pub fn guild_members_chunks(
+    guild_id: Snowflake<Guild>,
+    members: Vec<Member>,
+    nonce: Option<&str>,
+    max_members: usize,
+    max_size: usize,
+) -> Vec<GatewayEvent> {
+    let chunk_event = |members, chunk_index, chunk_count| GatewayEvent::GuildMembersChunk {
+        guild_id,
+        members,
+        chunk_index,
+        chunk_count,
+        nonce: nonce.map(ToOwned::to_owned),
+    };
+    // The size of a chunk without any members, the indices are accounted for at their maximum width
+    let envelope = serde_json::to_vec(&chunk_event(Vec::new(), u32::MAX, u32::MAX)).map_or(0, |v| v.len());
+
+    let mut chunks = Vec::new();
+    let mut current = Vec::new();
+    let mut size = envelope;
+
+    for member in members {
+        // Members are separated by a comma
+        let member_size = serde_json::to_vec(&member).map_or(0, |v| v.len()) + 1;
+
+        if !current.is_empty() && (current.len() >= max_members || size + member_size > max_size) {
+            chunks.push(std::mem::take(&mut current));
+            size = envelope;
+        }
+        size += member_size;
+        current.push(member);
+    }
+    chunks.push(current);
+
+    let chunk_count = u32::try_from(chunks.len()).unwrap_or(u32::MAX);
+    chunks
+        .into_iter()
+        .zip(0..)
+        .map(|(members, chunk_index)| chunk_event(members, chunk_index, chunk_count))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_test_member(id: i64) -> Member {
+        let user = User::builder()
+            .id(Snowflake::new(id))
+            .username(String::from("testuser"))
+            .last_presence(0)
+            .build()
+            .expect("Should successfully build a test user");
+        Member::new(user, Snowflake::new(1), None, chrono::DateTime::UNIX_EPOCH)
+    }
+
+    fn chunk_sizes(chunks: &[GatewayEvent]) -> Vec<usize> {
+        chunks
+            .iter()
+            .map(|c| match c {
+                GatewayEvent::GuildMembersChunk { members, .. } => members.len(),
+                _ => panic!("Expected a GUILD_MEMBERS_CHUNK"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_guild_members_chunks_empty() {
+        let chunks = guild_members_chunks(Snowflake::new(1), Vec::new(), None, 1000, 1024 * 1024);
+        assert_eq!(chunk_sizes(&chunks), vec![0]);
+    }
+
+    #[test]
+    fn test_guild_members_chunks_by_count() {
+        let members = (1..=5).map(new_test_member).collect();
+        let chunks = guild_members_chunks(Snowflake::new(1), members, Some("nonce"), 2, 1024 * 1024);
+        assert_eq!(chunk_sizes(&chunks), vec![2, 2, 1]);
+
+        let GatewayEvent::GuildMembersChunk {
+            chunk_index,
+            chunk_count,
+            nonce,
+            ..
+        } = &chunks[2]
+        else {
+            panic!("Expected a GUILD_MEMBERS_CHUNK");
+        };
+        assert_eq!((*chunk_index, *chunk_count), (2, 3));
+        assert_eq!(nonce.as_deref(), Some("nonce"));
+    }
+
+    #[test]
+    fn test_guild_members_chunks_by_size() {
+        let member_size = serde_json::to_vec(&new_test_member(1))
+            .expect("Member should serialize")
+            .len();
+        let members = (1..=10).map(new_test_member).collect();
+        let max_size = 1024;
+        let chunks = guild_members_chunks(Snowflake::new(1), members, None, 1000, max_size);
+
+        assert!(chunks.len() > 1);
+        assert_eq!(chunk_sizes(&chunks).iter().sum::<usize>(), 10);
+        for chunk in &chunks {
+            assert!(serde_json::to_vec(chunk).expect("Chunk should serialize").len() <= max_size);
+        }
+
+        // A member that does not fit on its own is still sent in its own chunk
+        let members = (1..=2).map(new_test_member).collect();
+        let chunks = guild_members_chunks(Snowflake::new(1), members, None, 1000, member_size / 2);
+        assert_eq!(chunk_sizes(&chunks), vec![1, 1]);
+    }
+
+    #[test]
+    fn test_event_name() {
+        let events = [
+            GatewayEvent::HeartbeatAck,
+            GatewayEvent::TypingStop {
+                user_id: Snowflake::new(1),
+                channel_id: Snowflake::new(2),
+            },
+            GatewayEvent::UnreadUpdate { badge: 1 },
+            GatewayEvent::MemberCreate(new_test_member(1)),
+        ];
+        for event in events {
+            let payload = serde_json::to_value(&event).expect("event should serialize");
+            assert_eq!(Some(event.name()), payload["event"].as_str());
+
+            let relayed = GatewayEvent::Relayed(RelayedEvent::new(&event));
+            assert_eq!(relayed.name(), event.name());
+        }
+    }
+
+    #[test]
+    fn test_muted_message_create() {
+        let message = Message::builder()
+            .id(Snowflake::new(3))
+            .channel_id(Snowflake::new(2))
+            .content(Some("The ending was a surprise".to_owned()))
+            .build()
+            .expect("Should successfully build a test message");
+        let event = GatewayEvent::MessageCreate(message);
+        let relayed = GatewayEvent::Relayed(RelayedEvent::new(&event));
+
+        for event in [&event, &relayed] {
+            assert_eq!(event.created_message_content(), Some("The ending was a surprise"));
+
+            let muted = event.to_muted().expect("MESSAGE_CREATE should be mutable");
+            let payload = serde_json::to_value(&muted).expect("event should serialize");
+            assert_eq!(payload["event"], "MESSAGE_CREATE");
+            assert_eq!(payload["data"]["muted"], true);
+            assert_eq!(payload["data"]["content"], "The ending was a surprise");
+            assert_eq!(muted.channel_id(), Some(Snowflake::new(2)));
+        }
+
+        // Only the muted copy is marked
+        assert!(
+            serde_json::to_value(&event).expect("event should serialize")["data"]
+                .get("muted")
+                .is_none()
+        );
+
+        let typing = GatewayEvent::TypingStart {
+            user_id: Snowflake::new(1),
+            channel_id: Snowflake::new(2),
+        };
+        assert!(typing.created_message_content().is_none());
+        assert!(GatewayEvent::Relayed(RelayedEvent::new(&typing)).to_muted().is_none());
+    }
+
+    #[test]
+    fn test_without_presences() {
+        let members: Vec<Member> = (1..=20)
+            .map(|id| {
+                let member = new_test_member(id);
+                Member::new(
+                    member.user().to_private(),
+                    member.guild_id(),
+                    None,
+                    chrono::DateTime::UNIX_EPOCH,
+                )
+            })
+            .collect();
+        let guild_create = GatewayEvent::GuildCreate(GuildCreatePayload::new(
+            Guild::new(Snowflake::new(1), "Among Us".into(), Snowflake::new(1)),
+            members.clone(),
+            Vec::new(),
+        ));
+        let member_create = GatewayEvent::MemberCreate(members[0].clone());
+
+        for event in [&guild_create, &member_create] {
+            for event in [event.clone(), GatewayEvent::Relayed(RelayedEvent::new(event))] {
+                let full = serde_json::to_value(&event).expect("event should serialize");
+                let trimmed = event.without_presences().expect("event should include members");
+                let trimmed = serde_json::to_value(&trimmed).expect("event should serialize");
+
+                let users = |payload: &serde_json::Value| {
+                    payload["data"].get("members").map_or_else(
+                        || vec![payload["data"]["user"].clone()],
+                        |members| {
+                            members
+                                .as_array()
+                                .expect("members should be an array")
+                                .iter()
+                                .map(|m| m["user"].clone())
+                                .collect()
+                        },
+                    )
+                };
+                assert!(users(&full).iter().all(|u| u["presence"] == "ONLINE"));
+                assert!(users(&trimmed).iter().all(|u| u.get("presence").is_none()));
+                assert_eq!(users(&trimmed)[0]["username"], "testuser");
+                assert!(trimmed.to_string().len() < full.to_string().len());
+            }
+        }
 
-        let channels = app.ops().fetch_channels_for(&guild).await?;
-        Ok(Self::new(guild, members, channels))
+        let typing = GatewayEvent::TypingStart {
+            user_id: Snowflake::new(1),
+            channel_id: Snowflake::new(2),
+        };
+        assert!(typing.without_presences().is_none());
+        assert_eq!(typing.required_capabilities(), ClientCapability::TYPING);
+        let presence = GatewayEvent::Relayed(RelayedEvent::new(&GatewayEvent::PresenceUpdate {
+            user_id: Snowflake::new(1),
+            presence: Presence::Online,
+        }));
+        assert_eq!(presence.required_capabilities(), ClientCapability::PRESENCE);
+        assert_eq!(guild_create.required_capabilities(), ClientCapability::empty());
     }
 }
@@ -0,0 +1,191 @@
+use chrono::{DateTime, SubsecRound, TimeDelta, Utc};
+use rand::{Rng, distributions::Alphanumeric};
+use serde::Serialize;
+
+use super::{channel::Channel, guild::Guild, snowflake::Snowflake, user::User};
+
+/// The length of generated guest link codes.
+pub const GUEST_LINK_CODE_LENGTH: usize = 10;
+/// The longest time a guest may keep their access for, in seconds.
+pub const MAX_GUEST_ACCESS_DURATION: u32 = 30 * 24 * 60 * 60;
+/// The time a guest keeps their access for if the link does not specify it, in seconds.
+pub const DEFAULT_GUEST_ACCESS_DURATION: u32 = 24 * 60 * 60;
+
+/// Represents a guest link stored in the database.
+pub struct GuestLinkRecord {
+    pub code: String,
+    pub guild_id: Snowflake<Guild>,
+    pub channel_id: Snowflake<Channel>,
+    pub creator_id: Option<i64>,
+    pub can_post: bool,
+    pub access_duration: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// A link that lets users join a guild as a guest, restricted to a single channel for a limited time.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct GuestLink {
+    /// The code the link is used through.
+    code: String,
+    /// The guild the link grants access to.
+    guild_id: Snowflake<Guild>,
+    /// The only channel guests joining through the link may view.
+    channel_id: Snowflake<Channel>,
+    /// The user who created the link, if they still exist.
+    creator_id: Option<Snowflake<User>>,
+    /// Whether guests may post in the channel.
+    can_post: bool,
+    /// How long guests keep their access after joining, in seconds.
+    access_duration: u32,
+    /// When the link can no longer be used, if ever.
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl GuestLink {
+    /// Create a new guest link with a randomly generated code.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the link grants access to.
+    /// * `channel` - The channel guests may view.
+    /// * `creator` - The user creating the link.
+    /// * `can_post` - Whether guests may post in the channel.
+    /// * `access_duration` - How long guests keep their access after joining, in seconds.
+    /// * `expires_at` - When the link can no longer be used, if ever.
+    pub fn new(
+        guild: impl Into<Snowflake<Guild>>,
+        channel: impl Into<Snowflake<Channel>>,
+        creator: impl Into<Snowflake<User>>,
+        can_post: bool,
+        access_duration: u32,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        let code = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(GUEST_LINK_CODE_LENGTH)
+            .map(char::from)
+            .collect();
+
+        Self {
+            code,
+            guild_id: guild.into(),
+            channel_id: channel.into(),
+            creator_id: Some(creator.into()),
+            can_post,
+            access_duration,
+            expires_at: expires_at.map(|at| at.trunc_subsecs(6)),
+        }
+    }
+
+    /// Build a guest link from a database record.
+    pub fn from_record(record: GuestLinkRecord) -> Self {
+        Self {
+            code: record.code,
+            guild_id: record.guild_id,
+            channel_id: record.channel_id,
+            creator_id: record.creator_id.map(Into::into),
+            can_post: record.can_post,
+            access_duration: record.access_duration.try_into().unwrap_or_default(),
+            expires_at: record.expires_at,
+        }
+    }
+
+    /// The code the link is used through.
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// The guild the link grants access to.
+    pub const fn guild_id(&self) -> Snowflake<Guild> {
+        self.guild_id
+    }
+
+    /// The only channel guests joining through the link may view.
+    pub const fn channel_id(&self) -> Snowflake<Channel> {
+        self.channel_id
+    }
+
+    /// The user who created the link, if they still exist.
+    pub const fn creator_id(&self) -> Option<Snowflake<User>> {
+        self.creator_id
+    }
+
+    /// Whether guests may post in the channel.
+    pub const fn can_post(&self) -> bool {
+        self.can_post
+    }
+
+    /// How long guests keep their access after joining, in seconds.
+    pub const fn access_duration(&self) -> u32 {
+        self.access_duration
+    }
+
+    /// When the link can no longer be used, if ever.
+    pub const fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at
+    }
+
+    /// Whether the link can no longer be used.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| at <= Utc::now())
+    }
+
+    /// When a guest joining now loses their access.
+    pub fn access_expires_at(&self) -> DateTime<Utc> {
+        Utc::now().trunc_subsecs(6) + TimeDelta::seconds(i64::from(self.access_duration))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_generates_code() {
+        let link = GuestLink::new(Snowflake::new(2), Snowflake::new(1), Snowflake::new(3), false, 60, None);
+        let other = GuestLink::new(Snowflake::new(2), Snowflake::new(1), Snowflake::new(3), false, 60, None);
+
+        assert_eq!(link.code().len(), GUEST_LINK_CODE_LENGTH);
+        assert!(link.code().chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(link.code(), other.code());
+        assert_eq!(link.guild_id(), Snowflake::new(2));
+        assert_eq!(link.channel_id(), Snowflake::new(1));
+        assert!(!link.is_expired());
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let now = Utc::now();
+
+        assert!(
+            GuestLink::new(
+                Snowflake::new(2),
+                Snowflake::new(1),
+                Snowflake::new(3),
+                false,
+                60,
+                Some(now - TimeDelta::seconds(1))
+            )
+            .is_expired()
+        );
+        assert!(
+            !GuestLink::new(
+                Snowflake::new(2),
+                Snowflake::new(1),
+                Snowflake::new(3),
+                false,
+                60,
+                Some(now + TimeDelta::seconds(60))
+            )
+            .is_expired()
+        );
+
+        let link = GuestLink::new(Snowflake::new(2), Snowflake::new(1), Snowflake::new(3), true, 60, None);
+        assert!(
+            (link.access_expires_at() - now - TimeDelta::seconds(60))
+                .num_seconds()
+                .abs()
+                < 10
+        );
+    }
+}
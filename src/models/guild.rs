@@ -1,10 +1,13 @@
-use serde::Serialize;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
 
 use crate::app::Config;
 
 use super::{
     avatar::{Avatar, FullAvatar, GuildAvatar, PartialAvatar},
-    errors::AppError,
+    errors::{AppError, BuildError},
+    notification_level::NotificationLevel,
     request_payloads::{CreateGuild, UpdateGuild},
     snowflake::Snowflake,
     user::User,
@@ -15,6 +18,47 @@ pub struct GuildRecord {
     pub name: String,
     pub owner_id: Snowflake<User>,
     pub avatar_hash: Option<String>,
+    pub features: Vec<String>,
+    pub attachment_archive_days: Option<i32>,
+    pub hide_history_before_join: bool,
+    pub default_notification_level: i16,
+}
+
+/// A feature that can be granted to a guild by an administrator,
+/// used to roll out experimental functionality per guild.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum GuildFeature {
+    /// The guild may be listed publicly. Informational only for now.
+    Discoverable,
+    /// The guild may lock channels so that only privileged members can post in them.
+    AnnouncementChannels,
+    /// The guild may claim a vanity URL.
+    VanityUrl,
+}
+
+impl GuildFeature {
+    /// The string representation of the feature, as stored in the database.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Discoverable => "DISCOVERABLE",
+            Self::AnnouncementChannels => "ANNOUNCEMENT_CHANNELS",
+            Self::VanityUrl => "VANITY_URL",
+        }
+    }
+}
+
+impl FromStr for GuildFeature {
+    type Err = BuildError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "DISCOVERABLE" => Ok(Self::Discoverable),
+            "ANNOUNCEMENT_CHANNELS" => Ok(Self::AnnouncementChannels),
+            "VANITY_URL" => Ok(Self::VanityUrl),
+            _ => Err(BuildError::ValidationError(format!("Unknown guild feature: {s}"))),
+        }
+    }
 }
 
 /// Represents a guild.
@@ -26,6 +70,13 @@ pub struct Guild {
 
     #[serde(rename = "avatar_hash")]
     avatar: Option<Avatar<GuildAvatar>>,
+    features: Vec<GuildFeature>,
+    /// The number of days after which attachments are moved to archive storage, `None` to never archive them.
+    attachment_archive_days: Option<u32>,
+    /// Whether members can only read messages sent after they joined the guild.
+    hide_history_before_join: bool,
+    /// Which messages wake the devices of members who did not set a level for the channel.
+    default_notification_level: NotificationLevel,
 }
 
 impl Guild {
@@ -42,6 +93,10 @@ impl Guild {
             name,
             owner_id: owner.into(),
             avatar: None,
+            features: Vec::new(),
+            attachment_archive_days: None,
+            hide_history_before_join: false,
+            default_notification_level: NotificationLevel::default(),
         }
     }
 
@@ -65,8 +120,40 @@ impl Guild {
         self.avatar.as_ref()
     }
 
+    /// The features granted to the guild, in a stable order.
+    pub fn features(&self) -> &[GuildFeature] {
+        &self.features
+    }
+
+    /// Whether the guild was granted the given feature.
+    pub fn has_feature(&self, feature: GuildFeature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    /// The number of days after which the guild's attachments are moved to archive storage,
+    /// or `None` if they are never archived.
+    pub const fn attachment_archive_days(&self) -> Option<u32> {
+        self.attachment_archive_days
+    }
+
+    /// Whether members can only read messages sent after they joined the guild.
+    pub const fn hide_history_before_join(&self) -> bool {
+        self.hide_history_before_join
+    }
+
+    /// Which messages wake the devices of members who did not set a level for the channel.
+    pub const fn default_notification_level(&self) -> NotificationLevel {
+        self.default_notification_level
+    }
+
     /// Create a new guild object from a database record.
+    ///
+    /// Features that are no longer known are ignored.
     pub fn from_record(record: GuildRecord) -> Self {
+        let mut features: Vec<GuildFeature> = record.features.iter().filter_map(|f| f.parse().ok()).collect();
+        features.sort_unstable();
+        features.dedup();
+
         Self {
             id: record.id,
             name: record.name,
@@ -76,6 +163,10 @@ impl Guild {
                     PartialAvatar::<GuildAvatar>::new(h, record.id).expect("Database should have valid avatar hash"),
                 )
             }),
+            features,
+            attachment_archive_days: record.attachment_archive_days.and_then(|d| d.try_into().ok()),
+            hide_history_before_join: record.hide_history_before_join,
+            default_notification_level: NotificationLevel::from(record.default_notification_level),
         }
     }
 
@@ -115,6 +206,15 @@ impl Guild {
         if let Some(owner_id) = payload.owner_id {
             self.owner_id = owner_id;
         }
+        if let Ok(days) = payload.attachment_archive_days.try_into() {
+            self.attachment_archive_days = days;
+        }
+        if let Some(hide) = payload.hide_history_before_join {
+            self.hide_history_before_join = hide;
+        }
+        if let Some(level) = payload.default_notification_level {
+            self.default_notification_level = level;
+        }
 
         if let Ok(avatar) = payload
             .avatar
@@ -182,6 +282,10 @@ mod tests {
             name: name.clone(),
             owner_id,
             avatar_hash,
+            features: vec!["VANITY_URL".into(), "REMOVED_FEATURE".into(), "DISCOVERABLE".into()],
+            attachment_archive_days: Some(30),
+            hide_history_before_join: true,
+            default_notification_level: 0,
         };
 
         let guild = Guild::from_record(record);
@@ -190,6 +294,28 @@ mod tests {
         assert_eq!(guild.name(), name);
         assert_eq!(guild.owner_id(), owner_id);
         assert!(guild.avatar().is_some());
+        assert_eq!(guild.features(), &[GuildFeature::Discoverable, GuildFeature::VanityUrl]);
+        assert!(guild.has_feature(GuildFeature::VanityUrl));
+        assert!(!guild.has_feature(GuildFeature::AnnouncementChannels));
+        assert_eq!(guild.attachment_archive_days(), Some(30));
+        assert!(guild.hide_history_before_join());
+        assert_eq!(guild.default_notification_level(), NotificationLevel::AllMessages);
+    }
+
+    #[test]
+    fn test_feature_str_roundtrip() {
+        for feature in [
+            GuildFeature::Discoverable,
+            GuildFeature::AnnouncementChannels,
+            GuildFeature::VanityUrl,
+        ] {
+            assert_eq!(feature.as_str().parse::<GuildFeature>().expect("Should parse"), feature);
+            assert_eq!(
+                serde_json::to_value(feature).expect("Should serialize"),
+                feature.as_str()
+            );
+        }
+        assert!("MORE_EMOJI".parse::<GuildFeature>().is_err());
     }
 
     #[test]
@@ -205,6 +331,9 @@ mod tests {
             name: Some(new_name.clone()),
             owner_id: None,
             avatar: OmittableOption::None,
+            attachment_archive_days: OmittableOption::Omitted,
+            hide_history_before_join: None,
+            default_notification_level: None,
         };
 
         let result = guild.update(update_payload);
@@ -225,6 +354,9 @@ mod tests {
             name: None,
             owner_id: Some(new_owner_id),
             avatar: OmittableOption::None,
+            attachment_archive_days: OmittableOption::Omitted,
+            hide_history_before_join: None,
+            default_notification_level: None,
         };
 
         let result = guild.update(update_payload);
@@ -245,6 +377,9 @@ mod tests {
             name: Some("ab".to_string()),
             owner_id: None,
             avatar: OmittableOption::None,
+            attachment_archive_days: OmittableOption::Omitted,
+            hide_history_before_join: None,
+            default_notification_level: None,
         };
 
         let result = guild.update(update_payload);
@@ -256,6 +391,9 @@ mod tests {
             name: Some("a".repeat(33)),
             owner_id: None,
             avatar: OmittableOption::None,
+            attachment_archive_days: OmittableOption::Omitted,
+            hide_history_before_join: None,
+            default_notification_level: None,
         };
 
         let result = guild.update(update_payload);
@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, SubsecRound, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    channel::Channel,
+    errors::BuildError,
+    guild::Guild,
+    request_payloads::{CreateGuildEvent, UpdateGuildEvent},
+    snowflake::Snowflake,
+    user::User,
+};
+use crate::{app::Config, external::fcm::Notification};
+
+/// The maximum length of a guild event's title.
+pub const MAX_GUILD_EVENT_TITLE_LENGTH: usize = 100;
+/// The maximum length of a guild event's description.
+pub const MAX_GUILD_EVENT_DESCRIPTION_LENGTH: usize = 1000;
+
+/// A member's answer to a guild event.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum RsvpStatus {
+    Going = 1,
+    Interested = 2,
+    Declined = 3,
+}
+
+impl RsvpStatus {
+    /// Whether members who gave this answer are reminded of the event.
+    pub const fn is_reminded(self) -> bool {
+        matches!(self, Self::Going | Self::Interested)
+    }
+}
+
+impl TryFrom<i16> for RsvpStatus {
+    type Error = BuildError;
+
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Going),
+            2 => Ok(Self::Interested),
+            3 => Ok(Self::Declined),
+            _ => Err(BuildError::ValidationError(format!("Unknown RSVP status: {value}"))),
+        }
+    }
+}
+
+/// Represents a guild event stored in the database.
+pub struct GuildEventRecord {
+    pub id: i64,
+    pub guild_id: i64,
+    pub creator_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    pub title: String,
+    pub description: Option<String>,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
+/// An event a guild scheduled for its members.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct GuildEvent {
+    id: Snowflake<Self>,
+    guild_id: Snowflake<Guild>,
+    /// The member who scheduled the event, if they still exist.
+    creator_id: Option<Snowflake<User>>,
+    /// The channel the event takes place in, if any.
+    channel_id: Option<Snowflake<Channel>>,
+    title: String,
+    description: Option<String>,
+    /// When the event starts.
+    starts_at: DateTime<Utc>,
+    /// When the event ends, if it was given.
+    ends_at: Option<DateTime<Utc>>,
+}
+
+impl GuildEvent {
+    /// Create a new event from a creation payload, with a freshly generated ID.
+    /// The channel is expected to have been checked to belong to the guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration, used to generate the ID.
+    /// * `guild` - The guild the event is scheduled in.
+    /// * `creator` - The member scheduling the event.
+    /// * `payload` - The event's details.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the details are invalid, or the event starts in the past.
+    pub fn from_payload(
+        config: &Config,
+        guild: impl Into<Snowflake<Guild>>,
+        creator: impl Into<Snowflake<User>>,
+        payload: CreateGuildEvent,
+    ) -> Result<Self, BuildError> {
+        let event = Self {
+            id: Snowflake::gen_new(config),
+            guild_id: guild.into(),
+            creator_id: Some(creator.into()),
+            channel_id: payload.channel_id,
+            title: payload.title.trim().to_owned(),
+            description: normalize_description(payload.description),
+            starts_at: payload.starts_at.trunc_subsecs(6),
+            ends_at: payload.ends_at.map(|ends_at| ends_at.trunc_subsecs(6)),
+        };
+        event.validate()?;
+
+        if event.starts_at <= Utc::now() {
+            return Err(BuildError::ValidationError("Events must start in the future".into()));
+        }
+        Ok(event)
+    }
+
+    /// Apply an update payload to the event.
+    /// A new channel is expected to have been checked to belong to the guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the updated details are invalid, or the event is moved into the past.
+    pub fn apply_update(&mut self, payload: UpdateGuildEvent) -> Result<(), BuildError> {
+        if let Some(title) = payload.title {
+            title.trim().clone_into(&mut self.title);
+        }
+        if let Ok(description) = Option::try_from(payload.description) {
+            self.description = normalize_description(description);
+        }
+        if let Some(starts_at) = payload.starts_at.map(|starts_at| starts_at.trunc_subsecs(6)) {
+            if starts_at != self.starts_at && starts_at <= Utc::now() {
+                return Err(BuildError::ValidationError("Events must start in the future".into()));
+            }
+            self.starts_at = starts_at;
+        }
+        if let Ok(ends_at) = Option::<DateTime<Utc>>::try_from(payload.ends_at) {
+            self.ends_at = ends_at.map(|ends_at| ends_at.trunc_subsecs(6));
+        }
+        if let Ok(channel_id) = Option::try_from(payload.channel_id) {
+            self.channel_id = channel_id;
+        }
+        self.validate()
+    }
+
+    /// Ensure the event's details are within their limits.
+    fn validate(&self) -> Result<(), BuildError> {
+        let title_length = self.title.chars().count();
+        if !(1..=MAX_GUILD_EVENT_TITLE_LENGTH).contains(&title_length) {
+            return Err(BuildError::ValidationError(format!(
+                "Event title must be between 1 and {MAX_GUILD_EVENT_TITLE_LENGTH} characters long"
+            )));
+        }
+        if self
+            .description
+            .as_ref()
+            .is_some_and(|d| d.chars().count() > MAX_GUILD_EVENT_DESCRIPTION_LENGTH)
+        {
+            return Err(BuildError::ValidationError(format!(
+                "Event description must be at most {MAX_GUILD_EVENT_DESCRIPTION_LENGTH} characters long"
+            )));
+        }
+        if self.ends_at.is_some_and(|ends_at| ends_at <= self.starts_at) {
+            return Err(BuildError::ValidationError("Events must end after they start".into()));
+        }
+        Ok(())
+    }
+
+    /// Build an event from a database record.
+    pub fn from_record(record: GuildEventRecord) -> Self {
+        Self {
+            id: record.id.into(),
+            guild_id: record.guild_id.into(),
+            creator_id: record.creator_id.map(Into::into),
+            channel_id: record.channel_id.map(Into::into),
+            title: record.title,
+            description: record.description,
+            starts_at: record.starts_at,
+            ends_at: record.ends_at,
+        }
+    }
+
+    /// The notification reminding attendees that the event is about to start.
+    pub fn reminder(&self) -> Notification {
+        Notification {
+            title: format!("{} is starting soon", self.title),
+            body: self
+                .description
+                .clone()
+                .unwrap_or_else(|| "An event you are attending is about to start.".into()),
+        }
+    }
+
+    /// The data payload of the push notification reminding attendees of the event.
+    pub fn reminder_data(&self) -> HashMap<String, String> {
+        let notification = self.reminder();
+
+        let mut data = HashMap::from([
+            ("type".to_string(), "guild_event_reminder".to_string()),
+            ("guild_id".to_string(), self.guild_id.to_string()),
+            ("event_id".to_string(), self.id.to_string()),
+            ("title".to_string(), notification.title),
+            ("body".to_string(), notification.body),
+            ("starts_at".to_string(), self.starts_at.to_rfc3339()),
+        ]);
+        if let Some(channel_id) = self.channel_id {
+            data.insert("channel_id".to_string(), channel_id.to_string());
+        }
+        data
+    }
+
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    pub const fn guild_id(&self) -> Snowflake<Guild> {
+        self.guild_id
+    }
+
+    /// The member who scheduled the event, if they still exist.
+    pub const fn creator_id(&self) -> Option<Snowflake<User>> {
+        self.creator_id
+    }
+
+    /// The channel the event takes place in, if any.
+    pub const fn channel_id(&self) -> Option<Snowflake<Channel>> {
+        self.channel_id
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// When the event starts.
+    pub const fn starts_at(&self) -> DateTime<Utc> {
+        self.starts_at
+    }
+
+    /// When the event ends, if it was given.
+    pub const fn ends_at(&self) -> Option<DateTime<Utc>> {
+        self.ends_at
+    }
+}
+
+/// A member's answer to a guild event.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct GuildEventRsvp {
+    pub event_id: Snowflake<GuildEvent>,
+    pub user_id: Snowflake<User>,
+    pub status: RsvpStatus,
+}
+
+/// Trim a description, dropping it if it is empty.
+fn normalize_description(description: Option<String>) -> Option<String> {
+    description.map(|d| d.trim().to_owned()).filter(|d| !d.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::models::omittableoption::OmittableOption;
+
+    fn event() -> GuildEvent {
+        GuildEvent::from_record(GuildEventRecord {
+            id: 1,
+            guild_id: 2,
+            creator_id: Some(3),
+            channel_id: None,
+            title: "Game night".into(),
+            description: None,
+            // Records are read back at the microsecond precision they are stored with
+            starts_at: (Utc::now() + TimeDelta::hours(1)).trunc_subsecs(6),
+            ends_at: None,
+        })
+    }
+
+    fn update() -> UpdateGuildEvent {
+        UpdateGuildEvent {
+            title: None,
+            description: OmittableOption::Omitted,
+            starts_at: None,
+            ends_at: OmittableOption::Omitted,
+            channel_id: OmittableOption::Omitted,
+        }
+    }
+
+    #[test]
+    fn test_apply_update() {
+        let mut event = event();
+        let starts_at = event.starts_at();
+
+        event
+            .apply_update(UpdateGuildEvent {
+                title: Some("  Movie night ".into()),
+                description: OmittableOption::Some("   ".into()),
+                ends_at: OmittableOption::Some(starts_at + TimeDelta::hours(2)),
+                ..update()
+            })
+            .expect("update is valid");
+        assert_eq!(event.title(), "Movie night");
+        assert_eq!(event.description(), None);
+        assert_eq!(event.ends_at(), Some(starts_at + TimeDelta::hours(2)));
+
+        event
+            .apply_update(UpdateGuildEvent {
+                ends_at: OmittableOption::None,
+                ..update()
+            })
+            .expect("update is valid");
+        assert_eq!(event.ends_at(), None);
+    }
+
+    #[test]
+    fn test_validation() {
+        let starts_at = event().starts_at();
+
+        for invalid in [
+            UpdateGuildEvent {
+                title: Some("  ".into()),
+                ..update()
+            },
+            UpdateGuildEvent {
+                title: Some("a".repeat(MAX_GUILD_EVENT_TITLE_LENGTH + 1)),
+                ..update()
+            },
+            UpdateGuildEvent {
+                description: OmittableOption::Some("a".repeat(MAX_GUILD_EVENT_DESCRIPTION_LENGTH + 1)),
+                ..update()
+            },
+            UpdateGuildEvent {
+                ends_at: OmittableOption::Some(starts_at),
+                ..update()
+            },
+            UpdateGuildEvent {
+                starts_at: Some(Utc::now() - TimeDelta::minutes(1)),
+                ..update()
+            },
+        ] {
+            assert!(event().apply_update(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_rsvp_status() {
+        assert_eq!(
+            RsvpStatus::try_from(RsvpStatus::Declined as i16).ok(),
+            Some(RsvpStatus::Declined)
+        );
+        assert!(RsvpStatus::try_from(0).is_err());
+        assert!(RsvpStatus::Interested.is_reminded());
+        assert!(!RsvpStatus::Declined.is_reminded());
+    }
+}
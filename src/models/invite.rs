@@ -0,0 +1,190 @@
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::Serialize;
+
+use super::{errors::BuildError, guild::Guild, snowflake::Snowflake, user::User};
+
+static VANITY_CODE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-z0-9]+(?:-[a-z0-9]+)*$").expect("Failed to compile vanity code regex"));
+
+/// Codes that may not be claimed as a vanity URL, as they either collide with
+/// routes or could be used to impersonate the service.
+const RESERVED_VANITY_CODES: &[&str] = &[
+    "admin",
+    "administrator",
+    "api",
+    "app",
+    "assets",
+    "chat",
+    "download",
+    "gateway",
+    "help",
+    "invite",
+    "invites",
+    "login",
+    "logout",
+    "mod",
+    "moderator",
+    "official",
+    "register",
+    "security",
+    "settings",
+    "staff",
+    "support",
+    "system",
+];
+
+/// Represents an invite that resolves to a guild.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Invite {
+    code: String,
+    guild: Guild,
+    /// Whether the code is the guild's vanity URL.
+    vanity: bool,
+    /// The number of full members of the guild when the invite was resolved.
+    approximate_member_count: i64,
+    /// Whether the guild has reached its member limit, so joining through the invite would fail.
+    is_full: bool,
+}
+
+impl Invite {
+    /// Create a new invite resolving to the guild's vanity URL.
+    ///
+    /// ## Arguments
+    ///
+    /// * `code` - The vanity code.
+    /// * `guild` - The guild the code resolves to.
+    /// * `member_count` - The number of full members of the guild.
+    /// * `member_limit` - The maximum number of full members a guild may have, if any.
+    pub fn vanity(code: String, guild: Guild, member_count: i64, member_limit: Option<usize>) -> Self {
+        Self {
+            code,
+            guild,
+            vanity: true,
+            approximate_member_count: member_count,
+            is_full: is_guild_full(member_count, member_limit),
+        }
+    }
+
+    /// The invite's code.
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// The guild the invite resolves to.
+    pub const fn guild(&self) -> &Guild {
+        &self.guild
+    }
+
+    /// Whether the code is the guild's vanity URL.
+    pub const fn is_vanity(&self) -> bool {
+        self.vanity
+    }
+
+    /// The number of full members of the guild when the invite was resolved.
+    pub const fn approximate_member_count(&self) -> i64 {
+        self.approximate_member_count
+    }
+
+    /// Whether the guild had reached its member limit when the invite was resolved.
+    pub const fn is_full(&self) -> bool {
+        self.is_full
+    }
+}
+
+/// Whether a guild with the given number of full members can not accept any more of them.
+///
+/// ## Arguments
+///
+/// * `member_count` - The number of full members of the guild.
+/// * `member_limit` - The maximum number of full members a guild may have, if any.
+pub fn is_guild_full(member_count: i64, member_limit: Option<usize>) -> bool {
+    member_limit.is_some_and(|limit| usize::try_from(member_count).is_ok_and(|count| count >= limit))
+}
+
+/// A single join through an invite.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct InviteUse {
+    /// The user who joined, if they still exist.
+    pub user_id: Option<Snowflake<User>>,
+    /// When the user joined.
+    pub used_at: DateTime<Utc>,
+}
+
+/// The usage of an invite, used by moderators to trace where members joined from.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct InviteUsage {
+    pub code: String,
+    pub guild_id: Snowflake<Guild>,
+    /// The total number of joins through the invite.
+    pub uses: i64,
+    /// The most recent joins through the invite, newest first.
+    pub recent_uses: Vec<InviteUse>,
+}
+
+/// Validates a vanity code, returning it on success.
+///
+/// A vanity code must be between 3 and 32 characters long, consist of lowercase letters,
+/// digits and non-consecutive hyphens, and must not be a reserved word.
+///
+/// ## Errors
+///
+/// * [`BuildError::ValidationError`] - If the code is invalid or reserved.
+pub fn validate_vanity_code(code: &str) -> Result<&str, BuildError> {
+    if !(3..=32).contains(&code.len()) {
+        return Err(BuildError::ValidationError(
+            "Invalid vanity code, must be between 3 and 32 characters long".to_string(),
+        ));
+    }
+    if !VANITY_CODE_REGEX.is_match(code) {
+        return Err(BuildError::ValidationError(format!(
+            "Invalid vanity code, must match regex: {}",
+            VANITY_CODE_REGEX.as_str()
+        )));
+    }
+    if RESERVED_VANITY_CODES.contains(&code) {
+        return Err(BuildError::ValidationError(format!("Vanity code '{code}' is reserved")));
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_vanity_code_valid() {
+        assert!(validate_vanity_code("abc").is_ok());
+        assert!(validate_vanity_code("rust-lang").is_ok());
+        assert!(validate_vanity_code("a1-b2-c3").is_ok());
+        assert!(validate_vanity_code(&"a".repeat(32)).is_ok());
+    }
+
+    #[test]
+    fn test_validate_vanity_code_invalid() {
+        assert!(validate_vanity_code("ab").is_err());
+        assert!(validate_vanity_code(&"a".repeat(33)).is_err());
+        assert!(validate_vanity_code("Upper").is_err());
+        assert!(validate_vanity_code("-abc").is_err());
+        assert!(validate_vanity_code("abc-").is_err());
+        assert!(validate_vanity_code("a--b").is_err());
+        assert!(validate_vanity_code("a b c").is_err());
+    }
+
+    #[test]
+    fn test_is_guild_full() {
+        assert!(!is_guild_full(1000, None));
+        assert!(!is_guild_full(9, Some(10)));
+        assert!(is_guild_full(10, Some(10)));
+        assert!(is_guild_full(11, Some(10)));
+    }
+
+    #[test]
+    fn test_validate_vanity_code_reserved() {
+        assert!(validate_vanity_code("admin").is_err());
+        assert!(validate_vanity_code("support").is_err());
+        assert!(validate_vanity_code("admins").is_ok());
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind};
+use dashmap::DashMap;
+use itertools::Itertools;
+use serde::Serialize;
+
+use super::{errors::BuildError, guild::Guild, snowflake::Snowflake};
+
+/// The maximum number of keywords a guild may watch for.
+pub const MAX_KEYWORDS: usize = 500;
+/// The maximum length of a single keyword, in characters.
+pub const MAX_KEYWORD_LENGTH: usize = 64;
+
+/// Normalize and validate a guild's keyword watch list.
+///
+/// Keywords are matched case-insensitively, so they are trimmed, lowercased and deduplicated.
+///
+/// ## Errors
+///
+/// * [`BuildError::ValidationError`] - If there are too many keywords, or a keyword is empty or too long.
+///
+/// ## Returns
+///
+/// The normalized keywords, sorted alphabetically.
+pub fn normalize_keywords(keywords: Vec<String>) -> Result<Vec<String>, BuildError> {
+    let keywords: Vec<String> = keywords
+        .into_iter()
+        .map(|k| k.trim().to_lowercase())
+        .sorted()
+        .dedup()
+        .collect();
+
+    if keywords.len() > MAX_KEYWORDS {
+        return Err(BuildError::ValidationError(format!(
+            "A guild may watch for at most {MAX_KEYWORDS} keywords"
+        )));
+    }
+
+    if keywords
+        .iter()
+        .any(|k| k.is_empty() || k.chars().count() > MAX_KEYWORD_LENGTH)
+    {
+        return Err(BuildError::ValidationError(format!(
+            "Keywords must be between 1 and {MAX_KEYWORD_LENGTH} characters long"
+        )));
+    }
+
+    Ok(keywords)
+}
+
+/// The keywords a guild watches for in new messages.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct KeywordWatchlist {
+    /// The guild the keywords belong to.
+    pub guild_id: Snowflake<Guild>,
+    /// The watched keywords, lowercased and sorted alphabetically.
+    pub keywords: Vec<String>,
+}
+
+/// A compiled keyword list, matching all keywords in a single pass over a message.
+///
+/// Used for the keyword watch lists of guilds, and the muted words of users.
+#[derive(Debug)]
+pub struct KeywordMatcher {
+    automaton: AhoCorasick,
+    keywords: Vec<String>,
+}
+
+impl KeywordMatcher {
+    /// Compile a matcher for the given keywords.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the automaton could not be built.
+    pub fn new(keywords: Vec<String>) -> Result<Self, BuildError> {
+        let automaton = AhoCorasickBuilder::new()
+            .match_kind(MatchKind::Standard)
+            .build(&keywords)
+            .map_err(|e| BuildError::ValidationError(format!("Failed to compile keywords: {e}")))?;
+
+        Ok(Self { automaton, keywords })
+    }
+
+    /// Whether the matcher has no keywords to match.
+    pub const fn is_empty(&self) -> bool {
+        self.keywords.is_empty()
+    }
+
+    /// Whether the given text contains any of the keywords.
+    ///
+    /// Matching is case-insensitive, as keywords are stored lowercased.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.is_match_lowercase(&text.to_lowercase())
+    }
+
+    /// Whether the given text, which is already lowercased, contains any of the keywords.
+    ///
+    /// Useful to check the same text against many matchers, lowercasing it only once.
+    pub fn is_match_lowercase(&self, lowered: &str) -> bool {
+        !self.is_empty() && self.automaton.is_match(lowered)
+    }
+
+    /// The keywords contained in the given text, in the order they were configured.
+    ///
+    /// Matching is case-insensitive, as keywords are stored lowercased.
+    pub fn find(&self, text: &str) -> Vec<&str> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        let lowered = text.to_lowercase();
+        self.automaton
+            .find_overlapping_iter(&lowered)
+            .map(|m| m.pattern().as_usize())
+            .sorted_unstable()
+            .dedup()
+            .map(|i| self.keywords[i].as_str())
+            .collect()
+    }
+}
+
+/// Compiled keyword matchers of guilds, so that watch lists are only compiled once
+/// instead of on every message. Entries must be invalidated when a guild's keywords change.
+#[derive(Debug, Default)]
+pub struct KeywordMatcherCache {
+    matchers: DashMap<Snowflake<Guild>, Arc<KeywordMatcher>>,
+}
+
+impl KeywordMatcherCache {
+    /// Create a new, empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The cached matcher of a guild, if any.
+    pub fn get(&self, guild: impl Into<Snowflake<Guild>>) -> Option<Arc<KeywordMatcher>> {
+        self.matchers.get(&guild.into()).map(|m| Arc::clone(&m))
+    }
+
+    /// Cache the matcher of a guild.
+    pub fn insert(&self, guild: impl Into<Snowflake<Guild>>, matcher: Arc<KeywordMatcher>) {
+        self.matchers.insert(guild.into(), matcher);
+    }
+
+    /// Drop the cached matcher of a guild, so that it is recompiled on next use.
+    pub fn invalidate(&self, guild: impl Into<Snowflake<Guild>>) {
+        self.matchers.remove(&guild.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_keywords() {
+        let keywords =
+            normalize_keywords(vec!["  Spam ".into(), "scam".into(), "SPAM".into()]).expect("keywords should be valid");
+        assert_eq!(keywords, vec!["scam", "spam"]);
+
+        assert!(normalize_keywords(vec!["   ".into()]).is_err());
+        assert!(normalize_keywords(vec!["a".repeat(MAX_KEYWORD_LENGTH + 1)]).is_err());
+        assert!(normalize_keywords((0..=MAX_KEYWORDS).map(|i| i.to_string()).collect()).is_err());
+    }
+
+    #[test]
+    fn test_matcher_find() {
+        let matcher = KeywordMatcher::new(vec!["scam".into(), "free nitro".into(), "cam".into()])
+            .expect("matcher should compile");
+
+        assert_eq!(
+            matcher.find("Get FREE NITRO here, not a scam!"),
+            vec!["scam", "free nitro", "cam"]
+        );
+        assert_eq!(matcher.find("Scam scam SCAM"), vec!["scam", "cam"]);
+        assert!(matcher.find("hello there").is_empty());
+        assert!(matcher.is_match("FREE NITRO"));
+        assert!(!matcher.is_match("hello there"));
+        assert!(
+            KeywordMatcher::new(Vec::new())
+                .expect("matcher should compile")
+                .find("scam")
+                .is_empty()
+        );
+    }
+}
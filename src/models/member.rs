@@ -1,10 +1,11 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, SubsecRound, Utc};
 use serde::Serialize;
 
-use crate::gateway::Gateway;
+use crate::{app::Config, gateway::Gateway};
 
 use super::{
     avatar::{Avatar, PartialAvatar, UserAvatar},
+    channel::Channel,
     errors::BuildError,
     guild::Guild,
 };
@@ -16,7 +17,10 @@ pub struct MemberRecord {
     pub user_id: Snowflake<User>,
     pub guild_id: Snowflake<Guild>,
     pub nickname: Option<String>,
-    pub joined_at: i64,
+    pub joined_at: DateTime<Utc>,
+    pub guest_channel_id: Option<i64>,
+    pub guest_can_post: bool,
+    pub guest_expires_at: Option<DateTime<Utc>>,
 }
 
 /// Represents a guild member record with associated user data as queried.
@@ -24,13 +28,59 @@ pub struct ExtendedMemberRecord {
     pub user_id: Snowflake<User>,
     pub guild_id: Snowflake<Guild>,
     pub nickname: Option<String>,
-    pub joined_at: i64,
+    pub joined_at: DateTime<Utc>,
+    pub guest_channel_id: Option<i64>,
+    pub guest_can_post: bool,
+    pub guest_expires_at: Option<DateTime<Utc>>,
     pub username: String,
     pub display_name: Option<String>,
     pub avatar_hash: Option<String>,
+    pub banner_hash: Option<String>,
     pub last_presence: i16,
 }
 
+/// The restricted access of a member that joined through a guest link.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GuestAccess {
+    /// The only channel the guest may view
+    channel_id: Snowflake<Channel>,
+    /// Whether the guest may post in the channel
+    can_post: bool,
+    /// When the guest is removed from the guild
+    expires_at: DateTime<Utc>,
+}
+
+impl GuestAccess {
+    /// Create a new guest access to the given channel.
+    pub fn new(channel: impl Into<Snowflake<Channel>>, can_post: bool, expires_at: DateTime<Utc>) -> Self {
+        Self {
+            channel_id: channel.into(),
+            can_post,
+            expires_at,
+        }
+    }
+
+    /// Build the guest access of a member from its database columns, if the member is a guest.
+    fn from_columns(channel_id: Option<i64>, can_post: bool, expires_at: Option<DateTime<Utc>>) -> Option<Self> {
+        Some(Self::new(channel_id?, can_post, expires_at?))
+    }
+
+    /// The only channel the guest may view
+    pub const fn channel_id(&self) -> Snowflake<Channel> {
+        self.channel_id
+    }
+
+    /// Whether the guest may post in the channel
+    pub const fn can_post(&self) -> bool {
+        self.can_post
+    }
+
+    /// When the guest is removed from the guild
+    pub const fn expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
+}
+
 #[derive(Serialize, Debug, Clone)]
 pub struct Member {
     /// The user this guild member represents
@@ -39,18 +89,27 @@ pub struct Member {
     guild_id: Snowflake<Guild>,
     /// Nickname of the user in this guild, if set
     nickname: Option<String>,
-    /// UNIX timestmap of when the user joined the guild
-    joined_at: i64,
+    /// When the user joined the guild
+    joined_at: DateTime<Utc>,
+    /// The access of the member if they joined through a guest link, restricting them to a single channel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    guest: Option<GuestAccess>,
 }
 
 impl Member {
     /// Create a new member with the given user, guild id, nickname, and joined at timestamp.
-    pub fn new(user: User, guild: impl Into<Snowflake<Guild>>, nickname: Option<String>, joined_at: i64) -> Self {
+    pub fn new(
+        user: User,
+        guild: impl Into<Snowflake<Guild>>,
+        nickname: Option<String>,
+        joined_at: DateTime<Utc>,
+    ) -> Self {
         Self {
             user,
             guild_id: guild.into(),
             nickname,
             joined_at,
+            guest: None,
         }
     }
 
@@ -73,11 +132,32 @@ impl Member {
         &mut self.nickname
     }
 
-    /// UNIX timestmap of when the user joined the guild
-    pub const fn joined_at(&self) -> i64 {
+    /// When the user joined the guild
+    pub const fn joined_at(&self) -> DateTime<Utc> {
         self.joined_at
     }
 
+    /// The smallest snowflake that could have been generated when the user joined the guild,
+    /// bounding the messages sent after they joined.
+    ///
+    /// ## Arguments
+    ///
+    /// * `epoch` - The snowflake epoch of the instance.
+    pub const fn joined_at_snowflake<T>(&self, epoch: i64) -> Snowflake<T> {
+        Snowflake::from_timestamp_with_epoch(self.joined_at.timestamp_millis(), epoch)
+    }
+
+    /// The access of the member if they joined through a guest link
+    pub const fn guest(&self) -> Option<&GuestAccess> {
+        self.guest.as_ref()
+    }
+
+    /// Whether the member may view the given channel of the guild.
+    /// Guests may only view the channel they were invited to.
+    pub fn can_view(&self, channel: impl Into<Snowflake<Channel>>) -> bool {
+        self.guest.is_none_or(|guest| guest.channel_id == channel.into())
+    }
+
     /// Mutable handle to the user this guild member represents
     pub const fn user_mut(&mut self) -> &mut User {
         &mut self.user
@@ -85,7 +165,10 @@ impl Member {
 
     /// Build a member object directly from a database record and a user
     pub fn from_record(user: User, record: MemberRecord) -> Self {
-        Self::new(user, record.guild_id, record.nickname, record.joined_at)
+        Self {
+            guest: GuestAccess::from_columns(record.guest_channel_id, record.guest_can_post, record.guest_expires_at),
+            ..Self::new(user, record.guild_id, record.nickname, record.joined_at)
+        }
     }
 
     /// Build a member object directly from a database record.
@@ -109,6 +192,10 @@ impl Member {
             builder.avatar(Avatar::Partial(PartialAvatar::new(avatar_hash, record.user_id)?));
         }
 
+        if let Some(banner_hash) = record.banner_hash {
+            builder.banner(Avatar::Partial(PartialAvatar::new(banner_hash, record.user_id)?));
+        }
+
         let user = builder
             .id(record.user_id)
             .username(record.username)
@@ -116,19 +203,29 @@ impl Member {
             .build()
             .expect("Failed to build user object.");
 
-        Ok(Self::new(user, record.guild_id, record.nickname, record.joined_at))
+        Ok(Self {
+            guest: GuestAccess::from_columns(record.guest_channel_id, record.guest_can_post, record.guest_expires_at),
+            ..Self::new(user, record.guild_id, record.nickname, record.joined_at)
+        })
     }
 
     /// Convert a user into a member with the given guild id.
     /// The join date of the member will be set to the current time.
     pub fn from_user(user: User, guild: impl Into<Snowflake<Guild>>) -> Self {
-        Self::new(user, guild.into(), None, Utc::now().timestamp())
+        Self::new(user, guild.into(), None, Utc::now().trunc_subsecs(6))
     }
 
-    /// Include the user's presence field in the member payload.
+    /// Include the user's presence field in the member payload, as shown to the given user.
     #[must_use]
-    pub async fn include_presence(self, gateway: &Gateway) -> Self {
-        let user = self.user.include_presence(gateway).await;
+    pub async fn include_presence(self, gateway: &Gateway, viewer: impl Into<Snowflake<User>>) -> Self {
+        let user = self.user.include_presence(gateway, viewer).await;
+        Self { user, ..self }
+    }
+
+    /// Leave out the user's presence field from the member payload, for clients that do not display presences.
+    #[must_use]
+    pub fn without_presence(self) -> Self {
+        let user = self.user.without_presence();
         Self { user, ..self }
     }
 }
@@ -166,6 +263,15 @@ impl UserLike {
         }
     }
 
+    /// The name shown for the user or member: its nickname in the guild, its display name, or its username.
+    pub fn shown_name(&self) -> &str {
+        match self {
+            Self::Member(member) => member.nickname().as_deref().or_else(|| member.user.display_name()),
+            Self::User(user) => user.display_name(),
+        }
+        .unwrap_or_else(|| self.username())
+    }
+
     /// The avatar of the user or member.
     pub const fn avatar(&self) -> Option<&Avatar<UserAvatar>> {
         match self {
@@ -176,8 +282,8 @@ impl UserLike {
 
     /// When the user or member was created.
     /// This is the same as the user ID's creation date.
-    pub const fn created_at(&self) -> DateTime<Utc> {
-        self.id().created_at()
+    pub const fn created_at(&self, config: &Config) -> DateTime<Utc> {
+        self.id().created_at(config)
     }
 }
 
@@ -218,6 +324,10 @@ mod tests {
             .expect("Should successfully build a test user")
     }
 
+    fn timestamp(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(secs, 0).expect("Timestamp should be in range")
+    }
+
     #[test]
     fn test_from_record() {
         let user_id = Snowflake::new(1);
@@ -227,14 +337,41 @@ mod tests {
             user_id,
             guild_id,
             nickname: Some(String::from("TestNickname")),
-            joined_at: 1000,
+            joined_at: timestamp(1000),
+            guest_channel_id: None,
+            guest_can_post: false,
+            guest_expires_at: None,
         };
 
         let member = Member::from_record(test_user, record);
         assert_eq!(member.user.id(), user_id);
         assert_eq!(member.guild_id, guild_id);
         assert_eq!(member.nickname, Some(String::from("TestNickname")));
-        assert_eq!(member.joined_at, 1000);
+        assert_eq!(member.joined_at, timestamp(1000));
+        assert!(member.guest.is_none());
+        assert!(member.can_view(Snowflake::new(3)));
+    }
+
+    #[test]
+    fn test_guest_from_record() {
+        let channel_id = Snowflake::new(3);
+        let record = MemberRecord {
+            user_id: Snowflake::new(1),
+            guild_id: Snowflake::new(2),
+            nickname: None,
+            joined_at: timestamp(1000),
+            guest_channel_id: Some(3),
+            guest_can_post: true,
+            guest_expires_at: Some(timestamp(5000)),
+        };
+
+        let member = Member::from_record(new_test_user(Snowflake::new(1)), record);
+        assert_eq!(
+            member.guest(),
+            Some(&GuestAccess::new(channel_id, true, timestamp(5000)))
+        );
+        assert!(member.can_view(channel_id));
+        assert!(!member.can_view(Snowflake::new(4)));
     }
 
     #[test]
@@ -245,10 +382,14 @@ mod tests {
             user_id,
             guild_id,
             nickname: Some(String::from("ExtendedNickname")),
-            joined_at: 2000,
+            joined_at: timestamp(2000),
+            guest_channel_id: None,
+            guest_can_post: false,
+            guest_expires_at: None,
             username: String::from("extendeduser"),
             display_name: Some(String::from("Extended Display")),
             avatar_hash: Some(String::from("hash123_png")),
+            banner_hash: None,
             last_presence: 1,
         };
 
@@ -256,7 +397,7 @@ mod tests {
         assert_eq!(member.user.id(), user_id);
         assert_eq!(member.guild_id, guild_id);
         assert_eq!(member.nickname, Some(String::from("ExtendedNickname")));
-        assert_eq!(member.joined_at, 2000);
+        assert_eq!(member.joined_at, timestamp(2000));
         assert_eq!(member.user.username(), "extendeduser");
         assert_eq!(member.user.display_name(), Some("Extended Display"));
         assert_eq!(
@@ -267,6 +408,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_joined_at_snowflake() {
+        let epoch = 1_000_000;
+        let member = Member::new(
+            new_test_user(Snowflake::new(1)),
+            Snowflake::new(2),
+            None,
+            timestamp(2000),
+        );
+        let floor: Snowflake<()> = member.joined_at_snowflake(epoch);
+
+        // Snowflakes generated during the second the member joined are at or above the floor
+        assert_eq!(floor.timestamp_with_epoch(epoch), 2_000_000);
+        assert!(Snowflake::<()>::new(i64::from(floor) | 0x3f_ffff) >= floor);
+        assert!(Snowflake::<()>::from_timestamp_with_epoch(2_000_999, epoch) >= floor);
+        // Those generated a millisecond before are not
+        assert!(
+            Snowflake::<()>::new(i64::from(Snowflake::<()>::from_timestamp_with_epoch(1_999_999, epoch)) | 0x3f_ffff)
+                < floor
+        );
+    }
+
     #[test]
     fn test_from_user() {
         let user_id = Snowflake::new(1);
@@ -276,7 +439,6 @@ mod tests {
         assert_eq!(member.user.id(), user_id);
         assert_eq!(member.guild_id, guild_id);
         assert_eq!(member.nickname, None);
-        let now = Utc::now().timestamp();
-        assert!((now - member.joined_at).abs() < 10);
+        assert!((Utc::now() - member.joined_at).num_seconds().abs() < 10);
     }
 }
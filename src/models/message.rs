@@ -1,7 +1,10 @@
-use axum::extract::Multipart;
-use chrono::{DateTime, Utc};
+use std::sync::LazyLock;
+
+use axum::{extract::Multipart, http::StatusCode};
+use chrono::{DateTime, SubsecRound, Utc};
 use derive_builder::Builder;
 use itertools::Itertools;
+use regex::Regex;
 use serde::Serialize;
 
 use crate::app::Config;
@@ -14,9 +17,28 @@ use super::{
     member::UserLike,
     request_payloads::{CreateMessage, UpdateMessage},
     snowflake::Snowflake,
+    upload_session::UploadSession,
     user::User,
 };
 
+/// Matches user mentions in message content, in the form of `<@user_id>`.
+static USER_MENTION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<@(\d{1,20})>").expect("Failed to compile user mention regex"));
+
+/// Matches channel mentions in message content, in the form of `<#channel_id>`.
+static CHANNEL_MENTION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<#(\d{1,20})>").expect("Failed to compile channel mention regex"));
+
+/// The IDs of the users mentioned in the given message content, in ascending order and without duplicates.
+pub fn mentioned_users(content: &str) -> Vec<Snowflake<User>> {
+    USER_MENTION_REGEX
+        .captures_iter(content)
+        .filter_map(|c| c[1].parse::<Snowflake<User>>().ok())
+        .sorted()
+        .dedup()
+        .collect()
+}
+
 /// Represents a message record stored in the database.
 pub struct MessageRecord {
     pub id: Snowflake<Message>,
@@ -24,6 +46,10 @@ pub struct MessageRecord {
     pub user_id: Option<Snowflake<User>>,
     pub edited: bool,
     pub content: String,
+    pub flagged: bool,
+    pub lang: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
 }
 
 /// Represents a message record with associated author data as queried.
@@ -34,12 +60,19 @@ pub struct ExtendedMessageRecord {
     pub content: Option<String>,
     pub user_id: Option<Snowflake<User>>,
     pub edited: bool,
+    pub flagged: bool,
+    pub lang: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
     pub username: Option<String>,
     pub display_name: Option<String>,
     pub avatar_hash: Option<String>,
+    pub banner_hash: Option<String>,
     pub attachment_id: Option<i32>,
     pub attachment_filename: Option<String>,
     pub attachment_content_type: Option<String>,
+    pub attachment_quarantined: Option<bool>,
+    pub attachment_key_version: Option<i16>,
 }
 
 /// A chat message.
@@ -47,13 +80,15 @@ pub struct ExtendedMessageRecord {
 #[builder(setter(into), build_fn(validate = "Self::validate", error = "BuildError"))]
 pub struct Message {
     /// The id of the message.
+    #[allow(clippy::use_self)] // Builder copies the field type verbatim
     id: Snowflake<Message>,
 
     /// The id of the channel this message was sent in.
     channel_id: Snowflake<Channel>,
 
-    /// The author of the message. This may be none if the author has been deleted since.
-    #[builder(setter(strip_option))]
+    /// The author of the message. This may be none if the author has been deleted since,
+    /// or if the message was sent by the system, such as welcome messages.
+    #[builder(default, setter(strip_option))]
     author: Option<UserLike>,
 
     /// A nonce that can be used by a client to determine if the message was sent.
@@ -65,13 +100,59 @@ pub struct Message {
     #[builder(default = "false")]
     edited: bool,
 
+    /// When the message was sent, truncated to the microsecond precision it is stored with.
+    #[builder(default = "Utc::now().trunc_subsecs(6)")]
+    created_at: DateTime<Utc>,
+
+    /// When the content of the message was last edited, if it was edited since edits were timestamped.
+    #[builder(default)]
+    edited_at: Option<DateTime<Utc>>,
+
+    /// If true, at least one of the message's attachments was quarantined by the attachment scanner.
+    #[builder(default = "false")]
+    flagged: bool,
+
     /// The content of the message.
     #[builder(default)]
     content: Option<String>,
 
+    /// The ISO 639-3 code of the language the content is written in, if it was detected.
+    #[builder(default)]
+    lang: Option<String>,
+
     /// Attachments sent with this message.
     #[builder(default)]
     attachments: Vec<Attachment>,
+
+    /// The channels of the same guild mentioned in the content, so that clients can link them without fetching them.
+    #[builder(default)]
+    mention_channels: Vec<ChannelMention>,
+}
+
+/// A channel mentioned in a message's content, with just enough data to render a link to it.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChannelMention {
+    /// The ID of the mentioned channel.
+    id: Snowflake<Channel>,
+    /// The name of the mentioned channel.
+    name: String,
+}
+
+impl ChannelMention {
+    /// Create a new channel mention.
+    pub const fn new(id: Snowflake<Channel>, name: String) -> Self {
+        Self { id, name }
+    }
+
+    /// The ID of the mentioned channel.
+    pub const fn id(&self) -> Snowflake<Channel> {
+        self.id
+    }
+
+    /// The name of the mentioned channel.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
 impl MessageBuilder {
@@ -99,7 +180,7 @@ impl Message {
 
     /// The user who sent this message.
     ///
-    /// This may be `None` if the author has been deleted since.
+    /// This may be `None` if the author has been deleted since, or if the message was sent by the system.
     pub const fn author(&self) -> Option<&UserLike> {
         self.author.as_ref()
     }
@@ -114,9 +195,19 @@ impl Message {
         self.edited
     }
 
+    /// If true, at least one of the message's attachments was quarantined by the attachment scanner.
+    pub const fn flagged(&self) -> bool {
+        self.flagged
+    }
+
     /// The time at which this message was sent.
     pub const fn created_at(&self) -> DateTime<Utc> {
-        self.id.created_at()
+        self.created_at
+    }
+
+    /// The time at which the content of this message was last edited, if it was edited since edits were timestamped.
+    pub const fn edited_at(&self) -> Option<DateTime<Utc>> {
+        self.edited_at
     }
 
     /// A nonce that can be used by a client to determine if the message was sent.
@@ -135,11 +226,56 @@ impl Message {
         self.content.as_mut()
     }
 
+    /// The ISO 639-3 code of the language the content is written in, if it was detected.
+    pub fn lang(&self) -> Option<&str> {
+        self.lang.as_deref()
+    }
+
+    /// Detect the language the content is written in. Content too short or ambiguous to tell is left undetected.
+    pub fn detect_language(&mut self) {
+        self.lang = self
+            .content
+            .as_deref()
+            .and_then(whatlang::detect)
+            .filter(whatlang::Info::is_reliable)
+            .map(|info| info.lang().code().to_string());
+    }
+
     /// The attachments sent with this message.
     pub fn attachments(&self) -> &[Attachment] {
         &self.attachments
     }
 
+    /// The IDs of the users mentioned in the message's content, in ascending order and without duplicates.
+    pub fn mentions(&self) -> Vec<Snowflake<User>> {
+        self.content().map(mentioned_users).unwrap_or_default()
+    }
+
+    /// The IDs of the channels mentioned in the message's content, in order of their first mention and without duplicates.
+    pub fn mentioned_channels(&self) -> Vec<Snowflake<Channel>> {
+        let Some(content) = self.content() else {
+            return Vec::new();
+        };
+
+        CHANNEL_MENTION_REGEX
+            .captures_iter(content)
+            .filter_map(|c| c[1].parse::<Snowflake<Channel>>().ok())
+            .unique()
+            .collect()
+    }
+
+    /// The resolved channels mentioned in the message's content.
+    pub fn mention_channels(&self) -> &[ChannelMention] {
+        &self.mention_channels
+    }
+
+    /// Set the resolved channels mentioned in the message's content.
+    ///
+    /// Mentions are resolved by [`MessageOps::resolve_channel_mentions`](crate::app::ops::MessageOps::resolve_channel_mentions).
+    pub fn set_mention_channels(&mut self, mentions: Vec<ChannelMention>) {
+        self.mention_channels = mentions;
+    }
+
     /// Create a new message or messages from the given records. Multiple records are linked together by their ID.
     ///
     /// ## Errors
@@ -169,11 +305,21 @@ impl Message {
                                     None => None,
                                 };
 
+                                let banner = match entry
+                                    .banner_hash
+                                    .map(|h| PartialAvatar::new(h, user_id).map(Avatar::Partial))
+                                {
+                                    Some(Ok(banner)) => Some(banner),
+                                    Some(Err(e)) => return Some(Err(e)),
+                                    None => None,
+                                };
+
                                 let user = match User::builder()
                                     .id(user_id)
                                     .username(entry.username.expect("User should have username")) // This is fine because user_id is not None.
                                     .display_name(entry.display_name)
                                     .avatar(avatar)
+                                    .banner(banner)
                                     .build()
                                 {
                                     Ok(user) => user,
@@ -190,10 +336,15 @@ impl Message {
                             id: entry.id.into(),
                             channel_id: entry.channel_id.into(),
                             edited: entry.edited,
+                            created_at: entry.created_at,
+                            edited_at: entry.edited_at,
+                            flagged: entry.flagged,
                             author,
                             content: entry.content,
+                            lang: entry.lang,
                             nonce: None,
                             attachments: attachment,
+                            mention_channels: Vec::new(),
                         }))
                     }
                     // An aggregate value already exists, append the attachment to the message
@@ -223,11 +374,17 @@ impl Message {
         if let Ok(mut content) = Option::try_from(payload.content) {
             content = content.map(|c: String| c.trim().to_string());
             self.edited = self.content != content;
+            if self.edited {
+                self.edited_at = Some(Utc::now().trunc_subsecs(6));
+                self.lang = None;
+                self.mention_channels.clear();
+            }
             self.content = content;
         }
     }
 
     /// Create a new message from the given formdata. Assigns a new snowflake to the message.
+    /// The language of the content is detected if enabled in the config.
     ///
     /// ## Errors
     ///
@@ -241,30 +398,88 @@ impl Message {
         let id = Snowflake::gen_new(config);
         let channel_id: Snowflake<Channel> = channel.into();
         let mut attachments: Vec<Attachment> = Vec::new();
+        let mut attachments_size = 0;
         let mut builder = Self::builder();
 
         builder.id(id).channel_id(channel_id).author(author);
 
         while let Some(part) = form.next_field().await? {
             if part.name() == Some("json") && part.content_type().is_some_and(|ct| ct == "application/json") {
-                let Ok(data) = part.bytes().await else {
-                    return Err(RESTError::MalformedField("json".to_string()));
+                let data = match part.bytes().await {
+                    Ok(data) => data,
+                    Err(e) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => return Err(e.into()),
+                    Err(_) => return Err(RESTError::MalformedField("json".to_string())),
                 };
                 let payload = serde_json::from_slice::<CreateMessage>(&data)?;
                 builder
                     .content(payload.content.map(|c| c.trim().to_string()))
                     .nonce(payload.nonce.clone());
             } else {
-                let attachment = FullAttachment::try_from_field(part, channel_id, id).await?;
+                // Stop before reading the attachment if the message already has as many as it may have
+                if attachments.len() >= config.max_message_attachments() {
+                    return Err(RESTError::TooManyAttachments(config.max_message_attachments()));
+                }
+
+                let mut attachment = FullAttachment::try_from_field(
+                    part,
+                    channel_id,
+                    id,
+                    attachments_size,
+                    config.max_message_attachments_size(),
+                )
+                .await?;
+                attachments_size += attachment.size();
 
                 if attachments.iter().any(|a| a.id() == attachment.id()) {
                     return Err(RESTError::DuplicateField("attachment.id".to_string()));
                 }
+
+                if config.strip_image_metadata() {
+                    attachment.strip_image_metadata().await;
+                }
                 attachments.push(Attachment::Full(attachment));
             }
         }
 
-        Ok(builder.attachments(attachments).build()?)
+        let mut message = builder.attachments(attachments).build()?;
+        if config.detect_message_language() {
+            message.detect_language();
+        }
+        Ok(message)
+    }
+
+    /// Create a new message from a completed upload session.
+    /// The message takes on the ID reserved by the session, with the uploaded file as its only attachment.
+    /// The language of the content is detected if enabled in the config.
+    ///
+    /// ## Parameters
+    ///
+    /// - `config` - The application configuration
+    /// - `author` - The author of the message
+    /// - `session` - The completed upload session
+    /// - `payload` - The message content accompanying the upload
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError`] - If the message could not be built
+    pub fn from_upload_session(
+        config: &Config,
+        author: UserLike,
+        session: &UploadSession,
+        payload: CreateMessage,
+    ) -> Result<Self, BuildError> {
+        let mut message = Self::builder()
+            .id(session.message_id())
+            .channel_id(session.channel_id())
+            .author(author)
+            .content(payload.content.map(|c| c.trim().to_string()))
+            .nonce(payload.nonce)
+            .attachments(vec![Attachment::Partial(session.attachment())])
+            .build()?;
+        if config.detect_message_language() {
+            message.detect_language();
+        }
+        Ok(message)
     }
 
     /// Turns all attachments into partial attachments, removing the attachment contents from memory.
@@ -377,13 +592,13 @@ mod tests {
             .build();
         assert!(result.is_err());
 
-        // Invalid: has no author
+        // Valid: system messages have no author
         let result = Message::builder()
             .id(Snowflake::new(1))
             .channel_id(Snowflake::new(2))
             .content(Some("Hello".to_string()))
             .build();
-        assert!(result.is_err());
+        assert!(result.is_ok_and(|m| m.author().is_none()));
     }
 
     #[test]
@@ -437,6 +652,7 @@ mod tests {
 
         assert_eq!(message.content(), Some("Updated content"));
         assert!(message.edited());
+        assert!(message.edited_at().is_some());
 
         // Update with same content shouldn't change edited flag
         let update = UpdateMessage {
@@ -450,6 +666,31 @@ mod tests {
         assert!(!message.edited());
     }
 
+    #[test]
+    fn test_detect_language() {
+        let mut message = dummy_message();
+        message.content = Some(
+            "I think we should meet at the station tomorrow morning, because the weather is going to be nice."
+                .to_string(),
+        );
+        message.detect_language();
+        assert_eq!(message.lang(), Some("eng"));
+
+        // Editing the content discards the language detected for the previous content
+        message.apply_update(UpdateMessage {
+            content: OmittableOption::Some(
+                "Ich glaube, wir sollten uns morgen früh am Bahnhof treffen, weil das Wetter schön wird.".to_string(),
+            ),
+        });
+        assert_eq!(message.lang(), None);
+        message.detect_language();
+        assert_eq!(message.lang(), Some("deu"));
+
+        message.content = None;
+        message.detect_language();
+        assert_eq!(message.lang(), None);
+    }
+
     #[test]
     fn test_from_records_empty() {
         let records: Vec<ExtendedMessageRecord> = Vec::new();
@@ -467,12 +708,19 @@ mod tests {
                     content: Some("Test content".to_string()),
                     user_id: Some(Snowflake::new(2)),
                     edited: false,
+                    flagged: false,
+                    lang: None,
+                    created_at: DateTime::UNIX_EPOCH,
+                    edited_at: None,
                     username: Some("testuser".to_string()),
                     display_name: Some("Test User".to_string()),
                     avatar_hash: Some("avatar_hash_png".to_string()),
+                    banner_hash: None,
                     attachment_id: Some(i),
                     attachment_filename: Some("test.txt".to_string()),
                     attachment_content_type: Some("text/plain".to_string()),
+                    attachment_quarantined: Some(false),
+                    attachment_key_version: Some(2),
                 })
                 .collect::<Vec<_>>()
         };
@@ -519,12 +767,19 @@ mod tests {
                     content: Some("Test content".to_string()),
                     user_id: Some(Snowflake::new(2)),
                     edited: false,
+                    flagged: false,
+                    lang: None,
+                    created_at: DateTime::UNIX_EPOCH,
+                    edited_at: None,
                     username: Some("testuser".to_string()),
                     display_name: Some("Test User".to_string()),
                     avatar_hash: Some("avatar_hash_png".to_string()),
+                    banner_hash: None,
                     attachment_id: Some((i / 5).try_into().expect("explod")),
                     attachment_filename: Some("test.txt".to_string()),
                     attachment_content_type: Some("text/plain".to_string()),
+                    attachment_quarantined: Some(false),
+                    attachment_key_version: Some(2),
                 })
                 .collect::<Vec<_>>()
         };
@@ -562,7 +817,7 @@ mod tests {
 
             message.attachments.sort_by_key(super::AttachmentLike::id);
 
-            assert_eq!(message.attachments().len(), 5, "message {i} should have 5 attachments",);
+            assert_eq!(message.attachments().len(), 5, "message {i} should have 5 attachments");
             assert_eq!(message.attachments()[0].id(), 0);
             assert_eq!(message.attachments()[1].id(), 1);
             assert_eq!(message.attachments()[2].id(), 2);
@@ -584,4 +839,28 @@ mod tests {
         let id2 = Snowflake::<Message>::from(message);
         assert_eq!(id2, Snowflake::new(123));
     }
+
+    #[test]
+    fn test_mentions() {
+        let mut message = dummy_message();
+        assert!(message.mentions().is_empty());
+
+        *message.content_mut().expect("content should be set") =
+            "hey <@42> and <@7>, also <@42> again <@nope> <@99999999999999999999> <@ 5>".to_string();
+        assert_eq!(message.mentions(), vec![Snowflake::new(7), Snowflake::new(42)]);
+    }
+
+    #[test]
+    fn test_mentioned_channels() {
+        let mut message = dummy_message();
+        assert!(message.mentioned_channels().is_empty());
+
+        *message.content_mut().expect("content should be set") =
+            "see <#42> and <#7>, also <#42> again <#nope> <#99999999999999999999> <# 5> <@3>".to_string();
+        assert_eq!(
+            message.mentioned_channels(),
+            vec![Snowflake::new(42), Snowflake::new(7)]
+        );
+        assert!(message.mention_channels().is_empty());
+    }
 }
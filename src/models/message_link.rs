@@ -0,0 +1,146 @@
+use std::{str::FromStr, sync::LazyLock};
+
+use regex::Regex;
+use serde::Serialize;
+
+use super::{
+    channel::{Channel, ChannelLike},
+    guild::Guild,
+    member::UserLike,
+    message::Message,
+    snowflake::Snowflake,
+};
+
+/// The longest snippet of the message content included in a preview, in characters.
+pub const MAX_SNIPPET_LENGTH: usize = 200;
+
+/// Message links end in `/channels/{guild_id}/{channel_id}/{message_id}`, the base URL is up to the client.
+static MESSAGE_LINK_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"/channels/(?P<guild>[0-9]+)/(?P<channel>[0-9]+)/(?P<message>[0-9]+)/?(?:[?#].*)?$")
+        .expect("Failed to compile message link regex")
+});
+
+/// A link pointing to a message in a guild channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageLink {
+    guild: Snowflake<Guild>,
+    channel: Snowflake<Channel>,
+    message: Snowflake<Message>,
+}
+
+impl MessageLink {
+    /// The guild the linked message is in.
+    pub const fn guild_id(&self) -> Snowflake<Guild> {
+        self.guild
+    }
+
+    /// The channel the linked message is in.
+    pub const fn channel_id(&self) -> Snowflake<Channel> {
+        self.channel
+    }
+
+    /// The ID of the linked message.
+    pub const fn message_id(&self) -> Snowflake<Message> {
+        self.message
+    }
+}
+
+impl FromStr for MessageLink {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let captures = MESSAGE_LINK_REGEX
+            .captures(s)
+            .ok_or_else(|| String::from("Not a message link"))?;
+        let id = |name: &str| captures[name].parse::<i64>().map_err(|_| format!("Invalid {name} ID"));
+
+        Ok(Self {
+            guild: id("guild")?.into(),
+            channel: id("channel")?.into(),
+            message: id("message")?.into(),
+        })
+    }
+}
+
+/// A compact preview of a linked message, for clients to embed in place of the link.
+#[derive(Serialize, Debug, Clone)]
+pub struct MessageLinkPreview {
+    guild_id: Option<Snowflake<Guild>>,
+    channel_id: Snowflake<Channel>,
+    channel_name: String,
+    message_id: Snowflake<Message>,
+    author: Option<UserLike>,
+    /// The start of the message content, at most [`MAX_SNIPPET_LENGTH`] characters.
+    snippet: Option<String>,
+    attachment_count: usize,
+}
+
+impl MessageLinkPreview {
+    /// Create a new preview of a message in the given channel.
+    pub fn new(channel: &Channel, message: &Message) -> Self {
+        Self {
+            guild_id: channel.guild_id(),
+            channel_id: channel.id(),
+            channel_name: channel.name().to_owned(),
+            message_id: message.id(),
+            author: message.author().cloned(),
+            snippet: message
+                .content()
+                .map(|content| content.chars().take(MAX_SNIPPET_LENGTH).collect()),
+            attachment_count: message.attachments().len(),
+        }
+    }
+
+    /// The start of the message content, if it has any.
+    pub fn snippet(&self) -> Option<&str> {
+        self.snippet.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::channel::TextChannel;
+
+    #[test]
+    fn test_parse_message_link() {
+        let link: MessageLink = "https://chat.example.com/channels/1/2/3"
+            .parse()
+            .expect("Should parse a message link");
+        assert_eq!(link.guild_id(), Snowflake::new(1));
+        assert_eq!(link.channel_id(), Snowflake::new(2));
+        assert_eq!(link.message_id(), Snowflake::new(3));
+
+        let link: MessageLink = "https://chat.example.com/app/channels/1/2/3/?highlight=true"
+            .parse()
+            .expect("Should parse a message link with a query");
+        assert_eq!(link.message_id(), Snowflake::new(3));
+
+        assert!("https://chat.example.com/channels/1/2".parse::<MessageLink>().is_err());
+        assert!(
+            "https://chat.example.com/channels/1/2/abc"
+                .parse::<MessageLink>()
+                .is_err()
+        );
+        assert!(
+            "https://chat.example.com/channels/1/2/99999999999999999999"
+                .parse::<MessageLink>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_preview_snippet() {
+        let channel = Channel::GuildText(TextChannel::new(Snowflake::new(2), Snowflake::new(1), "general"));
+        let message = Message::builder()
+            .id(Snowflake::new(3))
+            .channel_id(Snowflake::new(2))
+            .content(Some("ä".repeat(MAX_SNIPPET_LENGTH + 10)))
+            .build()
+            .expect("Should successfully build a test message");
+
+        let preview = MessageLinkPreview::new(&channel, &message);
+        assert_eq!(preview.snippet().map(|s| s.chars().count()), Some(MAX_SNIPPET_LENGTH));
+        assert_eq!(preview.channel_name, "general");
+    }
+}
@@ -1,16 +1,36 @@
+pub mod active_channel;
 pub mod attachment;
+pub mod audit_log;
 pub mod auth;
+pub mod automod;
 pub mod avatar;
+pub mod byte_range;
 pub mod capability;
 pub mod channel;
 pub mod data_uri;
+pub mod direct_upload;
 pub mod errors;
 pub mod gateway_event;
+pub mod guest_link;
 pub mod guild;
+pub mod guild_event;
+pub mod invite;
+pub mod keyword_alert;
 pub mod member;
 pub mod message;
+pub mod message_link;
+pub mod notification_digest;
+pub mod notification_level;
 pub mod omittableoption;
+pub mod onboarding;
+pub mod outbox;
 pub mod prefs;
+pub mod registration_code;
+pub mod relationship;
+pub mod report;
 pub mod request_payloads;
+pub mod role;
 pub mod snowflake;
+pub mod standing;
+pub mod upload_session;
 pub mod user;
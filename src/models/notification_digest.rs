@@ -0,0 +1,129 @@
+use std::{collections::HashMap, fmt::Write};
+
+use serde::Serialize;
+
+use crate::external::fcm::Notification;
+
+use super::{guild::Guild, snowflake::Snowflake};
+
+/// The maximum number of guilds listed by name in a digest notification.
+const MAX_LISTED_GUILDS: usize = 3;
+
+/// The number of unread messages a user has in a guild.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct GuildUnreadCount {
+    pub guild_id: Snowflake<Guild>,
+    pub guild_name: String,
+    pub unread: i64,
+}
+
+/// A single push notification summarizing a long-offline user's unread messages per guild.
+///
+/// Sent periodically instead of a push per message once the user has left
+/// enough pushes in a channel unanswered.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NotificationDigest {
+    guilds: Vec<GuildUnreadCount>,
+}
+
+impl NotificationDigest {
+    /// Create a new digest from the unread counts of each guild.
+    /// Guilds are ordered by their unread count, highest first.
+    pub fn new(mut guilds: Vec<GuildUnreadCount>) -> Self {
+        guilds.sort_by(|a, b| b.unread.cmp(&a.unread).then(a.guild_id.cmp(&b.guild_id)));
+        Self { guilds }
+    }
+
+    /// The unread counts of each guild in the digest.
+    pub fn guilds(&self) -> &[GuildUnreadCount] {
+        &self.guilds
+    }
+
+    /// The total number of unread messages across all guilds.
+    pub fn total_unread(&self) -> i64 {
+        self.guilds.iter().map(|g| g.unread).sum()
+    }
+
+    /// Whether there is nothing to notify the user about.
+    pub fn is_empty(&self) -> bool {
+        self.total_unread() == 0
+    }
+
+    /// The notification to display to the user.
+    pub fn notification(&self) -> Notification {
+        let mut body = self
+            .guilds
+            .iter()
+            .take(MAX_LISTED_GUILDS)
+            .map(|g| format!("{} in {}", g.unread, g.guild_name))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if self.guilds.len() > MAX_LISTED_GUILDS {
+            let _ = write!(body, " and {} more", self.guilds.len() - MAX_LISTED_GUILDS);
+        }
+
+        Notification {
+            title: format!("You have {} unread messages", self.total_unread()),
+            body,
+        }
+    }
+
+    /// The data payload sent alongside the notification.
+    pub fn data(&self) -> HashMap<String, String> {
+        let notification = self.notification();
+
+        HashMap::from([
+            ("type".to_string(), "digest".to_string()),
+            ("title".to_string(), notification.title),
+            ("body".to_string(), notification.body),
+            (
+                "guilds".to_string(),
+                serde_json::to_string(&self.guilds).expect("Failed to serialize digest"),
+            ),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(id: i64, name: &str, unread: i64) -> GuildUnreadCount {
+        GuildUnreadCount {
+            guild_id: Snowflake::new(id),
+            guild_name: name.to_string(),
+            unread,
+        }
+    }
+
+    #[test]
+    fn test_notification() {
+        let digest = NotificationDigest::new(vec![count(1, "Rust", 2), count(2, "Python", 5)]);
+        let notification = digest.notification();
+
+        assert_eq!(digest.total_unread(), 7);
+        assert_eq!(notification.title, "You have 7 unread messages");
+        assert_eq!(notification.body, "5 in Python, 2 in Rust");
+    }
+
+    #[test]
+    fn test_notification_truncates_guilds() {
+        let digest = NotificationDigest::new(vec![
+            count(1, "A", 1),
+            count(2, "B", 4),
+            count(3, "C", 3),
+            count(4, "D", 2),
+            count(5, "E", 1),
+        ]);
+
+        assert_eq!(digest.notification().body, "4 in B, 3 in C, 2 in D and 2 more");
+    }
+
+    #[test]
+    fn test_empty() {
+        assert!(NotificationDigest::default().is_empty());
+        assert!(NotificationDigest::new(vec![count(1, "A", 0)]).is_empty());
+        assert!(!NotificationDigest::new(vec![count(1, "A", 1)]).is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+use super::{channel::Channel, snowflake::Snowflake};
+use crate::external::fcm::PushPriority;
+
+/// Which messages of a channel wake the devices of the users who are not connected.
+/// Pushes about all other messages are still delivered, but silently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum NotificationLevel {
+    /// Every message wakes the device.
+    AllMessages = 0,
+    /// Only messages mentioning the user wake the device.
+    #[default]
+    OnlyMentions = 1,
+    /// No message wakes the device.
+    Nothing = 2,
+}
+
+impl From<i16> for NotificationLevel {
+    fn from(level: i16) -> Self {
+        match level {
+            0 => Self::AllMessages,
+            2 => Self::Nothing,
+            _ => Self::OnlyMentions,
+        }
+    }
+}
+
+impl NotificationLevel {
+    /// The priority of a push about a message under this level.
+    ///
+    /// ## Arguments
+    ///
+    /// * `mentioned` - Whether the message mentions the user the push is sent to.
+    pub const fn push_priority(self, mentioned: bool) -> PushPriority {
+        match (self, mentioned) {
+            (Self::AllMessages, _) | (Self::OnlyMentions, true) => PushPriority::High,
+            (Self::OnlyMentions | Self::Nothing, _) => PushPriority::Normal,
+        }
+    }
+}
+
+/// The notification level a user set for a channel, taking precedence over the default level of its guild.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotificationOverride {
+    /// The channel the level applies to.
+    pub channel_id: Snowflake<Channel>,
+    /// The level the user set for the channel.
+    pub level: NotificationLevel,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_priority() {
+        assert_eq!(NotificationLevel::AllMessages.push_priority(false), PushPriority::High);
+        assert_eq!(NotificationLevel::OnlyMentions.push_priority(true), PushPriority::High);
+        assert_eq!(
+            NotificationLevel::OnlyMentions.push_priority(false),
+            PushPriority::Normal
+        );
+        assert_eq!(NotificationLevel::Nothing.push_priority(true), PushPriority::Normal);
+    }
+
+    #[test]
+    fn test_level_from_column() {
+        assert_eq!(NotificationLevel::from(0), NotificationLevel::AllMessages);
+        assert_eq!(NotificationLevel::from(1), NotificationLevel::OnlyMentions);
+        assert_eq!(NotificationLevel::from(2), NotificationLevel::Nothing);
+        assert_eq!(
+            NotificationLevel::from(NotificationLevel::default() as i16),
+            NotificationLevel::OnlyMentions
+        );
+    }
+}
@@ -152,8 +152,7 @@ impl<T> OmittableOption<T> {
     pub fn expect(self, msg: &str) -> T {
         match self {
             Self::Some(value) => value,
-            Self::None => panic!("{}", msg),
-            Self::Omitted => panic!("{}", msg),
+            Self::None | Self::Omitted => panic!("{}", msg),
         }
     }
 
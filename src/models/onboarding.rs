@@ -0,0 +1,404 @@
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::app::Config;
+
+use super::{
+    channel::Channel, errors::BuildError, guild::Guild, request_payloads::UpdateOnboarding, role::Role,
+    snowflake::Snowflake, user::User,
+};
+
+/// The maximum amount of questions a guild's onboarding may ask.
+pub const MAX_ONBOARDING_QUESTIONS: usize = 10;
+/// The maximum amount of options a single onboarding question may offer.
+pub const MAX_ONBOARDING_OPTIONS: usize = 20;
+/// The maximum amount of channels new members follow by default.
+pub const MAX_DEFAULT_CHANNELS: usize = 25;
+
+/// A choice offered by an onboarding question.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OnboardingOption {
+    id: Snowflake<Self>,
+    label: String,
+    /// The roles assigned to members who pick this option.
+    role_ids: Vec<Snowflake<Role>>,
+}
+
+impl OnboardingOption {
+    pub const fn new(id: Snowflake<Self>, label: String, role_ids: Vec<Snowflake<Role>>) -> Self {
+        Self { id, label, role_ids }
+    }
+
+    /// The option's ID.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The text shown for the option.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// The roles assigned to members who pick this option.
+    pub fn role_ids(&self) -> &[Snowflake<Role>] {
+        &self.role_ids
+    }
+}
+
+/// A question asked to members joining a guild.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OnboardingQuestion {
+    id: Snowflake<Self>,
+    prompt: String,
+    options: Vec<OnboardingOption>,
+}
+
+impl OnboardingQuestion {
+    pub const fn new(id: Snowflake<Self>, prompt: String, options: Vec<OnboardingOption>) -> Self {
+        Self { id, prompt, options }
+    }
+
+    /// The question's ID.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The question asked.
+    pub fn prompt(&self) -> &str {
+        &self.prompt
+    }
+
+    /// The options members can pick from, in display order.
+    pub fn options(&self) -> &[OnboardingOption] {
+        &self.options
+    }
+}
+
+/// A guild's configuration for welcoming new members.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Onboarding {
+    guild_id: Snowflake<Guild>,
+    /// Channels new members follow when joining.
+    default_channel_ids: Vec<Snowflake<Channel>>,
+    /// The channel welcome messages are posted in.
+    system_channel_id: Option<Snowflake<Channel>>,
+    /// The welcome message posted when a member joins, `{user}` is replaced with a mention of the member.
+    welcome_message: Option<String>,
+    questions: Vec<OnboardingQuestion>,
+}
+
+impl Onboarding {
+    pub const fn new(
+        guild_id: Snowflake<Guild>,
+        default_channel_ids: Vec<Snowflake<Channel>>,
+        system_channel_id: Option<Snowflake<Channel>>,
+        welcome_message: Option<String>,
+        questions: Vec<OnboardingQuestion>,
+    ) -> Self {
+        Self {
+            guild_id,
+            default_channel_ids,
+            system_channel_id,
+            welcome_message,
+            questions,
+        }
+    }
+
+    /// The onboarding of a guild that did not configure it yet.
+    pub const fn empty(guild_id: Snowflake<Guild>) -> Self {
+        Self::new(guild_id, Vec::new(), None, None, Vec::new())
+    }
+
+    /// Build the onboarding described by an update payload, replacing the current one.
+    ///
+    /// Questions and options keep their ID if the payload refers to an existing one, otherwise they are assigned a new ID.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the payload exceeds any of the limits.
+    pub fn from_payload(config: &Config, current: &Self, payload: UpdateOnboarding) -> Result<Self, BuildError> {
+        if payload.questions.len() > MAX_ONBOARDING_QUESTIONS {
+            return Err(BuildError::ValidationError(format!(
+                "Onboarding may ask at most {MAX_ONBOARDING_QUESTIONS} questions"
+            )));
+        }
+        if let Some(message) = &payload.welcome_message
+            && !(1..=2000).contains(&message.len())
+        {
+            return Err(BuildError::ValidationError(
+                "Welcome message must be between 1 and 2000 characters long".into(),
+            ));
+        }
+
+        let default_channel_ids: Vec<_> = payload.default_channel_ids.into_iter().unique().collect();
+        if default_channel_ids.len() > MAX_DEFAULT_CHANNELS {
+            return Err(BuildError::ValidationError(format!(
+                "At most {MAX_DEFAULT_CHANNELS} default channels may be set"
+            )));
+        }
+
+        let question_ids: HashSet<_> = current.questions.iter().map(OnboardingQuestion::id).collect();
+        let option_ids: HashSet<_> = current.options().map(OnboardingOption::id).collect();
+
+        let questions = payload
+            .questions
+            .into_iter()
+            .map(|q| {
+                if !(1..=100).contains(&q.prompt.len()) {
+                    return Err(BuildError::ValidationError(
+                        "Question prompt must be between 1 and 100 characters long".into(),
+                    ));
+                }
+                if !(1..=MAX_ONBOARDING_OPTIONS).contains(&q.options.len()) {
+                    return Err(BuildError::ValidationError(format!(
+                        "Questions must offer between 1 and {MAX_ONBOARDING_OPTIONS} options"
+                    )));
+                }
+
+                let options = q
+                    .options
+                    .into_iter()
+                    .map(|o| {
+                        if !(1..=100).contains(&o.label.len()) {
+                            return Err(BuildError::ValidationError(
+                                "Option label must be between 1 and 100 characters long".into(),
+                            ));
+                        }
+                        let id =
+                            o.id.filter(|id| option_ids.contains(id))
+                                .unwrap_or_else(|| Snowflake::gen_new(config));
+                        Ok(OnboardingOption::new(
+                            id,
+                            o.label,
+                            o.role_ids.into_iter().unique().collect(),
+                        ))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let id =
+                    q.id.filter(|id| question_ids.contains(id))
+                        .unwrap_or_else(|| Snowflake::gen_new(config));
+                Ok(OnboardingQuestion::new(id, q.prompt, options))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let onboarding = Self::new(
+            current.guild_id,
+            default_channel_ids,
+            payload.system_channel_id,
+            payload.welcome_message,
+            questions,
+        );
+
+        if onboarding.options().map(OnboardingOption::id).unique().count() != onboarding.options().count()
+            || onboarding.questions.iter().map(OnboardingQuestion::id).unique().count() != onboarding.questions.len()
+        {
+            return Err(BuildError::ValidationError(
+                "Questions and options may not be listed more than once".into(),
+            ));
+        }
+
+        Ok(onboarding)
+    }
+
+    /// The ID of the guild this onboarding belongs to.
+    pub const fn guild_id(&self) -> Snowflake<Guild> {
+        self.guild_id
+    }
+
+    /// Channels new members follow when joining.
+    pub fn default_channel_ids(&self) -> &[Snowflake<Channel>] {
+        &self.default_channel_ids
+    }
+
+    /// The channel welcome messages are posted in.
+    pub const fn system_channel_id(&self) -> Option<Snowflake<Channel>> {
+        self.system_channel_id
+    }
+
+    /// The welcome message template.
+    pub fn welcome_message(&self) -> Option<&str> {
+        self.welcome_message.as_deref()
+    }
+
+    /// The questions asked to new members, in display order.
+    pub fn questions(&self) -> &[OnboardingQuestion] {
+        &self.questions
+    }
+
+    /// All options offered by the onboarding's questions.
+    pub fn options(&self) -> impl Iterator<Item = &OnboardingOption> {
+        self.questions.iter().flat_map(OnboardingQuestion::options)
+    }
+
+    /// The welcome message to post for the given member, if one should be posted.
+    pub fn render_welcome_message(&self, user: impl Into<Snowflake<User>>) -> Option<String> {
+        self.system_channel_id?;
+        let mention = format!("<@{}>", user.into());
+        self.welcome_message.as_ref().map(|m| m.replace("{user}", &mention))
+    }
+
+    /// Resolve picked options to the roles they assign.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If an option is not part of the onboarding.
+    pub fn resolve_answers(
+        &self,
+        option_ids: &[Snowflake<OnboardingOption>],
+    ) -> Result<Vec<Snowflake<Role>>, BuildError> {
+        let options: HashMap<_, _> = self.options().map(|o| (o.id(), o)).collect();
+
+        option_ids
+            .iter()
+            .map(|id| {
+                options
+                    .get(id)
+                    .map(|o| o.role_ids())
+                    .ok_or_else(|| BuildError::ValidationError(format!("Unknown onboarding option: {id}")))
+            })
+            .flatten_ok()
+            .map_ok(|id| *id)
+            .process_results(|roles| roles.sorted().dedup().collect())
+    }
+}
+
+/// The options a member picked while onboarding, and the roles they map to.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OnboardingResponses {
+    option_ids: Vec<Snowflake<OnboardingOption>>,
+    role_ids: Vec<Snowflake<Role>>,
+}
+
+impl OnboardingResponses {
+    pub const fn new(option_ids: Vec<Snowflake<OnboardingOption>>, role_ids: Vec<Snowflake<Role>>) -> Self {
+        Self { option_ids, role_ids }
+    }
+
+    /// The options the member picked.
+    pub fn option_ids(&self) -> &[Snowflake<OnboardingOption>] {
+        &self.option_ids
+    }
+
+    /// The roles the picked options map to.
+    pub fn role_ids(&self) -> &[Snowflake<Role>] {
+        &self.role_ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::*;
+    use crate::models::request_payloads::{OnboardingOptionPayload, OnboardingQuestionPayload};
+
+    fn config() -> Config {
+        Config::builder()
+            .database_url(Secret::new(String::new()))
+            .s3(None)
+            .listen_addr(([127, 0, 0, 1], 8080))
+            .machine_id(0)
+            .process_id(0)
+            .app_secret(Secret::new(String::new()))
+            .build()
+            .expect("config should be valid")
+    }
+
+    fn payload() -> UpdateOnboarding {
+        UpdateOnboarding {
+            default_channel_ids: vec![Snowflake::new(1), Snowflake::new(1), Snowflake::new(2)],
+            system_channel_id: Some(Snowflake::new(1)),
+            welcome_message: Some("Welcome {user}!".into()),
+            questions: vec![OnboardingQuestionPayload {
+                id: None,
+                prompt: "What brings you here?".into(),
+                options: vec![
+                    OnboardingOptionPayload {
+                        id: None,
+                        label: "Gaming".into(),
+                        role_ids: vec![Snowflake::new(10), Snowflake::new(11)],
+                    },
+                    OnboardingOptionPayload {
+                        id: None,
+                        label: "Music".into(),
+                        role_ids: vec![Snowflake::new(11)],
+                    },
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_from_payload() {
+        let config = config();
+        let empty = Onboarding::empty(Snowflake::new(5));
+        let onboarding = Onboarding::from_payload(&config, &empty, payload()).expect("payload should be valid");
+
+        assert_eq!(
+            onboarding.default_channel_ids(),
+            &[Snowflake::new(1), Snowflake::new(2)]
+        );
+        assert_eq!(onboarding.questions().len(), 1);
+        assert_eq!(onboarding.options().count(), 2);
+        assert_eq!(
+            onboarding.render_welcome_message(Snowflake::new(42)).as_deref(),
+            Some("Welcome <@42>!")
+        );
+
+        // Referencing existing questions and options keeps their IDs, unknown IDs are replaced
+        let mut update = payload();
+        update.questions[0].id = Some(onboarding.questions()[0].id());
+        update.questions[0].options[0].id = Some(onboarding.questions()[0].options()[0].id());
+        update.questions[0].options[1].id = Some(Snowflake::new(999));
+        let updated = Onboarding::from_payload(&config, &onboarding, update).expect("payload should be valid");
+        assert_eq!(updated.questions()[0].id(), onboarding.questions()[0].id());
+        assert_eq!(
+            updated.questions()[0].options()[0].id(),
+            onboarding.questions()[0].options()[0].id()
+        );
+        assert_ne!(updated.questions()[0].options()[1].id(), Snowflake::new(999));
+    }
+
+    #[test]
+    fn test_from_payload_invalid() {
+        let config = config();
+        let empty = Onboarding::empty(Snowflake::new(5));
+
+        let mut no_options = payload();
+        no_options.questions[0].options.clear();
+        assert!(Onboarding::from_payload(&config, &empty, no_options).is_err());
+
+        let mut empty_prompt = payload();
+        empty_prompt.questions[0].prompt = String::new();
+        assert!(Onboarding::from_payload(&config, &empty, empty_prompt).is_err());
+
+        let mut long_message = payload();
+        long_message.welcome_message = Some("a".repeat(2001));
+        assert!(Onboarding::from_payload(&config, &empty, long_message).is_err());
+    }
+
+    #[test]
+    fn test_resolve_answers() {
+        let config = config();
+        let onboarding = Onboarding::from_payload(&config, &Onboarding::empty(Snowflake::new(5)), payload())
+            .expect("payload should be valid");
+        let options: Vec<_> = onboarding.options().map(OnboardingOption::id).collect();
+
+        assert_eq!(
+            onboarding.resolve_answers(&options).expect("options should exist"),
+            vec![Snowflake::new(10), Snowflake::new(11)]
+        );
+        assert!(onboarding.resolve_answers(&[]).expect("no options").is_empty());
+        assert!(onboarding.resolve_answers(&[Snowflake::new(1)]).is_err());
+    }
+
+    #[test]
+    fn test_no_welcome_without_system_channel() {
+        let mut onboarding = Onboarding::empty(Snowflake::new(5));
+        onboarding.welcome_message = Some("Hi {user}".into());
+        assert!(onboarding.render_welcome_message(Snowflake::new(1)).is_none());
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    external::fcm::Notification,
+    gateway::{EventTrace, SendMode},
+};
+
+use super::{
+    channel::Channel,
+    gateway_event::{GatewayEvent, RelayedEvent},
+    guild::Guild,
+    message::Message,
+    snowflake::Snowflake,
+    user::User,
+};
+
+/// The number of times emitting an outbox entry may fail before it is dropped.
+pub const MAX_OUTBOX_ATTEMPTS: i32 = 5;
+
+/// A side effect of a change, written to the transactional outbox in the same transaction as the change itself.
+///
+/// Entries are emitted by the outbox relay once the transaction committed, and are only removed once emitted,
+/// so they are emitted at least once even if the application stops in between.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutboxEntry {
+    /// Dispatch an event through the gateway.
+    Dispatch {
+        event: RelayedEvent,
+        send_mode: SendMode,
+        /// The trace of the event, captured when the entry was written.
+        #[serde(default)]
+        trace: Option<EventTrace>,
+    },
+    /// Send the unread badge of the users a new message mentions to their sessions.
+    Badge { message_id: Snowflake<Message> },
+    /// Send a push notification to the users who can view the channel, but are not connected.
+    Push {
+        /// The guild the channel is in, or `None` for direct message channels.
+        #[serde(default)]
+        guild_id: Option<Snowflake<Guild>>,
+        channel_id: Snowflake<Channel>,
+        notification: Notification,
+        /// The content of the message the notification is about, members who muted a word in it are skipped.
+        #[serde(default)]
+        content: Option<String>,
+        /// The message the notification is about, included in the notification's data.
+        #[serde(default)]
+        message: Option<PushedMessage>,
+    },
+}
+
+/// The message a push notification is about, letting clients link to it and render the notification
+/// without fetching it first.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PushedMessage {
+    pub message_id: Snowflake<Message>,
+    pub channel_name: String,
+    /// The name shown for the author, see [`UserLike::shown_name`](super::member::UserLike::shown_name).
+    pub author_name: String,
+    /// The users the message mentions, whose devices are woken by pushes about it depending on their notification level.
+    #[serde(default)]
+    pub mentions: Vec<Snowflake<User>>,
+}
+
+impl PushedMessage {
+    /// Add the details of the message to the data payload of a push notification.
+    ///
+    /// ## Arguments
+    ///
+    /// * `data` - The data payload to add the details to.
+    pub fn extend_data(&self, data: &mut HashMap<String, String>) {
+        data.extend([
+            ("message_id".to_string(), self.message_id.to_string()),
+            ("channel_name".to_string(), self.channel_name.clone()),
+            ("author_name".to_string(), self.author_name.clone()),
+        ]);
+    }
+}
+
+impl OutboxEntry {
+    /// Create an entry dispatching an event through the gateway, traced from the current span.
+    ///
+    /// ## Arguments
+    ///
+    /// * `event` - The event to dispatch.
+    /// * `send_mode` - Who to dispatch the event to.
+    pub fn dispatch(event: &GatewayEvent, send_mode: SendMode) -> Self {
+        Self::Dispatch {
+            event: RelayedEvent::new(event),
+            send_mode,
+            trace: Some(EventTrace::current()),
+        }
+    }
+
+    /// Create an entry sending the unread badge of the users a new message mentions to their sessions.
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The message mentioning the users.
+    pub fn badge(message: impl Into<Snowflake<Message>>) -> Self {
+        Self::Badge {
+            message_id: message.into(),
+        }
+    }
+
+    /// Create an entry sending a push notification to inactive members.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the channel is in, or `None` for direct message channels.
+    /// * `channel` - The channel the notification originated from.
+    /// * `notification` - The notification to send.
+    /// * `content` - The content of the message the notification is about, if any.
+    /// * `message` - The message the notification is about, if any.
+    pub fn push(
+        guild: Option<Snowflake<Guild>>,
+        channel: impl Into<Snowflake<Channel>>,
+        notification: Notification,
+        content: Option<String>,
+        message: Option<PushedMessage>,
+    ) -> Self {
+        Self::Push {
+            guild_id: guild,
+            channel_id: channel.into(),
+            notification,
+            content,
+            message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relayed_event() {
+        let event = GatewayEvent::MessageRemove {
+            id: Snowflake::new(3),
+            channel_id: Snowflake::new(2),
+            guild_id: Some(Snowflake::new(1)),
+        };
+        let entry = OutboxEntry::dispatch(&event, SendMode::ToGuild(Snowflake::new(1)));
+
+        let stored = serde_json::to_string(&entry).expect("entry should serialize");
+        let OutboxEntry::Dispatch {
+            event: relayed,
+            send_mode,
+            trace,
+        } = serde_json::from_str(&stored).expect("entry should deserialize")
+        else {
+            panic!("Expected a dispatch entry");
+        };
+
+        assert!(matches!(send_mode, SendMode::ToGuild(guild) if guild == Snowflake::new(1)));
+        // The event keeps the trace it was given when the entry was written
+        assert!(matches!(entry, OutboxEntry::Dispatch { trace: written, .. } if written == trace));
+        assert!(trace.is_some());
+
+        // Clients receive the relayed event exactly as they would have received the original
+        let relayed = GatewayEvent::Relayed(relayed);
+        assert_eq!(relayed.channel_id(), Some(Snowflake::new(2)));
+        assert_eq!(
+            serde_json::to_value(&relayed).expect("event should serialize"),
+            serde_json::to_value(&event).expect("event should serialize"),
+        );
+    }
+
+    #[test]
+    fn test_push_entry() {
+        // Entries written before pushes included the message are still emitted
+        let stored = r#"{"type":"push","guild_id":"1","channel_id":"2","notification":{"title":"a","body":"b"}}"#;
+        let OutboxEntry::Push { message, content, .. } =
+            serde_json::from_str(stored).expect("entry should deserialize")
+        else {
+            panic!("Expected a push entry");
+        };
+        assert!(message.is_none());
+        assert!(content.is_none());
+
+        let pushed = PushedMessage {
+            message_id: Snowflake::new(3),
+            channel_name: "general".into(),
+            author_name: "Ferris".into(),
+            mentions: vec![Snowflake::new(4)],
+        };
+        let mut data = HashMap::from([("type".to_string(), "notification".to_string())]);
+        pushed.extend_data(&mut data);
+        assert_eq!(data["message_id"], "3");
+        assert_eq!(data["channel_name"], "general");
+        assert_eq!(data["author_name"], "Ferris");
+        assert_eq!(data.len(), 4);
+
+        // Messages stored before pushes had priorities mention nobody
+        let stored = r#"{"message_id":"3","channel_name":"general","author_name":"Ferris"}"#;
+        let pushed: PushedMessage = serde_json::from_str(stored).expect("message should deserialize");
+        assert!(pushed.mentions.is_empty());
+    }
+}
@@ -1,9 +1,56 @@
+use std::sync::Arc;
+
 use bitflags::bitflags;
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
 use crate::app::App;
 
-use super::{request_payloads::UpdatePrefs, snowflake::Snowflake, user::User};
+use super::{
+    errors::BuildError, keyword_alert::KeywordMatcher, request_payloads::UpdatePrefs, snowflake::Snowflake, user::User,
+};
+
+/// The maximum number of words and phrases a user may mute.
+pub const MAX_MUTED_WORDS: usize = 100;
+/// The maximum length of a single muted word or phrase, in characters.
+pub const MAX_MUTED_WORD_LENGTH: usize = 64;
+
+/// Normalize and validate a user's muted words.
+///
+/// Muted words are matched case-insensitively, so they are trimmed, lowercased and deduplicated.
+///
+/// ## Errors
+///
+/// * [`BuildError::ValidationError`] - If there are too many words, or a word is empty or too long.
+///
+/// ## Returns
+///
+/// The normalized words, sorted alphabetically.
+pub fn normalize_muted_words(words: Vec<String>) -> Result<Vec<String>, BuildError> {
+    let words: Vec<String> = words
+        .into_iter()
+        .map(|w| w.trim().to_lowercase())
+        .sorted()
+        .dedup()
+        .collect();
+
+    if words.len() > MAX_MUTED_WORDS {
+        return Err(BuildError::ValidationError(format!(
+            "A user may mute at most {MAX_MUTED_WORDS} words"
+        )));
+    }
+
+    if words
+        .iter()
+        .any(|w| w.is_empty() || w.chars().count() > MAX_MUTED_WORD_LENGTH)
+    {
+        return Err(BuildError::ValidationError(format!(
+            "Muted words must be between 1 and {MAX_MUTED_WORD_LENGTH} characters long"
+        )));
+    }
+
+    Ok(words)
+}
 
 bitflags! {
     /// Boolean flags for user preferences
@@ -64,6 +111,30 @@ impl<'de> Deserialize<'de> for Layout {
     }
 }
 
+/// Who a user's presence is shared with, besides the user themselves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum PresenceSharing {
+    /// Members of mutual guilds see the user's presence.
+    #[default]
+    Everyone = 0,
+    /// Only friends see the user's presence.
+    Friends = 1,
+    /// The user always appears offline to others.
+    Nobody = 2,
+}
+
+impl From<i16> for PresenceSharing {
+    fn from(sharing: i16) -> Self {
+        match sharing {
+            0 => Self::Everyone,
+            1 => Self::Friends,
+            _ => Self::Nobody,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Prefs {
     #[serde(skip)]
@@ -78,6 +149,10 @@ pub struct Prefs {
     pub text_size: u8,
     /// The date format for chat messages.
     pub locale: String,
+    /// Words and phrases the user is not notified about, lowercased and sorted alphabetically.
+    pub muted_words: Vec<String>,
+    /// Who the user's presence is shared with.
+    pub presence_sharing: PresenceSharing,
 }
 
 impl Prefs {
@@ -89,6 +164,8 @@ impl Prefs {
             layout: Layout::Normal,
             text_size: 12,
             locale: String::from("en_US"),
+            muted_words: Vec::new(),
+            presence_sharing: PresenceSharing::default(),
         }
     }
 
@@ -97,8 +174,17 @@ impl Prefs {
         self.user_id
     }
 
+    /// Compile the user's muted words into a matcher, or `None` if they did not mute any.
+    pub fn muted_word_matcher(&self) -> Option<Arc<KeywordMatcher>> {
+        muted_word_matcher(self.muted_words.clone())
+    }
+
     /// Apply a set of updates to the preferences.
-    pub fn update(&mut self, update: UpdatePrefs) {
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the muted words are invalid, see [`normalize_muted_words`].
+    pub fn update(&mut self, update: UpdatePrefs) -> Result<(), BuildError> {
         if let Some(flags) = update.flags {
             self.flags = flags;
         }
@@ -114,6 +200,13 @@ impl Prefs {
         if let Some(locale) = update.locale {
             self.locale = locale;
         }
+        if let Some(muted_words) = update.muted_words {
+            self.muted_words = normalize_muted_words(muted_words)?;
+        }
+        if let Some(presence_sharing) = update.presence_sharing {
+            self.presence_sharing = presence_sharing;
+        }
+        Ok(())
     }
 
     /// Fetch the preferences for a user.
@@ -134,7 +227,7 @@ impl Prefs {
         let user_id_i64: i64 = user_id.into();
 
         let result = sqlx::query!(
-            "SELECT user_id, flags, message_grouping_timeout, layout, text_size, locale
+            "SELECT user_id, flags, message_grouping_timeout, layout, text_size, locale, muted_words, presence_sharing
             FROM prefs
             WHERE user_id = $1",
             user_id_i64
@@ -154,6 +247,8 @@ impl Prefs {
             layout: Layout::from(result.layout as u8),
             text_size: result.text_size as u8,
             locale: result.locale,
+            muted_words: result.muted_words,
+            presence_sharing: PresenceSharing::from(result.presence_sharing),
         })
     }
 
@@ -171,16 +266,20 @@ impl Prefs {
         let flags: i64 = self.flags.bits().try_into().expect("Cannot fit flag into i64");
 
         sqlx::query!(
-            "INSERT INTO prefs (user_id, flags, message_grouping_timeout, layout, text_size, locale)
-            VALUES ($1, $2, $3, $4, $5, $6)
+            "INSERT INTO prefs (user_id, flags, message_grouping_timeout, layout, text_size, locale, muted_words,
+                presence_sharing)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
             ON CONFLICT (user_id)
-            DO UPDATE SET flags = $2, message_grouping_timeout = $3, layout = $4, text_size = $5, locale = $6",
+            DO UPDATE SET flags = $2, message_grouping_timeout = $3, layout = $4, text_size = $5, locale = $6,
+            muted_words = $7, presence_sharing = $8",
             user_id,
             flags,
             self.message_grouping_timeout as i32,
             self.layout as i32,
             i16::from(self.text_size),
             self.locale,
+            &self.muted_words,
+            self.presence_sharing as i16,
         )
         .execute(app.db())
         .await?;
@@ -188,3 +287,64 @@ impl Prefs {
         Ok(())
     }
 }
+
+/// Compile muted words into a matcher, or `None` if there are none.
+///
+/// ## Arguments
+///
+/// * `words` - The normalized muted words of a user
+pub fn muted_word_matcher(words: Vec<String>) -> Option<Arc<KeywordMatcher>> {
+    if words.is_empty() {
+        return None;
+    }
+
+    KeywordMatcher::new(words)
+        .inspect_err(|e| tracing::error!(error = %e, "Failed to compile muted words"))
+        .ok()
+        .map(Arc::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_muted_words() {
+        let words = normalize_muted_words(vec![" Spoilers ".into(), "the ending".into(), "SPOILERS".into()])
+            .expect("words should be valid");
+        assert_eq!(words, vec!["spoilers", "the ending"]);
+
+        assert!(normalize_muted_words(vec![String::new()]).is_err());
+        assert!(normalize_muted_words(vec!["a".repeat(MAX_MUTED_WORD_LENGTH + 1)]).is_err());
+        assert!(normalize_muted_words((0..=MAX_MUTED_WORDS).map(|i| i.to_string()).collect()).is_err());
+    }
+
+    #[test]
+    fn test_update_muted_words() {
+        let mut prefs = Prefs::new(Snowflake::new(1));
+        assert!(prefs.muted_word_matcher().is_none());
+
+        prefs
+            .update(UpdatePrefs {
+                muted_words: Some(vec!["Spoilers".into()]),
+                ..Default::default()
+            })
+            .expect("update should be valid");
+        assert_eq!(prefs.muted_words, vec!["spoilers"]);
+        assert!(
+            prefs
+                .muted_word_matcher()
+                .is_some_and(|m| m.is_match("No SPOILERS please"))
+        );
+
+        assert!(
+            prefs
+                .update(UpdatePrefs {
+                    muted_words: Some(vec!["  ".into()]),
+                    ..Default::default()
+                })
+                .is_err()
+        );
+        assert_eq!(prefs.muted_words, vec!["spoilers"]);
+    }
+}
@@ -0,0 +1,128 @@
+use chrono::{DateTime, SubsecRound, Utc};
+use rand::{Rng, distributions::Alphanumeric};
+use serde::Serialize;
+
+use super::{snowflake::Snowflake, user::User};
+
+/// The length of generated registration codes.
+pub const REGISTRATION_CODE_LENGTH: usize = 16;
+
+/// Represents a registration code stored in the database.
+pub struct RegistrationCodeRecord {
+    pub code: String,
+    pub creator_id: Option<i64>,
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A code handed out by an administrator, letting users register an account on closed instances.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RegistrationCode {
+    /// The code users register with.
+    code: String,
+    /// The administrator who created the code, if they still exist.
+    creator_id: Option<Snowflake<User>>,
+    /// The number of accounts that may be registered with the code, unlimited if `None`.
+    max_uses: Option<u32>,
+    /// The number of accounts registered with the code so far.
+    uses: u32,
+    /// When the code was created.
+    created_at: DateTime<Utc>,
+}
+
+impl RegistrationCode {
+    /// Create a new registration code with a randomly generated code.
+    ///
+    /// ## Arguments
+    ///
+    /// * `creator` - The administrator creating the code.
+    /// * `max_uses` - The number of accounts that may be registered with the code, unlimited if `None`.
+    pub fn new(creator: impl Into<Snowflake<User>>, max_uses: Option<u32>) -> Self {
+        let code = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(REGISTRATION_CODE_LENGTH)
+            .map(char::from)
+            .collect();
+
+        Self {
+            code,
+            creator_id: Some(creator.into()),
+            max_uses,
+            uses: 0,
+            created_at: Utc::now().trunc_subsecs(6),
+        }
+    }
+
+    /// Build a registration code from a database record.
+    pub fn from_record(record: RegistrationCodeRecord) -> Self {
+        Self {
+            code: record.code,
+            creator_id: record.creator_id.map(Into::into),
+            max_uses: record.max_uses.map(|m| m.try_into().unwrap_or_default()),
+            uses: record.uses.try_into().unwrap_or_default(),
+            created_at: record.created_at,
+        }
+    }
+
+    /// The code users register with.
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// The administrator who created the code, if they still exist.
+    pub const fn creator_id(&self) -> Option<Snowflake<User>> {
+        self.creator_id
+    }
+
+    /// The number of accounts that may be registered with the code, unlimited if `None`.
+    pub const fn max_uses(&self) -> Option<u32> {
+        self.max_uses
+    }
+
+    /// The number of accounts registered with the code so far.
+    pub const fn uses(&self) -> u32 {
+        self.uses
+    }
+
+    /// When the code was created.
+    pub const fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    /// Whether no further accounts may be registered with the code.
+    pub fn is_exhausted(&self) -> bool {
+        self.max_uses.is_some_and(|max| self.uses >= max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_generates_code() {
+        let code = RegistrationCode::new(Snowflake::new(1), Some(2));
+        let other = RegistrationCode::new(Snowflake::new(1), None);
+
+        assert_eq!(code.code().len(), REGISTRATION_CODE_LENGTH);
+        assert!(code.code().chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(code.code(), other.code());
+        assert_eq!(code.uses(), 0);
+    }
+
+    #[test]
+    fn test_is_exhausted() {
+        let record = |max_uses, uses| RegistrationCodeRecord {
+            code: "code".into(),
+            creator_id: None,
+            max_uses,
+            uses,
+            created_at: DateTime::UNIX_EPOCH,
+        };
+
+        assert!(!RegistrationCode::from_record(record(Some(2), 1)).is_exhausted());
+        assert!(RegistrationCode::from_record(record(Some(2), 2)).is_exhausted());
+        assert!(!RegistrationCode::from_record(record(None, 1000)).is_exhausted());
+    }
+}
@@ -0,0 +1,183 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    errors::BuildError,
+    snowflake::Snowflake,
+    user::{User, UserRecord},
+};
+
+/// The relationship between a user and another user, from the perspective of the former.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum RelationshipType {
+    /// The users are friends.
+    Friend = 1,
+    /// The other user sent a friend request that was not answered yet.
+    IncomingRequest = 2,
+    /// The user sent a friend request to the other user that was not answered yet.
+    OutgoingRequest = 3,
+}
+
+impl RelationshipType {
+    /// The same relationship, from the perspective of the other user.
+    #[must_use]
+    pub const fn counterpart(self) -> Self {
+        match self {
+            Self::Friend => Self::Friend,
+            Self::IncomingRequest => Self::OutgoingRequest,
+            Self::OutgoingRequest => Self::IncomingRequest,
+        }
+    }
+}
+
+impl TryFrom<i16> for RelationshipType {
+    type Error = BuildError;
+
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Friend),
+            2 => Ok(Self::IncomingRequest),
+            3 => Ok(Self::OutgoingRequest),
+            _ => Err(BuildError::ValidationError(format!(
+                "Unknown relationship type: {value}"
+            ))),
+        }
+    }
+}
+
+/// Represents a relationship record stored in the database, joined with the other user.
+pub struct ExtendedRelationshipRecord {
+    pub other_id: Snowflake<User>,
+    pub relationship_type: i16,
+    pub created_at: DateTime<Utc>,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_hash: Option<String>,
+    pub banner_hash: Option<String>,
+    pub last_presence: i16,
+}
+
+/// A user's relationship with another user.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Relationship {
+    /// The other user.
+    user: User,
+    /// The kind of relationship.
+    #[serde(rename = "type")]
+    kind: RelationshipType,
+    /// When the relationship was last changed.
+    since: DateTime<Utc>,
+}
+
+impl Relationship {
+    /// Create a new relationship with the given user.
+    pub const fn new(user: User, kind: RelationshipType, since: DateTime<Utc>) -> Self {
+        Self { user, kind, since }
+    }
+
+    /// Build a relationship from a database record.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the record has an unknown relationship type.
+    pub fn from_record(record: ExtendedRelationshipRecord) -> Result<Self, BuildError> {
+        let user = User::from_record(UserRecord {
+            id: record.other_id,
+            username: record.username,
+            display_name: record.display_name,
+            avatar_hash: record.avatar_hash,
+            banner_hash: record.banner_hash,
+            last_presence: record.last_presence,
+        });
+        Ok(Self::new(
+            user,
+            RelationshipType::try_from(record.relationship_type)?,
+            record.created_at,
+        ))
+    }
+
+    /// The other user.
+    pub const fn user(&self) -> &User {
+        &self.user
+    }
+
+    /// The kind of relationship.
+    pub const fn kind(&self) -> RelationshipType {
+        self.kind
+    }
+
+    /// When the relationship was last changed.
+    pub const fn since(&self) -> DateTime<Utc> {
+        self.since
+    }
+}
+
+/// Who users may open direct messages with, configured per instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DmPolicy {
+    /// Users may open direct messages with anyone.
+    Anyone,
+    /// Users may open direct messages with their friends and the users they share a guild with.
+    #[default]
+    FriendsOrMutualGuild,
+    /// Users may only open direct messages with their friends.
+    Friends,
+}
+
+impl FromStr for DmPolicy {
+    type Err = BuildError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "anyone" => Ok(Self::Anyone),
+            "friends_or_mutual_guild" => Ok(Self::FriendsOrMutualGuild),
+            "friends" => Ok(Self::Friends),
+            _ => Err(BuildError::ValidationError(format!("Unknown DM policy: {s}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counterpart() {
+        assert_eq!(RelationshipType::Friend.counterpart(), RelationshipType::Friend);
+        assert_eq!(
+            RelationshipType::IncomingRequest.counterpart(),
+            RelationshipType::OutgoingRequest
+        );
+        assert_eq!(
+            RelationshipType::OutgoingRequest.counterpart(),
+            RelationshipType::IncomingRequest
+        );
+    }
+
+    #[test]
+    fn test_relationship_type_from_i16() {
+        for kind in [
+            RelationshipType::Friend,
+            RelationshipType::IncomingRequest,
+            RelationshipType::OutgoingRequest,
+        ] {
+            assert_eq!(RelationshipType::try_from(kind as i16).ok(), Some(kind));
+        }
+        assert!(RelationshipType::try_from(0).is_err());
+    }
+
+    #[test]
+    fn test_dm_policy_from_str() {
+        assert_eq!("anyone".parse::<DmPolicy>().ok(), Some(DmPolicy::Anyone));
+        assert_eq!("friends".parse::<DmPolicy>().ok(), Some(DmPolicy::Friends));
+        assert_eq!(
+            "friends_or_mutual_guild".parse::<DmPolicy>().ok(),
+            Some(DmPolicy::FriendsOrMutualGuild)
+        );
+        assert!("nobody".parse::<DmPolicy>().is_err());
+    }
+}
@@ -0,0 +1,391 @@
+use chrono::{DateTime, SubsecRound, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{errors::BuildError, guild::Guild, snowflake::Snowflake, user::User};
+use crate::app::Config;
+
+/// The maximum length of the details a reporter may add to a report.
+pub const MAX_REPORT_DETAILS_LENGTH: usize = 1000;
+
+/// The kind of object a report is about.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum ReportTargetType {
+    Message = 1,
+    User = 2,
+    Guild = 3,
+}
+
+impl TryFrom<i16> for ReportTargetType {
+    type Error = BuildError;
+
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Message),
+            2 => Ok(Self::User),
+            3 => Ok(Self::Guild),
+            _ => Err(BuildError::ValidationError(format!(
+                "Unknown report target type: {value}"
+            ))),
+        }
+    }
+}
+
+/// Why an object was reported.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum ReportCategory {
+    Spam = 1,
+    Harassment = 2,
+    HateSpeech = 3,
+    Violence = 4,
+    SexualContent = 5,
+    SelfHarm = 6,
+    Impersonation = 7,
+    Other = 8,
+}
+
+impl TryFrom<i16> for ReportCategory {
+    type Error = BuildError;
+
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Spam),
+            2 => Ok(Self::Harassment),
+            3 => Ok(Self::HateSpeech),
+            4 => Ok(Self::Violence),
+            5 => Ok(Self::SexualContent),
+            6 => Ok(Self::SelfHarm),
+            7 => Ok(Self::Impersonation),
+            8 => Ok(Self::Other),
+            _ => Err(BuildError::ValidationError(format!("Unknown report category: {value}"))),
+        }
+    }
+}
+
+/// Where a report is in the triage of the instance's administrators.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum ReportStatus {
+    /// The report waits for an administrator to pick it up.
+    Open = 1,
+    /// An administrator is looking into the report.
+    Claimed = 2,
+    /// The report was dealt with.
+    Resolved = 3,
+}
+
+impl TryFrom<i16> for ReportStatus {
+    type Error = BuildError;
+
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Open),
+            2 => Ok(Self::Claimed),
+            3 => Ok(Self::Resolved),
+            _ => Err(BuildError::ValidationError(format!("Unknown report status: {value}"))),
+        }
+    }
+}
+
+/// What was done about a resolved report.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum ReportAction {
+    /// The report was reviewed, but nothing had to be done.
+    None = 1,
+    /// The reported content was removed.
+    ContentRemoved = 2,
+    /// The responsible user was warned.
+    UserWarned = 3,
+    /// The responsible user was removed from the guild.
+    MemberRemoved = 4,
+    /// The responsible user's account was terminated.
+    AccountTerminated = 5,
+}
+
+impl TryFrom<i16> for ReportAction {
+    type Error = BuildError;
+
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::None),
+            2 => Ok(Self::ContentRemoved),
+            3 => Ok(Self::UserWarned),
+            4 => Ok(Self::MemberRemoved),
+            5 => Ok(Self::AccountTerminated),
+            _ => Err(BuildError::ValidationError(format!("Unknown report action: {value}"))),
+        }
+    }
+}
+
+/// Represents a report stored in the database.
+pub struct ReportRecord {
+    pub id: i64,
+    pub reporter_id: Option<i64>,
+    pub target_type: i16,
+    pub target_id: i64,
+    pub guild_id: Option<i64>,
+    pub category: i16,
+    pub details: Option<String>,
+    pub forwarded: bool,
+    pub status: i16,
+    pub claimed_by: Option<i64>,
+    pub action_taken: Option<i16>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+/// A report of a message, user or guild breaking the rules, triaged by the instance's administrators.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    id: Snowflake<Self>,
+    /// The user who filed the report, if they still exist. Hidden from guild moderators.
+    reporter_id: Option<Snowflake<User>>,
+    target_type: ReportTargetType,
+    target_id: Snowflake<()>,
+    /// The guild the reported object belongs to, if any.
+    guild_id: Option<Snowflake<Guild>>,
+    category: ReportCategory,
+    details: Option<String>,
+    /// Whether the report was forwarded to the moderators of its guild.
+    forwarded: bool,
+    status: ReportStatus,
+    /// The administrator who claimed or resolved the report.
+    claimed_by: Option<Snowflake<User>>,
+    action_taken: Option<ReportAction>,
+    /// When the report was resolved.
+    resolved_at: Option<DateTime<Utc>>,
+}
+
+impl Report {
+    /// Create a new, open report with a freshly generated ID.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration, used to generate the ID.
+    /// * `reporter` - The user filing the report.
+    /// * `target_type` - The kind of object reported.
+    /// * `target_id` - The ID of the object reported.
+    /// * `category` - Why the object was reported.
+    pub fn new(
+        config: &Config,
+        reporter: impl Into<Snowflake<User>>,
+        target_type: ReportTargetType,
+        target_id: impl Into<Snowflake<()>>,
+        category: ReportCategory,
+    ) -> Self {
+        Self {
+            id: Snowflake::gen_new(config),
+            reporter_id: Some(reporter.into()),
+            target_type,
+            target_id: target_id.into(),
+            guild_id: None,
+            category,
+            details: None,
+            forwarded: false,
+            status: ReportStatus::Open,
+            claimed_by: None,
+            action_taken: None,
+            resolved_at: None,
+        }
+    }
+
+    /// Attach the reporter's explanation to the report.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the details are too long.
+    pub fn with_details(mut self, details: Option<String>) -> Result<Self, BuildError> {
+        let details = details.map(|d| d.trim().to_owned()).filter(|d| !d.is_empty());
+        if details
+            .as_ref()
+            .is_some_and(|d| d.chars().count() > MAX_REPORT_DETAILS_LENGTH)
+        {
+            return Err(BuildError::ValidationError(format!(
+                "Report details must be at most {MAX_REPORT_DETAILS_LENGTH} characters long"
+            )));
+        }
+        self.details = details;
+        Ok(self)
+    }
+
+    /// Scope the report to the guild the reported object belongs to.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild of the reported object.
+    /// * `forward` - Whether to forward the report to the guild's moderators.
+    #[must_use]
+    pub fn in_guild(mut self, guild: impl Into<Snowflake<Guild>>, forward: bool) -> Self {
+        self.guild_id = Some(guild.into());
+        self.forwarded = forward;
+        self
+    }
+
+    /// Build a report from a database record.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the record contains an unknown enum value.
+    pub fn from_record(record: ReportRecord) -> Result<Self, BuildError> {
+        Ok(Self {
+            id: record.id.into(),
+            reporter_id: record.reporter_id.map(Into::into),
+            target_type: record.target_type.try_into()?,
+            target_id: record.target_id.into(),
+            guild_id: record.guild_id.map(Into::into),
+            category: record.category.try_into()?,
+            details: record.details,
+            forwarded: record.forwarded,
+            status: record.status.try_into()?,
+            claimed_by: record.claimed_by.map(Into::into),
+            action_taken: record.action_taken.map(TryInto::try_into).transpose()?,
+            resolved_at: record.resolved_at,
+        })
+    }
+
+    /// The report as shown to the moderators of its guild, without the reporter's identity.
+    #[must_use]
+    pub fn anonymized(self) -> Self {
+        Self {
+            reporter_id: None,
+            claimed_by: None,
+            ..self
+        }
+    }
+
+    /// Mark the report as resolved.
+    ///
+    /// ## Arguments
+    ///
+    /// * `admin` - The administrator resolving the report.
+    /// * `action` - What was done about the report.
+    pub fn resolve(&mut self, admin: impl Into<Snowflake<User>>, action: ReportAction) {
+        self.status = ReportStatus::Resolved;
+        self.claimed_by = Some(admin.into());
+        self.action_taken = Some(action);
+        // Truncated to the precision it is stored with, so that it matches the report when fetched again
+        self.resolved_at = Some(Utc::now().trunc_subsecs(6));
+    }
+
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The user who filed the report, if they still exist.
+    pub const fn reporter_id(&self) -> Option<Snowflake<User>> {
+        self.reporter_id
+    }
+
+    pub const fn target_type(&self) -> ReportTargetType {
+        self.target_type
+    }
+
+    pub const fn target_id(&self) -> Snowflake<()> {
+        self.target_id
+    }
+
+    /// The guild the reported object belongs to, if any.
+    pub const fn guild_id(&self) -> Option<Snowflake<Guild>> {
+        self.guild_id
+    }
+
+    pub const fn category(&self) -> ReportCategory {
+        self.category
+    }
+
+    pub fn details(&self) -> Option<&str> {
+        self.details.as_deref()
+    }
+
+    /// Whether the report was forwarded to the moderators of its guild.
+    pub const fn is_forwarded(&self) -> bool {
+        self.forwarded
+    }
+
+    pub const fn status(&self) -> ReportStatus {
+        self.status
+    }
+
+    /// The administrator who claimed or resolved the report.
+    pub const fn claimed_by(&self) -> Option<Snowflake<User>> {
+        self.claimed_by
+    }
+
+    pub const fn action_taken(&self) -> Option<ReportAction> {
+        self.action_taken
+    }
+
+    /// When the report was resolved.
+    pub const fn resolved_at(&self) -> Option<DateTime<Utc>> {
+        self.resolved_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(status: i16) -> ReportRecord {
+        ReportRecord {
+            id: 1,
+            reporter_id: Some(2),
+            target_type: ReportTargetType::Message as i16,
+            target_id: 3,
+            guild_id: Some(4),
+            category: ReportCategory::Spam as i16,
+            details: None,
+            forwarded: true,
+            status,
+            claimed_by: Some(5),
+            action_taken: None,
+            resolved_at: None,
+        }
+    }
+
+    #[test]
+    fn test_from_record() {
+        let report = Report::from_record(record(ReportStatus::Claimed as i16)).expect("record should be valid");
+        assert_eq!(report.target_type(), ReportTargetType::Message);
+        assert_eq!(report.status(), ReportStatus::Claimed);
+        assert_eq!(report.claimed_by(), Some(Snowflake::new(5)));
+
+        assert!(Report::from_record(record(0)).is_err());
+    }
+
+    #[test]
+    fn test_anonymized() {
+        let report = Report::from_record(record(ReportStatus::Open as i16))
+            .expect("record should be valid")
+            .anonymized();
+        assert_eq!(report.reporter_id(), None);
+        assert_eq!(report.claimed_by(), None);
+        assert_eq!(report.guild_id(), Some(Snowflake::new(4)));
+    }
+
+    #[test]
+    fn test_details_length() {
+        let record = || Report::from_record(record(ReportStatus::Open as i16)).expect("record should be valid");
+
+        let report = record()
+            .with_details(Some("  spam bot  ".into()))
+            .expect("details are short");
+        assert_eq!(report.details(), Some("spam bot"));
+        assert_eq!(
+            record()
+                .with_details(Some("   ".into()))
+                .ok()
+                .and_then(|r| r.details().map(str::to_owned)),
+            None
+        );
+        assert!(
+            record()
+                .with_details(Some("a".repeat(MAX_REPORT_DETAILS_LENGTH + 1)))
+                .is_err()
+        );
+    }
+}
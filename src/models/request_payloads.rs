@@ -1,17 +1,26 @@
+use std::num::NonZeroU32;
+
+use chrono::{DateTime, Utc};
 use secrecy::Secret;
 use serde::Deserialize;
 
 use crate::app::ApplicationState;
 
 use super::{
+    automod::{AutomodAction, AutomodTrigger},
     channel::Channel,
     data_uri::DataUri,
-    errors::{AppError, RESTError},
+    errors::OpsError,
     guild::Guild,
+    guild_event::RsvpStatus,
     member::Member,
     message::Message,
+    notification_level::NotificationLevel,
     omittableoption::OmittableOption,
-    prefs::{Layout, PrefFlags},
+    onboarding::{OnboardingOption, OnboardingQuestion},
+    prefs::{Layout, PrefFlags, PresenceSharing},
+    report::{ReportAction, ReportCategory, ReportTargetType},
+    role::{Permissions, Role},
     snowflake::Snowflake,
     user::User,
 };
@@ -21,6 +30,18 @@ use super::{
 pub struct CreateUser {
     pub username: String,
     pub password: Secret<String>,
+    /// The registration code handed out by an administrator, required if the instance is closed
+    #[serde(default)]
+    pub registration_code: Option<String>,
+}
+
+/// A request to log in with a token issued by an external authentication provider
+#[derive(Deserialize, Debug, Clone)]
+pub struct ExternalLogin {
+    pub token: Secret<String>,
+    /// The registration code to create the account with on the first login, required if the instance is closed
+    #[serde(default)]
+    pub registration_code: Option<String>,
 }
 
 /// The JSON part of a multipart form request to create a message
@@ -30,6 +51,32 @@ pub struct CreateMessage {
     pub nonce: Option<String>,
 }
 
+/// A request to replace the keywords a guild watches for in new messages
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateModerationKeywords {
+    pub keywords: Vec<String>,
+}
+
+/// A request to start a resumable upload of a single attachment
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateUploadSession {
+    pub filename: String,
+    pub content_type: Option<String>,
+    /// The total size of the file in bytes
+    pub size: u64,
+    /// Whether the file is uploaded to S3 directly through a presigned URL, instead of in parts through the API
+    #[serde(default)]
+    pub direct: bool,
+}
+
+/// A request to upload an avatar or banner directly to S3
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateDirectUpload {
+    pub content_type: String,
+    /// The size of the file in bytes
+    pub size: u64,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct CreateGuild {
     pub name: String,
@@ -38,7 +85,7 @@ pub struct CreateGuild {
 impl CreateGuild {
     /// Perform the create operation
     ///
-    /// This is a shorthand for `app.ops().create_guild(payload, owner).await`
+    /// This is a shorthand for `app.ops().guilds().create_guild(payload, owner).await`
     ///
     /// # Parameters
     ///
@@ -57,8 +104,8 @@ impl CreateGuild {
         self,
         app: &ApplicationState,
         owner: impl Into<Snowflake<User>>,
-    ) -> Result<(Guild, Channel, Member), AppError> {
-        app.ops().create_guild(self, owner).await
+    ) -> Result<(Guild, Channel, Member), OpsError> {
+        app.ops().guilds().create_guild(self, owner).await
     }
 }
 
@@ -68,12 +115,19 @@ pub struct UpdateGuild {
     pub owner_id: Option<Snowflake<User>>,
     #[serde(default)]
     pub avatar: OmittableOption<DataUri>,
+    /// The amount of days after which attachments are moved to archive storage, `null` to never archive them
+    #[serde(default)]
+    pub attachment_archive_days: OmittableOption<u32>,
+    /// Whether members can only read messages sent after they joined the guild
+    pub hide_history_before_join: Option<bool>,
+    /// Which messages wake the devices of members who did not set a level for the channel
+    pub default_notification_level: Option<NotificationLevel>,
 }
 
 impl UpdateGuild {
     /// Perform the update operation
     ///
-    /// This is a shorthand for `app.ops().update_guild(payload).await`
+    /// This is a shorthand for `app.ops().guilds().update_guild(payload).await`
     ///
     /// # Parameters
     ///
@@ -88,8 +142,46 @@ impl UpdateGuild {
     ///
     /// Fails if the guild does not exist or the update operation fails
     #[inline]
-    pub async fn perform_request(self, app: &ApplicationState, guild: &Guild) -> Result<Guild, RESTError> {
-        app.ops().update_guild(self, guild).await
+    pub async fn perform_request(self, app: &ApplicationState, guild: &Guild) -> Result<Guild, OpsError> {
+        app.ops().guilds().update_guild(self, guild).await
+    }
+}
+
+/// Update payload for a guild's vanity URL
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateVanityUrl {
+    /// The new vanity code, or `None` to release the current one.
+    pub code: Option<String>,
+}
+
+impl UpdateVanityUrl {
+    /// Perform the update operation
+    ///
+    /// This is a shorthand for `app.ops().guilds().update_vanity_code(guild, user, payload.code).await`
+    ///
+    /// # Parameters
+    ///
+    /// - `app` - The application state
+    /// - `guild` - The guild to update the vanity URL of
+    /// - `user` - The user performing the change, used for audit logging
+    ///
+    /// # Returns
+    ///
+    /// The new vanity code, if any
+    ///
+    /// # Errors
+    ///
+    /// - [`OpsError::Conflict`] if the code is already claimed by another guild
+    /// - [`OpsError::Build`] if the code is invalid or reserved
+    /// - [`OpsError::Db`] if the update operation fails
+    #[inline]
+    pub async fn perform_request(
+        self,
+        app: &ApplicationState,
+        guild: &Guild,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Option<String>, OpsError> {
+        app.ops().guilds().update_vanity_code(guild, user, self.code).await
     }
 }
 
@@ -99,6 +191,43 @@ pub enum CreateChannel {
     GuildText { name: String },
 }
 
+/// Update payload for a channel
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateChannel {
+    pub name: Option<String>,
+    /// Whether only privileged members may post in the channel
+    pub locked: Option<bool>,
+    /// The amount of days to keep messages for, `null` to keep them forever
+    #[serde(default)]
+    pub retention_days: OmittableOption<u32>,
+}
+
+impl UpdateChannel {
+    /// Perform the update operation
+    ///
+    /// This is a shorthand for `app.ops().guilds().update_channel(channel).await` with the payload applied
+    ///
+    /// # Parameters
+    ///
+    /// - `app` - The application state
+    /// - `channel` - The current channel state that needs to be updated
+    ///
+    /// # Returns
+    ///
+    /// The updated channel
+    ///
+    /// # Errors
+    ///
+    /// Fails if the new name is invalid or the update operation fails
+    #[inline]
+    pub async fn perform_request(self, app: &ApplicationState, channel: &Channel) -> Result<Channel, OpsError> {
+        let mut channel = channel.clone();
+        channel.update(self);
+        app.ops().guilds().update_channel(&channel).await?;
+        Ok(channel)
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct UpdateUser {
     pub username: Option<String>,
@@ -106,11 +235,13 @@ pub struct UpdateUser {
     pub display_name: OmittableOption<String>,
     #[serde(default)]
     pub avatar: OmittableOption<DataUri>,
+    #[serde(default)]
+    pub banner: OmittableOption<DataUri>,
 }
 
 impl UpdateUser {
     /// Perform the update operation
-    /// This is a shorthand for `app.ops().update_user(user, payload).await`
+    /// This is a shorthand for `app.ops().users().update_user(user, payload).await`
     ///
     /// # Parameters
     ///
@@ -129,8 +260,8 @@ impl UpdateUser {
         self,
         app: &ApplicationState,
         user: impl Into<Snowflake<User>>,
-    ) -> Result<User, RESTError> {
-        app.ops().update_user(user, self).await
+    ) -> Result<User, OpsError> {
+        app.ops().users().update_user(user, self).await
     }
 }
 
@@ -143,7 +274,7 @@ pub struct UpdateMessage {
 impl UpdateMessage {
     /// Perform the update operation
     ///
-    /// This is a shorthand for `app.ops().update_message(message, payload).await`
+    /// This is a shorthand for `app.ops().messages().update_message(message, payload).await`
     ///
     /// # Parameters
     ///
@@ -162,19 +293,23 @@ impl UpdateMessage {
         self,
         app: &ApplicationState,
         message: impl Into<Snowflake<Message>>,
-    ) -> Result<Message, AppError> {
-        app.ops().update_message(message, self).await
+    ) -> Result<Message, OpsError> {
+        app.ops().messages().update_message(message, self).await
     }
 }
 
 /// Update payload for user preferences
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Default)]
 pub struct UpdatePrefs {
     pub flags: Option<PrefFlags>,
     pub message_grouping_timeout: Option<u64>,
     pub layout: Option<Layout>,
     pub text_size: Option<u8>,
     pub locale: Option<String>,
+    /// Replaces the user's muted words
+    pub muted_words: Option<Vec<String>>,
+    /// Who the user's presence is shared with
+    pub presence_sharing: Option<PresenceSharing>,
 }
 
 /// Update payload for FCM token updates
@@ -187,7 +322,7 @@ pub struct UpdateFCMToken {
 impl UpdateFCMToken {
     /// Perform the update operation
     ///
-    /// This is a shorthand for `app.ops().update_fcm_token(user, payload).await`
+    /// This is a shorthand for `app.ops().notifications().update_fcm_token(user, payload).await`
     ///
     /// # Parameters
     ///
@@ -196,16 +331,16 @@ impl UpdateFCMToken {
     ///
     /// # Errors
     ///
-    /// - [`AppError::NotFound`] if the user does not exist
-    /// - [`AppError::Database`] if the update operation fails
-    /// - [`RESTError::Conflict`] if the token already exists
+    /// - [`OpsError::NotFound`] if the user does not exist
+    /// - [`OpsError::Db`] if the update operation fails
+    /// - [`OpsError::Conflict`] if the token already exists
     #[inline]
     pub async fn perform_request(
         self,
         app: &ApplicationState,
         user: impl Into<Snowflake<User>>,
-    ) -> Result<(), RESTError> {
-        app.ops().update_fcm_token(user, self).await
+    ) -> Result<(), OpsError> {
+        app.ops().notifications().update_fcm_token(user, self).await
     }
 }
 
@@ -217,7 +352,7 @@ pub struct RemoveFCMToken {
 impl RemoveFCMToken {
     /// Perform the remove operation
     ///
-    /// This is a shorthand for `app.ops().remove_fcm_token(user, &payload.token).await`
+    /// This is a shorthand for `app.ops().notifications().remove_fcm_token(user, &payload.token).await`
     ///
     /// # Parameters
     ///
@@ -226,13 +361,209 @@ impl RemoveFCMToken {
     ///
     /// # Errors
     ///
-    /// - [`sqlx::Error`] if the delete operation fails
+    /// - [`OpsError::Db`] if the delete operation fails
     #[inline]
     pub async fn perform_request(
         self,
         app: &ApplicationState,
         user: impl Into<Snowflake<User>>,
-    ) -> Result<(), sqlx::Error> {
-        app.ops().remove_fcm_token(user, &self.token).await
+    ) -> Result<(), OpsError> {
+        app.ops().notifications().remove_fcm_token(user, &self.token).await
     }
 }
+
+/// Sets the notification level of a channel for the current user
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct UpdateNotificationOverride {
+    pub level: NotificationLevel,
+}
+
+/// Replaces a guild's onboarding configuration
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateOnboarding {
+    #[serde(default)]
+    pub default_channel_ids: Vec<Snowflake<Channel>>,
+    pub system_channel_id: Option<Snowflake<Channel>>,
+    pub welcome_message: Option<String>,
+    #[serde(default)]
+    pub questions: Vec<OnboardingQuestionPayload>,
+}
+
+/// A question in an [`UpdateOnboarding`] payload
+#[derive(Deserialize, Debug, Clone)]
+pub struct OnboardingQuestionPayload {
+    /// The ID of the existing question to update, if any
+    pub id: Option<Snowflake<OnboardingQuestion>>,
+    pub prompt: String,
+    pub options: Vec<OnboardingOptionPayload>,
+}
+
+/// An option in an [`OnboardingQuestionPayload`]
+#[derive(Deserialize, Debug, Clone)]
+pub struct OnboardingOptionPayload {
+    /// The ID of the existing option to update, if any
+    pub id: Option<Snowflake<OnboardingOption>>,
+    pub label: String,
+    #[serde(default)]
+    pub role_ids: Vec<Snowflake<Role>>,
+}
+
+/// The options a member picked while onboarding
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateOnboardingResponses {
+    pub option_ids: Vec<Snowflake<OnboardingOption>>,
+}
+
+/// A request to create a registration code
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct CreateRegistrationCode {
+    /// The number of accounts that may be registered with the code. If omitted, the code may be used indefinitely.
+    pub max_uses: Option<NonZeroU32>,
+}
+
+/// A request to change the log filter of the running instance
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateLogFilter {
+    /// The new filter directives, for example `info,chat_backend::gateway::actor=debug`
+    pub directives: String,
+    /// How long the filter stays in effect before it is reset, in seconds. If omitted, it stays until changed again.
+    pub reset_after: Option<NonZeroU32>,
+}
+
+/// A request to make the instance read-only
+#[derive(Deserialize, Debug, Clone)]
+pub struct EnableReadOnly {
+    /// Why the instance is read-only, shown to clients whose requests are rejected.
+    pub reason: Option<String>,
+}
+
+/// A request to send a friend request to a user by their username
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateRelationship {
+    /// The username of the user to send the friend request to.
+    pub username: String,
+}
+
+/// A request to open a direct message channel with a user
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateDirectChannel {
+    /// The ID of the user to message.
+    pub recipient_id: Snowflake<User>,
+}
+
+/// A request to create a guest link to a channel
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateGuestLink {
+    /// Whether guests may post in the channel
+    #[serde(default)]
+    pub can_post: bool,
+    /// How long guests keep their access after joining, in seconds
+    pub access_duration: Option<u32>,
+    /// How long the link can be used for, in seconds. If omitted, the link does not expire.
+    pub max_age: Option<u32>,
+}
+
+/// A request to report a message, user or guild to the instance's administrators
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateReport {
+    pub target_type: ReportTargetType,
+    /// The ID of the message, user or guild reported
+    pub target_id: Snowflake<()>,
+    pub category: ReportCategory,
+    /// An explanation of what is wrong, up to 1000 characters
+    pub details: Option<String>,
+    /// Whether to also forward the report to the moderators of the guild the reported object belongs to
+    #[serde(default)]
+    pub forward_to_guild: bool,
+}
+
+/// A request to resolve a report
+#[derive(Deserialize, Debug, Clone)]
+pub struct ResolveReport {
+    /// What was done about the report
+    pub action: ReportAction,
+}
+
+/// A request to issue a strike against a user
+#[derive(Deserialize, Debug, Clone)]
+pub struct IssueStrike {
+    /// Why the strike is issued, up to 1000 characters
+    pub reason: String,
+}
+
+/// A request to create an auto-moderation rule
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateAutomodRule {
+    pub name: String,
+    /// Whether the rule is enforced, defaults to true
+    pub enabled: Option<bool>,
+    pub trigger: AutomodTrigger,
+    /// At most one action of each type, alert channels must belong to the guild
+    pub actions: Vec<AutomodAction>,
+}
+
+/// Update payload for an auto-moderation rule
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateAutomodRule {
+    pub name: Option<String>,
+    pub enabled: Option<bool>,
+    pub trigger: Option<AutomodTrigger>,
+    pub actions: Option<Vec<AutomodAction>>,
+}
+
+/// A sample message to test a guild's auto-moderation rules against
+#[derive(Deserialize, Debug, Clone)]
+pub struct TestAutomodRules {
+    pub content: Option<String>,
+    /// The content types of the attachments the message would have
+    #[serde(default)]
+    pub content_types: Vec<String>,
+}
+
+/// A request to schedule a new guild event
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateGuildEvent {
+    pub title: String,
+    pub description: Option<String>,
+    /// When the event starts as an RFC 3339 timestamp, must be in the future
+    pub starts_at: DateTime<Utc>,
+    /// When the event ends as an RFC 3339 timestamp, must be after it starts
+    pub ends_at: Option<DateTime<Utc>>,
+    /// The channel the event takes place in, which must belong to the guild
+    pub channel_id: Option<Snowflake<Channel>>,
+}
+
+/// Update payload for a guild event
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateGuildEvent {
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: OmittableOption<String>,
+    pub starts_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub ends_at: OmittableOption<DateTime<Utc>>,
+    #[serde(default)]
+    pub channel_id: OmittableOption<Snowflake<Channel>>,
+}
+
+/// A request to answer a guild event
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateGuildEventRsvp {
+    pub status: RsvpStatus,
+}
+
+/// A request to create a new role
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateRole {
+    pub name: String,
+    /// The permissions granted to the members the role is assigned to, none if omitted
+    #[serde(default)]
+    pub permissions: Permissions,
+}
+
+/// Update payload for a role
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateRole {
+    pub name: Option<String>,
+    pub permissions: Option<Permissions>,
+}
@@ -0,0 +1,184 @@
+use bitflags::bitflags;
+use serde::{Deserialize, Serialize, de::Error as _};
+
+use super::{
+    errors::BuildError,
+    guild::Guild,
+    request_payloads::{CreateRole, UpdateRole},
+    snowflake::Snowflake,
+};
+use crate::app::Config;
+
+/// The maximum length of a role's name.
+pub const MAX_ROLE_NAME_LENGTH: usize = 100;
+
+bitflags! {
+    /// What the members a role is assigned to may do in its guild.
+    ///
+    /// The owner of a guild always has every permission.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct Permissions: u64 {
+        /// Grants every other permission.
+        const ADMINISTRATOR = 1;
+        /// Update the guild's settings, avatar, vanity URL and onboarding.
+        const MANAGE_GUILD = 1 << 1;
+        /// Create, update and delete channels, and manage their guest links.
+        const MANAGE_CHANNELS = 1 << 2;
+        /// Delete the messages of other members, and export channels.
+        const MANAGE_MESSAGES = 1 << 3;
+        /// Remove members from the guild.
+        const KICK_MEMBERS = 1 << 4;
+        /// Create, update, delete and assign roles granting at most the member's own permissions.
+        const MANAGE_ROLES = 1 << 5;
+        /// View reports and invite usage, and manage watched keywords and auto-moderation rules.
+        const MODERATE_GUILD = 1 << 6;
+        /// Schedule, update and cancel guild events.
+        const MANAGE_EVENTS = 1 << 7;
+    }
+}
+
+impl Permissions {
+    /// Whether these permissions allow everything the required ones do.
+    pub const fn allows(self, required: Self) -> bool {
+        self.contains(Self::ADMINISTRATOR) || self.contains(required)
+    }
+}
+
+impl Serialize for Permissions {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.bits())
+    }
+}
+
+impl<'de> Deserialize<'de> for Permissions {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bits = u64::deserialize(deserializer)?;
+        Self::from_bits(bits).ok_or_else(|| D::Error::custom(format!("Unknown permissions: {bits}")))
+    }
+}
+
+/// Represents a role stored in the database.
+pub struct RoleRecord {
+    pub id: i64,
+    pub guild_id: i64,
+    pub name: String,
+    pub permissions: i64,
+}
+
+/// A set of permissions that can be assigned to the members of a guild.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Role {
+    id: Snowflake<Self>,
+    guild_id: Snowflake<Guild>,
+    name: String,
+    /// The permissions granted to the members the role is assigned to.
+    permissions: Permissions,
+}
+
+impl Role {
+    /// Create a new role from a creation payload, with a freshly generated ID.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration, used to generate the ID.
+    /// * `guild` - The guild the role belongs to.
+    /// * `payload` - The role's details.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the name is invalid.
+    pub fn from_payload(
+        config: &Config,
+        guild: impl Into<Snowflake<Guild>>,
+        payload: &CreateRole,
+    ) -> Result<Self, BuildError> {
+        let role = Self {
+            id: Snowflake::gen_new(config),
+            guild_id: guild.into(),
+            name: payload.name.trim().to_owned(),
+            permissions: payload.permissions,
+        };
+        role.validate()?;
+        Ok(role)
+    }
+
+    /// Apply an update payload to the role.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the updated name is invalid.
+    pub fn apply_update(&mut self, payload: UpdateRole) -> Result<(), BuildError> {
+        if let Some(name) = payload.name {
+            name.trim().clone_into(&mut self.name);
+        }
+        if let Some(permissions) = payload.permissions {
+            self.permissions = permissions;
+        }
+        self.validate()
+    }
+
+    /// Ensure the role's details are within their limits.
+    fn validate(&self) -> Result<(), BuildError> {
+        let name_length = self.name.chars().count();
+        if !(1..=MAX_ROLE_NAME_LENGTH).contains(&name_length) {
+            return Err(BuildError::ValidationError(format!(
+                "Role name must be between 1 and {MAX_ROLE_NAME_LENGTH} characters long"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Build a role from a database record. Permissions unknown to this version are dropped.
+    pub fn from_record(record: RoleRecord) -> Self {
+        Self {
+            id: record.id.into(),
+            guild_id: record.guild_id.into(),
+            name: record.name,
+            permissions: Permissions::from_bits_truncate(record.permissions as u64),
+        }
+    }
+
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    pub const fn guild_id(&self) -> Snowflake<Guild> {
+        self.guild_id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The permissions granted to the members the role is assigned to.
+    pub const fn permissions(&self) -> Permissions {
+        self.permissions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permissions_allows() {
+        let moderator = Permissions::MODERATE_GUILD | Permissions::KICK_MEMBERS;
+        assert!(moderator.allows(Permissions::KICK_MEMBERS));
+        assert!(moderator.allows(Permissions::MODERATE_GUILD | Permissions::KICK_MEMBERS));
+        assert!(!moderator.allows(Permissions::MANAGE_ROLES));
+        assert!(!moderator.allows(Permissions::KICK_MEMBERS | Permissions::MANAGE_ROLES));
+        assert!(Permissions::empty().allows(Permissions::empty()));
+        assert!(Permissions::ADMINISTRATOR.allows(Permissions::all()));
+    }
+
+    #[test]
+    fn test_permissions_serde() {
+        let permissions = Permissions::MANAGE_GUILD | Permissions::MANAGE_EVENTS;
+        assert_eq!(serde_json::to_string(&permissions).expect("serializes"), "130");
+        assert_eq!(
+            serde_json::from_str::<Permissions>("130").expect("deserializes"),
+            permissions
+        );
+        assert!(serde_json::from_str::<Permissions>("256").is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     error::Error,
     fmt::{Debug, Display, Formatter},
     hash::{Hash, Hasher},
@@ -6,6 +7,7 @@ use std::{
     num::ParseIntError,
     ops::{Add, Div, Mul, Sub},
     str::FromStr,
+    sync::{LazyLock, Mutex},
 };
 
 use chrono::prelude::*;
@@ -19,6 +21,15 @@ use crate::app::Config;
 // Custom epoch of 2023-01-01T00:00:00Z in miliseconds
 pub const EPOCH: i64 = 1_672_531_200_000;
 
+/// The worker ID, process ID and epoch a snowflake generator was created with
+type GeneratorKey = (i32, i32, i64);
+
+/// The generators new snowflakes are created with, by the configuration they were created with.
+/// They are shared by the whole process, so that snowflakes generated within the same millisecond
+/// are told apart by their sequence number instead of colliding.
+static GENERATORS: LazyLock<Mutex<HashMap<GeneratorKey, SnowflakeIdGenerator>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
 /// A snowflake ID used to identify entities.
 ///
 /// Snowflakes are 64-bit integers that are guaranteed to be unique.
@@ -48,9 +59,17 @@ impl<T> Snowflake<T> {
     }
 
     /// Generate a new snowflake using the current time.
+    ///
+    /// Snowflakes generated with the same configuration are unique within the process,
+    /// even if they are generated within the same millisecond.
     pub fn gen_new(config: &Config) -> Self {
-        let mut generator = get_generator(config.machine_id(), config.process_id());
-        generator.generate().into()
+        let key = (config.machine_id(), config.process_id(), config.snowflake_epoch());
+        let mut generators = GENERATORS.lock().expect("Snowflake generators should not be poisoned");
+        generators
+            .entry(key)
+            .or_insert_with(|| get_generator(key.0, key.1, key.2))
+            .real_time_generate()
+            .into()
     }
 
     /// Cast this snowflake to a different marker type.
@@ -59,16 +78,26 @@ impl<T> Snowflake<T> {
         Snowflake::new(self.value)
     }
 
-    /// UNIX timestamp representing the time at which this snowflake was created in milliseconds.
+    /// Create the smallest snowflake that could have been generated at the given UNIX timestamp in milliseconds.
+    ///
+    /// Useful as a bound when querying for entities created before or after a point in time.
     #[inline]
-    pub const fn timestamp(&self) -> i64 {
-        (self.value >> 22) + EPOCH
+    pub const fn from_timestamp_with_epoch(timestamp: i64, epoch: i64) -> Self {
+        Self::new((timestamp - epoch) << 22)
     }
 
-    /// Returns the creation time of this snowflake.
+    /// UNIX timestamp representing the time at which this snowflake was created in milliseconds,
+    /// relative to the given epoch.
     #[inline]
-    pub const fn created_at(&self) -> DateTime<Utc> {
-        DateTime::from_timestamp_millis(self.timestamp()).expect("Failed to convert timestamp to DateTime")
+    pub const fn timestamp_with_epoch(&self, epoch: i64) -> i64 {
+        (self.value >> 22) + epoch
+    }
+
+    /// Returns the creation time of this snowflake, relative to the instance's [`Config::snowflake_epoch`].
+    #[inline]
+    pub const fn created_at(&self, config: &Config) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(self.timestamp_with_epoch(config.snowflake_epoch()))
+            .expect("Failed to convert timestamp to DateTime")
     }
 
     /// Returns the worker ID that generated this snowflake.
@@ -82,6 +111,13 @@ impl<T> Snowflake<T> {
     pub const fn process_id(&self) -> i64 {
         (self.value & 0x1F000) >> 12
     }
+
+    /// Returns the sequence number of this snowflake, used to distinguish
+    /// snowflakes generated by the same process within the same millisecond.
+    #[inline]
+    pub const fn sequence(&self) -> i64 {
+        self.value & 0xFFF
+    }
 }
 
 impl<T> From<i64> for Snowflake<T> {
@@ -272,21 +308,47 @@ impl<T> PgHasArrayType for Snowflake<T> {
     }
 }
 
-/// Retrieve a new Snowflake ID generator that uses the custom epoch.
+/// Retrieve a new Snowflake ID generator that uses the given epoch.
 #[inline]
-pub fn get_generator(worker_id: i32, process_id: i32) -> SnowflakeIdGenerator {
+pub fn get_generator(worker_id: i32, process_id: i32, epoch: i64) -> SnowflakeIdGenerator {
     SnowflakeIdGenerator::with_epoch(
         worker_id,
         process_id,
-        SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(EPOCH as u64),
+        SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(epoch as u64),
     )
 }
 
 #[cfg(test)]
 #[expect(clippy::unreadable_literal)]
 mod tests {
+    use secrecy::Secret;
+
     use super::*;
 
+    fn config(epoch: i64) -> Config {
+        Config::builder()
+            .database_url(Secret::new(String::new()))
+            .s3(None)
+            .listen_addr(([127, 0, 0, 1], 8080))
+            .machine_id(3)
+            .process_id(7)
+            .snowflake_epoch(epoch)
+            .app_secret(Secret::new(String::new()))
+            .build()
+            .expect("config should be valid")
+    }
+
+    #[test]
+    fn test_gen_new_unique() {
+        let config = config(EPOCH);
+
+        // More than fit in a single millisecond, most of them are generated within the same one
+        let ids: Vec<Snowflake<()>> = (0..5000).map(|_| Snowflake::gen_new(&config)).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        let now = Utc::now().timestamp_millis();
+        assert!((now - ids[4999].timestamp_with_epoch(config.snowflake_epoch())).abs() < 1000);
+    }
+
     #[test]
     fn test_new_and_default() {
         let s = Snowflake::<()>::new(123456);
@@ -309,10 +371,25 @@ mod tests {
         let ts_component = ts - EPOCH;
         let value = ts_component << 22;
         let s = Snowflake::<()>::new(value);
-        assert_eq!(s.timestamp(), ts);
+        assert_eq!(s.timestamp_with_epoch(EPOCH), ts);
 
         let expected_dt = DateTime::from_timestamp(ts / 1000, 0).expect("Datetime should be valid");
-        assert_eq!(s.created_at(), expected_dt);
+        assert_eq!(s.created_at(&config(EPOCH)), expected_dt);
+
+        // Instances with a custom epoch decode their snowflakes relative to it
+        let expected_dt = DateTime::from_timestamp((ts + 5000) / 1000, 0).expect("Datetime should be valid");
+        assert_eq!(s.created_at(&config(EPOCH + 5000)), expected_dt);
+    }
+
+    #[test]
+    fn test_from_timestamp() {
+        let s = Snowflake::<()>::from_timestamp_with_epoch(EPOCH + 5000, EPOCH);
+        assert_eq!(s.timestamp_with_epoch(EPOCH), EPOCH + 5000);
+        assert_eq!(s.sequence(), 0);
+
+        let later = Snowflake::<()>::new(i64::from(s) | (3 << 17) | 0x2A);
+        assert!(s <= later);
+        assert!(Snowflake::<()>::from_timestamp_with_epoch(EPOCH + 5001, EPOCH) > later);
     }
 
     #[test]
@@ -324,9 +401,23 @@ mod tests {
         let value = timestamp_bits | worker_bits | process_bits;
         let s = Snowflake::<()>::new(value);
 
-        assert_eq!(s.timestamp(), ts_component + EPOCH);
+        assert_eq!(s.timestamp_with_epoch(EPOCH), ts_component + EPOCH);
         assert_eq!(s.worker_id(), 15);
         assert_eq!(s.process_id(), 8);
+        assert_eq!(s.sequence(), 0);
+    }
+
+    #[test]
+    fn test_timestamp_with_epoch_and_sequence() {
+        let epoch = 1_700_000_000_000;
+        let value = (5000 << 22) | (3 << 17) | (1 << 12) | 0x2A;
+        let s = Snowflake::<()>::new(value);
+
+        assert_eq!(s.timestamp_with_epoch(epoch), epoch + 5000);
+        assert_eq!(s.timestamp_with_epoch(EPOCH), EPOCH + 5000);
+        assert_eq!(s.worker_id(), 3);
+        assert_eq!(s.process_id(), 1);
+        assert_eq!(s.sequence(), 42);
     }
 
     #[test]
@@ -0,0 +1,434 @@
+use std::time::Duration;
+
+use chrono::{DateTime, SubsecRound, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{errors::BuildError, snowflake::Snowflake, user::User};
+use crate::app::Config;
+
+/// The maximum length of the reason given for a strike.
+pub const MAX_STRIKE_REASON_LENGTH: usize = 1000;
+
+/// The penalty a strike carries, escalating with the number of active strikes of the user.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum StrikePenalty {
+    /// The user is only warned.
+    Warning = 1,
+    /// The user may not send messages, create guilds or join guilds until the timeout ends.
+    Timeout = 2,
+    /// Like a timeout, but the user may not connect to the gateway either until the suspension ends.
+    Suspension = 3,
+}
+
+impl TryFrom<i16> for StrikePenalty {
+    type Error = BuildError;
+
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Warning),
+            2 => Ok(Self::Timeout),
+            3 => Ok(Self::Suspension),
+            _ => Err(BuildError::ValidationError(format!("Unknown strike penalty: {value}"))),
+        }
+    }
+}
+
+/// How strikes escalate into timeouts and suspensions, configured per instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StandingPolicy {
+    strike_duration: Duration,
+    timeout_after: usize,
+    timeout_duration: Duration,
+    suspension_after: usize,
+    suspension_duration: Duration,
+}
+
+impl Default for StandingPolicy {
+    fn default() -> Self {
+        Self {
+            strike_duration: Duration::from_secs(90 * 24 * 60 * 60),
+            timeout_after: 2,
+            timeout_duration: Duration::from_secs(24 * 60 * 60),
+            suspension_after: 3,
+            suspension_duration: Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+impl StandingPolicy {
+    /// Create a new standing policy.
+    ///
+    /// ## Arguments
+    ///
+    /// * `strike_duration` - How long strikes count towards the standing of a user.
+    /// * `timeout_after` - The number of active strikes a strike times the user out at.
+    /// * `timeout_duration` - How long timeouts last.
+    /// * `suspension_after` - The number of active strikes a strike suspends the user at.
+    /// * `suspension_duration` - How long suspensions last.
+    pub const fn new(
+        strike_duration: Duration,
+        timeout_after: usize,
+        timeout_duration: Duration,
+        suspension_after: usize,
+        suspension_duration: Duration,
+    ) -> Self {
+        Self {
+            strike_duration,
+            timeout_after,
+            timeout_duration,
+            suspension_after,
+            suspension_duration,
+        }
+    }
+
+    /// How long strikes count towards the standing of a user.
+    pub const fn strike_duration(&self) -> Duration {
+        self.strike_duration
+    }
+
+    /// The number of active strikes a strike times the user out at.
+    pub const fn timeout_after(&self) -> usize {
+        self.timeout_after
+    }
+
+    /// How long timeouts last.
+    pub const fn timeout_duration(&self) -> Duration {
+        self.timeout_duration
+    }
+
+    /// The number of active strikes a strike suspends the user at.
+    pub const fn suspension_after(&self) -> usize {
+        self.suspension_after
+    }
+
+    /// How long suspensions last.
+    pub const fn suspension_duration(&self) -> Duration {
+        self.suspension_duration
+    }
+
+    /// The penalty of a new strike, and how long it lasts for if it is a timeout or suspension.
+    ///
+    /// ## Arguments
+    ///
+    /// * `active_strikes` - The number of active strikes of the user, including the new one.
+    pub const fn penalty_for(&self, active_strikes: usize) -> (StrikePenalty, Option<Duration>) {
+        if active_strikes >= self.suspension_after {
+            (StrikePenalty::Suspension, Some(self.suspension_duration))
+        } else if active_strikes >= self.timeout_after {
+            (StrikePenalty::Timeout, Some(self.timeout_duration))
+        } else {
+            (StrikePenalty::Warning, None)
+        }
+    }
+}
+
+/// Represents a strike stored in the database.
+pub struct StrikeRecord {
+    pub id: i64,
+    pub user_id: i64,
+    pub issuer_id: Option<i64>,
+    pub automated: bool,
+    pub reason: String,
+    pub penalty: i16,
+    pub penalty_ends_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A strike against a user for breaking the rules, issued by an administrator or by automated moderation.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Strike {
+    id: Snowflake<Self>,
+    user_id: Snowflake<User>,
+    /// The administrator who issued the strike, if they still exist. Hidden from the user.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    issuer_id: Option<Snowflake<User>>,
+    /// Whether the strike was issued by automated moderation.
+    automated: bool,
+    reason: String,
+    penalty: StrikePenalty,
+    /// When the timeout or suspension of the strike ends, if it carries one.
+    penalty_ends_at: Option<DateTime<Utc>>,
+    /// When the strike stops counting towards the standing of the user.
+    expires_at: DateTime<Utc>,
+}
+
+impl Strike {
+    /// Create a new strike with a freshly generated ID, carrying a warning until its penalty is escalated.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration, used to generate the ID and determine when the strike expires.
+    /// * `user` - The user the strike is issued against.
+    /// * `issuer` - The administrator issuing the strike, or `None` if it is issued by automated moderation.
+    /// * `reason` - Why the strike is issued.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the reason is empty or too long.
+    pub fn new(
+        config: &Config,
+        user: impl Into<Snowflake<User>>,
+        issuer: Option<Snowflake<User>>,
+        reason: &str,
+    ) -> Result<Self, BuildError> {
+        let reason = reason.trim();
+        if reason.is_empty() || reason.chars().count() > MAX_STRIKE_REASON_LENGTH {
+            return Err(BuildError::ValidationError(format!(
+                "Strike reason must be between 1 and {MAX_STRIKE_REASON_LENGTH} characters long"
+            )));
+        }
+
+        Ok(Self {
+            id: Snowflake::gen_new(config),
+            user_id: user.into(),
+            issuer_id: issuer,
+            automated: issuer.is_none(),
+            reason: reason.to_owned(),
+            penalty: StrikePenalty::Warning,
+            penalty_ends_at: None,
+            expires_at: from_now(config.standing_policy().strike_duration()),
+        })
+    }
+
+    /// Build a strike from a database record.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the record contains an unknown penalty.
+    pub fn from_record(record: StrikeRecord) -> Result<Self, BuildError> {
+        Ok(Self {
+            id: record.id.into(),
+            user_id: record.user_id.into(),
+            issuer_id: record.issuer_id.map(Into::into),
+            automated: record.automated,
+            reason: record.reason,
+            penalty: record.penalty.try_into()?,
+            penalty_ends_at: record.penalty_ends_at,
+            expires_at: record.expires_at,
+        })
+    }
+
+    /// Apply the penalty the policy prescribes for the user's number of active strikes.
+    ///
+    /// ## Arguments
+    ///
+    /// * `policy` - The standing policy of the instance.
+    /// * `active_strikes` - The number of active strikes of the user, including this one.
+    pub fn escalate(&mut self, policy: &StandingPolicy, active_strikes: usize) {
+        let (penalty, duration) = policy.penalty_for(active_strikes);
+        self.penalty = penalty;
+        self.penalty_ends_at = duration.map(from_now);
+    }
+
+    /// The strike as shown to the user it was issued against, without the issuing administrator.
+    #[must_use]
+    pub fn anonymized(self) -> Self {
+        Self {
+            issuer_id: None,
+            ..self
+        }
+    }
+
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    pub const fn user_id(&self) -> Snowflake<User> {
+        self.user_id
+    }
+
+    /// The administrator who issued the strike, if they still exist.
+    pub const fn issuer_id(&self) -> Option<Snowflake<User>> {
+        self.issuer_id
+    }
+
+    /// Whether the strike was issued by automated moderation.
+    pub const fn is_automated(&self) -> bool {
+        self.automated
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    pub const fn penalty(&self) -> StrikePenalty {
+        self.penalty
+    }
+
+    /// When the timeout or suspension of the strike ends, if it carries one.
+    pub const fn penalty_ends_at(&self) -> Option<DateTime<Utc>> {
+        self.penalty_ends_at
+    }
+
+    /// When the strike stops counting towards the standing of the user.
+    pub const fn expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
+}
+
+/// How restricted a user's account currently is.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum StandingState {
+    /// The user has no active strikes.
+    Good,
+    /// The user has active strikes, but no timeout or suspension is in force.
+    Warned,
+    /// A timeout is in force.
+    TimedOut,
+    /// A suspension is in force.
+    Suspended,
+}
+
+/// The standing of a user's account, derived from their active strikes.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Standing {
+    state: StandingState,
+    /// When the timeout or suspension in force ends, if any.
+    restricted_until: Option<DateTime<Utc>>,
+    /// The strikes counting towards the standing, oldest first.
+    strikes: Vec<Strike>,
+}
+
+impl Standing {
+    /// Derive the standing of a user from their strikes, ignoring expired ones.
+    ///
+    /// ## Arguments
+    ///
+    /// * `strikes` - The strikes of the user that were not revoked, oldest first.
+    /// * `now` - The current time.
+    pub fn from_strikes(strikes: Vec<Strike>, now: DateTime<Utc>) -> Self {
+        let strikes: Vec<Strike> = strikes.into_iter().filter(|s| s.expires_at > now).collect();
+        let in_force = |penalty: StrikePenalty| {
+            strikes
+                .iter()
+                .filter(|s| s.penalty == penalty)
+                .filter_map(|s| s.penalty_ends_at)
+                .filter(|ends_at| *ends_at > now)
+                .max()
+        };
+
+        // A suspension takes precedence over a timeout that outlasts it
+        let (state, restricted_until) = in_force(StrikePenalty::Suspension)
+            .map(|until| (StandingState::Suspended, Some(until)))
+            .or_else(|| in_force(StrikePenalty::Timeout).map(|until| (StandingState::TimedOut, Some(until))))
+            .unwrap_or(if strikes.is_empty() {
+                (StandingState::Good, None)
+            } else {
+                (StandingState::Warned, None)
+            });
+
+        Self {
+            state,
+            restricted_until,
+            strikes,
+        }
+    }
+
+    /// The standing as shown to the user, without the administrators who issued the strikes.
+    #[must_use]
+    pub fn anonymized(self) -> Self {
+        Self {
+            strikes: self.strikes.into_iter().map(Strike::anonymized).collect(),
+            ..self
+        }
+    }
+
+    pub const fn state(&self) -> StandingState {
+        self.state
+    }
+
+    /// When the timeout or suspension in force ends, if any.
+    pub const fn restricted_until(&self) -> Option<DateTime<Utc>> {
+        self.restricted_until
+    }
+
+    /// The strikes counting towards the standing, oldest first.
+    pub fn strikes(&self) -> &[Strike] {
+        &self.strikes
+    }
+}
+
+/// The time the given duration from now ends at.
+fn from_now(duration: Duration) -> DateTime<Utc> {
+    TimeDelta::from_std(duration)
+        .ok()
+        .and_then(|delta| Utc::now().trunc_subsecs(6).checked_add_signed(delta))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(secs, 0).expect("Timestamp should be in range")
+    }
+
+    fn strike(penalty: StrikePenalty, penalty_ends_at: Option<i64>, expires_at: i64) -> Strike {
+        Strike::from_record(StrikeRecord {
+            id: 1,
+            user_id: 2,
+            issuer_id: Some(3),
+            automated: false,
+            reason: "spam".into(),
+            penalty: penalty as i16,
+            penalty_ends_at: penalty_ends_at.map(at),
+            expires_at: at(expires_at),
+        })
+        .expect("record should be valid")
+    }
+
+    #[test]
+    fn test_penalty_for() {
+        let policy = StandingPolicy::default();
+        assert_eq!(policy.penalty_for(1).0, StrikePenalty::Warning);
+        assert_eq!(policy.penalty_for(2).0, StrikePenalty::Timeout);
+        assert_eq!(policy.penalty_for(3).0, StrikePenalty::Suspension);
+        assert_eq!(policy.penalty_for(10).0, StrikePenalty::Suspension);
+        assert_eq!(policy.penalty_for(1).1, None);
+    }
+
+    #[test]
+    fn test_standing_from_strikes() {
+        assert_eq!(Standing::from_strikes(vec![], at(100)).state(), StandingState::Good);
+
+        // Expired strikes no longer count
+        let expired = Standing::from_strikes(vec![strike(StrikePenalty::Warning, None, 50)], at(100));
+        assert_eq!(expired.state(), StandingState::Good);
+        assert!(expired.strikes().is_empty());
+
+        let warned = Standing::from_strikes(
+            vec![
+                strike(StrikePenalty::Warning, None, 200),
+                strike(StrikePenalty::Timeout, Some(90), 200),
+            ],
+            at(100),
+        );
+        assert_eq!(warned.state(), StandingState::Warned);
+        assert_eq!(warned.restricted_until(), None);
+
+        let timed_out = Standing::from_strikes(vec![strike(StrikePenalty::Timeout, Some(150), 200)], at(100));
+        assert_eq!(timed_out.state(), StandingState::TimedOut);
+        assert_eq!(timed_out.restricted_until(), Some(at(150)));
+
+        let suspended = Standing::from_strikes(
+            vec![
+                strike(StrikePenalty::Timeout, Some(300), 400),
+                strike(StrikePenalty::Suspension, Some(150), 400),
+            ],
+            at(100),
+        );
+        assert_eq!(suspended.state(), StandingState::Suspended);
+        assert_eq!(suspended.restricted_until(), Some(at(150)));
+    }
+
+    #[test]
+    fn test_anonymized() {
+        let standing = Standing::from_strikes(vec![strike(StrikePenalty::Warning, None, 200)], at(100)).anonymized();
+        assert_eq!(standing.strikes()[0].issuer_id(), None);
+        assert!(!standing.strikes()[0].is_automated());
+    }
+}
@@ -0,0 +1,347 @@
+use std::time::Duration;
+
+use mime::Mime;
+use serde::Serialize;
+
+use crate::{
+    app::Config,
+    external::s3::{KEYSPACE_VERSION, ObjectClass, versioned_key},
+};
+
+use super::{
+    attachment::PartialAttachment, channel::Channel, direct_upload::PresignedUpload, errors::BuildError,
+    message::Message, request_payloads::CreateUploadSession, snowflake::Snowflake, user::User,
+};
+
+/// The smallest part S3 accepts in a multipart upload, except for the last one.
+pub const MIN_PART_SIZE: usize = 5 * 1024 * 1024; // 5mb
+/// The largest part accepted in a single request.
+pub const MAX_PART_SIZE: usize = 16 * 1024 * 1024; // 16mb
+/// The largest file that may be uploaded through an upload session.
+pub const MAX_UPLOAD_SIZE: u64 = 100 * 1024 * 1024; // 100mb
+/// For how long a part may be uploading before its reservation is released, so that it can be sent again.
+pub const PART_RESERVATION: Duration = Duration::from_secs(15 * 60);
+
+/// Represents an upload session record stored in the database.
+pub struct UploadSessionRecord {
+    pub id: Snowflake<UploadSession>,
+    pub user_id: Snowflake<User>,
+    pub channel_id: Snowflake<Channel>,
+    pub message_id: Snowflake<Message>,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    pub uploaded: i64,
+    pub s3_upload_id: Option<String>,
+    pub key_version: i16,
+    pub direct: bool,
+}
+
+/// A resumable upload of a single large attachment.
+///
+/// The file is uploaded in parts, and once all parts have been received,
+/// the session is completed into a message with the file as its attachment.
+/// Direct sessions are instead uploaded to S3 in one request through a presigned URL,
+/// and moved to the attachment's key once completed.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct UploadSession {
+    id: Snowflake<Self>,
+    user_id: Snowflake<User>,
+    channel_id: Snowflake<Channel>,
+    /// The ID the message will be created with once the upload completes.
+    #[serde(skip)]
+    message_id: Snowflake<Message>,
+    filename: String,
+    content_type: String,
+    size: u64,
+    uploaded: u64,
+    #[serde(skip)]
+    s3_upload_id: Option<String>,
+    /// The S3 key layout the file is uploaded under.
+    #[serde(skip)]
+    key_version: i16,
+    direct: bool,
+    /// The request to upload the file with, only included for direct sessions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upload: Option<PresignedUpload>,
+}
+
+impl UploadSession {
+    /// Create a new upload session from a request payload.
+    /// Assigns new snowflakes to both the session and the message it will be completed into.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration, used to generate IDs.
+    /// * `user` - The user uploading the file.
+    /// * `channel` - The channel the file will be sent to.
+    /// * `payload` - The request payload describing the file.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the filename, content type or size is invalid.
+    pub fn from_payload(
+        config: &Config,
+        user: impl Into<Snowflake<User>>,
+        channel: impl Into<Snowflake<Channel>>,
+        payload: CreateUploadSession,
+    ) -> Result<Self, BuildError> {
+        if payload.filename.is_empty() || payload.filename.len() > 255 || payload.filename.contains('/') {
+            return Err(BuildError::ValidationError(
+                "Invalid filename, must be between 1 and 255 characters long and not contain '/'".into(),
+            ));
+        }
+
+        let content_type = payload
+            .content_type
+            .unwrap_or_else(|| "application/octet-stream".into());
+
+        if content_type.len() > 255 || content_type.parse::<Mime>().is_err() {
+            return Err(BuildError::ValidationError("Invalid content type".into()));
+        }
+
+        if payload.size == 0 || payload.size > MAX_UPLOAD_SIZE {
+            return Err(BuildError::ValidationError(format!(
+                "Invalid size, must be between 1 and {MAX_UPLOAD_SIZE} bytes"
+            )));
+        }
+
+        Ok(Self {
+            id: Snowflake::gen_new(config),
+            user_id: user.into(),
+            channel_id: channel.into(),
+            message_id: Snowflake::gen_new(config),
+            filename: payload.filename,
+            content_type,
+            size: payload.size,
+            uploaded: 0,
+            s3_upload_id: None,
+            key_version: KEYSPACE_VERSION,
+            direct: payload.direct,
+            upload: None,
+        })
+    }
+
+    /// Create a new upload session from a database record.
+    pub fn from_record(record: UploadSessionRecord) -> Self {
+        Self {
+            id: record.id,
+            user_id: record.user_id,
+            channel_id: record.channel_id,
+            message_id: record.message_id,
+            filename: record.filename,
+            content_type: record.content_type,
+            size: record.size as u64,
+            uploaded: record.uploaded as u64,
+            s3_upload_id: record.s3_upload_id,
+            key_version: record.key_version,
+            direct: record.direct,
+            upload: None,
+        }
+    }
+
+    /// The session's ID.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The ID of the user uploading the file.
+    pub const fn user_id(&self) -> Snowflake<User> {
+        self.user_id
+    }
+
+    /// The ID of the channel the file will be sent to.
+    pub const fn channel_id(&self) -> Snowflake<Channel> {
+        self.channel_id
+    }
+
+    /// The ID the message will be created with once the upload completes.
+    pub const fn message_id(&self) -> Snowflake<Message> {
+        self.message_id
+    }
+
+    /// The name of the file being uploaded.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// The MIME type of the file being uploaded.
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// The total size of the file in bytes.
+    pub const fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The number of bytes received so far.
+    pub const fn uploaded(&self) -> u64 {
+        self.uploaded
+    }
+
+    /// The ID of the backing S3 multipart upload, if S3 is configured.
+    pub fn s3_upload_id(&self) -> Option<&str> {
+        self.s3_upload_id.as_deref()
+    }
+
+    /// The version of the S3 key layout the file is uploaded under.
+    pub const fn key_version(&self) -> i16 {
+        self.key_version
+    }
+
+    /// Whether the file is uploaded to S3 directly through a presigned URL.
+    pub const fn is_direct(&self) -> bool {
+        self.direct
+    }
+
+    /// The key a direct session's file is uploaded under, until the session is completed.
+    pub fn s3_key(&self) -> String {
+        versioned_key(
+            KEYSPACE_VERSION,
+            ObjectClass::Uploads,
+            &format!("{}/{}", self.channel_id, self.id),
+        )
+    }
+
+    /// Set the request to upload a direct session's file with.
+    pub fn set_upload(&mut self, upload: PresignedUpload) {
+        self.upload = Some(upload);
+    }
+
+    /// Set the ID of the backing S3 multipart upload.
+    pub fn set_s3_upload_id(&mut self, upload_id: Option<String>) {
+        self.s3_upload_id = upload_id;
+    }
+
+    /// Whether all bytes of the file have been received.
+    pub const fn is_complete(&self) -> bool {
+        self.uploaded >= self.size
+    }
+
+    /// The upload progress as a percentage, rounded down.
+    pub const fn percentage(&self) -> u8 {
+        (self.uploaded * 100 / self.size) as u8
+    }
+
+    /// Validates that a part of the given length may be added to the upload.
+    ///
+    /// ## Arguments
+    ///
+    /// * `reserved` - The number of bytes taken by other parts, including parts that are still being uploaded.
+    /// * `len` - The length of the part.
+    /// * `followed` - Whether the part fills a gap left by a released part, and is thus followed by other parts.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the part overflows the declared size,
+    ///   or is too small while not being the final part.
+    pub fn validate_part(&self, reserved: u64, len: usize, followed: bool) -> Result<(), BuildError> {
+        let len = len as u64;
+
+        if len == 0 {
+            return Err(BuildError::ValidationError("Upload part cannot be empty".into()));
+        }
+        if reserved + len > self.size {
+            return Err(BuildError::ValidationError(
+                "Upload part exceeds the declared size of the file".into(),
+            ));
+        }
+        if (followed || reserved + len < self.size) && len < MIN_PART_SIZE as u64 {
+            return Err(BuildError::ValidationError(format!(
+                "Upload parts must be at least {MIN_PART_SIZE} bytes, except for the last one"
+            )));
+        }
+        Ok(())
+    }
+
+    /// The attachment the uploaded file will become once the session completes.
+    pub fn attachment(&self) -> PartialAttachment {
+        PartialAttachment::new(
+            0,
+            self.filename.clone(),
+            self.content_type.clone(),
+            self.channel_id,
+            self.message_id,
+        )
+        .with_key_version(self.key_version)
+    }
+}
+
+impl From<UploadSession> for Snowflake<UploadSession> {
+    fn from(session: UploadSession) -> Self {
+        session.id()
+    }
+}
+
+impl From<&UploadSession> for Snowflake<UploadSession> {
+    fn from(session: &UploadSession) -> Self {
+        session.id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::attachment::AttachmentLike;
+
+    fn session(size: u64, uploaded: u64) -> UploadSession {
+        UploadSession::from_record(UploadSessionRecord {
+            id: Snowflake::new(1),
+            user_id: Snowflake::new(2),
+            channel_id: Snowflake::new(3),
+            message_id: Snowflake::new(4),
+            filename: "video.mp4".into(),
+            content_type: "video/mp4".into(),
+            size: size as i64,
+            uploaded: uploaded as i64,
+            s3_upload_id: None,
+            key_version: KEYSPACE_VERSION,
+            direct: false,
+        })
+    }
+
+    #[test]
+    fn test_percentage() {
+        assert_eq!(session(200, 0).percentage(), 0);
+        assert_eq!(session(200, 99).percentage(), 49);
+        assert_eq!(session(200, 200).percentage(), 100);
+        assert!(session(200, 200).is_complete());
+        assert!(!session(200, 199).is_complete());
+    }
+
+    #[test]
+    fn test_validate_part() {
+        let size = (MIN_PART_SIZE * 2 + 10) as u64;
+
+        // Full-sized parts are accepted
+        assert!(session(size, 0).validate_part(0, MIN_PART_SIZE, false).is_ok());
+        // The last part may be smaller than the minimum
+        assert!(
+            session(size, 0)
+                .validate_part((MIN_PART_SIZE * 2) as u64, 10, false)
+                .is_ok()
+        );
+        // Non-final parts may not be smaller than the minimum
+        assert!(session(size, 0).validate_part(0, 10, false).is_err());
+        // Neither may parts filling a gap before other parts
+        assert!(
+            session(size, 0)
+                .validate_part((MIN_PART_SIZE * 2) as u64, 10, true)
+                .is_err()
+        );
+        // Parts may not overflow the declared size
+        assert!(
+            session(size, 0)
+                .validate_part((MIN_PART_SIZE * 2) as u64, 11, false)
+                .is_err()
+        );
+        assert!(session(size, 0).validate_part(0, 0, false).is_err());
+    }
+
+    #[test]
+    fn test_attachment() {
+        let attachment = session(10, 0).attachment();
+        assert_eq!(attachment.s3_key(), "v2/files/3/4/0/video.mp4");
+    }
+}
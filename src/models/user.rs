@@ -5,12 +5,14 @@ use chrono::prelude::*;
 use derive_builder::Builder;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
 
 use crate::app::Config;
+use crate::external::auth_provider::ExternalIdentity;
 use crate::gateway::Gateway;
 
 use super::{
-    avatar::{Avatar, FullAvatar, PartialAvatar, UserAvatar},
+    avatar::{Avatar, FullAvatar, PartialAvatar, UserAvatar, UserBanner},
     errors::BuildError,
     omittableoption::OmittableOption,
     request_payloads::{CreateUser, UpdateUser},
@@ -21,12 +23,25 @@ static USERNAME_REGEX: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r"^([a-z0-9]|[a-z0-9]+(?:[._][a-z0-9]+)*)$").expect("Failed to compile username regex")
 });
 
+/// Normalize a username to the form it is stored and compared in.
+///
+/// Usernames are unique regardless of case, so they are NFKC-normalized and lowercased.
+pub fn normalize_username(username: &str) -> String {
+    username.nfkc().flat_map(char::to_lowercase).collect()
+}
+
+/// Returns whether the display name is between 3 and 32 characters long.
+pub const fn is_valid_display_name(display_name: &str) -> bool {
+    display_name.len() >= 3 && display_name.len() <= 32
+}
+
 /// Represents the presence of a user.
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 #[repr(i16)]
 pub enum Presence {
     /// The user is currently active.
+    #[default]
     Online = 0,
     /// The user is idle or away from the keyboard.
     Away = 1,
@@ -47,18 +62,13 @@ impl From<i16> for Presence {
     }
 }
 
-impl Default for Presence {
-    fn default() -> Self {
-        Self::Online
-    }
-}
-
 /// Represents a user record stored in the database.
 pub struct UserRecord {
     pub id: Snowflake<User>,
     pub username: String,
     pub display_name: Option<String>,
     pub avatar_hash: Option<String>,
+    pub banner_hash: Option<String>,
     pub last_presence: i16,
 }
 
@@ -66,6 +76,7 @@ pub struct UserRecord {
 #[builder(setter(into), build_fn(error = "BuildError"))]
 pub struct User {
     /// The snowflake belonging to this user.
+    #[allow(clippy::use_self)] // Builder copies the field type verbatim
     id: Snowflake<User>,
     /// A user's username. This is unique to the user.
     username: String,
@@ -78,16 +89,22 @@ pub struct User {
     #[builder(default)]
     avatar: Option<Avatar<UserAvatar>>,
 
+    /// The user's profile banner hash.
+    #[serde(rename = "banner_hash")]
+    #[builder(default)]
+    banner: Option<Avatar<UserBanner>>,
+
     /// The last presence used by this user.
     /// This does not represent the user's actual presence, as that also depends on the gateway connection.
     #[serde(skip)]
     #[builder(default)]
     last_presence: Presence,
-    /// Is 'null' in all cases except when the user is sent in a `GUILD_CREATE` event.
-    /// This is the presence that is sent in payloads to clients.
-    #[serde(rename = "presence")]
-    #[builder(setter(skip), default)]
-    displayed_presence: Option<Presence>,
+    /// The presence that is sent in payloads to clients.
+    /// Only set for the user themselves and members of mutual guilds, see [`User::to_private`] and [`User::include_presence`].
+    /// Omitted entirely for clients that do not display presences, see [`User::without_presence`].
+    #[serde(rename = "presence", skip_serializing_if = "OmittableOption::is_omitted")]
+    #[builder(setter(skip), default = "OmittableOption::None")]
+    displayed_presence: OmittableOption<Presence>,
 }
 
 impl User {
@@ -102,8 +119,8 @@ impl User {
     }
 
     /// The user's creation date.
-    pub const fn created_at(&self) -> DateTime<Utc> {
-        self.id.created_at()
+    pub const fn created_at(&self, config: &Config) -> DateTime<Utc> {
+        self.id.created_at(config)
     }
 
     /// The user's username. This is unique to the user.
@@ -126,6 +143,11 @@ impl User {
         self.avatar.as_ref()
     }
 
+    /// The user's profile banner.
+    pub const fn banner(&self) -> Option<&Avatar<UserBanner>> {
+        self.banner.as_ref()
+    }
+
     /// The last known presence of the user.
     ///
     /// This does not represent the user's actual presence, as that also depends on the gateway connection.
@@ -133,15 +155,23 @@ impl User {
         &self.last_presence
     }
 
-    /// Retrieve the user's presence.
-    pub async fn presence(&self, gateway: &Gateway) -> &Presence {
-        if gateway.is_connected(self.id()).await {
-            &self.last_presence
-        } else {
-            &Presence::Offline
+    /// The presence included in the user's payload, if any.
+    pub const fn displayed_presence(&self) -> Option<Presence> {
+        match self.displayed_presence {
+            OmittableOption::Some(presence) => Some(presence),
+            OmittableOption::None | OmittableOption::Omitted => None,
         }
     }
 
+    /// Retrieve the user's presence, as shown to the given user.
+    /// Users who do not share their presence with the viewer appear offline.
+    pub async fn presence(&self, gateway: &Gateway, viewer: impl Into<Snowflake<Self>>) -> Presence {
+        gateway
+            .presence_of(self.id(), viewer)
+            .await
+            .unwrap_or(Presence::Offline)
+    }
+
     /// Create a new user from a payload.
     ///
     /// ## Parameters
@@ -159,14 +189,49 @@ impl User {
     pub fn from_payload(config: &Config, payload: &CreateUser) -> Result<Self, BuildError> {
         Ok(Self {
             id: Snowflake::gen_new(config),
-            username: Self::validate_username(&payload.username)?.to_string(),
+            username: Self::validate_username(&normalize_username(&payload.username))?.to_string(),
             display_name: None,
             avatar: None,
+            banner: None,
             last_presence: Presence::Online,
-            displayed_presence: None,
+            displayed_presence: OmittableOption::None,
         })
     }
 
+    /// Create a new user for an identity of an external authentication provider.
+    ///
+    /// The username suggested by the provider is used if it is valid, otherwise it is derived from the user's ID.
+    /// The display name is only taken over if it is valid.
+    ///
+    /// ## Parameters
+    ///
+    /// * `config` - The application configuration.
+    /// * `identity` - The identity asserted by the provider.
+    pub fn from_external_identity(config: &Config, identity: &ExternalIdentity) -> Self {
+        let id = Snowflake::gen_new(config);
+        let username = identity
+            .username
+            .as_deref()
+            .map(normalize_username)
+            .filter(|username| Self::validate_username(username).is_ok())
+            .unwrap_or_else(|| Self::fallback_username(id));
+
+        Self {
+            id,
+            username,
+            display_name: identity.display_name.clone().filter(|name| is_valid_display_name(name)),
+            avatar: None,
+            banner: None,
+            last_presence: Presence::Online,
+            displayed_presence: OmittableOption::None,
+        }
+    }
+
+    /// A username derived from the ID of the user, for users whose preferred username is unavailable.
+    pub fn fallback_username(id: Snowflake<Self>) -> String {
+        format!("user_{id}")
+    }
+
     /// Build a user object directly from a database record.
     pub fn from_record(record: UserRecord) -> Self {
         Self {
@@ -177,9 +242,14 @@ impl User {
                     PartialAvatar::<UserAvatar>::new(h, record.id).expect("Database should have valid avatar hash"),
                 )
             }),
+            banner: record.banner_hash.map(|h| {
+                Avatar::Partial(
+                    PartialAvatar::<UserBanner>::new(h, record.id).expect("Database should have valid banner hash"),
+                )
+            }),
             display_name: record.display_name,
             last_presence: Presence::from(record.last_presence),
-            displayed_presence: None,
+            displayed_presence: OmittableOption::None,
         }
     }
 
@@ -191,19 +261,19 @@ impl User {
     ///
     /// ## Returns
     ///
-    /// Whether the user's avatar was updated, requiring an upload to S3.
+    /// Whether the user's avatar or banner was updated, requiring an upload to S3.
     ///
     /// ## Errors
     ///
     /// * [`BuildError::ValidationError`] - If the new username is invalid.
-    /// * [`BuildError::ValidationError`] - If the new avatar data is invalid.
+    /// * [`BuildError::ValidationError`] - If the new avatar or banner data is invalid.
     ///
     /// ## Note
     ///
-    /// The avatar data still needs to be uploaded to S3.
+    /// The avatar and banner data still need to be uploaded to S3.
     pub fn update(&mut self, request: UpdateUser) -> Result<bool, BuildError> {
         if let Option::Some(username) = request.username {
-            self.set_username(username)?;
+            self.set_username(&username)?;
         }
 
         if let OmittableOption::Some(ref display_name) = request.display_name {
@@ -222,7 +292,7 @@ impl User {
             self.display_name = display_name;
         }
 
-        if let Ok(avatar) = request
+        let avatar_updated = if let Ok(avatar) = request
             .avatar
             .map(|uri| FullAvatar::from_data_uri(&*self, uri))
             .transpose()?
@@ -230,30 +300,77 @@ impl User {
             .try_into()
         {
             self.avatar = avatar;
-            return Ok(true);
+            true
+        } else {
+            false
+        };
+
+        let banner_updated = if let Ok(banner) = request
+            .banner
+            .map(|uri| FullAvatar::from_data_uri(&*self, uri))
+            .transpose()?
+            .map(Avatar::Full)
+            .try_into()
+        {
+            self.banner = banner;
+            true
+        } else {
+            false
+        };
+
+        Ok(avatar_updated || banner_updated)
+    }
+
+    /// The user as shown to themselves.
+    ///
+    /// Includes the presence they picked, which is shown even if they appear offline to others.
+    #[must_use]
+    pub fn to_private(&self) -> Self {
+        Self {
+            displayed_presence: OmittableOption::Some(self.last_presence),
+            ..self.clone()
+        }
+    }
+
+    /// The user as shown to anyone who does not share a guild with them.
+    ///
+    /// Only includes their public profile, their presence is left out.
+    #[must_use]
+    pub fn to_public(&self) -> Self {
+        Self {
+            displayed_presence: OmittableOption::None,
+            ..self.clone()
         }
+    }
 
-        Ok(false)
+    /// Transform this object to also include the user's presence, as shown to the given member of a mutual guild.
+    #[must_use]
+    pub async fn include_presence(self, gateway: &Gateway, viewer: impl Into<Snowflake<Self>>) -> Self {
+        let presence = self.presence(gateway, viewer).await;
+        Self {
+            displayed_presence: OmittableOption::Some(presence),
+            ..self
+        }
     }
 
-    /// Transform this object to also include the user's presence.
+    /// Transform this object to leave out the presence field entirely, for clients that do not display presences.
     #[must_use]
-    pub async fn include_presence(self, gateway: &Gateway) -> Self {
-        let presence = self.presence(gateway).await;
+    pub fn without_presence(self) -> Self {
         Self {
-            displayed_presence: Some(*presence),
+            displayed_presence: OmittableOption::Omitted,
             ..self
         }
     }
 
-    /// Validates and sets a new username for this user.
+    /// Validates, normalizes and sets a new username for this user.
     ///
     /// The username must be committed to the database for the change to take effect.
     ///
     /// ## Errors
     ///
     /// * [`BuilderError::ValidationError`] - If the username is invalid.
-    pub fn set_username(&mut self, username: String) -> Result<(), BuildError> {
+    pub fn set_username(&mut self, username: &str) -> Result<(), BuildError> {
+        let username = normalize_username(username);
         Self::validate_username(&username)?;
         self.username = username;
         Ok(())
@@ -305,6 +422,7 @@ impl From<&mut User> for Snowflake<User> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::{avatar::AvatarLike, data_uri::DataUri};
 
     // Helper to create a dummy User record.
     fn dummy_user() -> User {
@@ -313,6 +431,7 @@ mod tests {
             username: "initial".to_string(),
             display_name: None,
             avatar_hash: None,
+            banner_hash: None,
             last_presence: 0,
         };
         User::from_record(record)
@@ -345,7 +464,7 @@ mod tests {
     #[test]
     fn test_set_username_valid() {
         let mut user = dummy_user();
-        let res = user.set_username("new.valid".to_string());
+        let res = user.set_username("new.valid");
         assert!(res.is_ok());
         assert_eq!(user.username(), "new.valid");
     }
@@ -353,10 +472,20 @@ mod tests {
     #[test]
     fn test_set_username_invalid() {
         let mut user = dummy_user();
-        let res = user.set_username("NoUpper".to_string());
+        let res = user.set_username("no-dash");
         assert!(res.is_err());
     }
 
+    #[test]
+    fn test_set_username_normalizes() {
+        let mut user = dummy_user();
+        user.set_username("New.Valid").expect("username should be valid");
+        assert_eq!(user.username(), "new.valid");
+
+        // Fullwidth characters are folded by NFKC
+        assert_eq!(normalize_username("\u{FF21}lice"), "alice");
+    }
+
     #[test]
     fn test_from_record() {
         let record = UserRecord {
@@ -364,6 +493,7 @@ mod tests {
             username: "testuser".to_string(),
             display_name: Some("Test User".to_string()),
             avatar_hash: Some("abc123_png".to_string()),
+            banner_hash: Some("a_456_gif".to_string()),
             last_presence: 1,
         };
 
@@ -373,7 +503,76 @@ mod tests {
         assert_eq!(user.username(), "testuser");
         assert_eq!(user.display_name(), Some("Test User"));
         assert!(user.avatar().is_some());
+        assert!(user.banner().is_some_and(AvatarLike::is_animated));
         assert_eq!(*user.last_presence(), Presence::Away);
+
+        let payload = serde_json::to_value(&user).expect("user should serialize");
+        assert_eq!(payload["avatar_hash"], "abc123_png");
+        assert_eq!(payload["banner_hash"], "a_456_gif");
+    }
+
+    #[test]
+    fn test_projections() {
+        let user = User::from_record(UserRecord {
+            id: Snowflake::new(42),
+            username: "testuser".to_string(),
+            display_name: Some("Test User".to_string()),
+            avatar_hash: None,
+            banner_hash: None,
+            last_presence: Presence::Busy as i16,
+        });
+
+        // Users see the presence they picked
+        let private = serde_json::to_value(user.to_private()).expect("user should serialize");
+        assert_eq!(private["presence"], "BUSY");
+        assert_eq!(private["display_name"], "Test User");
+
+        let public = serde_json::to_value(user.to_private().to_public()).expect("user should serialize");
+        assert_eq!(public["presence"], serde_json::Value::Null);
+        assert_eq!(public["display_name"], "Test User");
+    }
+
+    #[test]
+    fn test_update_banner() {
+        let mut user = dummy_user();
+        // A GIF with two 1x1 frames
+        let mut gif = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff".to_vec();
+        for _ in 0..2 {
+            gif.extend_from_slice(b"\x2C\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02\x44\x01\x00");
+        }
+        gif.push(0x3B);
+
+        let request = UpdateUser {
+            username: None,
+            display_name: OmittableOption::Omitted,
+            avatar: OmittableOption::Omitted,
+            banner: OmittableOption::Some(DataUri::new(gif, mime::IMAGE_GIF)),
+        };
+        assert!(user.update(request).expect("banner should be valid"));
+        assert!(user.avatar().is_none());
+        let banner = user.banner().expect("user should have a banner");
+        assert!(banner.is_animated());
+        assert!(banner.avatar_hash().ends_with("_gif"));
+
+        let request = UpdateUser {
+            username: None,
+            display_name: OmittableOption::Omitted,
+            avatar: OmittableOption::Some(DataUri::new(
+                b"<svg/>".to_vec(),
+                "image/svg+xml".parse().expect("valid MIME"),
+            )),
+            banner: OmittableOption::None,
+        };
+        assert!(user.update(request).is_err());
+
+        let request = UpdateUser {
+            username: None,
+            display_name: OmittableOption::Omitted,
+            avatar: OmittableOption::Omitted,
+            banner: OmittableOption::None,
+        };
+        assert!(user.update(request).expect("removing the banner should succeed"));
+        assert!(user.banner().is_none());
     }
 
     #[test]
@@ -383,6 +582,7 @@ mod tests {
             username: None,
             display_name: OmittableOption::Omitted,
             avatar: OmittableOption::Omitted,
+            banner: OmittableOption::Omitted,
         };
 
         // Test valid display name
@@ -28,6 +28,7 @@ use crate::models::{
 ///
 /// * [`AuthError::InvalidCredentials`] - If the credentials are invalid.
 /// * [`AuthError::PasswordHash`] - If the password could not be hashed.
+#[allow(clippy::useless_let_if_seq)] // The dummy hash must be set up before the lookup
 pub async fn validate_credentials(app: App, credentials: Credentials) -> Result<Snowflake<User>, AuthError> {
     let mut user_id: Option<Snowflake<User>> = None;
     // We set up a dummy hash here so verify_password_hash is always run.
@@ -0,0 +1,166 @@
+use std::task::{Context, Poll};
+
+use axum::{
+    Json,
+    extract::{DefaultBodyLimit, Request},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use futures::future::BoxFuture;
+use serde_json::json;
+use tower::{Layer, Service};
+
+use crate::models::errors::BodyLimitExceeded;
+
+/// Limits the size of request bodies accepted by the wrapped routes.
+///
+/// Requests exceeding the limit are rejected with a `413 Payload Too Large` response
+/// that includes the applicable limit in bytes, so clients can validate payloads before sending them.
+///
+/// Limits applied closer to a route take precedence over limits applied to the router it is in.
+#[derive(Debug, Clone, Copy)]
+pub struct BodyLimitLayer {
+    limit: usize,
+}
+
+impl BodyLimitLayer {
+    /// Create a new body limit layer.
+    ///
+    /// ## Arguments
+    ///
+    /// * `limit` - The maximum size of request bodies, in bytes
+    pub const fn new(limit: usize) -> Self {
+        Self { limit }
+    }
+}
+
+impl<S> Layer<S> for BodyLimitLayer {
+    type Service = BodyLimitService<<DefaultBodyLimit as Layer<S>>::Service>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BodyLimitService {
+            inner: DefaultBodyLimit::max(self.limit).layer(inner),
+            limit: self.limit,
+        }
+    }
+}
+
+/// The service created by [`BodyLimitLayer`].
+#[derive(Debug, Clone)]
+pub struct BodyLimitService<S> {
+    inner: S,
+    limit: usize,
+}
+
+impl<S> Service<Request> for BodyLimitService<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let limit = self.limit;
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = response.await?;
+
+            if is_limit_rejection(&response) {
+                return Ok(payload_too_large(limit));
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// Returns whether the response was caused by the request body exceeding the limit.
+///
+/// Extractors reject oversized bodies with a plain-text response, while multipart errors
+/// are marked with [`BodyLimitExceeded`]. Other `413` responses, such as oversized avatars,
+/// are left untouched.
+fn is_limit_rejection(response: &Response) -> bool {
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE {
+        return false;
+    }
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|ct| ct.as_bytes().starts_with(b"application/json"));
+
+    !is_json || response.extensions().get::<BodyLimitExceeded>().is_some()
+}
+
+/// The response returned when a request body exceeds the limit.
+fn payload_too_large(limit: usize) -> Response {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Json(json!({
+            "error": format!("Payload Too Large: Request body must be {limit} bytes or smaller."),
+            "limit": limit,
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, routing::post};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn echo(body: String) -> String {
+        body
+    }
+
+    async fn send(router: Router, size: usize) -> (StatusCode, serde_json::Value) {
+        let response = router
+            .oneshot(
+                Request::post("/")
+                    .body(Body::from(vec![b'a'; size]))
+                    .expect("Failed to build request"),
+            )
+            .await
+            .expect("Infallible");
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("Failed to read body");
+
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_body_limit() {
+        let router = || Router::new().route("/", post(echo)).layer(BodyLimitLayer::new(16));
+
+        assert_eq!(send(router(), 16).await.0, StatusCode::OK);
+
+        let (status, body) = send(router(), 17).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["limit"], 16);
+    }
+
+    #[tokio::test]
+    async fn test_route_limit_takes_precedence() {
+        let router = || {
+            Router::new()
+                .route("/", post(echo).layer(BodyLimitLayer::new(32)))
+                .layer(BodyLimitLayer::new(16))
+        };
+
+        assert_eq!(send(router(), 32).await.0, StatusCode::OK);
+
+        let (status, body) = send(router(), 33).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["limit"], 32);
+    }
+}
@@ -0,0 +1,169 @@
+use axum::{
+    extract::{FromRequestParts, RawPathParams},
+    http::request::Parts,
+};
+
+use crate::{
+    app::App,
+    models::{
+        auth::Token,
+        channel::{Channel, ChannelLike},
+        errors::RESTError,
+        guild::Guild,
+        member::UserLike,
+        message::Message,
+        role::Permissions,
+        snowflake::Snowflake,
+        user::User,
+    },
+    rest::permissions,
+};
+
+/// The channel a request is scoped to, along with the user making the request.
+///
+/// Extracting it resolves the `channel_id` path parameter and ensures that the token-holder
+/// is a member of the channel's guild who may view the channel, or a recipient of the direct message channel,
+/// so routes cannot forget these checks.
+#[derive(Debug, Clone)]
+pub struct ChannelContext {
+    token: Token,
+    channel: Channel,
+    author: UserLike,
+}
+
+impl ChannelContext {
+    /// The token of the request, already validated.
+    pub const fn token(&self) -> &Token {
+        &self.token
+    }
+
+    /// The ID of the user making the request.
+    pub const fn user_id(&self) -> Snowflake<User> {
+        self.token.data().user_id()
+    }
+
+    /// The channel the request is scoped to.
+    pub const fn channel(&self) -> &Channel {
+        &self.channel
+    }
+
+    /// The ID of the channel the request is scoped to.
+    pub fn channel_id(&self) -> Snowflake<Channel> {
+        self.channel.id()
+    }
+
+    /// The ID of the guild the channel belongs to, or `None` for direct message channels.
+    pub fn guild_id(&self) -> Option<Snowflake<Guild>> {
+        self.channel.guild_id()
+    }
+
+    /// The user making the request, as a member of the channel's guild if it has one.
+    pub const fn author(&self) -> &UserLike {
+        &self.author
+    }
+
+    /// Consume the context, returning the channel and the user making the request.
+    pub fn into_parts(self) -> (Channel, UserLike) {
+        (self.channel, self.author)
+    }
+
+    /// Fetch the guild of the channel, ensuring that the member making the request has the required permissions.
+    ///
+    /// ## Arguments
+    ///
+    /// * `app` - The application state.
+    /// * `required` - The permissions the member must have.
+    /// * `denied` - The reason given to members who lack them.
+    ///
+    /// ## Errors
+    ///
+    /// * [`RESTError::NotFound`] - If the guild does not exist.
+    /// * [`RESTError::Forbidden`] - If the member lacks the required permissions, or the channel is a direct message channel.
+    pub async fn fetch_permitted_guild(
+        &self,
+        app: &App,
+        required: Permissions,
+        denied: &str,
+    ) -> Result<Guild, RESTError> {
+        let Some(guild_id) = self.guild_id() else {
+            return Err(RESTError::Forbidden(denied.into()));
+        };
+        permissions::fetch_permitted_guild(app, guild_id, self.user_id(), required, denied).await
+    }
+
+    /// The oldest message the member making the request may read,
+    /// if the guild hides the messages sent before members joined.
+    ///
+    /// ## Arguments
+    ///
+    /// * `app` - The application state.
+    ///
+    /// ## Errors
+    ///
+    /// * [`RESTError::NotFound`] - If the guild does not exist.
+    pub async fn history_floor(&self, app: &App) -> Result<Option<Snowflake<Message>>, RESTError> {
+        let (Some(guild_id), UserLike::Member(member)) = (self.guild_id(), &self.author) else {
+            return Ok(None);
+        };
+
+        let guild = app
+            .ops()
+            .guilds()
+            .fetch_guild(guild_id)
+            .await?
+            .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
+
+        Ok(guild
+            .hide_history_before_join()
+            .then(|| member.joined_at_snowflake(app.config.snowflake_epoch())))
+    }
+}
+
+/// Channel context extractor for axum.
+///
+/// Rejects the request with `404 Not Found` if the channel does not exist,
+/// and with `403 Forbidden` if the token-holder may not view it.
+impl FromRequestParts<App> for ChannelContext {
+    type Rejection = RESTError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &App) -> Result<Self, Self::Rejection> {
+        let token = Token::from_request_parts(parts, state).await?;
+
+        let params = RawPathParams::from_request_parts(parts, state)
+            .await
+            .map_err(|e| RESTError::InternalServerError(format!("Failed to read path parameters: {e}")))?;
+        let channel_id = params
+            .iter()
+            .find_map(|(key, value)| (key == "channel_id").then_some(value))
+            .ok_or_else(|| RESTError::InternalServerError("Route has no channel_id parameter".into()))?
+            .parse::<Snowflake<Channel>>()
+            .map_err(|_| RESTError::BadRequest("Invalid channel ID.".into()))?;
+
+        let channel = state
+            .ops()
+            .guilds()
+            .fetch_channel(channel_id)
+            .await?
+            .ok_or(RESTError::NotFound(
+                "Channel does not exist or is not available.".into(),
+            ))?;
+
+        let user_id = token.data().user_id();
+        let author = match channel.guild_id() {
+            Some(guild_id) => state
+                .ops()
+                .guilds()
+                .fetch_member(user_id, guild_id)
+                .await?
+                .filter(|m| m.can_view(channel.id()))
+                .map(UserLike::Member),
+            None if channel.recipient_ids().contains(&user_id) => {
+                state.ops().users().fetch_user(user_id).await?.map(UserLike::User)
+            }
+            None => None,
+        }
+        .ok_or(RESTError::Forbidden("Not permitted to access resource.".into()))?;
+
+        Ok(Self { token, channel, author })
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt::Write;
+
+use axum::{
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use axum_extra::{
+    TypedHeader,
+    headers::{ETag, IfNoneMatch},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::models::errors::RESTError;
+
+/// Compute a strong `ETag` from the serialized representation of a resource.
+///
+/// The tag is the first 128 bits of the SHA-256 digest of the body, hex-encoded and quoted.
+pub fn compute_etag(body: &[u8]) -> ETag {
+    let digest = Sha256::digest(body);
+    let mut tag = String::with_capacity(34);
+    tag.push('"');
+    for byte in &digest[..16] {
+        let _ = write!(tag, "{byte:02x}");
+    }
+    tag.push('"');
+    tag.parse().expect("Hex-encoded ETag should always be valid")
+}
+
+/// A JSON response that supports conditional requests.
+///
+/// The response carries an `ETag` header derived from its body. If the client
+/// already holds a matching representation (sent via `If-None-Match`),
+/// an empty `304 Not Modified` is returned instead.
+pub struct Conditional<T> {
+    value: T,
+    if_none_match: Option<IfNoneMatch>,
+}
+
+impl<T: Serialize> Conditional<T> {
+    /// Create a new conditional response.
+    ///
+    /// ## Arguments
+    ///
+    /// * `value` - The resource to respond with
+    /// * `if_none_match` - The `If-None-Match` header of the request, if any
+    pub fn new(value: T, if_none_match: Option<TypedHeader<IfNoneMatch>>) -> Self {
+        Self {
+            value,
+            if_none_match: if_none_match.map(|TypedHeader(h)| h),
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Conditional<T> {
+    fn into_response(self) -> Response {
+        let body = match serde_json::to_vec(&self.value) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("Failed to serialize response body: {e}");
+                return RESTError::InternalServerError("Failed to serialize response".into()).into_response();
+            }
+        };
+        let etag = compute_etag(&body);
+
+        if self
+            .if_none_match
+            .is_some_and(|header| !header.precondition_passes(&etag))
+        {
+            return (StatusCode::NOT_MODIFIED, TypedHeader(etag)).into_response();
+        }
+
+        (
+            TypedHeader(etag),
+            [(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())],
+            body,
+        )
+            .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_etag() {
+        let etag = compute_etag(b"{}");
+        assert_eq!(etag, compute_etag(b"{}"));
+        assert_ne!(etag, compute_etag(b"[]"));
+
+        let header: IfNoneMatch = etag.clone().into();
+        assert!(!header.precondition_passes(&etag));
+        assert!(header.precondition_passes(&compute_etag(b"[]")));
+    }
+}
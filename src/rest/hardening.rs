@@ -0,0 +1,175 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::{
+    HeaderMap, HeaderValue,
+    header::{CONTENT_TYPE, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS},
+};
+
+use crate::{
+    app::{App, HardeningConfig},
+    models::errors::RESTError,
+};
+
+/// Harden REST requests and responses according to [`HardeningConfig`].
+///
+/// Requests whose headers exceed [`HardeningConfig::max_header_size`] are rejected with `431` before they reach any route.
+/// Security headers are added to every response, including rejections, unless the route already set them.
+pub async fn harden(State(app): State<App>, request: Request, next: Next) -> Response {
+    let config = app.config.hardening();
+
+    let mut response = if header_size(request.headers()) > config.max_header_size() {
+        RESTError::HeadersTooLarge(config.max_header_size()).into_response()
+    } else {
+        next.run(request).await
+    };
+
+    if config.security_headers() {
+        add_security_headers(response.headers_mut(), config);
+    }
+    response
+}
+
+/// Reject requests with a suspicious `Content-Type` with `415`, unless [`HardeningConfig::strict_content_type`] is disabled.
+///
+/// Only applied to routes that take JSON or multipart bodies, routes that take raw file contents accept any `Content-Type`.
+pub async fn reject_suspicious_content_type(State(app): State<App>, request: Request, next: Next) -> Response {
+    if app.config.hardening().strict_content_type() && has_suspicious_content_type(request.headers()) {
+        return RESTError::UnsupportedMediaType("Request bodies must be JSON or multipart".into()).into_response();
+    }
+    next.run(request).await
+}
+
+/// The combined size of the names and values of the given headers, in bytes.
+fn header_size(headers: &HeaderMap) -> usize {
+    headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum()
+}
+
+/// Returns whether the `Content-Type` of a request is suspicious.
+///
+/// Browsers send form submissions and `text/plain` bodies to other origins without a CORS preflight,
+/// so no route accepts them. Malformed or repeated content types, and JSON in an encoding other than UTF-8,
+/// are rejected as well. Requests without a `Content-Type` are left to the route.
+fn has_suspicious_content_type(headers: &HeaderMap) -> bool {
+    let mut content_types = headers.get_all(CONTENT_TYPE).iter();
+    let Some(content_type) = content_types.next() else {
+        return false;
+    };
+    if content_types.next().is_some() {
+        return true;
+    }
+    let Ok(content_type) = content_type.to_str() else {
+        return true;
+    };
+
+    let mut parts = content_type.split(';');
+    let essence = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+    let Some((kind, subtype)) = essence.split_once('/') else {
+        return true;
+    };
+    if kind.is_empty() || subtype.is_empty() || kind == "text" || essence == "application/x-www-form-urlencoded" {
+        return true;
+    }
+
+    let is_json = subtype == "json" || subtype.ends_with("+json");
+    is_json
+        && parts.any(|param| {
+            param.split_once('=').is_some_and(|(name, value)| {
+                name.trim().eq_ignore_ascii_case("charset")
+                    && !value.trim().trim_matches('"').eq_ignore_ascii_case("utf-8")
+            })
+        })
+}
+
+/// Add the security headers to a response, keeping any the route already set.
+fn add_security_headers(headers: &mut HeaderMap, config: &HardeningConfig) {
+    headers
+        .entry(X_CONTENT_TYPE_OPTIONS)
+        .or_insert(HeaderValue::from_static("nosniff"));
+    headers
+        .entry(REFERRER_POLICY)
+        .or_insert(HeaderValue::from_static("no-referrer"));
+    headers
+        .entry(X_FRAME_OPTIONS)
+        .or_insert(HeaderValue::from_static("DENY"));
+    if let Some(max_age) = config.hsts_max_age() {
+        headers.entry(STRICT_TRANSPORT_SECURITY).or_insert(
+            HeaderValue::from_str(&format!("max-age={}", max_age.as_secs())).expect("max-age should be a valid header"),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn content_type(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(
+                CONTENT_TYPE,
+                HeaderValue::from_str(value).expect("value should be valid"),
+            );
+        }
+        headers
+    }
+
+    #[test]
+    fn test_suspicious_content_types() {
+        for accepted in [
+            &[][..],
+            &["application/json"],
+            &["application/json; charset=utf-8"],
+            &["application/json; charset=\"UTF-8\""],
+            &["application/merge-patch+json"],
+            &["multipart/form-data; boundary=boundary"],
+            &["application/octet-stream"],
+            &["image/png"],
+        ] {
+            assert!(!has_suspicious_content_type(&content_type(accepted)), "{accepted:?}");
+        }
+
+        for rejected in [
+            &["text/plain"][..],
+            &["Text/Plain; charset=utf-8"],
+            &["application/x-www-form-urlencoded"],
+            &["application/json; charset=latin1"],
+            &["application/json", "application/json"],
+            &["json"],
+            &["/json"],
+        ] {
+            assert!(has_suspicious_content_type(&content_type(rejected)), "{rejected:?}");
+        }
+    }
+
+    #[test]
+    fn test_security_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FRAME_OPTIONS, HeaderValue::from_static("SAMEORIGIN"));
+        add_security_headers(&mut headers, &HardeningConfig::default());
+
+        assert_eq!(headers[X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(headers[REFERRER_POLICY], "no-referrer");
+        assert_eq!(headers[X_FRAME_OPTIONS], "SAMEORIGIN");
+        assert!(!headers.contains_key(STRICT_TRANSPORT_SECURITY));
+
+        let config = HardeningConfig::new(true, Some(Duration::from_secs(60)), 1024, true);
+        add_security_headers(&mut headers, &config);
+        assert_eq!(headers[STRICT_TRANSPORT_SECURITY], "max-age=60");
+    }
+
+    #[test]
+    fn test_header_size() {
+        let mut headers = content_type(&["application/json"]);
+        assert_eq!(header_size(&headers), "content-type".len() + "application/json".len());
+        headers.insert("x-test", HeaderValue::from_static("a"));
+        assert_eq!(header_size(&headers), 28 + 7);
+    }
+}
@@ -0,0 +1,90 @@
+use axum::{
+    Router,
+    extract::Request,
+    response::{IntoResponse, Redirect, Response},
+};
+use http::{
+    StatusCode,
+    header::HOST,
+    uri::{Authority, PathAndQuery},
+};
+
+/// A router redirecting all plain HTTP requests to the same URL over HTTPS,
+/// served alongside the main router if `HTTP_REDIRECT_ADDR` is set.
+///
+/// ## Arguments
+///
+/// * `https_port` - The port HTTPS is served on, left out of redirects if it is the default port
+pub fn router(https_port: u16) -> Router {
+    Router::new().fallback(move |request: Request| async move { redirect(&request, https_port) })
+}
+
+/// Redirect a request to HTTPS, keeping its host, path and query.
+/// Requests without a valid `Host` header are rejected, as there is no URL to redirect them to.
+///
+/// ## Arguments
+///
+/// * `request` - The request to redirect
+/// * `https_port` - The port HTTPS is served on
+fn redirect(request: &Request, https_port: u16) -> Response {
+    let Some(authority) = request
+        .headers()
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok())
+    else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let host = authority.host();
+    let path = request.uri().path_and_query().map_or("/", PathAndQuery::as_str);
+    let location = if https_port == 443 {
+        format!("https://{host}{path}")
+    } else {
+        format!("https://{host}:{https_port}{path}")
+    };
+    // Permanent redirects keep the method and body, unlike 301
+    Redirect::permanent(&location).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http::header::LOCATION;
+
+    use super::*;
+
+    fn location(host: Option<&str>, uri: &str, https_port: u16) -> Option<String> {
+        let mut request = Request::builder().uri(uri);
+        if let Some(host) = host {
+            request = request.header(HOST, host);
+        }
+        let response = redirect(&request.body(Body::empty()).expect("request should build"), https_port);
+        if response.status() != StatusCode::PERMANENT_REDIRECT {
+            return None;
+        }
+        response
+            .headers()
+            .get(LOCATION)
+            .and_then(|l| l.to_str().ok())
+            .map(str::to_owned)
+    }
+
+    #[test]
+    fn test_redirect() {
+        assert_eq!(
+            location(Some("chat.example.com"), "/api/v1/users/@me?x=1", 443).as_deref(),
+            Some("https://chat.example.com/api/v1/users/@me?x=1")
+        );
+        assert_eq!(
+            location(Some("chat.example.com:80"), "/gateway/v1", 8443).as_deref(),
+            Some("https://chat.example.com:8443/gateway/v1")
+        );
+        assert_eq!(
+            location(Some("[::1]:8080"), "/", 443).as_deref(),
+            Some("https://[::1]/")
+        );
+        assert_eq!(location(None, "/", 443), None);
+        assert_eq!(location(Some("not a host"), "/", 443), None);
+    }
+}
@@ -0,0 +1,263 @@
+use axum::{
+    Json,
+    body::Body,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use axum_extra::{
+    TypedHeader,
+    headers::{ETag, HeaderMapExt, IfNoneMatch},
+};
+use serde::Deserialize;
+
+use crate::{
+    app::App,
+    external::s3::ObjectStream,
+    models::{
+        avatar::{AvatarKind, AvatarLike, PartialAvatar},
+        data_uri::DataUri,
+        direct_upload::DirectUpload,
+        errors::RESTError,
+        request_payloads::CreateDirectUpload,
+        snowflake::Snowflake,
+        user::User,
+    },
+};
+
+/// The `Cache-Control` policy of media anyone may fetch, such as avatars.
+///
+/// Media URLs are content-addressed, new content always gets a new URL,
+/// so shared caches such as a CDN may keep responses indefinitely.
+pub const PUBLIC_IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// The `Cache-Control` policy of media that requires authorization, such as attachments.
+///
+/// The content never changes, but only the client that fetched it may cache it.
+pub const PRIVATE_IMMUTABLE: &str = "private, max-age=31536000, immutable";
+
+/// Build a strong `ETag` from an identifier that changes whenever the content does.
+///
+/// ## Arguments
+///
+/// * `id` - The content address of the media, must not contain quotes.
+pub fn media_etag(id: &str) -> ETag {
+    format!("\"{id}\"")
+        .parse()
+        .expect("Media identifiers should always form a valid ETag")
+}
+
+/// Respond with `304 Not Modified` if the client already holds the media.
+///
+/// ## Arguments
+///
+/// * `etag` - The `ETag` of the media.
+/// * `if_none_match` - The `If-None-Match` header of the request, if any.
+/// * `cache_control` - The caching policy of the media.
+pub fn not_modified(
+    etag: &ETag,
+    if_none_match: Option<&TypedHeader<IfNoneMatch>>,
+    cache_control: &'static str,
+) -> Option<Response> {
+    if_none_match
+        .is_some_and(|TypedHeader(header)| !header.precondition_passes(etag))
+        .then(|| {
+            (
+                StatusCode::NOT_MODIFIED,
+                TypedHeader(etag.clone()),
+                [(header::CACHE_CONTROL, cache_control)],
+            )
+                .into_response()
+        })
+}
+
+/// Stream media from S3 to the client, with headers that let caches store it.
+///
+/// ## Arguments
+///
+/// * `object` - The object, or the requested range of it, to stream.
+/// * `content_type` - The MIME type of the media.
+/// * `etag` - The `ETag` of the media.
+/// * `cache_control` - The caching policy of the media.
+///
+/// ## Errors
+///
+/// * [`RESTError::InternalServerError`] - If the response could not be built.
+pub fn stream_media(
+    object: ObjectStream,
+    content_type: &str,
+    etag: ETag,
+    cache_control: &'static str,
+) -> Result<Response, RESTError> {
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CACHE_CONTROL, cache_control);
+
+    if let Some(len) = object.content_length {
+        response = response.header(header::CONTENT_LENGTH, len);
+    }
+    response = match object.content_range {
+        Some(content_range) => response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(header::CONTENT_RANGE, content_range),
+        None => response.status(StatusCode::OK),
+    };
+
+    let body = futures::stream::unfold(object.body, async |mut body| {
+        body.next().await.map(|chunk| (chunk, body))
+    });
+
+    let mut response = response
+        .body(Body::from_stream(body))
+        .map_err(|e| RESTError::InternalServerError(format!("Failed to build response: {e}")))?;
+    response.headers_mut().typed_insert(etag);
+    Ok(response)
+}
+
+/// The query parameters of avatar routes.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct AvatarQuery {
+    /// The size to serve the avatar at, one of [`AVATAR_SIZES`](crate::models::avatar::AVATAR_SIZES).
+    /// The original is served if omitted.
+    pub size: Option<u32>,
+}
+
+/// Serve an avatar to anyone, allowing shared caches to store it indefinitely.
+///
+/// Avatars are addressed by their hash, so a changed avatar is always served under a new URL.
+///
+/// ## Arguments
+///
+/// * `app` - The application state.
+/// * `holder` - The ID of the user or guild the avatar belongs to.
+/// * `avatar_hash` - The hash of the avatar, as included in the user or guild object.
+/// * `size` - The size to serve the avatar at, if not its original size.
+/// * `if_none_match` - The `If-None-Match` header of the request, if any.
+///
+/// ## Errors
+///
+/// * [`RESTError::BadRequest`] - If avatars of this kind are not available in the requested size.
+/// * [`RESTError::NotFound`] - If the avatar does not exist, or file storage is not available.
+/// * [`RESTError::App`] - If the S3 request fails.
+pub async fn serve_avatar<K: AvatarKind>(
+    app: &App,
+    holder: Snowflake<K::HolderType>,
+    avatar_hash: String,
+    size: Option<u32>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Result<Response, RESTError> {
+    let avatar = PartialAvatar::<K>::new(avatar_hash, holder)
+        .map_err(|_| RESTError::NotFound("Avatar does not exist.".into()))?;
+
+    let sizes = avatar.kind().sizes();
+    if size.is_some_and(|s| !sizes.contains(&s)) {
+        return Err(RESTError::BadRequest(format!(
+            "Size must be one of: {}",
+            sizes.iter().map(u32::to_string).collect::<Vec<_>>().join(", ")
+        )));
+    }
+
+    let etag = size.map_or_else(
+        || media_etag(avatar.avatar_hash()),
+        |size| media_etag(&format!("{}_{size}", avatar.avatar_hash())),
+    );
+    if let Some(response) = not_modified(&etag, if_none_match.as_ref(), PUBLIC_IMMUTABLE) {
+        return Ok(response);
+    }
+
+    let s3 = app
+        .s3()
+        .ok_or(RESTError::NotFound("File storage is not available.".into()))?;
+    let object = match size {
+        Some(size) => avatar.stream_sized(s3, size).await?,
+        None => avatar.stream(s3).await?,
+    };
+
+    stream_media(object, avatar.mime().as_ref(), etag, PUBLIC_IMMUTABLE)
+}
+
+/// Start a direct upload of an avatar, letting the client upload it to S3 without passing it through the API.
+///
+/// ## Arguments
+///
+/// * `app` - The application state.
+/// * `user` - The user uploading the avatar, already permitted to set it on its holder.
+/// * `holder` - The ID of the user or guild the avatar will be set on.
+/// * `payload` - The request payload describing the file.
+///
+/// ## Errors
+///
+/// * [`RESTError::App`] - If the file is not an image, direct uploads are unavailable, or a request fails.
+pub async fn create_avatar_upload<K: AvatarKind>(
+    app: &App,
+    user: Snowflake<User>,
+    holder: Snowflake<K::HolderType>,
+    payload: CreateDirectUpload,
+) -> Result<(StatusCode, Json<DirectUpload>), RESTError> {
+    let mut upload = DirectUpload::from_payload::<K>(&app.config, user, holder, payload)?;
+    app.ops().avatars().create_direct_upload(&mut upload).await?;
+
+    Ok((StatusCode::CREATED, Json(upload)))
+}
+
+/// Read back the file of a direct upload of an avatar, so that it can be set like an avatar sent through the API.
+///
+/// Once set, the upload should be removed with [`discard_avatar_upload`].
+///
+/// ## Arguments
+///
+/// * `app` - The application state.
+/// * `user` - The user that started the upload.
+/// * `holder` - The ID of the user or guild the avatar will be set on.
+/// * `upload_id` - The ID of the upload.
+///
+/// ## Errors
+///
+/// * [`RESTError::NotFound`] - If the upload does not exist, or was started for another holder or kind of avatar.
+/// * [`RESTError::App`] - If the file was not uploaded, does not match the upload, or a request fails.
+pub async fn read_avatar_upload<K: AvatarKind>(
+    app: &App,
+    user: Snowflake<User>,
+    holder: Snowflake<K::HolderType>,
+    upload_id: Snowflake<DirectUpload>,
+) -> Result<(DirectUpload, DataUri), RESTError> {
+    let upload = app
+        .ops()
+        .avatars()
+        .fetch_direct_upload(upload_id)
+        .await?
+        .filter(|u| u.user_id() == user && u.holder_id::<K>() == Some(holder))
+        .ok_or(RESTError::NotFound("Upload does not exist or has expired.".into()))?;
+
+    let uri = app.ops().avatars().read_direct_upload(&upload).await?;
+    Ok((upload, uri))
+}
+
+/// Remove a direct upload whose avatar was set.
+///
+/// Failures are only logged, as the avatar is already set, and stale uploads are discarded eventually.
+pub async fn discard_avatar_upload(app: &App, upload: &DirectUpload) {
+    if let Err(e) = app.ops().avatars().delete_direct_upload(upload).await {
+        tracing::warn!(error = %e, upload_id = %upload.id(), "Failed to remove finalized direct upload");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_not_modified() {
+        let etag = media_etag("1234_png");
+        let matching = TypedHeader(IfNoneMatch::from(etag.clone()));
+        let other = TypedHeader(IfNoneMatch::from(media_etag("5678_png")));
+
+        let response = not_modified(&etag, Some(&matching), PUBLIC_IMMUTABLE).expect("ETag matches");
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], "\"1234_png\"");
+        assert_eq!(response.headers()[header::CACHE_CONTROL], PUBLIC_IMMUTABLE);
+
+        assert!(not_modified(&etag, Some(&other), PUBLIC_IMMUTABLE).is_none());
+        assert!(not_modified(&etag, None, PUBLIC_IMMUTABLE).is_none());
+    }
+}
@@ -1,2 +1,11 @@
 pub mod auth;
+pub mod body_limit;
+pub mod channel_context;
+pub mod conditional;
+pub mod hardening;
+pub mod https_redirect;
+pub mod media;
+pub mod permissions;
+pub mod rate_limit;
+pub mod read_only;
 pub mod routes;
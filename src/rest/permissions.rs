@@ -0,0 +1,44 @@
+use crate::{
+    app::App,
+    models::{errors::RESTError, guild::Guild, role::Permissions, snowflake::Snowflake, user::User},
+};
+
+/// Fetch the guild with the given ID, ensuring that the user has the required permissions in it.
+///
+/// ## Arguments
+///
+/// * `app` - The application state.
+/// * `guild_id` - The ID of the guild.
+/// * `user_id` - The ID of the user making the request.
+/// * `required` - The permissions the user must have, see [`Permissions::allows`].
+/// * `denied` - The reason given to users who lack them.
+///
+/// ## Errors
+///
+/// * [`RESTError::NotFound`] - If the guild does not exist.
+/// * [`RESTError::Forbidden`] - If the user lacks the required permissions, or is not a member of the guild.
+pub async fn fetch_permitted_guild(
+    app: &App,
+    guild_id: Snowflake<Guild>,
+    user_id: Snowflake<User>,
+    required: Permissions,
+    denied: &str,
+) -> Result<Guild, RESTError> {
+    let guild = app
+        .ops()
+        .guilds()
+        .fetch_guild(guild_id)
+        .await?
+        .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
+
+    if !app
+        .ops()
+        .roles()
+        .fetch_permissions(&guild, user_id)
+        .await?
+        .allows(required)
+    {
+        return Err(RESTError::Forbidden(denied.into()));
+    }
+    Ok(guild)
+}
@@ -0,0 +1,421 @@
+use std::{collections::HashMap, hash::Hash, sync::Mutex, time::Duration};
+
+use axum::{
+    http::HeaderValue,
+    response::{IntoResponseParts, ResponseParts},
+};
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::models::{snowflake::Snowflake, user::User};
+
+/// The number of tracked keys after which idle buckets are pruned.
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// How many requests a key may make, and how long they may be queued for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateQuota {
+    requests: u32,
+    period: Duration,
+    burst: u32,
+    max_delay: Duration,
+}
+
+impl RateQuota {
+    /// Create a new quota, replenishing a number of requests every second.
+    ///
+    /// ## Arguments
+    ///
+    /// * `per_second` - The sustained number of requests per second, must not be 0.
+    /// * `burst` - The number of requests that can be made at once, must not be 0.
+    /// * `max_delay` - The longest a request is queued for before it is rejected instead.
+    pub const fn new(per_second: u32, burst: u32, max_delay: Duration) -> Self {
+        Self::per_period(per_second, Duration::from_secs(1), burst, max_delay)
+    }
+
+    /// Create a new quota, replenishing a number of requests every period.
+    /// Used for requests that are made far less often than once per second, such as signing up.
+    ///
+    /// ## Arguments
+    ///
+    /// * `requests` - The sustained number of requests per period, must not be 0.
+    /// * `period` - The period the requests are replenished over.
+    /// * `burst` - The number of requests that can be made at once, must not be 0.
+    /// * `max_delay` - The longest a request is queued for before it is rejected instead.
+    pub const fn per_period(requests: u32, period: Duration, burst: u32, max_delay: Duration) -> Self {
+        Self {
+            requests,
+            period,
+            burst,
+            max_delay,
+        }
+    }
+
+    /// The sustained number of requests per [`RateQuota::period`].
+    pub const fn requests(&self) -> u32 {
+        self.requests
+    }
+
+    /// The period requests are replenished over.
+    pub const fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// The number of requests that can be made at once.
+    pub const fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// The longest a request is queued for before it is rejected instead.
+    pub const fn max_delay(&self) -> Duration {
+        self.max_delay
+    }
+
+    /// The time it takes for a single request to be replenished.
+    fn interval(&self) -> Duration {
+        self.period / self.requests.max(1)
+    }
+}
+
+/// A rate limit applied to a single kind of request, tracked per user and per object the request is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitBucket {
+    /// Sending messages in a channel.
+    ChannelMessages,
+    /// Typing in a channel.
+    Typing,
+}
+
+impl RateLimitBucket {
+    /// The name of the bucket, as sent to clients.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::ChannelMessages => "channel_messages",
+            Self::Typing => "typing",
+        }
+    }
+}
+
+/// The state of a bucket after a request was admitted, sent to clients as response headers.
+///
+/// Grants of global limits are sent as `X-RateLimit-Global-*` headers,
+/// while grants of a [`RateLimitBucket`] are sent as `X-RateLimit-*` headers naming the bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateGrant {
+    /// The bucket the grant belongs to, or `None` for global limits.
+    bucket: Option<RateLimitBucket>,
+    /// The number of requests that can be made at once.
+    limit: u32,
+    /// The number of requests that can be made right now without being queued.
+    remaining: u32,
+    /// The time until the bucket is full again.
+    reset_after: Duration,
+    /// The time until the next request can be made without being queued.
+    replenish_after: Duration,
+    /// How long the request was queued for.
+    delay: Duration,
+}
+
+impl RateGrant {
+    /// The bucket the grant belongs to, or `None` for global limits.
+    pub const fn bucket(&self) -> Option<RateLimitBucket> {
+        self.bucket
+    }
+
+    /// The number of requests that can be made at once.
+    pub const fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    /// The number of requests that can be made right now without being queued.
+    pub const fn remaining(&self) -> u32 {
+        self.remaining
+    }
+
+    /// The time until the bucket is full again.
+    pub const fn reset_after(&self) -> Duration {
+        self.reset_after
+    }
+
+    /// The time until the next request can be made without being queued.
+    pub const fn replenish_after(&self) -> Duration {
+        self.replenish_after
+    }
+
+    /// How long the request was queued for.
+    pub const fn delay(&self) -> Duration {
+        self.delay
+    }
+}
+
+impl IntoResponseParts for RateGrant {
+    type Error = std::convert::Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        let headers = res.headers_mut();
+        if let Some(bucket) = self.bucket {
+            headers.insert("X-RateLimit-Bucket", HeaderValue::from_static(bucket.as_str()));
+        }
+        let [limit, remaining, reset_after] = if self.bucket.is_some() {
+            ["X-RateLimit-Limit", "X-RateLimit-Remaining", "X-RateLimit-Reset-After"]
+        } else {
+            [
+                "X-RateLimit-Global-Limit",
+                "X-RateLimit-Global-Remaining",
+                "X-RateLimit-Global-Reset-After",
+            ]
+        };
+        headers.insert(limit, HeaderValue::from(self.limit));
+        headers.insert(remaining, HeaderValue::from(self.remaining));
+        headers.insert(
+            reset_after,
+            HeaderValue::from_str(&format!("{:.3}", self.reset_after.as_secs_f64())).expect("Valid header value"),
+        );
+        Ok(res)
+    }
+}
+
+/// A token bucket shared by all requests made with the same key.
+///
+/// Requests exceeding the burst are not rejected outright, but queued until the bucket
+/// has replenished, so that clients sending many requests at once are smoothed out
+/// to the sustained rate. Requests that would have to wait longer than the quota's
+/// maximum delay are rejected.
+///
+/// Buckets are tracked as the time at which they will be full again (GCRA),
+/// so queued requests are admitted in the order they arrived.
+#[derive(Debug)]
+pub struct RateLimiter<K> {
+    /// The bucket the limiter enforces, or `None` for global limits.
+    bucket: Option<RateLimitBucket>,
+    quota: RateQuota,
+    /// The time at which the bucket of each key is full again.
+    buckets: Mutex<HashMap<K, Instant>>,
+}
+
+impl<K: Hash + Eq + Clone> RateLimiter<K> {
+    /// Create a new global rate limiter with the given quota for each key.
+    pub fn new(quota: RateQuota) -> Self {
+        Self {
+            bucket: None,
+            quota,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Create a new rate limiter enforcing a bucket, with the given quota for each key.
+    pub fn for_bucket(bucket: RateLimitBucket, quota: RateQuota) -> Self {
+        Self {
+            bucket: Some(bucket),
+            ..Self::new(quota)
+        }
+    }
+
+    /// The quota of each key.
+    pub const fn quota(&self) -> &RateQuota {
+        &self.quota
+    }
+
+    /// Reserve a request for the given key, without waiting.
+    ///
+    /// ## Returns
+    ///
+    /// The grant, including how long the request must wait before it may proceed.
+    ///
+    /// ## Errors
+    ///
+    /// The time after which the request should be retried,
+    /// if it would have to wait longer than the maximum delay.
+    fn reserve(&self, key: &K, now: Instant) -> Result<RateGrant, Duration> {
+        let interval = self.quota.interval();
+        let capacity = interval * self.quota.burst;
+        let mut buckets = self.buckets.lock().expect("Rate limiter poisoned");
+
+        let full_at = buckets.get(key).copied().filter(|t| *t > now).unwrap_or(now) + interval;
+        let delay = full_at.saturating_duration_since(now).saturating_sub(capacity);
+        if delay > self.quota.max_delay {
+            return Err(delay.saturating_sub(self.quota.max_delay));
+        }
+
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|_, t| *t > now);
+        }
+        buckets.insert(key.clone(), full_at);
+
+        let reset_after = full_at - now;
+        let used = reset_after.as_nanos().div_ceil(interval.as_nanos().max(1));
+        Ok(RateGrant {
+            bucket: self.bucket,
+            limit: self.quota.burst,
+            remaining: u32::try_from(u128::from(self.quota.burst).saturating_sub(used)).unwrap_or(0),
+            reset_after,
+            replenish_after: (reset_after + interval).saturating_sub(capacity),
+            delay,
+        })
+    }
+
+    /// Acquire a request for the given key, waiting until the bucket allows it.
+    ///
+    /// ## Errors
+    ///
+    /// The time after which the request should be retried,
+    /// if it would have to wait longer than the maximum delay.
+    pub async fn acquire(&self, key: &K) -> Result<RateGrant, Duration> {
+        let grant = self.reserve(key, Instant::now())?;
+        if !grant.delay.is_zero() {
+            tokio::time::sleep(grant.delay).await;
+        }
+        Ok(grant)
+    }
+}
+
+/// The key of a bucket: the user making the request, and the object the request is about.
+type ObjectKey = (Snowflake<User>, Snowflake<()>);
+
+/// The rate limiters of all [`RateLimitBucket`]s.
+///
+/// Both REST and gateway requests are limited through the registry,
+/// so that clients are told the same remaining budget regardless of how they made the request.
+#[derive(Debug)]
+pub struct RateLimitRegistry {
+    channel_messages: RateLimiter<ObjectKey>,
+    typing: RateLimiter<ObjectKey>,
+}
+
+impl RateLimitRegistry {
+    /// Create a new registry.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel_messages` - The quota of messages a user may send in a single channel.
+    /// * `typing` - The quota of typing indicators a user may send in a single channel.
+    pub fn new(channel_messages: RateQuota, typing: RateQuota) -> Self {
+        Self {
+            channel_messages: RateLimiter::for_bucket(RateLimitBucket::ChannelMessages, channel_messages),
+            typing: RateLimiter::for_bucket(RateLimitBucket::Typing, typing),
+        }
+    }
+
+    /// The limiter enforcing the given bucket.
+    pub const fn limiter(&self, bucket: RateLimitBucket) -> &RateLimiter<ObjectKey> {
+        match bucket {
+            RateLimitBucket::ChannelMessages => &self.channel_messages,
+            RateLimitBucket::Typing => &self.typing,
+        }
+    }
+
+    /// Acquire a request from a bucket, waiting until it allows it.
+    ///
+    /// ## Arguments
+    ///
+    /// * `bucket` - The bucket to acquire the request from.
+    /// * `user` - The user making the request.
+    /// * `object` - The object the request is about, such as a channel.
+    ///
+    /// ## Errors
+    ///
+    /// The time after which the request should be retried,
+    /// if it would have to wait longer than the maximum delay.
+    pub async fn acquire<T>(
+        &self,
+        bucket: RateLimitBucket,
+        user: Snowflake<User>,
+        object: Snowflake<T>,
+    ) -> Result<RateGrant, Duration> {
+        self.limiter(bucket).acquire(&(user, object.cast())).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::response::IntoResponse;
+
+    use super::*;
+
+    fn limiter() -> RateLimiter<u64> {
+        RateLimiter::new(RateQuota::new(2, 3, Duration::from_secs(1)))
+    }
+
+    #[test]
+    fn test_burst_then_queue() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        for remaining in [2, 1, 0] {
+            let grant = limiter.reserve(&1, now).expect("within burst");
+            assert_eq!(grant.delay, Duration::ZERO);
+            assert_eq!(grant.remaining, remaining);
+        }
+
+        // Exceeding the burst queues requests at the sustained rate
+        let grant = limiter.reserve(&1, now).expect("within max delay");
+        assert_eq!(grant.delay, Duration::from_millis(500));
+        let grant = limiter.reserve(&1, now).expect("within max delay");
+        assert_eq!(grant.delay, Duration::from_secs(1));
+
+        // Until the delay would exceed the maximum
+        assert_eq!(limiter.reserve(&1, now), Err(Duration::from_millis(500)));
+
+        // Other keys are unaffected
+        assert_eq!(limiter.reserve(&2, now).expect("new key").delay, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_grant_headers() {
+        let limiter = RateLimiter::for_bucket(RateLimitBucket::Typing, RateQuota::new(1, 2, Duration::ZERO));
+        let grant = limiter.reserve(&1, Instant::now()).expect("within burst");
+
+        let response = (grant, ()).into_response();
+        let headers = response.headers();
+        assert_eq!(headers["X-RateLimit-Bucket"], "typing");
+        assert_eq!(headers["X-RateLimit-Limit"], "2");
+        assert_eq!(headers["X-RateLimit-Remaining"], "1");
+        assert_eq!(headers["X-RateLimit-Reset-After"], "1.000");
+        assert!(!headers.contains_key("X-RateLimit-Global-Limit"));
+    }
+
+    #[test]
+    fn test_replenish() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            limiter.reserve(&1, now).expect("within burst");
+        }
+        let grant = limiter
+            .reserve(&1, now + Duration::from_millis(500))
+            .expect("replenished");
+        assert_eq!(grant.delay, Duration::ZERO);
+        assert_eq!(grant.remaining, 0);
+        assert_eq!(grant.replenish_after, Duration::from_millis(500));
+
+        let grant = limiter.reserve(&1, now + Duration::from_secs(10)).expect("full");
+        assert_eq!(grant.remaining, 2);
+    }
+
+    #[test]
+    fn test_per_period() {
+        let hour = Duration::from_secs(3600);
+        let limiter = RateLimiter::new(RateQuota::per_period(2, hour, 2, Duration::ZERO));
+        let now = Instant::now();
+
+        limiter.reserve(&1, now).expect("within burst");
+        limiter.reserve(&1, now).expect("within burst");
+        assert_eq!(limiter.reserve(&1, now), Err(hour / 2));
+
+        let grant = limiter.reserve(&1, now + hour / 2).expect("replenished");
+        assert_eq!(grant.remaining, 0);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_waits() {
+        let limiter = RateLimiter::new(RateQuota::new(20, 1, Duration::from_secs(1)));
+        let start = Instant::now();
+
+        limiter.acquire(&1).await.expect("within burst");
+        let grant = limiter.acquire(&1).await.expect("within max delay");
+        assert!(!grant.delay().is_zero());
+        assert!(start.elapsed() >= grant.delay());
+    }
+}
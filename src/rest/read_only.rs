@@ -0,0 +1,23 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::Method;
+
+use crate::{app::App, models::errors::RESTError};
+
+/// Reject requests that may modify data while the instance is read-only, see [`crate::app::read_only::ReadOnlyMode`].
+///
+/// Only `GET`, `HEAD` and `OPTIONS` requests are let through. Routes that must keep working,
+/// such as the one making the instance writable again, are merged in after this is applied.
+pub async fn reject_mutations(State(app): State<App>, request: Request, next: Next) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(request).await;
+    }
+
+    match app.read_only().get() {
+        Some(state) => RESTError::ReadOnly(state).into_response(),
+        None => next.run(request).await,
+    }
+}
@@ -0,0 +1,756 @@
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{delete, get, post, put},
+};
+use std::{collections::BTreeMap, time::Duration};
+
+use chrono::DateTime;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::{
+    app::{
+        App,
+        read_only::ReadOnlyState,
+        telemetry::{self, LogFilter, LogFilterError},
+    },
+    gateway::{DispatchRecord, EventMetrics, SendMode, TracedEvent},
+    models::{
+        auth::AdminToken,
+        errors::RESTError,
+        gateway_event::GatewayEvent,
+        guild::{Guild, GuildFeature},
+        registration_code::RegistrationCode,
+        report::{Report, ReportStatus},
+        request_payloads::{CreateRegistrationCode, EnableReadOnly, IssueStrike, ResolveReport, UpdateLogFilter},
+        snowflake::Snowflake,
+        standing::{Standing, Strike},
+        user::User,
+    },
+};
+
+#[derive(Deserialize, Debug, Clone)]
+struct FetchDispatchesQuery {
+    event: Option<String>,
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct FetchReportsQuery {
+    status: Option<ReportStatus>,
+    after: Option<Snowflake<Report>>,
+    limit: Option<u32>,
+}
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/snowflake/{snowflake}", get(decode_snowflake))
+        .route("/admin/channels/reconcile-stats", post(reconcile_channel_stats))
+        .route(
+            "/admin/guilds/{guild_id}/features/{feature}",
+            put(grant_guild_feature).delete(revoke_guild_feature),
+        )
+        .route("/admin/users/{user_id}/terminate", post(terminate_user))
+        .route("/admin/users/{user_id}/standing", get(fetch_user_standing))
+        .route("/admin/users/{user_id}/strikes", post(issue_strike))
+        .route("/admin/users/{user_id}/strikes/{strike_id}", delete(revoke_strike))
+        .route(
+            "/admin/registration-codes",
+            get(fetch_registration_codes).post(create_registration_code),
+        )
+        .route("/admin/registration-codes/{code}", delete(delete_registration_code))
+        .route("/admin/gateway/events/{event_id}", get(fetch_event_deliveries))
+        .route("/admin/gateway/dispatches", get(fetch_dispatches))
+        .route("/admin/gateway/metrics", get(fetch_gateway_metrics))
+        .route("/admin/reports", get(fetch_reports))
+        .route("/admin/reports/{report_id}", get(fetch_report))
+        .route("/admin/reports/{report_id}/claim", post(claim_report))
+        .route("/admin/reports/{report_id}/resolve", post(resolve_report))
+}
+
+/// Routes operating the instance itself, which keep working while the instance is read-only.
+pub fn get_operations_router() -> Router<App> {
+    Router::new()
+        .route(
+            "/admin/log-filter",
+            get(fetch_log_filter).put(update_log_filter).delete(reset_log_filter),
+        )
+        .route(
+            "/admin/read-only",
+            get(fetch_read_only).put(enable_read_only).delete(disable_read_only),
+        )
+}
+
+/// Decode a snowflake into its components, using the configured epoch.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `snowflake` - The snowflake to decode
+///
+/// ## Returns
+///
+/// * [`Value`] - A JSON response containing the components of the snowflake
+///
+/// ## Endpoint
+///
+/// GET `/snowflake/{snowflake}`
+async fn decode_snowflake(
+    Path(snowflake): Path<Snowflake<()>>,
+    State(app): State<App>,
+    _token: AdminToken,
+) -> Result<Json<Value>, RESTError> {
+    let timestamp = snowflake.timestamp_with_epoch(app.config.snowflake_epoch());
+    let created_at = DateTime::from_timestamp_millis(timestamp)
+        .ok_or_else(|| RESTError::BadRequest("Snowflake timestamp is out of range".into()))?;
+
+    Ok(Json(json!({
+        "id": snowflake,
+        "timestamp": timestamp,
+        "created_at": created_at,
+        "worker_id": snowflake.worker_id(),
+        "process_id": snowflake.process_id(),
+        "sequence": snowflake.sequence(),
+    })))
+}
+
+/// Recompute the message statistics of all channels.
+/// Used to backfill the statistics of existing channels, or to repair them if they have drifted.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Value`] - A JSON response containing the number of channels that were corrected
+///
+/// ## Endpoint
+///
+/// POST `/admin/channels/reconcile-stats`
+async fn reconcile_channel_stats(State(app): State<App>, _token: AdminToken) -> Result<Json<Value>, RESTError> {
+    let reconciled = app.ops().messages().reconcile_channel_stats().await?;
+
+    Ok(Json(json!({ "reconciled": reconciled })))
+}
+
+/// Grant a feature to a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `guild_id` - The ID of the guild to grant the feature to
+/// * `feature` - The feature to grant
+///
+/// ## Returns
+///
+/// * [`Guild`] - A JSON response containing the updated [`Guild`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildUpdate`] - Dispatched to the guild's members
+///
+/// ## Endpoint
+///
+/// PUT `/admin/guilds/{guild_id}/features/{feature}`
+async fn grant_guild_feature(
+    Path((guild_id, feature)): Path<(Snowflake<Guild>, GuildFeature)>,
+    State(app): State<App>,
+    _token: AdminToken,
+) -> Result<Json<Guild>, RESTError> {
+    set_guild_feature(&app, guild_id, feature, true).await
+}
+
+/// Revoke a feature from a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `guild_id` - The ID of the guild to revoke the feature from
+/// * `feature` - The feature to revoke
+///
+/// ## Returns
+///
+/// * [`Guild`] - A JSON response containing the updated [`Guild`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildUpdate`] - Dispatched to the guild's members
+///
+/// ## Endpoint
+///
+/// DELETE `/admin/guilds/{guild_id}/features/{feature}`
+async fn revoke_guild_feature(
+    Path((guild_id, feature)): Path<(Snowflake<Guild>, GuildFeature)>,
+    State(app): State<App>,
+    _token: AdminToken,
+) -> Result<Json<Guild>, RESTError> {
+    set_guild_feature(&app, guild_id, feature, false).await
+}
+
+/// Terminate a user's account, revoking its tokens and closing its gateway sessions.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `user_id` - The ID of the user to terminate
+///
+/// ## Returns
+///
+/// * [`Value`] - A JSON response containing the number of guilds the user was removed from
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::MemberRemove`] - Dispatched to each guild the user was removed from
+///
+/// ## Endpoint
+///
+/// POST `/admin/users/{user_id}/terminate`
+async fn terminate_user(
+    Path(user_id): Path<Snowflake<User>>,
+    State(app): State<App>,
+    _token: AdminToken,
+) -> Result<Json<Value>, RESTError> {
+    if app.config.is_admin(user_id) {
+        return Err(RESTError::Forbidden("Administrators cannot be terminated.".into()));
+    }
+
+    let removed = app.ops().terminate_user(user_id).await?;
+
+    Ok(Json(json!({ "removed_from": removed })))
+}
+
+/// Fetch the standing of a user, along with their active strikes and who issued them.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `user_id` - The ID of the user to fetch the standing of
+///
+/// ## Returns
+///
+/// * [`Standing`] - A JSON response containing the user's [`Standing`]
+///
+/// ## Endpoint
+///
+/// GET `/admin/users/{user_id}/standing`
+async fn fetch_user_standing(
+    Path(user_id): Path<Snowflake<User>>,
+    State(app): State<App>,
+    _token: AdminToken,
+) -> Result<Json<Standing>, RESTError> {
+    Ok(Json(app.ops().standing().fetch_standing(user_id).await?))
+}
+
+/// Issue a strike against a user. Its penalty escalates with the number of active strikes the user has.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `user_id` - The ID of the user to issue the strike against
+/// * `payload` - The [`IssueStrike`] payload, containing the reason for the strike
+///
+/// ## Returns
+///
+/// * [`Strike`] - A JSON response containing the issued [`Strike`]
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::StandingUpdate`] - To the user the strike was issued against
+///
+/// ## Endpoint
+///
+/// POST `/admin/users/{user_id}/strikes`
+async fn issue_strike(
+    Path(user_id): Path<Snowflake<User>>,
+    State(app): State<App>,
+    token: AdminToken,
+    Json(payload): Json<IssueStrike>,
+) -> Result<(StatusCode, Json<Strike>), RESTError> {
+    if app.config.is_admin(user_id) {
+        return Err(RESTError::Forbidden("Administrators cannot be issued strikes.".into()));
+    }
+
+    let mut strike = Strike::new(&app.config, user_id, Some(token.data().user_id()), &payload.reason)?;
+    app.ops().standing().issue_strike(&mut strike).await?;
+
+    Ok((StatusCode::CREATED, Json(strike)))
+}
+
+/// Revoke a strike, lifting any timeout or suspension it carries.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `user_id` - The ID of the user the strike was issued against
+/// * `strike_id` - The ID of the strike to revoke
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::StandingUpdate`] - To the user the strike was issued against
+///
+/// ## Endpoint
+///
+/// DELETE `/admin/users/{user_id}/strikes/{strike_id}`
+async fn revoke_strike(
+    Path((user_id, strike_id)): Path<(Snowflake<User>, Snowflake<Strike>)>,
+    State(app): State<App>,
+    _token: AdminToken,
+) -> Result<StatusCode, RESTError> {
+    app.ops().standing().revoke_strike(user_id, strike_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch all registration codes that were not revoked, newest first.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Vec<RegistrationCode>`] - A JSON response containing the registration codes
+///
+/// ## Endpoint
+///
+/// GET `/admin/registration-codes`
+async fn fetch_registration_codes(
+    State(app): State<App>,
+    _token: AdminToken,
+) -> Result<Json<Vec<RegistrationCode>>, RESTError> {
+    Ok(Json(app.ops().users().fetch_registration_codes().await?))
+}
+
+/// Create a registration code, letting users register an account if the instance requires a code.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `payload` - The `CreateRegistrationCode` payload, optionally limiting the uses of the code
+///
+/// ## Returns
+///
+/// * [`RegistrationCode`] - A JSON response containing the created [`RegistrationCode`]
+///
+/// ## Endpoint
+///
+/// POST `/admin/registration-codes`
+async fn create_registration_code(
+    State(app): State<App>,
+    token: AdminToken,
+    Json(payload): Json<CreateRegistrationCode>,
+) -> Result<(StatusCode, Json<RegistrationCode>), RESTError> {
+    let code = RegistrationCode::new(token.data().user_id(), payload.max_uses.map(Into::into));
+    app.ops().users().create_registration_code(&code).await?;
+
+    Ok((StatusCode::CREATED, Json(code)))
+}
+
+/// Revoke a registration code. Accounts already registered with it are not affected.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `code` - The code to revoke
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/admin/registration-codes/{code}`
+async fn delete_registration_code(
+    Path(code): Path<String>,
+    State(app): State<App>,
+    _token: AdminToken,
+) -> Result<StatusCode, RESTError> {
+    if !app.ops().users().delete_registration_code(&code).await? {
+        return Err(RESTError::NotFound("Registration code does not exist.".into()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch the reports filed by users, oldest first.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `status` - Only return reports with this status
+/// * `after` - Only return reports filed after the report with this ID
+/// * `limit` - The maximum number of reports to return, at most 100
+///
+/// ## Returns
+///
+/// * [`Vec<Report>`] - A JSON response containing the reports
+///
+/// ## Endpoint
+///
+/// GET `/admin/reports`
+async fn fetch_reports(
+    State(app): State<App>,
+    _token: AdminToken,
+    Query(query): Query<FetchReportsQuery>,
+) -> Result<Json<Vec<Report>>, RESTError> {
+    let reports = app
+        .ops()
+        .reports()
+        .fetch_reports(query.status, query.after, query.limit)
+        .await?;
+
+    Ok(Json(reports))
+}
+
+/// Fetch a single report.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `report_id` - The ID of the report
+///
+/// ## Returns
+///
+/// * [`Report`] - A JSON response containing the [`Report`]
+///
+/// ## Endpoint
+///
+/// GET `/admin/reports/{report_id}`
+async fn fetch_report(
+    Path(report_id): Path<Snowflake<Report>>,
+    State(app): State<App>,
+    _token: AdminToken,
+) -> Result<Json<Report>, RESTError> {
+    let report = app
+        .ops()
+        .reports()
+        .fetch_report(report_id)
+        .await?
+        .ok_or(RESTError::NotFound("Report does not exist.".into()))?;
+
+    Ok(Json(report))
+}
+
+/// Claim a report, letting other administrators know it is being looked into.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `report_id` - The ID of the report to claim
+///
+/// ## Returns
+///
+/// * [`Report`] - A JSON response containing the claimed [`Report`]
+///
+/// ## Endpoint
+///
+/// POST `/admin/reports/{report_id}/claim`
+async fn claim_report(
+    Path(report_id): Path<Snowflake<Report>>,
+    State(app): State<App>,
+    token: AdminToken,
+) -> Result<Json<Report>, RESTError> {
+    let report = app
+        .ops()
+        .reports()
+        .claim_report(report_id, token.data().user_id())
+        .await?;
+
+    Ok(Json(report))
+}
+
+/// Resolve a report, recording the action that was taken.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `report_id` - The ID of the report to resolve
+/// * `payload` - The `ResolveReport` payload, containing the action taken
+///
+/// ## Returns
+///
+/// * [`Report`] - A JSON response containing the resolved [`Report`]
+///
+/// ## Endpoint
+///
+/// POST `/admin/reports/{report_id}/resolve`
+async fn resolve_report(
+    Path(report_id): Path<Snowflake<Report>>,
+    State(app): State<App>,
+    token: AdminToken,
+    Json(payload): Json<ResolveReport>,
+) -> Result<Json<Report>, RESTError> {
+    let report = app
+        .ops()
+        .reports()
+        .resolve_report(report_id, token.data().user_id(), payload.action)
+        .await?;
+
+    tracing::info!(
+        user_id = %token.data().user_id(),
+        report_id = %report.id(),
+        action = ?payload.action,
+        "Report resolved"
+    );
+    Ok(Json(report))
+}
+
+async fn set_guild_feature(
+    app: &App,
+    guild_id: Snowflake<Guild>,
+    feature: GuildFeature,
+    enabled: bool,
+) -> Result<Json<Guild>, RESTError> {
+    let guild = app
+        .ops()
+        .guilds()
+        .set_guild_feature(guild_id, feature, enabled)
+        .await?
+        .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
+
+    app.events()
+        .dispatch(GatewayEvent::GuildUpdate(guild.clone()), SendMode::ToGuild(guild.id()));
+
+    Ok(Json(guild))
+}
+
+/// Fetch the log filter currently in effect.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Value`] - A JSON response containing the current and default filter directives
+///
+/// ## Endpoint
+///
+/// GET `/admin/log-filter`
+async fn fetch_log_filter(_token: AdminToken) -> Result<Json<Value>, RESTError> {
+    Ok(Json(log_filter_json(installed_log_filter()?)))
+}
+
+/// Change the log filter of this instance, for example to briefly enable debug logging for a single module.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `payload` - The `UpdateLogFilter` payload, containing the new directives
+///
+/// ## Returns
+///
+/// * [`Value`] - A JSON response containing the current and default filter directives
+///
+/// ## Endpoint
+///
+/// PUT `/admin/log-filter`
+async fn update_log_filter(token: AdminToken, Json(payload): Json<UpdateLogFilter>) -> Result<Json<Value>, RESTError> {
+    let filter = installed_log_filter()?;
+    let reset_after = payload.reset_after.map(|secs| Duration::from_secs(secs.get().into()));
+
+    filter.set(&payload.directives, reset_after).map_err(|e| match e {
+        LogFilterError::Invalid(e) => RESTError::BadRequest(format!("Invalid filter directives: {e}")),
+        LogFilterError::Reload(e) => RESTError::InternalServerError(e.to_string()),
+    })?;
+
+    tracing::warn!(
+        user_id = %token.data().user_id(),
+        directives = payload.directives,
+        reset_after = ?reset_after,
+        "Log filter changed"
+    );
+    Ok(Json(log_filter_json(filter)))
+}
+
+/// Reset the log filter of this instance to the one it started with.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Value`] - A JSON response containing the current and default filter directives
+///
+/// ## Endpoint
+///
+/// DELETE `/admin/log-filter`
+async fn reset_log_filter(token: AdminToken) -> Result<Json<Value>, RESTError> {
+    let filter = installed_log_filter()?;
+    filter
+        .reset()
+        .map_err(|e| RESTError::InternalServerError(e.to_string()))?;
+
+    tracing::warn!(user_id = %token.data().user_id(), "Log filter reset");
+    Ok(Json(log_filter_json(filter)))
+}
+
+/// Fetch the sessions a recently dispatched gateway event was sent to by this instance.
+/// Event IDs are logged when events are dispatched, along with the trace they originated from.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `event_id` - The ID of the event
+///
+/// ## Returns
+///
+/// * [`TracedEvent`] - A JSON response containing the event's trace and its deliveries
+///
+/// ## Endpoint
+///
+/// GET `/admin/gateway/events/{event_id}`
+async fn fetch_event_deliveries(
+    Path(event_id): Path<Uuid>,
+    State(app): State<App>,
+    _token: AdminToken,
+) -> Result<Json<TracedEvent>, RESTError> {
+    let event = app.gateway().deliveries().get(event_id).ok_or(RESTError::NotFound(
+        "Event was not dispatched by this instance, or is no longer retained.".into(),
+    ))?;
+
+    Ok(Json(event))
+}
+
+/// Fetch the most recent dispatches of gateway events by this instance, newest first.
+/// Only available if the instance keeps them, see `GATEWAY_DISPATCH_LOG_SIZE`.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `event` - Only return dispatches of events with this name
+/// * `limit` - The maximum number of dispatches to return, defaults to 100
+///
+/// ## Returns
+///
+/// * [`Vec<DispatchRecord>`] - A JSON response containing the dispatches
+///
+/// ## Endpoint
+///
+/// GET `/admin/gateway/dispatches`
+async fn fetch_dispatches(
+    State(app): State<App>,
+    _token: AdminToken,
+    Query(query): Query<FetchDispatchesQuery>,
+) -> Result<Json<Vec<DispatchRecord>>, RESTError> {
+    if app.config.gateway_dispatch_log_size() == 0 {
+        return Err(RESTError::NotFound(
+            "Dispatches are not kept by this instance, set GATEWAY_DISPATCH_LOG_SIZE to keep them.".into(),
+        ));
+    }
+    let limit = query.limit.map_or(100, |limit| limit as usize);
+
+    Ok(Json(app.gateway().dispatches().recent(query.event.as_deref(), limit)))
+}
+
+/// Fetch the counters of the gateway events dispatched by this instance since it started, per event name.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+///
+/// ## Returns
+///
+/// * [`BTreeMap<String, EventMetrics>`] - A JSON response containing the counters of each event
+///
+/// ## Endpoint
+///
+/// GET `/admin/gateway/metrics`
+async fn fetch_gateway_metrics(State(app): State<App>, _token: AdminToken) -> Json<BTreeMap<String, EventMetrics>> {
+    Json(app.gateway().metrics().snapshot())
+}
+
+/// Fetch whether this instance is read-only.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Value`] - A JSON response containing whether the instance is read-only, why and since when
+///
+/// ## Endpoint
+///
+/// GET `/admin/read-only`
+async fn fetch_read_only(State(app): State<App>, _token: AdminToken) -> Json<Value> {
+    Json(read_only_json(app.read_only().get().as_ref()))
+}
+
+/// Make this instance read-only, rejecting all requests that may modify data until it is made writable again.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+/// * `payload` - The `EnableReadOnly` payload, containing the reason shown to clients
+///
+/// ## Returns
+///
+/// * [`Value`] - A JSON response containing whether the instance is read-only, why and since when
+///
+/// ## Endpoint
+///
+/// PUT `/admin/read-only`
+async fn enable_read_only(
+    State(app): State<App>,
+    token: AdminToken,
+    Json(payload): Json<EnableReadOnly>,
+) -> Json<Value> {
+    let state = app.read_only().enable(payload.reason);
+
+    tracing::warn!(
+        user_id = %token.data().user_id(),
+        reason = state.reason(),
+        "Instance made read-only"
+    );
+    Json(read_only_json(Some(&state)))
+}
+
+/// Make this instance writable again.
+///
+/// ## Arguments
+///
+/// * `token` - The administrator's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Value`] - A JSON response containing whether the instance is read-only, why and since when
+///
+/// ## Endpoint
+///
+/// DELETE `/admin/read-only`
+async fn disable_read_only(State(app): State<App>, token: AdminToken) -> Json<Value> {
+    if app.read_only().disable() {
+        tracing::warn!(user_id = %token.data().user_id(), "Instance made writable");
+    }
+    Json(read_only_json(None))
+}
+
+fn read_only_json(state: Option<&ReadOnlyState>) -> Value {
+    json!({
+        "enabled": state.is_some(),
+        "reason": state.and_then(ReadOnlyState::reason),
+        "since": state.map(ReadOnlyState::since),
+    })
+}
+
+fn installed_log_filter() -> Result<&'static LogFilter, RESTError> {
+    telemetry::log_filter()
+        .ok_or_else(|| RESTError::InternalServerError("Log filter is not installed in this process".into()))
+}
+
+fn log_filter_json(filter: &LogFilter) -> Value {
+    json!({
+        "directives": filter.current(),
+        "default": filter.default_directives(),
+    })
+}
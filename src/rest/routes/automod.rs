@@ -0,0 +1,247 @@
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get, patch, post},
+};
+
+use crate::{
+    app::App,
+    models::{
+        auth::Token,
+        automod::{AutomodMatch, AutomodRule},
+        channel::ChannelLike,
+        errors::RESTError,
+        guild::Guild,
+        request_payloads::{CreateAutomodRule, TestAutomodRules, UpdateAutomodRule},
+        role::Permissions,
+        snowflake::Snowflake,
+        user::User,
+    },
+    rest::permissions::fetch_permitted_guild,
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/guilds/{guild_id}/automod/rules", get(fetch_rules).post(create_rule))
+        .route("/guilds/{guild_id}/automod/rules/test", post(test_rules))
+        .route(
+            "/guilds/{guild_id}/automod/rules/{rule_id}",
+            patch(update_rule).delete(delete_rule),
+        )
+        .route("/guilds/{guild_id}/automod/timeouts/{user_id}", delete(remove_timeout))
+}
+
+/// Fetch the auto-moderation rules of a guild, oldest first.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the rules of
+///
+/// ## Returns
+///
+/// * [`Vec<AutomodRule>`] - A JSON response containing the guild's [`AutomodRule`]s
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/automod/rules`
+async fn fetch_rules(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<AutomodRule>>, RESTError> {
+    fetch_moderated_guild(&app, guild_id, &token).await?;
+
+    Ok(Json(app.ops().automod().fetch_rules(guild_id).await?))
+}
+
+/// Create an auto-moderation rule in a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to create the rule in
+/// * `payload` - The [`CreateAutomodRule`] payload, containing the rule's trigger and actions
+///
+/// ## Returns
+///
+/// * [`AutomodRule`] - A JSON response containing the created [`AutomodRule`]
+///
+/// ## Endpoint
+///
+/// POST `/guilds/{guild_id}/automod/rules`
+async fn create_rule(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateAutomodRule>,
+) -> Result<(StatusCode, Json<AutomodRule>), RESTError> {
+    let guild = fetch_moderated_guild(&app, guild_id, &token).await?;
+
+    let rule = AutomodRule::from_payload(&app.config, &guild, payload)?;
+    check_alert_channel(&app, &rule).await?;
+    app.ops().automod().create_rule(&rule).await?;
+
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+/// Update an auto-moderation rule of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the rule belongs to
+/// * `rule_id` - The ID of the rule
+/// * `payload` - The [`UpdateAutomodRule`] payload, containing the fields to update
+///
+/// ## Returns
+///
+/// * [`AutomodRule`] - A JSON response containing the updated [`AutomodRule`]
+///
+/// ## Endpoint
+///
+/// PATCH `/guilds/{guild_id}/automod/rules/{rule_id}`
+async fn update_rule(
+    Path((guild_id, rule_id)): Path<(Snowflake<Guild>, Snowflake<AutomodRule>)>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateAutomodRule>,
+) -> Result<Json<AutomodRule>, RESTError> {
+    fetch_moderated_guild(&app, guild_id, &token).await?;
+
+    let mut rule = app
+        .ops()
+        .automod()
+        .fetch_rule(guild_id, rule_id)
+        .await?
+        .ok_or(RESTError::NotFound("Rule not found.".into()))?;
+    let previous_channel = rule.alert_channel_id();
+
+    rule.apply_update(payload)?;
+    if rule.alert_channel_id() != previous_channel {
+        check_alert_channel(&app, &rule).await?;
+    }
+    app.ops().automod().update_rule(&rule).await?;
+
+    Ok(Json(rule))
+}
+
+/// Delete an auto-moderation rule of a guild.
+/// Timeouts the rule already applied stay in force.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the rule belongs to
+/// * `rule_id` - The ID of the rule
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/guilds/{guild_id}/automod/rules/{rule_id}`
+async fn delete_rule(
+    Path((guild_id, rule_id)): Path<(Snowflake<Guild>, Snowflake<AutomodRule>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    fetch_moderated_guild(&app, guild_id, &token).await?;
+
+    if !app.ops().automod().delete_rule(guild_id, rule_id).await? {
+        return Err(RESTError::NotFound("Rule not found.".into()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Check a sample message against all auto-moderation rules of a guild, including disabled ones.
+/// None of the rules' actions are applied.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to test the rules of
+/// * `payload` - The [`TestAutomodRules`] payload, containing the sample message
+///
+/// ## Returns
+///
+/// * [`Vec<AutomodMatch>`] - A JSON response containing the rules that would fire, and why
+///
+/// ## Endpoint
+///
+/// POST `/guilds/{guild_id}/automod/rules/test`
+async fn test_rules(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<TestAutomodRules>,
+) -> Result<Json<Vec<AutomodMatch>>, RESTError> {
+    fetch_moderated_guild(&app, guild_id, &token).await?;
+
+    let matches = app
+        .ops()
+        .automod()
+        .test_rules(guild_id, payload.content.as_deref(), &payload.content_types)
+        .await?;
+
+    Ok(Json(matches))
+}
+
+/// Lift the auto-moderation timeout of a member, allowing them to send messages again.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the member is timed out in
+/// * `user_id` - The ID of the member
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/guilds/{guild_id}/automod/timeouts/{user_id}`
+async fn remove_timeout(
+    Path((guild_id, user_id)): Path<(Snowflake<Guild>, Snowflake<User>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    fetch_moderated_guild(&app, guild_id, &token).await?;
+
+    if !app.ops().automod().remove_timeout(guild_id, user_id).await? {
+        return Err(RESTError::NotFound("Member is not timed out.".into()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch the guild with the given ID, ensuring that the token-holder may moderate it.
+async fn fetch_moderated_guild(app: &App, guild_id: Snowflake<Guild>, token: &Token) -> Result<Guild, RESTError> {
+    fetch_permitted_guild(
+        app,
+        guild_id,
+        token.data().user_id(),
+        Permissions::MODERATE_GUILD,
+        "Not permitted to moderate this guild.",
+    )
+    .await
+}
+
+/// Ensure the channel a rule posts its alerts in belongs to the rule's guild.
+async fn check_alert_channel(app: &App, rule: &AutomodRule) -> Result<(), RESTError> {
+    let Some(channel_id) = rule.alert_channel_id() else {
+        return Ok(());
+    };
+
+    let channel = app.ops().guilds().fetch_channel(channel_id).await?;
+    if channel.is_none_or(|c| c.guild_id() != Some(rule.guild_id())) {
+        return Err(RESTError::BadRequest(
+            "The alert channel does not belong to this guild.".into(),
+        ));
+    }
+    Ok(())
+}
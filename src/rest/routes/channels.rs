@@ -1,25 +1,58 @@
+use std::time::Duration;
+
+use aws_sdk_s3::types::StorageClass;
 use axum::{
     Json, Router,
-    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
-    http::StatusCode,
-    routing::{delete, get, patch, post},
+    body::Body,
+    extract::{Multipart, Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::Response,
+    routing::{delete, get, patch, post, put},
 };
+use axum_extra::{TypedHeader, headers::IfNoneMatch};
+use bytes::Bytes;
+use chrono::{TimeDelta, Utc};
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::Instrument;
 
 use crate::{
-    app::App,
-    external::fcm::Notification,
+    app::{
+        App, Config, LimitedRoute,
+        ops::{MAX_MESSAGE_QUERY_LIMIT, MAX_UNREAD_COUNT},
+    },
+    external::{fcm::Notification, s3::KEYSPACE_VERSION},
     gateway::SendMode,
     models::{
+        attachment::{AttachmentLike, PartialAttachment},
         auth::Token,
+        byte_range::ByteRange,
         channel::{Channel, ChannelLike},
-        errors::RESTError,
-        gateway_event::GatewayEvent,
+        errors::{AppError, RESTError},
+        gateway_event::{GatewayEvent, TypingEntry},
+        guest_link::{DEFAULT_GUEST_ACCESS_DURATION, GuestLink, MAX_GUEST_ACCESS_DURATION},
+        guild::GuildFeature,
         member::UserLike,
         message::Message,
+        notification_level::NotificationOverride,
         omittableoption::OmittableOption,
-        request_payloads::UpdateMessage,
+        outbox::{OutboxEntry, PushedMessage},
+        request_payloads::{
+            CreateGuestLink, CreateMessage, CreateUploadSession, UpdateChannel, UpdateMessage,
+            UpdateNotificationOverride,
+        },
+        role::Permissions,
         snowflake::Snowflake,
+        standing::StrikePenalty,
+        upload_session::{MAX_PART_SIZE, UploadSession},
+        user::User,
+    },
+    rest::{
+        body_limit::BodyLimitLayer,
+        channel_context::ChannelContext,
+        media::{PRIVATE_IMMUTABLE, media_etag, not_modified, stream_media},
+        rate_limit::{RateGrant, RateLimitBucket},
     },
 };
 
@@ -31,30 +64,73 @@ struct FetchMessagesQuery {
     around: Option<Snowflake<Message>>,
 }
 
+/// The reason given to members who may not manage the guest links of a channel.
+const GUEST_LINKS_DENIED: &str = "Not permitted to manage guest links.";
+
+/// How long clients are asked to wait before requesting an attachment that is being restored from archive storage again.
+const ARCHIVE_RESTORE_RETRY_AFTER: Duration = Duration::from_secs(3600);
+
 /* let message_create_lim: SharedIDLimiter = Arc::new(RateLimiter::keyed(
     Quota::per_second(nonzero!(5u32)).allow_burst(nonzero!(5u32)),
 )); */
 
-pub fn get_router() -> Router<App> {
+pub fn get_router(config: &Config) -> Router<App> {
     Router::new()
         .route("/channels/{channel_id}", get(fetch_channel))
+        .route("/channels/{channel_id}", patch(update_channel))
         .route("/channels/{channel_id}", delete(delete_channel))
         .route(
             "/channels/{channel_id}/messages",
-            post(create_message).layer(DefaultBodyLimit::max(8 * 1024 * 1024 /* 8mb */)),
+            post(create_message).layer(BodyLimitLayer::new(
+                config.body_limits().get(LimitedRoute::CreateMessage),
+            )),
         )
         .route("/channels/{channel_id}/messages", get(fetch_messages))
+        .route("/channels/{channel_id}/messages/export", get(export_messages))
         .route("/channels/{channel_id}/messages/{message_id}", patch(update_message))
         .route("/channels/{channel_id}/messages/{message_id}", delete(delete_message))
         .route("/channels/{channel_id}/messages/{message_id}/ack", post(ack_message))
+        .route("/channels/{channel_id}/unread-count", get(fetch_unread_count))
+        .route("/channels/{channel_id}/typing", get(fetch_typing))
+        .route(
+            "/channels/{channel_id}/notification-override",
+            put(update_notification_override),
+        )
+        .route(
+            "/channels/{channel_id}/notification-override",
+            delete(remove_notification_override),
+        )
+        .route(
+            "/channels/{channel_id}/messages/{message_id}/attachments/{attachment_id}",
+            get(fetch_attachment),
+        )
+        .route("/channels/{channel_id}/uploads", post(create_upload_session))
+        .route("/channels/{channel_id}/uploads/{upload_id}", get(fetch_upload_session))
+        .route(
+            "/channels/{channel_id}/uploads/{upload_id}",
+            delete(abort_upload_session),
+        )
+        .route(
+            "/channels/{channel_id}/uploads/{upload_id}/complete",
+            post(complete_upload_session),
+        )
+        .route("/channels/{channel_id}/guest-links", get(fetch_guest_links))
+        .route("/channels/{channel_id}/guest-links", post(create_guest_link))
+}
+
+/// Get the channel routes whose request bodies are raw file contents, which accept any `Content-Type`.
+pub fn get_raw_body_router() -> Router<App> {
+    Router::new().route(
+        "/channels/{channel_id}/uploads/{upload_id}/parts",
+        post(upload_part).layer(BodyLimitLayer::new(MAX_PART_SIZE)),
+    )
 }
 
 /// Fetch a channel's data.
 ///
 /// ## Arguments
 ///
-/// * `token` - The user's session token, already validated
-/// * `channel_id` - The ID of the channel to fetch
+/// * `ctx` - The channel, and the user requesting it, already validated
 ///
 /// ## Returns
 ///
@@ -63,20 +139,51 @@ pub fn get_router() -> Router<App> {
 /// ## Endpoint
 ///
 /// GET `/channels/{channel_id}`
-async fn fetch_channel(
-    Path(channel_id): Path<Snowflake<Channel>>,
+async fn fetch_channel(ctx: ChannelContext) -> Json<Channel> {
+    Json(ctx.into_parts().0)
+}
+
+/// Update a channel's data.
+///
+/// ## Arguments
+///
+/// * `ctx` - The channel, and the user requesting it, already validated
+/// * `payload` - The [`UpdateChannel`] payload, containing the fields to update
+///
+/// ## Returns
+///
+/// * [`Channel`] - A JSON response containing the updated [`Channel`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::ChannelUpdate`] - To all members who can view the channel
+///
+/// ## Endpoint
+///
+/// PATCH `/channels/{channel_id}`
+async fn update_channel(
     State(app): State<App>,
-    token: Token,
+    ctx: ChannelContext,
+    Json(payload): Json<UpdateChannel>,
 ) -> Result<Json<Channel>, RESTError> {
-    let channel = app.ops().fetch_channel(channel_id).await.ok_or(RESTError::NotFound(
-        "Channel does not exist or is not available.".to_string(),
-    ))?;
+    let guild = ctx
+        .fetch_permitted_guild(&app, Permissions::MANAGE_CHANNELS, "Not permitted to update resource.")
+        .await?;
+    let channel = ctx.channel();
 
-    // Check if the user is in the channel's guild
-    app.ops()
-        .fetch_member(token.data().user_id(), channel.guild_id())
-        .await?
-        .ok_or(RESTError::Forbidden("Not permitted to view resource.".to_string()))?;
+    // Unlocking is always allowed, so guilds that lost the feature can clean up
+    if payload.locked == Some(true) && !channel.locked() && !guild.has_feature(GuildFeature::AnnouncementChannels) {
+        return Err(RESTError::Forbidden(
+            "Guild does not have the ANNOUNCEMENT_CHANNELS feature.".into(),
+        ));
+    }
+
+    let channel = payload.perform_request(&app, channel).await?;
+
+    app.events().dispatch(
+        GatewayEvent::ChannelUpdate(channel.clone()),
+        SendMode::ToGuild(guild.id()),
+    );
 
     Ok(Json(channel))
 }
@@ -85,8 +192,7 @@ async fn fetch_channel(
 ///
 /// ## Arguments
 ///
-/// * `token` - The user's session token, already validated
-/// * `channel_id` - The ID of the channel to delete
+/// * `ctx` - The channel, and the user requesting it, already validated
 ///
 /// ## Returns
 ///
@@ -99,29 +205,15 @@ async fn fetch_channel(
 /// ## Endpoint
 ///
 /// DELETE `/channels/{channel_id}`
-async fn delete_channel(
-    Path(channel_id): Path<Snowflake<Channel>>,
-    State(app): State<App>,
-    token: Token,
-) -> Result<StatusCode, RESTError> {
-    let channel = app.ops().fetch_channel(channel_id).await.ok_or(RESTError::NotFound(
-        "Channel does not exist or is not available.".into(),
-    ))?;
-
-    // Check guild owner_id
-    let guild = app
-        .ops()
-        .fetch_guild(channel.guild_id())
-        .await
-        .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
-
-    if guild.owner_id() != token.data().user_id() {
-        return Err(RESTError::NotFound("Not permitted to delete channel.".into()));
-    }
+async fn delete_channel(State(app): State<App>, ctx: ChannelContext) -> Result<StatusCode, RESTError> {
+    let guild = ctx
+        .fetch_permitted_guild(&app, Permissions::MANAGE_CHANNELS, "Not permitted to delete channel.")
+        .await?;
+    let (channel, _) = ctx.into_parts();
 
-    app.ops().delete_channel(&channel).await?;
+    app.ops().guilds().delete_channel(&channel).await?;
 
-    app.gateway()
+    app.events()
         .dispatch(GatewayEvent::ChannelRemove(channel), SendMode::ToGuild(guild.id()));
 
     Ok(StatusCode::NO_CONTENT)
@@ -131,7 +223,7 @@ async fn delete_channel(
 ///
 /// ## Arguments
 ///
-/// * `token` - The authorization token
+/// * `ctx` - The channel, and the user requesting it, already validated
 /// * `payload` - The multipart form data
 ///
 /// ## Returns
@@ -140,31 +232,61 @@ async fn delete_channel(
 ///
 /// ## Dispatches
 ///
-/// * [`GatewayEvent::MessageCreate`] - To all members who can view the channel
+/// * [`GatewayEvent::MessageCreate`] - To all users who can view the channel
 ///
 /// ## Endpoint
 ///
 /// POST `/channels/{channel_id}/messages`
 async fn create_message(
-    Path(channel_id): Path<Snowflake<Channel>>,
     State(app): State<App>,
-    token: Token,
+    ctx: ChannelContext,
     payload: Multipart,
-) -> Result<(StatusCode, Json<Message>), RESTError> {
-    let channel = app.ops().fetch_channel(channel_id).await.ok_or(RESTError::NotFound(
-        "Channel does not exist or is not available.".into(),
-    ))?;
+) -> Result<(StatusCode, MessageGrants, Json<Message>), RESTError> {
+    let (channel, author) = ctx.into_parts();
+    let channel_id = channel.id();
+    let author_id = author.id();
 
-    let member = app
-        .ops()
-        .fetch_member(token.data().user_id(), channel.guild_id())
-        .await?
-        .ok_or(RESTError::Forbidden("Not permitted to access resource.".into()))?;
+    if !app.ops().guilds().can_post_in(&channel, author_id).await? {
+        return Err(RESTError::Forbidden("Channel is locked.".into()));
+    }
+    app.ops()
+        .standing()
+        .ensure_unrestricted(author_id, StrikePenalty::Timeout)
+        .await?;
+    if let Some(guild_id) = channel.guild_id() {
+        app.ops().automod().ensure_not_timed_out(guild_id, author_id).await?;
+    }
+
+    let username = author.username().to_string();
+
+    let channel_grant = acquire_channel_message_budget(&app, author_id, channel_id).await?;
+    // Queue before the message is built, so that its ID reflects when it was actually sent
+    let bot_grant = acquire_bot_message_budget(&app, author_id).await?;
 
-    let username = member.user().username().to_string();
+    let mut message = Message::from_formdata(&app.config, author, channel_id, payload).await?;
 
-    let message = Message::from_formdata(&app.config, UserLike::Member(member), channel_id, payload).await?;
+    validate_content(&message)?;
+    app.ops()
+        .messages()
+        .resolve_channel_mentions(std::slice::from_mut(&mut message))
+        .await?;
+    app.ops().automod().moderate_message(&channel, &message).await?;
+
+    if message.content().is_none() && message.attachments().is_empty() {
+        return Err(RESTError::BadRequest(
+            "Message content or attachments must be provided.".into(),
+        ));
+    }
+
+    let outbox = announce_message(&channel, &username, &message);
+    app.ops().messages().commit_message_with(&message, &outbox).await?;
+
+    let message = message.strip_attachment_contents();
+    publish_message(&app, &channel, message, (bot_grant, channel_grant)).await
+}
 
+/// Ensure that the message's content, if any, is neither empty nor too long.
+fn validate_content(message: &Message) -> Result<(), RESTError> {
     if let Some(content) = message.content() {
         if content.is_empty() {
             return Err(RESTError::BadRequest("Message content cannot be empty.".into()));
@@ -172,25 +294,72 @@ async fn create_message(
             return Err(RESTError::BadRequest("Message content is too long.".into()));
         }
     }
+    Ok(())
+}
 
-    if message.content().is_none() && message.attachments().is_empty() {
-        return Err(RESTError::BadRequest(
-            "Message content or attachments must be provided.".into(),
-        ));
+/// Wait until the user may send another message, if they are a bot.
+///
+/// Bots share a single message rate across all channels. Messages exceeding it are queued,
+/// and only rejected if they would have to wait longer than the configured maximum delay.
+///
+/// ## Returns
+///
+/// The state of the bot's message rate, or `None` if the user is not a bot.
+///
+/// ## Errors
+///
+/// * [`RESTError::TooManyRequests`] - If the message would have to wait too long.
+async fn acquire_bot_message_budget(app: &App, user: Snowflake<User>) -> Result<Option<RateGrant>, RESTError> {
+    if !app.config.is_bot(user) {
+        return Ok(None);
     }
 
-    app.ops().commit_message(&message).await?;
-
-    let message = message.strip_attachment_contents();
-    let reply = Json(message.clone());
+    app.bot_message_limiter()
+        .acquire(&user)
+        .await
+        .map(Some)
+        .map_err(|retry_after| RESTError::TooManyRequests {
+            retry_after,
+            bucket: None,
+        })
+}
 
-    // Update read state for the user
+/// Acquire a message from the user's message rate in the channel.
+///
+/// ## Returns
+///
+/// The state of the user's message rate in the channel, or `None` if it is not limited.
+///
+/// ## Errors
+///
+/// * [`RESTError::TooManyRequests`] - If the user sent too many messages in the channel.
+async fn acquire_channel_message_budget(
+    app: &App,
+    user: Snowflake<User>,
+    channel: Snowflake<Channel>,
+) -> Result<Option<RateGrant>, RESTError> {
     app.ops()
-        .update_read_state(token.data().user_id(), channel.id(), message.id())
-        .await?;
+        .acquire_rate_limit(RateLimitBucket::ChannelMessages, user, channel)
+        .await
+        .map_err(|retry_after| RESTError::TooManyRequests {
+            retry_after,
+            bucket: Some(RateLimitBucket::ChannelMessages),
+        })
+}
 
-    let task_app = app.clone();
-    let guild_id = channel.guild_id();
+/// The state of the rates a message was sent under: the bot message rate if the author is a bot,
+/// and the author's message rate in the channel.
+type MessageGrants = (Option<RateGrant>, Option<RateGrant>);
+
+/// Build the outbox entries announcing a new message: the message itself, the unread badge of the users it mentions,
+/// and a push notification for inactive users who can view the channel.
+///
+/// ## Arguments
+///
+/// * `channel` - The channel the message is sent in
+/// * `username` - The username of the message's author, used in push notifications
+/// * `message` - The message to announce
+fn announce_message(channel: &Channel, username: &str, message: &Message) -> Vec<OutboxEntry> {
     let mut notif_body: String = message.content().unwrap_or("No content provided.").to_string();
 
     if notif_body.len() > 100 {
@@ -198,31 +367,86 @@ async fn create_message(
         notif_body.push_str("...");
     }
 
+    let title = match channel {
+        Channel::GuildText(_) => format!("@{} in #{}:", username, channel.name()),
+        Channel::DirectMessage(_) => format!("@{username}:"),
+    };
     let notif = Notification {
-        title: format!("@{} in #{}:", username, channel.name()),
+        title,
         body: notif_body,
     };
 
-    tokio::spawn(async move {
-        if let Err(e) = task_app
-            .ops()
-            .send_push_notif_to_inactives(guild_id, channel_id, notif)
-            .await
-        {
-            tracing::error!(
-                guild = %guild_id,
-                error = ?e,
-                "Failed to send push notification to inactives in guild",
-            );
-        }
-    });
+    let mut entries = vec![OutboxEntry::dispatch(
+        &GatewayEvent::MessageCreate(message.clone().strip_attachment_contents()),
+        channel.send_mode(),
+    )];
+    // Sent after the message, so that clients never show a badge for a message they did not receive yet
+    if !message.mentions().is_empty() {
+        entries.push(OutboxEntry::badge(message.id()));
+    }
+    entries.push(OutboxEntry::push(
+        channel.guild_id(),
+        channel.id(),
+        notif,
+        message.content().map(ToOwned::to_owned),
+        Some(PushedMessage {
+            message_id: message.id(),
+            channel_name: channel.name().to_string(),
+            author_name: message.author().map_or(username, UserLike::shown_name).to_string(),
+            mentions: message.mentions(),
+        }),
+    ));
+    entries
+}
+
+/// Update the author's read state and check a freshly committed message for watched keywords.
+/// The message itself was announced through the outbox when it was committed.
+///
+/// ## Arguments
+///
+/// * `app` - The application state
+/// * `channel` - The channel the message was sent in
+/// * `message` - The committed message, with attachment contents stripped
+/// * `grants` - The state of the author's message rates, returned as headers
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::KeywordAlert`] - To subscribed moderators, if the message contains watched keywords
+async fn publish_message(
+    app: &App,
+    channel: &Channel,
+    message: Message,
+    grants: MessageGrants,
+) -> Result<(StatusCode, MessageGrants, Json<Message>), RESTError> {
+    let reply = Json(message.clone());
 
-    app.gateway().dispatch(
-        GatewayEvent::MessageCreate(message),
-        SendMode::ToGuild(channel.guild_id()),
+    // Update read state for the author, sending a message also marks them as online if they were away
+    if let Some(author) = message.author() {
+        app.ops()
+            .notifications()
+            .update_read_state(author.id(), channel.id(), message.id())
+            .await?;
+        app.gateway().record_user_activity(author.id());
+    }
+
+    let task_app = app.clone();
+    let task_channel = channel.clone();
+
+    tokio::spawn(
+        async move {
+            if let Err(e) = task_app
+                .ops()
+                .guilds()
+                .dispatch_keyword_alerts(&task_channel, &message)
+                .await
+            {
+                tracing::error!(error = ?e, "Failed to dispatch keyword alerts");
+            }
+        }
+        .in_current_span(),
     );
 
-    Ok((StatusCode::CREATED, reply))
+    Ok((StatusCode::CREATED, grants, reply))
 }
 
 /// Update a message.
@@ -242,9 +466,9 @@ async fn create_message(
 ///
 /// PATCH `/channels/{channel_id}/messages/{message_id}`
 async fn update_message(
-    Path((channel_id, message_id)): Path<(Snowflake<Channel>, Snowflake<Message>)>,
+    Path((_, message_id)): Path<(Snowflake<Channel>, Snowflake<Message>)>,
     State(app): State<App>,
-    token: Token,
+    ctx: ChannelContext,
     Json(payload): Json<UpdateMessage>,
 ) -> Result<Json<Message>, RESTError> {
     if let OmittableOption::Some(ref content) = payload.content {
@@ -257,7 +481,8 @@ async fn update_message(
 
     let message = app
         .ops()
-        .fetch_message_in(channel_id, message_id)
+        .messages()
+        .fetch_message_in(ctx.channel_id(), message_id)
         .await?
         .ok_or(RESTError::NotFound(
             "Message does not exist or is not available.".into(),
@@ -267,27 +492,13 @@ async fn update_message(
         return Err(RESTError::BadRequest("Message content must be provided.".into()));
     }
 
-    let channel = app.ops().fetch_channel(channel_id).await.ok_or(RESTError::NotFound(
-        "Channel does not exist or is not available.".into(),
-    ))?;
-
-    let member = app
-        .ops()
-        .fetch_member(token.data().user_id(), channel.guild_id())
-        .await?
-        .ok_or(RESTError::Forbidden("Not permitted to access resource.".into()))?;
-
-    if member.user().id() != message.author().map_or(Snowflake::new(0), UserLike::id) {
+    if ctx.user_id() != message.author().map_or(Snowflake::new(0), UserLike::id) {
         return Err(RESTError::Forbidden("Not permitted to patch resource.".into()));
     }
 
     let msg = payload.perform_request(&app, message_id).await?;
 
-    let reply = Json(msg.clone());
-    app.gateway()
-        .dispatch(GatewayEvent::MessageUpdate(msg), SendMode::ToGuild(channel.guild_id()));
-
-    Ok(reply)
+    Ok(Json(msg))
 }
 
 /// Delete a message.
@@ -310,42 +521,26 @@ async fn update_message(
 ///
 /// DELETE `/channels/{channel_id}/messages/{message_id}`
 async fn delete_message(
-    Path((channel_id, message_id)): Path<(Snowflake<Channel>, Snowflake<Message>)>,
+    Path((_, message_id)): Path<(Snowflake<Channel>, Snowflake<Message>)>,
     State(app): State<App>,
-    token: Token,
+    ctx: ChannelContext,
 ) -> Result<StatusCode, RESTError> {
     let message = app
         .ops()
-        .fetch_message_in(channel_id, message_id)
+        .messages()
+        .fetch_message_in(ctx.channel_id(), message_id)
         .await?
         .ok_or(RESTError::NotFound(
             "Message does not exist or is not available.".into(),
         ))?;
 
-    let channel = app.ops().fetch_channel(channel_id).await.ok_or(RESTError::NotFound(
-        "Channel does not exist or is not available.".into(),
-    ))?;
-
-    let member = app
-        .ops()
-        .fetch_member(token.data().user_id(), channel.guild_id())
-        .await?
-        .ok_or(RESTError::Forbidden("Not permitted to access resource.".into()))?;
-
-    if member.user().id() != message.author().map_or(Snowflake::new(0), UserLike::id) {
-        return Err(RESTError::Forbidden("Not permitted to delete resource.".into()));
+    // Members may always delete their own messages
+    if ctx.user_id() != message.author().map_or(Snowflake::new(0), UserLike::id) {
+        ctx.fetch_permitted_guild(&app, Permissions::MANAGE_MESSAGES, "Not permitted to delete resource.")
+            .await?;
     }
 
-    app.ops().delete_message(channel_id, message).await?;
-
-    app.gateway().dispatch(
-        GatewayEvent::MessageRemove {
-            id: message_id,
-            channel_id,
-            guild_id: Some(channel.guild_id()),
-        },
-        SendMode::ToGuild(channel.guild_id()),
-    );
+    app.ops().messages().delete_message(ctx.channel_id(), message).await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -354,48 +549,187 @@ async fn delete_message(
 ///
 /// ## Arguments
 ///
-/// * `token` - The authorization token
-/// * `channel_id` - The ID of the channel to fetch messages from
+/// * `ctx` - The channel, and the user requesting it, already validated
 /// * `query` - The query parameters
 ///
 /// ## Returns
 ///
-/// * [`Vec<Message>`] - A JSON response containing a list of [`Message`] objects
+/// * [`Vec<Message>`] - A JSON response containing a list of [`Message`] objects,
+///   along with the applied and maximum limit in the `X-Message-Limit` and `X-Message-Limit-Max` headers
 ///
 /// ## Endpoint
 ///
 /// GET `/channels/{channel_id}/messages`
 async fn fetch_messages(
-    Path(channel_id): Path<Snowflake<Channel>>,
     State(app): State<App>,
-    token: Token,
+    ctx: ChannelContext,
     Query(query): Query<FetchMessagesQuery>,
-) -> Result<(StatusCode, Json<Vec<Message>>), RESTError> {
-    let channel = app.ops().fetch_channel(channel_id).await.ok_or(RESTError::NotFound(
-        "Channel does not exist or is not available.".into(),
-    ))?;
-
-    // Check if the user is in the channel's guild
-    app.ops()
-        .fetch_member(token.data().user_id(), channel.guild_id())
-        .await?
-        .ok_or(RESTError::Forbidden("Not permitted to access resource.".into()))?;
-
+) -> Result<(StatusCode, HeaderMap, Json<Vec<Message>>), RESTError> {
+    let limit = app.ops().messages().resolve_message_limit(query.limit)?;
+    let floor = ctx.history_floor(&app).await?;
     let messages = app
         .ops()
-        .fetch_messages_from(channel_id, query.limit, query.before, query.after, query.around)
+        .messages()
+        .fetch_messages_from(
+            ctx.channel_id(),
+            Some(limit),
+            query.before,
+            query.after,
+            query.around,
+            floor,
+        )
+        .await?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert("X-Message-Limit", HeaderValue::from(limit));
+    headers.insert("X-Message-Limit-Max", HeaderValue::from(MAX_MESSAGE_QUERY_LIMIT));
+    Ok((StatusCode::OK, headers, Json(messages)))
+}
+
+/// Export all messages of a channel, oldest first, as newline-delimited JSON.
+///
+/// The messages are streamed as they are read from the database, so the export is not subject to
+/// the pagination limit of [`fetch_messages`]. Only members with the `MANAGE_MESSAGES` permission may export channels.
+///
+/// ## Arguments
+///
+/// * `ctx` - The channel, and the user requesting it, already validated
+///
+/// ## Returns
+///
+/// * [`Response`] - An `application/x-ndjson` body with one [`Message`] object per line
+///
+/// ## Endpoint
+///
+/// GET `/channels/{channel_id}/messages/export`
+async fn export_messages(State(app): State<App>, ctx: ChannelContext) -> Result<Response, RESTError> {
+    ctx.fetch_permitted_guild(&app, Permissions::MANAGE_MESSAGES, "Not permitted to export channel.")
         .await?;
+    let channel_id = ctx.channel_id();
+
+    let body = app.ops().messages().export_messages(channel_id)?.map(|message| {
+        // Failing mid-stream aborts the response, so the client can tell that the export is incomplete
+        let mut line = serde_json::to_vec(&message?)?;
+        line.push(b'\n');
+        Ok::<_, AppError>(Bytes::from(line))
+    });
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"channel-{channel_id}.ndjson\""),
+        )
+        .body(Body::from_stream(body))
+        .map_err(|e| RESTError::InternalServerError(format!("Failed to build response: {e}")))
+}
+
+/// Download the contents of an attachment, or a part of them.
+///
+/// A single `bytes` range may be requested through the `Range` header, in which case only
+/// that part of the file is transferred. Unsupported ranges are ignored and the whole file is sent.
+///
+/// Attachments never change, so clients may cache them indefinitely and revalidate through `If-None-Match`.
+///
+/// Attachments served from archive storage carry an `X-Archived` header, as reading them may take longer.
+/// If an archived attachment has to be restored before it can be read, a restore is requested
+/// and `503 Service Unavailable` is returned with a `Retry-After` header.
+///
+/// ## Arguments
+///
+/// * `ctx` - The channel, and the user requesting it, already validated
+/// * `message_id` - The ID of the message the attachment belongs to
+/// * `attachment_id` - The ID of the attachment within the message
+/// * `headers` - The request headers, used to read the `Range` header
+/// * `if_none_match` - The `ETag`s the client already holds, if any
+///
+/// ## Returns
+///
+/// * [`Response`] - The file contents, with `206 Partial Content` if a range was requested,
+///   or `304 Not Modified` if the client already holds them
+///
+/// ## Endpoint
+///
+/// GET `/channels/{channel_id}/messages/{message_id}/attachments/{attachment_id}`
+async fn fetch_attachment(
+    Path((_, message_id, attachment_id)): Path<(Snowflake<Channel>, Snowflake<Message>, u8)>,
+    State(app): State<App>,
+    ctx: ChannelContext,
+    headers: HeaderMap,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Result<Response, RESTError> {
+    let floor = ctx.history_floor(&app).await?;
+    let attachment = PartialAttachment::fetch(app.clone(), attachment_id, message_id)
+        .await?
+        .filter(|a| a.channel_id() == ctx.channel_id() && floor.is_none_or(|floor| message_id >= floor))
+        .ok_or(RESTError::NotFound(
+            "Attachment does not exist or is not available.".into(),
+        ))?;
+
+    if attachment.quarantined() {
+        return Err(RESTError::Forbidden("Attachment has been quarantined.".into()));
+    }
+
+    // Attachments never change once sent, so their location is their content address
+    let etag = media_etag(&format!("{message_id}-{attachment_id}"));
+    if let Some(response) = not_modified(&etag, if_none_match.as_ref(), PRIVATE_IMMUTABLE) {
+        return Ok(response);
+    }
 
-    Ok((StatusCode::OK, Json(messages)))
+    let s3 = app
+        .s3()
+        .ok_or(RESTError::NotFound("File storage is not available.".into()))?;
+
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<ByteRange>().ok());
+
+    // Attachments stored under an older key layout are moved as they are accessed
+    let attachment = if attachment.key_version() < KEYSPACE_VERSION {
+        match app.ops().migrate_s3_keys(std::slice::from_ref(&attachment)).await {
+            Ok(_) => attachment.with_key_version(KEYSPACE_VERSION),
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to move attachment to the current key layout");
+                attachment
+            }
+        }
+    } else {
+        attachment
+    };
+
+    let object = match attachment.stream(s3, range).await {
+        Err(AppError::Archived(_)) => {
+            attachment.restore(s3).await?;
+            return Err(RESTError::ServiceUnavailable {
+                reason: "Attachment is being restored from archive storage.".into(),
+                retry_after: ARCHIVE_RESTORE_RETRY_AFTER,
+            });
+        }
+        object => object?,
+    };
+    let archived = object
+        .storage_class
+        .as_ref()
+        .is_some_and(|class| *class != StorageClass::Standard);
+
+    let mut response = stream_media(object, attachment.mime().as_ref(), etag, PRIVATE_IMMUTABLE)?;
+    if archived {
+        // Reads from archive storage are slower, let clients know to expect that
+        response
+            .headers_mut()
+            .insert("X-Archived", HeaderValue::from_static("true"));
+    }
+    Ok(response)
 }
 
 /// Acknowledge a message. This will update the user's read state for the message.
 ///
-/// Dispatches a [`GatewayEvent::MessageAck`] to all connected sessions of the user.
+/// Dispatches a [`GatewayEvent::MessageAck`] and a [`GatewayEvent::UnreadUpdate`] to all connected sessions of the user.
 ///
 /// ## Arguments
 ///
-/// * `channel_id` - The ID of the channel the message is in
+/// * `ctx` - The channel, and the user requesting it, already validated
 /// * `message_id` - The ID of the message to acknowledge
 ///
 /// ## Returns
@@ -406,28 +740,399 @@ async fn fetch_messages(
 ///
 /// POST `/channels/{channel_id}/messages/{message_id}/ack`
 async fn ack_message(
-    Path((channel_id, message_id)): Path<(Snowflake<Channel>, Snowflake<Message>)>,
+    Path((_, message_id)): Path<(Snowflake<Channel>, Snowflake<Message>)>,
     State(app): State<App>,
-    token: Token,
+    ctx: ChannelContext,
 ) -> Result<StatusCode, RESTError> {
-    let channel = app
+    let channel_id = ctx.channel_id();
+
+    app.ops()
+        .notifications()
+        .update_read_state(ctx.user_id(), channel_id, message_id)
+        .await?;
+
+    app.events().dispatch(
+        GatewayEvent::MessageAck { channel_id, message_id },
+        SendMode::ToUser(ctx.user_id()),
+    );
+
+    let badge = app.ops().notifications().fetch_badge(ctx.user_id()).await?;
+    app.events()
+        .dispatch(GatewayEvent::UnreadUpdate { badge }, SendMode::ToUser(ctx.user_id()));
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch the number of messages in a channel the user has not read yet.
+///
+/// ## Arguments
+///
+/// * `ctx` - The channel, and the user requesting it, already validated
+///
+/// ## Returns
+///
+/// * [`Value`] - The unread count, capped at [`MAX_UNREAD_COUNT`]
+///
+/// ## Endpoint
+///
+/// GET `/channels/{channel_id}/unread-count`
+async fn fetch_unread_count(State(app): State<App>, ctx: ChannelContext) -> Result<Json<Value>, RESTError> {
+    let count = app
         .ops()
-        .fetch_channel(channel_id)
-        .await
-        .ok_or(RESTError::NotFound("Channel not found.".into()))?;
+        .notifications()
+        .fetch_unread_count(ctx.user_id(), ctx.channel_id())
+        .await?;
+
+    Ok(Json(json!({
+        "channel_id": ctx.channel_id(),
+        "count": count,
+        "capped": count >= MAX_UNREAD_COUNT,
+    })))
+}
+
+/// Fetch the users currently typing in a channel, so that clients opening it can show an accurate indicator.
+///
+/// ## Arguments
+///
+/// * `ctx` - The channel, and the user requesting it, already validated
+///
+/// ## Returns
+///
+/// * [`TypingEntry`] - The users typing in the channel
+///
+/// ## Endpoint
+///
+/// GET `/channels/{channel_id}/typing`
+async fn fetch_typing(State(app): State<App>, ctx: ChannelContext) -> Json<TypingEntry> {
+    Json(TypingEntry {
+        channel_id: ctx.channel_id(),
+        user_ids: app.gateway().typing_in(ctx.channel_id()).await,
+    })
+}
+
+/// Set which messages of a channel wake the user's devices, taking precedence over the guild's default.
+///
+/// ## Arguments
+///
+/// * `ctx` - The channel, and the member setting the level, already validated
+/// * `payload` - The level to set
+///
+/// ## Returns
+///
+/// * [`NotificationOverride`] - The level set for the channel
+///
+/// ## Endpoint
+///
+/// PUT `/channels/{channel_id}/notification-override`
+async fn update_notification_override(
+    State(app): State<App>,
+    ctx: ChannelContext,
+    Json(payload): Json<UpdateNotificationOverride>,
+) -> Result<Json<NotificationOverride>, RESTError> {
+    let notification_override = app
+        .ops()
+        .notifications()
+        .set_notification_override(ctx.user_id(), ctx.channel_id(), payload.level)
+        .await?;
+
+    Ok(Json(notification_override))
+}
 
-    if !app.ops().has_member(channel.guild_id(), token.data().user_id()).await? {
-        return Err(RESTError::Forbidden("Not permitted to access resource.".into()));
+/// Remove the notification level the user set for a channel, falling back to the guild's default.
+///
+/// ## Arguments
+///
+/// * `ctx` - The channel, and the member removing the level, already validated
+///
+/// ## Endpoint
+///
+/// DELETE `/channels/{channel_id}/notification-override`
+async fn remove_notification_override(State(app): State<App>, ctx: ChannelContext) -> Result<StatusCode, RESTError> {
+    if !app
+        .ops()
+        .notifications()
+        .remove_notification_override(ctx.user_id(), ctx.channel_id())
+        .await?
+    {
+        return Err(RESTError::NotFound(
+            "No notification level was set for this channel.".into(),
+        ));
     }
 
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch an upload session belonging to the token-holder in the given channel.
+async fn fetch_own_upload_session(
+    app: &App,
+    token: &Token,
+    channel_id: Snowflake<Channel>,
+    upload_id: Snowflake<UploadSession>,
+) -> Result<UploadSession, RESTError> {
+    app.ops()
+        .messages()
+        .fetch_upload_session(upload_id)
+        .await?
+        .filter(|s| s.channel_id() == channel_id && s.user_id() == token.data().user_id())
+        .ok_or(RESTError::NotFound(
+            "Upload session does not exist or has expired.".into(),
+        ))
+}
+
+/// Start a resumable upload of a single attachment.
+///
+/// ## Arguments
+///
+/// * `ctx` - The channel, and the user requesting it, already validated
+/// * `payload` - The [`CreateUploadSession`] payload, describing the file
+///
+/// ## Returns
+///
+/// * [`UploadSession`] - A JSON response containing the created [`UploadSession`] object
+///
+/// ## Endpoint
+///
+/// POST `/channels/{channel_id}/uploads`
+async fn create_upload_session(
+    State(app): State<App>,
+    ctx: ChannelContext,
+    Json(payload): Json<CreateUploadSession>,
+) -> Result<(StatusCode, Json<UploadSession>), RESTError> {
+    if !app.ops().guilds().can_post_in(ctx.channel(), ctx.user_id()).await? {
+        return Err(RESTError::Forbidden("Channel is locked.".into()));
+    }
     app.ops()
-        .update_read_state(token.data().user_id(), channel_id, message_id)
+        .standing()
+        .ensure_unrestricted(ctx.user_id(), StrikePenalty::Timeout)
         .await?;
+    if let Some(guild_id) = ctx.guild_id() {
+        app.ops()
+            .automod()
+            .ensure_not_timed_out(guild_id, ctx.user_id())
+            .await?;
+    }
 
-    app.gateway().dispatch(
-        GatewayEvent::MessageAck { channel_id, message_id },
-        SendMode::ToUser(token.data().user_id()),
-    );
+    let mut session = UploadSession::from_payload(&app.config, ctx.user_id(), ctx.channel_id(), payload)?;
+    app.ops().messages().create_upload_session(&mut session).await?;
+
+    Ok((StatusCode::CREATED, Json(session)))
+}
+
+/// Fetch an upload session, to determine where to resume an interrupted upload from.
+/// Direct sessions come with a fresh request to upload the file with.
+///
+/// ## Arguments
+///
+/// * `channel_id` - The ID of the channel the file will be sent to
+/// * `upload_id` - The ID of the upload session
+/// * `token` - The authorization token
+///
+/// ## Returns
+///
+/// * [`UploadSession`] - A JSON response containing the [`UploadSession`] object
+///
+/// ## Endpoint
+///
+/// GET `/channels/{channel_id}/uploads/{upload_id}`
+async fn fetch_upload_session(
+    Path((channel_id, upload_id)): Path<(Snowflake<Channel>, Snowflake<UploadSession>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<UploadSession>, RESTError> {
+    let mut session = fetch_own_upload_session(&app, &token, channel_id, upload_id).await?;
+    if session.is_direct() {
+        app.ops().messages().presign_upload_session(&mut session).await?;
+    }
+
+    Ok(Json(session))
+}
+
+/// Upload the next part of a file. The request body is the raw contents of the part.
+///
+/// ## Arguments
+///
+/// * `channel_id` - The ID of the channel the file will be sent to
+/// * `upload_id` - The ID of the upload session
+/// * `token` - The authorization token
+/// * `data` - The contents of the part
+///
+/// ## Returns
+///
+/// * [`UploadSession`] - A JSON response containing the updated [`UploadSession`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::UploadProgress`] - To the uploader
+///
+/// ## Endpoint
+///
+/// POST `/channels/{channel_id}/uploads/{upload_id}/parts`
+async fn upload_part(
+    Path((channel_id, upload_id)): Path<(Snowflake<Channel>, Snowflake<UploadSession>)>,
+    State(app): State<App>,
+    token: Token,
+    data: Bytes,
+) -> Result<Json<UploadSession>, RESTError> {
+    let session = fetch_own_upload_session(&app, &token, channel_id, upload_id).await?;
+
+    Ok(Json(app.ops().messages().upload_session_part(&session, data).await?))
+}
+
+/// Complete an upload session, sending the uploaded file as a new message.
+///
+/// ## Arguments
+///
+/// * `ctx` - The channel, and the user requesting it, already validated
+/// * `upload_id` - The ID of the upload session
+/// * `payload` - The [`CreateMessage`] payload, containing the message content to send alongside the file
+///
+/// ## Returns
+///
+/// * [`Message`] - A JSON response containing the created [`Message`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::MessageCreate`] - To all users who can view the channel
+///
+/// ## Endpoint
+///
+/// POST `/channels/{channel_id}/uploads/{upload_id}/complete`
+async fn complete_upload_session(
+    Path((_, upload_id)): Path<(Snowflake<Channel>, Snowflake<UploadSession>)>,
+    State(app): State<App>,
+    ctx: ChannelContext,
+    Json(payload): Json<CreateMessage>,
+) -> Result<(StatusCode, MessageGrants, Json<Message>), RESTError> {
+    let session = fetch_own_upload_session(&app, ctx.token(), ctx.channel_id(), upload_id).await?;
+
+    let (channel, author) = ctx.into_parts();
+    let channel_id = channel.id();
+    let author_id = author.id();
+
+    // The channel may have been locked while the file was uploading
+    if !app.ops().guilds().can_post_in(&channel, author_id).await? {
+        return Err(RESTError::Forbidden("Channel is locked.".into()));
+    }
+    app.ops()
+        .standing()
+        .ensure_unrestricted(author_id, StrikePenalty::Timeout)
+        .await?;
+    if let Some(guild_id) = channel.guild_id() {
+        app.ops().automod().ensure_not_timed_out(guild_id, author_id).await?;
+    }
+
+    let username = author.username().to_string();
+
+    let channel_grant = acquire_channel_message_budget(&app, author_id, channel_id).await?;
+    let bot_grant = acquire_bot_message_budget(&app, author_id).await?;
+
+    let mut message = Message::from_upload_session(&app.config, author, &session, payload)?;
+
+    validate_content(&message)?;
+    app.ops()
+        .messages()
+        .resolve_channel_mentions(std::slice::from_mut(&mut message))
+        .await?;
+    app.ops().automod().moderate_message(&channel, &message).await?;
+
+    let outbox = announce_message(&channel, &username, &message);
+    app.ops()
+        .messages()
+        .complete_upload_session(&session, &message, &outbox)
+        .await?;
+
+    publish_message(&app, &channel, message, (bot_grant, channel_grant)).await
+}
+
+/// Abort an upload session, discarding all uploaded parts.
+///
+/// ## Arguments
+///
+/// * `channel_id` - The ID of the channel the file would have been sent to
+/// * `upload_id` - The ID of the upload session
+/// * `token` - The authorization token
+///
+/// ## Endpoint
+///
+/// DELETE `/channels/{channel_id}/uploads/{upload_id}`
+async fn abort_upload_session(
+    Path((channel_id, upload_id)): Path<(Snowflake<Channel>, Snowflake<UploadSession>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    let session = fetch_own_upload_session(&app, &token, channel_id, upload_id).await?;
+
+    app.ops().messages().abort_upload_session(&session).await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+/// Fetch all guest links to a channel that can still be used.
+///
+/// ## Arguments
+///
+/// * `ctx` - The channel, and the user requesting it, already validated
+///
+/// ## Returns
+///
+/// * [`Vec<GuestLink>`] - A JSON response containing the channel's [`GuestLink`] objects
+///
+/// ## Endpoint
+///
+/// GET `/channels/{channel_id}/guest-links`
+async fn fetch_guest_links(State(app): State<App>, ctx: ChannelContext) -> Result<Json<Vec<GuestLink>>, RESTError> {
+    ctx.fetch_permitted_guild(&app, Permissions::MANAGE_CHANNELS, GUEST_LINKS_DENIED)
+        .await?;
+
+    Ok(Json(app.ops().guilds().fetch_guest_links_for(ctx.channel_id()).await?))
+}
+
+/// Create a guest link, letting users join the channel's guild as a guest restricted to this channel.
+///
+/// ## Arguments
+///
+/// * `ctx` - The channel, and the user requesting it, already validated
+/// * `payload` - The [`CreateGuestLink`] payload
+///
+/// ## Returns
+///
+/// * [`GuestLink`] - A JSON response containing the created [`GuestLink`] object
+///
+/// ## Endpoint
+///
+/// POST `/channels/{channel_id}/guest-links`
+async fn create_guest_link(
+    State(app): State<App>,
+    ctx: ChannelContext,
+    Json(payload): Json<CreateGuestLink>,
+) -> Result<(StatusCode, Json<GuestLink>), RESTError> {
+    let guild = ctx
+        .fetch_permitted_guild(&app, Permissions::MANAGE_CHANNELS, GUEST_LINKS_DENIED)
+        .await?;
+
+    let access_duration = payload.access_duration.unwrap_or(DEFAULT_GUEST_ACCESS_DURATION);
+    if !(1..=MAX_GUEST_ACCESS_DURATION).contains(&access_duration) {
+        return Err(RESTError::BadRequest(format!(
+            "Access duration must be between 1 and {MAX_GUEST_ACCESS_DURATION} seconds."
+        )));
+    }
+    if payload.max_age == Some(0) {
+        return Err(RESTError::BadRequest("Max age must be positive.".into()));
+    }
+
+    let expires_at = payload
+        .max_age
+        .map(|max_age| Utc::now() + TimeDelta::seconds(i64::from(max_age)));
+
+    let link = GuestLink::new(
+        &guild,
+        ctx.channel(),
+        ctx.user_id(),
+        payload.can_post,
+        access_duration,
+        expires_at,
+    );
+    app.ops().guilds().create_guest_link(&link).await?;
+
+    Ok((StatusCode::CREATED, Json(link)))
+}
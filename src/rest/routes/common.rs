@@ -1,19 +1,39 @@
 use std::time::Duration;
 
-use axum::{Json, Router, extract::State, routing::get};
+use axum::{Json, Router, extract::State, middleware, routing::get};
 use http::{Method, header};
 use serde_json::{Value, json};
 use tower_http::cors::{Any, CorsLayer};
 
-use crate::app::App;
+use crate::{
+    app::{App, LimitedRoute},
+    models::upload_session::{MAX_PART_SIZE, MAX_UPLOAD_SIZE},
+    rest::{
+        body_limit::BodyLimitLayer,
+        hardening::{harden, reject_suspicious_content_type},
+        read_only::reject_mutations,
+    },
+};
 
-use super::channels::get_router as get_channel_router;
+use super::admin::{get_operations_router, get_router as get_admin_router};
+use super::automod::get_router as get_automod_router;
+use super::channels::{get_raw_body_router as get_raw_channel_router, get_router as get_channel_router};
+use super::guild_events::get_router as get_guild_event_router;
 use super::guilds::get_router as get_guild_router;
+use super::invites::get_router as get_invite_router;
+use super::message_links::get_router as get_message_link_router;
 use super::prefs::get_router as get_prefs_router;
+use super::reports::get_router as get_report_router;
+use super::roles::get_router as get_role_router;
 use super::users::get_router as get_user_router;
 
-/// Get all routes for the REST API. Includes CORS.
-pub fn get_router() -> Router<App> {
+/// Get all routes for the REST API. Includes CORS, request body limits, request hardening and the read-only mode.
+///
+/// ## Arguments
+///
+/// * `app` - The application state, used to determine body limits, hardening and whether the instance is read-only
+pub fn get_router(app: &App) -> Router<App> {
+    let config = &app.config;
     // https://javascript.info/fetch-crossorigin
     // https://developer.mozilla.org/en-US/docs/Web/HTTP/CORS
     let cors = CorsLayer::new()
@@ -35,16 +55,77 @@ pub fn get_router() -> Router<App> {
         ])
         .max_age(Duration::from_secs(3600));
 
-    get_channel_router()
-        .merge(get_guild_router())
-        .merge(get_user_router())
+    get_channel_router(config)
+        .merge(get_guild_router(config))
+        .merge(get_guild_event_router())
+        .merge(get_role_router())
+        .merge(get_automod_router())
+        .merge(get_invite_router())
+        .merge(get_message_link_router())
+        .merge(get_user_router(config))
         .merge(get_prefs_router())
+        .merge(get_report_router())
+        .merge(get_admin_router())
         .route("/", get(get_api_root))
+        .route("/instance", get(get_instance_info))
+        .route_layer(middleware::from_fn_with_state(
+            app.clone(),
+            reject_suspicious_content_type,
+        ))
+        // Raw file contents may be of any content type, so they are merged after the content type check
+        .merge(get_raw_channel_router())
+        .route_layer(middleware::from_fn_with_state(app.clone(), reject_mutations))
+        .merge(get_operations_router().route_layer(middleware::from_fn_with_state(
+            app.clone(),
+            reject_suspicious_content_type,
+        )))
+        .layer(BodyLimitLayer::new(config.body_limits().default_limit()))
         .layer(cors)
+        // Outermost, so that rejections by the other layers get security headers as well
+        .layer(middleware::from_fn_with_state(app.clone(), harden))
 }
 
 async fn get_api_root(State(app): State<App>) -> Json<Value> {
+    let auth_providers = app
+        .auth_providers()
+        .iter()
+        .map(|provider| provider.info())
+        .collect::<Vec<_>>();
+
+    Json(json!({
+        "capabilities": app.ops().get_capabilities(),
+        "auth_providers": auth_providers,
+        "cdn_url": app.config.cdn_url(),
+    }))
+}
+
+/// Fetch the metadata of this instance, so generic clients can adapt to the server they connect to.
+///
+/// ## Endpoint
+///
+/// GET `/instance`
+async fn get_instance_info(State(app): State<App>) -> Json<Value> {
+    let config = &app.config;
+    let registration = if config.registration_code_required() {
+        "registration_code"
+    } else {
+        "open"
+    };
+
     Json(json!({
+        "name": config.instance_name(),
+        "description": config.instance_description(),
+        "contact": config.instance_contact(),
+        "version": env!("CARGO_PKG_VERSION"),
+        "registration": registration,
         "capabilities": app.ops().get_capabilities(),
+        "limits": {
+            "max_body_size": config.body_limits().default_limit(),
+            "max_message_size": config.body_limits().get(LimitedRoute::CreateMessage),
+            "max_message_attachments": config.max_message_attachments(),
+            "max_message_attachments_size": config.max_message_attachments_size(),
+            "max_upload_size": MAX_UPLOAD_SIZE,
+            "max_upload_part_size": MAX_PART_SIZE,
+        },
     }))
 }
@@ -0,0 +1,375 @@
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, put},
+};
+
+use crate::{
+    app::App,
+    gateway::SendMode,
+    models::{
+        auth::Token,
+        channel::{Channel, ChannelLike},
+        errors::RESTError,
+        gateway_event::GatewayEvent,
+        guild::Guild,
+        guild_event::{GuildEvent, GuildEventRsvp},
+        request_payloads::{CreateGuildEvent, UpdateGuildEvent, UpdateGuildEventRsvp},
+        role::Permissions,
+        snowflake::Snowflake,
+    },
+    rest::permissions::fetch_permitted_guild,
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/guilds/{guild_id}/events", get(fetch_events).post(create_event))
+        .route(
+            "/guilds/{guild_id}/events/{event_id}",
+            get(fetch_event).patch(update_event).delete(delete_event),
+        )
+        .route("/guilds/{guild_id}/events/{event_id}/rsvps", get(fetch_rsvps))
+        .route(
+            "/guilds/{guild_id}/events/{event_id}/rsvps/@me",
+            put(update_rsvp).delete(delete_rsvp),
+        )
+}
+
+/// Fetch the events of a guild that did not end yet, the ones starting first first.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the events of
+///
+/// ## Returns
+///
+/// * [`Vec<GuildEvent>`] - A JSON response containing the guild's upcoming [`GuildEvent`]s
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/events`
+async fn fetch_events(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<GuildEvent>>, RESTError> {
+    if !app.ops().guilds().has_member(guild_id, token.data().user_id()).await? {
+        return Err(RESTError::Forbidden("Not permitted to view resource.".into()));
+    }
+
+    Ok(Json(app.ops().guild_events().fetch_events(guild_id).await?))
+}
+
+/// Schedule a new event in a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to schedule the event in
+/// * `payload` - The [`CreateGuildEvent`] payload, containing the event's details
+///
+/// ## Returns
+///
+/// * [`GuildEvent`] - A JSON response containing the created [`GuildEvent`]
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildEventCreate`] - For all members of the guild
+///
+/// ## Endpoint
+///
+/// POST `/guilds/{guild_id}/events`
+async fn create_event(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateGuildEvent>,
+) -> Result<(StatusCode, Json<GuildEvent>), RESTError> {
+    let guild = fetch_managed_guild(&app, guild_id, &token).await?;
+    check_event_channel(&app, &guild, payload.channel_id).await?;
+
+    let event = GuildEvent::from_payload(&app.config, &guild, token.data().user_id(), payload)?;
+    app.ops().guild_events().create_event(&event).await?;
+
+    app.events().dispatch(
+        GatewayEvent::GuildEventCreate(event.clone()),
+        SendMode::ToGuild(guild_id),
+    );
+
+    Ok((StatusCode::CREATED, Json(event)))
+}
+
+/// Fetch a single event of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the event belongs to
+/// * `event_id` - The ID of the event
+///
+/// ## Returns
+///
+/// * [`GuildEvent`] - A JSON response containing the [`GuildEvent`]
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/events/{event_id}`
+async fn fetch_event(
+    Path((guild_id, event_id)): Path<(Snowflake<Guild>, Snowflake<GuildEvent>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<GuildEvent>, RESTError> {
+    if !app.ops().guilds().has_member(guild_id, token.data().user_id()).await? {
+        return Err(RESTError::Forbidden("Not permitted to view resource.".into()));
+    }
+
+    Ok(Json(fetch_guild_event(&app, guild_id, event_id).await?))
+}
+
+/// Update an event of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the event belongs to
+/// * `event_id` - The ID of the event
+/// * `payload` - The [`UpdateGuildEvent`] payload, containing the fields to update
+///
+/// ## Returns
+///
+/// * [`GuildEvent`] - A JSON response containing the updated [`GuildEvent`]
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildEventUpdate`] - For all members of the guild
+///
+/// ## Endpoint
+///
+/// PATCH `/guilds/{guild_id}/events/{event_id}`
+async fn update_event(
+    Path((guild_id, event_id)): Path<(Snowflake<Guild>, Snowflake<GuildEvent>)>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateGuildEvent>,
+) -> Result<Json<GuildEvent>, RESTError> {
+    let guild = fetch_managed_guild(&app, guild_id, &token).await?;
+    if let Ok(Some(channel_id)) = Option::try_from(payload.channel_id) {
+        check_event_channel(&app, &guild, Some(channel_id)).await?;
+    }
+
+    let mut event = fetch_guild_event(&app, guild_id, event_id).await?;
+    event.apply_update(payload)?;
+    app.ops().guild_events().update_event(&event).await?;
+
+    app.events().dispatch(
+        GatewayEvent::GuildEventUpdate(event.clone()),
+        SendMode::ToGuild(guild_id),
+    );
+
+    Ok(Json(event))
+}
+
+/// Cancel an event of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the event belongs to
+/// * `event_id` - The ID of the event
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildEventRemove`] - For all members of the guild
+///
+/// ## Endpoint
+///
+/// DELETE `/guilds/{guild_id}/events/{event_id}`
+async fn delete_event(
+    Path((guild_id, event_id)): Path<(Snowflake<Guild>, Snowflake<GuildEvent>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    fetch_managed_guild(&app, guild_id, &token).await?;
+
+    if !app.ops().guild_events().delete_event(guild_id, event_id).await? {
+        return Err(RESTError::NotFound("Event not found.".into()));
+    }
+
+    app.events().dispatch(
+        GatewayEvent::GuildEventRemove { id: event_id, guild_id },
+        SendMode::ToGuild(guild_id),
+    );
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch the answers of members to an event of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the event belongs to
+/// * `event_id` - The ID of the event
+///
+/// ## Returns
+///
+/// * [`Vec<GuildEventRsvp>`] - A JSON response containing the answers to the event
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/events/{event_id}/rsvps`
+async fn fetch_rsvps(
+    Path((guild_id, event_id)): Path<(Snowflake<Guild>, Snowflake<GuildEvent>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<GuildEventRsvp>>, RESTError> {
+    if !app.ops().guilds().has_member(guild_id, token.data().user_id()).await? {
+        return Err(RESTError::Forbidden("Not permitted to view resource.".into()));
+    }
+
+    let event = fetch_guild_event(&app, guild_id, event_id).await?;
+    Ok(Json(app.ops().guild_events().fetch_rsvps(event.id()).await?))
+}
+
+/// Answer an event of a guild as the token-holder, replacing their previous answer.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the event belongs to
+/// * `event_id` - The ID of the event
+/// * `payload` - The [`UpdateGuildEventRsvp`] payload, containing the answer
+///
+/// ## Returns
+///
+/// * [`GuildEventRsvp`] - A JSON response containing the recorded answer
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildEventRsvpUpdate`] - For all members of the guild
+///
+/// ## Endpoint
+///
+/// PUT `/guilds/{guild_id}/events/{event_id}/rsvps/@me`
+async fn update_rsvp(
+    Path((guild_id, event_id)): Path<(Snowflake<Guild>, Snowflake<GuildEvent>)>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateGuildEventRsvp>,
+) -> Result<Json<GuildEventRsvp>, RESTError> {
+    let user_id = token.data().user_id();
+
+    if !app.ops().guilds().has_member(guild_id, user_id).await? {
+        return Err(RESTError::Forbidden("You are not a member of this guild.".into()));
+    }
+
+    let event = fetch_guild_event(&app, guild_id, event_id).await?;
+    app.ops()
+        .guild_events()
+        .set_rsvp(&event, user_id, payload.status)
+        .await?;
+
+    app.events().dispatch(
+        GatewayEvent::GuildEventRsvpUpdate {
+            event_id,
+            guild_id,
+            user_id,
+            status: Some(payload.status),
+        },
+        SendMode::ToGuild(guild_id),
+    );
+
+    Ok(Json(GuildEventRsvp {
+        event_id,
+        user_id,
+        status: payload.status,
+    }))
+}
+
+/// Withdraw the token-holder's answer to an event of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the event belongs to
+/// * `event_id` - The ID of the event
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildEventRsvpUpdate`] - For all members of the guild, if the user had answered the event
+///
+/// ## Endpoint
+///
+/// DELETE `/guilds/{guild_id}/events/{event_id}/rsvps/@me`
+async fn delete_rsvp(
+    Path((guild_id, event_id)): Path<(Snowflake<Guild>, Snowflake<GuildEvent>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    let user_id = token.data().user_id();
+
+    if !app.ops().guilds().has_member(guild_id, user_id).await? {
+        return Err(RESTError::Forbidden("You are not a member of this guild.".into()));
+    }
+
+    let event = fetch_guild_event(&app, guild_id, event_id).await?;
+    if app.ops().guild_events().remove_rsvp(event.id(), user_id).await? {
+        app.events().dispatch(
+            GatewayEvent::GuildEventRsvpUpdate {
+                event_id,
+                guild_id,
+                user_id,
+                status: None,
+            },
+            SendMode::ToGuild(guild_id),
+        );
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch the guild with the given ID, ensuring that the token-holder may manage its events.
+async fn fetch_managed_guild(app: &App, guild_id: Snowflake<Guild>, token: &Token) -> Result<Guild, RESTError> {
+    fetch_permitted_guild(
+        app,
+        guild_id,
+        token.data().user_id(),
+        Permissions::MANAGE_EVENTS,
+        "Not permitted to manage events.",
+    )
+    .await
+}
+
+/// Fetch an event, ensuring that it belongs to the given guild.
+async fn fetch_guild_event(
+    app: &App,
+    guild_id: Snowflake<Guild>,
+    event_id: Snowflake<GuildEvent>,
+) -> Result<GuildEvent, RESTError> {
+    app.ops()
+        .guild_events()
+        .fetch_event(guild_id, event_id)
+        .await?
+        .ok_or(RESTError::NotFound("Event not found.".into()))
+}
+
+/// Ensure the channel an event takes place in belongs to the event's guild.
+async fn check_event_channel(
+    app: &App,
+    guild: &Guild,
+    channel_id: Option<Snowflake<Channel>>,
+) -> Result<(), RESTError> {
+    let Some(channel_id) = channel_id else {
+        return Ok(());
+    };
+
+    let channel = app.ops().guilds().fetch_channel(channel_id).await?;
+    if channel.is_none_or(|c| c.guild_id() != Some(guild.id())) {
+        return Err(RESTError::BadRequest(
+            "The channel does not belong to this guild.".into(),
+        ));
+    }
+    Ok(())
+}
@@ -1,39 +1,107 @@
 use axum::{
     Json, Router,
-    extract::{DefaultBodyLimit, Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
-    routing::{delete, get, patch, post},
+    response::Response,
+    routing::{delete, get, patch, post, put},
 };
+use axum_extra::{TypedHeader, headers::IfNoneMatch};
+use serde::Deserialize;
+use serde_json::{Value, json};
 
 use crate::{
-    app::App,
+    app::{App, Config, LimitedRoute},
     gateway::SendMode,
     models::{
         auth::Token,
-        channel::Channel,
+        avatar::GuildAvatar,
+        channel::{Channel, ChannelLike},
+        direct_upload::DirectUpload,
         errors::RESTError,
         gateway_event::{GatewayEvent, GuildCreatePayload},
-        guild::Guild,
+        guild::{Guild, GuildFeature},
+        invite::InviteUsage,
+        keyword_alert::{KeywordWatchlist, normalize_keywords},
         member::Member,
-        request_payloads::{CreateChannel, CreateGuild, UpdateGuild},
+        omittableoption::OmittableOption,
+        onboarding::{Onboarding, OnboardingResponses},
+        report::Report,
+        request_payloads::{
+            CreateChannel, CreateDirectUpload, CreateGuild, UpdateGuild, UpdateModerationKeywords, UpdateOnboarding,
+            UpdateOnboardingResponses, UpdateVanityUrl,
+        },
+        role::Permissions,
         snowflake::Snowflake,
+        standing::StrikePenalty,
         user::User,
     },
+    rest::{
+        body_limit::BodyLimitLayer,
+        conditional::Conditional,
+        media::{AvatarQuery, create_avatar_upload, discard_avatar_upload, read_avatar_upload, serve_avatar},
+        permissions::fetch_permitted_guild,
+    },
 };
 
-pub fn get_router() -> Router<App> {
+#[derive(Deserialize, Debug, Clone)]
+struct SearchMembersQuery {
+    query: String,
+    after: Option<Snowflake<User>>,
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct FetchGuildReportsQuery {
+    before: Option<Snowflake<Report>>,
+    limit: Option<u32>,
+}
+
+pub fn get_router(config: &Config) -> Router<App> {
     Router::new()
         .route("/guilds", post(create_guild))
         .route("/guilds/{guild_id}", get(fetch_guild))
+        .route("/guilds/{guild_id}/avatars/{avatar_hash}", get(fetch_guild_avatar))
+        .route("/guilds/{guild_id}/channels", get(fetch_channels))
         .route("/guilds/{guild_id}/channels", post(create_channel))
+        .route("/guilds/{guild_id}/vanity-url", get(fetch_vanity_url))
+        .route("/guilds/{guild_id}/vanity-url", patch(update_vanity_url))
+        .route("/guilds/{guild_id}/onboarding", get(fetch_onboarding))
+        .route("/guilds/{guild_id}/onboarding", put(update_onboarding))
+        .route(
+            "/guilds/{guild_id}/onboarding/responses",
+            put(update_onboarding_responses),
+        )
+        .route("/guilds/{guild_id}/invites/{code}", get(fetch_invite_usage))
+        .route("/guilds/{guild_id}/moderation/keywords", get(fetch_moderation_keywords))
+        .route(
+            "/guilds/{guild_id}/moderation/keywords",
+            put(update_moderation_keywords),
+        )
+        .route(
+            "/guilds/{guild_id}/moderation/keywords/subscription",
+            put(subscribe_keyword_alerts),
+        )
+        .route(
+            "/guilds/{guild_id}/moderation/keywords/subscription",
+            delete(unsubscribe_keyword_alerts),
+        )
+        .route("/guilds/{guild_id}/moderation/reports", get(fetch_guild_reports))
+        .route("/guilds/{guild_id}/members", get(fetch_members))
         .route("/guilds/{guild_id}/members", post(create_member))
+        .route("/guilds/{guild_id}/members/search", get(search_members))
         .route("/guilds/{guild_id}/members/@me", get(fetch_member_self))
         .route("/guilds/{guild_id}/members/{member_id}", get(fetch_member))
         .route("/guilds/{guild_id}/members/@me", delete(leave_guild))
+        .route("/guilds/{guild_id}/members/{member_id}", delete(kick_member))
         .route("/guilds/{guild_id}", delete(delete_guild))
         .route(
             "/guilds/{guild_id}",
-            patch(update_guild).layer(DefaultBodyLimit::max(3 * 1024 * 1024 /* 3mb */)),
+            patch(update_guild).layer(BodyLimitLayer::new(config.body_limits().get(LimitedRoute::UpdateGuild))),
+        )
+        .route("/guilds/{guild_id}/avatar/uploads", post(create_guild_avatar_upload))
+        .route(
+            "/guilds/{guild_id}/avatar/uploads/{upload_id}/finalize",
+            post(finalize_guild_avatar_upload),
         )
 }
 
@@ -52,6 +120,10 @@ pub fn get_router() -> Router<App> {
 ///
 /// * [`GatewayEvent::GuildCreate`] - Dispatched when the guild is created
 ///
+/// ## Errors
+///
+/// * [`RESTError::Forbidden`] - If the user's account is too new to create guilds, or is timed out
+///
 /// ## Endpoint
 ///
 /// POST `/guilds`
@@ -60,11 +132,19 @@ async fn create_guild(
     State(app): State<App>,
     Json(payload): Json<CreateGuild>,
 ) -> Result<(StatusCode, Json<Guild>), RESTError> {
+    if app.config.is_new_account(token.data().user_id()) {
+        return Err(RESTError::Forbidden("Your account is too new to create guilds.".into()));
+    }
+    app.ops()
+        .standing()
+        .ensure_unrestricted(token.data().user_id(), StrikePenalty::Timeout)
+        .await?;
+
     let (guild, general, owner) = payload.perform_request(&app, token.data().user_id()).await?;
 
     app.gateway().add_member(token.data().user_id(), &guild);
 
-    app.gateway().dispatch(
+    app.events().dispatch(
         GatewayEvent::GuildCreate(GuildCreatePayload::new(guild.clone(), vec![owner], vec![general])),
         SendMode::ToGuild(guild.id()),
     );
@@ -97,21 +177,20 @@ async fn create_channel(
     token: Token,
     Json(payload): Json<CreateChannel>,
 ) -> Result<(StatusCode, Json<Channel>), RESTError> {
-    let guild = app
-        .ops()
-        .fetch_guild(guild_id)
-        .await
-        .ok_or(RESTError::NotFound("Guild not found".into()))?;
-
-    if guild.owner_id() != token.data().user_id() {
-        return Err(RESTError::Forbidden("You are not the owner of this guild.".into()));
-    }
+    fetch_permitted_guild(
+        &app,
+        guild_id,
+        token.data().user_id(),
+        Permissions::MANAGE_CHANNELS,
+        "Not permitted to create channels.",
+    )
+    .await?;
 
     let channel = Channel::from_payload(&app.config, payload, guild_id);
 
-    app.ops().create_channel(&channel).await?;
+    app.ops().guilds().create_channel(&channel).await?;
 
-    app.gateway().dispatch(
+    app.events().dispatch(
         GatewayEvent::ChannelCreate(channel.clone()),
         SendMode::ToGuild(guild_id),
     );
@@ -119,16 +198,46 @@ async fn create_channel(
     Ok((StatusCode::CREATED, Json(channel)))
 }
 
+/// Download a guild's avatar. No authorization is required.
+///
+/// Avatars are addressed by their hash and never change, so responses may be cached indefinitely,
+/// including by shared caches such as a CDN.
+///
+/// ## Arguments
+///
+/// * `guild_id` - The ID of the guild
+/// * `avatar_hash` - The avatar hash included in the guild object
+/// * `query` - The size to serve the avatar at, if not its original size
+/// * `if_none_match` - The `ETag`s the client already holds, if any
+///
+/// ## Returns
+///
+/// * [`Response`] - The image, or `304 Not Modified` if the client already holds it
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/avatars/{avatar_hash}?size={size}`
+async fn fetch_guild_avatar(
+    Path((guild_id, avatar_hash)): Path<(Snowflake<Guild>, String)>,
+    State(app): State<App>,
+    Query(query): Query<AvatarQuery>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Result<Response, RESTError> {
+    serve_avatar::<GuildAvatar>(&app, guild_id, avatar_hash, query.size, if_none_match).await
+}
+
 /// Fetch a guild's data.
 ///
 /// ## Arguments
 ///
 /// * `token` - The user's session token, already validated
 /// * `guild_id` - The ID of the guild to fetch
+/// * `if_none_match` - The `ETag`s the client already holds, if any
 ///
 /// ## Returns
 ///
-/// * [`Guild`] - A JSON response containing the fetched [`Guild`] object
+/// * [`Guild`] - A JSON response containing the fetched [`Guild`] object,
+///   or `304 Not Modified` if it matches `If-None-Match`
 ///
 /// ## Endpoint
 ///
@@ -137,21 +246,136 @@ async fn fetch_guild(
     Path(guild_id): Path<Snowflake<Guild>>,
     State(app): State<App>,
     token: Token,
-) -> Result<Json<Guild>, RESTError> {
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Result<Conditional<Guild>, RESTError> {
     app.ops()
+        .guilds()
         .fetch_member(token.data().user_id(), guild_id)
         .await?
         .ok_or(RESTError::Forbidden("Not permitted to view resource.".into()))?;
 
     let guild = app
         .ops()
+        .guilds()
         .fetch_guild(guild_id)
-        .await
+        .await?
         .ok_or(RESTError::InternalServerError(
             "Failed to fetch guild from database".into(),
         ))?;
 
-    Ok(Json(guild))
+    Ok(Conditional::new(guild, if_none_match))
+}
+
+/// Fetch all channels in a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the channels of
+/// * `if_none_match` - The `ETag`s the client already holds, if any
+///
+/// ## Returns
+///
+/// * [`Vec<Channel>`] - A JSON response containing the guild's [`Channel`] objects,
+///   or `304 Not Modified` if it matches `If-None-Match`
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/channels`
+async fn fetch_channels(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Result<Conditional<Vec<Channel>>, RESTError> {
+    app.ops()
+        .guilds()
+        .fetch_member(token.data().user_id(), guild_id)
+        .await?
+        .ok_or(RESTError::Forbidden("Not permitted to view resource.".into()))?;
+
+    let mut channels = app
+        .ops()
+        .guilds()
+        .fetch_channels_visible_to(guild_id, token.data().user_id())
+        .await?;
+    // Keep the order stable so the ETag doesn't change between identical fetches
+    channels.sort_unstable_by_key(ChannelLike::id);
+
+    Ok(Conditional::new(channels, if_none_match))
+}
+
+/// Fetch all members of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the members of
+/// * `if_none_match` - The `ETag`s the client already holds, if any
+///
+/// ## Returns
+///
+/// * [`Vec<Member>`] - A JSON response containing the guild's [`Member`] objects,
+///   or `304 Not Modified` if it matches `If-None-Match`
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/members`
+async fn fetch_members(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Result<Conditional<Vec<Member>>, RESTError> {
+    app.ops()
+        .guilds()
+        .fetch_member(token.data().user_id(), guild_id)
+        .await?
+        .ok_or(RESTError::Forbidden("Not permitted to view resource.".into()))?;
+
+    let mut members = app.ops().guilds().fetch_members_for(guild_id).await?;
+    // Keep the order stable so the ETag doesn't change between identical fetches
+    members.sort_unstable_by_key(|m| m.user().id());
+
+    Ok(Conditional::new(members, if_none_match))
+}
+
+/// Search the members of a guild by name, ordered by their user ID.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to search the members of
+/// * `query` - Only return members whose username, display name or nickname starts with this
+/// * `after` - Only return members whose user ID is greater than this
+/// * `limit` - The maximum number of members to return, at most 100
+///
+/// ## Returns
+///
+/// * [`Vec<Member>`] - A JSON response containing the matching [`Member`] objects
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/members/search`
+async fn search_members(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Query(query): Query<SearchMembersQuery>,
+) -> Result<Json<Vec<Member>>, RESTError> {
+    app.ops()
+        .guilds()
+        .fetch_member(token.data().user_id(), guild_id)
+        .await?
+        .ok_or(RESTError::Forbidden("Not permitted to view resource.".into()))?;
+
+    let members = app
+        .ops()
+        .guilds()
+        .search_members(guild_id, Some(&query.query), query.after, query.limit)
+        .await?;
+
+    Ok(Json(members))
 }
 
 /// Update a guild's data.
@@ -175,23 +399,100 @@ async fn update_guild(
     token: Token,
     Json(payload): Json<UpdateGuild>,
 ) -> Result<Json<Guild>, RESTError> {
-    let guild = app
-        .ops()
-        .fetch_guild(guild_id)
-        .await
-        .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
-
-    if guild.owner_id() != token.data().user_id() {
-        return Err(RESTError::Forbidden("Not permitted to update resource.".into()));
+    let guild = fetch_managed_guild(&app, guild_id, &token).await?;
+    if payload.owner_id.is_some() && guild.owner_id() != token.data().user_id() {
+        return Err(RESTError::Forbidden("Only the owner may transfer the guild.".into()));
     }
     let guild = payload.perform_request(&app, &guild).await?;
 
-    app.gateway()
+    app.events()
         .dispatch(GatewayEvent::GuildUpdate(guild.clone()), SendMode::ToGuild(guild.id()));
 
     Ok(Json(guild))
 }
 
+/// Start uploading a new avatar for a guild directly to S3.
+///
+/// ## Arguments
+///
+/// * `guild_id` - The ID of the guild to upload the avatar for
+/// * `token` - The user's session token, already validated
+/// * `payload` - The [`CreateDirectUpload`] payload, describing the image
+///
+/// ## Returns
+///
+/// * [`DirectUpload`] - A JSON response containing the created [`DirectUpload`], including the request to upload the image with
+///
+/// ## Endpoint
+///
+/// POST `/guilds/{guild_id}/avatar/uploads`
+async fn create_guild_avatar_upload(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateDirectUpload>,
+) -> Result<(StatusCode, Json<DirectUpload>), RESTError> {
+    fetch_managed_guild(&app, guild_id, &token).await?;
+
+    create_avatar_upload::<GuildAvatar>(&app, token.data().user_id(), guild_id, payload).await
+}
+
+/// Set an avatar uploaded directly to S3 as a guild's avatar.
+///
+/// ## Arguments
+///
+/// * `guild_id` - The ID of the guild to set the avatar of
+/// * `upload_id` - The ID of the [`DirectUpload`] the avatar was uploaded through
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Guild`] - A JSON response containing the updated [`Guild`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildUpdate`] - To all members of the guild
+///
+/// ## Endpoint
+///
+/// POST `/guilds/{guild_id}/avatar/uploads/{upload_id}/finalize`
+async fn finalize_guild_avatar_upload(
+    Path((guild_id, upload_id)): Path<(Snowflake<Guild>, Snowflake<DirectUpload>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Guild>, RESTError> {
+    let guild = fetch_managed_guild(&app, guild_id, &token).await?;
+    let (upload, avatar) = read_avatar_upload::<GuildAvatar>(&app, token.data().user_id(), guild_id, upload_id).await?;
+
+    let payload = UpdateGuild {
+        name: None,
+        owner_id: None,
+        avatar: OmittableOption::Some(avatar),
+        attachment_archive_days: OmittableOption::Omitted,
+        hide_history_before_join: None,
+        default_notification_level: None,
+    };
+    let guild = payload.perform_request(&app, &guild).await?;
+    discard_avatar_upload(&app, &upload).await;
+
+    app.events()
+        .dispatch(GatewayEvent::GuildUpdate(guild.clone()), SendMode::ToGuild(guild.id()));
+
+    Ok(Json(guild))
+}
+
+/// Fetch the guild with the given ID, ensuring that the token-holder may manage its settings.
+async fn fetch_managed_guild(app: &App, guild_id: Snowflake<Guild>, token: &Token) -> Result<Guild, RESTError> {
+    fetch_permitted_guild(
+        app,
+        guild_id,
+        token.data().user_id(),
+        Permissions::MANAGE_GUILD,
+        "Not permitted to update resource.",
+    )
+    .await
+}
+
 /// Delete a guild and all associated objects
 ///
 /// ## Arguments
@@ -209,22 +510,86 @@ async fn delete_guild(
 ) -> Result<StatusCode, RESTError> {
     let guild = app
         .ops()
+        .guilds()
         .fetch_guild(guild_id)
-        .await
+        .await?
         .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
 
     if guild.owner_id() != token.data().user_id() {
         return Err(RESTError::Forbidden("Not permitted to delete guild.".into()));
     }
 
-    app.ops().delete_guild(&guild).await?;
+    app.ops().guilds().delete_guild(&guild).await?;
 
-    app.gateway()
+    app.events()
         .dispatch(GatewayEvent::GuildRemove(guild.clone()), SendMode::ToGuild(guild_id));
 
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// Fetch a guild's vanity URL.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the vanity URL of
+///
+/// ## Returns
+///
+/// * [`Value`] - A JSON response containing the guild's vanity code, or `null` if it has none
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/vanity-url`
+async fn fetch_vanity_url(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Value>, RESTError> {
+    if !app.ops().guilds().has_member(guild_id, token.data().user_id()).await? {
+        return Err(RESTError::Forbidden("Not permitted to view resource.".into()));
+    }
+
+    let code = app.ops().guilds().fetch_vanity_code(guild_id).await?;
+
+    Ok(Json(json!({ "code": code })))
+}
+
+/// Claim, change or release a guild's vanity URL.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to update the vanity URL of
+/// * `payload` - The [`UpdateVanityUrl`] payload, containing the new code
+///
+/// ## Returns
+///
+/// * [`Value`] - A JSON response containing the guild's new vanity code, or `null` if it was released
+///
+/// ## Endpoint
+///
+/// PATCH `/guilds/{guild_id}/vanity-url`
+async fn update_vanity_url(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateVanityUrl>,
+) -> Result<Json<Value>, RESTError> {
+    let guild = fetch_managed_guild(&app, guild_id, &token).await?;
+
+    // Releasing a code is always allowed, so guilds that lost the feature can clean up
+    if payload.code.is_some() && !guild.has_feature(GuildFeature::VanityUrl) {
+        return Err(RESTError::Forbidden(
+            "Guild does not have the VANITY_URL feature.".into(),
+        ));
+    }
+
+    let code = payload.perform_request(&app, &guild, token.data().user_id()).await?;
+
+    Ok(Json(json!({ "code": code })))
+}
+
 /// Fetch a member's data.
 ///
 /// ## Arguments
@@ -246,12 +611,13 @@ async fn fetch_member(
     token: Token,
 ) -> Result<Json<Member>, RESTError> {
     // Check if the requester is in the channel's guild
-    if !app.ops().has_member(guild_id, token.data().user_id()).await? {
+    if !app.ops().guilds().has_member(guild_id, token.data().user_id()).await? {
         return Err(RESTError::Forbidden("Not permitted to view resource.".into()));
     }
 
     let member = app
         .ops()
+        .guilds()
         .fetch_member(member_id, guild_id)
         .await?
         .ok_or(RESTError::NotFound("Member does not exist or is not available.".into()))?;
@@ -280,6 +646,7 @@ async fn fetch_member_self(
 ) -> Result<Json<Member>, RESTError> {
     let member = app
         .ops()
+        .guilds()
         .fetch_member(token.data().user_id(), guild_id)
         .await?
         .ok_or(RESTError::NotFound("Member does not exist or is not available.".into()))?;
@@ -302,6 +669,7 @@ async fn fetch_member_self(
 ///
 /// * [`GatewayEvent::GuildCreate`] - For the user who joined the guild
 /// * [`GatewayEvent::MemberCreate`] - For all members already in the guild
+/// * [`GatewayEvent::MessageCreate`] - For all members, if the guild has a welcome message configured
 ///
 /// ## Endpoint
 ///
@@ -313,26 +681,330 @@ async fn create_member(
 ) -> Result<(StatusCode, Json<Member>), RESTError> {
     let guild = app
         .ops()
+        .guilds()
         .fetch_guild(guild_id)
-        .await
+        .await?
         .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
 
-    let member = app.ops().create_member(&guild, token.data().user_id()).await?;
+    let member = app.ops().guilds().create_member(&guild, token.data().user_id()).await?;
+
+    announce_member(&app, guild, &member).await?;
+
+    Ok((StatusCode::CREATED, Json(member)))
+}
+
+/// Announce a member who just joined a guild, and greet them if the guild has onboarding configured.
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildCreate`] - For the user who joined the guild
+/// * [`GatewayEvent::MemberCreate`] - For all members already in the guild
+/// * [`GatewayEvent::MessageCreate`] - For all members, if the guild has a welcome message configured
+pub(super) async fn announce_member(app: &App, guild: Guild, member: &Member) -> Result<(), RESTError> {
+    let guild_id = guild.id();
 
     // Create payload seperately as it needs read access to gateway
-    let gc_payload = GatewayEvent::GuildCreate(GuildCreatePayload::from_guild(&app, guild).await?);
+    let gc_payload = GatewayEvent::GuildCreate(GuildCreatePayload::from_guild(app, guild, member).await?);
 
     // Send GUILD_CREATE to the user who joined
-    app.gateway().send_to(&member, gc_payload);
+    app.events().send_to(member.user().id(), gc_payload);
 
     // Add the member to the gateway's cache
-    app.gateway().add_member(&member, guild_id);
+    app.gateway().add_member(member, guild_id);
 
     // Dispatch the member create event to all guild members
-    app.gateway()
+    app.events()
         .dispatch(GatewayEvent::MemberCreate(member.clone()), SendMode::ToGuild(guild_id));
 
-    Ok((StatusCode::CREATED, Json(member)))
+    if let Some(welcome) = app.ops().guilds().onboard_member(guild_id, member).await? {
+        app.events()
+            .dispatch(GatewayEvent::MessageCreate(welcome), SendMode::ToGuild(guild_id));
+    }
+
+    Ok(())
+}
+
+/// Fetch a guild's onboarding configuration.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the onboarding of
+///
+/// ## Returns
+///
+/// * [`Onboarding`] - A JSON response containing the guild's [`Onboarding`]
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/onboarding`
+async fn fetch_onboarding(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Onboarding>, RESTError> {
+    if !app.ops().guilds().has_member(guild_id, token.data().user_id()).await? {
+        return Err(RESTError::Forbidden("Not permitted to view resource.".into()));
+    }
+
+    Ok(Json(app.ops().guilds().fetch_onboarding(guild_id).await?))
+}
+
+/// Replace a guild's onboarding configuration.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to configure
+/// * `payload` - The [`UpdateOnboarding`] payload, containing the new configuration
+///
+/// ## Returns
+///
+/// * [`Onboarding`] - A JSON response containing the updated [`Onboarding`]
+///
+/// ## Endpoint
+///
+/// PUT `/guilds/{guild_id}/onboarding`
+async fn update_onboarding(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateOnboarding>,
+) -> Result<Json<Onboarding>, RESTError> {
+    fetch_managed_guild(&app, guild_id, &token).await?;
+
+    let current = app.ops().guilds().fetch_onboarding(guild_id).await?;
+    let onboarding = Onboarding::from_payload(&app.config, &current, payload)?;
+
+    app.ops().guilds().update_onboarding(&onboarding).await?;
+
+    Ok(Json(onboarding))
+}
+
+/// Answer a guild's onboarding questions as the token-holder.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to answer the questions of
+/// * `payload` - The [`UpdateOnboardingResponses`] payload, containing the picked options
+///
+/// ## Returns
+///
+/// * [`OnboardingResponses`] - A JSON response containing the recorded answers and the roles they map to
+///
+/// ## Endpoint
+///
+/// PUT `/guilds/{guild_id}/onboarding/responses`
+async fn update_onboarding_responses(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateOnboardingResponses>,
+) -> Result<Json<OnboardingResponses>, RESTError> {
+    let user_id = token.data().user_id();
+
+    if !app.ops().guilds().has_member(guild_id, user_id).await? {
+        return Err(RESTError::Forbidden("You are not a member of this guild.".into()));
+    }
+
+    let responses = app
+        .ops()
+        .guilds()
+        .set_onboarding_responses(guild_id, user_id, payload.option_ids)
+        .await?;
+
+    Ok(Json(responses))
+}
+
+/// Fetch the guild with the given ID, ensuring that the token-holder may moderate it.
+async fn fetch_moderated_guild(app: &App, guild_id: Snowflake<Guild>, token: &Token) -> Result<Guild, RESTError> {
+    fetch_permitted_guild(
+        app,
+        guild_id,
+        token.data().user_id(),
+        Permissions::MODERATE_GUILD,
+        "Not permitted to moderate this guild.",
+    )
+    .await
+}
+
+/// Fetch the usage of one of a guild's invites, including who joined through it and when.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the invite belongs to
+/// * `code` - The invite code, which may since have been changed or released
+///
+/// ## Returns
+///
+/// * [`InviteUsage`] - A JSON response containing the invite's [`InviteUsage`]
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/invites/{code}`
+async fn fetch_invite_usage(
+    Path((guild_id, code)): Path<(Snowflake<Guild>, String)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<InviteUsage>, RESTError> {
+    fetch_moderated_guild(&app, guild_id, &token).await?;
+
+    let usage = app
+        .ops()
+        .guilds()
+        .fetch_invite_usage(guild_id, &code)
+        .await?
+        .ok_or(RESTError::NotFound("Invite was never used in this guild.".into()))?;
+
+    Ok(Json(usage))
+}
+
+/// Fetch the reports members forwarded to the guild's moderators, newest first.
+/// The identities of the reporters are not included.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the reports of
+/// * `before` - Only return reports filed before the report with this ID
+/// * `limit` - The maximum number of reports to return, at most 100
+///
+/// ## Returns
+///
+/// * [`Vec<Report>`] - A JSON response containing the reports
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/moderation/reports`
+async fn fetch_guild_reports(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Query(query): Query<FetchGuildReportsQuery>,
+) -> Result<Json<Vec<Report>>, RESTError> {
+    fetch_moderated_guild(&app, guild_id, &token).await?;
+
+    let reports = app
+        .ops()
+        .reports()
+        .fetch_guild_reports(guild_id, query.before, query.limit)
+        .await?;
+
+    Ok(Json(reports))
+}
+
+/// Fetch the keywords a guild watches for in new messages.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the keywords of
+///
+/// ## Returns
+///
+/// * [`KeywordWatchlist`] - A JSON response containing the watched keywords
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/moderation/keywords`
+async fn fetch_moderation_keywords(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<KeywordWatchlist>, RESTError> {
+    fetch_moderated_guild(&app, guild_id, &token).await?;
+
+    let keywords = app.ops().guilds().fetch_moderation_keywords(guild_id).await?;
+
+    Ok(Json(KeywordWatchlist { guild_id, keywords }))
+}
+
+/// Replace the keywords a guild watches for in new messages.
+/// Subscribed moderators are alerted whenever a new message contains one of them.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to update the keywords of
+/// * `payload` - The [`UpdateModerationKeywords`] payload, containing the new keywords
+///
+/// ## Returns
+///
+/// * [`KeywordWatchlist`] - A JSON response containing the normalized keywords
+///
+/// ## Endpoint
+///
+/// PUT `/guilds/{guild_id}/moderation/keywords`
+async fn update_moderation_keywords(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateModerationKeywords>,
+) -> Result<Json<KeywordWatchlist>, RESTError> {
+    fetch_moderated_guild(&app, guild_id, &token).await?;
+
+    let keywords = normalize_keywords(payload.keywords)?;
+    app.ops().guilds().set_moderation_keywords(guild_id, &keywords).await?;
+
+    Ok(Json(KeywordWatchlist { guild_id, keywords }))
+}
+
+/// Subscribe the token-holder to the keyword alerts of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to receive keyword alerts of
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// PUT `/guilds/{guild_id}/moderation/keywords/subscription`
+async fn subscribe_keyword_alerts(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    fetch_moderated_guild(&app, guild_id, &token).await?;
+
+    app.ops()
+        .guilds()
+        .set_keyword_alert_subscription(guild_id, token.data().user_id(), true)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Unsubscribe the token-holder from the keyword alerts of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to stop receiving keyword alerts of
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/guilds/{guild_id}/moderation/keywords/subscription`
+async fn unsubscribe_keyword_alerts(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    app.ops()
+        .guilds()
+        .set_keyword_alert_subscription(guild_id, token.data().user_id(), false)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
 /// Remove the token-holder from a guild.
@@ -361,12 +1033,13 @@ async fn leave_guild(
 ) -> Result<StatusCode, RESTError> {
     let guild = app
         .ops()
+        .guilds()
         .fetch_guild(guild_id)
-        .await
+        .await?
         .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
     let member_id = token.data().user_id();
 
-    if !app.ops().has_member(guild_id, member_id).await? {
+    if !app.ops().guilds().has_member(guild_id, member_id).await? {
         return Err(RESTError::NotFound("Member does not exist.".into()));
     }
 
@@ -374,16 +1047,83 @@ async fn leave_guild(
         return Err(RESTError::Forbidden("Owner cannot leave owned guild.".into()));
     }
 
-    app.ops().delete_member(&guild, member_id).await?;
+    remove_member(&app, guild, member_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Remove another member from a guild.
+/// The member making the request must have every permission of the member they remove, and the owner cannot be removed.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to remove the member from
+/// * `member_id` - The ID of the member to remove
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildRemove`] - For the removed member
+/// * [`GatewayEvent::MemberRemove`] - For all members still in the guild
+///
+/// ## Endpoint
+///
+/// DELETE `/guilds/{guild_id}/members/{member_id}`
+async fn kick_member(
+    Path((guild_id, member_id)): Path<(Snowflake<Guild>, Snowflake<User>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    let guild = app
+        .ops()
+        .guilds()
+        .fetch_guild(guild_id)
+        .await?
+        .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
+    let user_id = token.data().user_id();
+
+    if member_id == user_id {
+        return Err(RESTError::BadRequest("Use the @me member ID to leave a guild.".into()));
+    }
+
+    let permissions = app.ops().roles().fetch_permissions(&guild, user_id).await?;
+    if !permissions.allows(Permissions::KICK_MEMBERS) {
+        return Err(RESTError::Forbidden("Not permitted to kick members.".into()));
+    }
+    if !app.ops().guilds().has_member(guild_id, member_id).await? {
+        return Err(RESTError::NotFound("Member does not exist.".into()));
+    }
+    if member_id == guild.owner_id() {
+        return Err(RESTError::Forbidden("The owner cannot be kicked.".into()));
+    }
+    if !permissions.allows(app.ops().roles().fetch_permissions(&guild, member_id).await?) {
+        return Err(RESTError::Forbidden(
+            "Not permitted to kick members with permissions you do not have.".into(),
+        ));
+    }
+
+    remove_member(&app, guild, member_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Remove a member from a guild, notifying them and the remaining members.
+async fn remove_member(app: &App, guild: Guild, member_id: Snowflake<User>) -> Result<(), RESTError> {
+    let guild_id = guild.id();
+    app.ops().guilds().delete_member(&guild, member_id).await?;
 
     // Remove the member from the gateway's sessions
     app.gateway().remove_member(member_id, guild_id);
 
-    // Send GUILD_REMOVE to the user who left
-    app.gateway().send_to(member_id, GatewayEvent::GuildRemove(guild));
+    // Send GUILD_REMOVE to the user who was removed
+    app.events().send_to(member_id, GatewayEvent::GuildRemove(guild));
 
     // Dispatch the member remove event
-    app.gateway().dispatch(
+    app.events().dispatch(
         GatewayEvent::MemberRemove {
             id: member_id,
             guild_id,
@@ -391,5 +1131,5 @@ async fn leave_guild(
         SendMode::ToGuild(guild_id),
     );
 
-    Ok(StatusCode::NO_CONTENT)
+    Ok(())
 }
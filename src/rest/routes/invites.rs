@@ -0,0 +1,238 @@
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+};
+
+use crate::{
+    app::App,
+    gateway::SendMode,
+    models::{
+        auth::Token,
+        errors::RESTError,
+        gateway_event::{GatewayEvent, GuildCreatePayload},
+        guest_link::GuestLink,
+        invite::Invite,
+        member::Member,
+        role::Permissions,
+        standing::StrikePenalty,
+    },
+    rest::permissions::fetch_permitted_guild,
+};
+
+use super::guilds::announce_member;
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/invites/{code}", get(fetch_invite))
+        .route("/invites/{code}", post(join_invite))
+        .route("/guest-links/{code}", get(fetch_guest_link))
+        .route("/guest-links/{code}", post(join_guest_link))
+        .route("/guest-links/{code}", delete(delete_guest_link))
+}
+
+/// Resolve an invite code to the guild it belongs to.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `code` - The invite or vanity code to resolve
+///
+/// ## Returns
+///
+/// * [`Invite`] - A JSON response containing the resolved [`Invite`] object
+///
+/// ## Endpoint
+///
+/// GET `/invites/{code}`
+async fn fetch_invite(
+    Path(code): Path<String>,
+    State(app): State<App>,
+    _token: Token,
+) -> Result<Json<Invite>, RESTError> {
+    let invite = app
+        .ops()
+        .guilds()
+        .fetch_invite(&code)
+        .await?
+        .ok_or(RESTError::NotFound("Invite does not exist or has expired.".into()))?;
+
+    Ok(Json(invite))
+}
+
+/// Join the guild an invite resolves to, recording that the user joined through it.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `code` - The invite or vanity code to join through
+///
+/// ## Returns
+///
+/// * [`Member`] - A JSON response containing the created [`Member`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildCreate`] - For the user who joined the guild
+/// * [`GatewayEvent::MemberCreate`] - For all members already in the guild
+/// * [`GatewayEvent::MessageCreate`] - For all members, if the guild has a welcome message configured
+///
+/// ## Endpoint
+///
+/// POST `/invites/{code}`
+async fn join_invite(
+    Path(code): Path<String>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<(StatusCode, Json<Member>), RESTError> {
+    app.ops()
+        .standing()
+        .ensure_unrestricted(token.data().user_id(), StrikePenalty::Timeout)
+        .await?;
+
+    let invite = app
+        .ops()
+        .guilds()
+        .fetch_invite(&code)
+        .await?
+        .ok_or(RESTError::NotFound("Invite does not exist or has expired.".into()))?;
+
+    let member = app
+        .ops()
+        .guilds()
+        .create_member_via_invite(&invite, token.data().user_id())
+        .await?;
+
+    announce_member(&app, invite.guild().clone(), &member).await?;
+
+    Ok((StatusCode::CREATED, Json(member)))
+}
+
+/// Resolve a guest link code.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `code` - The guest link code to resolve
+///
+/// ## Returns
+///
+/// * [`GuestLink`] - A JSON response containing the resolved [`GuestLink`] object
+///
+/// ## Endpoint
+///
+/// GET `/guest-links/{code}`
+async fn fetch_guest_link(
+    Path(code): Path<String>,
+    State(app): State<App>,
+    _token: Token,
+) -> Result<Json<GuestLink>, RESTError> {
+    let link = app
+        .ops()
+        .guilds()
+        .fetch_guest_link(&code)
+        .await?
+        .ok_or(RESTError::NotFound("Guest link does not exist or has expired.".into()))?;
+
+    Ok(Json(link))
+}
+
+/// Join a guild as a guest through a guest link.
+/// Guests can only view the channel the link was created for, and are removed once their access expires.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `code` - The guest link code to join through
+///
+/// ## Returns
+///
+/// * [`Member`] - A JSON response containing the created guest [`Member`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildCreate`] - For the user who joined the guild, containing only the channel they can view
+/// * [`GatewayEvent::MemberCreate`] - For all members already in the guild
+///
+/// ## Endpoint
+///
+/// POST `/guest-links/{code}`
+async fn join_guest_link(
+    Path(code): Path<String>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<(StatusCode, Json<Member>), RESTError> {
+    app.ops()
+        .standing()
+        .ensure_unrestricted(token.data().user_id(), StrikePenalty::Timeout)
+        .await?;
+
+    let link = app
+        .ops()
+        .guilds()
+        .fetch_guest_link(&code)
+        .await?
+        .ok_or(RESTError::NotFound("Guest link does not exist or has expired.".into()))?;
+
+    let guild = app
+        .ops()
+        .guilds()
+        .fetch_guild(link.guild_id())
+        .await?
+        .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
+
+    let member = app.ops().guilds().create_guest(&link, token.data().user_id()).await?;
+
+    let gc_payload = GatewayEvent::GuildCreate(GuildCreatePayload::from_guild(&app, guild, &member).await?);
+    app.events().send_to(member.user().id(), gc_payload);
+
+    app.gateway().add_guest(&member, link.guild_id(), link.channel_id());
+
+    app.events().dispatch(
+        GatewayEvent::MemberCreate(member.clone()),
+        SendMode::ToGuild(link.guild_id()),
+    );
+
+    Ok((StatusCode::CREATED, Json(member)))
+}
+
+/// Revoke a guest link. Guests who already joined through it keep their access until it expires.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `code` - The guest link code to revoke
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/guest-links/{code}`
+async fn delete_guest_link(
+    Path(code): Path<String>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    let link = app
+        .ops()
+        .guilds()
+        .fetch_guest_link(&code)
+        .await?
+        .ok_or(RESTError::NotFound("Guest link does not exist or has expired.".into()))?;
+
+    fetch_permitted_guild(
+        &app,
+        link.guild_id(),
+        token.data().user_id(),
+        Permissions::MANAGE_CHANNELS,
+        "Not permitted to manage guest links.",
+    )
+    .await?;
+
+    app.ops().guilds().delete_guest_link(link.code()).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
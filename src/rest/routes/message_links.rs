@@ -0,0 +1,80 @@
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    routing::get,
+};
+use serde::Deserialize;
+
+use crate::{
+    app::App,
+    models::{
+        auth::Token,
+        channel::ChannelLike,
+        errors::RESTError,
+        message_link::{MessageLink, MessageLinkPreview},
+    },
+};
+
+#[derive(Deserialize, Debug, Clone)]
+struct ResolveMessageLinkQuery {
+    url: String,
+}
+
+pub fn get_router() -> Router<App> {
+    Router::new().route("/message-links/resolve", get(resolve_message_link))
+}
+
+/// Resolve a link to a message into a preview of it, if the user can view the message.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `url` - The message link to resolve
+///
+/// ## Returns
+///
+/// * [`MessageLinkPreview`] - A JSON response containing a preview of the linked message
+///
+/// ## Endpoint
+///
+/// GET `/message-links/resolve`
+async fn resolve_message_link(
+    State(app): State<App>,
+    token: Token,
+    Query(query): Query<ResolveMessageLinkQuery>,
+) -> Result<Json<MessageLinkPreview>, RESTError> {
+    let link: MessageLink = query
+        .url
+        .parse()
+        .map_err(|_| RESTError::BadRequest("The URL is not a valid message link.".into()))?;
+
+    let channel = app
+        .ops()
+        .guilds()
+        .fetch_channel(link.channel_id())
+        .await?
+        .filter(|c| c.guild_id() == Some(link.guild_id()))
+        .ok_or(RESTError::NotFound(
+            "Message does not exist or is not available.".into(),
+        ))?;
+
+    // Check if the user is in the channel's guild and can view the channel
+    app.ops()
+        .guilds()
+        .fetch_member(token.data().user_id(), link.guild_id())
+        .await?
+        .filter(|m| m.can_view(channel.id()))
+        .ok_or(RESTError::Forbidden("Not permitted to access resource.".into()))?;
+
+    let message = app
+        .ops()
+        .messages()
+        .fetch_message(link.message_id())
+        .await?
+        .filter(|m| m.channel_id() == channel.id())
+        .ok_or(RESTError::NotFound(
+            "Message does not exist or is not available.".into(),
+        ))?;
+
+    Ok(Json(MessageLinkPreview::new(&channel, &message)))
+}
@@ -1,7 +1,14 @@
+pub mod admin;
+pub mod automod;
 pub mod channels;
 pub mod common;
+pub mod guild_events;
 pub mod guilds;
+pub mod invites;
+pub mod message_links;
 pub mod prefs;
+pub mod reports;
+pub mod roles;
 pub mod users;
 
 pub use common::get_router;
@@ -27,7 +27,17 @@ async fn update_prefs(
     Json(payload): Json<UpdatePrefs>,
 ) -> Result<StatusCode, RESTError> {
     let mut prefs = Prefs::fetch(app.clone(), token.data().user_id()).await?;
-    prefs.update(payload);
-    prefs.commit(app).await?;
+    let muted_words_changed = payload.muted_words.is_some();
+    let presence_sharing_changed = payload.presence_sharing.is_some();
+    prefs.update(payload)?;
+    prefs.commit(app.clone()).await?;
+
+    if muted_words_changed {
+        app.gateway()
+            .set_muted_words(prefs.user_id(), prefs.muted_word_matcher());
+    }
+    if presence_sharing_changed {
+        app.ops().users().refresh_presence_audience(prefs.user_id()).await?;
+    }
     Ok(StatusCode::NO_CONTENT)
 }
@@ -0,0 +1,147 @@
+use axum::{Json, Router, extract::State, http::StatusCode, routing::post};
+
+use crate::{
+    app::App,
+    models::{
+        auth::Token,
+        channel::ChannelLike,
+        errors::RESTError,
+        guild::Guild,
+        report::{Report, ReportTargetType},
+        request_payloads::CreateReport,
+        snowflake::Snowflake,
+    },
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new().route("/reports", post(create_report))
+}
+
+/// Report a message, user or guild to the instance's administrators.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `payload` - The `CreateReport` payload, describing what is reported and why
+///
+/// ## Returns
+///
+/// * [`Report`] - A JSON response containing the created [`Report`]
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::ReportCreate`](crate::models::gateway_event::GatewayEvent::ReportCreate) - Dispatched to the guild's owner, if the report is forwarded
+///
+/// ## Endpoint
+///
+/// POST `/reports`
+async fn create_report(
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateReport>,
+) -> Result<(StatusCode, Json<Report>), RESTError> {
+    let user_id = token.data().user_id();
+    let guild_id = find_report_guild(&app, &token, &payload).await?;
+
+    if payload.forward_to_guild && payload.target_type != ReportTargetType::Message {
+        return Err(RESTError::BadRequest(
+            "Only reports about messages can be forwarded to guild moderators.".into(),
+        ));
+    }
+
+    let mut report = Report::new(
+        &app.config,
+        user_id,
+        payload.target_type,
+        payload.target_id,
+        payload.category,
+    )
+    .with_details(payload.details)?;
+
+    if let Some(guild_id) = guild_id {
+        report = report.in_guild(guild_id, payload.forward_to_guild);
+    }
+
+    app.ops().reports().create_report(&report).await?;
+
+    tracing::info!(
+        user_id = %user_id,
+        report_id = %report.id(),
+        target_type = ?report.target_type(),
+        "Report created"
+    );
+    Ok((StatusCode::CREATED, Json(report)))
+}
+
+/// Ensure the reported object exists and is visible to the reporter.
+///
+/// ## Returns
+///
+/// The guild the reported object belongs to, if any.
+async fn find_report_guild(
+    app: &App,
+    token: &Token,
+    payload: &CreateReport,
+) -> Result<Option<Snowflake<Guild>>, RESTError> {
+    let user_id = token.data().user_id();
+    let target_id = i64::from(payload.target_id);
+
+    match payload.target_type {
+        ReportTargetType::Message => {
+            let not_found = || RESTError::NotFound("Message does not exist or is not available.".into());
+
+            let message = app
+                .ops()
+                .messages()
+                .fetch_message(target_id)
+                .await?
+                .ok_or_else(not_found)?;
+            let channel = app
+                .ops()
+                .guilds()
+                .fetch_channel(message.channel_id())
+                .await?
+                .ok_or_else(not_found)?;
+
+            // Messages in direct message channels belong to no guild, like reported users
+            let Some(guild_id) = channel.guild_id() else {
+                return if channel.recipient_ids().contains(&user_id) {
+                    Ok(None)
+                } else {
+                    Err(not_found())
+                };
+            };
+
+            app.ops()
+                .guilds()
+                .fetch_member(user_id, guild_id)
+                .await?
+                .filter(|m| m.can_view(channel.id()))
+                .ok_or_else(not_found)?;
+
+            Ok(Some(guild_id))
+        }
+        ReportTargetType::User => {
+            if target_id == i64::from(user_id) {
+                return Err(RESTError::BadRequest("Cannot report yourself.".into()));
+            }
+
+            app.ops()
+                .users()
+                .fetch_user(target_id)
+                .await?
+                .ok_or(RESTError::NotFound("User does not exist.".into()))?;
+
+            Ok(None)
+        }
+        ReportTargetType::Guild => {
+            app.ops()
+                .guilds()
+                .fetch_member(user_id, target_id)
+                .await?
+                .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
+
+            Ok(Some(target_id.into()))
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, put},
+};
+
+use crate::{
+    app::App,
+    gateway::SendMode,
+    models::{
+        auth::Token,
+        errors::RESTError,
+        gateway_event::GatewayEvent,
+        guild::Guild,
+        request_payloads::{CreateRole, UpdateRole},
+        role::{Permissions, Role},
+        snowflake::Snowflake,
+        user::User,
+    },
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/guilds/{guild_id}/roles", get(fetch_roles).post(create_role))
+        .route(
+            "/guilds/{guild_id}/roles/{role_id}",
+            get(fetch_role).patch(update_role).delete(delete_role),
+        )
+        .route("/guilds/{guild_id}/members/{member_id}/roles", get(fetch_member_roles))
+        .route(
+            "/guilds/{guild_id}/members/{member_id}/roles/{role_id}",
+            put(add_member_role).delete(remove_member_role),
+        )
+}
+
+/// Fetch the roles of a guild, the oldest first.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the roles of
+///
+/// ## Returns
+///
+/// * [`Vec<Role>`] - A JSON response containing the guild's [`Role`]s
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/roles`
+async fn fetch_roles(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<Role>>, RESTError> {
+    if !app.ops().guilds().has_member(guild_id, token.data().user_id()).await? {
+        return Err(RESTError::Forbidden("Not permitted to view resource.".into()));
+    }
+
+    Ok(Json(app.ops().roles().fetch_roles(guild_id).await?))
+}
+
+/// Create a new role in a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to create the role in
+/// * `payload` - The [`CreateRole`] payload, containing the role's name and permissions
+///
+/// ## Returns
+///
+/// * [`Role`] - A JSON response containing the created [`Role`]
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::RoleCreate`] - For all members of the guild
+///
+/// ## Endpoint
+///
+/// POST `/guilds/{guild_id}/roles`
+async fn create_role(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateRole>,
+) -> Result<(StatusCode, Json<Role>), RESTError> {
+    let permissions = fetch_role_permissions(&app, guild_id, &token).await?;
+    ensure_grantable(permissions, payload.permissions)?;
+
+    let role = Role::from_payload(&app.config, guild_id, &payload)?;
+    app.ops().roles().create_role(&role).await?;
+
+    app.events()
+        .dispatch(GatewayEvent::RoleCreate(role.clone()), SendMode::ToGuild(guild_id));
+
+    Ok((StatusCode::CREATED, Json(role)))
+}
+
+/// Fetch a single role of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the role belongs to
+/// * `role_id` - The ID of the role
+///
+/// ## Returns
+///
+/// * [`Role`] - A JSON response containing the [`Role`]
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/roles/{role_id}`
+async fn fetch_role(
+    Path((guild_id, role_id)): Path<(Snowflake<Guild>, Snowflake<Role>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Role>, RESTError> {
+    if !app.ops().guilds().has_member(guild_id, token.data().user_id()).await? {
+        return Err(RESTError::Forbidden("Not permitted to view resource.".into()));
+    }
+
+    Ok(Json(fetch_guild_role(&app, guild_id, role_id).await?))
+}
+
+/// Update a role of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the role belongs to
+/// * `role_id` - The ID of the role
+/// * `payload` - The [`UpdateRole`] payload, containing the fields to update
+///
+/// ## Returns
+///
+/// * [`Role`] - A JSON response containing the updated [`Role`]
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::RoleUpdate`] - For all members of the guild
+///
+/// ## Endpoint
+///
+/// PATCH `/guilds/{guild_id}/roles/{role_id}`
+async fn update_role(
+    Path((guild_id, role_id)): Path<(Snowflake<Guild>, Snowflake<Role>)>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateRole>,
+) -> Result<Json<Role>, RESTError> {
+    let permissions = fetch_role_permissions(&app, guild_id, &token).await?;
+
+    let mut role = fetch_guild_role(&app, guild_id, role_id).await?;
+    ensure_grantable(permissions, role.permissions())?;
+    role.apply_update(payload)?;
+    ensure_grantable(permissions, role.permissions())?;
+    app.ops().roles().update_role(&role).await?;
+
+    app.events()
+        .dispatch(GatewayEvent::RoleUpdate(role.clone()), SendMode::ToGuild(guild_id));
+
+    Ok(Json(role))
+}
+
+/// Delete a role of a guild, unassigning it from all members.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the role belongs to
+/// * `role_id` - The ID of the role
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::RoleRemove`] - For all members of the guild
+///
+/// ## Endpoint
+///
+/// DELETE `/guilds/{guild_id}/roles/{role_id}`
+async fn delete_role(
+    Path((guild_id, role_id)): Path<(Snowflake<Guild>, Snowflake<Role>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    let permissions = fetch_role_permissions(&app, guild_id, &token).await?;
+
+    let role = fetch_guild_role(&app, guild_id, role_id).await?;
+    ensure_grantable(permissions, role.permissions())?;
+
+    if !app.ops().roles().delete_role(guild_id, role_id).await? {
+        return Err(RESTError::NotFound("Role not found.".into()));
+    }
+
+    app.events().dispatch(
+        GatewayEvent::RoleRemove { id: role_id, guild_id },
+        SendMode::ToGuild(guild_id),
+    );
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch the IDs of the roles assigned to a member.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the member is in
+/// * `member_id` - The ID of the member
+///
+/// ## Returns
+///
+/// * [`Vec<Snowflake<Role>>`] - A JSON response containing the IDs of the member's roles
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/members/{member_id}/roles`
+async fn fetch_member_roles(
+    Path((guild_id, member_id)): Path<(Snowflake<Guild>, Snowflake<User>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<Snowflake<Role>>>, RESTError> {
+    if !app.ops().guilds().has_member(guild_id, token.data().user_id()).await? {
+        return Err(RESTError::Forbidden("Not permitted to view resource.".into()));
+    }
+    if !app.ops().guilds().has_member(guild_id, member_id).await? {
+        return Err(RESTError::NotFound("Member does not exist.".into()));
+    }
+
+    Ok(Json(
+        app.ops().roles().fetch_member_role_ids(guild_id, member_id).await?,
+    ))
+}
+
+/// Assign a role to a member of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the member is in
+/// * `member_id` - The ID of the member to assign the role to
+/// * `role_id` - The ID of the role to assign
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::MemberRolesUpdate`] - For all members of the guild, if the member did not have the role
+///
+/// ## Endpoint
+///
+/// PUT `/guilds/{guild_id}/members/{member_id}/roles/{role_id}`
+async fn add_member_role(
+    Path((guild_id, member_id, role_id)): Path<(Snowflake<Guild>, Snowflake<User>, Snowflake<Role>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    let permissions = fetch_role_permissions(&app, guild_id, &token).await?;
+
+    let role = fetch_guild_role(&app, guild_id, role_id).await?;
+    ensure_grantable(permissions, role.permissions())?;
+
+    let member = app
+        .ops()
+        .guilds()
+        .fetch_member(member_id, guild_id)
+        .await?
+        .ok_or(RESTError::NotFound("Member does not exist.".into()))?;
+    if member.guest().is_some() {
+        return Err(RESTError::BadRequest("Guests cannot be assigned roles.".into()));
+    }
+
+    if app.ops().roles().add_member_role(&role, member_id).await? {
+        dispatch_member_roles(&app, guild_id, member_id).await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Unassign a role from a member of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the member is in
+/// * `member_id` - The ID of the member to unassign the role from
+/// * `role_id` - The ID of the role to unassign
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::MemberRolesUpdate`] - For all members of the guild
+///
+/// ## Endpoint
+///
+/// DELETE `/guilds/{guild_id}/members/{member_id}/roles/{role_id}`
+async fn remove_member_role(
+    Path((guild_id, member_id, role_id)): Path<(Snowflake<Guild>, Snowflake<User>, Snowflake<Role>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    let permissions = fetch_role_permissions(&app, guild_id, &token).await?;
+
+    let role = fetch_guild_role(&app, guild_id, role_id).await?;
+    ensure_grantable(permissions, role.permissions())?;
+
+    if !app.ops().roles().remove_member_role(&role, member_id).await? {
+        return Err(RESTError::NotFound("Member does not have the role.".into()));
+    }
+    dispatch_member_roles(&app, guild_id, member_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Resolve the permissions of the token-holder, ensuring that they may manage the roles of the guild.
+async fn fetch_role_permissions(
+    app: &App,
+    guild_id: Snowflake<Guild>,
+    token: &Token,
+) -> Result<Permissions, RESTError> {
+    let guild = app
+        .ops()
+        .guilds()
+        .fetch_guild(guild_id)
+        .await?
+        .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
+
+    let permissions = app
+        .ops()
+        .roles()
+        .fetch_permissions(&guild, token.data().user_id())
+        .await?;
+    if !permissions.allows(Permissions::MANAGE_ROLES) {
+        return Err(RESTError::Forbidden("Not permitted to manage roles.".into()));
+    }
+    Ok(permissions)
+}
+
+/// Ensure a member with the given permissions may grant the permissions of a role, so roles cannot be used to escalate.
+fn ensure_grantable(permissions: Permissions, granted: Permissions) -> Result<(), RESTError> {
+    if !permissions.allows(granted) {
+        return Err(RESTError::Forbidden(
+            "Not permitted to manage roles with permissions you do not have.".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Fetch a role, ensuring that it belongs to the given guild.
+async fn fetch_guild_role(app: &App, guild_id: Snowflake<Guild>, role_id: Snowflake<Role>) -> Result<Role, RESTError> {
+    app.ops()
+        .roles()
+        .fetch_role(guild_id, role_id)
+        .await?
+        .ok_or(RESTError::NotFound("Role not found.".into()))
+}
+
+/// Notify the members of a guild of the roles now assigned to one of them.
+async fn dispatch_member_roles(
+    app: &App,
+    guild_id: Snowflake<Guild>,
+    member_id: Snowflake<User>,
+) -> Result<(), RESTError> {
+    let role_ids = app.ops().roles().fetch_member_role_ids(guild_id, member_id).await?;
+
+    app.events().dispatch(
+        GatewayEvent::MemberRolesUpdate {
+            guild_id,
+            user_id: member_id,
+            role_ids,
+        },
+        SendMode::ToGuild(guild_id),
+    );
+    Ok(())
+}
@@ -1,72 +1,212 @@
+use std::net::SocketAddr;
+
 use axum::{
-    Json, Router,
-    extract::{DefaultBodyLimit, Path, State},
+    Extension, Json, Router,
+    extract::{ConnectInfo, Path, Query, State},
     http::StatusCode,
+    response::Response,
     routing::{delete, get, patch, post, put},
 };
+use axum_extra::{TypedHeader, headers::IfNoneMatch};
 use secrecy::ExposeSecret;
+use serde::Deserialize;
 use serde_json::{Value, json};
 
 use crate::{
-    app::App,
-    gateway::SendMode,
+    app::{App, Config, LimitedRoute},
+    gateway::{SendMode, SessionInfo},
     models::{
+        active_channel::ActiveChannel,
         auth::{Credentials, StoredCredentials, Token},
-        errors::RESTError,
+        avatar::{UserAvatar, UserBanner},
+        channel::{Channel, ChannelLike},
+        direct_upload::DirectUpload,
+        errors::{AuthError, RESTError},
         gateway_event::GatewayEvent,
         guild::Guild,
-        request_payloads::{CreateUser, RemoveFCMToken, UpdateFCMToken, UpdateUser},
+        message::Message,
+        notification_level::NotificationOverride,
+        omittableoption::OmittableOption,
+        relationship::{Relationship, RelationshipType},
+        request_payloads::{
+            CreateDirectChannel, CreateDirectUpload, CreateRelationship, CreateUser, ExternalLogin, RemoveFCMToken,
+            UpdateFCMToken, UpdateUser,
+        },
+        snowflake::Snowflake,
+        standing::Standing,
         user::{Presence, User},
     },
-    rest::auth::{generate_hash, validate_credentials},
+    rest::{
+        auth::{generate_hash, validate_credentials},
+        body_limit::BodyLimitLayer,
+        media::{AvatarQuery, create_avatar_upload, discard_avatar_upload, read_avatar_upload, serve_avatar},
+    },
 };
 
-pub fn get_router() -> Router<App> {
+#[derive(Deserialize, Debug, Clone)]
+struct FetchMentionsQuery {
+    guild_id: Option<Snowflake<Guild>>,
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct FetchActiveChannelsQuery {
+    limit: Option<u32>,
+}
+
+pub fn get_router(config: &Config) -> Router<App> {
     Router::new()
         .route("/users", post(create_user))
         .route("/users/auth", get(auth_user))
         .route("/users/auth/refresh", post(refresh_token))
+        .route("/users/auth/providers/{provider}", post(auth_external))
         .route("/users/@me", get(fetch_self))
         .route("/users/@me/guilds", get(fetch_self_guilds))
+        .route("/users/@me/mentions", get(fetch_self_mentions))
+        .route("/users/@me/active-channels", get(fetch_self_active_channels))
         .route("/users/@me/fcm", put(update_fcm_token))
         .route("/users/@me/fcm", delete(remove_fcm_token))
         .route("/users/@me/presence", patch(update_presence))
+        .route("/users/@me/sessions", get(fetch_self_sessions))
+        .route("/users/@me/standing", get(fetch_self_standing))
+        .route(
+            "/users/@me/notification-overrides",
+            get(fetch_self_notification_overrides),
+        )
+        .route("/users/@me/avatar/uploads", post(create_self_avatar_upload))
+        .route(
+            "/users/@me/avatar/uploads/{upload_id}/finalize",
+            post(finalize_self_avatar_upload),
+        )
+        .route("/users/@me/banner/uploads", post(create_self_banner_upload))
+        .route(
+            "/users/@me/banner/uploads/{upload_id}/finalize",
+            post(finalize_self_banner_upload),
+        )
+        .route(
+            "/users/@me/relationships",
+            get(fetch_self_relationships).post(create_relationship),
+        )
+        .route(
+            "/users/@me/relationships/{user_id}",
+            put(add_relationship).delete(remove_relationship),
+        )
+        .route(
+            "/users/@me/channels",
+            get(fetch_self_channels).post(create_direct_channel),
+        )
+        .route("/users/{user_id}/avatars/{avatar_hash}", get(fetch_user_avatar))
+        .route("/users/{user_id}/banners/{banner_hash}", get(fetch_user_banner))
         .route("/usernames/{username}", get(query_username))
         .route(
             "/users/@me",
-            patch(update_self).layer(DefaultBodyLimit::max(3 * 1024 * 1024 /* 3mb */)),
+            patch(update_self).layer(BodyLimitLayer::new(config.body_limits().get(LimitedRoute::UpdateSelf))),
         )
 }
 
+/// Download a user's avatar. No authorization is required.
+///
+/// Avatars are addressed by their hash and never change, so responses may be cached indefinitely,
+/// including by shared caches such as a CDN.
+///
+/// ## Arguments
+///
+/// * `user_id` - The ID of the user
+/// * `avatar_hash` - The avatar hash included in the user object
+/// * `query` - The size to serve the avatar at, if not its original size
+/// * `if_none_match` - The `ETag`s the client already holds, if any
+///
+/// ## Returns
+///
+/// * [`Response`] - The image, or `304 Not Modified` if the client already holds it
+///
+/// ## Endpoint
+///
+/// GET `/users/{user_id}/avatars/{avatar_hash}?size={size}`
+async fn fetch_user_avatar(
+    Path((user_id, avatar_hash)): Path<(Snowflake<User>, String)>,
+    State(app): State<App>,
+    Query(query): Query<AvatarQuery>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Result<Response, RESTError> {
+    serve_avatar::<UserAvatar>(&app, user_id, avatar_hash, query.size, if_none_match).await
+}
+
+/// Download a user's profile banner. No authorization is required.
+///
+/// Like avatars, banners are addressed by their hash and may be cached indefinitely.
+///
+/// ## Arguments
+///
+/// * `user_id` - The ID of the user
+/// * `banner_hash` - The banner hash included in the user object
+/// * `if_none_match` - The `ETag`s the client already holds, if any
+///
+/// ## Returns
+///
+/// * [`Response`] - The image, or `304 Not Modified` if the client already holds it
+///
+/// ## Endpoint
+///
+/// GET `/users/{user_id}/banners/{banner_hash}`
+async fn fetch_user_banner(
+    Path((user_id, banner_hash)): Path<(Snowflake<User>, String)>,
+    State(app): State<App>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Result<Response, RESTError> {
+    serve_avatar::<UserBanner>(&app, user_id, banner_hash, None, if_none_match).await
+}
+
 /// Create a new user and return the user data.
 ///
 /// ## Arguments
 ///
+/// * `connect_info` - The address of the client, used to limit how many accounts it signs up
 /// * `payload` - The `CreateUser` payload, containing the username and password
 ///
 /// ## Returns
 ///
 /// * [`User`] - A JSON response containing the created [`User`] object
 ///
+/// ## Errors
+///
+/// * [`RESTError::TooManyRequests`] - If too many accounts were signed up from the client's IP address
+///
 /// ## Endpoint
 ///
 /// POST `/users`
-async fn create_user(State(app): State<App>, Json(payload): Json<CreateUser>) -> Result<Json<User>, RESTError> {
+async fn create_user(
+    State(app): State<App>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    Json(payload): Json<CreateUser>,
+) -> Result<Json<User>, RESTError> {
+    throttle_signup(&app, connect_info).await?;
     let password = payload.password.clone();
 
-    if app.ops().is_username_taken(&payload.username).await? {
-        return Err(RESTError::BadRequest(format!(
-            "User with username {} already exists",
-            payload.username
-        )));
-    }
-
     // User needs to be created before credentials to avoid foreign key constraint violation
-    let user = app.ops().create_user(payload).await?;
+    let user = app.ops().users().create_user(payload).await?;
     let credentials = StoredCredentials::new(user.id(), generate_hash(&password)?);
     credentials.commit(app).await?;
 
-    Ok(Json(user))
+    Ok(Json(user.to_private()))
+}
+
+/// Count a new account towards the signups of the client's IP address.
+///
+/// ## Errors
+///
+/// * [`RESTError::TooManyRequests`] - If too many accounts were signed up from the client's IP address
+async fn throttle_signup(app: &App, connect_info: Option<Extension<ConnectInfo<SocketAddr>>>) -> Result<(), RESTError> {
+    if let Some(Extension(ConnectInfo(addr))) = connect_info {
+        app.signup_limiter()
+            .acquire(&addr.ip())
+            .await
+            .map_err(|retry_after| RESTError::TooManyRequests {
+                retry_after,
+                bucket: None,
+            })?;
+    }
+    Ok(())
 }
 
 /// Validate a user's credentials and return a token if successful.
@@ -92,6 +232,72 @@ async fn auth_user(State(app): State<App>, credentials: Credentials) -> Result<J
     })))
 }
 
+/// Exchange a token issued by an external authentication provider for a session token.
+/// Users logging in for the first time are created automatically.
+///
+/// ## Arguments
+///
+/// * `provider` - The name of the provider that issued the token
+/// * `connect_info` - The address of the client, used to limit how many accounts it signs up
+/// * `payload` - The `ExternalLogin` payload, containing the token issued by the provider,
+///   and the registration code to create the account with on the first login
+///
+/// ## Returns
+///
+/// * `{"user_id": user_id, "token": token}` - A JSON response containing the session token and `user_id`
+///
+/// ## Errors
+///
+/// * [`RESTError::TooManyRequests`] - If the user logs in for the first time,
+///   and too many accounts were signed up from the client's IP address
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::UserUpdate`] - For all members of mutual guilds, if the display name changed at the provider
+///
+/// ## Endpoint
+///
+/// POST `/users/auth/providers/{provider}`
+async fn auth_external(
+    Path(provider): Path<String>,
+    State(app): State<App>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    Json(payload): Json<ExternalLogin>,
+) -> Result<Json<Value>, RESTError> {
+    let provider = app
+        .auth_provider(&provider)
+        .ok_or_else(|| RESTError::NotFound(format!("Authentication provider {provider} is not configured")))?;
+
+    let identity = provider
+        .authenticate(payload.token.expose_secret())
+        .await
+        .map_err(AuthError::from)?;
+    let users = app.ops().users();
+    let (user, changed) = if let Some(login) = users.login_external(provider.name(), &identity).await? {
+        login
+    } else {
+        throttle_signup(&app, connect_info).await?;
+        let user = users
+            .provision_external(provider.name(), &identity, payload.registration_code.as_deref())
+            .await?;
+        (user, true)
+    };
+
+    if changed {
+        app.events().dispatch(
+            GatewayEvent::UserUpdate(user.to_public()),
+            SendMode::ToMutualGuilds(user.id()),
+        );
+    }
+
+    let token = Token::new_for(app.config.app_secret(), user.id())?;
+
+    Ok(Json(json!({
+        "user_id": user.id(),
+        "token": token.expose_secret(),
+    })))
+}
+
 /// Refresh a user's token. This generates a new token for the user.
 ///
 /// ## Arguments
@@ -129,10 +335,11 @@ async fn refresh_token(State(app): State<App>, token: Token) -> Result<Json<Valu
 /// GET `/users/@me`
 async fn fetch_self(State(app): State<App>, token: Token) -> Result<Json<User>, RESTError> {
     app.ops()
+        .users()
         .fetch_user(token.data().user_id())
-        .await
+        .await?
         .ok_or(RESTError::NotFound("User not found".into()))
-        .map(Json)
+        .map(|user| Json(user.to_private()))
 }
 
 /// Fetch a user's guilds.
@@ -149,11 +356,390 @@ async fn fetch_self(State(app): State<App>, token: Token) -> Result<Json<User>,
 ///
 /// GET `/users/@me/guilds`
 async fn fetch_self_guilds(State(app): State<App>, token: Token) -> Result<Json<Vec<Guild>>, RESTError> {
-    let guilds = app.ops().fetch_guilds_for(token.data().user_id()).await?;
+    let guilds = app.ops().guilds().fetch_guilds_for(token.data().user_id()).await?;
 
     Ok(Json(guilds))
 }
 
+/// Fetch the token-holder's gateway sessions, including those waiting to be resumed.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Vec<SessionInfo>`] - A JSON response containing the user's sessions
+///
+/// ## Endpoint
+///
+/// GET `/users/@me/sessions`
+async fn fetch_self_sessions(State(app): State<App>, token: Token) -> Result<Json<Vec<SessionInfo>>, RESTError> {
+    let sessions = app.gateway().sessions_of(token.data().user_id()).await;
+
+    Ok(Json(sessions))
+}
+
+/// Fetch the token-holder's account standing, along with their active strikes.
+/// Available while suspended, so that users can find out why and for how long.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Standing`] - A JSON response containing the user's [`Standing`]
+///
+/// ## Endpoint
+///
+/// GET `/users/@me/standing`
+async fn fetch_self_standing(State(app): State<App>, token: Token) -> Result<Json<Standing>, RESTError> {
+    let standing = app.ops().standing().fetch_standing(token.data().user_id()).await?;
+
+    Ok(Json(standing.anonymized()))
+}
+
+/// Fetch the notification levels the token-holder set for individual channels.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Vec<NotificationOverride>`] - A JSON response containing the levels, ordered by channel ID
+///
+/// ## Endpoint
+///
+/// GET `/users/@me/notification-overrides`
+async fn fetch_self_notification_overrides(
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<NotificationOverride>>, RESTError> {
+    let overrides = app
+        .ops()
+        .notifications()
+        .fetch_notification_overrides(token.data().user_id())
+        .await?;
+
+    Ok(Json(overrides))
+}
+
+/// Fetch the token-holder's friends and pending friend requests.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Vec<Relationship>`] - A JSON response containing the user's relationships
+///
+/// ## Endpoint
+///
+/// GET `/users/@me/relationships`
+async fn fetch_self_relationships(State(app): State<App>, token: Token) -> Result<Json<Vec<Relationship>>, RESTError> {
+    let relationships = app
+        .ops()
+        .relationships()
+        .fetch_relationships(token.data().user_id())
+        .await?;
+
+    Ok(Json(relationships))
+}
+
+/// Send a friend request to a user by their username, or accept theirs.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `payload` - The username of the user to befriend
+///
+/// ## Returns
+///
+/// * [`Relationship`] - A JSON response containing the relationship with the user
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::RelationshipAdd`] - To both users, if the relationship changed
+///
+/// ## Endpoint
+///
+/// POST `/users/@me/relationships`
+async fn create_relationship(
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateRelationship>,
+) -> Result<Json<Relationship>, RESTError> {
+    let other = app
+        .ops()
+        .users()
+        .fetch_user_by_username(&payload.username)
+        .await?
+        .ok_or(RESTError::NotFound("User not found".into()))?;
+
+    befriend(&app, &token, other).await
+}
+
+/// Send a friend request to a user, or accept theirs.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `user_id` - The ID of the user to befriend
+///
+/// ## Returns
+///
+/// * [`Relationship`] - A JSON response containing the relationship with the user
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::RelationshipAdd`] - To both users, if the relationship changed
+///
+/// ## Endpoint
+///
+/// PUT `/users/@me/relationships/{user_id}`
+async fn add_relationship(
+    Path(user_id): Path<Snowflake<User>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Relationship>, RESTError> {
+    let other = app
+        .ops()
+        .users()
+        .fetch_user(user_id)
+        .await?
+        .ok_or(RESTError::NotFound("User not found".into()))?;
+
+    befriend(&app, &token, other).await
+}
+
+/// Send a friend request from the token-holder to the given user, or accept theirs,
+/// notifying both users if the relationship changed.
+async fn befriend(app: &App, token: &Token, other: User) -> Result<Json<Relationship>, RESTError> {
+    let user_id = token.data().user_id();
+    let relationships = app.ops().relationships();
+    let now = chrono::Utc::now();
+
+    let Some(kind) = relationships.add_relationship(user_id, other.id()).await? else {
+        // Nothing changed, the request was already sent or the users are already friends
+        let current = relationships
+            .fetch_relationships(user_id)
+            .await?
+            .into_iter()
+            .find(|r| r.user().id() == other.id())
+            .ok_or(RESTError::NotFound("User not found".into()))?;
+        return Ok(Json(current));
+    };
+
+    let user = app
+        .ops()
+        .users()
+        .fetch_user(user_id)
+        .await?
+        .ok_or(RESTError::NotFound("User not found".into()))?;
+
+    app.events().dispatch(
+        GatewayEvent::RelationshipAdd(Relationship::new(user, kind.counterpart(), now)),
+        SendMode::ToUser(other.id()),
+    );
+
+    // New friends may see each other's presence
+    if kind == RelationshipType::Friend {
+        app.ops().users().refresh_presence_audience(user_id).await?;
+        app.ops().users().refresh_presence_audience(other.id()).await?;
+    }
+
+    let relationship = Relationship::new(other, kind, now);
+    app.events().dispatch(
+        GatewayEvent::RelationshipAdd(relationship.clone()),
+        SendMode::ToUser(user_id),
+    );
+
+    Ok(Json(relationship))
+}
+
+/// Remove the token-holder's relationship with a user.
+/// This declines or cancels a pending friend request, or ends a friendship.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `user_id` - The ID of the other user
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::RelationshipRemove`] - To both users
+///
+/// ## Endpoint
+///
+/// DELETE `/users/@me/relationships/{user_id}`
+async fn remove_relationship(
+    Path(user_id): Path<Snowflake<User>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    let self_id = token.data().user_id();
+
+    if !app.ops().relationships().remove_relationship(self_id, user_id).await? {
+        return Err(RESTError::NotFound("Relationship not found".into()));
+    }
+
+    app.events()
+        .dispatch(GatewayEvent::RelationshipRemove { user_id }, SendMode::ToUser(self_id));
+    app.events().dispatch(
+        GatewayEvent::RelationshipRemove { user_id: self_id },
+        SendMode::ToUser(user_id),
+    );
+
+    // Former friends may no longer see each other's presence
+    app.ops().users().refresh_presence_audience(self_id).await?;
+    app.ops().users().refresh_presence_audience(user_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch the direct message channels of the token-holder, the most recently active first.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Vec<Channel>`] - A JSON response containing the user's direct message channels
+///
+/// ## Endpoint
+///
+/// GET `/users/@me/channels`
+async fn fetch_self_channels(State(app): State<App>, token: Token) -> Result<Json<Vec<Channel>>, RESTError> {
+    let channels = app
+        .ops()
+        .direct_channels()
+        .fetch_direct_channels(token.data().user_id())
+        .await?;
+
+    Ok(Json(channels))
+}
+
+/// Open a direct message channel with a user, or return the one the users already share.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `payload` - The ID of the user to message
+///
+/// ## Returns
+///
+/// * [`Channel`] - A JSON response containing the direct message channel,
+///   with `201 Created` if it was opened by this request
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::ChannelCreate`] - To both recipients, if the channel was opened by this request
+///
+/// ## Endpoint
+///
+/// POST `/users/@me/channels`
+async fn create_direct_channel(
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateDirectChannel>,
+) -> Result<(StatusCode, Json<Channel>), RESTError> {
+    let user_id = token.data().user_id();
+
+    let other = app
+        .ops()
+        .users()
+        .fetch_user(payload.recipient_id)
+        .await?
+        .ok_or(RESTError::NotFound("User not found".into()))?;
+
+    if other.id() != user_id && !app.ops().relationships().can_open_dm(user_id, other.id()).await? {
+        return Err(RESTError::Forbidden("Not permitted to message this user.".into()));
+    }
+
+    let (channel, created) = app
+        .ops()
+        .direct_channels()
+        .open_direct_channel(user_id, other.id())
+        .await?;
+
+    if !created {
+        return Ok((StatusCode::OK, Json(channel)));
+    }
+
+    // Connected recipients must learn of the channel before its first event is sent to them
+    for recipient in channel.recipient_ids() {
+        app.gateway().add_recipient(*recipient, channel.id());
+    }
+    app.events()
+        .dispatch(GatewayEvent::ChannelCreate(channel.clone()), channel.send_mode());
+
+    Ok((StatusCode::CREATED, Json(channel)))
+}
+
+/// Fetch the most recent messages mentioning the token-holder, newest first.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `query` - Optionally restricts the mentions to a guild, and limits the amount of messages returned
+///
+/// ## Returns
+///
+/// * [`Vec<Message>`] - A JSON response containing the messages mentioning the user
+///
+/// ## Endpoint
+///
+/// GET `/users/@me/mentions`
+async fn fetch_self_mentions(
+    Query(query): Query<FetchMentionsQuery>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<Message>>, RESTError> {
+    let messages = app
+        .ops()
+        .messages()
+        .fetch_mentions(token.data().user_id(), query.guild_id, query.limit)
+        .await?;
+
+    Ok(Json(messages))
+}
+
+/// Fetch the channels with recent activity involving the token-holder, the most recently active first.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `query` - Optionally limits the amount of channels returned
+///
+/// ## Returns
+///
+/// * [`Vec<ActiveChannel>`] - A JSON response containing the active channels
+///
+/// ## Endpoint
+///
+/// GET `/users/@me/active-channels`
+async fn fetch_self_active_channels(
+    Query(query): Query<FetchActiveChannelsQuery>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<ActiveChannel>>, RESTError> {
+    let channels = app
+        .ops()
+        .messages()
+        .fetch_active_channels(token.data().user_id(), query.limit)
+        .await?;
+
+    Ok(Json(channels))
+}
+
 /// Update the token-holder's presence.
 ///
 /// ## Arguments
@@ -193,14 +779,13 @@ async fn update_presence(
     .execute(app.db())
     .await?;
 
+    app.gateway().set_presence(token.data().user_id(), new_presence);
+
     if app.gateway().is_connected(token.data().user_id()).await {
-        app.gateway().dispatch(
-            GatewayEvent::PresenceUpdate {
-                presence: new_presence,
-                user_id: token.data().user_id(),
-            },
-            SendMode::ToMutualGuilds(token.data().user_id()),
-        );
+        app.ops()
+            .users()
+            .dispatch_presence(token.data().user_id(), new_presence)
+            .await?;
     }
 
     Ok(Json(new_presence))
@@ -230,12 +815,139 @@ async fn update_self(
     token: Token,
     Json(payload): Json<UpdateUser>,
 ) -> Result<Json<User>, RESTError> {
-    let user = payload.perform_request(&app, token.data().user_id()).await?;
-    app.gateway().dispatch(
-        GatewayEvent::UserUpdate(user.clone()),
+    perform_self_update(&app, &token, payload).await.map(Json)
+}
+
+/// Apply an update to the token-holder's user data, and let everyone who can see the user know.
+async fn perform_self_update(app: &App, token: &Token, payload: UpdateUser) -> Result<User, RESTError> {
+    let user = payload.perform_request(app, token.data().user_id()).await?;
+    app.events().dispatch(
+        GatewayEvent::UserUpdate(user.to_public()),
         SendMode::ToMutualGuilds(user.id()),
     );
 
+    Ok(user.to_private())
+}
+
+/// Start uploading a new avatar for the token-holder directly to S3.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `payload` - The [`CreateDirectUpload`] payload, describing the image
+///
+/// ## Returns
+///
+/// * [`DirectUpload`] - A JSON response containing the created [`DirectUpload`], including the request to upload the image with
+///
+/// ## Endpoint
+///
+/// POST `/users/@me/avatar/uploads`
+async fn create_self_avatar_upload(
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateDirectUpload>,
+) -> Result<(StatusCode, Json<DirectUpload>), RESTError> {
+    let user_id = token.data().user_id();
+    create_avatar_upload::<UserAvatar>(&app, user_id, user_id, payload).await
+}
+
+/// Set an avatar uploaded directly to S3 as the token-holder's avatar.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `upload_id` - The ID of the [`DirectUpload`] the avatar was uploaded through
+///
+/// ## Returns
+///
+/// * [`User`] - A JSON response containing the updated [`User`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::UserUpdate`] - To all guilds the user is a member of
+///
+/// ## Endpoint
+///
+/// POST `/users/@me/avatar/uploads/{upload_id}/finalize`
+async fn finalize_self_avatar_upload(
+    Path(upload_id): Path<Snowflake<DirectUpload>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<User>, RESTError> {
+    let user_id = token.data().user_id();
+    let (upload, avatar) = read_avatar_upload::<UserAvatar>(&app, user_id, user_id, upload_id).await?;
+
+    let payload = UpdateUser {
+        username: None,
+        display_name: OmittableOption::Omitted,
+        avatar: OmittableOption::Some(avatar),
+        banner: OmittableOption::Omitted,
+    };
+    let user = perform_self_update(&app, &token, payload).await?;
+    discard_avatar_upload(&app, &upload).await;
+
+    Ok(Json(user))
+}
+
+/// Start uploading a new banner for the token-holder directly to S3.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `payload` - The [`CreateDirectUpload`] payload, describing the image
+///
+/// ## Returns
+///
+/// * [`DirectUpload`] - A JSON response containing the created [`DirectUpload`], including the request to upload the image with
+///
+/// ## Endpoint
+///
+/// POST `/users/@me/banner/uploads`
+async fn create_self_banner_upload(
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateDirectUpload>,
+) -> Result<(StatusCode, Json<DirectUpload>), RESTError> {
+    let user_id = token.data().user_id();
+    create_avatar_upload::<UserBanner>(&app, user_id, user_id, payload).await
+}
+
+/// Set a banner uploaded directly to S3 as the token-holder's banner.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `upload_id` - The ID of the [`DirectUpload`] the banner was uploaded through
+///
+/// ## Returns
+///
+/// * [`User`] - A JSON response containing the updated [`User`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::UserUpdate`] - To all guilds the user is a member of
+///
+/// ## Endpoint
+///
+/// POST `/users/@me/banner/uploads/{upload_id}/finalize`
+async fn finalize_self_banner_upload(
+    Path(upload_id): Path<Snowflake<DirectUpload>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<User>, RESTError> {
+    let user_id = token.data().user_id();
+    let (upload, banner) = read_avatar_upload::<UserBanner>(&app, user_id, user_id, upload_id).await?;
+
+    let payload = UpdateUser {
+        username: None,
+        display_name: OmittableOption::Omitted,
+        avatar: OmittableOption::Omitted,
+        banner: OmittableOption::Some(banner),
+    };
+    let user = perform_self_update(&app, &token, payload).await?;
+    discard_avatar_upload(&app, &upload).await;
+
     Ok(Json(user))
 }
 
@@ -258,7 +970,7 @@ async fn update_self(
 ///
 /// GET `/users/{username}`
 async fn query_username(State(app): State<App>, Path(username): Path<String>) -> Result<StatusCode, RESTError> {
-    if app.ops().is_username_taken(&username).await? {
+    if app.ops().users().is_username_taken(&username).await? {
         Ok(StatusCode::NO_CONTENT)
     } else {
         Err(RESTError::NotFound("User not found".into()))
@@ -0,0 +1,154 @@
+/// The signature every PNG file starts with.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Count the frames of an image, without decoding it.
+///
+/// Animated GIF and PNG (APNG) images are inspected, all other images are assumed to be still.
+///
+/// ## Arguments
+///
+/// * `content` - The encoded image.
+///
+/// ## Errors
+///
+/// * If the image claims to be a GIF or PNG, but is malformed.
+///
+/// ## Returns
+///
+/// The number of frames, which is `1` for still images.
+pub fn frame_count(content: &[u8]) -> Result<u32, String> {
+    if content.starts_with(b"GIF87a") || content.starts_with(b"GIF89a") {
+        gif_frame_count(content).ok_or_else(|| "Malformed GIF image".into())
+    } else if content.starts_with(PNG_SIGNATURE) {
+        png_frame_count(content).ok_or_else(|| "Malformed PNG image".into())
+    } else {
+        Ok(1)
+    }
+}
+
+/// Count the image descriptors of a GIF.
+fn gif_frame_count(content: &[u8]) -> Option<u32> {
+    /// Skip a sequence of data sub-blocks, returning the position after the block terminator.
+    fn skip_sub_blocks(content: &[u8], mut pos: usize) -> Option<usize> {
+        loop {
+            let len = usize::from(*content.get(pos)?);
+            pos += 1;
+            if len == 0 {
+                return Some(pos);
+            }
+            pos += len;
+        }
+    }
+
+    /// The size of the color table following a descriptor with the given packed fields, if any.
+    fn color_table_size(packed: u8) -> usize {
+        if packed & 0x80 == 0 {
+            0
+        } else {
+            3 * (1 << ((packed & 0x07) + 1))
+        }
+    }
+
+    // Header and logical screen descriptor, followed by the global color table
+    let mut pos = 13 + color_table_size(*content.get(10)?);
+    let mut frames = 0;
+
+    loop {
+        match *content.get(pos)? {
+            // Image descriptor, followed by a local color table, the LZW code size and the image data
+            0x2C => {
+                let packed = *content.get(pos + 9)?;
+                pos = skip_sub_blocks(content, pos + 10 + color_table_size(packed) + 1)?;
+                frames += 1;
+            }
+            // Extension, consisting of a label and data sub-blocks
+            0x21 => pos = skip_sub_blocks(content, pos + 2)?,
+            // Trailer
+            0x3B => return Some(frames),
+            _ => return None,
+        }
+    }
+}
+
+/// Read the number of frames from the animation control chunk of a PNG, which APNGs have before the image data.
+fn png_frame_count(content: &[u8]) -> Option<u32> {
+    let mut pos = PNG_SIGNATURE.len();
+
+    loop {
+        let len = u32::from_be_bytes(content.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let kind = content.get(pos + 4..pos + 8)?;
+        let data = content.get(pos + 8..pos + 8 + len)?;
+
+        match kind {
+            b"acTL" => return Some(u32::from_be_bytes(data.get(..4)?.try_into().ok()?)),
+            b"IDAT" | b"IEND" => return Some(1),
+            _ => {}
+        }
+        // Length, type, data and CRC
+        pos += 12 + len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a GIF with the given amount of 1x1 frames, using a global color table.
+    fn gif(frames: usize) -> Vec<u8> {
+        let mut gif = b"GIF89a\x01\x00\x01\x00\x80\x00\x00".to_vec();
+        gif.extend_from_slice(&[0, 0, 0, 255, 255, 255]);
+        // Application extension, as used for looping animations
+        gif.extend_from_slice(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00");
+        for _ in 0..frames {
+            // Graphic control extension, image descriptor and image data
+            gif.extend_from_slice(b"\x21\xF9\x04\x00\x0A\x00\x00\x00");
+            gif.extend_from_slice(b"\x2C\x00\x00\x00\x00\x01\x00\x01\x00\x00");
+            gif.extend_from_slice(b"\x02\x02\x44\x01\x00");
+        }
+        gif.push(0x3B);
+        gif
+    }
+
+    fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = u32::try_from(data.len()).expect("chunk fits").to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        // The CRC is not checked
+        chunk.extend_from_slice(&[0, 0, 0, 0]);
+        chunk
+    }
+
+    fn png(frames: Option<u32>) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend(png_chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]));
+        if let Some(frames) = frames {
+            let mut actl = frames.to_be_bytes().to_vec();
+            actl.extend_from_slice(&0u32.to_be_bytes());
+            png.extend(png_chunk(b"acTL", &actl));
+        }
+        png.extend(png_chunk(b"IDAT", &[0]));
+        png.extend(png_chunk(b"IEND", &[]));
+        png
+    }
+
+    #[test]
+    fn test_gif_frame_count() {
+        assert_eq!(frame_count(&gif(1)), Ok(1));
+        assert_eq!(frame_count(&gif(12)), Ok(12));
+
+        let truncated = gif(3);
+        assert!(frame_count(&truncated[..truncated.len() - 8]).is_err());
+    }
+
+    #[test]
+    fn test_png_frame_count() {
+        assert_eq!(frame_count(&png(None)), Ok(1));
+        assert_eq!(frame_count(&png(Some(24))), Ok(24));
+        assert!(frame_count(PNG_SIGNATURE).is_err());
+    }
+
+    #[test]
+    fn test_other_formats_are_still() {
+        assert_eq!(frame_count(b"\xFF\xD8\xFF\xE0"), Ok(1));
+    }
+}
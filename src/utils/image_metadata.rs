@@ -0,0 +1,194 @@
+use std::io::Cursor;
+
+use bytes::Bytes;
+use image::{
+    DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageReader,
+    codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+};
+
+/// The quality re-encoded JPEG images are saved with.
+const JPEG_QUALITY: u8 = 90;
+
+/// Returns whether metadata can be stripped from images of the given MIME type.
+pub fn is_supported(mime: &mime::Mime) -> bool {
+    ImageFormat::from_mime_type(mime.essence_str()).is_some_and(|f| SUPPORTED_FORMATS.contains(&f))
+}
+
+const SUPPORTED_FORMATS: [ImageFormat; 3] = [ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::WebP];
+
+/// Strip all metadata, such as EXIF location data, from a JPEG, PNG or WebP image.
+///
+/// The image is re-encoded in its original format. Any rotation the EXIF orientation
+/// tag describes is applied to the pixels first, so the image is still displayed upright.
+///
+/// ## Arguments
+///
+/// * `content` - The encoded image.
+///
+/// ## Errors
+///
+/// * [`ImageError`] - If the image could not be decoded or re-encoded,
+///   or it is not in one of the supported formats.
+///
+/// ## Returns
+///
+/// The re-encoded image without metadata.
+pub fn strip(content: &[u8]) -> Result<Bytes, ImageError> {
+    let (image, format) = decode(content)?;
+    encode(image, format, content.len())
+}
+
+/// Downscale a JPEG, PNG or WebP image to fit within a square of the given size,
+/// preserving its aspect ratio. Metadata is stripped like by [`strip`].
+///
+/// ## Arguments
+///
+/// * `content` - The encoded image.
+/// * `size` - The maximum width and height of the downscaled image, in pixels.
+///
+/// ## Errors
+///
+/// * [`ImageError`] - If the image could not be decoded or re-encoded,
+///   or it is not in one of the supported formats.
+///
+/// ## Returns
+///
+/// The downscaled image in its original format, or `None` if it already fits within the given size.
+pub fn downscale(content: &[u8], size: u32) -> Result<Option<Bytes>, ImageError> {
+    let (image, format) = decode(content)?;
+    if image.width() <= size && image.height() <= size {
+        return Ok(None);
+    }
+
+    let image = image.resize(size, size, FilterType::Lanczos3);
+    encode(image, format, content.len() / 4).map(Some)
+}
+
+/// Decode an image in one of the supported formats, with its EXIF orientation applied.
+fn decode(content: &[u8]) -> Result<(DynamicImage, ImageFormat), ImageError> {
+    let reader = ImageReader::new(Cursor::new(content)).with_guessed_format()?;
+    let format = reader
+        .format()
+        .filter(|f| SUPPORTED_FORMATS.contains(f))
+        .ok_or_else(|| unsupported(reader.format()))?;
+
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    Ok((image, format))
+}
+
+/// Encode an image in the given format, without any metadata.
+fn encode(image: DynamicImage, format: ImageFormat, capacity: usize) -> Result<Bytes, ImageError> {
+    let mut out = Vec::with_capacity(capacity);
+    match format {
+        ImageFormat::Jpeg => image
+            .into_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY))?,
+        ImageFormat::Png => image.write_with_encoder(PngEncoder::new(&mut out))?,
+        ImageFormat::WebP => image
+            .into_rgba8()
+            .write_with_encoder(WebPEncoder::new_lossless(&mut out))?,
+        _ => return Err(unsupported(Some(format))),
+    }
+
+    Ok(out.into())
+}
+
+fn unsupported(format: Option<ImageFormat>) -> ImageError {
+    use image::error::{ImageFormatHint, UnsupportedError};
+
+    let hint = format.map_or(ImageFormatHint::Unknown, ImageFormatHint::Exact);
+    ImageError::Unsupported(UnsupportedError::from(hint))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageBuffer, Rgb, metadata::Orientation};
+
+    use super::*;
+
+    /// Build a minimal APP1 segment containing an EXIF orientation tag.
+    fn exif_segment(orientation: u16) -> Vec<u8> {
+        let mut tiff = b"MM\x00\x2a\x00\x00\x00\x08\x00\x01".to_vec();
+        tiff.extend_from_slice(&[0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01]);
+        tiff.extend_from_slice(&orientation.to_be_bytes());
+        tiff.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+        let mut payload = b"Exif\x00\x00".to_vec();
+        payload.extend_from_slice(&tiff);
+
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend_from_slice(&u16::try_from(payload.len() + 2).expect("segment fits").to_be_bytes());
+        segment.extend_from_slice(&payload);
+        segment
+    }
+
+    fn jpeg_with_exif(width: u32, height: u32, orientation: u16) -> Vec<u8> {
+        let image = ImageBuffer::from_pixel(width, height, Rgb([200u8, 30, 30]));
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_with_encoder(JpegEncoder::new(&mut jpeg))
+            .expect("image should encode");
+
+        // Insert the EXIF segment right after the SOI marker
+        let mut out = jpeg[..2].to_vec();
+        out.extend(exif_segment(orientation));
+        out.extend_from_slice(&jpeg[2..]);
+        out
+    }
+
+    #[test]
+    fn test_strip_removes_exif_and_applies_orientation() {
+        let original = jpeg_with_exif(4, 2, 6);
+        assert!(original.windows(4).any(|w| w == b"Exif"));
+
+        let stripped = strip(&original).expect("image should be stripped");
+        assert!(!stripped.windows(4).any(|w| w == b"Exif"));
+
+        let mut decoder = ImageReader::new(Cursor::new(&stripped))
+            .with_guessed_format()
+            .expect("format should be guessed")
+            .into_decoder()
+            .expect("image should decode");
+        assert_eq!(
+            decoder.orientation().expect("orientation should be read"),
+            Orientation::NoTransforms
+        );
+        // Rotated by 90 degrees
+        assert_eq!(decoder.dimensions(), (2, 4));
+    }
+
+    #[test]
+    fn test_downscale() {
+        let image = ImageBuffer::from_pixel(64, 32, Rgb([200u8, 30, 30]));
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_with_encoder(PngEncoder::new(&mut png))
+            .expect("image should encode");
+
+        let downscaled = downscale(&png, 16)
+            .expect("image should be downscaled")
+            .expect("image is larger than the size");
+        let reader = ImageReader::new(Cursor::new(&downscaled))
+            .with_guessed_format()
+            .expect("format should be guessed");
+        assert_eq!(reader.format(), Some(ImageFormat::Png));
+        // The aspect ratio is preserved
+        assert_eq!(reader.into_dimensions().expect("image should decode"), (16, 8));
+
+        assert!(downscale(&png, 64).expect("image should decode").is_none());
+        assert!(downscale(b"not an image", 16).is_err());
+    }
+
+    #[test]
+    fn test_strip_rejects_unsupported() {
+        assert!(strip(b"not an image").is_err());
+        assert!(is_supported(&mime::IMAGE_JPEG));
+        assert!(is_supported(&"image/webp".parse().expect("mime should parse")));
+        assert!(!is_supported(&mime::IMAGE_GIF));
+    }
+}
@@ -1,2 +1,4 @@
+pub mod animation;
+pub mod image_metadata;
 pub mod join_handle;
 pub mod multipart_json;
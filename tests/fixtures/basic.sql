@@ -37,8 +37,8 @@ VALUES (
     );
 -- Add member entries to guild
 INSERT INTO members (user_id, guild_id, joined_at)
-VALUES (274560698946818049, 274586748720386049, 0),
-    (278890683744522241, 274586748720386049, 0);
+VALUES (274560698946818049, 274586748720386049, to_timestamp(0)),
+    (278890683744522241, 274586748720386049, to_timestamp(0));
 -- Create a second guild owned by test2
 INSERT INTO guilds (id, owner_id, name)
 VALUES (
@@ -56,4 +56,4 @@ VALUES (
     );
 -- Only add test2 to the second guild
 INSERT INTO members (user_id, guild_id, joined_at)
-VALUES (278890683744522241, 278890858219180033, 0);
\ No newline at end of file
+VALUES (278890683744522241, 278890858219180033, to_timestamp(0));
\ No newline at end of file
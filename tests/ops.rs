@@ -1,19 +1,46 @@
 #![cfg(feature = "db_tests")] // Only runs with `cargo test -F db_tests`
 #![allow(clippy::unwrap_used, clippy::unreadable_literal, dead_code, unused_imports)]
 
-use chat_backend::models::{
-    channel::{ChannelLike, TextChannel},
-    errors::RESTError,
-    member::UserLike,
-    message::Message,
-    omittableoption::OmittableOption,
-    request_payloads::{CreateGuild, UpdateGuild, UpdateMessage, UpdateUser},
-    snowflake::Snowflake,
+use chat_backend::{
+    app::ops::{MAX_UNREAD_COUNT, OutboxOps},
+    external::auth_provider::ExternalIdentity,
+    gateway::{PresenceAudience, SendMode},
+    models::{
+        automod::{AutomodAction, AutomodRule, AutomodTrigger},
+        avatar::AvatarLike,
+        channel::{Channel, ChannelLike, TextChannel},
+        data_uri::DataUri,
+        errors::OpsError,
+        gateway_event::GatewayEvent,
+        guest_link::GuestLink,
+        guild_event::{GuildEvent, RsvpStatus},
+        keyword_alert::normalize_keywords,
+        member::UserLike,
+        message::Message,
+        notification_level::{NotificationLevel, NotificationOverride},
+        omittableoption::OmittableOption,
+        onboarding::{Onboarding, OnboardingOption},
+        outbox::OutboxEntry,
+        relationship::RelationshipType,
+        report::{Report, ReportAction, ReportCategory, ReportStatus, ReportTargetType},
+        request_payloads::{
+            CreateAutomodRule, CreateGuild, CreateGuildEvent, CreateRole, CreateUser, OnboardingOptionPayload,
+            OnboardingQuestionPayload, UpdateAutomodRule, UpdateChannel, UpdateGuild, UpdateMessage, UpdateOnboarding,
+            UpdateRole, UpdateUser,
+        },
+        role::{Permissions, Role},
+        snowflake::Snowflake,
+        standing::{StandingState, Strike, StrikePenalty},
+        user::User,
+    },
 };
+use chrono::SubsecRound;
+use futures::TryStreamExt;
+use secrecy::Secret;
 use sqlx::PgPool;
 use utils::fixture_constants::basic::{
     BASIC_GUILD_1, BASIC_GUILD_1_BOT, BASIC_GUILD_1_GENERAL, BASIC_GUILD_1_RANDOM, BASIC_GUILD_1_STAFF, BASIC_GUILD_2,
-    BASIC_USER_1, BASIC_USER_2,
+    BASIC_GUILD_2_GENERAL, BASIC_USER_1, BASIC_USER_2,
 }; // add import for channel types
 
 mod utils;
@@ -21,7 +48,13 @@ mod utils;
 #[sqlx::test(fixtures("basic"))]
 async fn test_user_fetch(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let user = app.ops().fetch_user(BASIC_USER_1).await.expect("DB operation failed");
+    let user = app
+        .ops()
+        .users()
+        .fetch_user(BASIC_USER_1)
+        .await
+        .unwrap()
+        .expect("DB operation failed");
     assert_eq!(user.id(), BASIC_USER_1);
     assert_eq!(user.username(), "test");
 }
@@ -31,10 +64,11 @@ async fn test_update_and_fetch_read_states(pool: PgPool) {
     let app = utils::DBApp::new(pool);
 
     app.ops()
+        .notifications()
         .update_read_state(BASIC_USER_1, BASIC_GUILD_1_GENERAL, 100_i64)
         .await
         .unwrap();
-    let states = app.ops().fetch_read_states(BASIC_USER_1).await.unwrap();
+    let states = app.ops().notifications().fetch_read_states(BASIC_USER_1).await.unwrap();
     let state = states
         .iter()
         .find(|s| s.channel_id == BASIC_GUILD_1_GENERAL)
@@ -42,10 +76,11 @@ async fn test_update_and_fetch_read_states(pool: PgPool) {
     assert_eq!(state.last_read_message_id.unwrap(), 100_i64.into());
 
     app.ops()
+        .notifications()
         .update_read_state(BASIC_USER_1, BASIC_GUILD_1_GENERAL, 50_i64)
         .await
         .unwrap();
-    let states = app.ops().fetch_read_states(BASIC_USER_1).await.unwrap();
+    let states = app.ops().notifications().fetch_read_states(BASIC_USER_1).await.unwrap();
     let state = states
         .iter()
         .find(|s| s.channel_id == BASIC_GUILD_1_GENERAL)
@@ -53,10 +88,11 @@ async fn test_update_and_fetch_read_states(pool: PgPool) {
     assert_eq!(state.last_read_message_id.unwrap(), 100_i64.into());
 
     app.ops()
+        .notifications()
         .update_read_state(BASIC_USER_1, BASIC_GUILD_1_GENERAL, 150_i64)
         .await
         .unwrap();
-    let states = app.ops().fetch_read_states(BASIC_USER_1).await.unwrap();
+    let states = app.ops().notifications().fetch_read_states(BASIC_USER_1).await.unwrap();
     let state = states
         .iter()
         .find(|s| s.channel_id == BASIC_GUILD_1_GENERAL)
@@ -64,53 +100,303 @@ async fn test_update_and_fetch_read_states(pool: PgPool) {
     assert_eq!(state.last_read_message_id.unwrap(), 150_i64.into());
 }
 
+#[sqlx::test(fixtures("basic"))]
+async fn test_read_states_hidden_channels(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let guild = app.ops().guilds().fetch_guild(BASIC_GUILD_2).await.unwrap().unwrap();
+    let general = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_2_GENERAL)
+        .await
+        .unwrap()
+        .unwrap();
+    let hidden = TextChannel::new(Snowflake::gen_new(app.config()), &guild, "hidden").into();
+    let hidden = app.ops().guilds().create_channel(&hidden).await.unwrap();
+
+    let link = GuestLink::new(&guild, &general, BASIC_USER_2, false, 3600, None);
+    app.ops().guilds().create_guest_link(&link).await.unwrap();
+    app.ops().guilds().create_guest(&link, BASIC_USER_1).await.unwrap();
+
+    // A read state left over from when the channel was visible must not leak
+    app.ops()
+        .notifications()
+        .update_read_state(BASIC_USER_1, hidden.id(), 100_i64)
+        .await
+        .unwrap();
+    let states = app.ops().notifications().fetch_read_states(BASIC_USER_1).await.unwrap();
+    assert!(states.iter().all(|s| s.channel_id != hidden.id()));
+    assert!(states.iter().any(|s| s.channel_id == BASIC_GUILD_2_GENERAL));
+
+    // Once the channel becomes visible, its read state is returned again
+    app.ops()
+        .guilds()
+        .create_member(BASIC_GUILD_2, BASIC_USER_1)
+        .await
+        .unwrap();
+    let states = app.ops().notifications().fetch_read_states(BASIC_USER_1).await.unwrap();
+    let state = states
+        .iter()
+        .find(|s| s.channel_id == hidden.id())
+        .expect("State for channel should exist");
+    assert_eq!(state.last_read_message_id, Some(100_i64.into()));
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_notification_overrides(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let notifications = app.ops().notifications();
+
+    assert!(
+        notifications
+            .fetch_notification_overrides(BASIC_USER_1)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    notifications
+        .set_notification_override(BASIC_USER_1, BASIC_GUILD_1_RANDOM, NotificationLevel::Nothing)
+        .await
+        .unwrap();
+    // Setting a level again replaces the previous one
+    let set = notifications
+        .set_notification_override(BASIC_USER_1, BASIC_GUILD_1_GENERAL, NotificationLevel::Nothing)
+        .await
+        .unwrap();
+    assert_eq!(
+        notifications
+            .set_notification_override(BASIC_USER_1, BASIC_GUILD_1_GENERAL, NotificationLevel::AllMessages)
+            .await
+            .unwrap(),
+        NotificationOverride {
+            level: NotificationLevel::AllMessages,
+            ..set
+        }
+    );
+    assert_eq!(
+        notifications.fetch_notification_overrides(BASIC_USER_1).await.unwrap(),
+        vec![
+            NotificationOverride {
+                channel_id: BASIC_GUILD_1_GENERAL,
+                level: NotificationLevel::AllMessages,
+            },
+            NotificationOverride {
+                channel_id: BASIC_GUILD_1_RANDOM,
+                level: NotificationLevel::Nothing,
+            },
+        ]
+    );
+    // Overrides are personal
+    assert!(
+        notifications
+            .fetch_notification_overrides(BASIC_USER_2)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    assert!(
+        notifications
+            .remove_notification_override(BASIC_USER_1, BASIC_GUILD_1_GENERAL)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !notifications
+            .remove_notification_override(BASIC_USER_1, BASIC_GUILD_1_GENERAL)
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        notifications
+            .fetch_notification_overrides(BASIC_USER_1)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // Guilds default to waking devices for mentions only
+    let guild = app.ops().guilds().fetch_guild(BASIC_GUILD_1).await.unwrap().unwrap();
+    assert_eq!(guild.default_notification_level(), NotificationLevel::OnlyMentions);
+    let payload = UpdateGuild {
+        name: None,
+        owner_id: None,
+        avatar: OmittableOption::Omitted,
+        attachment_archive_days: OmittableOption::Omitted,
+        hide_history_before_join: None,
+        default_notification_level: Some(NotificationLevel::AllMessages),
+    };
+    let guild = app.ops().guilds().update_guild(payload, &guild).await.unwrap();
+    assert_eq!(guild.default_notification_level(), NotificationLevel::AllMessages);
+}
+
+#[sqlx::test(fixtures("basic", "basic_messages"))]
+async fn test_unread_count(pool: PgPool) {
+    let app = utils::DBApp::new(pool.clone());
+
+    // Channels that were never read count every message, up to the cap
+    let count = app
+        .ops()
+        .notifications()
+        .fetch_unread_count(BASIC_USER_1, BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap();
+    assert_eq!(count, MAX_UNREAD_COUNT);
+
+    let states = app.ops().notifications().fetch_read_states(BASIC_USER_1).await.unwrap();
+    let state = states.iter().find(|s| s.channel_id == BASIC_GUILD_1_GENERAL).unwrap();
+    assert_eq!(state.last_viewed_at, None);
+
+    let ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM messages WHERE channel_id = $1 ORDER BY id DESC LIMIT 6")
+        .bind(BASIC_GUILD_1_GENERAL)
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+
+    app.ops()
+        .notifications()
+        .update_read_state(BASIC_USER_1, BASIC_GUILD_1_GENERAL, ids[5])
+        .await
+        .unwrap();
+    let count = app
+        .ops()
+        .notifications()
+        .fetch_unread_count(BASIC_USER_1, BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap();
+    assert_eq!(count, 5);
+
+    let states = app.ops().notifications().fetch_read_states(BASIC_USER_1).await.unwrap();
+    let state = states.iter().find(|s| s.channel_id == BASIC_GUILD_1_GENERAL).unwrap();
+    assert!(state.last_viewed_at.is_some());
+
+    app.ops()
+        .notifications()
+        .update_read_state(BASIC_USER_1, BASIC_GUILD_1_GENERAL, ids[0])
+        .await
+        .unwrap();
+    let count = app
+        .ops()
+        .notifications()
+        .fetch_unread_count(BASIC_USER_1, BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
 #[sqlx::test(fixtures("basic"))]
 async fn test_is_channel_present(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let exists = app.ops().is_channel_present(BASIC_GUILD_1_GENERAL).await.unwrap();
+    let exists = app
+        .ops()
+        .guilds()
+        .is_channel_present(BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap();
     assert!(exists);
-    let not_exists = app.ops().is_channel_present(999999_i64).await.unwrap();
+    let not_exists = app.ops().guilds().is_channel_present(999999_i64).await.unwrap();
     assert!(!not_exists);
 }
 
 #[sqlx::test(fixtures("basic"))]
 async fn test_fetch_channel(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let channel = app.ops().fetch_channel(BASIC_GUILD_1_GENERAL).await;
+    let channel = app.ops().guilds().fetch_channel(BASIC_GUILD_1_GENERAL).await.unwrap();
     assert!(channel.is_some());
 }
 
 #[sqlx::test(fixtures("basic"))]
 async fn test_create_channel(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let existing = app.ops().fetch_channel(BASIC_GUILD_1_GENERAL).await.unwrap();
-    let guild = app.ops().fetch_guild(existing.guild_id()).await.unwrap();
+    let existing = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap()
+        .unwrap();
+    let guild = app
+        .ops()
+        .guilds()
+        .fetch_guild(existing.guild_id().unwrap())
+        .await
+        .unwrap()
+        .unwrap();
     let new_id = Snowflake::gen_new(app.config());
-    let test_channel = TextChannel::new(new_id, &guild, "test-channel".to_owned()).into();
-    let created = app.ops().create_channel(&test_channel).await.unwrap();
+    let test_channel = TextChannel::new(new_id, &guild, "test-channel").into();
+    let created = app.ops().guilds().create_channel(&test_channel).await.unwrap();
     assert_eq!(created.name(), "test-channel");
-    assert_eq!(created.guild_id(), guild.id());
+    assert_eq!(created.guild_id(), Some(guild.id()));
     assert_eq!(created.id(), new_id);
+
+    // Names are unique within a guild once normalized
+    let duplicate = TextChannel::new(Snowflake::gen_new(app.config()), &guild, " Test Channel ").into();
+    assert!(matches!(
+        app.ops().guilds().create_channel(&duplicate).await,
+        Err(OpsError::AlreadyTaken { field: "name" })
+    ));
+    let elsewhere = TextChannel::new(Snowflake::gen_new(app.config()), BASIC_GUILD_2, "Test Channel").into();
+    app.ops().guilds().create_channel(&elsewhere).await.unwrap();
 }
 
 #[sqlx::test(fixtures("basic"))]
 async fn test_update_channel(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let mut existing = app.ops().fetch_channel(BASIC_GUILD_1_GENERAL).await.unwrap();
-    existing.name_mut().clear();
-    existing.name_mut().push_str("updated-channel");
+    let mut existing = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap()
+        .unwrap();
+    existing.update(UpdateChannel {
+        name: Some("updated-channel".to_owned()),
+        locked: None,
+        retention_days: OmittableOption::Omitted,
+    });
 
-    app.ops().update_channel(&existing).await.unwrap();
-    let updated = app.ops().fetch_channel(BASIC_GUILD_1_GENERAL).await.unwrap();
+    app.ops().guilds().update_channel(&existing).await.unwrap();
+    let updated = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap()
+        .unwrap();
     assert_eq!(updated.name(), "updated-channel");
+
+    let mut random = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_1_RANDOM)
+        .await
+        .unwrap()
+        .unwrap();
+    random.update(UpdateChannel {
+        name: Some("Updated Channel".to_owned()),
+        locked: None,
+        retention_days: OmittableOption::Omitted,
+    });
+    assert!(matches!(
+        app.ops().guilds().update_channel(&random).await,
+        Err(OpsError::AlreadyTaken { field: "name" })
+    ));
 }
 
 #[sqlx::test(fixtures("basic"))]
 async fn test_delete_channel(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let existing = app.ops().fetch_channel(BASIC_GUILD_1_GENERAL).await.unwrap();
-    app.ops().delete_channel(existing.id()).await.unwrap();
-    let exists = app.ops().is_channel_present(existing.id()).await.unwrap();
+    let existing = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap()
+        .unwrap();
+    app.ops().guilds().delete_channel(existing.id()).await.unwrap();
+    let exists = app.ops().guilds().is_channel_present(existing.id()).await.unwrap();
     assert!(!exists);
 }
 
@@ -119,30 +405,64 @@ async fn test_fetch_messages_default(pool: PgPool) {
     let app = utils::DBApp::new(pool);
     let messages = app
         .ops()
+        .messages()
         .fetch_messages_from(
             BASIC_GUILD_1_GENERAL,
             None,
             None::<Snowflake<Message>>,
             None::<Snowflake<Message>>,
             None::<Snowflake<Message>>,
+            None,
         )
         .await
         .expect("Failed to fetch messages");
     assert_eq!(messages.len(), 50, "Expected 50 messages");
 }
 
+#[sqlx::test(fixtures("basic", "basic_messages"))]
+async fn test_fetch_messages_floor(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let messages = app.ops().messages();
+    // The newest message in the channel
+    let newest = Snowflake::<Message>::new(289530032228012033);
+    let fetch = |before: Option<Snowflake<Message>>, around: Option<Snowflake<Message>>, floor: Snowflake<Message>| {
+        messages.fetch_messages_from(
+            BASIC_GUILD_1_GENERAL,
+            Some(10),
+            before,
+            None::<Snowflake<Message>>,
+            around,
+            Some(floor),
+        )
+    };
+
+    // A message sent exactly at the floor is visible
+    let fetched = fetch(None, None, newest).await.unwrap();
+    assert_eq!(fetched.iter().map(Message::id).collect::<Vec<_>>(), vec![newest]);
+    let fetched = fetch(None, Some(newest), newest).await.unwrap();
+    assert_eq!(fetched.iter().map(Message::id).collect::<Vec<_>>(), vec![newest]);
+
+    // Messages just below it are not, even when paginating or fetching around them
+    let floor = Snowflake::new(i64::from(newest) + 1);
+    assert!(fetch(None, None, floor).await.unwrap().is_empty());
+    assert!(fetch(None, Some(newest), floor).await.unwrap().is_empty());
+    assert!(fetch(Some(newest), None, newest).await.unwrap().is_empty());
+}
+
 #[sqlx::test(fixtures("basic", "basic_messages"))]
 async fn test_fetch_messages_before(pool: PgPool) {
     let app = utils::DBApp::new(pool);
     let before_id = 289530032228012033_i64;
     let mut messages = app
         .ops()
+        .messages()
         .fetch_messages_from(
             BASIC_GUILD_1_GENERAL,
             Some(10),
             Some(before_id),
             None::<Snowflake<Message>>,
             None::<Snowflake<Message>>,
+            None,
         )
         .await
         .expect("Failed to fetch messages with 'before'");
@@ -168,12 +488,14 @@ async fn test_fetch_messages_before_partial(pool: PgPool) {
     let before_id = 289528069797056514_i64;
     let messages = app
         .ops()
+        .messages()
         .fetch_messages_from(
             BASIC_GUILD_1_GENERAL,
             Some(10),
             Some(before_id),
             None::<Snowflake<Message>>,
             None::<Snowflake<Message>>,
+            None,
         )
         .await
         .expect("Failed to fetch messages with 'before'");
@@ -196,12 +518,14 @@ async fn test_fetch_messages_after(pool: PgPool) {
     let after_id = 278891037475344385_i64;
     let mut messages = app
         .ops()
+        .messages()
         .fetch_messages_from(
             BASIC_GUILD_1_GENERAL,
             Some(10),
             None::<Snowflake<Message>>,
             Some(after_id),
             None::<Snowflake<Message>>,
+            None,
         )
         .await
         .expect("Failed to fetch messages with 'after'");
@@ -227,12 +551,14 @@ async fn test_fetch_messages_after_partial(pool: PgPool) {
     let after_id = 289530032198651904_i64;
     let messages = app
         .ops()
+        .messages()
         .fetch_messages_from(
             BASIC_GUILD_1_GENERAL,
             Some(10),
             None::<Snowflake<Message>>,
             Some(after_id),
             None::<Snowflake<Message>>,
+            None,
         )
         .await
         .expect("Failed to fetch messages with 'after'");
@@ -252,12 +578,14 @@ async fn test_fetch_messages_around(pool: PgPool) {
     let anchor_id = 278891037475344385_i64;
     let messages = app
         .ops()
+        .messages()
         .fetch_messages_from(
             BASIC_GUILD_1_GENERAL,
             Some(10),
             None::<Snowflake<Message>>,
             None::<Snowflake<Message>>,
             Some(anchor_id),
+            None,
         )
         .await
         .expect("Failed to fetch messages with 'around'");
@@ -275,24 +603,26 @@ async fn test_fetch_messages_bad_request(pool: PgPool) {
     // Providing both before and after should trigger a bad request error.
     let res = app
         .ops()
+        .messages()
         .fetch_messages_from(
             BASIC_GUILD_1_GENERAL,
             Some(10),
             Some(278891037475344385_i64),
             Some(278891037475344385_i64),
             None::<Snowflake<Message>>,
+            None,
         )
         .await;
     match res {
-        Err(RESTError::BadRequest(_)) => {}
-        _ => panic!("Expected RESTError::BadRequest when both 'before' and 'after' parameters are provided"),
+        Err(OpsError::BadRequest(_)) => {}
+        _ => panic!("Expected OpsError::BadRequest when both 'before' and 'after' parameters are provided"),
     }
 }
 
 #[sqlx::test(fixtures("basic"))]
 async fn test_fetch_guild(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let guild = app.ops().fetch_guild(BASIC_GUILD_1).await.unwrap();
+    let guild = app.ops().guilds().fetch_guild(BASIC_GUILD_1).await.unwrap().unwrap();
     assert_eq!(guild.id(), BASIC_GUILD_1);
     assert_eq!(guild.name(), "Test Guild");
     assert_eq!(guild.owner_id(), BASIC_USER_1);
@@ -305,49 +635,86 @@ async fn test_create_guild(pool: PgPool) {
     let payload = CreateGuild {
         name: "Test Guild".to_owned(),
     };
-    let (guild, general_channel, owner) = app.ops().create_guild(payload, BASIC_USER_1).await.unwrap();
+    let (guild, general_channel, owner) = app.ops().guilds().create_guild(payload, BASIC_USER_1).await.unwrap();
     assert_eq!(guild.name(), "Test Guild");
     assert_eq!(guild.owner_id(), BASIC_USER_1);
-    assert_eq!(general_channel.guild_id(), guild.id());
+    assert_eq!(general_channel.guild_id(), Some(guild.id()));
     assert_eq!(owner.user().id(), BASIC_USER_1);
 }
 
 #[sqlx::test(fixtures("basic"))]
 async fn test_update_guild(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let guild = app.ops().fetch_guild(BASIC_GUILD_1).await.unwrap();
+    let guild = app.ops().guilds().fetch_guild(BASIC_GUILD_1).await.unwrap().unwrap();
     let update_payload = UpdateGuild {
         name: Some("Updated Guild".to_owned()),
         owner_id: None,
         avatar: OmittableOption::Omitted,
+        attachment_archive_days: OmittableOption::Omitted,
+        hide_history_before_join: None,
+        default_notification_level: None,
     };
-    let updated = app.ops().update_guild(update_payload, &guild).await.unwrap();
+    let updated = app.ops().guilds().update_guild(update_payload, &guild).await.unwrap();
     assert_eq!(updated.name(), "Updated Guild");
     assert_eq!(updated.owner_id(), guild.owner_id());
     assert_eq!(updated.avatar(), guild.avatar());
+    assert_eq!(updated.attachment_archive_days(), None);
+
+    let update_payload = UpdateGuild {
+        name: None,
+        owner_id: None,
+        avatar: OmittableOption::Omitted,
+        attachment_archive_days: OmittableOption::Some(30),
+        hide_history_before_join: None,
+        default_notification_level: None,
+    };
+    let updated = app.ops().guilds().update_guild(update_payload, &updated).await.unwrap();
+    assert_eq!(updated.attachment_archive_days(), Some(30));
+    assert_eq!(
+        app.ops()
+            .guilds()
+            .fetch_guild(BASIC_GUILD_1)
+            .await
+            .unwrap()
+            .unwrap()
+            .attachment_archive_days(),
+        Some(30)
+    );
+
+    let update_payload = UpdateGuild {
+        name: None,
+        owner_id: None,
+        avatar: OmittableOption::Omitted,
+        attachment_archive_days: OmittableOption::Some(0),
+        hide_history_before_join: None,
+        default_notification_level: None,
+    };
+    assert!(app.ops().guilds().update_guild(update_payload, &updated).await.is_err());
 }
 
 #[sqlx::test(fixtures("basic"))]
 async fn test_delete_guild(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    app.ops().delete_guild(BASIC_GUILD_1).await.unwrap();
-    let fetched = app.ops().fetch_guild(BASIC_GUILD_1).await;
+    app.ops().guilds().delete_guild(BASIC_GUILD_1).await.unwrap();
+    let fetched = app.ops().guilds().fetch_guild(BASIC_GUILD_1).await.unwrap();
     assert!(fetched.is_none());
 
-    let channels = app.ops().fetch_channels_for(BASIC_GUILD_1).await.unwrap();
+    let channels = app.ops().guilds().fetch_channels_for(BASIC_GUILD_1).await.unwrap();
     assert!(channels.is_empty(), "Channels should be deleted with the guild");
 
-    let members = app.ops().fetch_members_for(BASIC_GUILD_1).await.unwrap();
+    let members = app.ops().guilds().fetch_members_for(BASIC_GUILD_1).await.unwrap();
     assert!(members.is_empty(), "Members should be deleted with the guild");
 
     let messages = app
         .ops()
+        .messages()
         .fetch_messages_from(
             BASIC_GUILD_1_GENERAL,
             None,
             None::<Snowflake<Message>>,
             None::<Snowflake<Message>>,
             None::<Snowflake<Message>>,
+            None,
         )
         .await
         .unwrap();
@@ -357,25 +724,106 @@ async fn test_delete_guild(pool: PgPool) {
 #[sqlx::test(fixtures("basic"))]
 async fn test_fetch_guild_owner(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let guild = app.ops().fetch_guild(BASIC_GUILD_1).await.unwrap();
-    let owner = app.ops().fetch_guild_owner(&guild).await.unwrap();
+    let guild = app.ops().guilds().fetch_guild(BASIC_GUILD_1).await.unwrap().unwrap();
+    let owner = app.ops().guilds().fetch_guild_owner(&guild).await.unwrap();
     assert_eq!(owner.user().id(), guild.owner_id());
+
+    // The guild may be gone by the time its owner is fetched
+    app.ops().guilds().delete_guild(&guild).await.unwrap();
+    assert!(matches!(
+        app.ops().guilds().fetch_guild_owner(&guild).await,
+        Err(OpsError::NotFound(_))
+    ));
 }
 
 #[sqlx::test(fixtures("basic"))]
 async fn test_fetch_members_for(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let members = app.ops().fetch_members_for(BASIC_GUILD_1).await.unwrap();
+    let members = app.ops().guilds().fetch_members_for(BASIC_GUILD_1).await.unwrap();
     assert!(members.len() >= 2, "Expected at least two members in the guild");
     let member_ids: Vec<_> = members.into_iter().map(|m| m.user().id()).collect();
     assert!(member_ids.contains(&BASIC_USER_1), "BASIC_USER_1 not found in members");
     assert!(member_ids.contains(&BASIC_USER_2), "BASIC_USER_2 not found in members");
 }
 
+#[sqlx::test(fixtures("basic"))]
+async fn test_search_members(pool: PgPool) {
+    let app = utils::DBApp::new(pool.clone());
+
+    let members = app
+        .ops()
+        .guilds()
+        .search_members(BASIC_GUILD_1, None, None, None)
+        .await
+        .unwrap();
+    let member_ids: Vec<_> = members.iter().map(|m| m.user().id()).collect();
+    assert_eq!(member_ids, vec![BASIC_USER_1, BASIC_USER_2]);
+
+    let members = app
+        .ops()
+        .guilds()
+        .search_members(BASIC_GUILD_1, None, None, Some(1))
+        .await
+        .unwrap();
+    assert_eq!(members.len(), 1);
+
+    // Pages continue after the last member of the previous one
+    let members = app
+        .ops()
+        .guilds()
+        .search_members(BASIC_GUILD_1, None, Some(members[0].user().id()), None)
+        .await
+        .unwrap();
+    let member_ids: Vec<_> = members.iter().map(|m| m.user().id()).collect();
+    assert_eq!(member_ids, vec![BASIC_USER_2]);
+
+    // Usernames are matched by prefix, case-insensitively
+    let members = app
+        .ops()
+        .guilds()
+        .search_members(BASIC_GUILD_1, Some("TEST2"), None, None)
+        .await
+        .unwrap();
+    let member_ids: Vec<_> = members.iter().map(|m| m.user().id()).collect();
+    assert_eq!(member_ids, vec![BASIC_USER_2]);
+
+    // Nicknames are matched too
+    sqlx::query("UPDATE members SET nickname = 'Amogus' WHERE user_id = $1 AND guild_id = $2")
+        .bind(BASIC_USER_1)
+        .bind(BASIC_GUILD_1)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let members = app
+        .ops()
+        .guilds()
+        .search_members(BASIC_GUILD_1, Some("amo"), None, None)
+        .await
+        .unwrap();
+    let member_ids: Vec<_> = members.iter().map(|m| m.user().id()).collect();
+    assert_eq!(member_ids, vec![BASIC_USER_1]);
+
+    // Wildcards are matched literally
+    let members = app
+        .ops()
+        .guilds()
+        .search_members(BASIC_GUILD_1, Some("%"), None, None)
+        .await
+        .unwrap();
+    assert!(members.is_empty());
+    let members = app
+        .ops()
+        .guilds()
+        .search_members(BASIC_GUILD_1, Some("te_t"), None, None)
+        .await
+        .unwrap();
+    assert!(members.is_empty());
+}
+
 #[sqlx::test(fixtures("basic"))]
 async fn test_fetch_channels_for(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let channels = app.ops().fetch_channels_for(BASIC_GUILD_1).await.unwrap();
+    let channels = app.ops().guilds().fetch_channels_for(BASIC_GUILD_1).await.unwrap();
     assert_eq!(channels.len(), 4, "Expected 4 channels in the guild");
     assert!(
         channels.iter().any(|c| c.id() == BASIC_GUILD_1_GENERAL),
@@ -400,6 +848,7 @@ async fn test_create_and_fetch_member(pool: PgPool) {
     let app = utils::DBApp::new(pool);
     let (guild, _channel, _owner) = app
         .ops()
+        .guilds()
         .create_guild(
             CreateGuild {
                 name: "Member Test Guild".to_owned(),
@@ -408,11 +857,16 @@ async fn test_create_and_fetch_member(pool: PgPool) {
         )
         .await
         .unwrap();
-    let member = app.ops().fetch_member(BASIC_USER_2, guild.id()).await.unwrap();
+    let member = app.ops().guilds().fetch_member(BASIC_USER_2, guild.id()).await.unwrap();
     assert!(member.is_none(), "BASIC_USER_2 should not be a member initially");
-    let new_member = app.ops().create_member(guild.id(), BASIC_USER_2).await.unwrap();
+    let new_member = app
+        .ops()
+        .guilds()
+        .create_member(guild.id(), BASIC_USER_2)
+        .await
+        .unwrap();
     assert_eq!(new_member.user().id(), BASIC_USER_2);
-    let fetched = app.ops().fetch_member(BASIC_USER_2, guild.id()).await.unwrap();
+    let fetched = app.ops().guilds().fetch_member(BASIC_USER_2, guild.id()).await.unwrap();
     assert!(fetched.is_some(), "Member should be found after creation");
 }
 
@@ -421,6 +875,7 @@ async fn test_update_member_nickname(pool: PgPool) {
     let app = utils::DBApp::new(pool);
     let (guild, _channel, _owner) = app
         .ops()
+        .guilds()
         .create_guild(
             CreateGuild {
                 name: "Nickname Test Guild".to_owned(),
@@ -429,21 +884,47 @@ async fn test_update_member_nickname(pool: PgPool) {
         )
         .await
         .unwrap();
-    let mut member = app.ops().create_member(guild.id(), BASIC_USER_2).await.unwrap();
+    let mut member = app
+        .ops()
+        .guilds()
+        .create_member(guild.id(), BASIC_USER_2)
+        .await
+        .unwrap();
     member.nickname_mut().replace("CoolNickname".to_owned());
-    app.ops().update_member(&member).await.unwrap();
-    let updated = app.ops().fetch_member(BASIC_USER_2, guild.id()).await.unwrap().unwrap();
+    app.ops().guilds().update_member(&member).await.unwrap();
+    let updated = app
+        .ops()
+        .guilds()
+        .fetch_member(BASIC_USER_2, guild.id())
+        .await
+        .unwrap()
+        .unwrap();
     assert_eq!(updated.nickname().as_deref(), Some("CoolNickname"));
 }
 
 #[sqlx::test(fixtures("basic"))]
 async fn test_has_member_function(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let has_user1 = app.ops().has_member(BASIC_GUILD_1, BASIC_USER_1).await.unwrap();
+    let has_user1 = app
+        .ops()
+        .guilds()
+        .has_member(BASIC_GUILD_1, BASIC_USER_1)
+        .await
+        .unwrap();
     assert!(has_user1, "BASIC_USER_1 should be a member of BASIC_GUILD_1");
-    let has_user2 = app.ops().has_member(BASIC_GUILD_1, BASIC_USER_2).await.unwrap();
+    let has_user2 = app
+        .ops()
+        .guilds()
+        .has_member(BASIC_GUILD_1, BASIC_USER_2)
+        .await
+        .unwrap();
     assert!(has_user2, "BASIC_USER_2 should be a member of BASIC_GUILD_1");
-    let has_user_1_in_guild_2 = app.ops().has_member(BASIC_GUILD_2, BASIC_USER_1).await.unwrap();
+    let has_user_1_in_guild_2 = app
+        .ops()
+        .guilds()
+        .has_member(BASIC_GUILD_2, BASIC_USER_1)
+        .await
+        .unwrap();
     assert!(
         !has_user_1_in_guild_2,
         "BASIC_USER_1 should not be a member of BASIC_GUILD_2"
@@ -455,6 +936,7 @@ async fn test_delete_member_non_owner(pool: PgPool) {
     let app = utils::DBApp::new(pool);
     let (guild, _channel, _owner) = app
         .ops()
+        .guilds()
         .create_guild(
             CreateGuild {
                 name: "DeleteMember Guild".to_owned(),
@@ -463,21 +945,25 @@ async fn test_delete_member_non_owner(pool: PgPool) {
         )
         .await
         .unwrap();
-    app.ops().create_member(guild.id(), BASIC_USER_2).await.unwrap();
-    let member = app.ops().fetch_member(BASIC_USER_2, guild.id()).await.unwrap();
+    app.ops()
+        .guilds()
+        .create_member(guild.id(), BASIC_USER_2)
+        .await
+        .unwrap();
+    let member = app.ops().guilds().fetch_member(BASIC_USER_2, guild.id()).await.unwrap();
     assert!(member.is_some(), "Member should exist before deletion");
-    app.ops().delete_member(&guild, BASIC_USER_2).await.unwrap();
-    let after_delete = app.ops().fetch_member(BASIC_USER_2, guild.id()).await.unwrap();
+    app.ops().guilds().delete_member(&guild, BASIC_USER_2).await.unwrap();
+    let after_delete = app.ops().guilds().fetch_member(BASIC_USER_2, guild.id()).await.unwrap();
     assert!(after_delete.is_none(), "Member should be deleted");
 }
 
 #[sqlx::test(fixtures("basic"))]
 async fn test_delete_member_owner_error(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let guild = app.ops().fetch_guild(BASIC_GUILD_1).await.unwrap();
-    let res = app.ops().delete_member(&guild, BASIC_USER_1).await;
+    let guild = app.ops().guilds().fetch_guild(BASIC_GUILD_1).await.unwrap().unwrap();
+    let res = app.ops().guilds().delete_member(&guild, BASIC_USER_1).await;
     match res {
-        Err(RESTError::Forbidden(_)) => { /* expected */ }
+        Err(OpsError::Forbidden(_)) => { /* expected */ }
         _ => panic!("Deleting guild owner should return a Forbidden error"),
     }
 }
@@ -487,7 +973,13 @@ async fn test_commit_and_fetch_message(pool: PgPool) {
     let app = utils::DBApp::new(pool);
     let msg_id = Snowflake::gen_new(app.config());
 
-    let author = app.ops().fetch_user(BASIC_USER_1).await.expect("fetch_user failed");
+    let author = app
+        .ops()
+        .users()
+        .fetch_user(BASIC_USER_1)
+        .await
+        .unwrap()
+        .expect("fetch_user failed");
 
     let message = Message::builder()
         .id(msg_id)
@@ -497,9 +989,18 @@ async fn test_commit_and_fetch_message(pool: PgPool) {
         .build()
         .expect("Failed to build message");
 
-    app.ops().commit_message(&message).await.expect("commit_message failed");
+    app.ops()
+        .messages()
+        .commit_message(&message)
+        .await
+        .expect("commit_message failed");
 
-    let fetched = app.ops().fetch_message(msg_id).await.expect("fetch_message failed");
+    let fetched = app
+        .ops()
+        .messages()
+        .fetch_message(msg_id)
+        .await
+        .expect("fetch_message failed");
     assert!(fetched.is_some(), "Message should exist after commit");
     let fetched_msg = fetched.unwrap();
     assert_eq!(fetched_msg.id(), msg_id);
@@ -507,6 +1008,8 @@ async fn test_commit_and_fetch_message(pool: PgPool) {
     assert_eq!(fetched_msg.author().map(UserLike::id), Some(BASIC_USER_1));
     assert!(fetched_msg.attachments().is_empty());
     assert_eq!(fetched_msg.channel_id(), BASIC_GUILD_1_GENERAL);
+    // The timestamp sent to clients matches the one stored
+    assert_eq!(fetched_msg.created_at(), message.created_at());
 }
 
 #[sqlx::test(fixtures("basic"))]
@@ -514,7 +1017,13 @@ async fn test_update_message(pool: PgPool) {
     let app = utils::DBApp::new(pool);
     let msg_id = Snowflake::gen_new(app.config());
 
-    let author = app.ops().fetch_user(BASIC_USER_1).await.expect("fetch_user failed");
+    let author = app
+        .ops()
+        .users()
+        .fetch_user(BASIC_USER_1)
+        .await
+        .unwrap()
+        .expect("fetch_user failed");
 
     let message = Message::builder()
         .id(msg_id)
@@ -524,17 +1033,26 @@ async fn test_update_message(pool: PgPool) {
         .build()
         .expect("Failed to build message");
 
-    app.ops().commit_message(&message).await.expect("commit_message failed");
+    app.ops()
+        .messages()
+        .commit_message(&message)
+        .await
+        .expect("commit_message failed");
 
     let update_payload = UpdateMessage {
         content: OmittableOption::Some("Updated content".to_owned()),
     };
     let updated_msg = app
         .ops()
+        .messages()
         .update_message(msg_id, update_payload)
         .await
         .expect("update_message failed");
     assert_eq!(updated_msg.content(), Some("Updated content"));
+
+    let fetched = app.ops().messages().fetch_message(msg_id).await.unwrap().unwrap();
+    assert!(updated_msg.edited_at().is_some());
+    assert_eq!(fetched.edited_at(), updated_msg.edited_at());
 }
 
 #[sqlx::test(fixtures("basic"))]
@@ -542,7 +1060,13 @@ async fn test_delete_message(pool: PgPool) {
     let app = utils::DBApp::new(pool);
     let msg_id = Snowflake::gen_new(app.config());
 
-    let author = app.ops().fetch_user(BASIC_USER_1).await.expect("fetch_user failed");
+    let author = app
+        .ops()
+        .users()
+        .fetch_user(BASIC_USER_1)
+        .await
+        .unwrap()
+        .expect("fetch_user failed");
 
     let message = Message::builder()
         .id(msg_id)
@@ -552,33 +1076,126 @@ async fn test_delete_message(pool: PgPool) {
         .build()
         .expect("Failed to build message");
 
-    app.ops().commit_message(&message).await.expect("commit_message failed");
+    app.ops()
+        .messages()
+        .commit_message(&message)
+        .await
+        .expect("commit_message failed");
 
     app.ops()
+        .messages()
         .delete_message(BASIC_GUILD_1_GENERAL, msg_id)
         .await
         .expect("delete_message failed");
-    let fetched = app.ops().fetch_message(msg_id).await.expect("fetch_message failed");
+    let fetched = app
+        .ops()
+        .messages()
+        .fetch_message(msg_id)
+        .await
+        .expect("fetch_message failed");
     assert!(fetched.is_none(), "Message should be deleted");
 }
 
+#[sqlx::test(fixtures("basic"))]
+#[expect(clippy::disallowed_methods)] // Enqueues entries without a change to follow
+async fn test_outbox(pool: PgPool) {
+    let app = utils::DBApp::new(pool.clone());
+    let pending = async || {
+        sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM event_outbox"#)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+    };
+
+    let author = app.ops().users().fetch_user(BASIC_USER_1).await.unwrap().unwrap();
+    let message = Message::builder()
+        .id(Snowflake::gen_new(app.config()))
+        .author(UserLike::User(author))
+        .channel_id(BASIC_GUILD_1_GENERAL)
+        .content(Some("Hello".to_owned()))
+        .build()
+        .unwrap();
+
+    // Entries are committed along with the message, and emitted once relayed
+    let create = OutboxEntry::dispatch(
+        &GatewayEvent::MessageCreate(message.clone()),
+        SendMode::ToGuild(BASIC_GUILD_1),
+    );
+    app.ops()
+        .messages()
+        .commit_message_with(&message, std::slice::from_ref(&create))
+        .await
+        .unwrap();
+    assert_eq!(pending().await, 1);
+
+    app.ops()
+        .messages()
+        .delete_message(BASIC_GUILD_1_GENERAL, message.id())
+        .await
+        .unwrap();
+    assert_eq!(pending().await, 2);
+
+    assert_eq!(app.ops().outbox().relay().await.unwrap(), 2);
+    assert_eq!(pending().await, 0);
+
+    // Entries that cannot be read are dropped instead of blocking the outbox
+    sqlx::query!("INSERT INTO event_outbox (entry) VALUES ('not an entry')")
+        .execute(&pool)
+        .await
+        .unwrap();
+    app.ops().outbox().relay().await.unwrap();
+    assert_eq!(pending().await, 0);
+
+    // Entries claimed by another relay are left alone until the claim expired
+    OutboxOps::enqueue(&pool, &[create]).await.unwrap();
+    sqlx::query!("UPDATE event_outbox SET leased_until = NOW() + INTERVAL '1 minute'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(app.ops().outbox().relay().await.unwrap(), 0);
+    assert_eq!(pending().await, 1);
+
+    sqlx::query!("UPDATE event_outbox SET leased_until = NOW() - INTERVAL '1 second'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(app.ops().outbox().relay().await.unwrap(), 1);
+    assert_eq!(pending().await, 0);
+}
+
 #[sqlx::test(fixtures("basic"))]
 async fn test_fetch_presence(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let presence = app.ops().fetch_presence(BASIC_USER_1).await;
+    let presence = app.ops().users().fetch_presence(BASIC_USER_1).await.unwrap();
     assert!(presence.is_some(), "Presence should exist for BASIC_USER_1");
 
-    let not_existing = app.ops().fetch_presence(999999_i64).await;
+    let not_existing = app.ops().users().fetch_presence(999999_i64).await.unwrap();
     assert!(
         not_existing.is_none(),
         "Presence should not exist for non-existent user"
     );
 }
 
+#[sqlx::test(fixtures("basic"))]
+async fn test_fetch_presence_audience(pool: PgPool) {
+    let app = utils::DBApp::new(pool.clone());
+    // Users without stored preferences share their presence with everyone
+    let audience = app.ops().users().fetch_presence_audience(BASIC_USER_1).await.unwrap();
+    assert_eq!(audience, PresenceAudience::Everyone);
+
+    sqlx::query("INSERT INTO prefs (user_id, presence_sharing) VALUES ($1, 2)")
+        .bind(BASIC_USER_1)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let audience = app.ops().users().fetch_presence_audience(BASIC_USER_1).await.unwrap();
+    assert!(!audience.includes(BASIC_USER_2));
+}
+
 #[sqlx::test(fixtures("basic"))]
 async fn test_fetch_user_by_username(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let user = app.ops().fetch_user_by_username("test").await;
+    let user = app.ops().users().fetch_user_by_username("test").await.unwrap();
     assert!(user.is_some(), "User with username 'test' should exist");
     assert_eq!(
         user.unwrap().id(),
@@ -590,35 +1207,134 @@ async fn test_fetch_user_by_username(pool: PgPool) {
 #[sqlx::test(fixtures("basic"))]
 async fn test_is_username_taken(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let exists = app.ops().is_username_taken("test").await.unwrap();
+    let exists = app.ops().users().is_username_taken("test").await.unwrap();
     assert!(exists, "'test' should be a taken username");
-    let not_exists = app.ops().is_username_taken("nonexistentusername").await.unwrap();
+    let not_exists = app
+        .ops()
+        .users()
+        .is_username_taken("nonexistentusername")
+        .await
+        .unwrap();
     assert!(!not_exists, "'nonexistentusername' should not be taken");
+    let other_case = app.ops().users().is_username_taken("TeSt").await.unwrap();
+    assert!(other_case, "Usernames should be taken regardless of case");
 }
 
 #[sqlx::test(fixtures("basic"))]
-async fn test_fetch_guilds_for_function(pool: PgPool) {
-    let app = utils::DBApp::new(pool);
-    let guilds_user1 = app.ops().fetch_guilds_for(BASIC_USER_1).await.unwrap();
-    assert_eq!(guilds_user1.len(), 1);
-    assert_eq!(guilds_user1[0].id(), BASIC_GUILD_1);
-    assert_eq!(guilds_user1[0].name(), "Test Guild");
+async fn test_usernames_case_insensitive(pool: PgPool) {
+    let app = utils::DBApp::new(pool.clone());
 
-    let guilds_user2 = app.ops().fetch_guilds_for(BASIC_USER_2).await.unwrap();
-    assert_eq!(guilds_user2.len(), 2);
-    let guild_ids: Vec<_> = guilds_user2.into_iter().map(|g| g.id()).collect();
-    assert!(guild_ids.contains(&BASIC_GUILD_1));
-    assert!(guild_ids.contains(&BASIC_GUILD_2));
-}
+    let user = app.ops().users().fetch_user_by_username("TEST").await.unwrap();
+    assert_eq!(user.map(|u| u.id()), Some(BASIC_USER_1));
 
-#[sqlx::test(fixtures("basic"))]
-async fn test_fetch_guild_ids_for_function(pool: PgPool) {
-    let app = utils::DBApp::new(pool);
-    let ids_user1 = app.ops().fetch_guild_ids_for(BASIC_USER_1).await.unwrap();
-    assert_eq!(ids_user1.len(), 1);
-    assert!(ids_user1.contains(&BASIC_GUILD_1));
+    let payload = UpdateUser {
+        username: Some("Test".to_string()),
+        display_name: OmittableOption::Omitted,
+        avatar: OmittableOption::Omitted,
+        banner: OmittableOption::Omitted,
+    };
+    let result = app.ops().users().update_user(BASIC_USER_2, payload).await;
+    assert!(matches!(result, Err(OpsError::AlreadyTaken { field: "username" })));
+
+    let inserted = sqlx::query!("INSERT INTO users (id, username) VALUES (1, 'TEST')")
+        .execute(&pool)
+        .await;
+    assert!(
+        inserted.is_err(),
+        "The database should reject usernames differing only in case"
+    );
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_concurrent_signups(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let payload = || CreateUser {
+        username: "newcomer".to_string(),
+        password: Secret::new("Amongus1.".to_string()),
+        registration_code: None,
+    };
+
+    let users = app.ops().users();
+    let results: [_; 2] = tokio::join!(users.create_user(payload()), users.create_user(payload())).into();
+
+    // Exactly one of the signups claims the username, regardless of which one wins the race
+    assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+    assert!(
+        results
+            .iter()
+            .any(|r| matches!(r, Err(OpsError::AlreadyTaken { field: "username" })))
+    );
+
+    let result = app.ops().users().create_user(payload()).await;
+    assert!(matches!(result, Err(OpsError::AlreadyTaken { field: "username" })));
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_reset_stale_presences(pool: PgPool) {
+    let app = utils::DBApp::new(pool.clone());
+    let live = uuid::Uuid::new_v4();
+
+    // An instance that stopped heartbeating long ago, with both users connected to it
+    sqlx::query!("INSERT INTO gateway_instances (id, heartbeat_at) VALUES ('dead', 0)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        "INSERT INTO gateway_connections (user_id, instance_id) VALUES ($1, 'dead'), ($2, 'dead')",
+        i64::from(BASIC_USER_1),
+        i64::from(BASIC_USER_2),
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    // The second user is still connected through a live instance
+    app.ops().instances().heartbeat(live).await.unwrap();
+    app.ops()
+        .instances()
+        .record_connection(live, BASIC_USER_2)
+        .await
+        .unwrap();
+
+    assert_eq!(app.ops().instances().reset_stale_presences().await.unwrap(), 1);
+    assert_eq!(app.ops().instances().reset_stale_presences().await.unwrap(), 0);
+
+    let instances = sqlx::query_scalar!("SELECT id FROM gateway_instances")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(instances, vec![live.to_string()]);
 
-    let ids_user2 = app.ops().fetch_guild_ids_for(BASIC_USER_2).await.unwrap();
+    let connections = sqlx::query_scalar!("SELECT user_id FROM gateway_connections")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(connections, vec![i64::from(BASIC_USER_2)]);
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_fetch_guilds_for_function(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let guilds_user1 = app.ops().guilds().fetch_guilds_for(BASIC_USER_1).await.unwrap();
+    assert_eq!(guilds_user1.len(), 1);
+    assert_eq!(guilds_user1[0].id(), BASIC_GUILD_1);
+    assert_eq!(guilds_user1[0].name(), "Test Guild");
+
+    let guilds_user2 = app.ops().guilds().fetch_guilds_for(BASIC_USER_2).await.unwrap();
+    assert_eq!(guilds_user2.len(), 2);
+    let guild_ids: Vec<_> = guilds_user2.into_iter().map(|g| g.id()).collect();
+    assert!(guild_ids.contains(&BASIC_GUILD_1));
+    assert!(guild_ids.contains(&BASIC_GUILD_2));
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_fetch_guild_ids_for_function(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let ids_user1 = app.ops().guilds().fetch_guild_ids_for(BASIC_USER_1).await.unwrap();
+    assert_eq!(ids_user1.len(), 1);
+    assert!(ids_user1.contains(&BASIC_GUILD_1));
+
+    let ids_user2 = app.ops().guilds().fetch_guild_ids_for(BASIC_USER_2).await.unwrap();
     assert_eq!(ids_user2.len(), 2);
     assert!(ids_user2.contains(&BASIC_GUILD_1));
     assert!(ids_user2.contains(&BASIC_GUILD_2));
@@ -633,8 +1349,9 @@ async fn test_update_user_success(pool: PgPool) {
         username: Some(new_username.to_owned()),
         display_name: OmittableOption::Some(new_display_name.to_owned()),
         avatar: OmittableOption::Omitted,
+        banner: OmittableOption::Omitted,
     };
-    let updated = app.ops().update_user(BASIC_USER_1, payload).await.unwrap();
+    let updated = app.ops().users().update_user(BASIC_USER_1, payload).await.unwrap();
     assert_eq!(updated.username(), new_username);
     assert_eq!(updated.display_name(), Some(new_display_name));
 }
@@ -642,13 +1359,14 @@ async fn test_update_user_success(pool: PgPool) {
 #[sqlx::test(fixtures("basic"))]
 async fn test_update_user_no_change(pool: PgPool) {
     let app = utils::DBApp::new(pool);
-    let old_user = app.ops().fetch_user(BASIC_USER_1).await.unwrap();
+    let old_user = app.ops().users().fetch_user(BASIC_USER_1).await.unwrap().unwrap();
     let payload = UpdateUser {
         username: None,
         display_name: OmittableOption::Omitted,
         avatar: OmittableOption::Omitted,
+        banner: OmittableOption::Omitted,
     };
-    let updated = app.ops().update_user(BASIC_USER_1, payload).await.unwrap();
+    let updated = app.ops().users().update_user(BASIC_USER_1, payload).await.unwrap();
     // ...existing code...
     assert_eq!(updated.username(), old_user.username());
     assert_eq!(updated.display_name(), old_user.display_name());
@@ -661,10 +1379,2511 @@ async fn test_update_user_nonexistent(pool: PgPool) {
         username: Some("nonexistent".to_string()),
         display_name: OmittableOption::Omitted,
         avatar: OmittableOption::Omitted,
+        banner: OmittableOption::Omitted,
     };
-    let result = app.ops().update_user(999999_i64, payload).await;
+    let result = app.ops().users().update_user(999999_i64, payload).await;
     match result {
-        Err(RESTError::NotFound(_)) => { /* expected */ }
+        Err(OpsError::NotFound(_)) => { /* expected */ }
         _ => panic!("Expected NotFound error for non-existent user"),
     }
 }
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_update_user_banner(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let banner = |content: Vec<u8>| UpdateUser {
+        username: None,
+        display_name: OmittableOption::Omitted,
+        avatar: OmittableOption::Omitted,
+        banner: OmittableOption::Some(DataUri::new(content, mime::IMAGE_JPEG)),
+    };
+
+    let result = app
+        .ops()
+        .users()
+        .update_user(BASIC_USER_1, banner(vec![0; 5 * 1024 * 1024]))
+        .await;
+    assert!(matches!(result, Err(OpsError::PayloadTooLarge(_))));
+
+    let updated = app
+        .ops()
+        .users()
+        .update_user(BASIC_USER_1, banner(b"\xFF\xD8\xFF\xE0".to_vec()))
+        .await
+        .unwrap();
+    let hash = updated.banner().unwrap().avatar_hash().to_owned();
+    assert!(hash.ends_with("_jpeg"));
+    assert!(updated.avatar().is_none());
+
+    let fetched = app.ops().users().fetch_user(BASIC_USER_1).await.unwrap().unwrap();
+    assert_eq!(fetched.banner().map(AvatarLike::avatar_hash), Some(hash.as_str()));
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_avatar_upload_queue(pool: PgPool) {
+    use bytes::Bytes;
+    use chat_backend::models::avatar::{FullAvatar, UserAvatar};
+
+    let app = utils::DBApp::new(pool.clone());
+    let avatars = app.ops().avatars();
+    let avatar = |hash: &str| {
+        FullAvatar::<UserAvatar>::builder()
+            .avatar_hash(hash)
+            .holder_id(BASIC_USER_1)
+            .mime(mime::IMAGE_PNG)
+            .content(Bytes::from_static(b"\x89PNG"))
+            .build()
+            .unwrap()
+    };
+
+    assert_eq!(
+        avatars.cancel_upload::<UserAvatar>(&pool, BASIC_USER_1).await.unwrap(),
+        None
+    );
+
+    // Replacing a pending upload keeps the last uploaded avatar to restore on failure
+    avatars
+        .enqueue_upload(&pool, &avatar("1_png"), Some("0_png"))
+        .await
+        .unwrap();
+    avatars
+        .enqueue_upload(&pool, &avatar("2_png"), Some("1_png"))
+        .await
+        .unwrap();
+    assert_eq!(
+        avatars
+            .cancel_upload::<UserAvatar>(&pool, BASIC_USER_1)
+            .await
+            .unwrap()
+            .as_deref(),
+        Some("0_png")
+    );
+
+    // Nothing is uploaded without S3
+    avatars.enqueue_upload(&pool, &avatar("3_png"), None).await.unwrap();
+    assert_eq!(avatars.upload_pending_avatars().await.unwrap(), 0);
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_update_vanity_code(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+
+    let code = app
+        .ops()
+        .guilds()
+        .update_vanity_code(BASIC_GUILD_1, BASIC_USER_1, Some("test-guild".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(code.as_deref(), Some("test-guild"));
+    assert_eq!(
+        app.ops()
+            .guilds()
+            .fetch_vanity_code(BASIC_GUILD_1)
+            .await
+            .unwrap()
+            .as_deref(),
+        Some("test-guild")
+    );
+
+    let invite = app.ops().guilds().fetch_invite("Test-Guild").await.unwrap().unwrap();
+    assert_eq!(invite.code(), "test-guild");
+    assert_eq!(invite.guild().id(), BASIC_GUILD_1);
+    assert!(invite.is_vanity());
+
+    // Another guild cannot claim the same code
+    let result = app
+        .ops()
+        .guilds()
+        .update_vanity_code(BASIC_GUILD_2, BASIC_USER_2, Some("test-guild".to_string()))
+        .await;
+    assert!(matches!(result, Err(OpsError::AlreadyTaken { field: "code" })));
+
+    // Releasing the code makes it unresolvable
+    let code = app
+        .ops()
+        .guilds()
+        .update_vanity_code(BASIC_GUILD_1, BASIC_USER_1, None)
+        .await
+        .unwrap();
+    assert_eq!(code, None);
+    assert!(app.ops().guilds().fetch_invite("test-guild").await.unwrap().is_none());
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_update_vanity_code_reserved(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let result = app
+        .ops()
+        .guilds()
+        .update_vanity_code(BASIC_GUILD_1, BASIC_USER_1, Some("admin".to_string()))
+        .await;
+    assert!(result.is_err());
+    assert_eq!(app.ops().guilds().fetch_vanity_code(BASIC_GUILD_1).await.unwrap(), None);
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_upload_session_lifecycle(pool: PgPool) {
+    use bytes::Bytes;
+    use chat_backend::models::{
+        request_payloads::{CreateMessage, CreateUploadSession},
+        upload_session::{MIN_PART_SIZE, UploadSession},
+    };
+
+    let app = utils::DBApp::new(pool);
+    let payload = CreateUploadSession {
+        filename: "video.mp4".to_string(),
+        content_type: Some("video/mp4".to_string()),
+        size: (MIN_PART_SIZE + 10) as u64,
+        direct: false,
+    };
+    let mut session = UploadSession::from_payload(app.config(), BASIC_USER_1, BASIC_GUILD_1_GENERAL, payload).unwrap();
+    app.ops().messages().create_upload_session(&mut session).await.unwrap();
+
+    // Parts other than the last must meet the minimum size
+    let result = app
+        .ops()
+        .messages()
+        .upload_session_part(&session, Bytes::from(vec![0; 10]))
+        .await;
+    assert!(result.is_err());
+
+    let session = app
+        .ops()
+        .messages()
+        .upload_session_part(&session, Bytes::from(vec![0; MIN_PART_SIZE]))
+        .await
+        .unwrap();
+    assert_eq!(session.uploaded(), MIN_PART_SIZE as u64);
+    assert!(!session.is_complete());
+
+    let member = app
+        .ops()
+        .guilds()
+        .fetch_member(BASIC_USER_1, BASIC_GUILD_1)
+        .await
+        .unwrap()
+        .unwrap();
+    let message = Message::from_upload_session(
+        app.config(),
+        UserLike::Member(member.clone()),
+        &session,
+        CreateMessage {
+            content: Some("Check this out".to_string()),
+            nonce: None,
+        },
+    )
+    .unwrap();
+
+    // Cannot complete before all bytes are received
+    let result = app
+        .ops()
+        .messages()
+        .complete_upload_session(&session, &message, &[])
+        .await;
+    assert!(matches!(result, Err(OpsError::BadRequest(_))));
+
+    let session = app
+        .ops()
+        .messages()
+        .upload_session_part(&session, Bytes::from(vec![0; 10]))
+        .await
+        .unwrap();
+    assert!(session.is_complete());
+    assert_eq!(session.percentage(), 100);
+
+    app.ops()
+        .messages()
+        .complete_upload_session(&session, &message, &[])
+        .await
+        .unwrap();
+
+    let fetched = app
+        .ops()
+        .messages()
+        .fetch_message(session.message_id())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(fetched.content(), Some("Check this out"));
+    assert_eq!(fetched.channel_id(), BASIC_GUILD_1_GENERAL);
+    assert!(
+        app.ops()
+            .messages()
+            .fetch_upload_session(&session)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_upload_session_reserved_parts(pool: PgPool) {
+    use bytes::Bytes;
+    use chat_backend::models::{
+        request_payloads::CreateUploadSession,
+        upload_session::{MIN_PART_SIZE, UploadSession},
+    };
+
+    let app = utils::DBApp::new(pool.clone());
+    let payload = CreateUploadSession {
+        filename: "video.mp4".to_string(),
+        content_type: Some("video/mp4".to_string()),
+        size: (MIN_PART_SIZE + 10) as u64,
+        direct: false,
+    };
+    let mut session = UploadSession::from_payload(app.config(), BASIC_USER_1, BASIC_GUILD_1_GENERAL, payload).unwrap();
+    app.ops().messages().create_upload_session(&mut session).await.unwrap();
+
+    // A part still being uploaded by another request
+    sqlx::query!(
+        "INSERT INTO upload_session_parts (session_id, part_number, size, reserved_until)
+        VALUES ($1, 1, $2, NOW() + INTERVAL '1 minute')",
+        session.id() as Snowflake<UploadSession>,
+        MIN_PART_SIZE as i64,
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    // Its bytes are reserved, so the part may not overflow the declared size
+    let result = app
+        .ops()
+        .messages()
+        .upload_session_part(&session, Bytes::from(vec![0; MIN_PART_SIZE]))
+        .await;
+    assert!(matches!(result, Err(OpsError::Build(_))));
+
+    // But are only counted as uploaded once stored
+    let session = app
+        .ops()
+        .messages()
+        .upload_session_part(&session, Bytes::from(vec![0; 10]))
+        .await
+        .unwrap();
+    assert_eq!(session.uploaded(), 10);
+    assert!(!session.is_complete());
+
+    let parts = sqlx::query_scalar!(
+        "SELECT part_number FROM upload_session_parts WHERE session_id = $1 ORDER BY part_number",
+        session.id() as Snowflake<UploadSession>,
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(parts, vec![1, 2]);
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_upload_session_released_parts(pool: PgPool) {
+    use bytes::Bytes;
+    use chat_backend::models::{
+        request_payloads::CreateUploadSession,
+        upload_session::{MIN_PART_SIZE, UploadSession},
+    };
+
+    let app = utils::DBApp::new(pool.clone());
+    let payload = CreateUploadSession {
+        filename: "video.mp4".to_string(),
+        content_type: Some("video/mp4".to_string()),
+        size: (MIN_PART_SIZE * 2 + 10) as u64,
+        direct: false,
+    };
+    let mut session = UploadSession::from_payload(app.config(), BASIC_USER_1, BASIC_GUILD_1_GENERAL, payload).unwrap();
+    app.ops().messages().create_upload_session(&mut session).await.unwrap();
+    let parts = || {
+        sqlx::query_scalar!(
+            "SELECT part_number FROM upload_session_parts WHERE session_id = $1 ORDER BY part_number",
+            session.id() as Snowflake<UploadSession>,
+        )
+        .fetch_all(&pool)
+    };
+
+    // The first part failed to upload and was released, while the second one is still uploading
+    sqlx::query!(
+        "INSERT INTO upload_session_parts (session_id, part_number, size, reserved_until)
+        VALUES ($1, 2, $2, NOW() + INTERVAL '1 minute')",
+        session.id() as Snowflake<UploadSession>,
+        MIN_PART_SIZE as i64,
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    // Parts filling the gap are followed by other parts, so they must meet the minimum size
+    let result = app
+        .ops()
+        .messages()
+        .upload_session_part(&session, Bytes::from(vec![0; 10]))
+        .await;
+    assert!(matches!(result, Err(OpsError::Build(_))));
+
+    // The first part sent again keeps its place in the file
+    let session = app
+        .ops()
+        .messages()
+        .upload_session_part(&session, Bytes::from(vec![0; MIN_PART_SIZE]))
+        .await
+        .unwrap();
+    assert_eq!(session.uploaded(), MIN_PART_SIZE as u64);
+    assert_eq!(parts().await.unwrap(), vec![1, 2]);
+
+    // The instance uploading the second part stopped, so its reservation expires and it can be sent again
+    sqlx::query!(
+        "UPDATE upload_session_parts SET reserved_until = NOW() - INTERVAL '1 second'
+        WHERE session_id = $1 AND part_number = 2",
+        session.id() as Snowflake<UploadSession>,
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let session = app
+        .ops()
+        .messages()
+        .upload_session_part(&session, Bytes::from(vec![0; MIN_PART_SIZE]))
+        .await
+        .unwrap();
+    let session = app
+        .ops()
+        .messages()
+        .upload_session_part(&session, Bytes::from(vec![0; 10]))
+        .await
+        .unwrap();
+    assert!(session.is_complete());
+    assert_eq!(parts().await.unwrap(), vec![1, 2, 3]);
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_abort_upload_session(pool: PgPool) {
+    use chat_backend::models::{request_payloads::CreateUploadSession, upload_session::UploadSession};
+
+    let app = utils::DBApp::new(pool);
+    let payload = CreateUploadSession {
+        filename: "archive.zip".to_string(),
+        content_type: None,
+        size: 1024,
+        direct: false,
+    };
+    let mut session = UploadSession::from_payload(app.config(), BASIC_USER_1, BASIC_GUILD_1_GENERAL, payload).unwrap();
+    app.ops().messages().create_upload_session(&mut session).await.unwrap();
+    assert!(
+        app.ops()
+            .messages()
+            .fetch_upload_session(&session)
+            .await
+            .unwrap()
+            .is_some()
+    );
+
+    app.ops().messages().abort_upload_session(&session).await.unwrap();
+    assert!(
+        app.ops()
+            .messages()
+            .fetch_upload_session(&session)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_abort_stale_uploads(pool: PgPool) {
+    use chat_backend::models::{request_payloads::CreateUploadSession, upload_session::UploadSession};
+
+    let app = utils::DBApp::new(pool.clone());
+    let mut sessions = Vec::new();
+    for filename in ["stale.zip", "fresh.zip"] {
+        let payload = CreateUploadSession {
+            filename: filename.to_string(),
+            content_type: None,
+            size: 1024,
+            direct: false,
+        };
+        let mut session =
+            UploadSession::from_payload(app.config(), BASIC_USER_1, BASIC_GUILD_1_GENERAL, payload).unwrap();
+        app.ops().messages().create_upload_session(&mut session).await.unwrap();
+        sessions.push(session);
+    }
+
+    sqlx::query!(
+        "UPDATE upload_sessions SET created_at = NOW() - INTERVAL '2 days' WHERE id = $1",
+        i64::from(sessions[0].id())
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    // Without S3 there are no multipart uploads to abort, but abandoned sessions are still removed
+    assert_eq!(app.ops().messages().abort_stale_uploads().await.unwrap(), 0);
+    let remaining = sqlx::query_scalar!("SELECT id FROM upload_sessions")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, vec![i64::from(sessions[1].id())]);
+}
+
+#[sqlx::test]
+async fn test_job_leases(pool: PgPool) {
+    use chat_backend::external::Database;
+
+    // Two instances sharing the same database
+    let (a, b) = (Database::from_pool(pool.clone()), Database::from_pool(pool));
+
+    assert!(a.try_lead_job("sweep").await.unwrap());
+    assert!(a.try_lead_job("sweep").await.unwrap());
+    assert!(!b.try_lead_job("sweep").await.unwrap());
+
+    // Each job is run by a single instance, but not necessarily the same one
+    assert!(b.try_lead_job("digest").await.unwrap());
+    assert!(!a.try_lead_job("digest").await.unwrap());
+
+    // Once an instance resigns, the other takes over its jobs
+    a.resign_jobs().await;
+    assert!(b.try_lead_job("sweep").await.unwrap());
+    assert!(!a.try_lead_job("sweep").await.unwrap());
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_verify_snowflake_epoch(pool: PgPool) {
+    use chat_backend::{
+        app::{Config, ops::Ops},
+        external::Database,
+        models::{
+            errors::{BuildError, OpsError},
+            snowflake::EPOCH,
+        },
+    };
+
+    let app = utils::DBApp::new(pool.clone());
+    // The first start records the epoch, subsequent starts must match it
+    app.ops().verify_snowflake_epoch().await.unwrap();
+    app.ops().verify_snowflake_epoch().await.unwrap();
+
+    let config = Config::builder()
+        .database_url(Secret::new(String::new()))
+        .s3(None)
+        .listen_addr("127.0.0.1:8080".parse::<std::net::SocketAddr>().unwrap())
+        .machine_id(0)
+        .process_id(0)
+        .app_secret(Secret::new(String::new()))
+        .snowflake_epoch(EPOCH + 1000)
+        .build()
+        .unwrap();
+    let db = Database::from_pool(pool);
+    let result = Ops::builder()
+        .db(&db)
+        .config(&config)
+        .build()
+        .unwrap()
+        .verify_snowflake_epoch()
+        .await;
+    assert!(matches!(result, Err(OpsError::Build(BuildError::IllegalState(_)))));
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_notification_digest_state(pool: PgPool) {
+    let app = utils::DBApp::new(pool.clone());
+
+    sqlx::query("INSERT INTO notification_states (user_id, channel_id, unanswered, pending_digest) VALUES ($1, $2, 5, TRUE), ($1, $3, 1, FALSE)")
+        .bind(BASIC_USER_1)
+        .bind(BASIC_GUILD_1_GENERAL)
+        .bind(BASIC_GUILD_1_RANDOM)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let pending = || async {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM notification_states WHERE pending_digest")
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+    };
+
+    // Without FCM configured, pending digests are dropped without sending anything
+    assert_eq!(pending().await, 1);
+    assert_eq!(app.ops().notifications().send_notification_digests().await.unwrap(), 0);
+    assert_eq!(pending().await, 0);
+
+    // Reading a channel answers its pushes
+    app.ops()
+        .notifications()
+        .update_read_state(BASIC_USER_1, BASIC_GUILD_1_RANDOM, Snowflake::new(1))
+        .await
+        .unwrap();
+    let remaining = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM notification_states")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 1);
+}
+
+#[sqlx::test(fixtures("basic", "basic_messages"))]
+async fn test_channel_stats(pool: PgPool) {
+    let app = utils::DBApp::new(pool.clone());
+
+    let (count, last_id) =
+        sqlx::query_as::<_, (i64, i64)>("SELECT COUNT(*), MAX(id) FROM messages WHERE channel_id = $1")
+            .bind(BASIC_GUILD_1_GENERAL)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+    // Fixture messages were inserted directly, so the stats need to be backfilled
+    assert!(app.ops().messages().reconcile_channel_stats().await.unwrap() >= 1);
+    assert_eq!(app.ops().messages().reconcile_channel_stats().await.unwrap(), 0);
+
+    let channel = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(channel.message_count(), count);
+    assert_eq!(channel.last_message_id(), Some(Snowflake::new(last_id)));
+
+    let author = app.ops().users().fetch_user(BASIC_USER_1).await.unwrap().unwrap();
+    let mut message = Message::builder()
+        .id(Snowflake::gen_new(app.config()))
+        .author(UserLike::User(author))
+        .channel_id(BASIC_GUILD_1_GENERAL)
+        .content(Some("Hello".to_string()))
+        .build()
+        .unwrap();
+    app.ops().messages().commit_message(&message).await.unwrap();
+
+    let channel = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(channel.message_count(), count + 1);
+    assert_eq!(channel.last_message_id(), Some(message.id()));
+
+    // Edits do not count as new messages
+    message.apply_update(UpdateMessage {
+        content: OmittableOption::Some("Edited".to_string()),
+    });
+    app.ops().messages().commit_message(&message).await.unwrap();
+    let channel = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(channel.message_count(), count + 1);
+
+    // Deleting the last message falls back to the one before it
+    app.ops()
+        .messages()
+        .delete_message(BASIC_GUILD_1_GENERAL, message.id())
+        .await
+        .unwrap();
+    let channel = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(channel.message_count(), count);
+    assert_eq!(channel.last_message_id(), Some(Snowflake::new(last_id)));
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_locked_channel(pool: PgPool) {
+    use chat_backend::models::request_payloads::UpdateChannel;
+
+    let app = utils::DBApp::new(pool);
+    let mut channel = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_1_RANDOM)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!channel.locked());
+    assert!(app.ops().guilds().can_post_in(&channel, BASIC_USER_2).await.unwrap());
+
+    channel.update(UpdateChannel {
+        name: None,
+        locked: Some(true),
+        retention_days: OmittableOption::Omitted,
+    });
+    app.ops().guilds().update_channel(&channel).await.unwrap();
+
+    let channel = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_1_RANDOM)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(channel.locked());
+    // Only the guild owner and members who may manage messages may post in locked channels
+    assert!(app.ops().guilds().can_post_in(&channel, BASIC_USER_1).await.unwrap());
+    assert!(!app.ops().guilds().can_post_in(&channel, BASIC_USER_2).await.unwrap());
+
+    let role = |permissions| {
+        Role::from_payload(
+            app.config(),
+            BASIC_GUILD_1,
+            &CreateRole {
+                name: "Announcer".to_owned(),
+                permissions,
+            },
+        )
+        .unwrap()
+    };
+    let events = role(Permissions::MANAGE_EVENTS);
+    app.ops().roles().create_role(&events).await.unwrap();
+    app.ops().roles().add_member_role(&events, BASIC_USER_2).await.unwrap();
+    assert!(!app.ops().guilds().can_post_in(&channel, BASIC_USER_2).await.unwrap());
+
+    let announcer = role(Permissions::MANAGE_MESSAGES);
+    app.ops().roles().create_role(&announcer).await.unwrap();
+    app.ops()
+        .roles()
+        .add_member_role(&announcer, BASIC_USER_2)
+        .await
+        .unwrap();
+    assert!(app.ops().guilds().can_post_in(&channel, BASIC_USER_2).await.unwrap());
+}
+
+#[sqlx::test(fixtures("basic", "basic_messages"))]
+async fn test_sweep_expired_messages(pool: PgPool) {
+    use chat_backend::models::request_payloads::UpdateChannel;
+
+    let app = utils::DBApp::new(pool.clone());
+    app.ops().messages().reconcile_channel_stats().await.unwrap();
+
+    let author = app.ops().users().fetch_user(BASIC_USER_1).await.unwrap().unwrap();
+    let message = Message::builder()
+        .id(Snowflake::gen_new(app.config()))
+        .author(UserLike::User(author))
+        .channel_id(BASIC_GUILD_1_GENERAL)
+        .content(Some("Still fresh".to_string()))
+        .build()
+        .unwrap();
+    app.ops().messages().commit_message(&message).await.unwrap();
+
+    // Channels without retention keep their messages forever
+    assert_eq!(app.ops().messages().sweep_expired_messages().await.unwrap(), 0);
+
+    let mut channel = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap()
+        .unwrap();
+    let count = channel.message_count();
+    channel.update(UpdateChannel {
+        name: None,
+        locked: None,
+        retention_days: OmittableOption::Some(30),
+    });
+    app.ops().guilds().update_channel(&channel).await.unwrap();
+
+    // The fixture messages are years old, only the new message survives
+    let expired = app.ops().messages().sweep_expired_messages().await.unwrap();
+    assert_eq!(i64::try_from(expired).unwrap(), count - 1);
+    assert_eq!(app.ops().messages().sweep_expired_messages().await.unwrap(), 0);
+
+    let channel = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(channel.retention_days(), Some(30));
+    assert_eq!(channel.message_count(), 1);
+    assert_eq!(channel.last_message_id(), Some(message.id()));
+    assert_eq!(app.ops().messages().reconcile_channel_stats().await.unwrap(), 0);
+
+    let (action, target, new_value) = sqlx::query_as::<_, (String, i64, String)>(
+        "SELECT action, target_id, new_value FROM audit_log_entries WHERE guild_id = $1 AND user_id IS NULL",
+    )
+    .bind(BASIC_GUILD_1)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(action, "MESSAGE_EXPIRE");
+    assert_eq!(target, i64::from(BASIC_GUILD_1_GENERAL));
+    assert_eq!(new_value, expired.to_string());
+
+    // Retention is bounded
+    let mut channel = channel;
+    channel.update(UpdateChannel {
+        name: None,
+        locked: None,
+        retention_days: OmittableOption::Some(0),
+    });
+    assert!(app.ops().guilds().update_channel(&channel).await.is_err());
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_quarantine_attachment(pool: PgPool) {
+    use chat_backend::{
+        external::scanner::ScanVerdict,
+        models::attachment::{AttachmentLike, PartialAttachment},
+    };
+
+    let app = utils::DBApp::new(pool.clone());
+    let author = app.ops().users().fetch_user(BASIC_USER_1).await.unwrap().unwrap();
+    let message = Message::builder()
+        .id(Snowflake::gen_new(app.config()))
+        .author(UserLike::User(author))
+        .channel_id(BASIC_GUILD_1_GENERAL)
+        .content(Some("Look at this".to_string()))
+        .build()
+        .unwrap();
+    app.ops().messages().commit_message(&message).await.unwrap();
+
+    let attachment = PartialAttachment::new(
+        0,
+        "totally_safe.exe".into(),
+        "application/octet-stream".into(),
+        BASIC_GUILD_1_GENERAL,
+        message.id(),
+    );
+    sqlx::query(
+        "INSERT INTO attachments (id, filename, message_id, channel_id, content_type) VALUES (0, $1, $2, $3, $4)",
+    )
+    .bind(attachment.filename())
+    .bind(i64::from(message.id()))
+    .bind(i64::from(BASIC_GUILD_1_GENERAL))
+    .bind(attachment.mime().to_string())
+    .execute(&pool)
+    .await
+    .unwrap();
+    app.ops().messages().enqueue_attachment_scan(&attachment).await.unwrap();
+
+    app.ops()
+        .messages()
+        .quarantine_attachment(&attachment, ScanVerdict::Malware)
+        .await
+        .unwrap();
+
+    let message = app.ops().messages().fetch_message(message.id()).await.unwrap().unwrap();
+    assert!(message.flagged());
+    let json = serde_json::to_value(&message).unwrap();
+    assert_eq!(json["flagged"], true);
+    assert_eq!(json["attachments"][0]["quarantined"], true);
+
+    // The attachment was removed from the scan queue
+    let queued = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM attachment_scans")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, 0);
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_migrate_s3_keys(pool: PgPool) {
+    use chat_backend::{
+        external::s3::KEYSPACE_VERSION,
+        models::attachment::{AttachmentLike, PartialAttachment},
+    };
+
+    let app = utils::DBApp::new(pool.clone());
+    let author = app.ops().users().fetch_user(BASIC_USER_1).await.unwrap().unwrap();
+    let message = Message::builder()
+        .id(Snowflake::gen_new(app.config()))
+        .author(UserLike::User(author))
+        .channel_id(BASIC_GUILD_1_GENERAL)
+        .content(Some("An old picture".to_string()))
+        .build()
+        .unwrap();
+    app.ops().messages().commit_message(&message).await.unwrap();
+
+    // Attachments sent before the keyspace was versioned are stored under the original layout
+    sqlx::query(
+        "INSERT INTO attachments (id, filename, message_id, channel_id, content_type, key_version)
+        VALUES (0, 'cat.png', $1, $2, 'image/png', 1)",
+    )
+    .bind(i64::from(message.id()))
+    .bind(i64::from(BASIC_GUILD_1_GENERAL))
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let legacy = PartialAttachment::new(
+        0,
+        "cat.png".into(),
+        "image/png".into(),
+        BASIC_GUILD_1_GENERAL,
+        message.id(),
+    )
+    .with_key_version(1);
+    assert_eq!(
+        legacy.s3_key(),
+        format!("{BASIC_GUILD_1_GENERAL}/{}/0/cat.png", message.id())
+    );
+
+    assert_eq!(
+        app.ops().migrate_s3_keys(std::slice::from_ref(&legacy)).await.unwrap(),
+        1
+    );
+    // Attachments that were already moved are skipped
+    assert_eq!(app.ops().migrate_s3_keys(&[legacy]).await.unwrap(), 0);
+
+    let message = app.ops().messages().fetch_message(message.id()).await.unwrap().unwrap();
+    let json = serde_json::to_value(&message).unwrap();
+    assert_eq!(json["attachments"][0]["filename"], "cat.png");
+
+    let key_version = sqlx::query_scalar::<_, i16>("SELECT key_version FROM attachments WHERE message_id = $1")
+        .bind(i64::from(message.id()))
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(key_version, KEYSPACE_VERSION);
+
+    let migrated = PartialAttachment::new(
+        0,
+        "cat.png".into(),
+        "image/png".into(),
+        BASIC_GUILD_1_GENERAL,
+        message.id(),
+    );
+    assert_eq!(
+        migrated.s3_key(),
+        format!("v2/images/{BASIC_GUILD_1_GENERAL}/{}/0/cat.png", message.id())
+    );
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_badges(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let author = app.ops().users().fetch_user(BASIC_USER_1).await.unwrap().unwrap();
+    assert_eq!(app.ops().notifications().fetch_badge(BASIC_USER_2).await.unwrap(), 0);
+
+    let mut last = None;
+    for content in [format!("Hey <@{BASIC_USER_2}>"), format!("Again <@{BASIC_USER_2}>")] {
+        let message = Message::builder()
+            .id(Snowflake::gen_new(app.config()))
+            .author(UserLike::User(author.clone()))
+            .channel_id(BASIC_GUILD_1_GENERAL)
+            .content(Some(content))
+            .build()
+            .unwrap();
+        app.ops().messages().commit_message(&message).await.unwrap();
+        last = Some(message.id());
+    }
+
+    let badges = app
+        .ops()
+        .notifications()
+        .fetch_badges(&[BASIC_USER_1, BASIC_USER_2])
+        .await
+        .unwrap();
+    assert_eq!(badges.get(&BASIC_USER_1).copied().unwrap_or_default(), 0);
+    assert_eq!(badges.get(&BASIC_USER_2).copied(), Some(2));
+
+    // Acknowledging the newest message clears the badge
+    app.ops()
+        .notifications()
+        .update_read_state(BASIC_USER_2, BASIC_GUILD_1_GENERAL, last.unwrap())
+        .await
+        .unwrap();
+    assert_eq!(app.ops().notifications().fetch_badge(BASIC_USER_2).await.unwrap(), 0);
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_mentions(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let author = app.ops().users().fetch_user(BASIC_USER_1).await.unwrap().unwrap();
+
+    let mut messages = Vec::new();
+    for (channel, content) in [
+        (BASIC_GUILD_1_GENERAL, format!("Hey <@{BASIC_USER_2}>")),
+        (
+            BASIC_GUILD_1_RANDOM,
+            format!("<@{BASIC_USER_2}> <@{BASIC_USER_2}> and me <@{BASIC_USER_1}>"),
+        ),
+        (BASIC_GUILD_1_RANDOM, "No mentions here".to_string()),
+    ] {
+        let message = Message::builder()
+            .id(Snowflake::gen_new(app.config()))
+            .author(UserLike::User(author.clone()))
+            .channel_id(channel)
+            .content(Some(content))
+            .build()
+            .unwrap();
+        app.ops().messages().commit_message(&message).await.unwrap();
+        messages.push(message);
+    }
+
+    // Newest first, authors do not mention themselves
+    let mentions = app
+        .ops()
+        .messages()
+        .fetch_mentions(BASIC_USER_2, None, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        mentions.iter().map(Message::id).collect::<Vec<_>>(),
+        vec![messages[1].id(), messages[0].id()]
+    );
+    assert!(
+        app.ops()
+            .messages()
+            .fetch_mentions(BASIC_USER_1, None, None)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    let limited = app
+        .ops()
+        .messages()
+        .fetch_mentions(BASIC_USER_2, None, Some(1))
+        .await
+        .unwrap();
+    assert_eq!(limited.len(), 1);
+    assert_eq!(limited[0].id(), messages[1].id());
+    assert!(
+        app.ops()
+            .messages()
+            .fetch_mentions(BASIC_USER_2, Some(BASIC_GUILD_2), None)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // Editing a mention out removes it from the index
+    let mut edited = messages[0].clone();
+    edited.apply_update(UpdateMessage {
+        content: OmittableOption::Some("Hey everyone".to_string()),
+    });
+    app.ops().messages().commit_message(&edited).await.unwrap();
+    let mentions = app
+        .ops()
+        .messages()
+        .fetch_mentions(BASIC_USER_2, Some(BASIC_GUILD_1), None)
+        .await
+        .unwrap();
+    assert_eq!(mentions.len(), 1);
+
+    // Mentions from guilds the user left are hidden
+    let guild = app.ops().guilds().fetch_guild(BASIC_GUILD_1).await.unwrap().unwrap();
+    app.ops().guilds().delete_member(&guild, BASIC_USER_2).await.unwrap();
+    assert!(
+        app.ops()
+            .messages()
+            .fetch_mentions(BASIC_USER_2, None, None)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_channel_mentions(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let author = app.ops().users().fetch_user(BASIC_USER_1).await.unwrap().unwrap();
+
+    // Channels of other guilds and unknown channels are dropped, the rest keep the order they were mentioned in
+    let mut message = Message::builder()
+        .id(Snowflake::gen_new(app.config()))
+        .author(UserLike::User(author))
+        .channel_id(BASIC_GUILD_1_GENERAL)
+        .content(Some(format!(
+            "See <#{BASIC_GUILD_1_STAFF}>, <#{BASIC_GUILD_2_GENERAL}>, <#1> and <#{BASIC_GUILD_1_RANDOM}>"
+        )))
+        .build()
+        .unwrap();
+    app.ops()
+        .messages()
+        .resolve_channel_mentions(std::slice::from_mut(&mut message))
+        .await
+        .unwrap();
+    let names = |m: &Message| {
+        m.mention_channels()
+            .iter()
+            .map(|c| (c.id(), c.name().to_string()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(&message),
+        vec![
+            (BASIC_GUILD_1_STAFF, "staff".to_string()),
+            (BASIC_GUILD_1_RANDOM, "random".to_string())
+        ]
+    );
+    app.ops().messages().commit_message(&message).await.unwrap();
+
+    // Fetched messages are resolved too
+    let fetched = app.ops().messages().fetch_message(message.id()).await.unwrap().unwrap();
+    assert_eq!(names(&fetched), names(&message));
+
+    // Edits resolve the new content
+    let updated = app
+        .ops()
+        .messages()
+        .update_message(
+            message.id(),
+            UpdateMessage {
+                content: OmittableOption::Some(format!("Actually <#{BASIC_GUILD_1_BOT}>")),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(names(&updated), vec![(BASIC_GUILD_1_BOT, "bot".to_string())]);
+
+    let updated = app
+        .ops()
+        .messages()
+        .update_message(
+            message.id(),
+            UpdateMessage {
+                content: OmittableOption::Some("Never mind".to_string()),
+            },
+        )
+        .await
+        .unwrap();
+    assert!(updated.mention_channels().is_empty());
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_automod(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let channel = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_1_GENERAL)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let rule = AutomodRule::from_payload(
+        app.config(),
+        BASIC_GUILD_1,
+        CreateAutomodRule {
+            name: "No scams".into(),
+            enabled: None,
+            trigger: AutomodTrigger::Keyword {
+                keywords: vec!["Free Nitro".into()],
+            },
+            actions: vec![
+                AutomodAction::Block,
+                AutomodAction::Alert {
+                    channel_id: BASIC_GUILD_1_STAFF,
+                },
+                AutomodAction::Timeout { duration: 600 },
+            ],
+        },
+    )
+    .unwrap();
+    app.ops().automod().create_rule(&rule).await.unwrap();
+    assert_eq!(
+        app.ops().automod().fetch_rules(BASIC_GUILD_1).await.unwrap(),
+        vec![rule.clone()]
+    );
+
+    let matches = app
+        .ops()
+        .automod()
+        .test_rules(BASIC_GUILD_1, Some("get free nitro"), &[])
+        .await
+        .unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].rule.id(), rule.id());
+
+    let message = |author: User, content: &str| {
+        Message::builder()
+            .id(Snowflake::gen_new(app.config()))
+            .author(UserLike::User(author))
+            .channel_id(BASIC_GUILD_1_GENERAL)
+            .content(Some(content.to_string()))
+            .build()
+            .unwrap()
+    };
+    let member = app.ops().users().fetch_user(BASIC_USER_2).await.unwrap().unwrap();
+    let owner = app.ops().users().fetch_user(BASIC_USER_1).await.unwrap().unwrap();
+
+    // The owner is exempt from the guild's rules
+    app.ops()
+        .automod()
+        .moderate_message(&channel, &message(owner, "FREE NITRO"))
+        .await
+        .unwrap();
+
+    app.ops()
+        .automod()
+        .moderate_message(&channel, &message(member.clone(), "hello"))
+        .await
+        .unwrap();
+    assert!(matches!(
+        app.ops()
+            .automod()
+            .moderate_message(&channel, &message(member, "FREE NITRO"))
+            .await,
+        Err(OpsError::Forbidden(_))
+    ));
+
+    // The member was timed out and the moderators alerted
+    assert!(
+        app.ops()
+            .automod()
+            .timed_out_until(BASIC_GUILD_1, BASIC_USER_2)
+            .await
+            .unwrap()
+            .is_some()
+    );
+    assert!(
+        app.ops()
+            .automod()
+            .ensure_not_timed_out(BASIC_GUILD_1, BASIC_USER_2)
+            .await
+            .is_err()
+    );
+    let alerts = app
+        .ops()
+        .messages()
+        .fetch_messages_from(
+            BASIC_GUILD_1_STAFF,
+            None,
+            None::<Snowflake<Message>>,
+            None::<Snowflake<Message>>,
+            None::<Snowflake<Message>>,
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(alerts.len(), 1);
+    assert!(alerts[0].author().is_none());
+    assert_eq!(alerts[0].mention_channels()[0].id(), BASIC_GUILD_1_GENERAL);
+
+    assert!(
+        app.ops()
+            .automod()
+            .remove_timeout(BASIC_GUILD_1, BASIC_USER_2)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !app.ops()
+            .automod()
+            .remove_timeout(BASIC_GUILD_1, BASIC_USER_2)
+            .await
+            .unwrap()
+    );
+
+    // Members who may manage messages are exempt as well
+    let moderator = Role::from_payload(
+        app.config(),
+        BASIC_GUILD_1,
+        &CreateRole {
+            name: "Moderator".to_owned(),
+            permissions: Permissions::MANAGE_MESSAGES,
+        },
+    )
+    .unwrap();
+    app.ops().roles().create_role(&moderator).await.unwrap();
+    app.ops()
+        .roles()
+        .add_member_role(&moderator, BASIC_USER_2)
+        .await
+        .unwrap();
+    let member = app.ops().users().fetch_user(BASIC_USER_2).await.unwrap().unwrap();
+    app.ops()
+        .automod()
+        .moderate_message(&channel, &message(member, "FREE NITRO"))
+        .await
+        .unwrap();
+    app.ops()
+        .roles()
+        .remove_member_role(&moderator, BASIC_USER_2)
+        .await
+        .unwrap();
+
+    // Disabled rules are still tested, but not enforced
+    let mut disabled = rule.clone();
+    disabled
+        .apply_update(UpdateAutomodRule {
+            name: None,
+            enabled: Some(false),
+            trigger: None,
+            actions: None,
+        })
+        .unwrap();
+    app.ops().automod().update_rule(&disabled).await.unwrap();
+    let member = app.ops().users().fetch_user(BASIC_USER_2).await.unwrap().unwrap();
+    app.ops()
+        .automod()
+        .moderate_message(&channel, &message(member, "FREE NITRO"))
+        .await
+        .unwrap();
+    assert_eq!(
+        app.ops()
+            .automod()
+            .test_rules(BASIC_GUILD_1, Some("free nitro"), &[])
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    assert!(app.ops().automod().delete_rule(BASIC_GUILD_1, rule.id()).await.unwrap());
+    assert!(
+        app.ops()
+            .automod()
+            .fetch_rule(BASIC_GUILD_1, rule.id())
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_onboarding(pool: PgPool) {
+    let app = utils::DBApp::new(pool.clone());
+
+    assert_eq!(
+        app.ops().guilds().fetch_onboarding(BASIC_GUILD_2).await.unwrap(),
+        Onboarding::empty(BASIC_GUILD_2)
+    );
+
+    let payload = |questions: Vec<OnboardingQuestionPayload>| UpdateOnboarding {
+        default_channel_ids: vec![BASIC_GUILD_2_GENERAL],
+        system_channel_id: Some(BASIC_GUILD_2_GENERAL),
+        welcome_message: Some("Welcome {user}!".to_string()),
+        questions,
+    };
+    let option = |label: &str, role: i64| OnboardingOptionPayload {
+        id: None,
+        label: label.to_string(),
+        role_ids: vec![Snowflake::new(role)],
+    };
+
+    let onboarding = Onboarding::from_payload(
+        app.config(),
+        &Onboarding::empty(BASIC_GUILD_2),
+        payload(vec![OnboardingQuestionPayload {
+            id: None,
+            prompt: "What brings you here?".to_string(),
+            options: vec![option("Games", 1), option("Music", 2)],
+        }]),
+    )
+    .unwrap();
+    app.ops().guilds().update_onboarding(&onboarding).await.unwrap();
+    assert_eq!(
+        app.ops().guilds().fetch_onboarding(BASIC_GUILD_2).await.unwrap(),
+        onboarding
+    );
+
+    // Channels of other guilds cannot be referenced
+    let mut foreign = payload(Vec::new());
+    foreign.default_channel_ids.push(BASIC_GUILD_1_RANDOM);
+    let foreign = Onboarding::from_payload(app.config(), &onboarding, foreign).unwrap();
+    assert!(app.ops().guilds().update_onboarding(&foreign).await.is_err());
+
+    // Joining follows the default channels and posts the welcome message
+    let guild = app.ops().guilds().fetch_guild(BASIC_GUILD_2).await.unwrap().unwrap();
+    app.ops().guilds().create_member(&guild, BASIC_USER_1).await.unwrap();
+    let welcome = app
+        .ops()
+        .guilds()
+        .onboard_member(BASIC_GUILD_2, BASIC_USER_1)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(welcome.author().is_none());
+    assert_eq!(welcome.channel_id(), BASIC_GUILD_2_GENERAL);
+    assert_eq!(welcome.content(), Some(format!("Welcome <@{BASIC_USER_1}>!").as_str()));
+
+    let followed: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM read_states WHERE user_id = $1 AND channel_id = $2")
+        .bind(BASIC_USER_1)
+        .bind(BASIC_GUILD_2_GENERAL)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(followed, 1);
+
+    // Answers map to the roles of the picked options
+    let options: Vec<_> = onboarding.options().map(OnboardingOption::id).collect();
+    let responses = app
+        .ops()
+        .guilds()
+        .set_onboarding_responses(BASIC_GUILD_2, BASIC_USER_1, vec![options[1]])
+        .await
+        .unwrap();
+    assert_eq!(responses.option_ids(), &[options[1]]);
+    assert_eq!(responses.role_ids(), &[Snowflake::new(2)]);
+    assert!(
+        app.ops()
+            .guilds()
+            .set_onboarding_responses(BASIC_GUILD_2, BASIC_USER_1, vec![Snowflake::new(1)])
+            .await
+            .is_err()
+    );
+
+    // Removing the questions discards the answers given to them
+    let cleared = Onboarding::from_payload(app.config(), &onboarding, payload(Vec::new())).unwrap();
+    app.ops().guilds().update_onboarding(&cleared).await.unwrap();
+    assert!(
+        app.ops()
+            .guilds()
+            .fetch_onboarding(BASIC_GUILD_2)
+            .await
+            .unwrap()
+            .questions()
+            .is_empty()
+    );
+
+    let answers: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM onboarding_answers WHERE user_id = $1")
+        .bind(BASIC_USER_1)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(answers, 0);
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_guest_access(pool: PgPool) {
+    let app = utils::DBApp::new(pool.clone());
+    let guild = app.ops().guilds().fetch_guild(BASIC_GUILD_2).await.unwrap().unwrap();
+    let general = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_2_GENERAL)
+        .await
+        .unwrap()
+        .unwrap();
+    let other = TextChannel::new(Snowflake::gen_new(app.config()), &guild, "other").into();
+    let other = app.ops().guilds().create_channel(&other).await.unwrap();
+
+    let link = GuestLink::new(&guild, &general, BASIC_USER_2, false, 3600, None);
+    app.ops().guilds().create_guest_link(&link).await.unwrap();
+    assert_eq!(
+        app.ops().guilds().fetch_guest_link(link.code()).await.unwrap(),
+        Some(link.clone())
+    );
+    assert_eq!(
+        app.ops()
+            .guilds()
+            .fetch_guest_links_for(BASIC_GUILD_2_GENERAL)
+            .await
+            .unwrap(),
+        vec![link.clone()]
+    );
+
+    // Guests are members that can only view a single channel
+    let guest = app.ops().guilds().create_guest(&link, BASIC_USER_1).await.unwrap();
+    assert_eq!(guest.guest().unwrap().channel_id(), BASIC_GUILD_2_GENERAL);
+    assert!(
+        app.ops()
+            .guilds()
+            .has_member(BASIC_GUILD_2, BASIC_USER_1)
+            .await
+            .unwrap()
+    );
+    assert!(guest.can_view(BASIC_GUILD_2_GENERAL));
+    assert!(!guest.can_view(other.id()));
+
+    let visible = app
+        .ops()
+        .guilds()
+        .fetch_channels_visible_to(BASIC_GUILD_2, BASIC_USER_1)
+        .await
+        .unwrap();
+    assert_eq!(
+        visible.iter().map(ChannelLike::id).collect::<Vec<_>>(),
+        vec![BASIC_GUILD_2_GENERAL]
+    );
+    assert_eq!(
+        app.ops()
+            .guilds()
+            .fetch_channels_visible_to(BASIC_GUILD_2, BASIC_USER_2)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+    let read_states = app.ops().notifications().fetch_read_states(BASIC_USER_1).await.unwrap();
+    assert!(read_states.iter().all(|r| r.channel_id != other.id()));
+
+    // Read-only guests cannot post, not even in their own channel
+    assert!(!app.ops().guilds().can_post_in(&general, BASIC_USER_1).await.unwrap());
+    assert!(app.ops().guilds().can_post_in(&general, BASIC_USER_2).await.unwrap());
+
+    // Joining through another link replaces the guest's access
+    let posting = GuestLink::new(&guild, &general, BASIC_USER_2, true, 3600, None);
+    app.ops().guilds().create_guest_link(&posting).await.unwrap();
+    app.ops().guilds().create_guest(&posting, BASIC_USER_1).await.unwrap();
+    assert!(app.ops().guilds().can_post_in(&general, BASIC_USER_1).await.unwrap());
+    assert!(!app.ops().guilds().can_post_in(&other, BASIC_USER_1).await.unwrap());
+
+    // Full members cannot become guests
+    assert!(matches!(
+        app.ops().guilds().create_guest(&link, BASIC_USER_2).await,
+        Err(OpsError::Conflict(_))
+    ));
+
+    // Expired links cannot be resolved
+    let expired = GuestLink::new(
+        &guild,
+        &general,
+        BASIC_USER_2,
+        false,
+        3600,
+        Some(chrono::DateTime::UNIX_EPOCH),
+    );
+    app.ops().guilds().create_guest_link(&expired).await.unwrap();
+    assert_eq!(app.ops().guilds().fetch_guest_link(expired.code()).await.unwrap(), None);
+
+    // Expired guests are removed
+    assert_eq!(app.ops().guilds().remove_expired_guests().await.unwrap(), 0);
+    sqlx::query("UPDATE members SET guest_expires_at = to_timestamp(0) WHERE user_id = $1 AND guild_id = $2")
+        .bind(BASIC_USER_1)
+        .bind(BASIC_GUILD_2)
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(app.ops().guilds().remove_expired_guests().await.unwrap(), 1);
+    assert!(
+        !app.ops()
+            .guilds()
+            .has_member(BASIC_GUILD_2, BASIC_USER_1)
+            .await
+            .unwrap()
+    );
+    let links: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM guest_links")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(links, 2);
+
+    // Guests joining as full members are promoted
+    app.ops().guilds().create_guest(&link, BASIC_USER_1).await.unwrap();
+    let member = app.ops().guilds().create_member(&guild, BASIC_USER_1).await.unwrap();
+    assert!(member.guest().is_none());
+    assert!(member.can_view(other.id()));
+}
+
+#[sqlx::test(fixtures("basic", "basic_messages"))]
+async fn test_export_messages(pool: PgPool) {
+    let app = utils::DBApp::new(pool.clone());
+
+    let attachment_message: i64 = sqlx::query_scalar("SELECT MIN(id) FROM messages WHERE channel_id = $1")
+        .bind(BASIC_GUILD_1_GENERAL)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    for (id, filename) in [(0, "a.png"), (1, "b.png")] {
+        sqlx::query(
+            "INSERT INTO attachments (id, filename, message_id, channel_id, content_type) VALUES ($1, $2, $3, $4, 'image/png')",
+        )
+        .bind(id)
+        .bind(filename)
+        .bind(attachment_message)
+        .bind(BASIC_GUILD_1_GENERAL)
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+
+    let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE channel_id = $1")
+        .bind(BASIC_GUILD_1_GENERAL)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+    // Exports are not limited by pagination, and contain each message once, oldest first
+    let messages: Vec<Message> = app
+        .ops()
+        .messages()
+        .export_messages(BASIC_GUILD_1_GENERAL)
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert!(total > 100);
+    assert_eq!(messages.len() as i64, total);
+    assert!(messages.windows(2).all(|w| w[0].id() < w[1].id()));
+    assert!(messages.iter().all(|m| m.channel_id() == BASIC_GUILD_1_GENERAL));
+    assert_eq!(i64::from(messages[0].id()), attachment_message);
+    assert_eq!(messages[0].attachments().len(), 2);
+
+    let empty: Vec<Message> = app
+        .ops()
+        .messages()
+        .export_messages(BASIC_GUILD_1_RANDOM)
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert!(empty.is_empty());
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_moderation_keywords(pool: PgPool) {
+    let app = utils::DBApp::new(pool.clone());
+    assert!(
+        app.ops()
+            .guilds()
+            .fetch_moderation_keywords(BASIC_GUILD_1)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    let keywords = normalize_keywords(vec!["Spoiler".into(), "scam".into(), "scam ".into()]).unwrap();
+    app.ops()
+        .guilds()
+        .set_moderation_keywords(BASIC_GUILD_1, &keywords)
+        .await
+        .unwrap();
+    assert_eq!(
+        app.ops()
+            .guilds()
+            .fetch_moderation_keywords(BASIC_GUILD_1)
+            .await
+            .unwrap(),
+        vec!["scam".to_owned(), "spoiler".to_owned()]
+    );
+
+    // Replacing the list removes keywords missing from it
+    app.ops()
+        .guilds()
+        .set_moderation_keywords(BASIC_GUILD_1, &["phishing".to_owned()])
+        .await
+        .unwrap();
+    assert_eq!(
+        app.ops()
+            .guilds()
+            .fetch_moderation_keywords(BASIC_GUILD_1)
+            .await
+            .unwrap(),
+        vec!["phishing".to_owned()]
+    );
+
+    // Subscribing is idempotent
+    for _ in 0..2 {
+        app.ops()
+            .guilds()
+            .set_keyword_alert_subscription(BASIC_GUILD_1, BASIC_USER_1, true)
+            .await
+            .unwrap();
+    }
+    let subscribers: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM keyword_alert_subscribers WHERE guild_id = $1")
+        .bind(BASIC_GUILD_1)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers, 1);
+
+    app.ops()
+        .guilds()
+        .set_keyword_alert_subscription(BASIC_GUILD_1, BASIC_USER_1, false)
+        .await
+        .unwrap();
+    let subscribers: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM keyword_alert_subscribers WHERE guild_id = $1")
+        .bind(BASIC_GUILD_1)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers, 0);
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_login_external(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let identity = |subject: &str, username: &str, display_name: &str| ExternalIdentity {
+        subject: subject.into(),
+        username: Some(username.into()),
+        display_name: Some(display_name.into()),
+        email: None,
+    };
+
+    // New identities have no account until they are provisioned, with the suggested username
+    assert!(
+        app.ops()
+            .users()
+            .login_external("oidc", &identity("a", "Alice", "Alice Smith"))
+            .await
+            .unwrap()
+            .is_none()
+    );
+    let user = app
+        .ops()
+        .users()
+        .provision_external("oidc", &identity("a", "Alice", "Alice Smith"), None)
+        .await
+        .unwrap();
+    assert_eq!(user.username(), "alice");
+    assert_eq!(user.display_name(), Some("Alice Smith"));
+
+    // Returning users keep their account, and pick up display name changes
+    let (same, changed) = app
+        .ops()
+        .users()
+        .login_external("oidc", &identity("a", "Alice", "Alice Smith"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!changed);
+    assert_eq!(same.id(), user.id());
+
+    let (same, changed) = app
+        .ops()
+        .users()
+        .login_external("oidc", &identity("a", "Alice", "Alice Jones"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(changed);
+    assert_eq!(same.id(), user.id());
+    let stored = app.ops().users().fetch_user(user.id()).await.unwrap().unwrap();
+    assert_eq!(stored.display_name(), Some("Alice Jones"));
+
+    // Subjects are only unique within a provider, and taken or invalid usernames fall back to the ID
+    for (provider, subject, username) in [("other", "a", "alice"), ("oidc", "b", "not a username")] {
+        let other = app
+            .ops()
+            .users()
+            .provision_external(provider, &identity(subject, username, "x"), None)
+            .await
+            .unwrap();
+        assert_ne!(other.id(), user.id());
+        assert_eq!(other.username(), format!("user_{}", other.id()));
+        assert_eq!(other.display_name(), None);
+    }
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_terminate_user(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let identity = ExternalIdentity {
+        subject: "a".into(),
+        username: Some("alice".into()),
+        display_name: None,
+        email: None,
+    };
+    let external = app
+        .ops()
+        .users()
+        .provision_external("oidc", &identity, None)
+        .await
+        .unwrap();
+
+    // Memberships are removed, except in guilds the user owns
+    assert_eq!(app.ops().terminate_user(BASIC_USER_2).await.unwrap(), 1);
+    assert!(
+        !app.ops()
+            .guilds()
+            .has_member(BASIC_GUILD_1, BASIC_USER_2)
+            .await
+            .unwrap()
+    );
+    assert!(
+        app.ops()
+            .guilds()
+            .has_member(BASIC_GUILD_2, BASIC_USER_2)
+            .await
+            .unwrap()
+    );
+
+    // Users can only be terminated once
+    assert!(matches!(
+        app.ops().terminate_user(BASIC_USER_2).await,
+        Err(OpsError::NotFound(_))
+    ));
+
+    // Terminated external users can no longer log in
+    assert!(app.ops().users().has_external_identity(external.id()).await.unwrap());
+    assert_eq!(app.ops().terminate_user(external.id()).await.unwrap(), 0);
+    assert!(!app.ops().users().has_external_identity(external.id()).await.unwrap());
+    assert!(matches!(
+        app.ops().users().login_external("oidc", &identity).await,
+        Err(OpsError::Forbidden(_))
+    ));
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_invite_usage(pool: PgPool) {
+    let app = utils::DBApp::new(pool.clone());
+    app.ops()
+        .guilds()
+        .update_vanity_code(BASIC_GUILD_2, BASIC_USER_2, Some("test-guild".to_string()))
+        .await
+        .unwrap();
+    let invite = app.ops().guilds().fetch_invite("Test-Guild").await.unwrap().unwrap();
+
+    assert_eq!(
+        app.ops()
+            .guilds()
+            .fetch_invite_usage(BASIC_GUILD_2, "test-guild")
+            .await
+            .unwrap(),
+        None
+    );
+
+    let member = app
+        .ops()
+        .guilds()
+        .create_member_via_invite(&invite, BASIC_USER_1)
+        .await
+        .unwrap();
+    assert!(
+        app.ops()
+            .guilds()
+            .has_member(BASIC_GUILD_2, BASIC_USER_1)
+            .await
+            .unwrap()
+    );
+
+    let usage = app
+        .ops()
+        .guilds()
+        .fetch_invite_usage(BASIC_GUILD_2, "TEST-GUILD")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(usage.uses, 1);
+    assert_eq!(usage.recent_uses.len(), 1);
+    assert_eq!(usage.recent_uses[0].user_id, Some(BASIC_USER_1));
+    assert_eq!(usage.recent_uses[0].used_at, member.joined_at());
+
+    // Joins are recorded in the audit log along with the invite's total uses
+    let (target, new_value) = sqlx::query_as::<_, (i64, String)>(
+        "SELECT target_id, new_value FROM audit_log_entries WHERE guild_id = $1 AND action = 'INVITE_USE'",
+    )
+    .bind(BASIC_GUILD_2)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(target, i64::from(BASIC_USER_1));
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&new_value).unwrap(),
+        serde_json::json!({"code": "test-guild", "uses": 1})
+    );
+
+    // Members cannot join again, and failed joins are not recorded
+    assert!(
+        app.ops()
+            .guilds()
+            .create_member_via_invite(&invite, BASIC_USER_1)
+            .await
+            .is_err()
+    );
+    let usage = app
+        .ops()
+        .guilds()
+        .fetch_invite_usage(BASIC_GUILD_2, "test-guild")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(usage.uses, 1);
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_guild_member_limit(pool: PgPool) {
+    use chat_backend::{app::ops::Ops, external::Database};
+
+    let app = utils::DBApp::new(pool.clone());
+    app.ops()
+        .guilds()
+        .update_vanity_code(BASIC_GUILD_2, BASIC_USER_2, Some("test-guild".to_string()))
+        .await
+        .unwrap();
+
+    // Without a limit, guilds are never full
+    let invite = app.ops().guilds().fetch_invite("test-guild").await.unwrap().unwrap();
+    assert_eq!(invite.approximate_member_count(), 1);
+    assert!(!invite.is_full());
+
+    let config = utils::app::mock_config().guild_member_limit(Some(1)).build().unwrap();
+    let db = Database::from_pool(pool);
+    let ops = Ops::builder().db(&db).config(&config).build().unwrap();
+
+    let invite = ops.guilds().fetch_invite("test-guild").await.unwrap().unwrap();
+    assert_eq!(invite.approximate_member_count(), 1);
+    assert!(invite.is_full());
+
+    // Joins are rejected once the guild is full, and are not recorded
+    assert!(matches!(
+        ops.guilds().create_member_via_invite(&invite, BASIC_USER_1).await,
+        Err(OpsError::GuildFull)
+    ));
+    assert!(!ops.guilds().has_member(BASIC_GUILD_2, BASIC_USER_1).await.unwrap());
+    assert_eq!(
+        ops.guilds()
+            .fetch_invite_usage(BASIC_GUILD_2, "test-guild")
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_roles(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let roles = app.ops().roles();
+    let guild = app.ops().guilds().fetch_guild(BASIC_GUILD_1).await.unwrap().unwrap();
+
+    // The owner has every permission, other members have none until roles are assigned
+    assert_eq!(
+        roles.fetch_permissions(&guild, BASIC_USER_1).await.unwrap(),
+        Permissions::all()
+    );
+    assert_eq!(
+        roles.fetch_permissions(&guild, BASIC_USER_2).await.unwrap(),
+        Permissions::empty()
+    );
+
+    let moderator = Role::from_payload(
+        app.config(),
+        BASIC_GUILD_1,
+        &CreateRole {
+            name: " Moderator ".to_owned(),
+            permissions: Permissions::KICK_MEMBERS | Permissions::MODERATE_GUILD,
+        },
+    )
+    .unwrap();
+    assert_eq!(moderator.name(), "Moderator");
+    roles.create_role(&moderator).await.unwrap();
+
+    let mut events = Role::from_payload(
+        app.config(),
+        BASIC_GUILD_1,
+        &CreateRole {
+            name: "Events".to_owned(),
+            permissions: Permissions::MANAGE_EVENTS,
+        },
+    )
+    .unwrap();
+    roles.create_role(&events).await.unwrap();
+
+    assert_eq!(
+        roles.fetch_roles(BASIC_GUILD_1).await.unwrap(),
+        vec![moderator.clone(), events.clone()]
+    );
+    assert_eq!(roles.fetch_roles(BASIC_GUILD_2).await.unwrap(), vec![]);
+    assert_eq!(roles.fetch_role(BASIC_GUILD_2, moderator.id()).await.unwrap(), None);
+
+    // Permissions are the union of the member's roles
+    assert!(roles.add_member_role(&moderator, BASIC_USER_2).await.unwrap());
+    assert!(!roles.add_member_role(&moderator, BASIC_USER_2).await.unwrap());
+    assert!(roles.add_member_role(&events, BASIC_USER_2).await.unwrap());
+    assert_eq!(
+        roles.fetch_member_role_ids(BASIC_GUILD_1, BASIC_USER_2).await.unwrap(),
+        vec![moderator.id(), events.id()]
+    );
+    assert_eq!(
+        roles.fetch_permissions(&guild, BASIC_USER_2).await.unwrap(),
+        Permissions::KICK_MEMBERS | Permissions::MODERATE_GUILD | Permissions::MANAGE_EVENTS
+    );
+
+    // Moderators are notified about forwarded reports and quarantined attachments along with the owner
+    let mut moderators = roles
+        .fetch_users_with_permission(BASIC_GUILD_1, Permissions::MODERATE_GUILD)
+        .await
+        .unwrap();
+    moderators.sort();
+    assert_eq!(moderators, vec![BASIC_USER_1, BASIC_USER_2]);
+    assert_eq!(
+        roles
+            .fetch_users_with_permission(BASIC_GUILD_1, Permissions::MANAGE_MESSAGES)
+            .await
+            .unwrap(),
+        vec![BASIC_USER_1]
+    );
+
+    // Updates apply to every member the role is assigned to
+    events
+        .apply_update(UpdateRole {
+            name: None,
+            permissions: Some(Permissions::MANAGE_CHANNELS),
+        })
+        .unwrap();
+    roles.update_role(&events).await.unwrap();
+    assert_eq!(
+        roles.fetch_role(BASIC_GUILD_1, events.id()).await.unwrap(),
+        Some(events.clone())
+    );
+    assert_eq!(
+        roles.fetch_permissions(&guild, BASIC_USER_2).await.unwrap(),
+        Permissions::KICK_MEMBERS | Permissions::MODERATE_GUILD | Permissions::MANAGE_CHANNELS
+    );
+
+    assert!(roles.remove_member_role(&moderator, BASIC_USER_2).await.unwrap());
+    assert!(!roles.remove_member_role(&moderator, BASIC_USER_2).await.unwrap());
+    assert_eq!(
+        roles.fetch_permissions(&guild, BASIC_USER_2).await.unwrap(),
+        Permissions::MANAGE_CHANNELS
+    );
+
+    // Deleting a role unassigns it
+    assert!(roles.delete_role(BASIC_GUILD_1, events.id()).await.unwrap());
+    assert!(!roles.delete_role(BASIC_GUILD_1, events.id()).await.unwrap());
+    assert_eq!(
+        roles.fetch_member_role_ids(BASIC_GUILD_1, BASIC_USER_2).await.unwrap(),
+        vec![]
+    );
+    assert_eq!(
+        roles.fetch_permissions(&guild, BASIC_USER_2).await.unwrap(),
+        Permissions::empty()
+    );
+
+    // Leaving the guild unassigns all roles
+    assert!(roles.add_member_role(&moderator, BASIC_USER_2).await.unwrap());
+    app.ops().guilds().delete_member(&guild, BASIC_USER_2).await.unwrap();
+    assert_eq!(
+        roles.fetch_member_role_ids(BASIC_GUILD_1, BASIC_USER_2).await.unwrap(),
+        vec![]
+    );
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_roles_not_assigned_to_guests(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let general = app
+        .ops()
+        .guilds()
+        .fetch_channel(BASIC_GUILD_2_GENERAL)
+        .await
+        .unwrap()
+        .unwrap();
+    let link = GuestLink::new(BASIC_GUILD_2, &general, BASIC_USER_2, false, 3600, None);
+    app.ops().guilds().create_guest_link(&link).await.unwrap();
+    app.ops().guilds().create_guest(&link, BASIC_USER_1).await.unwrap();
+
+    let role = Role::from_payload(
+        app.config(),
+        BASIC_GUILD_2,
+        &CreateRole {
+            name: "Moderator".to_owned(),
+            permissions: Permissions::MANAGE_MESSAGES,
+        },
+    )
+    .unwrap();
+    app.ops().roles().create_role(&role).await.unwrap();
+
+    assert!(!app.ops().roles().add_member_role(&role, BASIC_USER_1).await.unwrap());
+    assert_eq!(
+        app.ops()
+            .roles()
+            .fetch_member_role_ids(BASIC_GUILD_2, BASIC_USER_1)
+            .await
+            .unwrap(),
+        vec![]
+    );
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_relationships(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let relationships = app.ops().relationships();
+
+    assert!(matches!(
+        relationships.add_relationship(BASIC_USER_1, BASIC_USER_1).await,
+        Err(OpsError::BadRequest(_))
+    ));
+
+    // Sending a request twice does not change it
+    assert_eq!(
+        relationships
+            .add_relationship(BASIC_USER_1, BASIC_USER_2)
+            .await
+            .unwrap(),
+        Some(RelationshipType::OutgoingRequest)
+    );
+    assert_eq!(
+        relationships
+            .add_relationship(BASIC_USER_1, BASIC_USER_2)
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        relationships
+            .fetch_relationship(BASIC_USER_2, BASIC_USER_1)
+            .await
+            .unwrap(),
+        Some(RelationshipType::IncomingRequest)
+    );
+
+    // Answering the request makes both users friends
+    assert_eq!(
+        relationships
+            .add_relationship(BASIC_USER_2, BASIC_USER_1)
+            .await
+            .unwrap(),
+        Some(RelationshipType::Friend)
+    );
+    let friends = relationships.fetch_relationships(BASIC_USER_1).await.unwrap();
+    assert_eq!(friends.len(), 1);
+    assert_eq!(friends[0].user().id(), BASIC_USER_2);
+    assert_eq!(friends[0].kind(), RelationshipType::Friend);
+
+    // Users sharing a guild may open DMs under the default policy, even if they are no longer friends
+    assert!(relationships.can_open_dm(BASIC_USER_1, BASIC_USER_2).await.unwrap());
+    assert!(
+        relationships
+            .remove_relationship(BASIC_USER_2, BASIC_USER_1)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !relationships
+            .remove_relationship(BASIC_USER_2, BASIC_USER_1)
+            .await
+            .unwrap()
+    );
+    assert!(
+        relationships
+            .fetch_relationships(BASIC_USER_1)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(relationships.can_open_dm(BASIC_USER_1, BASIC_USER_2).await.unwrap());
+
+    let guild = app.ops().guilds().fetch_guild(BASIC_GUILD_1).await.unwrap().unwrap();
+    app.ops().guilds().delete_member(&guild, BASIC_USER_2).await.unwrap();
+    assert!(!relationships.can_open_dm(BASIC_USER_1, BASIC_USER_2).await.unwrap());
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_signup_restrictions(pool: PgPool) {
+    use chat_backend::{app::ops::Ops, external::Database};
+    use std::{collections::HashSet, time::Duration};
+
+    let config = utils::app::mock_config()
+        .disposable_email_domains(HashSet::from(["mailinator.com".to_string()]))
+        .new_account_restriction(Some(Duration::from_secs(3600)))
+        .build()
+        .unwrap();
+    let db = Database::from_pool(pool);
+    let ops = Ops::builder().db(&db).config(&config).build().unwrap();
+    let identity = |subject: &str, email: &str| ExternalIdentity {
+        subject: subject.into(),
+        username: Some(subject.into()),
+        display_name: None,
+        email: Some(email.into()),
+    };
+
+    // Disposable email domains are rejected, regardless of case
+    let result = ops
+        .users()
+        .provision_external("oidc", &identity("a", "a@Mailinator.COM"), None)
+        .await;
+    assert!(matches!(result, Err(OpsError::Forbidden(_))));
+
+    let user = ops
+        .users()
+        .provision_external("oidc", &identity("b", "b@example.com"), None)
+        .await
+        .unwrap();
+
+    // New accounts may only open DMs with friends, even if they share a guild
+    ops.guilds().create_member(BASIC_GUILD_1, user.id()).await.unwrap();
+    assert!(config.is_new_account(user.id()));
+    assert!(!config.is_new_account(BASIC_USER_1));
+    assert!(!ops.relationships().can_open_dm(user.id(), BASIC_USER_1).await.unwrap());
+    assert!(ops.relationships().can_open_dm(BASIC_USER_1, user.id()).await.unwrap());
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_direct_channels(pool: PgPool) {
+    let app = utils::DBApp::new(pool.clone());
+    let direct_channels = app.ops().direct_channels();
+
+    let result = direct_channels.open_direct_channel(BASIC_USER_1, BASIC_USER_1).await;
+    assert!(matches!(result, Err(OpsError::BadRequest(_))));
+
+    let (channel, created) = direct_channels
+        .open_direct_channel(BASIC_USER_1, BASIC_USER_2)
+        .await
+        .unwrap();
+    assert!(created);
+    assert_eq!(channel.guild_id(), None);
+    assert!(matches!(channel.send_mode(), SendMode::ToChannelRecipients(id) if id == channel.id()));
+
+    // Opening the channel again, from either side, returns the same channel
+    let (reopened, created) = direct_channels
+        .open_direct_channel(BASIC_USER_2, BASIC_USER_1)
+        .await
+        .unwrap();
+    assert!(!created);
+    assert_eq!(reopened.id(), channel.id());
+
+    let fetched = app.ops().guilds().fetch_channel(channel.id()).await.unwrap().unwrap();
+    assert!(matches!(fetched, Channel::DirectMessage(_)));
+    let mut recipients = vec![BASIC_USER_1, BASIC_USER_2];
+    recipients.sort();
+    assert_eq!(fetched.recipient_ids(), recipients.as_slice());
+    let listed = direct_channels.fetch_direct_channels(BASIC_USER_2).await.unwrap();
+    assert_eq!(
+        listed.iter().map(ChannelLike::id).collect::<Vec<_>>(),
+        vec![channel.id()]
+    );
+
+    // Only the recipients may post in the channel
+    let outsider = app
+        .ops()
+        .users()
+        .create_user(CreateUser {
+            username: "outsider".to_string(),
+            password: Secret::new("Amongus1.".to_string()),
+            registration_code: None,
+        })
+        .await
+        .unwrap();
+    assert!(app.ops().guilds().can_post_in(&fetched, BASIC_USER_2).await.unwrap());
+    assert!(!app.ops().guilds().can_post_in(&fetched, outsider.id()).await.unwrap());
+
+    let author = app.ops().users().fetch_user(BASIC_USER_1).await.unwrap().unwrap();
+    let message = Message::builder()
+        .id(Snowflake::gen_new(app.config()))
+        .author(UserLike::User(author))
+        .channel_id(channel.id())
+        .content(Some(format!("Hello <@{BASIC_USER_2}>")))
+        .build()
+        .unwrap();
+    app.ops().messages().commit_message(&message).await.unwrap();
+
+    let messages = app
+        .ops()
+        .messages()
+        .fetch_messages_from(
+            channel.id(),
+            None,
+            None::<Snowflake<Message>>,
+            None::<Snowflake<Message>>,
+            None::<Snowflake<Message>>,
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(messages.iter().map(Message::id).collect::<Vec<_>>(), vec![message.id()]);
+
+    // Recipients track their read state and mentions in the channel like in guild channels
+    let states = app.ops().notifications().fetch_read_states(BASIC_USER_2).await.unwrap();
+    let state = states.iter().find(|s| s.channel_id == channel.id()).unwrap();
+    assert_eq!(state.last_message_id, Some(message.id()));
+    let states = app
+        .ops()
+        .notifications()
+        .fetch_read_states(outsider.id())
+        .await
+        .unwrap();
+    assert!(states.is_empty());
+    let mentions = app
+        .ops()
+        .messages()
+        .fetch_mentions(BASIC_USER_2, None, None)
+        .await
+        .unwrap();
+    assert_eq!(mentions.iter().map(Message::id).collect::<Vec<_>>(), vec![message.id()]);
+
+    // Recipients may only post while they may still open direct messages with each other
+    let guild = app.ops().guilds().fetch_guild(BASIC_GUILD_1).await.unwrap().unwrap();
+    app.ops().guilds().delete_member(&guild, BASIC_USER_2).await.unwrap();
+    assert!(!app.ops().guilds().can_post_in(&fetched, BASIC_USER_1).await.unwrap());
+    assert!(!app.ops().guilds().can_post_in(&fetched, BASIC_USER_2).await.unwrap());
+
+    // Only direct message channels belong to no guild
+    let result = sqlx::query("INSERT INTO channels (id, channel_type, name) VALUES ($1, 'GUILD_TEXT', 'orphan')")
+        .bind(Snowflake::<Channel>::gen_new(app.config()))
+        .execute(&pool)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        result.as_database_error().and_then(|e| e.constraint()),
+        Some("direct_channels_without_guild")
+    );
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_active_channels(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let messages = app.ops().messages();
+    let user_1 = app.ops().users().fetch_user(BASIC_USER_1).await.unwrap().unwrap();
+    let user_2 = app.ops().users().fetch_user(BASIC_USER_2).await.unwrap().unwrap();
+    let message = |author: &User, channel: Snowflake<Channel>, content: String| {
+        Message::builder()
+            .id(Snowflake::gen_new(app.config()))
+            .author(UserLike::User(author.clone()))
+            .channel_id(channel)
+            .content(Some(content))
+            .build()
+            .unwrap()
+    };
+    let active = async |user: Snowflake<User>, limit: Option<u32>| {
+        messages
+            .fetch_active_channels(user, limit)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|a| (a.channel.id(), a.last_activity_id, a.last_mention_id))
+            .collect::<Vec<_>>()
+    };
+
+    let posted = message(&user_1, BASIC_GUILD_1_GENERAL, "Hello".to_string());
+    messages.commit_message(&posted).await.unwrap();
+    assert_eq!(
+        active(BASIC_USER_1, None).await,
+        vec![(BASIC_GUILD_1_GENERAL, posted.id(), None)]
+    );
+    assert!(active(BASIC_USER_2, None).await.is_empty());
+
+    // Editing a message is not new activity
+    messages.commit_message(&posted).await.unwrap();
+    assert_eq!(active(BASIC_USER_1, None).await.len(), 1);
+
+    let mention = message(&user_2, BASIC_GUILD_1_RANDOM, format!("Hey <@{BASIC_USER_1}>"));
+    messages.commit_message(&mention).await.unwrap();
+
+    let (channel, _) = app
+        .ops()
+        .direct_channels()
+        .open_direct_channel(BASIC_USER_2, BASIC_USER_1)
+        .await
+        .unwrap();
+    // Opening a direct message channel is not activity until a message is sent in it
+    assert_eq!(
+        active(BASIC_USER_2, None).await,
+        vec![(BASIC_GUILD_1_RANDOM, mention.id(), None)]
+    );
+    let direct = message(&user_2, channel.id(), "Psst".to_string());
+    messages.commit_message(&direct).await.unwrap();
+    assert_eq!(
+        active(BASIC_USER_2, None).await,
+        vec![
+            (channel.id(), direct.id(), None),
+            (BASIC_GUILD_1_RANDOM, mention.id(), None)
+        ]
+    );
+
+    assert_eq!(
+        active(BASIC_USER_1, None).await,
+        vec![
+            (channel.id(), direct.id(), None),
+            (BASIC_GUILD_1_RANDOM, mention.id(), Some(mention.id())),
+            (BASIC_GUILD_1_GENERAL, posted.id(), None),
+        ]
+    );
+    assert_eq!(
+        active(BASIC_USER_1, Some(1)).await,
+        vec![(channel.id(), direct.id(), None)]
+    );
+
+    app.ops()
+        .notifications()
+        .update_read_state(BASIC_USER_1, BASIC_GUILD_1_RANDOM, mention.id())
+        .await
+        .unwrap();
+    let channels = messages.fetch_active_channels(BASIC_USER_1, None).await.unwrap();
+    assert_eq!(channels[1].last_read_message_id, Some(mention.id()));
+    assert_eq!(channels[0].last_read_message_id, None);
+
+    // Channels the user can no longer view are omitted
+    let guild = app.ops().guilds().fetch_guild(BASIC_GUILD_1).await.unwrap().unwrap();
+    app.ops().guilds().delete_member(&guild, BASIC_USER_2).await.unwrap();
+    assert_eq!(
+        active(BASIC_USER_2, None).await,
+        vec![(channel.id(), direct.id(), None)]
+    );
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_reports(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let reports = app.ops().reports();
+
+    let report = Report::new(
+        app.config(),
+        BASIC_USER_1,
+        ReportTargetType::Guild,
+        BASIC_GUILD_1.cast(),
+        ReportCategory::Spam,
+    )
+    .in_guild(BASIC_GUILD_1, true);
+    reports.create_report(&report).await.unwrap();
+
+    // The same user cannot report the same object again while the report is pending
+    let duplicate = Report::new(
+        app.config(),
+        BASIC_USER_1,
+        ReportTargetType::Guild,
+        BASIC_GUILD_1.cast(),
+        ReportCategory::Other,
+    );
+    assert!(matches!(
+        reports.create_report(&duplicate).await,
+        Err(OpsError::Conflict(_))
+    ));
+
+    // Forwarded reports are shown to the guild's moderators without the reporter
+    let forwarded = reports.fetch_guild_reports(BASIC_GUILD_1, None, None).await.unwrap();
+    assert_eq!(forwarded.len(), 1);
+    assert_eq!(forwarded[0].id(), report.id());
+    assert_eq!(forwarded[0].reporter_id(), None);
+
+    let open = reports
+        .fetch_reports(Some(ReportStatus::Open), None, None)
+        .await
+        .unwrap();
+    assert_eq!(open, vec![report.clone()]);
+
+    // Only one administrator can claim a report
+    let claimed = reports.claim_report(report.id(), BASIC_USER_1).await.unwrap();
+    assert_eq!(claimed.status(), ReportStatus::Claimed);
+    assert_eq!(claimed.claimed_by(), Some(BASIC_USER_1));
+    assert!(matches!(
+        reports.claim_report(report.id(), BASIC_USER_2).await,
+        Err(OpsError::Conflict(_))
+    ));
+    assert!(matches!(
+        reports
+            .resolve_report(report.id(), BASIC_USER_2, ReportAction::None)
+            .await,
+        Err(OpsError::Conflict(_))
+    ));
+
+    let resolved = reports
+        .resolve_report(report.id(), BASIC_USER_1, ReportAction::ContentRemoved)
+        .await
+        .unwrap();
+    assert_eq!(resolved.status(), ReportStatus::Resolved);
+    assert_eq!(resolved.action_taken(), Some(ReportAction::ContentRemoved));
+    assert_eq!(reports.fetch_report(report.id()).await.unwrap(), Some(resolved));
+    assert!(matches!(
+        reports.claim_report(report.id(), BASIC_USER_1).await,
+        Err(OpsError::Conflict(_))
+    ));
+    assert!(matches!(
+        reports.claim_report(Snowflake::new(1), BASIC_USER_1).await,
+        Err(OpsError::NotFound(_))
+    ));
+
+    // Once resolved, the object can be reported again
+    reports.create_report(&duplicate).await.unwrap();
+    assert!(
+        reports
+            .fetch_reports(Some(ReportStatus::Claimed), None, None)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(reports.fetch_reports(None, None, None).await.unwrap().len(), 2);
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_strikes(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let standing = app.ops().standing();
+
+    assert_eq!(
+        standing.fetch_standing(BASIC_USER_2).await.unwrap().state(),
+        StandingState::Good
+    );
+
+    // Strikes escalate from a warning into a timeout, then a suspension
+    let mut penalties = Vec::new();
+    for _ in 0..3 {
+        let mut strike = Strike::new(app.config(), BASIC_USER_2, Some(BASIC_USER_1), "Spam").unwrap();
+        standing.issue_strike(&mut strike).await.unwrap();
+        penalties.push(strike);
+    }
+    assert_eq!(
+        penalties.iter().map(Strike::penalty).collect::<Vec<_>>(),
+        vec![
+            StrikePenalty::Warning,
+            StrikePenalty::Timeout,
+            StrikePenalty::Suspension
+        ]
+    );
+
+    let current = standing.fetch_standing(BASIC_USER_2).await.unwrap();
+    assert_eq!(current.state(), StandingState::Suspended);
+    assert_eq!(current.strikes().len(), 3);
+    assert!(matches!(
+        standing.ensure_unrestricted(BASIC_USER_2, StrikePenalty::Timeout).await,
+        Err(OpsError::Forbidden(_))
+    ));
+
+    // Revoking the suspension leaves the timeout in force
+    standing.revoke_strike(BASIC_USER_2, penalties[2].id()).await.unwrap();
+    assert!(matches!(
+        standing.revoke_strike(BASIC_USER_2, penalties[2].id()).await,
+        Err(OpsError::NotFound(_))
+    ));
+    assert_eq!(
+        standing.fetch_standing(BASIC_USER_2).await.unwrap().state(),
+        StandingState::TimedOut
+    );
+    standing
+        .ensure_unrestricted(BASIC_USER_2, StrikePenalty::Suspension)
+        .await
+        .unwrap();
+
+    standing.revoke_strike(BASIC_USER_2, penalties[1].id()).await.unwrap();
+    assert_eq!(
+        standing.fetch_standing(BASIC_USER_2).await.unwrap().state(),
+        StandingState::Warned
+    );
+    assert!(
+        standing
+            .restricted_until(BASIC_USER_2, StrikePenalty::Timeout)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[sqlx::test(fixtures("basic"))]
+async fn test_guild_event_reminders(pool: PgPool) {
+    let app = utils::DBApp::new(pool);
+    let events = app.ops().guild_events();
+    // Events are truncated to the microseconds they are stored with, so they compare equal after a roundtrip
+    let now = chrono::Utc::now();
+
+    let event = |starts_at: chrono::DateTime<chrono::Utc>| {
+        GuildEvent::from_payload(
+            app.config(),
+            BASIC_GUILD_1,
+            BASIC_USER_1,
+            CreateGuildEvent {
+                title: "Game night".into(),
+                description: None,
+                starts_at,
+                ends_at: None,
+                channel_id: None,
+            },
+        )
+        .unwrap()
+    };
+    let soon = event(now + chrono::TimeDelta::minutes(1));
+    let later = event(now + chrono::TimeDelta::days(1));
+    events.create_event(&soon).await.unwrap();
+    events.create_event(&later).await.unwrap();
+
+    events
+        .set_rsvp(&soon, BASIC_USER_1, RsvpStatus::Declined)
+        .await
+        .unwrap();
+    events.set_rsvp(&soon, BASIC_USER_2, RsvpStatus::Going).await.unwrap();
+    events.set_rsvp(&later, BASIC_USER_2, RsvpStatus::Going).await.unwrap();
+    assert_eq!(
+        events.fetch_events(BASIC_GUILD_1).await.unwrap(),
+        vec![soon.clone(), later.clone()]
+    );
+    assert_eq!(events.fetch_rsvps(soon.id()).await.unwrap().len(), 2);
+
+    // Only the event starting within the reminder lead is reminded of, and only once
+    assert_eq!(events.send_event_reminders().await.unwrap(), 1);
+    assert_eq!(events.send_event_reminders().await.unwrap(), 0);
+
+    assert!(events.remove_rsvp(later.id(), BASIC_USER_2).await.unwrap());
+    assert!(!events.remove_rsvp(later.id(), BASIC_USER_2).await.unwrap());
+    assert!(events.delete_event(BASIC_GUILD_1, soon.id()).await.unwrap());
+    assert_eq!(events.fetch_event(BASIC_GUILD_1, soon.id()).await.unwrap(), None);
+    assert!(events.fetch_rsvps(soon.id()).await.unwrap().is_empty());
+}
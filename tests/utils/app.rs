@@ -1,32 +1,152 @@
-use std::net::SocketAddr;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
 
 use axum::{Router, body::Body, extract::Request, response::Response};
 use chat_backend::{
-    app::{App, ApplicationState, Config},
-    external::Database,
-    gateway::Gateway,
+    app::{App, ApplicationState, Config, ConfigBuilder},
+    external::{
+        AuthProvider, Database,
+        auth_provider::{AuthProviderError, AuthProviderInfo, ExternalIdentity},
+    },
+    gateway::{EventSink, Gateway, SendMode},
+    models::{gateway_event::GatewayEvent, snowflake::Snowflake, user::User},
 };
+use futures::future::BoxFuture;
 use http::{Method, StatusCode};
 use http_body_util::BodyExt;
 use secrecy::Secret;
 use sqlx::PgPool;
 use tower::{Service, ServiceExt};
 
+use super::fixture_constants::basic::BASIC_USER_1;
+
 pub async fn mock_app(pool: PgPool) -> App {
-    let db = Database::from_pool(pool);
-    let config = Config::builder()
+    mock_app_with_auth_providers(pool, Vec::new()).await
+}
+
+/// An authentication provider named `mock`, accepting tokens of the form `subject:username:display_name`.
+#[derive(Debug)]
+pub struct MockAuthProvider;
+
+impl AuthProvider for MockAuthProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn info(&self) -> AuthProviderInfo {
+        AuthProviderInfo {
+            name: self.name().into(),
+            kind: "mock",
+            issuer: None,
+            client_id: None,
+        }
+    }
+
+    fn authenticate<'a>(&'a self, token: &'a str) -> BoxFuture<'a, Result<ExternalIdentity, AuthProviderError>> {
+        Box::pin(async move {
+            let mut parts = token.split(':').map(String::from);
+            let (Some(subject), Some(username), Some(display_name)) = (parts.next(), parts.next(), parts.next()) else {
+                return Err(AuthProviderError::Rejected("Malformed token".into()));
+            };
+            Ok(ExternalIdentity {
+                subject,
+                username: Some(username),
+                display_name: Some(display_name),
+                email: parts.next(),
+            })
+        })
+    }
+
+    fn check(&self) -> BoxFuture<'_, Result<(), AuthProviderError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+pub async fn mock_app_with_auth_providers(pool: PgPool, auth_providers: Vec<Arc<dyn AuthProvider>>) -> App {
+    let config = mock_config().build().expect("Failed to build Config");
+    mock_app_with_config(pool, config, auth_providers).await
+}
+
+/// The configuration used by mock apps, to be customized by tests before building it.
+pub fn mock_config() -> ConfigBuilder {
+    let mut builder = Config::builder();
+    builder
         .database_url(Secret::new(String::new()))
         .s3(None)
         .listen_addr("127.0.0.1:8080".parse::<SocketAddr>().expect("Not valid SocketAddr"))
         .machine_id(0)
         .process_id(0)
         .app_secret(Secret::new(String::from("test")))
-        .build()
-        .expect("Failed to build Config");
+        .admins(vec![BASIC_USER_1]);
+    builder
+}
+
+pub async fn mock_app_with_config(pool: PgPool, config: Config, auth_providers: Vec<Arc<dyn AuthProvider>>) -> App {
+    let db = Database::from_pool(pool);
+
+    // Boxed so that the futures of tests building an app stay small
+    Box::pin(ApplicationState::from_components(
+        db,
+        Gateway::new(),
+        config,
+        None,
+        None,
+        None,
+        auth_providers,
+        None,
+    ))
+    .await
+    .expect("Failed to create ApplicationState")
+}
+
+/// An event sink that records all emitted events instead of sending them to the gateway.
+/// Events sent to a single user are recorded with [`SendMode::ToUser`].
+#[derive(Debug, Default)]
+pub struct RecordingEventSink {
+    events: Mutex<Vec<(GatewayEvent, SendMode)>>,
+}
+
+impl RecordingEventSink {
+    /// Take all events recorded so far, in the order they were emitted.
+    pub fn take(&self) -> Vec<(GatewayEvent, SendMode)> {
+        std::mem::take(&mut *self.events.lock().expect("Recorded events should not be poisoned"))
+    }
+}
+
+impl EventSink for RecordingEventSink {
+    fn dispatch(&self, event: GatewayEvent, send_mode: SendMode) {
+        self.events
+            .lock()
+            .expect("Recorded events should not be poisoned")
+            .push((event, send_mode));
+    }
+
+    fn send_to(&self, user: Snowflake<User>, event: GatewayEvent) {
+        self.dispatch(event, SendMode::ToUser(user));
+    }
+}
+
+/// Create a mock app whose emitted events are recorded by the returned sink. The gateway actor is not started.
+pub async fn mock_app_with_recorder(pool: PgPool) -> (App, Arc<RecordingEventSink>) {
+    let db = Database::from_pool(pool);
+    let config = mock_config().build().expect("Failed to build Config");
+    let recorder = Arc::new(RecordingEventSink::default());
 
-    ApplicationState::from_components(db, Gateway::new(), config, None, None)
-        .await
-        .expect("Failed to create ApplicationState")
+    let app = ApplicationState::from_components(
+        db,
+        Gateway::new(),
+        config,
+        None,
+        None,
+        None,
+        Vec::new(),
+        Some(recorder.clone()),
+    )
+    .await
+    .expect("Failed to create ApplicationState");
+    (app, recorder)
 }
 
 /// Get a token for the given credentials.
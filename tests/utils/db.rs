@@ -39,8 +39,12 @@ impl DBApp {
     }
 
     /// The Ops struct for this application.
-    pub const fn ops(&self) -> Ops<'_> {
-        Ops::new(&self.db, &self.config, None, None, None)
+    pub fn ops(&self) -> Ops<'_> {
+        Ops::builder()
+            .db(&self.db)
+            .config(&self.config)
+            .build()
+            .expect("Failed to build Ops")
     }
 
     pub const fn config(&self) -> &Config {